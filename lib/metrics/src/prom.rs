@@ -1,5 +1,6 @@
 use std::fmt;
 use std::marker::{PhantomData, Sized};
+use std::sync::Arc;
 
 /// Writes a block of metrics in prometheus-formatted output.
 pub trait FmtMetrics {
@@ -159,6 +160,12 @@ impl<'a, A: FmtMetrics + 'a> FmtMetrics for &'a A {
     }
 }
 
+impl<A: FmtMetrics> FmtMetrics for Arc<A> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt_metrics(f)
+    }
+}
+
 impl<A: FmtMetrics, B: FmtMetrics> FmtMetrics for AndThen<A, B> {
     fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.0.fmt_metrics(f)?;
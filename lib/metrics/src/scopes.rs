@@ -1,12 +1,25 @@
 use super::prom::FmtLabels;
 use indexmap::IndexMap;
 use std::hash::Hash;
+use std::time::{Duration, Instant};
 
 /// Holds an `S`-typed scope for each `L`-typed label set.
 ///
 /// An `S` type typically holds one or more metrics.
+///
+/// Each scope records when it was last updated, so that label sets that are
+/// no longer in use may be dropped with `retain_updated_within`.
+#[derive(Debug)]
+pub struct Scopes<L: FmtLabels + Hash + Eq, S>(IndexMap<L, Scope<S>>);
+
 #[derive(Debug)]
-pub struct Scopes<L: FmtLabels + Hash + Eq, S>(IndexMap<L, S>);
+struct Scope<S> {
+    value: S,
+    last_update: Instant,
+}
+
+/// Iterates over the label sets and scopes of a `Scopes`.
+pub struct Iter<'a, L: 'a, S: 'a>(::indexmap::map::Iter<'a, L, Scope<S>>);
 
 impl<L: FmtLabels + Hash + Eq, S> Default for Scopes<L, S> {
     fn default() -> Self {
@@ -16,7 +29,7 @@ impl<L: FmtLabels + Hash + Eq, S> Default for Scopes<L, S> {
 
 impl<L: FmtLabels + Hash + Eq, S> Scopes<L, S> {
     pub fn get(&self, key: &L) -> Option<&S> {
-        self.0.get(key)
+        self.0.get(key).map(|s| &s.value)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -27,25 +40,82 @@ impl<L: FmtLabels + Hash + Eq, S> Scopes<L, S> {
         self.0.len()
     }
 
-    pub fn retain<F>(&mut self, f: F)
+    pub fn retain<F>(&mut self, mut f: F)
     where
         F: FnMut(&L, &mut S) -> bool,
     {
-        self.0.retain(f)
+        self.0.retain(|l, s| f(l, &mut s.value))
+    }
+
+    /// Drops the scopes that have not been updated within `retain_idle`.
+    pub fn retain_updated_within(&mut self, retain_idle: Duration) {
+        let now = Instant::now();
+        self.0.retain(|_, s| now - s.last_update <= retain_idle)
     }
 }
 
 impl<L: FmtLabels + Hash + Eq, S: Default> Scopes<L, S> {
+    /// Returns the scope for `key`, creating it if necessary, and records that
+    /// it was updated.
     pub fn get_or_default(&mut self, key: L) -> &mut S {
-        self.0.entry(key).or_insert_with(|| S::default())
+        let now = Instant::now();
+        let scope = self.0.entry(key).or_insert_with(|| Scope {
+            value: S::default(),
+            last_update: now,
+        });
+        scope.last_update = now;
+        &mut scope.value
     }
 }
 
 impl<'a, L: FmtLabels + Hash + Eq, S> IntoIterator for &'a Scopes<L, S> {
-    type Item = <&'a IndexMap<L, S> as IntoIterator>::Item;
-    type IntoIter = <&'a IndexMap<L, S> as IntoIterator>::IntoIter;
+    type Item = (&'a L, &'a S);
+    type IntoIter = Iter<'a, L, S>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.iter()
+        Iter(self.0.iter())
+    }
+}
+
+impl<'a, L, S> Iterator for Iter<'a, L, S> {
+    type Item = (&'a L, &'a S);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(l, s)| (l, &s.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt;
+
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    struct Label(usize);
+
+    impl FmtLabels for Label {
+        fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "n=\"{}\"", self.0)
+        }
+    }
+
+    #[test]
+    fn retains_recently_updated_scopes() {
+        let mut scopes = Scopes::<Label, usize>::default();
+        *scopes.get_or_default(Label(0)) += 1;
+        *scopes.get_or_default(Label(1)) += 1;
+
+        scopes.retain_updated_within(Duration::from_secs(60));
+        assert_eq!(scopes.len(), 2);
+
+        ::std::thread::sleep(Duration::from_millis(10));
+        *scopes.get_or_default(Label(1)) += 1;
+        scopes.retain_updated_within(Duration::from_millis(5));
+        assert_eq!(scopes.get(&Label(0)), None);
+        assert_eq!(scopes.get(&Label(1)), Some(&2));
     }
 }
@@ -2,6 +2,7 @@ use indexmap::IndexMap;
 use std::{
     hash::Hash,
     ops::{Deref, DerefMut},
+    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -22,6 +23,12 @@ pub use indexmap::Equivalent;
 /// - `store` computes in O(1) time (average).
 /// - `reserve` computes in O(n) time (average) when capacity is not available,
 ///
+/// ## Eviction
+///
+/// When the cache is full, `reserve` evicts the least-recently-used value that has been
+/// idle for longer than `max_idle_age`. Values with in-flight requests, as tracked by
+/// `InFlight` handles, are never evicted.
+///
 /// ### TODO
 ///
 /// The underlying datastructure could be improved somewhat so that `reserve` can evict
@@ -42,12 +49,18 @@ pub trait Now {
 }
 
 /// Wraps cache values so that each tracks its last access time.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct Node<T> {
     value: T,
     last_access: Instant,
+    in_flight: Arc<()>,
 }
 
+/// Marks a value as being in use, so that it is not evicted until the handle is
+/// dropped.
+#[derive(Clone, Debug)]
+pub struct InFlight(Arc<()>);
+
 /// A smart pointer that updates an access time when dropped.
 ///
 /// Wraps a mutable reference to a `V`-typed value.
@@ -70,6 +83,8 @@ pub struct Reserve<'a, K: Hash + Eq + 'a, V: 'a, N: 'a> {
 #[derive(Clone, Debug, PartialEq)]
 pub struct CapacityExhausted {
     pub capacity: usize,
+    /// The number of values that could not be evicted due to in-flight requests.
+    pub in_flight: usize,
 }
 
 // ===== impl Cache =====
@@ -101,25 +116,35 @@ impl<K: Hash + Eq, V, N: Now> Cache<K, V, N> {
     /// Ensures that there is capacity to store an additional route.
     ///
     /// Returns a handle that may be used to store an ite,. If there is no available
-    /// capacity, idle entries may be evicted to create capacity.
+    /// capacity, the least-recently-used idle entry may be evicted to create capacity.
     ///
     /// An error is returned if there is no available capacity.
     pub fn reserve(&mut self) -> Result<Reserve<K, V, N>, CapacityExhausted> {
-        if self.vals.len() == self.capacity {
-            // Only whole seconds are used to determine whether a node should be retained.
+        if self.vals.len() >= self.capacity {
+            // Only whole seconds are used to determine whether a node may be evicted.
             // This is intended to prevent the need for repetitive reservations when
             // entries are clustered in tight time ranges.
             let max_age = self.max_idle_age.as_secs();
             let now = self.now.now();
-            self.vals.retain(|_, n| {
-                let age = now - n.last_access();
-                age.as_secs() <= max_age
-            });
-
-            if self.vals.len() == self.capacity {
-                return Err(CapacityExhausted {
-                    capacity: self.capacity,
-                });
+            let lru = self
+                .vals
+                .values()
+                .enumerate()
+                .filter(|&(_, n)| !n.is_in_flight() && (now - n.last_access()).as_secs() > max_age)
+                .min_by_key(|&(_, n)| n.last_access())
+                .map(|(i, _)| i);
+
+            match lru {
+                Some(i) => {
+                    self.vals.swap_remove_index(i);
+                }
+                None => {
+                    let in_flight = self.vals.values().filter(|n| n.is_in_flight()).count();
+                    return Err(CapacityExhausted {
+                        capacity: self.capacity,
+                        in_flight,
+                    });
+                }
             }
         }
 
@@ -145,9 +170,14 @@ impl<K: Hash + Eq, V, N: Now> Cache<K, V, N> {
 
 impl<'a, K: Hash + Eq + 'a, V: 'a, N: Now + 'a> Reserve<'a, K, V, N> {
     /// Stores a route in the cache.
-    pub fn store(self, key: K, val: V) {
+    ///
+    /// Returns a handle that prevents the route from being evicted while it is
+    /// held.
+    pub fn store(self, key: K, val: V) -> InFlight {
         let node = Node::new(val.into(), self.now.now());
+        let in_flight = node.in_flight();
         self.vals.insert(key, node);
+        in_flight
     }
 }
 
@@ -167,6 +197,12 @@ impl<'a, T: 'a, N: Now + 'a> DerefMut for Access<'a, T, N> {
 }
 
 impl<'a, T: 'a, N: Now + 'a> Access<'a, T, N> {
+    /// Returns a handle that prevents the value from being evicted while it is
+    /// held.
+    pub fn in_flight(&self) -> InFlight {
+        self.node.in_flight()
+    }
+
     #[cfg(test)]
     fn last_access(&self) -> Instant {
         self.node.last_access
@@ -183,7 +219,11 @@ impl<'a, T: 'a, N: Now + 'a> Drop for Access<'a, T, N> {
 
 impl<T> Node<T> {
     pub fn new(value: T, last_access: Instant) -> Self {
-        Node { value, last_access }
+        Node {
+            value,
+            last_access,
+            in_flight: Arc::new(()),
+        }
     }
 
     pub fn access<'a, N: Now + 'a>(&'a mut self, now: &'a N) -> Access<'a, T, N> {
@@ -193,6 +233,14 @@ impl<T> Node<T> {
     pub fn last_access(&self) -> Instant {
         self.last_access
     }
+
+    fn in_flight(&self) -> InFlight {
+        InFlight(self.in_flight.clone())
+    }
+
+    fn is_in_flight(&self) -> bool {
+        Arc::strong_count(&self.in_flight) > 1
+    }
 }
 
 impl<T> Deref for Node<T> {
@@ -271,7 +319,10 @@ mod tests {
 
         assert_eq!(
             cache.reserve().err(),
-            Some(CapacityExhausted { capacity: 2 })
+            Some(CapacityExhausted {
+                capacity: 2,
+                in_flight: 0,
+            })
         );
         assert_eq!(cache.vals.len(), 2);
     }
@@ -328,7 +379,10 @@ mod tests {
             .store(1, MultiplyAndAssign::default());
         assert_eq!(
             cache.reserve().err(),
-            Some(CapacityExhausted { capacity: 1 })
+            Some(CapacityExhausted {
+                capacity: 1,
+                in_flight: 0,
+            })
         );
         assert_eq!(cache.vals.len(), 1);
 
@@ -336,7 +390,10 @@ mod tests {
         clock.advance(Duration::from_secs(1));
         assert_eq!(
             cache.reserve().err(),
-            Some(CapacityExhausted { capacity: 1 })
+            Some(CapacityExhausted {
+                capacity: 1,
+                in_flight: 0,
+            })
         );
         assert_eq!(cache.vals.len(), 1);
 
@@ -344,7 +401,10 @@ mod tests {
         clock.advance(Duration::from_secs(1));
         assert_eq!(
             cache.reserve().err(),
-            Some(CapacityExhausted { capacity: 1 })
+            Some(CapacityExhausted {
+                capacity: 1,
+                in_flight: 0,
+            })
         );
         assert_eq!(cache.vals.len(), 1);
 
@@ -354,6 +414,61 @@ mod tests {
         assert_eq!(cache.vals.len(), 0);
     }
 
+    #[test]
+    fn reserve_evicts_least_recently_used() {
+        let mut clock = Clock::default();
+        let mut cache =
+            Cache::<_, MultiplyAndAssign>::new(2, Duration::from_secs(0)).with_clock(clock.clone());
+
+        cache
+            .reserve()
+            .expect("capacity")
+            .store(1, MultiplyAndAssign::default());
+        clock.advance(Duration::from_secs(1));
+        cache
+            .reserve()
+            .expect("capacity")
+            .store(2, MultiplyAndAssign::default());
+
+        // Touch `1` so that `2` is the least recently used.
+        clock.advance(Duration::from_secs(1));
+        drop(cache.access(&1));
+
+        clock.advance(Duration::from_secs(1));
+        cache
+            .reserve()
+            .expect("capacity")
+            .store(3, MultiplyAndAssign::default());
+        assert!(cache.access(&1).is_some());
+        assert!(cache.access(&2).is_none());
+        assert!(cache.access(&3).is_some());
+    }
+
+    #[test]
+    fn reserve_never_evicts_in_flight() {
+        let mut clock = Clock::default();
+        let mut cache =
+            Cache::<_, MultiplyAndAssign>::new(1, Duration::from_secs(0)).with_clock(clock.clone());
+
+        let in_flight = cache
+            .reserve()
+            .expect("capacity")
+            .store(1, MultiplyAndAssign::default());
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(
+            cache.reserve().err(),
+            Some(CapacityExhausted {
+                capacity: 1,
+                in_flight: 1,
+            })
+        );
+
+        drop(in_flight);
+        assert!(cache.reserve().is_ok());
+        assert_eq!(cache.vals.len(), 0);
+    }
+
     #[test]
     fn last_access() {
         let mut clock = Clock::default();
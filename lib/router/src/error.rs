@@ -3,7 +3,11 @@ use std::fmt;
 pub(crate) type Error = Box<dyn std::error::Error + Send + Sync>;
 
 #[derive(Debug)]
-pub struct NoCapacity(pub usize);
+pub struct NoCapacity {
+    pub capacity: usize,
+    /// The number of routes that could not be evicted due to in-flight requests.
+    pub in_flight: usize,
+}
 
 #[derive(Debug)]
 pub struct NotRecognized;
@@ -13,7 +17,12 @@ pub struct MakeRoute(pub(crate) Error);
 
 impl fmt::Display for NoCapacity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "router capacity reached ({})", self.0)
+        write!(
+            f,
+            "router capacity reached ({} routes, {} with in-flight requests); \
+             idle routes are evicted after the router's max idle age",
+            self.capacity, self.in_flight
+        )
     }
 }
 
@@ -39,11 +39,19 @@ pub trait Recognize<Request> {
     fn recognize(&self, req: &Request) -> Option<Self::Target>;
 }
 
+/// A request that could not be routed because the router is at capacity.
+pub struct Overflowed<Req> {
+    pub request: Req,
+    pub error: error::NoCapacity,
+}
+
 pub struct ResponseFuture<Req, Svc>
 where
     Svc: svc::Service<Req>,
 {
     state: State<Req, Svc>,
+    /// Prevents the route from being evicted while the request is in flight.
+    _in_flight: Option<cache::InFlight>,
 }
 
 struct Inner<Req, Rec, Stk>
@@ -127,24 +135,50 @@ where
     ///
     /// The response fails when the request cannot be routed.
     fn call(&mut self, request: Req) -> Self::Future {
+        match self.route(request) {
+            Ok(future) => future,
+            Err(overflowed) => ResponseFuture::error(overflowed.error.into()),
+        }
+    }
+}
+
+impl<Req, Rec, Stk, Svc> Router<Req, Rec, Stk>
+where
+    Rec: Recognize<Req>,
+    Stk: stack::Stack<Rec::Target, Value = Svc>,
+    Stk::Error: Into<error::Error>,
+    Svc: svc::Service<Req> + Clone,
+    Svc::Error: Into<error::Error>,
+{
+    /// Routes the request through an underlying service, like `call`, except
+    /// that the request is returned if the router has no capacity for a new
+    /// route.
+    pub fn route(&mut self, request: Req) -> Result<ResponseFuture<Req, Svc>, Overflowed<Req>> {
         let target = match self.inner.recognize.recognize(&request) {
             Some(target) => target,
-            None => return ResponseFuture::not_recognized(),
+            None => return Ok(ResponseFuture::not_recognized()),
         };
 
         let cache = &mut *self.inner.cache.lock().expect("lock router cache");
 
         // First, try to load a cached route for `target`.
         if let Some(service) = cache.access(&target) {
-            return ResponseFuture::new(request, service.clone());
+            return ResponseFuture::new(request, service.clone(), service.in_flight());
         }
 
         // Since there wasn't a cached route, ensure that there is capacity for a
         // new one.
         let reserve = match cache.reserve() {
             Ok(r) => r,
-            Err(cache::CapacityExhausted { capacity }) => {
-                return ResponseFuture::no_capacity(capacity);
+            Err(cache::CapacityExhausted {
+                capacity,
+                in_flight,
+            }) => {
+                let error = error::NoCapacity {
+                    capacity,
+                    in_flight,
+                };
+                return Err(Overflowed { request, error });
             }
         };
 
@@ -152,12 +186,29 @@ where
         let service = match self.inner.make.make(&target) {
             Ok(svc) => svc,
             Err(e) => {
-                return ResponseFuture::route_error(e);
+                return Ok(ResponseFuture::route_error(e));
             }
         };
 
-        reserve.store(target, service.clone());
-        ResponseFuture::new(request, service)
+        let in_flight = reserve.store(target, service.clone());
+        Ok(ResponseFuture::new(request, service, in_flight))
+    }
+
+    /// Routes the request through a new service that is not cached, e.g. when
+    /// the router has no capacity for a new route.
+    pub fn route_uncached(&mut self, request: Req) -> ResponseFuture<Req, Svc> {
+        let target = match self.inner.recognize.recognize(&request) {
+            Some(target) => target,
+            None => return ResponseFuture::not_recognized(),
+        };
+
+        match self.inner.make.make(&target) {
+            Ok(service) => ResponseFuture {
+                state: State::NotReady(request, service),
+                _in_flight: None,
+            },
+            Err(e) => ResponseFuture::route_error(e),
+        }
     }
 }
 
@@ -180,15 +231,17 @@ impl<Req, Svc> ResponseFuture<Req, Svc>
 where
     Svc: svc::Service<Req>,
 {
-    fn new(req: Req, svc: Svc) -> Self {
+    fn new(req: Req, svc: Svc, in_flight: cache::InFlight) -> Self {
         ResponseFuture {
             state: State::NotReady(req, svc),
+            _in_flight: Some(in_flight),
         }
     }
 
     fn error(err: error::Error) -> Self {
         ResponseFuture {
             state: State::Error(err),
+            _in_flight: None,
         }
     }
 
@@ -199,10 +252,6 @@ where
     fn not_recognized() -> Self {
         Self::error(error::NotRecognized.into())
     }
-
-    fn no_capacity(capacity: usize) -> Self {
-        Self::error(error::NoCapacity(capacity).into())
-    }
 }
 
 impl<Req, Svc> Future for ResponseFuture<Req, Svc>
@@ -401,11 +450,32 @@ mod tests {
         assert_eq!(
             rsp.downcast_ref::<error::NoCapacity>()
                 .expect("error should be NoCapacity")
-                .0,
+                .capacity,
             1
         );
     }
 
+    #[test]
+    fn overflowed_requests_are_returned() {
+        let mut router = Router::new(Recognize, Recognize, 1, Duration::from_secs(1));
+
+        let rsp = router.call_ok(2);
+        assert_eq!(rsp, 2);
+
+        let overflowed = match router.route(Request::from(3)) {
+            Ok(_) => panic!("router should be at capacity"),
+            Err(overflowed) => overflowed,
+        };
+        assert_eq!(overflowed.error.capacity, 1);
+
+        let rsp = router.route_uncached(overflowed.request).wait();
+        assert_eq!(rsp.expect("uncached route should succeed"), 3);
+
+        // The uncached route did not displace the cached one.
+        let rsp = router.call_ok(2);
+        assert_eq!(rsp, 4);
+    }
+
     #[test]
     fn services_cached() {
         let mut router = Router::new(Recognize, Recognize, 1, Duration::from_secs(0));
@@ -9,6 +9,8 @@ use std::{error::Error as StdError, fmt, io, sync::Arc};
 
 pub use futures::future::Executor;
 use futures::future::{ExecuteError, ExecuteErrorKind, Future};
+use futures::sync::oneshot;
+use futures::{Async, Poll};
 
 pub use tokio::spawn;
 use tokio::{
@@ -59,6 +61,17 @@ pub enum MainRuntime {
     ThreadPool(thread_pool::Runtime),
 }
 
+/// Cancels a background task when dropped.
+///
+/// The handle is owned by the task's consumer, so that the task runs no longer
+/// than the consumer needs it to.
+#[derive(Debug)]
+pub struct Handle(oneshot::Sender<()>);
+
+/// Completes when the corresponding `Handle` is dropped.
+#[derive(Debug)]
+pub struct Canceled(oneshot::Receiver<()>);
+
 /// Like a `SpawnError` or `ExecuteError`, but with an implementation
 /// of `std::error::Error`.
 #[derive(Debug, Clone)]
@@ -206,6 +219,39 @@ impl fmt::Debug for ArcExecutor {
         f.pad("ArcExecutor")
     }
 }
+
+/// Creates a `Handle` and the `Canceled` future that completes once the handle
+/// is dropped.
+pub fn cancelable() -> (Handle, Canceled) {
+    let (tx, rx) = oneshot::channel();
+    (Handle(tx), Canceled(rx))
+}
+
+// ===== impl Canceled =====
+
+impl Canceled {
+    /// Returns true if the handle has been dropped.
+    ///
+    /// If it has not, the current task is notified when it is.
+    pub fn poll_canceled(&mut self) -> bool {
+        self.poll().map(|a| a.is_ready()).unwrap_or(true)
+    }
+}
+
+impl Future for Canceled {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        // The handle never sends, so the receiver only completes when the
+        // handle is dropped.
+        match self.0.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) | Err(oneshot::Canceled) => Ok(Async::Ready(())),
+        }
+    }
+}
+
 // ===== impl MainRuntime =====
 
 impl MainRuntime {
@@ -0,0 +1,517 @@
+//! Writes a structured access log.
+//!
+//! One JSON record is written per request, once its response completes,
+//! describing the client, the route, and the response. Records are handed to a
+//! dedicated writer thread over a bounded channel; when the writer falls
+//! behind, records are dropped rather than applying backpressure to requests.
+
+use bytes::Buf;
+use futures::{Async, Future, Poll};
+use http;
+use hyper::body::Payload;
+use std::fmt::{self, Write as FmtWrite};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tower_http_service;
+
+use proxy::http::request_id;
+use proxy::server::Source;
+use svc;
+use Conditional;
+
+use super::dst::{Direction, Route};
+
+/// Where access log records are written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    /// An already-open file descriptor, e.g. `fd:3`.
+    Fd(i32),
+    /// A file that is created or appended to.
+    Path(PathBuf),
+}
+
+/// A handle to the access log writer.
+#[derive(Clone, Debug)]
+pub struct AccessLog {
+    tx: Option<mpsc::SyncSender<Record>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+/// Describes a completed request.
+#[derive(Debug)]
+struct Record {
+    timestamp: SystemTime,
+    direction: Direction,
+    request_id: Option<String>,
+    client_id: Option<String>,
+    client_addr: Option<SocketAddr>,
+    authority: String,
+    route: Vec<(String, String)>,
+    status: Option<http::StatusCode>,
+    latency: Duration,
+    response_bytes: u64,
+}
+
+pub fn layer(log: AccessLog) -> Layer {
+    Layer { log }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    log: AccessLog,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    log: AccessLog,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    direction: Direction,
+    authority: String,
+    route: Vec<(String, String)>,
+    log: AccessLog,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    pending: Option<Pending>,
+}
+
+/// Request metadata retained until the response completes.
+struct Pending {
+    record: Record,
+    started_at: Instant,
+    log: AccessLog,
+}
+
+/// Counts the bytes of a response body, and logs the request when the body is
+/// dropped.
+#[derive(Debug, Default)]
+pub struct ResponseBody<B> {
+    inner: B,
+    logged: Option<(Record, AccessLog)>,
+}
+
+// === impl Destination ===
+
+impl Destination {
+    fn open(&self) -> io::Result<File> {
+        match self {
+            Destination::Fd(fd) => from_raw_fd(*fd),
+            Destination::Path(ref path) => OpenOptions::new().create(true).append(true).open(path),
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Destination::Fd(fd) => write!(f, "fd:{}", fd),
+            Destination::Path(ref path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn from_raw_fd(fd: i32) -> io::Result<File> {
+    use std::os::unix::io::FromRawFd;
+
+    // The file descriptor is owned by the access log from now on.
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn from_raw_fd(_: i32) -> io::Result<File> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "file descriptors are only supported on unix",
+    ))
+}
+
+// === impl AccessLog ===
+
+impl AccessLog {
+    /// Opens `dst` and spawns a thread that writes up to `capacity` buffered
+    /// records to it.
+    ///
+    /// If no destination is configured, requests are not logged.
+    pub fn new(dst: Option<&Destination>, capacity: usize) -> io::Result<Self> {
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let dst = match dst {
+            Some(dst) => dst,
+            None => return Ok(Self { tx: None, dropped }),
+        };
+
+        let file = dst.open()?;
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let writer_dropped = dropped.clone();
+        thread::Builder::new()
+            .name("access-log".into())
+            .spawn(move || write_records(rx, file, &writer_dropped))?;
+        info!("writing access log to {}", dst);
+
+        Ok(Self {
+            tx: Some(tx),
+            dropped,
+        })
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    fn log(&self, record: Record) {
+        if let Some(ref tx) = self.tx {
+            if let Err(mpsc::TrySendError::Full(_)) = tx.try_send(record) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Writes records to `w` until all senders have been dropped.
+///
+/// Buffered records are written in batches, with a flush after each batch.
+fn write_records<W: Write>(rx: mpsc::Receiver<Record>, w: W, dropped: &AtomicUsize) {
+    let mut w = io::BufWriter::new(w);
+    let mut line = String::new();
+
+    while let Ok(record) = rx.recv() {
+        let batch = ::std::iter::once(record).chain(rx.try_iter());
+        for record in batch {
+            line.clear();
+            let _ = writeln!(line, "{}", record);
+            if let Err(e) = w.write_all(line.as_bytes()) {
+                warn!("failed to write access log: {}", e);
+            }
+        }
+        if let Err(e) = w.flush() {
+            warn!("failed to flush access log: {}", e);
+        }
+
+        let n = dropped.swap(0, Ordering::Relaxed);
+        if n > 0 {
+            warn!("dropped {} access log records", n);
+        }
+    }
+}
+
+// === impl Record ===
+
+/// Formats a record as a single-line JSON object.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ts = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        let direction = match self.direction {
+            Direction::In => "inbound",
+            Direction::Out => "outbound",
+        };
+
+        write!(
+            f,
+            "{{\"timestamp\":{}.{:03},\"direction\":\"{}\",\"request_id\":",
+            ts.as_secs(),
+            ts.subsec_millis(),
+            direction,
+        )?;
+        match self.request_id {
+            Some(ref id) => write!(f, "\"{}\"", Escape(id))?,
+            None => f.write_str("null")?,
+        }
+
+        f.write_str(",\"client_id\":")?;
+        match self.client_id {
+            Some(ref id) => write!(f, "\"{}\"", Escape(id))?,
+            None => f.write_str("null")?,
+        }
+
+        f.write_str(",\"client_addr\":")?;
+        match self.client_addr {
+            Some(addr) => write!(f, "\"{}\"", addr)?,
+            None => f.write_str("null")?,
+        }
+
+        write!(
+            f,
+            ",\"authority\":\"{}\",\"route\":{{",
+            Escape(&self.authority)
+        )?;
+        for (i, (k, v)) in self.route.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "\"{}\":\"{}\"", Escape(k), Escape(v))?;
+        }
+
+        f.write_str("},\"status\":")?;
+        match self.status {
+            Some(status) => write!(f, "{}", status.as_u16())?,
+            None => f.write_str("null")?,
+        }
+
+        let latency_ms = self.latency.as_secs() * 1_000 + u64::from(self.latency.subsec_millis());
+        write!(
+            f,
+            ",\"latency_ms\":{},\"response_bytes\":{}}}",
+            latency_ms, self.response_bytes,
+        )
+    }
+}
+
+/// Escapes a string for inclusion in a JSON string literal.
+pub(super) struct Escape<'a>(pub(super) &'a str);
+
+impl<'a> fmt::Display for Escape<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Route, Route, M> for Layer
+where
+    M: svc::Stack<Route>,
+{
+    type Value = <Stack<M> as svc::Stack<Route>>::Value;
+    type Error = <Stack<M> as svc::Stack<Route>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            log: self.log.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Route> for Stack<M>
+where
+    M: svc::Stack<Route>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &Route) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        let route = target
+            .route
+            .labels()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Ok(Service {
+            inner,
+            direction: target.dst_addr.direction(),
+            authority: target.dst_addr.to_string(),
+            route,
+            log: self.log.clone(),
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let pending = if self.log.is_enabled() {
+            let source = req.extensions().get::<Source>();
+            let client_id = source.and_then(|src| match src.tls_peer {
+                Conditional::Some(ref id) => Some(id.as_ref().to_owned()),
+                Conditional::None(_) => None,
+            });
+            // When the connection was proxied by a load balancer, this is the
+            // address described by its PROXY protocol header.
+            let client_addr = source.map(|src| src.remote);
+            Some(Pending {
+                record: Record {
+                    timestamp: SystemTime::now(),
+                    direction: self.direction,
+                    request_id: request_id::get(&req).map(String::from),
+                    client_id,
+                    client_addr,
+                    authority: self.authority.clone(),
+                    route: self.route.clone(),
+                    status: None,
+                    latency: Duration::from_secs(0),
+                    response_bytes: 0,
+                },
+                started_at: Instant::now(),
+                log: self.log.clone(),
+            })
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            pending,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<ResponseBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = match self.inner.poll() {
+            Ok(Async::Ready(rsp)) => rsp,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                // Requests that fail without a response are logged
+                // immediately, without a status.
+                if let Some(mut p) = self.pending.take() {
+                    p.record.latency = p.started_at.elapsed();
+                    p.log.log(p.record);
+                }
+                return Err(e);
+            }
+        };
+
+        let logged = self.pending.take().map(|mut p| {
+            p.record.status = Some(rsp.status());
+            p.record.latency = p.started_at.elapsed();
+            (p.record, p.log)
+        });
+
+        Ok(Async::Ready(
+            rsp.map(|inner| ResponseBody { inner, logged }),
+        ))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B: Payload> Payload for ResponseBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = try_ready!(self.inner.poll_data());
+
+        if let Some((ref mut record, _)) = self.logged {
+            if let Some(ref d) = data {
+                record.response_bytes += d.remaining() as u64;
+            }
+        }
+
+        Ok(Async::Ready(data))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+impl<B: Payload> tower_http_service::Body for ResponseBody<B> {
+    type Item = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_buf(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B> Drop for ResponseBody<B> {
+    fn drop(&mut self) {
+        if let Some((record, log)) = self.logged.take() {
+            log.log(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_records_as_json() {
+        let record = Record {
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_500),
+            direction: Direction::In,
+            request_id: Some("5f0c6d4e3b2a1908f7e6d5c4b3a29180".into()),
+            client_id: Some("web.ns.serviceaccount.identity.linkerd.cluster.local".into()),
+            client_addr: Some("10.1.2.3:45678".parse().unwrap()),
+            authority: "books.ns.svc.cluster.local:8080".into(),
+            route: vec![("route".into(), "GET /books/{id}".into())],
+            status: Some(http::StatusCode::NOT_FOUND),
+            latency: Duration::from_millis(12),
+            response_bytes: 42,
+        };
+
+        assert_eq!(
+            record.to_string(),
+            "{\"timestamp\":1.500,\"direction\":\"inbound\",\
+             \"request_id\":\"5f0c6d4e3b2a1908f7e6d5c4b3a29180\",\
+             \"client_id\":\"web.ns.serviceaccount.identity.linkerd.cluster.local\",\
+             \"client_addr\":\"10.1.2.3:45678\",\
+             \"authority\":\"books.ns.svc.cluster.local:8080\",\
+             \"route\":{\"route\":\"GET /books/{id}\"},\
+             \"status\":404,\"latency_ms\":12,\"response_bytes\":42}"
+        );
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(
+            Escape("a\"b\\c\n\u{1}").to_string(),
+            "a\\\"b\\\\c\\n\\u0001"
+        );
+    }
+}
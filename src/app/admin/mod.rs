@@ -1,13 +1,30 @@
 //! Serves an HTTP/1.1. admin server.
 //!
-//! * `/metrics` -- reports prometheus-formatted metrics.
+//! * `/metrics` -- reports prometheus-formatted metrics, unless disabled.
 //! * `/ready` -- returns 200 when the proxy is ready to participate in meshed traffic.
+//! * `/deprecations` -- lists deprecated configuration that is in use.
+//! * `/debug/failures` -- lists recently captured route failures.
+//! * `/identity` -- describes the local identity's current certificate as JSON.
+//! * `POST /identity/refresh` -- forces the proxy to refresh its certificate.
+//! * `/drain` -- describes the progress of a shutdown as JSON.
+//! * `/trace-sampling` -- lists the trace samplers that override the global
+//!   sampler for profile routes. `PUT ?route=<route>&sampler=<sampler>` sets a
+//!   route's sampler, and `DELETE ?route=<route>` removes it.
 
 use futures::future::{self, FutureResult};
-use http::StatusCode;
+use http::{header, Method, StatusCode};
 use hyper::{service::Service, Body, Request, Response};
+use std::fmt::Write;
 use std::io;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
+use super::access_log::Escape;
+use super::capture::Captures;
+use super::config::Deprecation;
+use super::identity;
+use super::trace::RouteSamplers;
+use drain;
 use metrics;
 
 mod readiness;
@@ -19,17 +36,56 @@ where
     M: metrics::FmtMetrics,
 {
     metrics: metrics::Serve<M>,
+    prometheus: bool,
     ready: Readiness,
+    deprecations: Arc<Vec<Deprecation>>,
+    captures: Captures,
+    identity: Option<identity::Local>,
+    identity_refresh: Option<identity::Refresh>,
+    drain: drain::Progress,
+    trace_sampling: Option<RouteSamplers>,
 }
 
 impl<M> Admin<M>
 where
     M: metrics::FmtMetrics,
 {
-    pub fn new(m: M, ready: Readiness) -> Self {
+    pub fn new(
+        m: M,
+        ready: Readiness,
+        deprecations: Vec<Deprecation>,
+        captures: Captures,
+        identity: Option<identity::Local>,
+        identity_refresh: Option<identity::Refresh>,
+    ) -> Self {
         Self {
             metrics: metrics::Serve::new(m),
+            prometheus: true,
             ready,
+            deprecations: Arc::new(deprecations),
+            captures,
+            identity,
+            identity_refresh,
+            drain: drain::Progress::default(),
+            trace_sampling: None,
+        }
+    }
+
+    pub fn with_drain_progress(self, drain: drain::Progress) -> Self {
+        Self { drain, ..self }
+    }
+
+    /// When disabled, `/metrics` is not served, e.g. because metrics are
+    /// pushed elsewhere.
+    pub fn with_prometheus(self, prometheus: bool) -> Self {
+        Self { prometheus, ..self }
+    }
+
+    /// Allows per-route trace samplers to be changed, if tracing is enabled.
+    pub fn with_trace_sampling(self, trace_sampling: Option<RouteSamplers>) -> Self {
+        Self {
+            trace_sampling,
+            ..self
         }
     }
 
@@ -46,6 +102,189 @@ where
                 .expect("builder with known status code must not fail")
         }
     }
+
+    fn deprecations_rsp(&self) -> Response<Body> {
+        let body = self
+            .deprecations
+            .iter()
+            .map(|d| format!("{}\n", d))
+            .collect::<String>();
+        Response::builder()
+            .status(StatusCode::OK)
+            .body(body.into())
+            .expect("builder with known status code must not fail")
+    }
+
+    fn failures_rsp(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::OK)
+            .body(self.captures.to_string().into())
+            .expect("builder with known status code must not fail")
+    }
+
+    fn identity_rsp(&self) -> Response<Body> {
+        let local = match self.identity {
+            Some(ref local) => local,
+            None => return text_rsp(StatusCode::NOT_FOUND, "identity is disabled\n"),
+        };
+        let crt_key = match local.crt_key() {
+            Some(crt_key) => crt_key,
+            None => {
+                return text_rsp(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "no certificate has been obtained\n",
+                );
+            }
+        };
+        let info = match crt_key.info() {
+            Some(info) => info,
+            None => {
+                return text_rsp(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "the certificate could not be read\n",
+                );
+            }
+        };
+
+        let not_after = info
+            .not_after
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut body = format!(
+            "{{\"name\":\"{}\",\"serial\":\"{}\",\"issuer\":\"{}\",\"not_after\":{},\"sans\":[",
+            Escape(local.name().as_ref()),
+            Escape(&info.serial),
+            Escape(&info.issuer),
+            not_after,
+        );
+        for (i, san) in info.sans.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "," };
+            write!(body, "{}\"{}\"", sep, Escape(san)).expect("writing to a string must not fail");
+        }
+        body.push_str("]}\n");
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .expect("builder with known status code must not fail")
+    }
+
+    fn identity_refresh_rsp(&self, method: &Method) -> Response<Body> {
+        let (status, body) = match self.identity_refresh {
+            None => (StatusCode::NOT_FOUND, "identity is disabled\n".to_owned()),
+            Some(_) if *method != Method::POST => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                "method not allowed\n".to_owned(),
+            ),
+            Some(ref refresh) => match refresh.request() {
+                Ok(()) => (StatusCode::ACCEPTED, "refreshing\n".to_owned()),
+                Err(identity::RateLimited(wait)) => (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!("refreshed too recently; retry in {}s\n", wait.as_secs() + 1),
+                ),
+            },
+        };
+        Response::builder()
+            .status(status)
+            .body(body.into())
+            .expect("builder with known status code must not fail")
+    }
+
+    fn drain_rsp(&self) -> Response<Body> {
+        let body = format!(
+            "{{\"draining\":{},\"pending\":{}}}\n",
+            self.drain.is_draining(),
+            self.drain.pending(),
+        );
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .expect("builder with known status code must not fail")
+    }
+
+    fn trace_sampling_rsp(&self, method: &Method, query: Option<&str>) -> Response<Body> {
+        let samplers = match self.trace_sampling {
+            Some(ref samplers) => samplers,
+            None => return text_rsp(StatusCode::NOT_FOUND, "tracing is disabled\n"),
+        };
+
+        if *method == Method::GET {
+            return Response::builder()
+                .status(StatusCode::OK)
+                .body(samplers.to_string().into())
+                .expect("builder with known status code must not fail");
+        }
+        if *method != Method::PUT && *method != Method::DELETE {
+            return text_rsp(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+        }
+
+        let route = match query.and_then(|q| query_param(q, "route")) {
+            Some(route) => route,
+            None => return text_rsp(StatusCode::BAD_REQUEST, "a route must be specified\n"),
+        };
+
+        if *method == Method::DELETE {
+            return if samplers.remove(&route) {
+                text_rsp(StatusCode::OK, "removed\n")
+            } else {
+                text_rsp(StatusCode::NOT_FOUND, "the route has no sampler\n")
+            };
+        }
+
+        match query
+            .and_then(|q| query_param(q, "sampler"))
+            .and_then(|s| s.parse().ok())
+        {
+            Some(sampler) => {
+                samplers.set(route, sampler);
+                text_rsp(StatusCode::OK, "updated\n")
+            }
+            None => text_rsp(
+                StatusCode::BAD_REQUEST,
+                "the sampler must be always, never, parent, or a ratio\n",
+            ),
+        }
+    }
+}
+
+/// Returns the percent-decoded value of the `name` parameter in `query`.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let mut kv = pair.splitn(2, '=');
+        if kv.next()? == name {
+            percent_decode(kv.next().unwrap_or(""))
+        } else {
+            None
+        }
+    })
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        match s.as_bytes()[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = s.get(i + 1..i + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 2;
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn text_rsp(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(body.into())
+        .expect("builder with known status code must not fail")
 }
 
 impl<M> Service for Admin<M>
@@ -59,8 +298,16 @@ where
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
         match req.uri().path() {
-            "/metrics" => self.metrics.call(req),
+            "/metrics" if self.prometheus => self.metrics.call(req),
             "/ready" => future::ok(self.ready_rsp()),
+            "/deprecations" => future::ok(self.deprecations_rsp()),
+            "/debug/failures" => future::ok(self.failures_rsp()),
+            "/identity" => future::ok(self.identity_rsp()),
+            "/identity/refresh" => future::ok(self.identity_refresh_rsp(req.method())),
+            "/drain" => future::ok(self.drain_rsp()),
+            "/trace-sampling" => {
+                future::ok(self.trace_sampling_rsp(req.method(), req.uri().query()))
+            }
             _ => future::ok(
                 Response::builder()
                     .status(StatusCode::NOT_FOUND)
@@ -88,7 +335,7 @@ mod tests {
         let l1 = l0.clone();
 
         let mut rt = Runtime::new().unwrap();
-        let mut srv = Admin::new((), r);
+        let mut srv = Admin::new((), r, Vec::new(), Captures::new(0, 0.0), None, None);
         macro_rules! call {
             () => {{
                 let r = Request::builder()
@@ -109,4 +356,183 @@ mod tests {
         drop(l1);
         assert_eq!(call!().status(), StatusCode::OK);
     }
+
+    #[test]
+    fn identity_refresh_is_rate_limited() {
+        let (r, _l) = Readiness::new();
+        let refresh = identity::Refresh::new(Duration::from_secs(60));
+
+        let mut rt = Runtime::new().unwrap();
+        let mut srv = Admin::new(
+            (),
+            r,
+            Vec::new(),
+            Captures::new(0, 0.0),
+            None,
+            Some(refresh),
+        );
+        macro_rules! call {
+            ($method:expr) => {{
+                let r = Request::builder()
+                    .method($method)
+                    .uri("http://4.3.2.1:5678/identity/refresh")
+                    .body(Body::empty())
+                    .unwrap();
+                let f = srv.call(r);
+                rt.block_on_for(TIMEOUT, f).expect("call")
+            };};
+        }
+
+        assert_eq!(call!(Method::GET).status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(call!(Method::POST).status(), StatusCode::ACCEPTED);
+        assert_eq!(call!(Method::POST).status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn drain_describes_progress() {
+        use futures::Stream;
+
+        let (r, _l) = Readiness::new();
+        let (signal, watch) = drain::channel();
+        let mut rt = Runtime::new().unwrap();
+        let mut srv = Admin::new((), r, Vec::new(), Captures::new(0, 0.0), None, None)
+            .with_drain_progress(watch.progress());
+        macro_rules! call {
+            () => {{
+                let r = Request::builder()
+                    .method(Method::GET)
+                    .uri("http://4.3.2.1:5678/drain")
+                    .body(Body::empty())
+                    .unwrap();
+                let f = srv.call(r);
+                let rsp = rt.block_on_for(TIMEOUT, f).expect("call");
+                assert_eq!(rsp.status(), StatusCode::OK);
+                let body = rt
+                    .block_on_for(TIMEOUT, rsp.into_body().concat2())
+                    .expect("body");
+                ::std::str::from_utf8(&body).unwrap().to_owned()
+            };};
+        }
+
+        let _conn = watch.watch(future::empty::<(), ()>(), |_| {});
+        assert_eq!(call!(), "{\"draining\":false,\"pending\":1}\n");
+
+        let _drained = signal.drain();
+        assert_eq!(call!(), "{\"draining\":true,\"pending\":1}\n");
+    }
+
+    #[test]
+    fn metrics_may_be_disabled() {
+        let (r, _l) = Readiness::new();
+        let mut rt = Runtime::new().unwrap();
+        let mut srv =
+            Admin::new((), r, Vec::new(), Captures::new(0, 0.0), None, None).with_prometheus(false);
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri("http://4.3.2.1:5678/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let rsp = rt.block_on_for(TIMEOUT, srv.call(req)).expect("call");
+        assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn trace_sampling_sets_route_samplers() {
+        use super::super::trace::Sampler;
+        use futures::Stream;
+
+        let (r, _l) = Readiness::new();
+        let samplers = RouteSamplers::new(vec![("GET /books".to_owned(), Sampler::Always)]);
+        let mut rt = Runtime::new().unwrap();
+        let mut srv = Admin::new((), r, Vec::new(), Captures::new(0, 0.0), None, None)
+            .with_trace_sampling(Some(samplers.clone()));
+        macro_rules! call {
+            ($method:expr, $uri:expr) => {{
+                let r = Request::builder()
+                    .method($method)
+                    .uri($uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let f = srv.call(r);
+                rt.block_on_for(TIMEOUT, f).expect("call")
+            };};
+        }
+
+        let rsp = call!(
+            Method::PUT,
+            "http://4.3.2.1:5678/trace-sampling?route=GET%20/authors&sampler=0.5"
+        );
+        assert_eq!(rsp.status(), StatusCode::OK);
+        let rsp = call!(
+            Method::PUT,
+            "http://4.3.2.1:5678/trace-sampling?route=GET+/authors&sampler=sometimes"
+        );
+        assert_eq!(rsp.status(), StatusCode::BAD_REQUEST);
+        let rsp = call!(
+            Method::DELETE,
+            "http://4.3.2.1:5678/trace-sampling?route=GET%20%2Fbooks"
+        );
+        assert_eq!(rsp.status(), StatusCode::OK);
+
+        let rsp = call!(Method::GET, "http://4.3.2.1:5678/trace-sampling");
+        assert_eq!(rsp.status(), StatusCode::OK);
+        let body = rt
+            .block_on_for(TIMEOUT, rsp.into_body().concat2())
+            .expect("body");
+        assert_eq!(::std::str::from_utf8(&body).unwrap(), "GET /authors=0.5\n");
+        assert_eq!(samplers.to_string(), "GET /authors=0.5\n");
+    }
+
+    #[test]
+    fn identity_describes_crt() {
+        use futures::Stream;
+        use identity::test_util::FOO_NS1;
+        use std::path::PathBuf;
+
+        let config = identity::Config {
+            trust_anchors: FOO_NS1.trust_anchors(),
+            trust_anchors_file: None,
+            crl: None,
+            local_name: identity::Name::from_hostname(FOO_NS1.name.as_bytes()).unwrap(),
+            additional_names: vec![],
+            source: identity::Source::Files {
+                crt: PathBuf::new(),
+                key: PathBuf::new(),
+            },
+        };
+        let (local, mut crt_store, _ta_store) = identity::Local::new(&config);
+
+        let (r, _l) = Readiness::new();
+        let mut rt = Runtime::new().unwrap();
+        let mut srv = Admin::new((), r, Vec::new(), Captures::new(0, 0.0), Some(local), None);
+        macro_rules! call {
+            () => {{
+                let r = Request::builder()
+                    .method(Method::GET)
+                    .uri("http://4.3.2.1:5678/identity")
+                    .body(Body::empty())
+                    .unwrap();
+                let f = srv.call(r);
+                rt.block_on_for(TIMEOUT, f).expect("call")
+            };};
+        }
+
+        assert_eq!(call!().status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        crt_store
+            .store(Some(FOO_NS1.validate().expect("crt must be valid")))
+            .unwrap();
+        let rsp = call!();
+        assert_eq!(rsp.status(), StatusCode::OK);
+        let body = rt
+            .block_on_for(TIMEOUT, rsp.into_body().concat2())
+            .expect("body");
+        assert_eq!(
+            ::std::str::from_utf8(&body).unwrap(),
+            "{\"name\":\"foo.ns1.serviceaccount.identity.linkerd.cluster.local\",\
+             \"serial\":\"28247957eff46da4ea818c798ff3ca2f4afdc518\",\
+             \"issuer\":\"OU=None\",\"not_after\":1584122520,\
+             \"sans\":[\"foo.ns1.serviceaccount.identity.linkerd.cluster.local\"]}\n"
+        );
+    }
 }
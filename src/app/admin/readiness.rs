@@ -1,10 +1,18 @@
 use std::sync::{Arc, Weak};
 
+use super::super::identity;
+use super::super::node_drain::Draining;
+
 /// Tracks the processes's readiness to serve traffic.
 ///
-/// Once `is_ready()` returns true, it will never return false.
+/// Once all latches are released, the process is ready unless its node is
+/// draining or it has not yet obtained a certificate for its identity.
 #[derive(Clone, Debug)]
-pub struct Readiness(Weak<()>);
+pub struct Readiness {
+    latch: Weak<()>,
+    draining: Draining,
+    identity: Option<identity::Local>,
+}
 
 /// When all latches are dropped, the process is considered ready.
 #[derive(Clone, Debug)]
@@ -13,11 +21,35 @@ pub struct Latch(Arc<()>);
 impl Readiness {
     pub fn new() -> (Readiness, Latch) {
         let r = Arc::new(());
-        (Readiness(Arc::downgrade(&r)), Latch(r))
+        let readiness = Readiness {
+            latch: Arc::downgrade(&r),
+            draining: Draining::default(),
+            identity: None,
+        };
+        (readiness, Latch(r))
+    }
+
+    /// Reports that the process is not ready while `draining`.
+    pub fn with_node_drain(self, draining: Draining) -> Self {
+        Self { draining, ..self }
+    }
+
+    /// Reports that the process is not ready until `identity` has a
+    /// certificate.
+    pub fn with_identity(self, identity: identity::Local) -> Self {
+        Self {
+            identity: Some(identity),
+            ..self
+        }
     }
 
     pub fn is_ready(&self) -> bool {
-        self.0.upgrade().is_none()
+        self.latch.upgrade().is_none()
+            && !self.draining.is_draining()
+            && self
+                .identity
+                .as_ref()
+                .map_or(true, identity::Local::is_ready)
     }
 }
 
@@ -0,0 +1,496 @@
+//! Captures debug records of requests that fail.
+//!
+//! When a response is classified as a failure by its route's response
+//! classes, a sample of such requests is recorded, with their headers, timing,
+//! and endpoint, in a bounded ring buffer that is served by the admin server.
+//! This helps rare failures to be diagnosed after the fact, without having had
+//! to tap the proxy at the time.
+
+use futures::{Future, Poll};
+use http::{self, header};
+use rand;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use proxy::http::metrics::classify::{ClassifyEos, ClassifyResponse};
+use svc;
+
+use super::classify;
+use super::dst::Route;
+
+/// Headers whose values are credentials, and so are redacted from records.
+const SENSITIVE_HEADERS: &[header::HeaderName] = &[
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// A bounded buffer of captured failures.
+#[derive(Clone, Debug)]
+pub struct Captures {
+    records: Arc<Mutex<VecDeque<Record>>>,
+    capacity: usize,
+    sample_rate: f64,
+}
+
+/// A captured failure.
+#[derive(Debug)]
+struct Record {
+    captured_at: SystemTime,
+    route: String,
+    method: http::Method,
+    uri: http::Uri,
+    request_headers: http::HeaderMap,
+    status: http::StatusCode,
+    response_headers: http::HeaderMap,
+    latency: Duration,
+    endpoint: Option<Endpoint>,
+}
+
+/// Describes the endpoint that served a response.
+///
+/// Inserted into response extensions by `endpoint_layer`.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    addr: String,
+    latency: Duration,
+}
+
+/// Captures failures of each `Route`.
+pub fn layer(captures: Captures) -> Layer {
+    Layer { captures }
+}
+
+/// Annotates responses with the endpoint that served them, so that they may be
+/// captured by a route's `layer`.
+pub fn endpoint_layer() -> EndpointLayer {
+    EndpointLayer
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    captures: Captures,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    captures: Captures,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    route: String,
+    captures: Captures,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    pending: Option<Pending>,
+}
+
+/// Request metadata retained until the response is classified.
+struct Pending {
+    route: String,
+    classify: classify::Response,
+    method: http::Method,
+    uri: http::Uri,
+    request_headers: http::HeaderMap,
+    started_at: Instant,
+    captures: Captures,
+}
+
+#[derive(Clone, Debug)]
+pub struct EndpointLayer;
+
+#[derive(Clone, Debug)]
+pub struct EndpointStack<M> {
+    inner: M,
+}
+
+#[derive(Clone, Debug)]
+pub struct EndpointService<S> {
+    inner: S,
+    addr: String,
+}
+
+pub struct EndpointFuture<F> {
+    inner: F,
+    addr: String,
+    started_at: Instant,
+}
+
+// === impl Captures ===
+
+impl Captures {
+    pub fn new(capacity: usize, sample_rate: f64) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            sample_rate,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0 && self.sample_rate > 0.0
+    }
+
+    fn should_sample(&self) -> bool {
+        self.sample_rate >= 1.0 || rand::random::<f64>() < self.sample_rate
+    }
+
+    fn push(&self, record: Record) {
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() == self.capacity {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+}
+
+/// Formats captured failures, oldest first.
+impl fmt::Display for Captures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let records = match self.records.lock() {
+            Ok(records) => records,
+            Err(_) => return Ok(()),
+        };
+
+        for record in records.iter() {
+            writeln!(f, "{}", record)?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Record ===
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let captured_at = self
+            .captured_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        writeln!(
+            f,
+            "captured_at={} route={} status={} latency_ms={}",
+            captured_at,
+            self.route,
+            self.status.as_u16(),
+            as_millis(self.latency),
+        )?;
+
+        match self.endpoint {
+            Some(ref ep) => writeln!(
+                f,
+                "endpoint={} endpoint_latency_ms={}",
+                ep.addr,
+                as_millis(ep.latency),
+            )?,
+            None => writeln!(f, "endpoint=unknown")?,
+        }
+
+        writeln!(f, "> {} {}", self.method, self.uri)?;
+        for (name, value) in self.request_headers.iter() {
+            writeln!(f, "> {}: {:?}", name, value)?;
+        }
+        writeln!(f, "< {}", self.status)?;
+        for (name, value) in self.response_headers.iter() {
+            writeln!(f, "< {}: {:?}", name, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn as_millis(d: Duration) -> u64 {
+    d.as_secs() * 1_000 + u64::from(d.subsec_millis())
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Route, Route, M> for Layer
+where
+    M: svc::Stack<Route>,
+{
+    type Value = <Stack<M> as svc::Stack<Route>>::Value;
+    type Error = <Stack<M> as svc::Stack<Route>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            captures: self.captures.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Route> for Stack<M>
+where
+    M: svc::Stack<Route>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &Route) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        let labels = target
+            .labels()
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(Service {
+            inner,
+            route: format!("{}[{}]", target.dst_addr, labels),
+            captures: self.captures.clone(),
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        // Only responses classified by a route's response classes are
+        // captured.
+        let pending = req
+            .extensions()
+            .get::<classify::Response>()
+            .filter(|c| self.captures.is_enabled() && is_profile(c))
+            .map(|c| Pending {
+                route: self.route.clone(),
+                classify: c.clone(),
+                method: req.method().clone(),
+                uri: req.uri().clone(),
+                request_headers: redact(req.headers()),
+                started_at: Instant::now(),
+                captures: self.captures.clone(),
+            });
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            pending,
+        }
+    }
+}
+
+/// Copies headers, replacing the values of sensitive headers so that
+/// credentials are neither retained nor served by the admin server.
+fn redact(headers: &http::HeaderMap) -> http::HeaderMap {
+    let mut headers = headers.clone();
+    for name in SENSITIVE_HEADERS {
+        // Replaces all of the header's values with one.
+        if headers.contains_key(name) {
+            headers.insert(name.clone(), header::HeaderValue::from_static(REDACTED));
+        }
+    }
+    headers
+}
+
+fn is_profile(classify: &classify::Response) -> bool {
+    match classify {
+        classify::Response::Profile(..) => true,
+        _ => false,
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = try_ready!(self.inner.poll());
+
+        if let Some(p) = self.pending.take() {
+            let is_failure = p.classify.start(&rsp).eos(None).is_failure();
+            if is_failure && p.captures.should_sample() {
+                debug!("capturing failure; route={}", p.route);
+                p.captures.push(Record {
+                    captured_at: SystemTime::now(),
+                    route: p.route,
+                    method: p.method,
+                    uri: p.uri,
+                    request_headers: p.request_headers,
+                    status: rsp.status(),
+                    response_headers: redact(rsp.headers()),
+                    latency: p.started_at.elapsed(),
+                    endpoint: rsp.extensions().get::<Endpoint>().cloned(),
+                });
+            }
+        }
+
+        Ok(rsp.into())
+    }
+}
+
+// === impl EndpointLayer ===
+
+impl<T, M> svc::Layer<T, T, M> for EndpointLayer
+where
+    T: fmt::Display,
+    M: svc::Stack<T>,
+{
+    type Value = <EndpointStack<M> as svc::Stack<T>>::Value;
+    type Error = <EndpointStack<M> as svc::Stack<T>>::Error;
+    type Stack = EndpointStack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        EndpointStack { inner }
+    }
+}
+
+// === impl EndpointStack ===
+
+impl<T, M> svc::Stack<T> for EndpointStack<M>
+where
+    T: fmt::Display,
+    M: svc::Stack<T>,
+{
+    type Value = EndpointService<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(EndpointService {
+            inner,
+            addr: target.to_string(),
+        })
+    }
+}
+
+// === impl EndpointService ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for EndpointService<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = EndpointFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        EndpointFuture {
+            inner: self.inner.call(req),
+            addr: self.addr.clone(),
+            started_at: Instant::now(),
+        }
+    }
+}
+
+// === impl EndpointFuture ===
+
+impl<F, B> Future for EndpointFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+        rsp.extensions_mut().insert(Endpoint {
+            addr: self.addr.clone(),
+            latency: self.started_at.elapsed(),
+        });
+        Ok(rsp.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status: u16) -> Record {
+        Record {
+            captured_at: UNIX_EPOCH,
+            route: "web.svc.cluster.local:8080[]".into(),
+            method: http::Method::GET,
+            uri: "/".parse().unwrap(),
+            request_headers: http::HeaderMap::new(),
+            status: http::StatusCode::from_u16(status).unwrap(),
+            response_headers: http::HeaderMap::new(),
+            latency: Duration::from_millis(3),
+            endpoint: None,
+        }
+    }
+
+    #[test]
+    fn evicts_oldest_records() {
+        let captures = Captures::new(2, 1.0);
+        captures.push(record(500));
+        captures.push(record(502));
+        captures.push(record(503));
+
+        let records = captures.records.lock().unwrap();
+        let statuses = records
+            .iter()
+            .map(|r| r.status.as_u16())
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, vec![502, 503]);
+    }
+
+    #[test]
+    fn redacts_sensitive_headers() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer s3cr3t".parse().unwrap());
+        headers.insert(header::PROXY_AUTHORIZATION, "Basic s3cr3t".parse().unwrap());
+        headers.append(header::COOKIE, "session=s3cr3t".parse().unwrap());
+        headers.append(header::COOKIE, "other=s3cr3t".parse().unwrap());
+        headers.insert(header::USER_AGENT, "curl/7.64.0".parse().unwrap());
+
+        let mut rsp_headers = http::HeaderMap::new();
+        rsp_headers.insert(header::SET_COOKIE, "session=s3cr3t".parse().unwrap());
+
+        let mut rec = record(500);
+        rec.request_headers = redact(&headers);
+        rec.response_headers = redact(&rsp_headers);
+        let out = rec.to_string();
+
+        assert!(!out.contains("s3cr3t"), "{}", out);
+        assert!(out.contains("> authorization: \"[redacted]\""), "{}", out);
+        assert!(
+            out.contains("> proxy-authorization: \"[redacted]\""),
+            "{}",
+            out
+        );
+        assert!(out.contains("> cookie: \"[redacted]\""), "{}", out);
+        assert!(out.contains("< set-cookie: \"[redacted]\""), "{}", out);
+        assert!(out.contains("> user-agent: \"curl/7.64.0\""), "{}", out);
+        assert_eq!(
+            rec.request_headers.get_all(header::COOKIE).iter().count(),
+            1
+        );
+    }
+}
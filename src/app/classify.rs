@@ -3,21 +3,24 @@ use std::borrow::Cow;
 use h2;
 use http;
 
+use proxy::http::error_kind;
+use proxy::http::failfast;
+use proxy::http::load_shed;
 pub use proxy::http::metrics::classify::{self, layer, CanClassify};
-use proxy::http::profiles;
+use proxy::http::profiles::{self, RedirectClass};
 use proxy::http::timeout;
 
 #[derive(Clone, Debug)]
 pub enum Request {
-    Default,
-    Profile(profiles::ResponseClasses),
+    Default(RedirectClass),
+    Profile(profiles::ResponseClasses, RedirectClass),
 }
 
 #[derive(Clone, Debug)]
 pub enum Response {
-    Default,
+    Default(RedirectClass),
     Grpc,
-    Profile(profiles::ResponseClasses),
+    Profile(profiles::ResponseClasses, RedirectClass),
 }
 
 #[derive(Clone, Debug)]
@@ -45,23 +48,26 @@ pub enum Class {
 pub enum SuccessOrFailure {
     Success,
     Failure,
+    Neutral,
 }
 
 // === impl Request ===
 
-impl From<profiles::ResponseClasses> for Request {
-    fn from(classes: profiles::ResponseClasses) -> Self {
+impl Request {
+    /// Redirects are classified as successes unless `redirects` is set.
+    pub fn new(classes: profiles::ResponseClasses, redirects: Option<RedirectClass>) -> Self {
+        let redirects = redirects.unwrap_or(RedirectClass::Success);
         if classes.is_empty() {
-            Request::Default
+            Request::Default(redirects)
         } else {
-            Request::Profile(classes)
+            Request::Profile(classes, redirects)
         }
     }
 }
 
 impl Default for Request {
     fn default() -> Self {
-        Request::Default
+        Request::Default(RedirectClass::Success)
     }
 }
 
@@ -73,8 +79,8 @@ impl classify::Classify for Request {
 
     fn classify<B>(&self, req: &http::Request<B>) -> Self::ClassifyResponse {
         match self {
-            Request::Profile(classes) => Response::Profile(classes.clone()),
-            Request::Default => {
+            Request::Profile(classes, redirects) => Response::Profile(classes.clone(), *redirects),
+            Request::Default(redirects) => {
                 let is_grpc = req
                     .headers()
                     .get(http::header::CONTENT_TYPE)
@@ -85,7 +91,7 @@ impl classify::Classify for Request {
                 if is_grpc {
                     Response::Grpc
                 } else {
-                    Response::Default
+                    Response::Default(*redirects)
                 }
             }
         }
@@ -98,7 +104,7 @@ impl Default for Response {
     fn default() -> Self {
         // By default, simply perform HTTP classification. This only applies
         // when no `insert` layer is present.
-        Response::Default
+        Response::Default(RedirectClass::Success)
     }
 }
 
@@ -132,19 +138,31 @@ impl classify::ClassifyResponse for Response {
             return Eos::Error("timeout");
         }
 
+        if rsp.extensions().get::<load_shed::Shed>().is_some() {
+            return Eos::Error("load_shed");
+        }
+
+        if rsp.extensions().get::<failfast::FailFast>().is_some() {
+            return Eos::Error("failfast");
+        }
+
+        if let Some(kind) = rsp.extensions().get::<error_kind::Kind>() {
+            return Eos::Error(kind.name());
+        }
+
         match self {
-            Response::Default => grpc_class(rsp.headers())
+            Response::Default(redirects) => grpc_class(rsp.headers())
                 .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
-                .unwrap_or_else(|| Eos::Default(rsp.status())),
+                .unwrap_or_else(|| http_eos(rsp.status(), redirects)),
             Response::Grpc => grpc_class(rsp.headers())
                 .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
                 .unwrap_or(Eos::Grpc(GrpcEos::Open)),
-            Response::Profile(ref classes) => Self::match_class(rsp, classes.as_ref())
+            Response::Profile(ref classes, redirects) => Self::match_class(rsp, classes.as_ref())
                 .map(Eos::Profile)
                 .unwrap_or_else(|| {
                     grpc_class(rsp.headers())
                         .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
-                        .unwrap_or_else(|| Eos::Default(rsp.status()))
+                        .unwrap_or_else(|| http_eos(rsp.status(), redirects))
                 }),
         }
     }
@@ -182,6 +200,22 @@ impl classify::ClassifyEos for Eos {
     }
 }
 
+fn http_eos(status: http::StatusCode, redirects: RedirectClass) -> Eos {
+    if status.is_redirection() {
+        match redirects {
+            RedirectClass::Success => {}
+            RedirectClass::Failure => {
+                return Eos::Profile(Class::Default(SuccessOrFailure::Failure));
+            }
+            RedirectClass::Neutral => {
+                return Eos::Profile(Class::Default(SuccessOrFailure::Neutral));
+            }
+        }
+    }
+
+    Eos::Default(status)
+}
+
 fn grpc_class(headers: &http::HeaderMap) -> Option<Class> {
     headers
         .get("grpc-status")
@@ -218,6 +252,15 @@ impl Class {
             _ => false,
         }
     }
+
+    pub(super) fn is_neutral(&self) -> bool {
+        match self {
+            Class::Default(SuccessOrFailure::Neutral)
+            | Class::Grpc(SuccessOrFailure::Neutral, _)
+            | Class::Stream(SuccessOrFailure::Neutral, _) => true,
+            _ => false,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -225,12 +268,14 @@ mod tests {
     use http::{HeaderMap, Response, StatusCode};
 
     use super::{Class, SuccessOrFailure};
+    use proxy::http::error_kind;
     use proxy::http::metrics::classify::{ClassifyEos as _CE, ClassifyResponse as _CR};
+    use proxy::http::profiles::RedirectClass;
 
     #[test]
     fn http_response_status_ok() {
         let rsp = Response::builder().status(StatusCode::OK).body(()).unwrap();
-        let class = super::Response::Default.start(&rsp).eos(None);
+        let class = super::Response::default().start(&rsp).eos(None);
         assert_eq!(class, Class::Default(SuccessOrFailure::Success));
     }
 
@@ -240,7 +285,7 @@ mod tests {
             .status(StatusCode::BAD_REQUEST)
             .body(())
             .unwrap();
-        let class = super::Response::Default.start(&rsp).eos(None);
+        let class = super::Response::default().start(&rsp).eos(None);
         assert_eq!(class, Class::Default(SuccessOrFailure::Success));
     }
 
@@ -250,7 +295,7 @@ mod tests {
             .status(StatusCode::INTERNAL_SERVER_ERROR)
             .body(())
             .unwrap();
-        let class = super::Response::Default.start(&rsp).eos(None);
+        let class = super::Response::default().start(&rsp).eos(None);
         assert_eq!(class, Class::Default(SuccessOrFailure::Failure));
     }
 
@@ -302,9 +347,44 @@ mod tests {
         let mut trailers = HeaderMap::new();
         trailers.insert("grpc-status", 3.into());
 
-        let class = super::Response::Profile(Default::default())
+        let class = super::Response::Profile(Default::default(), RedirectClass::Success)
             .start(&rsp)
             .eos(Some(&trailers));
         assert_eq!(class, Class::Grpc(SuccessOrFailure::Failure, 3));
     }
+
+    #[test]
+    fn proxy_error_response_is_classified_by_kind() {
+        let mut rsp = Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .body(())
+            .unwrap();
+        rsp.extensions_mut().insert(error_kind::Kind::Connect);
+        let class = super::Response::default().start(&rsp).eos(None);
+        assert_eq!(
+            class,
+            Class::Stream(SuccessOrFailure::Failure, "connect".into())
+        );
+    }
+
+    #[test]
+    fn http_response_redirect_classes() {
+        let rsp = Response::builder()
+            .status(StatusCode::FOUND)
+            .body(())
+            .unwrap();
+        let classify = |redirects| super::Response::Default(redirects).start(&rsp).eos(None);
+        assert_eq!(
+            classify(RedirectClass::Success),
+            Class::Default(SuccessOrFailure::Success)
+        );
+        assert_eq!(
+            classify(RedirectClass::Failure),
+            Class::Default(SuccessOrFailure::Failure)
+        );
+        assert_eq!(
+            classify(RedirectClass::Neutral),
+            Class::Default(SuccessOrFailure::Neutral)
+        );
+    }
 }
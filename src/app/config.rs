@@ -1,19 +1,41 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::iter::FromIterator;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
-use indexmap::IndexSet;
+use http::{
+    self,
+    header::{HeaderName, HeaderValue},
+};
+use indexmap::{IndexMap, IndexSet};
+use ipnet::IpNet;
+use regex::Regex;
 
+use super::access_log;
 use super::control::ControlAddr;
 use super::identity;
+use super::trace;
 use addr;
 use convert::TryFrom;
 use dns;
-use transport::tls;
+use proxy::http::{
+    compress, deadline,
+    egress::CostAttribution,
+    forwarded,
+    path_template::{self, PathTemplates},
+    profiles::{RedirectClass, Wildcard},
+    ring_hash,
+    router::Overflow,
+    scrub_headers,
+};
+use proxy::{authz, egress_policy, tls_passthrough};
+use telemetry::statsd;
+use transport::{mesh, tls, ConnectOptions, Keepalive, ListenOptions};
 use {Addr, Conditional};
 
 /// Tracks all configuration settings for the process.
@@ -34,34 +56,93 @@ pub struct Config {
     /// Where to forward externally received connections.
     pub inbound_forward: Option<SocketAddr>,
 
+    /// When set, the path of a Unix socket on which inbound connections are
+    /// also accepted.
+    pub inbound_listen_unix_path: Option<PathBuf>,
+
+    /// When set, only processes running as one of these user IDs may connect
+    /// to `inbound_listen_unix_path`.
+    pub inbound_listen_unix_allowed_uids: Option<IndexSet<u32>>,
+
+    /// When set, the path of a Unix socket over which connections to the
+    /// local application are established.
+    pub inbound_connect_unix_path: Option<PathBuf>,
+
     /// The maximum amount of time to wait for a connection to a local peer.
     pub inbound_connect_timeout: Duration,
 
     /// The maximum amount of time to wait for a connection to a remote peer.
     pub outbound_connect_timeout: Duration,
 
+    /// Socket options set on connections to remote peers.
+    pub outbound_connect_options: ConnectOptions,
+
+    /// The amount of time a destination may be unavailable before requests to
+    /// it fail immediately. If unset, requests wait for the destination.
+    pub outbound_failfast_timeout: Option<Duration>,
+
+    /// When set, outbound requests are balanced over a consistent-hash ring
+    /// keyed by this request property.
+    pub outbound_balance_hash_key: Option<ring_hash::HashKey>,
+
+    /// When set, the share of outbound traffic sent to newly-added endpoints
+    /// is ramped up over this window.
+    pub outbound_balance_slow_start: Option<Duration>,
+
+    /// When set, outbound balancers use endpoints that are not ready while
+    /// fewer than this fraction of their endpoints are ready.
+    pub outbound_balance_panic_threshold: Option<f64>,
+
+    /// The initial latency estimate for endpoints in outbound balancers.
+    pub outbound_balance_ewma_default_rtt: Duration,
+
+    /// How long it takes for past latency observations to decay in outbound
+    /// balancers' latency estimates.
+    pub outbound_balance_ewma_decay: Duration,
+
     /// The amount of time to wait between connection attempts.
     pub inbound_connect_backoff: Duration,
 
     /// The amount of time to wait between connection attempts.
     pub outbound_connect_backoff: Duration,
 
+    /// The maximum amount of time to wait between connection attempts, as the
+    /// backoff doubles after each consecutive connection error.
+    pub outbound_connect_backoff_max: Duration,
+
+    /// The maximum number of endpoints to which an outbound request is
+    /// dispatched when connections to them cannot be established.
+    pub outbound_connect_attempts: usize,
+
     // TCP Keepalive set on accepted inbound connections.
-    pub inbound_accept_keepalive: Option<Duration>,
+    pub inbound_accept_keepalive: Option<Keepalive>,
 
     // TCP Keepalive set on accepted outbound connections.
-    pub outbound_accept_keepalive: Option<Duration>,
+    pub outbound_accept_keepalive: Option<Keepalive>,
 
     // TCP Keepalive set on inbound connections to the local application.
-    pub inbound_connect_keepalive: Option<Duration>,
+    pub inbound_connect_keepalive: Option<Keepalive>,
 
     // TCP Keepalive set on outbound connections to the remote peers.
-    pub outbound_connect_keepalive: Option<Duration>,
+    pub outbound_connect_keepalive: Option<Keepalive>,
 
     pub inbound_ports_disable_protocol_detection: IndexSet<u16>,
 
     pub outbound_ports_disable_protocol_detection: IndexSet<u16>,
 
+    /// Inbound ports on which connections begin with a PROXY protocol header.
+    pub inbound_proxy_protocol_ports: IndexSet<u16>,
+
+    /// The networks from which PROXY protocol headers are accepted.
+    pub inbound_proxy_protocol_sources: mesh::Networks,
+
+    /// How long a peer may take to send a PROXY protocol header.
+    pub inbound_proxy_protocol_timeout: Duration,
+
+    /// Outbound ports to which forwarded TCP connections are prefixed with a
+    /// PROXY protocol header.
+    pub outbound_proxy_protocol_ports: IndexSet<u16>,
+
     pub inbound_router_capacity: usize,
 
     pub outbound_router_capacity: usize,
@@ -70,9 +151,130 @@ pub struct Config {
 
     pub outbound_router_max_idle_age: Duration,
 
+    /// How requests for new routes are handled when a router is at capacity.
+    pub inbound_router_overflow: Overflow,
+
+    pub outbound_router_overflow: Overflow,
+
+    /// The maximum size of a request's header block accepted from a remote
+    /// client.
+    pub inbound_max_header_size: Option<usize>,
+
+    /// The maximum size of a request's header block sent by the outbound
+    /// client.
+    pub outbound_max_header_size: Option<usize>,
+
+    /// The maximum number of inbound connections handled concurrently.
+    pub inbound_max_connections: Option<usize>,
+
+    /// The maximum number of outbound connections handled concurrently.
+    pub outbound_max_connections: Option<usize>,
+
+    /// The maximum size of a request body accepted from a remote client.
+    pub inbound_max_request_body_size: Option<usize>,
+
+    /// The maximum size of a request body accepted from the local
+    /// application.
+    pub outbound_max_request_body_size: Option<usize>,
+
+    /// When set, inbound requests to destinations without a profile are
+    /// labeled with their path template.
+    pub inbound_path_templates: Option<PathTemplates>,
+
+    /// When set, outbound requests to external domains are tagged with the
+    /// workload's cost-attribution identifiers.
+    pub outbound_cost_attribution: Option<CostAttribution>,
+
+    /// Headers that are removed from traffic to destinations outside of the
+    /// mesh.
+    pub egress_scrub_headers: Vec<scrub_headers::Pattern>,
+
+    /// Destinations outside of the mesh that outbound traffic may be sent to.
+    /// If unset, traffic is not restricted.
+    pub egress_allow: Option<Vec<egress_policy::Rule>>,
+
+    /// `l5d-*` headers that are not stripped from inbound requests sent by
+    /// unauthenticated clients.
+    pub inbound_trusted_headers: Vec<HeaderName>,
+
+    /// How forwarding headers on inbound requests are handled.
+    pub inbound_forwarded_headers: forwarded::Mode,
+
+    /// Whether inbound gRPC-Web requests are translated to gRPC.
+    pub inbound_grpc_web: bool,
+
+    /// Which clients' deadline headers are honored on inbound requests.
+    pub inbound_deadline_headers: deadline::Trust,
+
+    /// Whether each inbound port requires clients to authenticate with TLS.
+    pub inbound_tls_policy: tls::policy::Policy,
+
+    /// Which inbound requests and connections are authorized.
+    pub inbound_authz: authz::Policy,
+
+    /// Where inbound TLS connections that the proxy does not terminate may be
+    /// forwarded, by SNI. If unset, they are forwarded to their original
+    /// destination.
+    pub inbound_tls_passthrough: Option<Vec<tls_passthrough::Rule>>,
+
+    /// The proportion of route failures that are captured for debugging.
+    pub failure_capture_sample_rate: f64,
+
+    /// The maximum number of captured failures that are retained.
+    pub failure_capture_capacity: usize,
+
+    /// When set, responses to inbound requests are compressed for clients
+    /// that accept gzip.
+    pub inbound_compression: Option<compress::Config>,
+
+    /// When set, a record of each request is written to this destination.
+    pub access_log: Option<access_log::Destination>,
+
+    /// The maximum number of access log records that may be buffered before
+    /// records are dropped.
+    pub access_log_capacity: usize,
+
+    /// When set, a span is recorded for each sampled request and exported to
+    /// this collector.
+    pub trace_collector: Option<trace::Collector>,
+
+    /// Which requests' spans are recorded.
+    pub trace_sampler: trace::Sampler,
+
+    /// Samplers that override `trace_sampler` for requests on the named
+    /// profile routes.
+    pub trace_route_samplers: Vec<(String, trace::Sampler)>,
+
+    /// The maximum number of spans that may be buffered before spans are
+    /// dropped.
+    pub trace_export_capacity: usize,
+
+    /// Legacy environment variable names that were used to configure the
+    /// proxy.
+    pub deprecated_env_vars: Vec<Deprecation>,
+
     /// Age after which metrics may be dropped.
     pub metrics_retain_idle: Duration,
 
+    /// The maximum number of label sets recorded by each family of HTTP
+    /// metrics, beyond which metrics are recorded with `overflow="true"`.
+    pub metrics_max_scopes: usize,
+
+    /// When set, metrics are periodically pushed to a StatsD agent.
+    pub metrics_statsd: Option<statsd::Config>,
+
+    /// When set, metrics are periodically pushed to an OTLP collector.
+    pub metrics_otlp_addr: Option<ControlAddr>,
+
+    pub metrics_otlp_push_interval: Duration,
+
+    /// Identifies the proxy to the OTLP collector as its resource's
+    /// `service.name`.
+    pub metrics_otlp_service_name: String,
+
+    /// Whether metrics are served to Prometheus by the admin server.
+    pub metrics_prometheus_enabled: bool,
+
     /// Time to wait when encountering errors talking to control plane before
     /// a new connection.
     pub control_backoff_delay: Duration,
@@ -80,6 +282,19 @@ pub struct Config {
     /// The maximum amount of time to wait for a connection to the controller.
     pub control_connect_timeout: Duration,
 
+    /// The maximum amount of time to wait for the controller to respond to a
+    /// request before its connection is abandoned.
+    pub control_request_timeout: Duration,
+
+    /// TCP keepalive for connections to the controller. When unset, the
+    /// inbound or outbound connect keepalive is used, depending on whether
+    /// the controller is local.
+    pub control_connect_keepalive: Option<Keepalive>,
+
+    /// Whether a standby connection to the controller is kept ready to
+    /// replace a failed connection.
+    pub control_warm_standby: bool,
+
     pub identity_config: tls::Conditional<identity::Config>,
     //
     // Destination Config
@@ -98,12 +313,24 @@ pub struct Config {
     /// active concurrently.
     pub destination_concurrency_limit: usize,
 
+    /// The maximum number of destinations which may be resolved by the
+    /// Destination service concurrently. Resolutions beyond this limit are
+    /// queued until others complete.
+    pub destination_resolution_limit: usize,
+
     /// Configured by `ENV_DESTINATION_GET_SUFFIXES`.
     pub destination_get_suffixes: Vec<dns::Suffix>,
 
     /// Configured by `ENV_DESTINATION_PROFILE_SUFFIXES`.
     pub destination_profile_suffixes: Vec<dns::Suffix>,
 
+    /// Configured by `ENV_DESTINATION_PROFILE_WILDCARDS`.
+    pub destination_profile_wildcards: Vec<Wildcard>,
+
+    /// When set, how `3xx` responses are classified on routes that don't
+    /// configure their own redirect class.
+    pub redirect_class: Option<RedirectClass>,
+
     /// This token is passed to the Destination service so that it can return
     /// different results depending on the identity of the proxy making the
     /// call.
@@ -121,8 +348,61 @@ pub struct Config {
     /// Optional maximum TTL for DNS lookups.
     pub dns_max_ttl: Option<Duration>,
 
+    /// Nameservers to query instead of those in `/etc/resolv.conf`.
+    pub dns_nameservers: Option<Vec<SocketAddr>>,
+
+    /// How `dns_nameservers` are queried.
+    pub dns_transport: dns::Transport,
+
+    /// A search path to use instead of the one in `/etc/resolv.conf`.
+    pub dns_search: Option<Vec<dns::Name>>,
+
+    /// Overrides the `ndots` option in `/etc/resolv.conf`.
+    pub dns_ndots: Option<usize>,
+
+    /// Which address families names resolve to, in order of preference.
+    pub dns_ip_preference: dns::IpPreference,
+
+    /// How long names that do not exist are cached, if at all.
+    pub dns_negative_cache_ttl: Option<Duration>,
+
     pub dns_canonicalize_timeout: Duration,
 
+    /// Bounds the TTLs of DNS canonicalization results, which determine when
+    /// a name is refreshed.
+    pub dns_canonicalize_min_ttl: Duration,
+    pub dns_canonicalize_max_ttl: Duration,
+
+    /// Whether names that are resolved by the Destination service (i.e.
+    /// within `destination_get_suffixes`) skip DNS canonicalization.
+    pub dns_canonicalize_bypass_destination: bool,
+
+    /// When set, a file that reads `true` while the proxy's node is draining.
+    pub node_drain_path: Option<PathBuf>,
+
+    /// When set, the endpoints discovered for each destination are persisted
+    /// to this file.
+    pub destination_cache_path: Option<PathBuf>,
+
+    /// When set, destinations that the Destination service does not resolve
+    /// within this timeout are resolved via DNS until it does.
+    pub destination_dns_fallback_timeout: Option<Duration>,
+
+    /// How long a destination's resolution is retained after it is no longer
+    /// used, if at all.
+    pub destination_idle_timeout: Option<Duration>,
+
+    /// How often `node_drain_path` is read.
+    pub node_drain_poll_interval: Duration,
+
+    /// How long the proxy waits, once shutdown is signaled, for connections
+    /// to complete before it exits.
+    pub shutdown_grace_period: Duration,
+
+    /// The networks in which peers that are not meshed are considered to be
+    /// in the cluster, rather than external.
+    pub cluster_networks: mesh::Networks,
+
     pub h2_settings: H2Settings,
 }
 
@@ -130,6 +410,10 @@ pub struct Config {
 pub struct H2Settings {
     pub initial_stream_window_size: Option<u32>,
     pub initial_connection_window_size: Option<u32>,
+
+    /// How long a stream may wait for its peer's next frame before it is
+    /// reset. Streams are never reset for being idle when this is `None`.
+    pub stream_idle_timeout: Option<Duration>,
 }
 
 /// Configuration settings for binding a listener.
@@ -139,6 +423,9 @@ pub struct H2Settings {
 pub struct Listener {
     /// The address to which the listener should bind.
     pub addr: SocketAddr,
+
+    /// Socket options for the listener and its accepted connections.
+    pub options: ListenOptions,
 }
 
 /// Errors produced when loading a `Config` struct.
@@ -159,6 +446,29 @@ pub enum ParseError {
     NameError,
     InvalidTokenSource,
     InvalidTrustAnchors,
+    NotARegex,
+    NotAHeaderName,
+    NotAHeaderValue,
+    NotASampleRate,
+    NotAForwardedHeadersMode,
+    NotADeadlineTrust,
+    NotATlsPolicy,
+    NotATlsVersion,
+    NotACipherSuite,
+    NotAnAuthzRule,
+    NotAnEgressRule,
+    NotATlsPassthroughRule,
+    NotAHashKey,
+    NotARedirectClass,
+    NotARatio,
+    NotARouterOverflow,
+    NotANetwork,
+    NotADnsTransport,
+    NotAnIpPreference,
+    NotATraceCollector,
+    NotATraceProtocol,
+    NotATraceSampler,
+    NotAStatsdFlavor,
 }
 
 /// The strings used to build a configuration.
@@ -172,11 +482,49 @@ pub trait Strings {
 /// An implementation of `Strings` that reads the values from environment variables.
 pub struct Env;
 
+/// Wraps a `Strings` so that values set under legacy names are used when the
+/// current name is unset, recording each such use.
+struct Compat<'a, S: 'a> {
+    strings: &'a S,
+    legacy: &'static [(&'static str, &'static str)],
+    used: RefCell<Vec<Deprecation>>,
+}
+
+/// A legacy environment variable that was used in place of its replacement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    pub name: &'static str,
+    pub replacement: &'static str,
+}
+
 #[derive(Clone)]
 pub struct TestEnv {
     values: HashMap<&'static str, String>,
 }
 
+/// Environment variables that have been renamed, paired with their current
+/// names.
+///
+/// A legacy name is only consulted when its replacement is unset. Legacy
+/// "private" names refer to the local application's side of the proxy, and
+/// "public" names to the remote side; so, for instance, the private connect
+/// timeout applies to inbound connections to the application.
+const LEGACY_ENV_VARS: &[(&str, &str)] = &[
+    ("LINKERD2_PROXY_PRIVATE_LISTENER", ENV_OUTBOUND_LISTEN_ADDR),
+    ("LINKERD2_PROXY_PUBLIC_LISTENER", ENV_INBOUND_LISTEN_ADDR),
+    ("LINKERD2_PROXY_CONTROL_LISTENER", ENV_CONTROL_LISTEN_ADDR),
+    ("LINKERD2_PROXY_METRICS_LISTENER", ENV_ADMIN_LISTEN_ADDR),
+    ("LINKERD2_PROXY_PRIVATE_FORWARD", ENV_INBOUND_FORWARD),
+    (
+        "LINKERD2_PROXY_PRIVATE_CONNECT_TIMEOUT",
+        ENV_INBOUND_CONNECT_TIMEOUT,
+    ),
+    (
+        "LINKERD2_PROXY_PUBLIC_CONNECT_TIMEOUT",
+        ENV_OUTBOUND_CONNECT_TIMEOUT,
+    ),
+];
+
 // Environment variables to look at when loading the configuration
 pub const ENV_OUTBOUND_LISTEN_ADDR: &str = "LINKERD2_PROXY_OUTBOUND_LISTEN_ADDR";
 pub const ENV_INBOUND_FORWARD: &str = "LINKERD2_PROXY_INBOUND_FORWARD";
@@ -184,17 +532,220 @@ pub const ENV_INBOUND_LISTEN_ADDR: &str = "LINKERD2_PROXY_INBOUND_LISTEN_ADDR";
 pub const ENV_CONTROL_LISTEN_ADDR: &str = "LINKERD2_PROXY_CONTROL_LISTEN_ADDR";
 pub const ENV_ADMIN_LISTEN_ADDR: &str = "LINKERD2_PROXY_ADMIN_LISTEN_ADDR";
 pub const ENV_METRICS_RETAIN_IDLE: &str = "LINKERD2_PROXY_METRICS_RETAIN_IDLE";
+
+/// The maximum number of label sets (e.g. destinations or endpoints) for which
+/// each family of HTTP metrics is recorded. The metrics of further label sets
+/// are folded into a single scope labeled `overflow="true"`.
+pub const ENV_METRICS_MAX_SCOPES: &str = "LINKERD2_PROXY_METRICS_MAX_SCOPES";
+
+/// The `IP:PORT` of a StatsD agent to which metrics are pushed over UDP.
+///
+/// If unspecified, metrics are not pushed.
+pub const ENV_METRICS_STATSD_ADDR: &str = "LINKERD2_PROXY_METRICS_STATSD_ADDR";
+
+/// The dialect spoken by the StatsD agent: `statsd` (the default), which
+/// appends labels to metric names, or `dogstatsd`, which sends them as tags.
+pub const ENV_METRICS_STATSD_FLAVOR: &str = "LINKERD2_PROXY_METRICS_STATSD_FLAVOR";
+
+/// A prefix for the names of metrics that are pushed to the StatsD agent.
+pub const ENV_METRICS_STATSD_PREFIX: &str = "LINKERD2_PROXY_METRICS_STATSD_PREFIX";
+
+/// How often metrics are pushed to the StatsD agent.
+pub const ENV_METRICS_STATSD_FLUSH_INTERVAL: &str = "LINKERD2_PROXY_METRICS_STATSD_FLUSH_INTERVAL";
+
+/// The OpenTelemetry collector to which metrics are pushed over OTLP/gRPC,
+/// configured by `_ADDR` and `_NAME` variables like the other control plane
+/// services. Connections are authenticated with the proxy's identity.
+///
+/// If unspecified, metrics are not pushed.
+pub const ENV_METRICS_OTLP_SVC_BASE: &str = "LINKERD2_PROXY_METRICS_OTLP_SVC";
+
+/// How often metrics are pushed to the OTLP collector.
+pub const ENV_METRICS_OTLP_PUSH_INTERVAL: &str = "LINKERD2_PROXY_METRICS_OTLP_PUSH_INTERVAL";
+
+pub const ENV_METRICS_OTLP_SERVICE_NAME: &str = "LINKERD2_PROXY_METRICS_OTLP_SERVICE_NAME";
+
+/// If set, the admin server does not serve metrics to Prometheus, e.g. when
+/// they are only pushed to a StatsD agent.
+pub const ENV_METRICS_PROMETHEUS_DISABLED: &str = "LINKERD2_PROXY_METRICS_PROMETHEUS_DISABLED";
+
+/// The path of a Unix socket on which inbound connections are accepted, in
+/// addition to the inbound TCP listener, e.g. from a co-located process.
+///
+/// These connections have no original destination, so they are forwarded to
+/// `LINKERD2_PROXY_INBOUND_FORWARD`.
+pub const ENV_INBOUND_LISTEN_UNIX_PATH: &str = "LINKERD2_PROXY_INBOUND_LISTEN_UNIX_PATH";
+
+/// A comma-separated list of user IDs. When set, connections to the inbound
+/// Unix socket are only accepted from processes running as one of these
+/// users, according to the socket's peer credentials.
+pub const ENV_INBOUND_LISTEN_UNIX_ALLOWED_UIDS: &str =
+    "LINKERD2_PROXY_INBOUND_LISTEN_UNIX_ALLOWED_UIDS";
+
+/// The path of a Unix socket on which the local application accepts
+/// connections. When set, inbound connections are established over this
+/// socket rather than to their original destination over TCP.
+pub const ENV_INBOUND_CONNECT_UNIX_PATH: &str = "LINKERD2_PROXY_INBOUND_CONNECT_UNIX_PATH";
+
+/// The maximum number of pending connections queued for a listener.
+///
+/// If unspecified, a backlog of 128 is used.
+pub const ENV_INBOUND_LISTEN_BACKLOG: &str = "LINKERD2_PROXY_INBOUND_LISTEN_BACKLOG";
+pub const ENV_OUTBOUND_LISTEN_BACKLOG: &str = "LINKERD2_PROXY_OUTBOUND_LISTEN_BACKLOG";
+
+/// When set to a non-empty value, `SO_REUSEPORT` is set on the listener, so
+/// that multiple proxy processes may accept connections on the same port.
+pub const ENV_INBOUND_LISTEN_REUSEPORT_ENABLED: &str =
+    "LINKERD2_PROXY_INBOUND_LISTEN_REUSEPORT_ENABLED";
+pub const ENV_OUTBOUND_LISTEN_REUSEPORT_ENABLED: &str =
+    "LINKERD2_PROXY_OUTBOUND_LISTEN_REUSEPORT_ENABLED";
+
+/// When set to a non-empty value, `TCP_NODELAY` is not set on accepted
+/// connections.
+pub const ENV_INBOUND_LISTEN_NODELAY_DISABLED: &str =
+    "LINKERD2_PROXY_INBOUND_LISTEN_NODELAY_DISABLED";
+pub const ENV_OUTBOUND_LISTEN_NODELAY_DISABLED: &str =
+    "LINKERD2_PROXY_OUTBOUND_LISTEN_NODELAY_DISABLED";
+
+/// The sizes, in bytes, of the receive and send buffers of accepted
+/// connections.
+///
+/// If unspecified, the operating system's defaults are used.
+pub const ENV_INBOUND_LISTEN_RECV_BUFFER_SIZE: &str =
+    "LINKERD2_PROXY_INBOUND_LISTEN_RECV_BUFFER_SIZE";
+pub const ENV_INBOUND_LISTEN_SEND_BUFFER_SIZE: &str =
+    "LINKERD2_PROXY_INBOUND_LISTEN_SEND_BUFFER_SIZE";
+pub const ENV_OUTBOUND_LISTEN_RECV_BUFFER_SIZE: &str =
+    "LINKERD2_PROXY_OUTBOUND_LISTEN_RECV_BUFFER_SIZE";
+pub const ENV_OUTBOUND_LISTEN_SEND_BUFFER_SIZE: &str =
+    "LINKERD2_PROXY_OUTBOUND_LISTEN_SEND_BUFFER_SIZE";
+
+/// When set to a non-empty value, `IP_TRANSPARENT` is set on the listener, so
+/// that it accepts connections redirected by an iptables `TPROXY` rule rather
+/// than `REDIRECT`. The original destination of each connection is then read
+/// from its local address instead of `SO_ORIGINAL_DST`.
+///
+/// Only supported on Linux, and requires `CAP_NET_ADMIN`.
+pub const ENV_INBOUND_LISTEN_TRANSPARENT_ENABLED: &str =
+    "LINKERD2_PROXY_INBOUND_LISTEN_TRANSPARENT_ENABLED";
+pub const ENV_OUTBOUND_LISTEN_TRANSPARENT_ENABLED: &str =
+    "LINKERD2_PROXY_OUTBOUND_LISTEN_TRANSPARENT_ENABLED";
+
+/// The number of sockets bound to a listener's address, each accepting
+/// connections on its own task, e.g. one per core, so that accepting
+/// connections does not bottleneck on a single accept loop. `SO_REUSEPORT`
+/// is set when more than one is used.
+///
+/// If unspecified, a single socket is used.
+pub const ENV_INBOUND_LISTEN_ACCEPTORS: &str = "LINKERD2_PROXY_INBOUND_LISTEN_ACCEPTORS";
+pub const ENV_OUTBOUND_LISTEN_ACCEPTORS: &str = "LINKERD2_PROXY_OUTBOUND_LISTEN_ACCEPTORS";
+
+/// When set to a non-empty value, inbound requests to destinations without a
+/// service profile are labeled, in route metrics, with a template of the
+/// request's path (e.g. `rt_path="/users/{id}"`).
+pub const ENV_INBOUND_PATH_TEMPLATES_ENABLED: &str =
+    "LINKERD2_PROXY_INBOUND_PATH_TEMPLATES_ENABLED";
+
+/// A whitespace-separated list of regular expressions. Path segments matched
+/// in their entirety by any of these are templated as `{id}` (in addition to
+/// numeric and UUID segments).
+pub const ENV_INBOUND_PATH_TEMPLATES_RULES: &str = "LINKERD2_PROXY_INBOUND_PATH_TEMPLATES_RULES";
+
+/// Limits the number of distinct path templates. Once the limit is reached,
+/// requests with new templates are recorded without a path label.
+pub const ENV_INBOUND_PATH_TEMPLATES_MAX: &str = "LINKERD2_PROXY_INBOUND_PATH_TEMPLATES_MAX";
 const ENV_INBOUND_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_INBOUND_CONNECT_TIMEOUT";
 const ENV_OUTBOUND_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_TIMEOUT";
+
+/// The local IP address from which connections to remote peers originate, on
+/// hosts with several addresses. Connections to addresses of another family
+/// are not bound.
+pub const ENV_OUTBOUND_CONNECT_SOURCE_ADDR: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_SOURCE_ADDR";
+
+/// The network interface to which connections to remote peers are bound
+/// (`SO_BINDTODEVICE`). Only supported on Linux, and requires `CAP_NET_RAW`.
+pub const ENV_OUTBOUND_CONNECT_INTERFACE: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_INTERFACE";
+
+/// A firewall mark (`SO_MARK`) set on connections to remote peers, so that
+/// they may be matched by policy routing or iptables rules. Only supported on
+/// Linux, and requires `CAP_NET_ADMIN`.
+pub const ENV_OUTBOUND_CONNECT_MARK: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_MARK";
 const ENV_INBOUND_CONNECT_BACKOFF: &str = "LINKERD2_PROXY_INBOUND_CONNECT_BACKOFF";
 const ENV_OUTBOUND_CONNECT_BACKOFF: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_BACKOFF";
 
+/// The maximum time to wait between connection attempts to an outbound
+/// endpoint. When greater than `LINKERD2_PROXY_OUTBOUND_CONNECT_BACKOFF`, the
+/// wait doubles after each consecutive connection error, up to this value.
+///
+/// If unspecified, the backoff is fixed.
+pub const ENV_OUTBOUND_CONNECT_BACKOFF_MAX: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_BACKOFF_MAX";
+
+/// The maximum number of endpoints an outbound request may be sent to when
+/// connections cannot be established (e.g. because they are refused, or the TLS
+/// handshake fails). `1` disables connect retries.
+const ENV_OUTBOUND_CONNECT_ATTEMPTS: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_ATTEMPTS";
+
+/// The amount of time an outbound destination may be unavailable (e.g. because
+/// it has no endpoints) before requests to it fail with a `503 Service
+/// Unavailable` response, rather than waiting until they time out.
+///
+/// If unspecified, requests are never failed early.
+pub const ENV_OUTBOUND_FAILFAST_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_FAILFAST_TIMEOUT";
+
+/// When set, outbound requests are balanced over a consistent-hash ring, so
+/// that requests with the same value of a property are sent to the same
+/// endpoint. One of `header:<name>`, `cookie:<name>`, or `authority`.
+///
+/// If unspecified, requests are balanced by load.
+pub const ENV_OUTBOUND_BALANCE_HASH_KEY: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_HASH_KEY";
+
+/// When set, endpoints that are added to an outbound balancer receive a small
+/// share of its traffic at first, which grows to a full share over this
+/// window.
+///
+/// If unspecified, new endpoints immediately receive a full share.
+pub const ENV_OUTBOUND_BALANCE_SLOW_START: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_SLOW_START";
+
+/// A fraction between 0 and 1. When fewer than this fraction of an outbound
+/// balancer's endpoints are ready, requests are balanced over all of its
+/// endpoints, rather than concentrated on the few that are ready.
+///
+/// If unspecified, only ready endpoints are used.
+pub const ENV_OUTBOUND_BALANCE_PANIC_THRESHOLD: &str =
+    "LINKERD2_PROXY_OUTBOUND_BALANCE_PANIC_THRESHOLD";
+
+/// The latency that outbound balancers assume for an endpoint before any
+/// responses have been observed from it.
+pub const ENV_OUTBOUND_BALANCE_EWMA_DEFAULT_RTT: &str =
+    "LINKERD2_PROXY_OUTBOUND_BALANCE_EWMA_DEFAULT_RTT";
+
+/// The time constant over which outbound balancers' latency estimates decay.
+/// Shorter values react more quickly to changes in latency; longer values
+/// smooth out bursts.
+pub const ENV_OUTBOUND_BALANCE_EWMA_DECAY: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_EWMA_DECAY";
+
 const ENV_INBOUND_ACCEPT_KEEPALIVE: &str = "LINKERD2_PROXY_INBOUND_ACCEPT_KEEPALIVE";
 const ENV_OUTBOUND_ACCEPT_KEEPALIVE: &str = "LINKERD2_PROXY_OUTBOUND_ACCEPT_KEEPALIVE";
 
 const ENV_INBOUND_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_INBOUND_CONNECT_KEEPALIVE";
 const ENV_OUTBOUND_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_OUTBOUND_CONNECT_KEEPALIVE";
 
+/// The time between TCP keepalive probes, for all sockets on which keepalive
+/// is enabled by one of the `*_KEEPALIVE` variables (which configure how long
+/// a connection may be idle before probes are sent). Rounded down to whole
+/// seconds.
+///
+/// If unspecified, the operating system's default is used. Only supported on
+/// Linux.
+pub const ENV_KEEPALIVE_INTERVAL: &str = "LINKERD2_PROXY_KEEPALIVE_INTERVAL";
+
+/// The number of unacknowledged TCP keepalive probes after which a connection
+/// is dropped.
+///
+/// If unspecified, the operating system's default is used. Only supported on
+/// Linux.
+pub const ENV_KEEPALIVE_COUNT: &str = "LINKERD2_PROXY_KEEPALIVE_COUNT";
+
 pub const DEPRECATED_ENV_PRIVATE_LISTEN_ADDR: &str = "LINKERD2_PROXY_PRIVATE_LISTEN_ADDR";
 pub const DEPRECATED_ENV_PRIVATE_FORWARD: &str = "LINKERD2_PROXY_PRIVATE_FORWARD";
 
@@ -204,9 +755,204 @@ pub const DEPRECATED_ENV_PRIVATE_FORWARD: &str = "LINKERD2_PROXY_PRIVATE_FORWARD
 pub const ENV_INBOUND_ROUTER_CAPACITY: &str = "LINKERD2_PROXY_INBOUND_ROUTER_CAPACITY";
 pub const ENV_OUTBOUND_ROUTER_CAPACITY: &str = "LINKERD2_PROXY_OUTBOUND_ROUTER_CAPACITY";
 
+// When a router is at capacity, its least-recently-used route that has been idle for
+// longer than the max idle age is evicted to make room for a new route. Routes with
+// in-flight requests are never evicted.
 pub const ENV_INBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_INBOUND_ROUTER_MAX_IDLE_AGE";
 pub const ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_OUTBOUND_ROUTER_MAX_IDLE_AGE";
 
+// When a router is at capacity and no route can be evicted, a request for a new route
+// is either rejected (`reject`), queued until a route can be evicted or the given
+// timeout elapses (`queue:<duration>`), or served by a route that is not cached
+// (`spill`). Defaults to `reject`.
+pub const ENV_INBOUND_ROUTER_OVERFLOW: &str = "LINKERD2_PROXY_INBOUND_ROUTER_OVERFLOW";
+pub const ENV_OUTBOUND_ROUTER_OVERFLOW: &str = "LINKERD2_PROXY_OUTBOUND_ROUTER_OVERFLOW";
+
+/// Limits the size, in bytes, of the header block of requests accepted by the
+/// inbound server or sent by the outbound client. Requests that exceed this
+/// limit fail with a `431 Request Header Fields Too Large` response.
+///
+/// HTTP/1 request heads are always read into a buffer of at least 8KB, so a
+/// smaller limit is enforced after the head has been read.
+///
+/// If unspecified, hyper's default HTTP/1 buffer size bounds request headers.
+pub const ENV_INBOUND_MAX_HEADER_SIZE: &str = "LINKERD2_PROXY_INBOUND_MAX_HEADER_SIZE";
+pub const ENV_OUTBOUND_MAX_HEADER_SIZE: &str = "LINKERD2_PROXY_OUTBOUND_MAX_HEADER_SIZE";
+
+/// Limits the number of connections that the proxy handles concurrently, so
+/// that it does not exhaust its file descriptors. Connections beyond this
+/// limit are closed as soon as they are accepted; HTTP clients are first sent
+/// a `503 Service Unavailable` response.
+///
+/// If unspecified, the number of connections is not limited.
+pub const ENV_INBOUND_MAX_CONNECTIONS: &str = "LINKERD2_PROXY_INBOUND_MAX_CONNECTIONS";
+pub const ENV_OUTBOUND_MAX_CONNECTIONS: &str = "LINKERD2_PROXY_OUTBOUND_MAX_CONNECTIONS";
+
+/// Limits the size, in bytes, of request bodies accepted by the proxy.
+/// Requests that exceed this limit fail with a `413 Payload Too Large`
+/// response. A profile route may specify a limit of its own, with its
+/// `max_request_body_size` metrics label, which takes precedence.
+///
+/// If unspecified, request bodies are not limited.
+pub const ENV_INBOUND_MAX_REQUEST_BODY_SIZE: &str = "LINKERD2_PROXY_INBOUND_MAX_REQUEST_BODY_SIZE";
+pub const ENV_OUTBOUND_MAX_REQUEST_BODY_SIZE: &str =
+    "LINKERD2_PROXY_OUTBOUND_MAX_REQUEST_BODY_SIZE";
+
+/// A comma-separated list of external domain name suffixes. Outbound requests
+/// to names within these domains are tagged for cost attribution, and the
+/// bytes sent to each domain are reported as metrics.
+pub const ENV_OUTBOUND_COST_ATTRIBUTION_DOMAINS: &str =
+    "LINKERD2_PROXY_OUTBOUND_COST_ATTRIBUTION_DOMAINS";
+
+/// The value of the cost-attribution header, typically identifying the
+/// workload's team and service (e.g. `team=payments,service=checkout`).
+pub const ENV_OUTBOUND_COST_ATTRIBUTION: &str = "LINKERD2_PROXY_OUTBOUND_COST_ATTRIBUTION";
+
+/// The name of the cost-attribution header. Defaults to
+/// `l5d-cost-attribution`.
+pub const ENV_OUTBOUND_COST_ATTRIBUTION_HEADER: &str =
+    "LINKERD2_PROXY_OUTBOUND_COST_ATTRIBUTION_HEADER";
+
+/// A comma-separated list of header names that are removed from requests to,
+/// and responses from, names outside of the mesh (i.e. names that are not
+/// within `ENV_DESTINATION_GET_SUFFIXES`). An entry that ends with `*`, e.g.
+/// `l5d-*`, matches all headers that start with the rest of the entry.
+///
+/// If unspecified, headers are not scrubbed.
+pub const ENV_EGRESS_SCRUB_HEADERS: &str = "LINKERD2_PROXY_EGRESS_SCRUB_HEADERS";
+
+/// A semicolon-separated list of rules that allow outbound traffic to
+/// destinations outside of the mesh, i.e. names that are not within
+/// `ENV_DESTINATION_GET_SUFFIXES` and addresses that are not in
+/// `ENV_CLUSTER_NETWORKS`. Each rule has any of these criteria:
+///
+/// - `host=<names>`: the destination's name is one of the comma-separated
+///   names, which may start with `*.` to match subdomains;
+/// - `net=<networks>`: the destination's address is in one of the
+///   comma-separated networks;
+/// - `port=<port>`: the destination's port.
+///
+/// e.g. `host=api.example.com port=443; net=203.0.113.0/24`
+///
+/// When set, HTTP requests to other external destinations fail with a 403
+/// response, and other connections to them are closed. If unspecified,
+/// traffic is not restricted.
+pub const ENV_EGRESS_ALLOW: &str = "LINKERD2_PROXY_EGRESS_ALLOW";
+
+/// A comma-separated list of `l5d-*` header names, e.g. `l5d-request-id`, that
+/// are trusted on inbound requests from clients that did not authenticate
+/// with TLS. All other `l5d-*` headers are stripped from these requests.
+///
+/// Headers are only stripped when the proxy has an identity, since otherwise
+/// no client can authenticate.
+///
+/// If unspecified, `l5d-orig-proto` is trusted, since meshed proxies that do
+/// not use TLS, e.g. because they have no identity, set it when they upgrade
+/// HTTP/1 requests to HTTP/2.
+pub const ENV_INBOUND_TRUSTED_HEADERS: &str = "LINKERD2_PROXY_INBOUND_TRUSTED_HEADERS";
+
+/// Determines how the `X-Forwarded-For`, `X-Forwarded-Proto`, and `Forwarded`
+/// headers of inbound requests are handled:
+///
+/// - `passthrough`: the headers are not modified;
+/// - `append`: the client's address is appended to the headers;
+/// - `sanitize`: any values set by the client are replaced with its address.
+///
+/// If unspecified, the headers are passed through.
+pub const ENV_INBOUND_FORWARDED_HEADERS: &str = "LINKERD2_PROXY_INBOUND_FORWARDED_HEADERS";
+
+/// When set to a non-empty value, inbound `application/grpc-web` requests
+/// (e.g. from browsers) are translated to gRPC for the local application, and
+/// its responses are translated back to gRPC-Web.
+pub const ENV_INBOUND_GRPC_WEB_ENABLED: &str = "LINKERD2_PROXY_INBOUND_GRPC_WEB_ENABLED";
+
+/// Determines which clients' `l5d-deadline` and `grpc-timeout` headers bound
+/// the time that the inbound proxy spends on their requests:
+///
+/// - `never`: the headers are ignored;
+/// - `meshed`: the headers are honored for clients with a verified identity;
+/// - `always`: the headers are honored for all clients.
+///
+/// If unspecified, only meshed clients' deadlines are honored.
+pub const ENV_INBOUND_DEADLINE_HEADERS: &str = "LINKERD2_PROXY_INBOUND_DEADLINE_HEADERS";
+
+/// Determines whether inbound connections must be authenticated with mutual
+/// TLS:
+///
+/// - `require-identity`: connections are refused unless the client presents
+///   a verified identity;
+/// - `optional`: TLS is terminated when the client initiates it, but
+///   connections without a client identity are accepted;
+/// - `plaintext`: TLS is not terminated.
+///
+/// If unspecified, TLS is optional.
+pub const ENV_INBOUND_TLS_POLICY: &str = "LINKERD2_PROXY_INBOUND_TLS_POLICY";
+
+/// Overrides `LINKERD2_PROXY_INBOUND_TLS_POLICY` for individual ports, as a
+/// comma-separated list of `port=policy` pairs, e.g.
+/// `8443=require-identity,9990=plaintext`.
+pub const ENV_INBOUND_PORTS_TLS_POLICY: &str = "LINKERD2_PROXY_INBOUND_PORTS_TLS_POLICY";
+
+/// A semicolon-separated list of rules that authorize inbound traffic. Each
+/// rule is `allow` or `deny`, followed by any of these criteria:
+///
+/// - `id=<names>`: the client's verified identity is one of the
+///   comma-separated names, which may be `*` (any identity) or start with `*.`
+///   to match a suffix;
+/// - `net=<networks>`: the client's address is in one of the comma-separated
+///   networks;
+/// - `port=<port>`: the connection's target port;
+/// - `path=<prefix>`: the request's path starts with the prefix. Connections
+///   that are not HTTP never match these rules.
+///
+/// e.g. `deny path=/admin; allow id=*.ns1.serviceaccount.identity.linkerd.cluster.local`
+///
+/// The first rule that matches decides; HTTP requests that are denied fail
+/// with a 403 response, and other connections are closed.
+pub const ENV_INBOUND_AUTHZ_RULES: &str = "LINKERD2_PROXY_INBOUND_AUTHZ_RULES";
+
+/// Whether inbound traffic that matches no rule in
+/// `LINKERD2_PROXY_INBOUND_AUTHZ_RULES` is allowed or denied.
+///
+/// If unspecified, it is allowed.
+pub const ENV_INBOUND_AUTHZ_DEFAULT: &str = "LINKERD2_PROXY_INBOUND_AUTHZ_DEFAULT";
+
+/// A comma-separated list of server names for which inbound TLS connections
+/// that the proxy does not terminate are forwarded. Names may start with `*.`
+/// to match subdomains, or be `*` to match any name, and may be followed by
+/// `=<addr>` to forward connections to that address instead of their original
+/// destination.
+///
+/// e.g. `api.example.com,*.example.org=10.1.2.3:443`
+///
+/// The first name that matches a connection's SNI decides. When set, TLS
+/// connections with other names are closed. If unspecified, they are all
+/// forwarded to their original destination.
+pub const ENV_INBOUND_TLS_PASSTHROUGH: &str = "LINKERD2_PROXY_INBOUND_TLS_PASSTHROUGH";
+
+/// The proportion, between 0 and 1, of requests classified as failures by
+/// their route's response classes that are captured for debugging. Captured
+/// failures are served by the admin server at `/debug/failures`.
+///
+/// If unspecified, failures are not captured.
+pub const ENV_FAILURE_CAPTURE_SAMPLE_RATE: &str = "LINKERD2_PROXY_FAILURE_CAPTURE_SAMPLE_RATE";
+
+/// The maximum number of captured failures that are retained. Once the limit
+/// is reached, the oldest captures are discarded.
+pub const ENV_FAILURE_CAPTURE_CAPACITY: &str = "LINKERD2_PROXY_FAILURE_CAPTURE_CAPACITY";
+
+/// A comma-separated list of content types (e.g. `text/,application/json`).
+/// Responses to inbound requests with any of these content type prefixes are
+/// gzip-compressed when the client accepts it.
+///
+/// If unspecified, responses are not compressed.
+pub const ENV_INBOUND_COMPRESSION_CONTENT_TYPES: &str =
+    "LINKERD2_PROXY_INBOUND_COMPRESSION_CONTENT_TYPES";
+
+/// Responses with a `content-length` below this size, in bytes, are not
+/// compressed.
+pub const ENV_INBOUND_COMPRESSION_MIN_SIZE: &str = "LINKERD2_PROXY_INBOUND_COMPRESSION_MIN_SIZE";
+
 /// Constrains which destination names are resolved through the destination
 /// service.
 ///
@@ -230,6 +976,24 @@ pub const ENV_DESTINATION_GET_SUFFIXES: &str = "LINKERD2_PROXY_DESTINATION_GET_S
 /// If unspecified, a default value is used.
 pub const ENV_DESTINATION_PROFILE_SUFFIXES: &str = "LINKERD2_PROXY_DESTINATION_PROFILE_SUFFIXES";
 
+/// A comma-separated list of wildcard profile destinations, e.g.
+/// `*.payments.svc.cluster.local.`.
+///
+/// Routes for a destination within a wildcard's domain are discovered from the
+/// wildcard's profile, rather than the destination's own profile, so that one
+/// profile may apply to a family of similar services. When several wildcards
+/// match, the most specific one is used.
+///
+/// If unspecified, each destination uses its own profile.
+pub const ENV_DESTINATION_PROFILE_WILDCARDS: &str = "LINKERD2_PROXY_DESTINATION_PROFILE_WILDCARDS";
+
+/// How responses with a `3xx` status are classified for metrics and retries,
+/// unless a route configures otherwise: one of `success`, `failure`, or
+/// `neutral`. Neutral responses count as neither successes nor failures.
+///
+/// If unspecified, redirects are classified as successes.
+pub const ENV_REDIRECT_CLASS: &str = "LINKERD2_PROXY_REDIRECT_CLASS";
+
 /// Limits the maximum number of outbound Destination service queries.
 ///
 /// Routes which do not result in service discovery lookups will not be capped
@@ -238,6 +1002,15 @@ pub const ENV_DESTINATION_PROFILE_SUFFIXES: &str = "LINKERD2_PROXY_DESTINATION_P
 pub const ENV_DESTINATION_CLIENT_CONCURRENCY_LIMIT: &str =
     "LINKERD2_PROXY_DESTINATION_CLIENT_CONCURRENCY_LIMIT";
 
+/// Limits the number of destinations that are resolved by the Destination
+/// service concurrently. Destinations beyond this limit are queued until other
+/// resolutions are dropped, and are counted by
+/// `destination_resolutions_queued_total`.
+///
+/// Must not be greater than `ENV_DESTINATION_CLIENT_CONCURRENCY_LIMIT`, which
+/// it defaults to.
+pub const ENV_DESTINATION_RESOLUTION_LIMIT: &str = "LINKERD2_PROXY_DESTINATION_RESOLUTION_LIMIT";
+
 // These *disable* our protocol detection for connections whose SO_ORIGINAL_DST
 // has a port in the provided list.
 pub const ENV_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
@@ -245,14 +1018,136 @@ pub const ENV_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
 pub const ENV_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
     "LINKERD2_PROXY_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION";
 
+/// Inbound ports on which accepted connections are expected to begin with a
+/// PROXY protocol (v1 or v2) header, e.g. because they are received from an L4
+/// load balancer. The client address described by the header is used in place
+/// of the connection's remote address. Connections without a valid header are
+/// closed.
+///
+/// `LINKERD2_PROXY_INBOUND_PROXY_PROTOCOL_SOURCES` must also be set.
+pub const ENV_INBOUND_PROXY_PROTOCOL_PORTS: &str = "LINKERD2_PROXY_INBOUND_PROXY_PROTOCOL_PORTS";
+
+/// A comma-separated list of networks, e.g. `10.0.0.0/8`, or addresses of the
+/// load balancers that send PROXY protocol headers. Connections to PROXY
+/// protocol ports from other peers are closed, since their headers could
+/// describe any client address.
+pub const ENV_INBOUND_PROXY_PROTOCOL_SOURCES: &str =
+    "LINKERD2_PROXY_INBOUND_PROXY_PROTOCOL_SOURCES";
+
+/// How long a peer may take to send a PROXY protocol header before its
+/// connection is closed. Defaults to 5 seconds.
+pub const ENV_INBOUND_PROXY_PROTOCOL_TIMEOUT: &str =
+    "LINKERD2_PROXY_INBOUND_PROXY_PROTOCOL_TIMEOUT";
+
+/// Outbound ports to which TCP connections are forwarded with a PROXY protocol
+/// v2 header, describing the address of the local application's connection.
+///
+/// This only applies to connections that are forwarded as opaque TCP.
+pub const ENV_OUTBOUND_PROXY_PROTOCOL_PORTS: &str = "LINKERD2_PROXY_OUTBOUND_PROXY_PROTOCOL_PORTS";
+
 pub const ENV_IDENTITY_DISABLED: &str = "LINKERD2_PROXY_IDENTITY_DISABLED";
+/// A PEM-encoded certificate chain for the local identity, starting with its
+/// leaf certificate. When set, along with `LINKERD2_PROXY_IDENTITY_KEY_FILE`,
+/// the certificate is read from this file instead of being obtained from the
+/// Identity service; both files are read again periodically, so that they may
+/// be renewed.
+pub const ENV_IDENTITY_CRT_FILE: &str = "LINKERD2_PROXY_IDENTITY_CRT_FILE";
+
+/// The local identity's PEM-encoded PKCS#8 ECDSA P-256 private key.
+pub const ENV_IDENTITY_KEY_FILE: &str = "LINKERD2_PROXY_IDENTITY_KEY_FILE";
+
+/// A directory that holds the local identity's ECDSA P-256 private key, as
+/// `key.p8` (PKCS#8), and a certificate signing request for it, as `csr.der`.
 pub const ENV_IDENTITY_DIR: &str = "LINKERD2_PROXY_IDENTITY_DIR";
+
+/// A PKCS#11 module (a shared library), e.g. for an HSM or a TPM, whose token
+/// holds the local identity's key pair. When set, the key is not read from
+/// `key.p8`, and it is only used through the token. Since `csr.der` cannot be
+/// known to be for the token's key, the certificate signing request is always
+/// generated from the token's key. This requires the proxy to be built with the
+/// `pkcs11` feature.
+pub const ENV_IDENTITY_PKCS11_MODULE: &str = "LINKERD2_PROXY_IDENTITY_PKCS11_MODULE";
+
+/// The PKCS#11 token's slot ID. Defaults to 0.
+pub const ENV_IDENTITY_PKCS11_SLOT: &str = "LINKERD2_PROXY_IDENTITY_PKCS11_SLOT";
+
+/// The label of the private and public keys in the PKCS#11 token.
+pub const ENV_IDENTITY_PKCS11_KEY_LABEL: &str = "LINKERD2_PROXY_IDENTITY_PKCS11_KEY_LABEL";
+
+/// A file that holds the user PIN with which to log in to the PKCS#11 token, if
+/// it requires one.
+pub const ENV_IDENTITY_PKCS11_PIN_FILE: &str = "LINKERD2_PROXY_IDENTITY_PKCS11_PIN_FILE";
 pub const ENV_IDENTITY_TRUST_ANCHORS: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS";
+
+/// A file from which the PEM-encoded trust anchors are read, instead of
+/// `LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS`. The file is polled, so that the
+/// trust anchors may be rotated without restarting the proxy.
+pub const ENV_IDENTITY_TRUST_ANCHORS_FILE: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS_FILE";
+
+/// When set, the secrets of all TLS sessions are appended to this file in the
+/// NSS key log format, so that captured traffic may be decrypted, e.g. with
+/// Wireshark. This is for debugging only and is disabled by default.
+pub const ENV_TLS_KEY_LOG_FILE: &str = "LINKERD2_PROXY_TLS_KEY_LOG_FILE";
+
+/// Bound the TLS protocol versions, `1.2` or `1.3`, that are negotiated by
+/// both clients and servers, e.g. to satisfy a compliance regime.
+///
+/// If neither is specified, clients offer TLS 1.2 and 1.3, and servers only
+/// accept TLS 1.2. If only one is specified, the other bound is the oldest or
+/// newest supported version.
+pub const ENV_TLS_MIN_VERSION: &str = "LINKERD2_PROXY_TLS_MIN_VERSION";
+pub const ENV_TLS_MAX_VERSION: &str = "LINKERD2_PROXY_TLS_MAX_VERSION";
+
+/// A comma-separated list of the TLS cipher suites that are negotiated, in
+/// order of preference, named as in the IANA registry, e.g.
+/// `TLS_AES_128_GCM_SHA256,TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256`.
+///
+/// TLS 1.2 suites must use ECDSA authentication, since the proxy's identity
+/// has an ECDSA key. If unspecified, all suites supported by the proxy are
+/// negotiated.
+pub const ENV_TLS_CIPHER_SUITES: &str = "LINKERD2_PROXY_TLS_CIPHER_SUITES";
+
+/// When set, TLS sessions are resumed when reconnecting to recently contacted
+/// endpoints, which saves a full handshake. Up to this many sessions are
+/// cached, and the inbound proxy issues session tickets so that its clients
+/// may resume sessions, too. If unspecified, session tickets are disabled.
+pub const ENV_TLS_SESSION_CACHE_SIZE: &str = "LINKERD2_PROXY_TLS_SESSION_CACHE_SIZE";
+
+/// A PEM- or DER-encoded certificate revocation list. Certificates that it
+/// revokes are rejected, both for the local identity and for peers. The file is
+/// polled, so that the list may be updated without restarting the proxy.
+pub const ENV_IDENTITY_CRL_FILE: &str = "LINKERD2_PROXY_IDENTITY_CRL_FILE";
 pub const ENV_IDENTITY_IDENTITY_LOCAL_NAME: &str = "LINKERD2_PROXY_IDENTITY_LOCAL_NAME";
+
+/// A comma-separated list of DNS-like names, e.g. a stable service name, that
+/// the local identity's certificate is also requested for. Inbound TLS
+/// connections are terminated for any of these names. When set, the
+/// certificate signing request is generated from the local key, rather than
+/// read from `csr.der`.
+pub const ENV_IDENTITY_ADDITIONAL_NAMES: &str = "LINKERD2_PROXY_IDENTITY_ADDITIONAL_NAMES";
 pub const ENV_IDENTITY_TOKEN_FILE: &str = "LINKERD2_PROXY_IDENTITY_TOKEN_FILE";
 pub const ENV_IDENTITY_MIN_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MIN_REFRESH";
 pub const ENV_IDENTITY_MAX_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MAX_REFRESH";
 
+/// The fraction, between 0 and 1, of a certificate's lifetime after which it is
+/// refreshed.
+pub const ENV_IDENTITY_REFRESH_FRACTION: &str = "LINKERD2_PROXY_IDENTITY_REFRESH_FRACTION";
+
+/// The fraction, between 0 and 1, of the delay before a refresh by which the
+/// refresh may be randomly advanced, so that proxies that were certified
+/// together do not refresh together.
+pub const ENV_IDENTITY_REFRESH_JITTER: &str = "LINKERD2_PROXY_IDENTITY_REFRESH_JITTER";
+
+/// Bound the exponential backoff between failed attempts to obtain a
+/// certificate from the Identity service.
+pub const ENV_IDENTITY_MIN_RETRY: &str = "LINKERD2_PROXY_IDENTITY_MIN_RETRY";
+pub const ENV_IDENTITY_MAX_RETRY: &str = "LINKERD2_PROXY_IDENTITY_MAX_RETRY";
+
+/// How far in the future a certificate's validity may begin and still be
+/// accepted, so that a certificate that was just issued by a host whose clock
+/// is ahead is not rejected.
+pub const ENV_IDENTITY_CLOCK_SKEW_TOLERANCE: &str = "LINKERD2_PROXY_IDENTITY_CLOCK_SKEW_TOLERANCE";
+
 pub const ENV_IDENTITY_SVC_BASE: &str = "LINKERD2_PROXY_IDENTITY_SVC";
 
 pub const ENV_DESTINATION_SVC_BASE: &str = "LINKERD2_PROXY_DESTINATION_SVC";
@@ -262,6 +1157,22 @@ pub const ENV_DESTINATION_CONTEXT: &str = "LINKERD2_PROXY_DESTINATION_CONTEXT";
 
 pub const ENV_CONTROL_BACKOFF_DELAY: &str = "LINKERD2_PROXY_CONTROL_BACKOFF_DELAY";
 const ENV_CONTROL_CONNECT_TIMEOUT: &str = "LINKERD2_PROXY_CONTROL_CONNECT_TIMEOUT";
+
+/// The maximum amount of time to wait for a controller to respond to a
+/// request. For streaming requests, this bounds the time until the response
+/// begins.
+///
+/// When a request times out, its connection is abandoned and the proxy
+/// reconnects, to the next of the controller's addresses if it has several.
+const ENV_CONTROL_REQUEST_TIMEOUT: &str = "LINKERD2_PROXY_CONTROL_REQUEST_TIMEOUT";
+
+/// Configures TCP keepalive for connections to the controller.
+const ENV_CONTROL_CONNECT_KEEPALIVE: &str = "LINKERD2_PROXY_CONTROL_CONNECT_KEEPALIVE";
+
+/// When set to a non-empty value, a second connection to each controller is
+/// established in the background and is used as soon as the active connection
+/// fails.
+pub const ENV_CONTROL_WARM_STANDBY_ENABLED: &str = "LINKERD2_PROXY_CONTROL_WARM_STANDBY_ENABLED";
 const ENV_RESOLV_CONF: &str = "LINKERD2_PROXY_RESOLV_CONF";
 
 /// Configures a minimum value for the TTL of DNS lookups.
@@ -273,10 +1184,111 @@ const ENV_DNS_MIN_TTL: &str = "LINKERD2_PROXY_DNS_MIN_TTL";
 /// Lookups with TTLs above this value will use this value instead.
 const ENV_DNS_MAX_TTL: &str = "LINKERD2_PROXY_DNS_MAX_TTL";
 
+/// Configures a comma-separated list of nameservers, as `IP` or `IP:PORT`, to
+/// query instead of those in the system's resolver configuration.
+const ENV_DNS_NAMESERVERS: &str = "LINKERD2_PROXY_DNS_NAMESERVERS";
+
+/// Configures how the nameservers in `LINKERD2_PROXY_DNS_NAMESERVERS` are
+/// queried: `udp` (the default), or `tls:NAME` or `https:NAME`, in which case
+/// the nameservers must present a certificate for `NAME` that is issued by a
+/// well-known (webpki) root. Nameservers use port 853 for `tls` and 443 for
+/// `https` unless another is given.
+const ENV_DNS_TRANSPORT: &str = "LINKERD2_PROXY_DNS_TRANSPORT";
+
+/// Configures a comma-separated list of domains to search for names with
+/// fewer than `ndots` dots, instead of the system's search path. An empty
+/// value disables searching.
+const ENV_DNS_SEARCH: &str = "LINKERD2_PROXY_DNS_SEARCH";
+
+/// Configures the number of dots a name must contain for it to be looked up
+/// as-is before the search path is tried.
+const ENV_DNS_NDOTS: &str = "LINKERD2_PROXY_DNS_NDOTS";
+
+/// Configures the address families that names are resolved to: `v4-only`,
+/// `v6-only`, `prefer-v4` (the default), or `prefer-v6`. When both families
+/// are resolved, connections are attempted to addresses in the preferred
+/// family first.
+const ENV_DNS_IP_PREFERENCE: &str = "LINKERD2_PROXY_DNS_IP_PREFERENCE";
+
+/// Configures how long a name for which DNS returned no records is remembered,
+/// so that it is not looked up again in the meantime. Zero disables negative
+/// caching.
+const ENV_DNS_NEGATIVE_CACHE_TTL: &str = "LINKERD2_PROXY_DNS_NEGATIVE_CACHE_TTL";
+
 /// The amount of time to wait for a DNS query to succeed before falling back to
 /// an uncanonicalized address.
 const ENV_DNS_CANONICALIZE_TIMEOUT: &str = "LINKERD2_PROXY_DNS_CANONICALIZE_TIMEOUT";
 
+/// Configures the minimum and maximum amounts of time for which a canonicalized
+/// name is used before it is refreshed.
+///
+/// A name is refreshed when its DNS record's TTL expires, though never sooner
+/// than the minimum nor later than the maximum.
+const ENV_DNS_CANONICALIZE_MIN_TTL: &str = "LINKERD2_PROXY_DNS_CANONICALIZE_MIN_TTL";
+const ENV_DNS_CANONICALIZE_MAX_TTL: &str = "LINKERD2_PROXY_DNS_CANONICALIZE_MAX_TTL";
+
+/// When set to a non-empty value, names for which the Destination service is
+/// authoritative (as configured by `ENV_DESTINATION_GET_SUFFIXES`) are not
+/// canonicalized via DNS.
+///
+/// Has no effect if the Destination service is not configured.
+const ENV_DNS_CANONICALIZE_BYPASS_DESTINATION: &str =
+    "LINKERD2_PROXY_DNS_CANONICALIZE_BYPASS_DESTINATION";
+
+/// A file to which the last-known endpoints of each destination are written.
+/// When the proxy starts, these endpoints are used until the Destination
+/// service provides an update, so that a proxy that restarts while the control
+/// plane is unavailable may continue to route requests.
+///
+/// If unspecified, endpoints are not persisted.
+pub const ENV_DESTINATION_CACHE_PATH: &str = "LINKERD2_PROXY_DESTINATION_CACHE_PATH";
+
+/// The amount of time to wait for the Destination service to resolve a
+/// destination before resolving it via DNS instead. Destinations are also
+/// resolved via DNS as soon as their query fails. Endpoints that are
+/// discovered this way are labeled `discovery="dns_fallback"`, and are replaced
+/// once the Destination service responds.
+///
+/// If unspecified, destinations are never resolved via DNS while the
+/// Destination service is unavailable.
+pub const ENV_DESTINATION_DNS_FALLBACK_TIMEOUT: &str =
+    "LINKERD2_PROXY_DESTINATION_DNS_FALLBACK_TIMEOUT";
+
+/// The amount of time for which a destination's Destination service query is
+/// retained after the last router that resolved it has dropped it, so that the
+/// query may be shared by routers that resolve it again. Zero drops queries as
+/// soon as they are unused.
+///
+/// Idle queries are dropped early when other destinations are waiting for
+/// query capacity.
+pub const ENV_DESTINATION_IDLE_TIMEOUT: &str = "LINKERD2_PROXY_DESTINATION_IDLE_TIMEOUT";
+
+/// The path of a file that reads `true` while the proxy's node is draining,
+/// e.g. a pod annotation projected by the Kubernetes downward API.
+///
+/// While the node is draining, the proxy reports that it is not ready and
+/// does not reuse outbound HTTP/1 connections.
+pub const ENV_NODE_DRAIN_PATH: &str = "LINKERD2_PROXY_NODE_DRAIN_PATH";
+
+/// How often the node drain file is read.
+pub const ENV_NODE_DRAIN_POLL_INTERVAL: &str = "LINKERD2_PROXY_NODE_DRAIN_POLL_INTERVAL";
+
+/// How long the proxy waits, once it receives `SIGTERM`, for in-flight
+/// requests and TCP streams to complete before it exits. New connections are
+/// not accepted during this time, and HTTP clients are asked to close their
+/// connections.
+///
+/// If unspecified, a grace period of 30 seconds is used.
+pub const ENV_SHUTDOWN_GRACE_PERIOD: &str = "LINKERD2_PROXY_SHUTDOWN_GRACE_PERIOD";
+
+/// A comma-separated list of the networks (e.g. `10.0.0.0/8`) that hold the
+/// cluster's pods.
+///
+/// Request and TCP metrics label peers without a verified identity as
+/// `unmeshed` when they are in one of these networks, or as `external`
+/// otherwise.
+pub const ENV_CLUSTER_NETWORKS: &str = "LINKERD2_PROXY_CLUSTER_NETWORKS";
+
 /// Configure the stream or connection level flow control setting for HTTP2.
 ///
 /// If unspecified, the default value of 65,535 is used.
@@ -284,20 +1296,83 @@ const ENV_INITIAL_STREAM_WINDOW_SIZE: &str = "LINKERD2_PROXY_HTTP2_INITIAL_STREA
 const ENV_INITIAL_CONNECTION_WINDOW_SIZE: &str =
     "LINKERD2_PROXY_HTTP2_INITIAL_CONNECTION_WINDOW_SIZE";
 
+/// How long an HTTP/2 stream proxied in either direction may wait for the
+/// peer's next frame before both sides of the stream are reset with `CANCEL`.
+///
+/// This detects peers that have silently gone away from long-lived streams.
+/// If unspecified, idle streams are not reset.
+pub const ENV_HTTP2_STREAM_IDLE_TIMEOUT: &str = "LINKERD2_PROXY_HTTP2_STREAM_IDLE_TIMEOUT";
+
 // Default values for various configuration fields
 const DEFAULT_OUTBOUND_LISTEN_ADDR: &str = "127.0.0.1:4140";
 const DEFAULT_INBOUND_LISTEN_ADDR: &str = "0.0.0.0:4143";
 const DEFAULT_CONTROL_LISTEN_ADDR: &str = "0.0.0.0:4190";
 const DEFAULT_ADMIN_LISTEN_ADDR: &str = "127.0.0.1:4191";
 const DEFAULT_METRICS_RETAIN_IDLE: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_METRICS_MAX_SCOPES: usize = 10_000;
+const DEFAULT_METRICS_STATSD_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_METRICS_OTLP_PUSH_INTERVAL: Duration = Duration::from_secs(10);
 const DEFAULT_INBOUND_CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
 const DEFAULT_INBOUND_CONNECT_BACKOFF: Duration = Duration::from_millis(100);
 const DEFAULT_OUTBOUND_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
 const DEFAULT_OUTBOUND_CONNECT_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_OUTBOUND_CONNECT_ATTEMPTS: usize = 3;
+const DEFAULT_OUTBOUND_BALANCE_EWMA_DEFAULT_RTT: Duration = Duration::from_millis(30);
+const DEFAULT_OUTBOUND_BALANCE_EWMA_DECAY: Duration = Duration::from_secs(10);
 const DEFAULT_CONTROL_BACKOFF_DELAY: Duration = Duration::from_secs(1);
 const DEFAULT_CONTROL_CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+const DEFAULT_CONTROL_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_DNS_CANONICALIZE_TIMEOUT: Duration = Duration::from_millis(100);
+const DEFAULT_DNS_NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(5);
+const DEFAULT_DNS_CANONICALIZE_MIN_TTL: Duration = Duration::from_secs(5);
+const DEFAULT_DNS_CANONICALIZE_MAX_TTL: Duration = Duration::from_secs(300);
 const DEFAULT_RESOLV_CONF: &str = "/etc/resolv.conf";
+const DEFAULT_NODE_DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(30);
+const DEFAULT_INBOUND_PROXY_PROTOCOL_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_CLUSTER_NETWORKS: &str =
+    "10.0.0.0/8,100.64.0.0/10,172.16.0.0/12,192.168.0.0/16,fd00::/8";
+
+/// Where a JSON record of each request is written: either a file path, which
+/// is appended to, or an open file descriptor, as `fd:<n>`.
+///
+/// If unspecified, requests are not logged.
+pub const ENV_ACCESS_LOG: &str = "LINKERD2_PROXY_ACCESS_LOG";
+
+/// The maximum number of access log records that may be buffered while they
+/// are written. When the buffer is full, records are dropped.
+pub const ENV_ACCESS_LOG_CAPACITY: &str = "LINKERD2_PROXY_ACCESS_LOG_CAPACITY";
+
+/// The `http://` URI to which spans are exported, e.g.
+/// `http://collector.linkerd:4318/v1/traces`.
+///
+/// If unspecified, requests are not traced and trace context headers are left
+/// untouched.
+pub const ENV_TRACE_COLLECTOR: &str = "LINKERD2_PROXY_TRACE_COLLECTOR";
+
+/// The protocol spoken by the trace collector: `otlp` (the default) or
+/// `opencensus`.
+pub const ENV_TRACE_COLLECTOR_PROTOCOL: &str = "LINKERD2_PROXY_TRACE_COLLECTOR_PROTOCOL";
+
+/// The name under which the proxy's spans are reported.
+pub const ENV_TRACE_SERVICE_NAME: &str = "LINKERD2_PROXY_TRACE_SERVICE_NAME";
+
+/// Which requests are traced: `always`, `never`, `parent` (the default),
+/// which traces requests whose trace context is marked as sampled, or a
+/// proportion of requests between 0.0 and 1.0.
+pub const ENV_TRACE_SAMPLER: &str = "LINKERD2_PROXY_TRACE_SAMPLER";
+
+/// A comma-separated list of `<route>=<sampler>` pairs that override the
+/// trace sampler for requests on the named profile routes, e.g.
+/// `GET /books=0.001,GET /authors=always`.
+///
+/// Overrides may also be changed at runtime through the admin server's
+/// `/trace-sampling` endpoint.
+pub const ENV_TRACE_ROUTE_SAMPLERS: &str = "LINKERD2_PROXY_TRACE_ROUTE_SAMPLERS";
+
+/// The maximum number of spans that may be buffered while they are exported.
+/// When the buffer is full, spans are dropped.
+pub const ENV_TRACE_EXPORT_CAPACITY: &str = "LINKERD2_PROXY_TRACE_EXPORT_CAPACITY";
 
 /// It's assumed that a typical proxy can serve inbound traffic for up to 100 pod-local
 /// HTTP services and may communicate with up to 10K external HTTP domains.
@@ -309,11 +1384,26 @@ const DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE: Duration = Duration::from_secs(60);
 
 const DEFAULT_DESTINATION_CLIENT_CONCURRENCY_LIMIT: usize = 100;
 
+const DEFAULT_INBOUND_PATH_TEMPLATES_MAX: usize = 100;
+const DEFAULT_OUTBOUND_COST_ATTRIBUTION_HEADER: &str = "l5d-cost-attribution";
+const DEFAULT_INBOUND_TRUSTED_HEADERS: &str = "l5d-orig-proto";
+const DEFAULT_FAILURE_CAPTURE_CAPACITY: usize = 100;
+const DEFAULT_INBOUND_COMPRESSION_MIN_SIZE: usize = 1024;
+const DEFAULT_ACCESS_LOG_CAPACITY: usize = 10_000;
+const DEFAULT_SERVICE_NAME: &str = "linkerd-proxy";
+const DEFAULT_TRACE_EXPORT_CAPACITY: usize = 10_000;
+
+const DEFAULT_DESTINATION_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
 const DEFAULT_DESTINATION_GET_SUFFIXES: &str = "svc.cluster.local.";
 const DEFAULT_DESTINATION_PROFILE_SUFFIXES: &str = "svc.cluster.local.";
 
 const DEFAULT_IDENTITY_MIN_REFRESH: Duration = Duration::from_secs(10);
 const DEFAULT_IDENTITY_MAX_REFRESH: Duration = Duration::from_secs(60 * 60 * 24);
+const DEFAULT_IDENTITY_REFRESH_FRACTION: f64 = 0.7;
+const DEFAULT_IDENTITY_REFRESH_JITTER: f64 = 0.1;
+const DEFAULT_IDENTITY_MIN_RETRY: Duration = Duration::from_secs(1);
+const DEFAULT_IDENTITY_MAX_RETRY: Duration = Duration::from_secs(60);
+const DEFAULT_IDENTITY_CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(0);
 
 // By default, we keep a list of known assigned ports of server-first protocols.
 //
@@ -335,12 +1425,33 @@ impl dns::ConfigureResolver for Config {
         //       configured separately?
         opts.negative_min_ttl = self.dns_min_ttl;
         opts.negative_max_ttl = self.dns_max_ttl;
+        if let Some(ndots) = self.dns_ndots {
+            opts.ndots = ndots;
+        }
+    }
+
+    fn nameservers(&self) -> Option<&[SocketAddr]> {
+        self.dns_nameservers.as_ref().map(Vec::as_slice)
+    }
+
+    fn transport(&self) -> dns::Transport {
+        self.dns_transport.clone()
+    }
+
+    fn search(&self) -> Option<&[dns::Name]> {
+        self.dns_search.as_ref().map(Vec::as_slice)
+    }
+
+    fn ip_preference(&self) -> dns::IpPreference {
+        self.dns_ip_preference
     }
 }
 
 impl Config {
     /// Load a `Config` by reading ENV variables.
     pub fn parse<S: Strings>(strings: &S) -> Result<Self, Error> {
+        let strings = &Compat::new(strings, LEGACY_ENV_VARS);
+
         // Parse all the environment variables. `parse` will log any errors so
         // defer returning any errors until all of them have been parsed.
         let outbound_listener_addr = parse(strings, ENV_OUTBOUND_LISTEN_ADDR, parse_socket_addr);
@@ -348,12 +1459,63 @@ impl Config {
         let control_listener_addr = parse(strings, ENV_CONTROL_LISTEN_ADDR, parse_socket_addr);
         let admin_listener_addr = parse(strings, ENV_ADMIN_LISTEN_ADDR, parse_socket_addr);
         let inbound_forward = parse(strings, ENV_INBOUND_FORWARD, parse_socket_addr);
+        let inbound_listen_unix_path = parse(strings, ENV_INBOUND_LISTEN_UNIX_PATH, |s| {
+            Ok(PathBuf::from(s))
+        });
+        let inbound_listen_unix_allowed_uids =
+            parse(strings, ENV_INBOUND_LISTEN_UNIX_ALLOWED_UIDS, parse_uid_set);
+        let inbound_connect_unix_path = parse(strings, ENV_INBOUND_CONNECT_UNIX_PATH, |s| {
+            Ok(PathBuf::from(s))
+        });
+
+        let outbound_listener_options = parse_listen_options(
+            strings,
+            ENV_OUTBOUND_LISTEN_BACKLOG,
+            ENV_OUTBOUND_LISTEN_REUSEPORT_ENABLED,
+            ENV_OUTBOUND_LISTEN_NODELAY_DISABLED,
+            ENV_OUTBOUND_LISTEN_RECV_BUFFER_SIZE,
+            ENV_OUTBOUND_LISTEN_SEND_BUFFER_SIZE,
+            ENV_OUTBOUND_LISTEN_TRANSPARENT_ENABLED,
+            ENV_OUTBOUND_LISTEN_ACCEPTORS,
+        );
+        let inbound_listener_options = parse_listen_options(
+            strings,
+            ENV_INBOUND_LISTEN_BACKLOG,
+            ENV_INBOUND_LISTEN_REUSEPORT_ENABLED,
+            ENV_INBOUND_LISTEN_NODELAY_DISABLED,
+            ENV_INBOUND_LISTEN_RECV_BUFFER_SIZE,
+            ENV_INBOUND_LISTEN_SEND_BUFFER_SIZE,
+            ENV_INBOUND_LISTEN_TRANSPARENT_ENABLED,
+            ENV_INBOUND_LISTEN_ACCEPTORS,
+        );
 
         let inbound_connect_timeout = parse(strings, ENV_INBOUND_CONNECT_TIMEOUT, parse_duration);
         let outbound_connect_timeout = parse(strings, ENV_OUTBOUND_CONNECT_TIMEOUT, parse_duration);
+        let outbound_connect_source_addr =
+            parse(strings, ENV_OUTBOUND_CONNECT_SOURCE_ADDR, parse_ip_addr);
+        let outbound_connect_interface = strings.get(ENV_OUTBOUND_CONNECT_INTERFACE);
+        let outbound_connect_mark = parse(strings, ENV_OUTBOUND_CONNECT_MARK, parse_number);
+        let outbound_failfast_timeout =
+            parse(strings, ENV_OUTBOUND_FAILFAST_TIMEOUT, parse_duration);
+        let outbound_balance_hash_key =
+            parse(strings, ENV_OUTBOUND_BALANCE_HASH_KEY, parse_hash_key);
+        let outbound_balance_slow_start =
+            parse(strings, ENV_OUTBOUND_BALANCE_SLOW_START, parse_duration);
+        let outbound_balance_panic_threshold =
+            parse(strings, ENV_OUTBOUND_BALANCE_PANIC_THRESHOLD, parse_ratio);
+        let outbound_balance_ewma_default_rtt = parse(
+            strings,
+            ENV_OUTBOUND_BALANCE_EWMA_DEFAULT_RTT,
+            parse_duration,
+        );
+        let outbound_balance_ewma_decay =
+            parse(strings, ENV_OUTBOUND_BALANCE_EWMA_DECAY, parse_duration);
 
         let inbound_connect_backoff = parse(strings, ENV_INBOUND_CONNECT_BACKOFF, parse_duration);
         let outbound_connect_backoff = parse(strings, ENV_OUTBOUND_CONNECT_BACKOFF, parse_duration);
+        let outbound_connect_backoff_max =
+            parse(strings, ENV_OUTBOUND_CONNECT_BACKOFF_MAX, parse_duration);
+        let outbound_connect_attempts = parse(strings, ENV_OUTBOUND_CONNECT_ATTEMPTS, parse_number);
 
         let inbound_accept_keepalive = parse(strings, ENV_INBOUND_ACCEPT_KEEPALIVE, parse_duration);
         let outbound_accept_keepalive =
@@ -363,6 +1525,8 @@ impl Config {
             parse(strings, ENV_INBOUND_CONNECT_KEEPALIVE, parse_duration);
         let outbound_connect_keepalive =
             parse(strings, ENV_OUTBOUND_CONNECT_KEEPALIVE, parse_duration);
+        let keepalive_interval = parse(strings, ENV_KEEPALIVE_INTERVAL, parse_duration);
+        let keepalive_count = parse(strings, ENV_KEEPALIVE_COUNT, parse_number);
 
         let inbound_disable_ports = parse(
             strings,
@@ -374,16 +1538,94 @@ impl Config {
             ENV_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION,
             parse_port_set,
         );
+        let inbound_proxy_protocol_ports =
+            parse(strings, ENV_INBOUND_PROXY_PROTOCOL_PORTS, parse_port_set);
+        let inbound_proxy_protocol_sources =
+            parse(strings, ENV_INBOUND_PROXY_PROTOCOL_SOURCES, parse_networks);
+        let inbound_proxy_protocol_timeout =
+            parse(strings, ENV_INBOUND_PROXY_PROTOCOL_TIMEOUT, parse_duration);
+        let outbound_proxy_protocol_ports =
+            parse(strings, ENV_OUTBOUND_PROXY_PROTOCOL_PORTS, parse_port_set);
 
         let inbound_router_capacity = parse(strings, ENV_INBOUND_ROUTER_CAPACITY, parse_number);
         let outbound_router_capacity = parse(strings, ENV_OUTBOUND_ROUTER_CAPACITY, parse_number);
 
         let inbound_router_max_idle_age =
             parse(strings, ENV_INBOUND_ROUTER_MAX_IDLE_AGE, parse_duration);
+        let inbound_router_overflow =
+            parse(strings, ENV_INBOUND_ROUTER_OVERFLOW, parse_router_overflow);
+        let outbound_router_overflow =
+            parse(strings, ENV_OUTBOUND_ROUTER_OVERFLOW, parse_router_overflow);
         let outbound_router_max_idle_age =
             parse(strings, ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE, parse_duration);
 
+        let inbound_max_header_size = parse(strings, ENV_INBOUND_MAX_HEADER_SIZE, parse_number);
+        let outbound_max_header_size = parse(strings, ENV_OUTBOUND_MAX_HEADER_SIZE, parse_number);
+        let inbound_max_connections =
+            parse(strings, ENV_INBOUND_MAX_CONNECTIONS, parse_max_connections);
+        let outbound_max_connections =
+            parse(strings, ENV_OUTBOUND_MAX_CONNECTIONS, parse_max_connections);
+        let inbound_max_request_body_size =
+            parse(strings, ENV_INBOUND_MAX_REQUEST_BODY_SIZE, parse_number);
+        let outbound_max_request_body_size =
+            parse(strings, ENV_OUTBOUND_MAX_REQUEST_BODY_SIZE, parse_number);
+
         let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
+        let metrics_max_scopes = parse(strings, ENV_METRICS_MAX_SCOPES, parse_number);
+        let metrics_statsd = parse_statsd(strings);
+        let metrics_otlp_push_interval =
+            parse(strings, ENV_METRICS_OTLP_PUSH_INTERVAL, parse_duration);
+        let metrics_otlp_service_name = strings.get(ENV_METRICS_OTLP_SERVICE_NAME);
+        let metrics_prometheus_disabled = strings
+            .get(ENV_METRICS_PROMETHEUS_DISABLED)
+            .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+
+        let inbound_path_templates = parse_path_templates(strings);
+        let outbound_cost_attribution = parse_cost_attribution(strings);
+        let egress_scrub_headers = parse(strings, ENV_EGRESS_SCRUB_HEADERS, parse_header_patterns);
+        let egress_allow = parse(strings, ENV_EGRESS_ALLOW, parse_egress_rules);
+        let inbound_trusted_headers =
+            parse(strings, ENV_INBOUND_TRUSTED_HEADERS, parse_header_names);
+        let inbound_forwarded_headers = parse(
+            strings,
+            ENV_INBOUND_FORWARDED_HEADERS,
+            parse_forwarded_headers,
+        );
+        let inbound_deadline_headers = parse(
+            strings,
+            ENV_INBOUND_DEADLINE_HEADERS,
+            parse_deadline_headers,
+        );
+        let inbound_tls_policy = parse(strings, ENV_INBOUND_TLS_POLICY, parse_tls_policy_mode);
+        let inbound_authz_rules = parse(strings, ENV_INBOUND_AUTHZ_RULES, parse_authz_rules);
+        let inbound_authz_default = parse(strings, ENV_INBOUND_AUTHZ_DEFAULT, parse_authz_action);
+        let inbound_tls_passthrough = parse(
+            strings,
+            ENV_INBOUND_TLS_PASSTHROUGH,
+            parse_tls_passthrough_rules,
+        );
+        let inbound_ports_tls_policy = parse(
+            strings,
+            ENV_INBOUND_PORTS_TLS_POLICY,
+            parse_ports_tls_policy,
+        );
+        let inbound_grpc_web = strings
+            .get(ENV_INBOUND_GRPC_WEB_ENABLED)
+            .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+        let failure_capture_sample_rate =
+            parse(strings, ENV_FAILURE_CAPTURE_SAMPLE_RATE, parse_sample_rate);
+        let failure_capture_capacity = parse(strings, ENV_FAILURE_CAPTURE_CAPACITY, parse_number);
+        let inbound_compression = parse_compression(strings);
+        let access_log = parse(strings, ENV_ACCESS_LOG, parse_access_log);
+        let access_log_capacity = parse(strings, ENV_ACCESS_LOG_CAPACITY, parse_number);
+        let trace_collector = parse_trace_collector(strings);
+        let trace_sampler = parse(strings, ENV_TRACE_SAMPLER, parse_trace_sampler);
+        let trace_route_samplers = parse(
+            strings,
+            ENV_TRACE_ROUTE_SAMPLERS,
+            parse_trace_route_samplers,
+        );
+        let trace_export_capacity = parse(strings, ENV_TRACE_EXPORT_CAPACITY, parse_number);
 
         // DNS
 
@@ -391,13 +1633,53 @@ impl Config {
 
         let dns_min_ttl = parse(strings, ENV_DNS_MIN_TTL, parse_duration);
         let dns_max_ttl = parse(strings, ENV_DNS_MAX_TTL, parse_duration);
+        let dns_transport = parse(strings, ENV_DNS_TRANSPORT, parse_dns_transport);
+        let dns_nameservers = {
+            let port = match dns_transport {
+                Ok(Some(ref transport)) => transport.default_port(),
+                _ => dns::Transport::Plain.default_port(),
+            };
+            parse(strings, ENV_DNS_NAMESERVERS, |s| parse_nameservers(s, port))
+        };
+        let dns_search = parse(strings, ENV_DNS_SEARCH, parse_dns_names);
+        let dns_ndots = parse(strings, ENV_DNS_NDOTS, parse_number);
+        let dns_ip_preference = parse(strings, ENV_DNS_IP_PREFERENCE, parse_ip_preference);
+        let dns_negative_cache_ttl = parse(strings, ENV_DNS_NEGATIVE_CACHE_TTL, parse_duration);
 
         let dns_canonicalize_timeout = parse(strings, ENV_DNS_CANONICALIZE_TIMEOUT, parse_duration);
+        let dns_canonicalize_min_ttl = parse(strings, ENV_DNS_CANONICALIZE_MIN_TTL, parse_duration);
+        let dns_canonicalize_max_ttl = parse(strings, ENV_DNS_CANONICALIZE_MAX_TTL, parse_duration);
+        let dns_canonicalize_bypass_destination = strings
+            .get(ENV_DNS_CANONICALIZE_BYPASS_DESTINATION)
+            .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+
+        let node_drain_path = parse(strings, ENV_NODE_DRAIN_PATH, |s| Ok(PathBuf::from(s)));
+        let destination_cache_path = parse(strings, ENV_DESTINATION_CACHE_PATH, |s| {
+            Ok(PathBuf::from(s))
+        });
+        let destination_dns_fallback_timeout = parse(
+            strings,
+            ENV_DESTINATION_DNS_FALLBACK_TIMEOUT,
+            parse_duration,
+        );
+        let destination_idle_timeout = parse(strings, ENV_DESTINATION_IDLE_TIMEOUT, parse_duration);
+        let node_drain_poll_interval = parse(strings, ENV_NODE_DRAIN_POLL_INTERVAL, parse_duration);
+        let shutdown_grace_period = parse(strings, ENV_SHUTDOWN_GRACE_PERIOD, parse_duration);
+
+        let cluster_networks = parse(strings, ENV_CLUSTER_NETWORKS, parse_networks);
 
         let control_backoff_delay = parse(strings, ENV_CONTROL_BACKOFF_DELAY, parse_duration)?
             .unwrap_or(DEFAULT_CONTROL_BACKOFF_DELAY);
         let control_connect_timeout = parse(strings, ENV_CONTROL_CONNECT_TIMEOUT, parse_duration)?
             .unwrap_or(DEFAULT_CONTROL_CONNECT_TIMEOUT);
+        let control_request_timeout = parse(strings, ENV_CONTROL_REQUEST_TIMEOUT, parse_duration)?
+            .unwrap_or(DEFAULT_CONTROL_REQUEST_TIMEOUT);
+        let control_connect_keepalive =
+            parse(strings, ENV_CONTROL_CONNECT_KEEPALIVE, parse_duration);
+        let control_warm_standby = strings
+            .get(ENV_CONTROL_WARM_STANDBY_ENABLED)?
+            .map(|v| !v.is_empty())
+            .unwrap_or(false);
 
         let identity_config = parse_identity_config(strings);
 
@@ -410,6 +1692,11 @@ impl Config {
         } else {
             parse_control_addr(strings, ENV_DESTINATION_SVC_BASE)
         };
+        let metrics_otlp_addr = if id_disabled {
+            parse_control_addr_disable_identity(strings, ENV_METRICS_OTLP_SVC_BASE)
+        } else {
+            parse_control_addr(strings, ENV_METRICS_OTLP_SVC_BASE)
+        };
 
         let dst_token = strings.get(ENV_DESTINATION_CONTEXT);
 
@@ -418,58 +1705,153 @@ impl Config {
             ENV_DESTINATION_CLIENT_CONCURRENCY_LIMIT,
             parse_number,
         );
+        let dst_resolution_limit = parse(strings, ENV_DESTINATION_RESOLUTION_LIMIT, parse_number);
         let dst_get_suffixes = parse(strings, ENV_DESTINATION_GET_SUFFIXES, parse_dns_suffixes);
         let dst_profile_suffixes = parse(
             strings,
             ENV_DESTINATION_PROFILE_SUFFIXES,
             parse_dns_suffixes,
         );
+        let dst_profile_wildcards = parse(
+            strings,
+            ENV_DESTINATION_PROFILE_WILDCARDS,
+            parse_profile_wildcards,
+        );
+        let redirect_class = parse(strings, ENV_REDIRECT_CLASS, parse_redirect_class);
 
         let initial_stream_window_size =
             parse(strings, ENV_INITIAL_STREAM_WINDOW_SIZE, parse_number);
         let initial_connection_window_size =
             parse(strings, ENV_INITIAL_CONNECTION_WINDOW_SIZE, parse_number);
+        let http2_stream_idle_timeout =
+            parse(strings, ENV_HTTP2_STREAM_IDLE_TIMEOUT, parse_duration);
+
+        let outbound_connect_backoff =
+            outbound_connect_backoff?.unwrap_or(DEFAULT_OUTBOUND_CONNECT_BACKOFF);
+
+        let dns_canonicalize_min_ttl =
+            dns_canonicalize_min_ttl?.unwrap_or(DEFAULT_DNS_CANONICALIZE_MIN_TTL);
+        let dns_canonicalize_max_ttl =
+            dns_canonicalize_max_ttl?.unwrap_or(DEFAULT_DNS_CANONICALIZE_MAX_TTL);
+        let dns_nameservers = dns_nameservers?;
+        let dns_transport = dns_transport?.unwrap_or_default();
+        if dns_transport != dns::Transport::Plain && dns_nameservers.is_none() {
+            error!("{} requires {}", ENV_DNS_TRANSPORT, ENV_DNS_NAMESERVERS);
+            return Err(Error::InvalidEnvVar);
+        }
 
-        Ok(Config {
-            outbound_listener: Listener {
-                addr: outbound_listener_addr?
-                    .unwrap_or_else(|| parse_socket_addr(DEFAULT_OUTBOUND_LISTEN_ADDR).unwrap()),
-            },
-            inbound_listener: Listener {
-                addr: inbound_listener_addr?
-                    .unwrap_or_else(|| parse_socket_addr(DEFAULT_INBOUND_LISTEN_ADDR).unwrap()),
+        let dst_concurrency_limit =
+            dst_concurrency_limit?.unwrap_or(DEFAULT_DESTINATION_CLIENT_CONCURRENCY_LIMIT);
+        let dst_resolution_limit = dst_resolution_limit?.unwrap_or(dst_concurrency_limit);
+        if dst_resolution_limit > dst_concurrency_limit {
+            error!(
+                "{} must not be greater than {}",
+                ENV_DESTINATION_RESOLUTION_LIMIT, ENV_DESTINATION_CLIENT_CONCURRENCY_LIMIT
+            );
+            return Err(Error::InvalidEnvVar);
+        }
+
+        let inbound_proxy_protocol_ports = inbound_proxy_protocol_ports?.unwrap_or_default();
+        let inbound_proxy_protocol_sources = match inbound_proxy_protocol_sources? {
+            Some(sources) => sources,
+            None if inbound_proxy_protocol_ports.is_empty() => mesh::Networks::default(),
+            None => {
+                error!(
+                    "{} must be set when {} is set",
+                    ENV_INBOUND_PROXY_PROTOCOL_SOURCES, ENV_INBOUND_PROXY_PROTOCOL_PORTS
+                );
+                return Err(Error::InvalidEnvVar);
+            }
+        };
+
+        if dns_canonicalize_min_ttl > dns_canonicalize_max_ttl {
+            error!(
+                "{} must not be greater than {}",
+                ENV_DNS_CANONICALIZE_MIN_TTL, ENV_DNS_CANONICALIZE_MAX_TTL
+            );
+            return Err(Error::InvalidEnvVar);
+        }
+
+        let keepalive_interval = keepalive_interval?;
+        let keepalive_count = keepalive_count?;
+        let keepalive = |idle: Option<Duration>| {
+            idle.map(|idle| Keepalive {
+                idle,
+                interval: keepalive_interval,
+                count: keepalive_count,
+            })
+        };
+
+        Ok(Config {
+            outbound_listener: Listener {
+                addr: outbound_listener_addr?
+                    .unwrap_or_else(|| parse_socket_addr(DEFAULT_OUTBOUND_LISTEN_ADDR).unwrap()),
+                options: outbound_listener_options?,
+            },
+            inbound_listener: Listener {
+                addr: inbound_listener_addr?
+                    .unwrap_or_else(|| parse_socket_addr(DEFAULT_INBOUND_LISTEN_ADDR).unwrap()),
+                options: inbound_listener_options?,
             },
             control_listener: Listener {
                 addr: control_listener_addr?
                     .unwrap_or_else(|| parse_socket_addr(DEFAULT_CONTROL_LISTEN_ADDR).unwrap()),
+                options: ListenOptions::default(),
             },
             admin_listener: Listener {
                 addr: admin_listener_addr?
                     .unwrap_or_else(|| parse_socket_addr(DEFAULT_ADMIN_LISTEN_ADDR).unwrap()),
+                options: ListenOptions::default(),
             },
             inbound_forward: inbound_forward?,
+            inbound_listen_unix_path: inbound_listen_unix_path?,
+            inbound_listen_unix_allowed_uids: inbound_listen_unix_allowed_uids?,
+            inbound_connect_unix_path: inbound_connect_unix_path?,
 
             inbound_connect_timeout: inbound_connect_timeout?
                 .unwrap_or(DEFAULT_INBOUND_CONNECT_TIMEOUT),
             outbound_connect_timeout: outbound_connect_timeout?
                 .unwrap_or(DEFAULT_OUTBOUND_CONNECT_TIMEOUT),
+            outbound_connect_options: ConnectOptions {
+                source_addr: outbound_connect_source_addr?,
+                interface: outbound_connect_interface?.filter(|i| !i.is_empty()),
+                mark: outbound_connect_mark?,
+            },
+            outbound_failfast_timeout: outbound_failfast_timeout?,
+            outbound_balance_hash_key: outbound_balance_hash_key?,
+            outbound_balance_slow_start: outbound_balance_slow_start?,
+            outbound_balance_panic_threshold: outbound_balance_panic_threshold?,
+            outbound_balance_ewma_default_rtt: outbound_balance_ewma_default_rtt?
+                .unwrap_or(DEFAULT_OUTBOUND_BALANCE_EWMA_DEFAULT_RTT),
+            outbound_balance_ewma_decay: outbound_balance_ewma_decay?
+                .unwrap_or(DEFAULT_OUTBOUND_BALANCE_EWMA_DECAY),
 
             inbound_connect_backoff: inbound_connect_backoff?
                 .unwrap_or(DEFAULT_INBOUND_CONNECT_BACKOFF),
-            outbound_connect_backoff: outbound_connect_backoff?
-                .unwrap_or(DEFAULT_OUTBOUND_CONNECT_BACKOFF),
+            outbound_connect_backoff,
+            outbound_connect_backoff_max: outbound_connect_backoff_max?
+                .unwrap_or(outbound_connect_backoff),
+            outbound_connect_attempts: outbound_connect_attempts?
+                .unwrap_or(DEFAULT_OUTBOUND_CONNECT_ATTEMPTS),
 
-            inbound_accept_keepalive: inbound_accept_keepalive?,
-            outbound_accept_keepalive: outbound_accept_keepalive?,
+            inbound_accept_keepalive: keepalive(inbound_accept_keepalive?),
+            outbound_accept_keepalive: keepalive(outbound_accept_keepalive?),
 
-            inbound_connect_keepalive: inbound_connect_keepalive?,
-            outbound_connect_keepalive: outbound_connect_keepalive?,
+            inbound_connect_keepalive: keepalive(inbound_connect_keepalive?),
+            outbound_connect_keepalive: keepalive(outbound_connect_keepalive?),
+            control_connect_keepalive: keepalive(control_connect_keepalive?),
 
             inbound_ports_disable_protocol_detection: inbound_disable_ports?
                 .unwrap_or_else(|| default_disable_ports_protocol_detection()),
             outbound_ports_disable_protocol_detection: outbound_disable_ports?
                 .unwrap_or_else(|| default_disable_ports_protocol_detection()),
 
+            inbound_proxy_protocol_ports,
+            inbound_proxy_protocol_sources,
+            inbound_proxy_protocol_timeout: inbound_proxy_protocol_timeout?
+                .unwrap_or(DEFAULT_INBOUND_PROXY_PROTOCOL_TIMEOUT),
+            outbound_proxy_protocol_ports: outbound_proxy_protocol_ports?.unwrap_or_default(),
+
             inbound_router_capacity: inbound_router_capacity?
                 .unwrap_or(DEFAULT_INBOUND_ROUTER_CAPACITY),
             outbound_router_capacity: outbound_router_capacity?
@@ -480,8 +1862,18 @@ impl Config {
             outbound_router_max_idle_age: outbound_router_max_idle_age?
                 .unwrap_or(DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE),
 
-            destination_concurrency_limit: dst_concurrency_limit?
-                .unwrap_or(DEFAULT_DESTINATION_CLIENT_CONCURRENCY_LIMIT),
+            inbound_router_overflow: inbound_router_overflow?.unwrap_or(Overflow::Reject),
+            outbound_router_overflow: outbound_router_overflow?.unwrap_or(Overflow::Reject),
+
+            inbound_max_header_size: inbound_max_header_size?,
+            outbound_max_header_size: outbound_max_header_size?,
+            inbound_max_connections: inbound_max_connections?,
+            outbound_max_connections: outbound_max_connections?,
+            inbound_max_request_body_size: inbound_max_request_body_size?,
+            outbound_max_request_body_size: outbound_max_request_body_size?,
+
+            destination_concurrency_limit: dst_concurrency_limit,
+            destination_resolution_limit: dst_resolution_limit,
 
             destination_get_suffixes: dst_get_suffixes?
                 .unwrap_or(parse_dns_suffixes(DEFAULT_DESTINATION_GET_SUFFIXES).unwrap()),
@@ -489,6 +1881,9 @@ impl Config {
             destination_profile_suffixes: dst_profile_suffixes?
                 .unwrap_or(parse_dns_suffixes(DEFAULT_DESTINATION_PROFILE_SUFFIXES).unwrap()),
 
+            destination_profile_wildcards: dst_profile_wildcards?.unwrap_or_default(),
+            redirect_class: redirect_class?,
+
             destination_addr: dst_addr?,
             destination_context: dst_token?.unwrap_or_default(),
 
@@ -501,20 +1896,92 @@ impl Config {
                 .into(),
             control_backoff_delay,
             control_connect_timeout,
+            control_request_timeout,
+            control_warm_standby,
 
             metrics_retain_idle: metrics_retain_idle?.unwrap_or(DEFAULT_METRICS_RETAIN_IDLE),
+            metrics_max_scopes: metrics_max_scopes?.unwrap_or(DEFAULT_METRICS_MAX_SCOPES),
+            metrics_statsd: metrics_statsd?,
+            metrics_otlp_addr: metrics_otlp_addr?,
+            metrics_otlp_push_interval: metrics_otlp_push_interval?
+                .unwrap_or(DEFAULT_METRICS_OTLP_PUSH_INTERVAL),
+            metrics_otlp_service_name: metrics_otlp_service_name?
+                .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_owned()),
+            metrics_prometheus_enabled: !metrics_prometheus_disabled?,
+
+            inbound_path_templates: inbound_path_templates?,
+            outbound_cost_attribution: outbound_cost_attribution?,
+            egress_scrub_headers: egress_scrub_headers?.unwrap_or_default(),
+            egress_allow: egress_allow?,
+            inbound_trusted_headers: inbound_trusted_headers?.unwrap_or_else(|| {
+                parse_header_names(DEFAULT_INBOUND_TRUSTED_HEADERS)
+                    .expect("default trusted headers must parse")
+            }),
+            inbound_forwarded_headers: inbound_forwarded_headers?.unwrap_or_default(),
+            inbound_grpc_web: inbound_grpc_web?,
+            inbound_deadline_headers: inbound_deadline_headers?.unwrap_or_default(),
+            inbound_tls_policy: tls::policy::Policy::new(
+                inbound_tls_policy?.unwrap_or_default(),
+                inbound_ports_tls_policy?.unwrap_or_default(),
+            ),
+            inbound_authz: authz::Policy::new(
+                inbound_authz_rules?.unwrap_or_default(),
+                inbound_authz_default?.unwrap_or_default(),
+            ),
+            inbound_tls_passthrough: inbound_tls_passthrough?,
+            failure_capture_sample_rate: failure_capture_sample_rate?.unwrap_or(0.0),
+            failure_capture_capacity: failure_capture_capacity?
+                .unwrap_or(DEFAULT_FAILURE_CAPTURE_CAPACITY),
+            inbound_compression: inbound_compression?,
+            access_log: access_log?,
+            access_log_capacity: access_log_capacity?.unwrap_or(DEFAULT_ACCESS_LOG_CAPACITY),
+            trace_collector: trace_collector?,
+            trace_sampler: trace_sampler?.unwrap_or(trace::Sampler::Parent),
+            trace_route_samplers: trace_route_samplers?.unwrap_or_default(),
+            trace_export_capacity: trace_export_capacity?.unwrap_or(DEFAULT_TRACE_EXPORT_CAPACITY),
 
             dns_min_ttl: dns_min_ttl?,
 
             dns_max_ttl: dns_max_ttl?,
 
+            dns_nameservers,
+            dns_transport,
+            dns_search: dns_search?,
+            dns_ndots: dns_ndots?,
+            dns_ip_preference: dns_ip_preference?.unwrap_or_default(),
+            dns_negative_cache_ttl: Some(
+                dns_negative_cache_ttl?.unwrap_or(DEFAULT_DNS_NEGATIVE_CACHE_TTL),
+            )
+            .filter(|ttl| *ttl > Duration::from_secs(0)),
+
             dns_canonicalize_timeout: dns_canonicalize_timeout?
                 .unwrap_or(DEFAULT_DNS_CANONICALIZE_TIMEOUT),
+            dns_canonicalize_min_ttl,
+            dns_canonicalize_max_ttl,
+            dns_canonicalize_bypass_destination: dns_canonicalize_bypass_destination?,
+
+            node_drain_path: node_drain_path?,
+            destination_cache_path: destination_cache_path?,
+            destination_dns_fallback_timeout: destination_dns_fallback_timeout?,
+            destination_idle_timeout: Some(
+                destination_idle_timeout?.unwrap_or(DEFAULT_DESTINATION_IDLE_TIMEOUT),
+            )
+            .filter(|timeout| *timeout > Duration::from_secs(0)),
+            node_drain_poll_interval: node_drain_poll_interval?
+                .unwrap_or(DEFAULT_NODE_DRAIN_POLL_INTERVAL),
+            shutdown_grace_period: shutdown_grace_period?.unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD),
+
+            cluster_networks: cluster_networks?.unwrap_or_else(|| {
+                parse_networks(DEFAULT_CLUSTER_NETWORKS).expect("default networks must parse")
+            }),
 
             h2_settings: H2Settings {
                 initial_stream_window_size: initial_stream_window_size?,
                 initial_connection_window_size: initial_connection_window_size?,
+                stream_idle_timeout: http2_stream_idle_timeout?,
             },
+
+            deprecated_env_vars: strings.deprecations(),
         })
     }
 }
@@ -560,6 +2027,56 @@ impl Strings for TestEnv {
     }
 }
 
+// ===== impl Compat =====
+
+impl<'a, S: Strings> Compat<'a, S> {
+    fn new(strings: &'a S, legacy: &'static [(&'static str, &'static str)]) -> Self {
+        Self {
+            strings,
+            legacy,
+            used: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn deprecations(&self) -> Vec<Deprecation> {
+        self.used.borrow().clone()
+    }
+}
+
+impl<'a, S: Strings> Strings for Compat<'a, S> {
+    fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        if let Some(v) = self.strings.get(key)? {
+            return Ok(Some(v));
+        }
+
+        for &(name, replacement) in self.legacy.iter().filter(|&&(_, r)| r == key) {
+            if let Some(v) = self.strings.get(name)? {
+                let dep = Deprecation { name, replacement };
+                warn!("{}", dep);
+                let mut used = self.used.borrow_mut();
+                if !used.contains(&dep) {
+                    used.push(dep);
+                }
+                return Ok(Some(v));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+// ===== impl Deprecation =====
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} has been deprecated; use {}",
+            self.name, self.replacement
+        )
+    }
+}
+
 // ===== Parsing =====
 
 fn parse_number<T>(s: &str) -> Result<T, ParseError>
@@ -569,6 +2086,193 @@ where
     s.parse().map_err(|_| ParseError::NotANumber)
 }
 
+fn parse_sample_rate(s: &str) -> Result<f64, ParseError> {
+    let rate = parse_number::<f64>(s)?;
+    if rate >= 0.0 && rate <= 1.0 {
+        Ok(rate)
+    } else {
+        Err(ParseError::NotASampleRate)
+    }
+}
+
+fn parse_ratio(s: &str) -> Result<f64, ParseError> {
+    let ratio = parse_number::<f64>(s).map_err(|_| ParseError::NotARatio)?;
+    if ratio >= 0.0 && ratio <= 1.0 {
+        Ok(ratio)
+    } else {
+        Err(ParseError::NotARatio)
+    }
+}
+
+fn parse_access_log(s: &str) -> Result<access_log::Destination, ParseError> {
+    if s.starts_with("fd:") {
+        let fd = parse_number(&s["fd:".len()..])?;
+        return Ok(access_log::Destination::Fd(fd));
+    }
+
+    Ok(access_log::Destination::Path(PathBuf::from(s)))
+}
+
+fn parse_trace_collector_uri(s: &str) -> Result<http::Uri, ParseError> {
+    let uri = s
+        .parse::<http::Uri>()
+        .map_err(|_| ParseError::NotATraceCollector)?;
+    match (uri.scheme_part(), uri.authority_part()) {
+        (Some(scheme), Some(_)) if scheme.as_str() == "http" => Ok(uri),
+        _ => Err(ParseError::NotATraceCollector),
+    }
+}
+
+fn parse_trace_protocol(s: &str) -> Result<trace::Protocol, ParseError> {
+    s.parse().map_err(|()| ParseError::NotATraceProtocol)
+}
+
+fn parse_trace_sampler(s: &str) -> Result<trace::Sampler, ParseError> {
+    s.parse().map_err(|()| ParseError::NotATraceSampler)
+}
+
+fn parse_trace_route_samplers(s: &str) -> Result<Vec<(String, trace::Sampler)>, ParseError> {
+    let mut samplers = Vec::new();
+    for pair in s.split(',') {
+        let pair = pair.trim();
+        if pair.is_empty() {
+            continue;
+        }
+        // Route names may contain `=`, but samplers never do.
+        let eq = pair.rfind('=').ok_or(ParseError::NotATraceSampler)?;
+        let route = pair[..eq].trim();
+        if route.is_empty() {
+            return Err(ParseError::NotATraceSampler);
+        }
+        let sampler = parse_trace_sampler(&pair[eq + 1..])?;
+        samplers.push((route.to_owned(), sampler));
+    }
+    Ok(samplers)
+}
+
+fn parse_statsd_flavor(s: &str) -> Result<statsd::Flavor, ParseError> {
+    s.parse().map_err(|()| ParseError::NotAStatsdFlavor)
+}
+
+fn parse_forwarded_headers(s: &str) -> Result<forwarded::Mode, ParseError> {
+    s.parse().map_err(|()| ParseError::NotAForwardedHeadersMode)
+}
+
+fn parse_deadline_headers(s: &str) -> Result<deadline::Trust, ParseError> {
+    s.parse().map_err(|()| ParseError::NotADeadlineTrust)
+}
+
+fn parse_tls_policy_mode(s: &str) -> Result<tls::policy::Mode, ParseError> {
+    s.parse().map_err(|()| ParseError::NotATlsPolicy)
+}
+
+fn parse_tls_version(s: &str) -> Result<identity::TlsVersion, ParseError> {
+    s.parse().map_err(|()| ParseError::NotATlsVersion)
+}
+
+fn parse_cipher_suites(s: &str) -> Result<Vec<identity::CipherSuite>, ParseError> {
+    let suites = s
+        .split(',')
+        .map(str::trim)
+        .filter(|suite| !suite.is_empty())
+        .map(|suite| suite.parse().map_err(|()| ParseError::NotACipherSuite))
+        .collect::<Result<Vec<_>, _>>()?;
+    if suites.is_empty() {
+        return Err(ParseError::NotACipherSuite);
+    }
+    Ok(suites)
+}
+
+fn parse_session_cache_size(s: &str) -> Result<usize, ParseError> {
+    match parse_number(s)? {
+        0 => Err(ParseError::NotANumber),
+        size => Ok(size),
+    }
+}
+
+fn parse_authz_action(s: &str) -> Result<authz::Action, ParseError> {
+    s.parse().map_err(|()| ParseError::NotAnAuthzRule)
+}
+
+fn parse_authz_rules(s: &str) -> Result<Vec<authz::Rule>, ParseError> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| rule.parse().map_err(|()| ParseError::NotAnAuthzRule))
+        .collect()
+}
+
+fn parse_egress_rules(s: &str) -> Result<Vec<egress_policy::Rule>, ParseError> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| rule.parse().map_err(|()| ParseError::NotAnEgressRule))
+        .collect()
+}
+
+fn parse_tls_passthrough_rules(s: &str) -> Result<Vec<tls_passthrough::Rule>, ParseError> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            rule.parse()
+                .map_err(|()| ParseError::NotATlsPassthroughRule)
+        })
+        .collect()
+}
+
+fn parse_ports_tls_policy(s: &str) -> Result<IndexMap<u16, tls::policy::Mode>, ParseError> {
+    let mut ports = IndexMap::new();
+    for item in s.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        let mut parts = item.splitn(2, '=');
+        let port = parse_number::<u16>(parts.next().unwrap_or(""))?;
+        let mode = parse_tls_policy_mode(parts.next().ok_or(ParseError::NotATlsPolicy)?)?;
+        ports.insert(port, mode);
+    }
+    Ok(ports)
+}
+
+fn parse_hash_key(s: &str) -> Result<ring_hash::HashKey, ParseError> {
+    s.parse().map_err(|()| ParseError::NotAHashKey)
+}
+
+fn parse_redirect_class(s: &str) -> Result<RedirectClass, ParseError> {
+    s.parse().map_err(|()| ParseError::NotARedirectClass)
+}
+
+fn parse_router_overflow(s: &str) -> Result<Overflow, ParseError> {
+    match s {
+        "reject" => Ok(Overflow::Reject),
+        "spill" => Ok(Overflow::Spill),
+        _ if s.starts_with("queue:") => parse_duration(&s["queue:".len()..])
+            .map(Overflow::Queue)
+            .map_err(|_| ParseError::NotARouterOverflow),
+        _ => Err(ParseError::NotARouterOverflow),
+    }
+}
+
+fn parse_networks(s: &str) -> Result<mesh::Networks, ParseError> {
+    let mut networks = Vec::new();
+    for net in s.split(',') {
+        let net = net.trim();
+        if net.is_empty() {
+            continue;
+        }
+        // A bare address is a network of one.
+        let net = match IpAddr::from_str(net) {
+            Ok(IpAddr::V4(_)) => format!("{}/32", net),
+            Ok(IpAddr::V6(_)) => format!("{}/128", net),
+            Err(_) => net.to_owned(),
+        };
+        networks.push(IpNet::from_str(&net).map_err(|_| ParseError::NotANetwork)?);
+    }
+    Ok(mesh::Networks::new(networks))
+}
+
 fn parse_duration(s: &str) -> Result<Duration, ParseError> {
     use regex::Regex;
 
@@ -588,6 +2292,10 @@ fn parse_duration(s: &str) -> Result<Duration, ParseError> {
     }
 }
 
+fn parse_ip_addr(s: &str) -> Result<IpAddr, ParseError> {
+    IpAddr::from_str(s).map_err(|_| ParseError::HostIsNotAnIpAddress)
+}
+
 fn parse_socket_addr(s: &str) -> Result<SocketAddr, ParseError> {
     match parse_addr(s)? {
         Addr::Socket(a) => Ok(a),
@@ -605,6 +2313,21 @@ fn parse_addr(s: &str) -> Result<Addr, ParseError> {
     })
 }
 
+/// Parses a comma-separated list of a controller's addresses, returning the
+/// first address and those to fail over to.
+fn parse_control_addrs(s: &str) -> Result<(Addr, Vec<Addr>), ParseError> {
+    let mut addrs = s
+        .split(',')
+        .map(|a| parse_addr(a.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let addr = addrs.remove(0);
+    Ok((addr, addrs))
+}
+
+fn parse_uid_set(s: &str) -> Result<IndexSet<u32>, ParseError> {
+    s.split(',').map(|uid| parse_number(uid.trim())).collect()
+}
+
 fn parse_port_set(s: &str) -> Result<IndexSet<u16>, ParseError> {
     let mut set = IndexSet::new();
     for num in s.split(',') {
@@ -614,12 +2337,79 @@ fn parse_port_set(s: &str) -> Result<IndexSet<u16>, ParseError> {
 }
 
 pub(super) fn parse_identity(s: &str) -> Result<identity::Name, ParseError> {
-    identity::Name::from_hostname(s.as_bytes()).map_err(|identity::InvalidName| {
+    identity::Name::parse(s.as_bytes()).map_err(|identity::InvalidName| {
         error!("Not a valid identity name: {}", s);
         ParseError::NameError
     })
 }
 
+/// Opens the local identity's key in a PKCS#11 token, if one is configured.
+#[cfg(feature = "pkcs11")]
+fn parse_pkcs11_key<S: Strings>(strings: &S) -> Result<Option<identity::Key>, Error> {
+    let module = match strings.get(ENV_IDENTITY_PKCS11_MODULE)? {
+        Some(module) => module,
+        None => return Ok(None),
+    };
+    let slot = parse(strings, ENV_IDENTITY_PKCS11_SLOT, parse_number)?.unwrap_or(0);
+    let label = strings.get(ENV_IDENTITY_PKCS11_KEY_LABEL)?.ok_or_else(|| {
+        error!(
+            "{} must be set when {} is set.",
+            ENV_IDENTITY_PKCS11_KEY_LABEL, ENV_IDENTITY_PKCS11_MODULE
+        );
+        Error::InvalidEnvVar
+    })?;
+    let pin = match strings.get(ENV_IDENTITY_PKCS11_PIN_FILE)? {
+        Some(path) => {
+            let pin = fs::read_to_string(&path).map_err(|e| {
+                error!("Failed to read {}: {}", path, e);
+                Error::InvalidEnvVar
+            })?;
+            Some(pin.trim().to_owned())
+        }
+        None => None,
+    };
+
+    let config = identity::pkcs11::Config {
+        module,
+        slot,
+        label,
+        pin,
+    };
+    identity::pkcs11::open(&config).map(Some).map_err(|e| {
+        error!("Failed to open the PKCS#11 key: {}", e);
+        Error::InvalidEnvVar
+    })
+}
+
+#[cfg(not(feature = "pkcs11"))]
+fn parse_pkcs11_key<S: Strings>(strings: &S) -> Result<Option<identity::Key>, Error> {
+    if strings.get(ENV_IDENTITY_PKCS11_MODULE)?.is_some() {
+        error!(
+            "{} is set, but the proxy was built without the pkcs11 feature.",
+            ENV_IDENTITY_PKCS11_MODULE
+        );
+        return Err(Error::InvalidEnvVar);
+    }
+    Ok(None)
+}
+
+/// Parses a comma-separated list of DNS-like identity names.
+fn parse_identities(list: &str) -> Result<Vec<identity::Name>, ParseError> {
+    let mut names = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if !item.is_empty() {
+            let name = identity::Name::from_hostname(item.as_bytes()).map_err(|_| {
+                error!("Not a valid DNS-like identity name: {}", item);
+                ParseError::NameError
+            })?;
+            names.push(name);
+        }
+    }
+
+    Ok(names)
+}
+
 pub(super) fn parse<T, Parse>(
     strings: &Strings,
     name: &str,
@@ -640,29 +2430,6 @@ where
     }
 }
 
-#[allow(dead_code)]
-fn parse_deprecated<T, Parse>(
-    strings: &Strings,
-    name: &str,
-    deprecated_name: &str,
-    f: Parse,
-) -> Result<Option<T>, Error>
-where
-    Parse: Copy,
-    Parse: Fn(&str) -> Result<T, ParseError>,
-{
-    match parse(strings, name, f)? {
-        Some(v) => Ok(Some(v)),
-        None => {
-            let v = parse(strings, deprecated_name, f)?;
-            if v.is_some() {
-                warn!("{} has been deprecated; use {}", deprecated_name, name);
-            }
-            Ok(v)
-        }
-    }
-}
-
 fn parse_dns_suffixes(list: &str) -> Result<Vec<dns::Suffix>, ParseError> {
     let mut suffixes = Vec::new();
     for item in list.split(',') {
@@ -686,22 +2453,315 @@ fn parse_dns_suffix(s: &str) -> Result<dns::Suffix, ParseError> {
         .map_err(|_| ParseError::NotADomainSuffix)
 }
 
+fn parse_dns_names(list: &str) -> Result<Vec<dns::Name>, ParseError> {
+    let mut names = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if !item.is_empty() {
+            let name =
+                dns::Name::try_from(item.as_bytes()).map_err(|_| ParseError::NotADomainSuffix)?;
+            names.push(name);
+        }
+    }
+
+    Ok(names)
+}
+
+/// Parses a list of nameservers, which use `default_port` unless another is
+/// given.
+fn parse_nameservers(list: &str, default_port: u16) -> Result<Vec<SocketAddr>, ParseError> {
+    let mut nameservers = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if !item.is_empty() {
+            let addr = match parse_ip_addr(item) {
+                Ok(ip) => SocketAddr::new(ip, default_port),
+                Err(_) => parse_socket_addr(item)?,
+            };
+            nameservers.push(addr);
+        }
+    }
+
+    if nameservers.is_empty() {
+        error!("{} must not be empty", ENV_DNS_NAMESERVERS);
+        return Err(ParseError::HostIsNotAnIpAddress);
+    }
+    Ok(nameservers)
+}
+
+fn parse_dns_transport(s: &str) -> Result<dns::Transport, ParseError> {
+    let mut parts = s.trim().splitn(2, ':');
+    let transport = parts.next().unwrap_or("");
+    let name = parts
+        .next()
+        .map(|name| dns::Name::try_from(name.as_bytes()).map_err(|_| ParseError::NameError));
+    match (transport, name) {
+        ("udp", None) => Ok(dns::Transport::Plain),
+        ("tls", Some(name)) => Ok(dns::Transport::Tls(name?)),
+        ("https", Some(name)) => Ok(dns::Transport::Https(name?)),
+        _ => Err(ParseError::NotADnsTransport),
+    }
+}
+
+fn parse_ip_preference(s: &str) -> Result<dns::IpPreference, ParseError> {
+    match s.trim() {
+        "v4-only" => Ok(dns::IpPreference::V4Only),
+        "v6-only" => Ok(dns::IpPreference::V6Only),
+        "prefer-v4" => Ok(dns::IpPreference::PreferV4),
+        "prefer-v6" => Ok(dns::IpPreference::PreferV6),
+        _ => Err(ParseError::NotAnIpPreference),
+    }
+}
+
+fn parse_profile_wildcards(list: &str) -> Result<Vec<Wildcard>, ParseError> {
+    let mut wildcards = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim();
+        if !item.is_empty() {
+            if !item.starts_with("*.") {
+                error!("Not a wildcard: {}", item);
+                return Err(ParseError::NotADomainSuffix);
+            }
+            let domain = dns::Name::try_from(item[2..].as_bytes())
+                .map_err(|_| ParseError::NotADomainSuffix)?;
+            wildcards.push(Wildcard::new(domain));
+        }
+    }
+
+    Ok(wildcards)
+}
+
+fn parse_path_template_rules(s: &str) -> Result<Vec<Regex>, ParseError> {
+    s.split_whitespace()
+        .map(|r| {
+            path_template::rule(r).map_err(|e| {
+                error!("Not a valid path template rule: {}: {}", r, e);
+                ParseError::NotARegex
+            })
+        })
+        .collect()
+}
+
+fn parse_path_templates<S: Strings>(strings: &S) -> Result<Option<PathTemplates>, Error> {
+    let enabled = strings
+        .get(ENV_INBOUND_PATH_TEMPLATES_ENABLED)
+        .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+    let rules = parse(
+        strings,
+        ENV_INBOUND_PATH_TEMPLATES_RULES,
+        parse_path_template_rules,
+    );
+    let max = parse(strings, ENV_INBOUND_PATH_TEMPLATES_MAX, parse_number);
+
+    if !enabled? {
+        return Ok(None);
+    }
+
+    let templates = PathTemplates::new(
+        rules?.unwrap_or_default(),
+        max?.unwrap_or(DEFAULT_INBOUND_PATH_TEMPLATES_MAX),
+    );
+    Ok(Some(templates))
+}
+
+fn parse_listen_options<S: Strings>(
+    strings: &S,
+    backlog_env: &str,
+    reuse_port_env: &str,
+    nodelay_disabled_env: &str,
+    recv_buffer_size_env: &str,
+    send_buffer_size_env: &str,
+    transparent_env: &str,
+    acceptors_env: &str,
+) -> Result<ListenOptions, Error> {
+    let backlog = parse(strings, backlog_env, parse_number);
+    let reuse_port = strings
+        .get(reuse_port_env)
+        .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+    let nodelay_disabled = strings
+        .get(nodelay_disabled_env)
+        .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+    let recv_buffer_size = parse(strings, recv_buffer_size_env, parse_number);
+    let send_buffer_size = parse(strings, send_buffer_size_env, parse_number);
+    let transparent = strings
+        .get(transparent_env)
+        .map(|v| v.map(|v| !v.is_empty()).unwrap_or(false));
+    let acceptors = parse(strings, acceptors_env, parse_acceptors);
+
+    let defaults = ListenOptions::default();
+    Ok(ListenOptions {
+        backlog: backlog?.unwrap_or(defaults.backlog),
+        reuse_port: reuse_port?,
+        nodelay: !nodelay_disabled?,
+        recv_buffer_size: recv_buffer_size?,
+        send_buffer_size: send_buffer_size?,
+        transparent: transparent?,
+        acceptors: acceptors?.unwrap_or(defaults.acceptors),
+    })
+}
+
+fn parse_max_connections(s: &str) -> Result<usize, ParseError> {
+    match parse_number(s)? {
+        0 => Err(ParseError::NotANumber),
+        max => Ok(max),
+    }
+}
+
+fn parse_acceptors(s: &str) -> Result<usize, ParseError> {
+    match parse_number(s)? {
+        0 => Err(ParseError::NotANumber),
+        n => Ok(n),
+    }
+}
+
+fn parse_header_name(s: &str) -> Result<HeaderName, ParseError> {
+    HeaderName::from_bytes(s.as_bytes()).map_err(|_| ParseError::NotAHeaderName)
+}
+
+fn parse_header_names(list: &str) -> Result<Vec<HeaderName>, ParseError> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(parse_header_name)
+        .collect()
+}
+
+fn parse_header_patterns(list: &str) -> Result<Vec<scrub_headers::Pattern>, ParseError> {
+    let mut patterns = Vec::new();
+    for item in list.split(',') {
+        let item = item.trim().to_ascii_lowercase();
+        if item.is_empty() {
+            continue;
+        }
+        if item.ends_with('*') {
+            let prefix = item.trim_end_matches('*');
+            // Check that the prefix could begin a valid header name.
+            parse_header_name(prefix)?;
+            patterns.push(scrub_headers::Pattern::Prefix(prefix.to_owned()));
+        } else {
+            patterns.push(scrub_headers::Pattern::Name(parse_header_name(&item)?));
+        }
+    }
+    Ok(patterns)
+}
+
+fn parse_header_value(s: &str) -> Result<HeaderValue, ParseError> {
+    HeaderValue::from_str(s).map_err(|_| ParseError::NotAHeaderValue)
+}
+
+fn parse_content_types(list: &str) -> Result<Vec<String>, ParseError> {
+    Ok(list
+        .split(',')
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect())
+}
+
+fn parse_compression<S: Strings>(strings: &S) -> Result<Option<compress::Config>, Error> {
+    let content_types = parse(
+        strings,
+        ENV_INBOUND_COMPRESSION_CONTENT_TYPES,
+        parse_content_types,
+    );
+    let min_size = parse(strings, ENV_INBOUND_COMPRESSION_MIN_SIZE, parse_number);
+
+    match content_types? {
+        Some(ref types) if !types.is_empty() => Ok(Some(compress::Config::new(
+            types.clone(),
+            min_size?.unwrap_or(DEFAULT_INBOUND_COMPRESSION_MIN_SIZE),
+        ))),
+        _ => Ok(None),
+    }
+}
+
+fn parse_trace_collector<S: Strings>(strings: &S) -> Result<Option<trace::Collector>, Error> {
+    let uri = parse(strings, ENV_TRACE_COLLECTOR, parse_trace_collector_uri);
+    let protocol = parse(strings, ENV_TRACE_COLLECTOR_PROTOCOL, parse_trace_protocol);
+    let service_name = strings.get(ENV_TRACE_SERVICE_NAME);
+
+    match uri? {
+        Some(uri) => Ok(Some(trace::Collector {
+            uri,
+            protocol: protocol?.unwrap_or(trace::Protocol::Otlp),
+            service_name: service_name?.unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_owned()),
+        })),
+        None => Ok(None),
+    }
+}
+
+fn parse_statsd<S: Strings>(strings: &S) -> Result<Option<statsd::Config>, Error> {
+    let addr = parse(strings, ENV_METRICS_STATSD_ADDR, parse_socket_addr);
+    let flavor = parse(strings, ENV_METRICS_STATSD_FLAVOR, parse_statsd_flavor);
+    let prefix = strings.get(ENV_METRICS_STATSD_PREFIX);
+    let flush_interval = parse(strings, ENV_METRICS_STATSD_FLUSH_INTERVAL, parse_duration);
+
+    match addr? {
+        Some(addr) => Ok(Some(statsd::Config {
+            addr,
+            flavor: flavor?.unwrap_or(statsd::Flavor::Statsd),
+            prefix: prefix?.filter(|p| !p.is_empty()),
+            flush_interval: flush_interval?.unwrap_or(DEFAULT_METRICS_STATSD_FLUSH_INTERVAL),
+        })),
+        None => Ok(None),
+    }
+}
+
+fn parse_cost_attribution<S: Strings>(strings: &S) -> Result<Option<CostAttribution>, Error> {
+    let domains = parse(
+        strings,
+        ENV_OUTBOUND_COST_ATTRIBUTION_DOMAINS,
+        parse_dns_suffixes,
+    );
+    let value = parse(strings, ENV_OUTBOUND_COST_ATTRIBUTION, parse_header_value);
+    let header = parse(
+        strings,
+        ENV_OUTBOUND_COST_ATTRIBUTION_HEADER,
+        parse_header_name,
+    );
+
+    match (domains?, value?) {
+        (Some(domains), Some(value)) => {
+            if domains.is_empty() {
+                return Ok(None);
+            }
+            let header = header?.unwrap_or_else(|| {
+                HeaderName::from_static(DEFAULT_OUTBOUND_COST_ATTRIBUTION_HEADER)
+            });
+            Ok(Some(CostAttribution::new(header, value, domains)))
+        }
+        (Some(_), None) => {
+            error!(
+                "{} must be set when {} is set",
+                ENV_OUTBOUND_COST_ATTRIBUTION, ENV_OUTBOUND_COST_ATTRIBUTION_DOMAINS
+            );
+            Err(Error::InvalidEnvVar)
+        }
+        (None, _) => Ok(None),
+    }
+}
+
 pub fn parse_control_addr<S: Strings>(
     strings: &S,
     base: &str,
 ) -> Result<Option<ControlAddr>, Error> {
     let a_env = format!("{}_ADDR", base);
-    let a = parse(strings, &a_env, parse_addr);
+    let a = parse(strings, &a_env, parse_control_addrs);
     let n_env = format!("{}_NAME", base);
     let n = parse(strings, &n_env, parse_identity);
     match (a?, n?) {
         (None, None) => Ok(None),
-        (Some(ref addr), _) if addr.is_loopback() => Ok(Some(ControlAddr {
-            addr: addr.clone(),
-            identity: Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
-        })),
-        (Some(addr), Some(name)) => Ok(Some(ControlAddr {
+        (Some((ref addr, ref failover_addrs)), _)
+            if addr.is_loopback() && failover_addrs.iter().all(Addr::is_loopback) =>
+        {
+            Ok(Some(ControlAddr {
+                addr: addr.clone(),
+                failover_addrs: failover_addrs.clone(),
+                identity: Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
+            }))
+        }
+        (Some((addr, failover_addrs)), Some(name)) => Ok(Some(ControlAddr {
             addr,
+            failover_addrs,
             identity: Conditional::Some(name),
         })),
         (Some(_), None) => {
@@ -719,32 +2779,204 @@ pub fn parse_control_addr_disable_identity<S: Strings>(
     strings: &S,
     base: &str,
 ) -> Result<Option<ControlAddr>, Error> {
-    let a = parse(strings, &format!("{}_ADDR", base), parse_addr)?;
+    let a = parse(strings, &format!("{}_ADDR", base), parse_control_addrs)?;
     let identity = Conditional::None(tls::ReasonForNoIdentity::Disabled);
-    Ok(a.map(|addr| ControlAddr { addr, identity }))
+    Ok(a.map(|(addr, failover_addrs)| ControlAddr {
+        addr,
+        failover_addrs,
+        identity,
+    }))
 }
 
 pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity::Config>, Error> {
     let sa = parse_control_addr(strings, ENV_IDENTITY_SVC_BASE);
-    let ta = parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
-        identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
+    let ta_file = parse(strings, ENV_IDENTITY_TRUST_ANCHORS_FILE, |ref s| {
+        Ok(PathBuf::from(s))
+    })?;
+    let ta = match ta_file {
+        Some(ref path) => {
+            let pem = fs::read_to_string(path).map_err(|e| {
+                error!(
+                    "Failed to read trust anchors from {}: {}",
+                    path.display(),
+                    e
+                );
+                Error::InvalidEnvVar
+            })?;
+            let ta = identity::TrustAnchors::from_pem(&pem).ok_or_else(|| {
+                error!("Invalid trust anchors in {}", path.display());
+                Error::InvalidEnvVar
+            });
+            ta.map(Some)
+        }
+        None => parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
+            identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
+        }),
+    };
+    let key_log = match parse(strings, ENV_TLS_KEY_LOG_FILE, |ref s| Ok(PathBuf::from(s)))? {
+        Some(path) => {
+            let key_log = identity::KeyLog::open(&path).map_err(|e| {
+                error!("Failed to open TLS key log {}: {}", path.display(), e);
+                Error::InvalidEnvVar
+            })?;
+            warn!(
+                "Logging TLS secrets to {}; sessions are not confidential",
+                path.display()
+            );
+            Some(key_log)
+        }
+        None => None,
+    };
+    let crl = match parse(strings, ENV_IDENTITY_CRL_FILE, |ref s| Ok(PathBuf::from(s)))? {
+        Some(path) => {
+            let crl = identity::Crl::read(&path).map_err(|e| {
+                error!("Failed to read CRL from {}: {}", path.display(), e);
+                Error::InvalidEnvVar
+            })?;
+            Some(crl)
+        }
+        None => None,
+    };
+    let clock_skew = parse(strings, ENV_IDENTITY_CLOCK_SKEW_TOLERANCE, parse_duration)?
+        .unwrap_or(DEFAULT_IDENTITY_CLOCK_SKEW_TOLERANCE);
+    let tls_versions = match (
+        parse(strings, ENV_TLS_MIN_VERSION, parse_tls_version)?,
+        parse(strings, ENV_TLS_MAX_VERSION, parse_tls_version)?,
+    ) {
+        (None, None) => None,
+        (min, max) => {
+            let min = min.unwrap_or(identity::TlsVersion::Tls12);
+            let max = max.unwrap_or(identity::TlsVersion::Tls13);
+            if min > max {
+                error!(
+                    "{} must not be newer than {}",
+                    ENV_TLS_MIN_VERSION, ENV_TLS_MAX_VERSION
+                );
+                return Err(Error::InvalidEnvVar);
+            }
+            Some((min, max))
+        }
+    };
+    let cipher_suites = parse(strings, ENV_TLS_CIPHER_SUITES, parse_cipher_suites)?;
+    let session_cache_size = parse(
+        strings,
+        ENV_TLS_SESSION_CACHE_SIZE,
+        parse_session_cache_size,
+    )?;
+    let ta = ta.map(|ta| {
+        ta.map(|ta| {
+            let ta = ta.with_clock_skew_tolerance(clock_skew);
+            let ta = match tls_versions {
+                Some((min, max)) => ta.with_tls_versions(min, max),
+                None => ta,
+            };
+            let ta = match cipher_suites {
+                Some(ref suites) => ta.with_cipher_suites(suites),
+                None => ta,
+            };
+            let ta = match session_cache_size {
+                Some(size) => ta.with_session_resumption(size),
+                None => ta,
+            };
+            let ta = match key_log {
+                Some(ref key_log) => ta.with_key_log(key_log),
+                None => ta,
+            };
+            match crl {
+                Some(ref crl) => ta.with_crl(crl.clone()),
+                None => ta,
+            }
+        })
     });
     let dir = parse(strings, ENV_IDENTITY_DIR, |ref s| Ok(PathBuf::from(s)));
     let tok = parse(strings, ENV_IDENTITY_TOKEN_FILE, |ref s| {
-        identity::TokenSource::if_nonempty_file(s.to_string()).map_err(|e| {
-            error!("Could not read {}: {}", ENV_IDENTITY_TOKEN_FILE, e);
-            ParseError::InvalidTokenSource
-        })
+        if s.is_empty() {
+            return Err(ParseError::InvalidTokenSource);
+        }
+        let token = identity::TokenSource::from_file(s.to_string());
+        // The token may not have been provisioned yet, so it is read again
+        // before each attempt to obtain a certificate.
+        if let Err(e) = token.load() {
+            warn!("Could not read {}: {}", ENV_IDENTITY_TOKEN_FILE, e);
+        }
+        Ok(token)
     });
     let li = parse(strings, ENV_IDENTITY_IDENTITY_LOCAL_NAME, parse_identity);
+    let additional_names =
+        parse(strings, ENV_IDENTITY_ADDITIONAL_NAMES, parse_identities)?.unwrap_or_default();
     let min_refresh = parse(strings, ENV_IDENTITY_MIN_REFRESH, parse_duration);
     let max_refresh = parse(strings, ENV_IDENTITY_MAX_REFRESH, parse_duration);
+    let refresh_fraction = parse(strings, ENV_IDENTITY_REFRESH_FRACTION, parse_ratio);
+    let refresh_jitter = parse(strings, ENV_IDENTITY_REFRESH_JITTER, parse_ratio);
+    let min_retry = parse(strings, ENV_IDENTITY_MIN_RETRY, parse_duration);
+    let max_retry = parse(strings, ENV_IDENTITY_MAX_RETRY, parse_duration);
+
+    let crt_file = parse(strings, ENV_IDENTITY_CRT_FILE, |ref s| Ok(PathBuf::from(s)))?;
+    let key_file = parse(strings, ENV_IDENTITY_KEY_FILE, |ref s| Ok(PathBuf::from(s)))?;
 
     let disabled = strings
         .get(ENV_IDENTITY_DISABLED)?
         .map(|d| !d.is_empty())
         .unwrap_or(false);
 
+    // When a certificate and key are provided, the Identity service is not
+    // used.
+    if crt_file.is_some() || key_file.is_some() {
+        let service = [
+            (sa?.is_some(), ENV_IDENTITY_SVC_BASE),
+            (dir?.is_some(), ENV_IDENTITY_DIR),
+            (tok?.is_some(), ENV_IDENTITY_TOKEN_FILE),
+            (
+                strings.get(ENV_IDENTITY_PKCS11_MODULE)?.is_some(),
+                ENV_IDENTITY_PKCS11_MODULE,
+            ),
+        ];
+        return match (disabled, crt_file, key_file, ta?, li?) {
+            (false, Some(crt), Some(key), Some(trust_anchors), Some(local_name))
+                if service.iter().all(|&(set, _)| !set) =>
+            {
+                Ok(Some(identity::Config {
+                    trust_anchors,
+                    trust_anchors_file: ta_file,
+                    crl,
+                    local_name,
+                    additional_names,
+                    source: identity::Source::Files { crt, key },
+                }))
+            }
+            (disabled, crt, key, trust_anchors, local_id) => {
+                if disabled {
+                    error!(
+                        "{} must be unset when other identity variables are set.",
+                        ENV_IDENTITY_DISABLED,
+                    );
+                }
+                for (unset, name) in &[
+                    (crt.is_none(), ENV_IDENTITY_CRT_FILE),
+                    (key.is_none(), ENV_IDENTITY_KEY_FILE),
+                    (trust_anchors.is_none(), ENV_IDENTITY_TRUST_ANCHORS),
+                    (local_id.is_none(), ENV_IDENTITY_IDENTITY_LOCAL_NAME),
+                ] {
+                    if *unset {
+                        error!(
+                            "{} must be set when other identity variables are set.",
+                            name
+                        );
+                    }
+                }
+                for &(set, name) in &service {
+                    if set {
+                        error!(
+                            "{} must be unset when {} is set.",
+                            name, ENV_IDENTITY_CRT_FILE
+                        );
+                    }
+                }
+                Err(Error::InvalidEnvVar)
+            }
+        };
+    }
+
     match (
         disabled,
         sa?,
@@ -776,7 +3008,13 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
             min_refresh,
             max_refresh,
         ) => {
-            let key = {
+            let pkcs11_key = parse_pkcs11_key(strings)?;
+            // A `csr.der` is only known to be for `key.p8`, so a token's key
+            // always gets a generated CSR.
+            let generate_csr = pkcs11_key.is_some() || !additional_names.is_empty();
+            let key = if let Some(key) = pkcs11_key {
+                Ok(key)
+            } else {
                 let mut p = dir.clone();
                 p.push("key");
                 p.set_extension("p8");
@@ -794,7 +3032,8 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
                     })
             };
 
-            let csr = {
+            let key = key?;
+            let csr = if !generate_csr {
                 let mut p = dir;
                 p.push("csr");
                 p.set_extension("der");
@@ -810,17 +3049,32 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
                             Error::InvalidEnvVar
                         })
                     })
+            } else {
+                identity::Csr::generate(&key, &local_name, &additional_names).ok_or_else(|| {
+                    error!("Failed to generate a CSR");
+                    Error::InvalidEnvVar
+                })
             };
 
-            Ok(Some(identity::Config {
+            let service = identity::ServiceConfig {
                 svc,
-                local_name,
                 token,
-                trust_anchors,
                 csr: csr?,
-                key: key?,
+                key,
                 min_refresh: min_refresh.unwrap_or(DEFAULT_IDENTITY_MIN_REFRESH),
                 max_refresh: max_refresh.unwrap_or(DEFAULT_IDENTITY_MAX_REFRESH),
+                refresh_fraction: refresh_fraction?.unwrap_or(DEFAULT_IDENTITY_REFRESH_FRACTION),
+                refresh_jitter: refresh_jitter?.unwrap_or(DEFAULT_IDENTITY_REFRESH_JITTER),
+                min_retry: min_retry?.unwrap_or(DEFAULT_IDENTITY_MIN_RETRY),
+                max_retry: max_retry?.unwrap_or(DEFAULT_IDENTITY_MAX_RETRY),
+            };
+            Ok(Some(identity::Config {
+                trust_anchors,
+                trust_anchors_file: ta_file,
+                crl,
+                local_name,
+                additional_names,
+                source: identity::Source::Service(service),
             }))
         }
         (disabled, svc, trust_anchors, end_entity_dir, local_id, token, _minr, _maxr) => {
@@ -855,6 +3109,69 @@ pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<identity:
 mod tests {
     use super::*;
 
+    #[test]
+    fn compat_prefers_current_names() {
+        let mut env = TestEnv::new();
+        env.put("LINKERD2_PROXY_PRIVATE_LISTENER", "legacy".into());
+        let compat = Compat::new(&env, LEGACY_ENV_VARS);
+        assert_eq!(
+            compat.get(ENV_OUTBOUND_LISTEN_ADDR).unwrap(),
+            Some("legacy".to_owned())
+        );
+        assert_eq!(
+            compat.deprecations(),
+            vec![Deprecation {
+                name: "LINKERD2_PROXY_PRIVATE_LISTENER",
+                replacement: ENV_OUTBOUND_LISTEN_ADDR,
+            }]
+        );
+
+        env.put(ENV_OUTBOUND_LISTEN_ADDR, "current".into());
+        let compat = Compat::new(&env, LEGACY_ENV_VARS);
+        assert_eq!(
+            compat.get(ENV_OUTBOUND_LISTEN_ADDR).unwrap(),
+            Some("current".to_owned())
+        );
+        assert!(compat.deprecations().is_empty());
+    }
+
+    #[test]
+    fn compat_maps_each_legacy_name() {
+        let aliases = [
+            ("LINKERD2_PROXY_PRIVATE_LISTENER", ENV_OUTBOUND_LISTEN_ADDR),
+            ("LINKERD2_PROXY_PUBLIC_LISTENER", ENV_INBOUND_LISTEN_ADDR),
+            ("LINKERD2_PROXY_CONTROL_LISTENER", ENV_CONTROL_LISTEN_ADDR),
+            ("LINKERD2_PROXY_METRICS_LISTENER", ENV_ADMIN_LISTEN_ADDR),
+            ("LINKERD2_PROXY_PRIVATE_FORWARD", ENV_INBOUND_FORWARD),
+            (
+                "LINKERD2_PROXY_PRIVATE_CONNECT_TIMEOUT",
+                ENV_INBOUND_CONNECT_TIMEOUT,
+            ),
+            (
+                "LINKERD2_PROXY_PUBLIC_CONNECT_TIMEOUT",
+                ENV_OUTBOUND_CONNECT_TIMEOUT,
+            ),
+        ];
+        assert_eq!(aliases.len(), LEGACY_ENV_VARS.len());
+
+        for &(name, replacement) in aliases.iter() {
+            let mut env = TestEnv::new();
+            env.put(name, "legacy".into());
+            let compat = Compat::new(&env, LEGACY_ENV_VARS);
+            assert_eq!(
+                compat.get(replacement).unwrap(),
+                Some("legacy".to_owned()),
+                "{} must alias {}",
+                name,
+                replacement,
+            );
+            assert_eq!(
+                compat.deprecations(),
+                vec![Deprecation { name, replacement }]
+            );
+        }
+    }
+
     fn test_unit<F: Fn(u64) -> Duration>(unit: &str, to_duration: F) {
         for v in &[0, 1, 23, 456_789] {
             let d = to_duration(*v);
@@ -926,6 +3243,440 @@ mod tests {
         assert_eq!(parse_duration("1"), Err(ParseError::NotADuration));
     }
 
+    #[test]
+    fn profile_wildcards() {
+        let wildcards = parse_profile_wildcards(" *.payments.svc.cluster.local., *.b.c")
+            .unwrap()
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(wildcards, vec!["*.payments.svc.cluster.local", "*.b.c"]);
+
+        assert_eq!(
+            parse_profile_wildcards("payments.svc.cluster.local").map(|_| ()),
+            Err(ParseError::NotADomainSuffix)
+        );
+        assert_eq!(
+            parse_profile_wildcards("*.").map(|_| ()),
+            Err(ParseError::NotADomainSuffix)
+        );
+    }
+
+    #[test]
+    fn parse_scrubbed_header_patterns() {
+        assert_eq!(
+            parse_header_patterns(" l5d-*, Baggage,"),
+            Ok(vec![
+                scrub_headers::Pattern::Prefix("l5d-".to_owned()),
+                scrub_headers::Pattern::Name(HeaderName::from_static("baggage")),
+            ])
+        );
+        assert_eq!(
+            parse_header_patterns("bad header"),
+            Err(ParseError::NotAHeaderName)
+        );
+    }
+
+    #[test]
+    fn parse_inbound_listen_options() {
+        let parse_inbound = |env: &TestEnv| {
+            parse_listen_options(
+                env,
+                ENV_INBOUND_LISTEN_BACKLOG,
+                ENV_INBOUND_LISTEN_REUSEPORT_ENABLED,
+                ENV_INBOUND_LISTEN_NODELAY_DISABLED,
+                ENV_INBOUND_LISTEN_RECV_BUFFER_SIZE,
+                ENV_INBOUND_LISTEN_SEND_BUFFER_SIZE,
+                ENV_INBOUND_LISTEN_TRANSPARENT_ENABLED,
+                ENV_INBOUND_LISTEN_ACCEPTORS,
+            )
+            .unwrap()
+        };
+
+        let mut env = TestEnv::new();
+        assert_eq!(parse_inbound(&env), ListenOptions::default());
+
+        env.put(ENV_INBOUND_LISTEN_BACKLOG, "4096".into());
+        env.put(ENV_INBOUND_LISTEN_REUSEPORT_ENABLED, "true".into());
+        env.put(ENV_INBOUND_LISTEN_NODELAY_DISABLED, "true".into());
+        env.put(ENV_INBOUND_LISTEN_RECV_BUFFER_SIZE, "65536".into());
+        env.put(ENV_INBOUND_LISTEN_TRANSPARENT_ENABLED, "true".into());
+        env.put(ENV_INBOUND_LISTEN_ACCEPTORS, "4".into());
+        assert_eq!(
+            parse_inbound(&env),
+            ListenOptions {
+                backlog: 4096,
+                reuse_port: true,
+                nodelay: false,
+                recv_buffer_size: Some(65536),
+                send_buffer_size: None,
+                transparent: true,
+                acceptors: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_balance_hash_keys() {
+        assert_eq!(
+            parse_hash_key("cookie:session"),
+            Ok(ring_hash::HashKey::Cookie("session".to_owned()))
+        );
+        assert_eq!(parse_hash_key("header:"), Err(ParseError::NotAHashKey));
+    }
+
+    #[test]
+    fn parse_ratios() {
+        assert_eq!(parse_ratio("0.5"), Ok(0.5));
+        assert_eq!(parse_ratio("1"), Ok(1.0));
+        assert_eq!(parse_ratio("1.5"), Err(ParseError::NotARatio));
+        assert_eq!(parse_ratio("half"), Err(ParseError::NotARatio));
+    }
+
+    #[test]
+    fn parse_router_overflows() {
+        assert_eq!(parse_router_overflow("reject"), Ok(Overflow::Reject));
+        assert_eq!(parse_router_overflow("spill"), Ok(Overflow::Spill));
+        assert_eq!(
+            parse_router_overflow("queue:100ms"),
+            Ok(Overflow::Queue(Duration::from_millis(100)))
+        );
+        assert_eq!(
+            parse_router_overflow("queue"),
+            Err(ParseError::NotARouterOverflow)
+        );
+        assert_eq!(
+            parse_router_overflow("queue:soon"),
+            Err(ParseError::NotARouterOverflow)
+        );
+    }
+
+    #[test]
+    fn parse_networks_lists() {
+        let networks = parse_networks("10.0.0.0/8, fd00::/8,192.168.1.1").unwrap();
+        assert!(networks.contains("10.1.2.3".parse().unwrap()));
+        assert!(networks.contains("fd00::1".parse().unwrap()));
+        assert!(networks.contains("192.168.1.1".parse().unwrap()));
+        assert!(!networks.contains("192.168.1.2".parse().unwrap()));
+        assert_eq!(
+            parse_networks("10.0.0.0/8,cluster").err(),
+            Some(ParseError::NotANetwork)
+        );
+        assert!(parse_networks(DEFAULT_CLUSTER_NETWORKS).is_ok());
+    }
+
+    #[test]
+    fn parse_control_addr_failover() {
+        let mut env = TestEnv::new();
+        env.put(
+            ENV_DESTINATION_SVC_ADDR,
+            "dst-0.linkerd:8086, dst-1.linkerd:8086".into(),
+        );
+        assert!(parse_control_addr(&env, ENV_DESTINATION_SVC_BASE).is_err());
+
+        env.put(
+            "LINKERD2_PROXY_DESTINATION_SVC_NAME",
+            "linkerd-destination.linkerd.serviceaccount.identity.linkerd.cluster.local".into(),
+        );
+        let ctl = parse_control_addr(&env, ENV_DESTINATION_SVC_BASE)
+            .unwrap()
+            .unwrap();
+        assert_eq!(ctl.addr.to_string(), "dst-0.linkerd:8086");
+        assert_eq!(ctl.failover_addrs.len(), 1);
+        assert_eq!(ctl.failover_addrs[0].to_string(), "dst-1.linkerd:8086");
+        assert!(ctl.identity.is_some());
+
+        env.put(ENV_DESTINATION_SVC_ADDR, "127.0.0.1:8086,[::1]:8086".into());
+        let ctl = parse_control_addr(&env, ENV_DESTINATION_SVC_BASE)
+            .unwrap()
+            .unwrap();
+        assert!(ctl.identity.is_none());
+    }
+
+    #[test]
+    fn parse_redirect_classes() {
+        assert_eq!(parse_redirect_class("neutral"), Ok(RedirectClass::Neutral));
+        assert_eq!(parse_redirect_class("Failure"), Ok(RedirectClass::Failure));
+        assert_eq!(
+            parse_redirect_class("ignore"),
+            Err(ParseError::NotARedirectClass)
+        );
+    }
+
+    #[test]
+    fn parse_inbound_tls_policies() {
+        use transport::tls::policy::Mode;
+
+        let ports = parse_ports_tls_policy("8443=require-identity, 9990=plaintext").unwrap();
+        assert_eq!(ports.get(&8443), Some(&Mode::RequireIdentity));
+        assert_eq!(ports.get(&9990), Some(&Mode::Plaintext));
+        assert_eq!(
+            parse_ports_tls_policy("8443"),
+            Err(ParseError::NotATlsPolicy)
+        );
+        assert_eq!(
+            parse_ports_tls_policy("8443=sometimes"),
+            Err(ParseError::NotATlsPolicy)
+        );
+        assert_eq!(
+            parse_ports_tls_policy("https=optional"),
+            Err(ParseError::NotANumber)
+        );
+    }
+
+    #[test]
+    fn parse_inbound_trusted_headers() {
+        assert_eq!(
+            parse_header_names("l5d-request-id, L5D-Deadline,"),
+            Ok(vec![
+                HeaderName::from_static("l5d-request-id"),
+                HeaderName::from_static("l5d-deadline"),
+            ])
+        );
+        assert_eq!(
+            parse_header_names("l5d request id"),
+            Err(ParseError::NotAHeaderName)
+        );
+        assert_eq!(
+            parse_header_names(DEFAULT_INBOUND_TRUSTED_HEADERS),
+            Ok(vec![HeaderName::from_static("l5d-orig-proto")])
+        );
+    }
+
+    #[test]
+    fn parse_egress_allow_rules() {
+        let rules = parse_egress_rules("host=api.example.com port=443; net=203.0.113.0/24;");
+        assert_eq!(rules.map(|r| r.len()), Ok(2));
+        assert!(parse_egress_rules("host=api.example.com; allow").is_err());
+    }
+
+    #[test]
+    fn parse_inbound_tls_passthrough_rules() {
+        let rules = parse_tls_passthrough_rules("api.example.com, *.example.org=10.1.2.3:443,");
+        assert_eq!(rules.map(|r| r.len()), Ok(2));
+        assert!(parse_tls_passthrough_rules("api.example.com=api.example.org:443").is_err());
+    }
+
+    #[test]
+    fn parse_tls_settings() {
+        assert_eq!(parse_tls_version("1.3"), Ok(identity::TlsVersion::Tls13));
+        assert_eq!(parse_tls_version("1.1"), Err(ParseError::NotATlsVersion));
+        assert_eq!(
+            parse_cipher_suites("TLS_AES_256_GCM_SHA384, TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256")
+                .map(|s| s.len()),
+            Ok(2)
+        );
+        assert!(parse_cipher_suites(",").is_err());
+        assert!(parse_cipher_suites("TLS_ECDHE_ECDSA_WITH_RC4_128_SHA").is_err());
+        assert_eq!(parse_session_cache_size("256"), Ok(256));
+        assert_eq!(parse_session_cache_size("0"), Err(ParseError::NotANumber));
+    }
+
+    #[test]
+    fn parse_inbound_authz_rules() {
+        let rules = parse_authz_rules("deny path=/admin; allow id=* ;").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(parse_authz_rules("").map(|r| r.len()), Ok(0));
+        assert!(parse_authz_rules("allow; permit port=80").is_err());
+        assert_eq!(parse_authz_action("deny"), Ok(authz::Action::Deny));
+        assert_eq!(
+            parse_authz_action("reject"),
+            Err(ParseError::NotAnAuthzRule)
+        );
+    }
+
+    #[test]
+    fn parse_deadline_headers_trust() {
+        assert_eq!(parse_deadline_headers("never"), Ok(deadline::Trust::Never));
+        assert_eq!(
+            parse_deadline_headers("Always"),
+            Ok(deadline::Trust::Always)
+        );
+        assert_eq!(
+            parse_deadline_headers("sometimes"),
+            Err(ParseError::NotADeadlineTrust)
+        );
+    }
+
+    #[test]
+    fn parse_identity_files() {
+        let mut env = TestEnv::new();
+        env.put(ENV_IDENTITY_CRT_FILE, "/var/run/identity/crt.pem".into());
+        env.put(ENV_IDENTITY_KEY_FILE, "/var/run/identity/key.pem".into());
+        env.put(
+            ENV_IDENTITY_TRUST_ANCHORS,
+            include_str!("../identity/testdata/ca1.pem").into(),
+        );
+        env.put(
+            ENV_IDENTITY_IDENTITY_LOCAL_NAME,
+            "foo.ns1.serviceaccount.identity.linkerd.cluster.local".into(),
+        );
+        match parse_identity_config(&env).unwrap().unwrap().source {
+            identity::Source::Files { crt, key } => {
+                assert_eq!(crt, PathBuf::from("/var/run/identity/crt.pem"));
+                assert_eq!(key, PathBuf::from("/var/run/identity/key.pem"));
+            }
+            identity::Source::Service(_) => panic!("identity must be read from files"),
+        }
+
+        // The Identity service must not also be configured.
+        env.put(ENV_IDENTITY_TOKEN_FILE, "/var/run/token".into());
+        assert!(parse_identity_config(&env).is_err());
+    }
+
+    #[test]
+    fn parse_additional_identities() {
+        let names = parse_identities("web.ns1.svc.cluster.local, web.example.com,").unwrap();
+        assert_eq!(
+            names.iter().map(|n| n.as_ref()).collect::<Vec<_>>(),
+            vec!["web.ns1.svc.cluster.local", "web.example.com"]
+        );
+        assert_eq!(
+            parse_identities("spiffe://cluster.local/ns/ns1/sa/web").map(|_| ()),
+            Err(ParseError::NameError)
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "pkcs11"))]
+    fn parse_pkcs11_key_requires_feature() {
+        let mut env = TestEnv::new();
+        assert!(parse_pkcs11_key(&env).unwrap().is_none());
+
+        env.put(
+            ENV_IDENTITY_PKCS11_MODULE,
+            "/usr/lib/softhsm/libsofthsm2.so".into(),
+        );
+        assert!(parse_pkcs11_key(&env).is_err());
+    }
+
+    #[test]
+    fn parse_access_log_destinations() {
+        assert_eq!(parse_access_log("fd:3"), Ok(access_log::Destination::Fd(3)));
+        assert_eq!(parse_access_log("fd:x"), Err(ParseError::NotANumber));
+        assert_eq!(
+            parse_access_log("/var/log/access.log"),
+            Ok(access_log::Destination::Path("/var/log/access.log".into()))
+        );
+    }
+
+    #[test]
+    fn parse_trace_settings() {
+        assert!(parse_trace_collector_uri("http://collector:4318/v1/traces").is_ok());
+        assert_eq!(
+            parse_trace_collector_uri("https://collector:4318/v1/traces"),
+            Err(ParseError::NotATraceCollector)
+        );
+        assert_eq!(
+            parse_trace_collector_uri("collector:4318"),
+            Err(ParseError::NotATraceCollector)
+        );
+        assert_eq!(parse_trace_protocol("OTLP"), Ok(trace::Protocol::Otlp));
+        assert_eq!(
+            parse_trace_protocol("opencensus"),
+            Ok(trace::Protocol::OpenCensus)
+        );
+        assert_eq!(
+            parse_trace_protocol("zipkin"),
+            Err(ParseError::NotATraceProtocol)
+        );
+        assert_eq!(parse_trace_sampler("never"), Ok(trace::Sampler::Never));
+        assert_eq!(
+            parse_trace_sampler("sometimes"),
+            Err(ParseError::NotATraceSampler)
+        );
+        assert_eq!(
+            parse_trace_sampler("0.001"),
+            Ok(trace::Sampler::Ratio(0.001))
+        );
+        assert_eq!(
+            parse_trace_route_samplers("GET /books=0.001, GET /authors=always"),
+            Ok(vec![
+                ("GET /books".to_owned(), trace::Sampler::Ratio(0.001)),
+                ("GET /authors".to_owned(), trace::Sampler::Always),
+            ])
+        );
+        assert_eq!(
+            parse_trace_route_samplers("GET /books"),
+            Err(ParseError::NotATraceSampler)
+        );
+        assert_eq!(
+            parse_trace_route_samplers("=always"),
+            Err(ParseError::NotATraceSampler)
+        );
+    }
+
+    #[test]
+    fn parse_statsd_flavors() {
+        assert_eq!(parse_statsd_flavor("StatsD"), Ok(statsd::Flavor::Statsd));
+        assert_eq!(
+            parse_statsd_flavor("dogstatsd"),
+            Ok(statsd::Flavor::DogStatsd)
+        );
+        assert_eq!(
+            parse_statsd_flavor("graphite"),
+            Err(ParseError::NotAStatsdFlavor)
+        );
+    }
+
+    #[test]
+    fn parse_dns_nameservers() {
+        assert_eq!(
+            parse_nameservers("10.96.0.10, [fd00::10]:5353", 53).unwrap(),
+            vec![
+                "10.96.0.10:53".parse::<SocketAddr>().unwrap(),
+                "[fd00::10]:5353".parse().unwrap(),
+            ]
+        );
+        assert_eq!(
+            parse_nameservers(" , ", 53).err(),
+            Some(ParseError::HostIsNotAnIpAddress)
+        );
+        assert!(parse_nameservers("kube-dns.kube-system", 53).is_err());
+        assert_eq!(
+            parse_nameservers("1.1.1.1", 853).unwrap(),
+            vec!["1.1.1.1:853".parse::<SocketAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn parse_ip_preferences() {
+        assert_eq!(
+            parse_ip_preference("prefer-v6"),
+            Ok(dns::IpPreference::PreferV6)
+        );
+        assert_eq!(
+            parse_ip_preference("v4-only"),
+            Ok(dns::IpPreference::V4Only)
+        );
+        assert_eq!(
+            parse_ip_preference("dual"),
+            Err(ParseError::NotAnIpPreference)
+        );
+    }
+
+    #[test]
+    fn parse_dns_transports() {
+        let name = |s: &str| dns::Name::try_from(s.as_bytes()).unwrap();
+        assert_eq!(parse_dns_transport("udp"), Ok(dns::Transport::Plain));
+        assert_eq!(
+            parse_dns_transport("tls:cloudflare-dns.com"),
+            Ok(dns::Transport::Tls(name("cloudflare-dns.com")))
+        );
+        assert_eq!(
+            parse_dns_transport("https:dns.example.com"),
+            Ok(dns::Transport::Https(name("dns.example.com")))
+        );
+        assert_eq!(
+            parse_dns_transport("tls"),
+            Err(ParseError::NotADnsTransport)
+        );
+        assert_eq!(
+            parse_dns_transport("quic:dns.example.com"),
+            Err(ParseError::NotADnsTransport)
+        );
+    }
+
     #[test]
     fn dns_suffixes() {
         fn p(s: &str) -> Result<Vec<String>, ParseError> {
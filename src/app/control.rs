@@ -6,9 +6,22 @@ use Addr;
 #[derive(Clone, Debug)]
 pub struct ControlAddr {
     pub addr: Addr,
+    /// Addresses of other instances of the controller, which are connected to
+    /// in turn when a connection to `addr` fails.
+    pub failover_addrs: Vec<Addr>,
     pub identity: tls::PeerIdentity,
 }
 
+impl ControlAddr {
+    /// Returns the address of the `n`th connection to the controller.
+    fn nth_addr(&self, n: usize) -> &Addr {
+        match n % (self.failover_addrs.len() + 1) {
+            0 => &self.addr,
+            i => &self.failover_addrs[i - 1],
+        }
+    }
+}
+
 impl fmt::Display for ControlAddr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&self.addr, f)
@@ -87,6 +100,9 @@ pub mod add_origin {
 }
 
 /// Resolves the controller's `addr` once before building a client.
+///
+/// Each client connects to the next of the controller's addresses, so that
+/// a failed controller is replaced by another when the client reconnects.
 pub mod resolve {
     use futures::{Future, Poll};
     use std::marker::PhantomData;
@@ -114,6 +130,8 @@ pub mod resolve {
         config: ControlAddr,
         dns: dns::Resolver,
         stack: M,
+        /// The number of clients that have been built.
+        connects: usize,
     }
 
     pub struct Init<M>
@@ -130,8 +148,9 @@ pub mod resolve {
         M::Value: svc::Service<()>,
     {
         Resolve {
-            future: dns::IpAddrFuture,
+            future: dns::IpAddrsFuture,
             config: ControlAddr,
+            addr: Addr,
             stack: M,
         },
         Inner(<M::Value as svc::Service<()>>::Future),
@@ -196,6 +215,7 @@ pub mod resolve {
                 dns: self.dns.clone(),
                 config: config.clone(),
                 stack: self.inner.clone(),
+                connects: 0,
             })
         }
     }
@@ -216,12 +236,19 @@ pub mod resolve {
         }
 
         fn call(&mut self, _target: ()) -> Self::Future {
-            let state = match self.config.addr {
-                Addr::Socket(sa) => State::make_inner(sa, &self.config, &self.stack),
+            let addr = self.config.nth_addr(self.connects).clone();
+            self.connects = self.connects.wrapping_add(1);
+            if self.connects > 1 && !self.config.failover_addrs.is_empty() {
+                debug!("connecting to controller at {}", addr);
+            }
+
+            let state = match addr {
+                Addr::Socket(sa) => State::make_inner(vec![sa], &self.config, &addr, &self.stack),
                 Addr::Name(ref na) => State::Resolve {
-                    future: self.dns.resolve_one_ip(na.name()),
+                    future: self.dns.resolve_ips(na.name()),
                     stack: self.stack.clone(),
                     config: self.config.clone(),
+                    addr: addr.clone(),
                 },
             };
 
@@ -248,11 +275,16 @@ pub mod resolve {
                     State::Resolve {
                         ref mut future,
                         ref config,
+                        ref addr,
                         ref stack,
                     } => {
-                        let ip = try_ready!(future.poll().map_err(Error::Dns));
-                        let sa = SocketAddr::from((ip, config.addr.port()));
-                        State::make_inner(sa, &config, &stack)
+                        let ips = try_ready!(future.poll().map_err(Error::Dns));
+                        let port = addr.port();
+                        let addrs = ips
+                            .into_iter()
+                            .map(|ip| SocketAddr::from((ip, port)))
+                            .collect();
+                        State::make_inner(addrs, &config, &addr, &stack)
                     }
                     State::Invalid(ref mut e) => {
                         return Err(Error::Invalid(
@@ -269,11 +301,20 @@ pub mod resolve {
         M: svc::Stack<client::Target>,
         M::Value: svc::Service<()>,
     {
-        fn make_inner(addr: SocketAddr, dst: &ControlAddr, stack: &M) -> Self {
+        /// Builds a client for the first of `addrs`; when it has several
+        /// addresses, connections race the others as they are established.
+        fn make_inner(
+            mut addrs: Vec<SocketAddr>,
+            dst: &ControlAddr,
+            dst_addr: &Addr,
+            stack: &M,
+        ) -> Self {
+            let addr = addrs.remove(0);
             let target = client::Target {
                 addr,
+                fallback_addrs: addrs,
                 server_name: dst.identity.clone(),
-                log_ctx: ::logging::admin().client("control", dst.addr.clone()),
+                log_ctx: ::logging::admin().client("control", dst_addr.clone()),
             };
 
             match stack.make(&target) {
@@ -299,6 +340,522 @@ pub mod resolve {
     impl<S: error::Error, I: error::Error> error::Error for Error<S, I> {}
 }
 
+/// Keeps a warm standby connection to the control plane.
+///
+/// Whenever a connection is made, a second connection is established in the
+/// background. When the active connection fails, the standby is promoted in
+/// its place, so that the proxy need not wait to resolve and connect to the
+/// controller again. A new standby is then established.
+pub mod standby {
+    use futures::{future::Executor, sync::oneshot, Async, Future, Poll};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    use svc;
+    use task::LazyExecutor;
+
+    #[derive(Debug)]
+    pub struct Layer<Req> {
+        enabled: bool,
+        _p: PhantomData<fn(Req)>,
+    }
+
+    #[derive(Debug)]
+    pub struct Stack<Req, M> {
+        enabled: bool,
+        inner: M,
+        _p: PhantomData<fn(Req)>,
+    }
+
+    pub struct NewService<Req, N>
+    where
+        N: svc::Service<()>,
+    {
+        enabled: bool,
+        inner: N,
+        standby: Option<oneshot::Receiver<N::Response>>,
+        _p: PhantomData<fn(Req)>,
+    }
+
+    pub enum ResponseFuture<F: Future> {
+        Standby(Option<F::Item>),
+        Connect(F),
+    }
+
+    // === impl Layer ===
+
+    pub fn layer<Req>(enabled: bool) -> Layer<Req> {
+        Layer {
+            enabled,
+            _p: PhantomData,
+        }
+    }
+
+    impl<Req> Clone for Layer<Req> {
+        fn clone(&self) -> Self {
+            layer(self.enabled)
+        }
+    }
+
+    impl<T, Req, M> svc::Layer<T, T, M> for Layer<Req>
+    where
+        M: svc::Stack<T>,
+        M::Value: svc::Service<()>,
+    {
+        type Value = <Stack<Req, M> as svc::Stack<T>>::Value;
+        type Error = <Stack<Req, M> as svc::Stack<T>>::Error;
+        type Stack = Stack<Req, M>;
+
+        fn bind(&self, inner: M) -> Self::Stack {
+            Stack {
+                inner,
+                enabled: self.enabled,
+                _p: PhantomData,
+            }
+        }
+    }
+
+    // === impl Stack ===
+
+    impl<Req, M: Clone> Clone for Stack<Req, M> {
+        fn clone(&self) -> Self {
+            Stack {
+                inner: self.inner.clone(),
+                enabled: self.enabled,
+                _p: PhantomData,
+            }
+        }
+    }
+
+    impl<T, Req, M> svc::Stack<T> for Stack<Req, M>
+    where
+        M: svc::Stack<T>,
+        M::Value: svc::Service<()>,
+    {
+        type Value = NewService<Req, M::Value>;
+        type Error = M::Error;
+
+        fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+            let inner = self.inner.make(target)?;
+            Ok(NewService {
+                inner,
+                enabled: self.enabled,
+                standby: None,
+                _p: PhantomData,
+            })
+        }
+    }
+
+    // === impl NewService ===
+
+    impl<Req, N, S> NewService<Req, N>
+    where
+        N: svc::Service<(), Response = S>,
+        N::Future: Send + 'static,
+        N::Error: fmt::Display,
+        S: svc::Service<Req> + Send + 'static,
+    {
+        /// Returns the standby connection, if it has been established and is
+        /// still usable.
+        fn take_standby(&mut self) -> Option<S> {
+            let mut rx = self.standby.take()?;
+            match rx.poll() {
+                Ok(Async::NotReady) => {
+                    self.standby = Some(rx);
+                    None
+                }
+                Ok(Async::Ready(mut svc)) => match svc.poll_ready() {
+                    Ok(_) => {
+                        debug!("promoting standby connection");
+                        Some(svc)
+                    }
+                    Err(_) => {
+                        debug!("standby connection failed");
+                        None
+                    }
+                },
+                Err(oneshot::Canceled) => None,
+            }
+        }
+
+        /// Begins establishing a standby connection in the background, unless
+        /// one already exists.
+        fn prepare_standby(&mut self) {
+            if self.standby.is_some() {
+                return;
+            }
+            match self.inner.poll_ready() {
+                Ok(Async::Ready(())) => {}
+                _ => return,
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let connect = self
+                .inner
+                .call(())
+                .map_err(|e| debug!("failed to establish standby connection: {}", e))
+                .and_then(move |svc| tx.send(svc).map_err(|_| ()));
+            match LazyExecutor.execute(connect) {
+                Ok(()) => self.standby = Some(rx),
+                Err(_) => debug!("failed to spawn standby connection"),
+            }
+        }
+    }
+
+    impl<Req, N, S> svc::Service<()> for NewService<Req, N>
+    where
+        N: svc::Service<(), Response = S>,
+        N::Future: Send + 'static,
+        N::Error: fmt::Display,
+        S: svc::Service<Req> + Send + 'static,
+    {
+        type Response = S;
+        type Error = N::Error;
+        type Future = ResponseFuture<N::Future>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            self.inner.poll_ready()
+        }
+
+        fn call(&mut self, _target: ()) -> Self::Future {
+            if !self.enabled {
+                return ResponseFuture::Connect(self.inner.call(()));
+            }
+
+            let standby = self.take_standby();
+            let future = match standby {
+                Some(svc) => ResponseFuture::Standby(Some(svc)),
+                None => ResponseFuture::Connect(self.inner.call(())),
+            };
+            self.prepare_standby();
+            future
+        }
+    }
+
+    // === impl ResponseFuture ===
+
+    impl<F: Future> Future for ResponseFuture<F> {
+        type Item = F::Item;
+        type Error = F::Error;
+
+        fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+            match *self {
+                ResponseFuture::Standby(ref mut svc) => {
+                    let svc = svc.take().expect("polled after ready");
+                    Ok(Async::Ready(svc))
+                }
+                ResponseFuture::Connect(ref mut f) => f.poll(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use futures::future;
+        use never::Never;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use svc::Service as _Service;
+        use tokio::runtime::current_thread::Runtime;
+
+        /// Establishes numbered connections, of which `broken` fails.
+        struct Connect {
+            connects: Arc<AtomicUsize>,
+            broken: Option<usize>,
+        }
+
+        struct Conn {
+            id: usize,
+            broken: bool,
+        }
+
+        impl svc::Service<()> for Connect {
+            type Response = Conn;
+            type Error = Never;
+            type Future = future::FutureResult<Conn, Never>;
+
+            fn poll_ready(&mut self) -> Poll<(), Never> {
+                Ok(().into())
+            }
+
+            fn call(&mut self, _: ()) -> Self::Future {
+                let id = self.connects.fetch_add(1, Ordering::SeqCst);
+                future::ok(Conn {
+                    id,
+                    broken: self.broken == Some(id),
+                })
+            }
+        }
+
+        impl svc::Service<()> for Conn {
+            type Response = ();
+            type Error = ();
+            type Future = future::FutureResult<(), ()>;
+
+            fn poll_ready(&mut self) -> Poll<(), ()> {
+                if self.broken {
+                    return Err(());
+                }
+                Ok(().into())
+            }
+
+            fn call(&mut self, _: ()) -> Self::Future {
+                future::ok(())
+            }
+        }
+
+        fn new_service(
+            enabled: bool,
+            broken: Option<usize>,
+        ) -> (NewService<(), Connect>, Arc<AtomicUsize>) {
+            let connects = Arc::new(AtomicUsize::new(0));
+            let svc = NewService {
+                enabled,
+                inner: Connect {
+                    connects: connects.clone(),
+                    broken,
+                },
+                standby: None,
+                _p: PhantomData,
+            };
+            (svc, connects)
+        }
+
+        /// Establishes a connection, and then drives any standby connection.
+        fn connect(rt: &mut Runtime, svc: &mut NewService<(), Connect>) -> usize {
+            let conn = rt.block_on(future::lazy(|| svc.call(()))).expect("connect");
+            rt.run().expect("standby");
+            conn.id
+        }
+
+        #[test]
+        fn promotes_standby_connection() {
+            let mut rt = Runtime::new().unwrap();
+            let (mut svc, connects) = new_service(true, None);
+
+            assert_eq!(connect(&mut rt, &mut svc), 0);
+            assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+            // The standby connection is used, and another is prepared.
+            assert_eq!(connect(&mut rt, &mut svc), 1);
+            assert_eq!(connects.load(Ordering::SeqCst), 3);
+        }
+
+        #[test]
+        fn does_not_promote_a_failed_standby_connection() {
+            let mut rt = Runtime::new().unwrap();
+            let (mut svc, connects) = new_service(true, Some(1));
+
+            assert_eq!(connect(&mut rt, &mut svc), 0);
+            assert_eq!(connect(&mut rt, &mut svc), 2);
+            assert_eq!(connects.load(Ordering::SeqCst), 4);
+        }
+
+        #[test]
+        fn does_not_prepare_standby_when_disabled() {
+            let mut rt = Runtime::new().unwrap();
+            let (mut svc, connects) = new_service(false, None);
+
+            assert_eq!(connect(&mut rt, &mut svc), 0);
+            assert_eq!(connect(&mut rt, &mut svc), 1);
+            assert_eq!(connects.load(Ordering::SeqCst), 2);
+        }
+    }
+}
+
+/// Abandons a connection to the controller when it fails to respond to a
+/// request in time.
+///
+/// A timed-out request fails, and the connection then fails its readiness
+/// check, so that it is replaced rather than left to wedge later requests.
+pub mod response_timeout {
+    use futures::{Future, Poll};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::{error, fmt};
+
+    use svc;
+    use timeout::{error::Timedout, Timeout};
+
+    type Error = Box<dyn error::Error + Send + Sync>;
+
+    #[derive(Clone, Debug)]
+    pub struct Layer {
+        timeout: Duration,
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Stack<M> {
+        inner: M,
+        timeout: Duration,
+    }
+
+    pub struct NewService<N> {
+        inner: N,
+        timeout: Duration,
+    }
+
+    pub struct MakeFuture<F> {
+        inner: F,
+        timeout: Duration,
+    }
+
+    pub struct Service<S> {
+        inner: Timeout<S>,
+        timeout: Duration,
+        timed_out: Arc<AtomicBool>,
+    }
+
+    pub struct ResponseFuture<F> {
+        inner: F,
+        timed_out: Arc<AtomicBool>,
+    }
+
+    /// Indicates that a request on the connection timed out.
+    #[derive(Debug)]
+    pub struct Unresponsive(Duration);
+
+    // === impl Layer ===
+
+    pub fn layer(timeout: Duration) -> Layer {
+        Layer { timeout }
+    }
+
+    impl<T, M> svc::Layer<T, T, M> for Layer
+    where
+        M: svc::Stack<T>,
+        M::Value: svc::Service<()>,
+    {
+        type Value = <Stack<M> as svc::Stack<T>>::Value;
+        type Error = <Stack<M> as svc::Stack<T>>::Error;
+        type Stack = Stack<M>;
+
+        fn bind(&self, inner: M) -> Self::Stack {
+            Stack {
+                inner,
+                timeout: self.timeout,
+            }
+        }
+    }
+
+    // === impl Stack ===
+
+    impl<T, M> svc::Stack<T> for Stack<M>
+    where
+        M: svc::Stack<T>,
+        M::Value: svc::Service<()>,
+    {
+        type Value = NewService<M::Value>;
+        type Error = M::Error;
+
+        fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+            let inner = self.inner.make(target)?;
+            Ok(NewService {
+                inner,
+                timeout: self.timeout,
+            })
+        }
+    }
+
+    // === impl NewService ===
+
+    impl<N> svc::Service<()> for NewService<N>
+    where
+        N: svc::Service<()>,
+    {
+        type Response = Service<N::Response>;
+        type Error = N::Error;
+        type Future = MakeFuture<N::Future>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            self.inner.poll_ready()
+        }
+
+        fn call(&mut self, target: ()) -> Self::Future {
+            MakeFuture {
+                inner: self.inner.call(target),
+                timeout: self.timeout,
+            }
+        }
+    }
+
+    // === impl MakeFuture ===
+
+    impl<F: Future> Future for MakeFuture<F> {
+        type Item = Service<F::Item>;
+        type Error = F::Error;
+
+        fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+            let inner = try_ready!(self.inner.poll());
+            Ok(Service {
+                inner: Timeout::new(inner, self.timeout),
+                timeout: self.timeout,
+                timed_out: Arc::new(AtomicBool::new(false)),
+            }
+            .into())
+        }
+    }
+
+    // === impl Service ===
+
+    impl<S, Req> svc::Service<Req> for Service<S>
+    where
+        S: svc::Service<Req>,
+        S::Error: Into<Error>,
+    {
+        type Response = S::Response;
+        type Error = Error;
+        type Future = ResponseFuture<<Timeout<S> as svc::Service<Req>>::Future>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            if self.timed_out.load(Ordering::Acquire) {
+                return Err(Unresponsive(self.timeout).into());
+            }
+            self.inner.poll_ready()
+        }
+
+        fn call(&mut self, req: Req) -> Self::Future {
+            ResponseFuture {
+                inner: self.inner.call(req),
+                timed_out: self.timed_out.clone(),
+            }
+        }
+    }
+
+    // === impl ResponseFuture ===
+
+    impl<F> Future for ResponseFuture<F>
+    where
+        F: Future<Error = Error>,
+    {
+        type Item = F::Item;
+        type Error = Error;
+
+        fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+            let timed_out = &self.timed_out;
+            self.inner.poll().map_err(|e| {
+                if e.is::<Timedout>() {
+                    warn!("controller did not respond; reconnecting");
+                    timed_out.store(true, Ordering::Release);
+                }
+                e
+            })
+        }
+    }
+
+    // === impl Unresponsive ===
+
+    impl fmt::Display for Unresponsive {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "controller did not respond within {:?}", self.0)
+        }
+    }
+
+    impl error::Error for Unresponsive {}
+}
+
 /// Creates a client suitable for gRPC.
 pub mod client {
     use hyper::body::Payload;
@@ -315,6 +872,7 @@ pub mod client {
     #[derive(Clone, Debug)]
     pub struct Target {
         pub(super) addr: SocketAddr,
+        pub(super) fallback_addrs: Vec<SocketAddr>,
         pub(super) server_name: tls::PeerIdentity,
         pub(super) log_ctx: ::logging::Client<&'static str, Addr>,
     }
@@ -336,6 +894,10 @@ pub mod client {
         fn peer_addr(&self) -> SocketAddr {
             self.addr
         }
+
+        fn fallback_peer_addrs(&self) -> &[SocketAddr] {
+            &self.fallback_addrs
+        }
     }
 
     impl tls::HasPeerIdentity for Target {
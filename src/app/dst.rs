@@ -6,6 +6,7 @@ use std::time::Duration;
 use tower_retry::budget::Budget;
 
 use proxy::http::{
+    max_body_size::HasMaxBodySize,
     metrics::classify::{CanClassify, Classify, ClassifyEos, ClassifyResponse},
     profiles, retry, timeout,
 };
@@ -28,7 +29,7 @@ pub struct Route {
 #[derive(Clone, Debug)]
 pub struct Retry {
     budget: Arc<Budget>,
-    response_classes: profiles::ResponseClasses,
+    classify: classify::Request,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -43,7 +44,10 @@ impl CanClassify for Route {
     type Classify = classify::Request;
 
     fn classify(&self) -> classify::Request {
-        self.route.response_classes().clone().into()
+        classify::Request::new(
+            self.route.response_classes().clone(),
+            self.route.redirect_class(),
+        )
     }
 }
 
@@ -53,7 +57,7 @@ impl retry::CanRetry for Route {
     fn can_retry(&self) -> Option<Self::Retry> {
         self.route.retries().map(|retries| Retry {
             budget: retries.budget().clone(),
-            response_classes: self.route.response_classes().clone(),
+            classify: self.classify(),
         })
     }
 }
@@ -64,6 +68,12 @@ impl timeout::HasTimeout for Route {
     }
 }
 
+impl HasMaxBodySize for Route {
+    fn max_body_size(&self) -> Option<usize> {
+        self.route.max_request_body_size()
+    }
+}
+
 // === impl Retry ===
 
 impl retry::Retry for Retry {
@@ -72,10 +82,7 @@ impl retry::Retry for Retry {
         req: &http::Request<B1>,
         res: &http::Response<B2>,
     ) -> Result<(), retry::NoRetry> {
-        let class = classify::Request::from(self.response_classes.clone())
-            .classify(req)
-            .start(res)
-            .eos(None);
+        let class = self.classify.classify(req).start(res).eos(None);
 
         if class.is_failure() {
             return self
@@ -84,7 +91,10 @@ impl retry::Retry for Retry {
                 .map_err(|_overdrawn| retry::NoRetry::Budget);
         }
 
-        self.budget.deposit();
+        // Neutral responses are not retried, but don't earn retries either.
+        if !class.is_neutral() {
+            self.budget.deposit();
+        }
         Err(retry::NoRetry::Success)
     }
 
@@ -147,6 +157,17 @@ impl profiles::CanGetDestination for DstAddr {
     }
 }
 
+impl profiles::WithDstOverride for DstAddr {
+    fn with_dst_override(&self, addr: NameAddr) -> Option<Self> {
+        // Only outbound traffic is split; inbound requests are always served
+        // by the local application.
+        match self.direction {
+            Direction::Out => Some(DstAddr::outbound(Addr::Name(addr))),
+            Direction::In => None,
+        }
+    }
+}
+
 impl profiles::WithRoute for DstAddr {
     type Output = Route;
 
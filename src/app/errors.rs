@@ -3,6 +3,8 @@
 use futures::{Future, Poll};
 use http::{header, Request, Response, StatusCode};
 
+use proxy::http::error_kind::{HasErrorKind, Kind};
+use proxy::http::load_shed;
 use svc;
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -86,11 +88,24 @@ where
         match self.inner.poll() {
             Ok(ok) => Ok(ok),
             Err(err) => {
-                let response = Response::builder()
-                    .status(map_err_to_5xx(err.into()))
-                    .header(header::CONTENT_LENGTH, "0")
-                    .body(B::default())
-                    .expect("app::errors response is valid");
+                let err: Error = err.into();
+                let kind = err.error_kind().unwrap_or(Kind::Other);
+                let mut response = if kind == Kind::NoCapacity {
+                    // The router is saturated, so the request is shed and the
+                    // client is asked to back off.
+                    warn!("{}", err);
+                    load_shed::response()
+                } else {
+                    let mut response = Response::new(B::default());
+                    *response.status_mut() = map_err_to_5xx(err);
+                    response
+                };
+                response.headers_mut().insert(
+                    header::CONTENT_LENGTH,
+                    header::HeaderValue::from_static("0"),
+                );
+                // Lets response classifiers label the failure by its kind.
+                response.extensions_mut().insert(kind);
 
                 Ok(response.into())
             }
@@ -101,10 +116,7 @@ where
 fn map_err_to_5xx(e: Error) -> StatusCode {
     use proxy::http::router::error as router;
 
-    if let Some(ref c) = e.downcast_ref::<router::NoCapacity>() {
-        warn!("router at capacity ({})", c.0);
-        http::StatusCode::SERVICE_UNAVAILABLE
-    } else if let Some(ref r) = e.downcast_ref::<router::MakeRoute>() {
+    if let Some(ref r) = e.downcast_ref::<router::MakeRoute>() {
         error!("router error: {:?}", r);
         http::StatusCode::BAD_GATEWAY
     } else if let Some(_) = e.downcast_ref::<router::NotRecognized>() {
@@ -116,3 +128,39 @@ fn map_err_to_5xx(e: Error) -> StatusCode {
         http::StatusCode::BAD_GATEWAY
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use proxy::http::router::error as router;
+
+    fn respond(err: Error) -> Response<()> {
+        let inner = future::err::<Response<()>, Error>(err);
+        ResponseFuture { inner }
+            .wait()
+            .expect("errors must be responses")
+    }
+
+    #[test]
+    fn no_capacity_is_shed() {
+        let rsp = respond(Box::new(router::NoCapacity {
+            capacity: 1,
+            in_flight: 1,
+        }));
+        assert_eq!(rsp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            rsp.headers()[header::RETRY_AFTER],
+            load_shed::RETRY_AFTER_SECS
+        );
+        assert!(rsp.extensions().get::<load_shed::Shed>().is_some());
+    }
+
+    #[test]
+    fn other_errors_are_not_shed() {
+        let rsp = respond(Box::new(router::NotRecognized));
+        assert_eq!(rsp.status(), StatusCode::BAD_GATEWAY);
+        assert!(rsp.headers().get(header::RETRY_AFTER).is_none());
+        assert!(rsp.extensions().get::<load_shed::Shed>().is_none());
+    }
+}
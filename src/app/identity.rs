@@ -1,27 +1,94 @@
-use futures::{Async, Future, Poll};
+use futures::{task::AtomicTask, Async, Future, Poll};
 use futures_watch::{Store, Watch};
-use std::sync::Arc;
+use rand;
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio_timer::{clock, Delay};
+use std::{fmt, fs};
+use tokio_timer::{clock, Delay, Interval};
 use tower_grpc::{self as grpc, generic::client::GrpcService, BoxBody};
 
 use api::identity as api;
+use backoff;
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge};
 use never::Never;
-
-pub use identity::{Crt, CrtKey, Csr, InvalidName, Key, Name, TokenSource, TrustAnchors};
+use token_bucket::{self, TokenBucket};
+
+#[cfg(feature = "pkcs11")]
+pub use identity::pkcs11;
+pub use identity::{
+    CipherSuite, Crl, Crt, CrtKey, Csr, InvalidName, Key, KeyLog, Name, TlsVersion, TokenSource,
+    TrustAnchors,
+};
 use transport::tls;
 
-/// Configures the Identity service and local identity.
+metrics! {
+    identity_cert_refresh_consecutive_failures: Gauge {
+        "Number of consecutive failed attempts to obtain a certificate"
+    },
+    identity_cert_certify_total: Counter {
+        "Total number of requests to the Identity service to certify the local identity"
+    },
+    identity_cert_expiration_timestamp_seconds: Gauge {
+        "Time when the current certificate expires (in seconds since the UNIX epoch)"
+    },
+    identity_cert_refresh_timestamp_seconds: Gauge {
+        "Time of the last successful certificate refresh (in seconds since the UNIX epoch)"
+    },
+    identity_cert_not_yet_valid_total: Counter {
+        "Total number of certificates obtained before their validity period began"
+    },
+    identity_refresh_tokens_available: Gauge {
+        "Number of forced certificate refreshes that may be requested immediately"
+    },
+    identity_refresh_requests_total: Counter {
+        "Total number of forced certificate refreshes requested"
+    }
+}
+
+/// Configures the local identity.
 #[derive(Clone, Debug)]
 pub struct Config {
-    pub svc: super::control::ControlAddr,
     pub trust_anchors: TrustAnchors,
+    /// When set, the trust anchors are reloaded when this file changes.
+    pub trust_anchors_file: Option<PathBuf>,
+    /// When set, the certificates that it revokes are rejected.
+    pub crl: Option<Crl>,
+    pub local_name: Name,
+    /// Other DNS-like names for which inbound TLS connections are terminated.
+    pub additional_names: Vec<Name>,
+    pub source: Source,
+}
+
+/// Determines how the local identity's certificate is obtained.
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// The certificate is obtained from the Identity service.
+    Service(ServiceConfig),
+    /// The PEM-encoded certificate chain and PKCS#8 key are read from files,
+    /// e.g. as issued by cert-manager, which are read again periodically so
+    /// that they may be renewed.
+    Files { crt: PathBuf, key: PathBuf },
+}
+
+/// Configures the Identity service.
+#[derive(Clone, Debug)]
+pub struct ServiceConfig {
+    pub svc: super::control::ControlAddr,
     pub key: Key,
     pub csr: Csr,
     pub token: TokenSource,
-    pub local_name: Name,
     pub min_refresh: Duration,
     pub max_refresh: Duration,
+    /// The fraction of a certificate's lifetime after which it is refreshed.
+    pub refresh_fraction: f64,
+    /// The fraction of the refresh delay by which a refresh may be advanced.
+    pub refresh_jitter: f64,
+    pub min_retry: Duration,
+    pub max_retry: Duration,
 }
 
 /// Holds the process's local TLS identity state.
@@ -29,8 +96,9 @@ pub struct Config {
 /// Updates dynamically as certificates are provisioned from the Identity service.
 #[derive(Clone, Debug)]
 pub struct Local {
-    trust_anchors: TrustAnchors,
+    trust_anchors: Watch<TrustAnchors>,
     name: Name,
+    additional_names: Vec<Name>,
     crt_key: Watch<Option<CrtKey>>,
 }
 
@@ -41,19 +109,123 @@ pub struct AwaitCrt(Option<Local>);
 #[derive(Copy, Clone, Debug)]
 pub struct LostDaemon;
 
+/// Requests that a `Daemon` refresh its certificate immediately.
+///
+/// Forced refreshes are rate-limited to one per `min_refresh`.
+#[derive(Clone, Debug)]
+pub struct Refresh(Arc<RefreshState>);
+
+/// Indicates that a refresh was requested too recently; another may be
+/// requested after the contained duration.
+#[derive(Copy, Clone, Debug)]
+pub struct RateLimited(pub Duration);
+
+#[derive(Debug)]
+struct RefreshState {
+    requested: AtomicBool,
+    task: AtomicTask,
+    limit: Mutex<TokenBucket>,
+}
+
 pub type CrtKeyStore = Store<Option<CrtKey>>;
 
+pub type TrustAnchorsStore = Store<TrustAnchors>;
+
+/// How often the certificate and key files are read.
+const FILES_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the trust anchors file is read.
+const TRUST_ANCHORS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the CRL file is read.
+const CRL_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Polls a file to update the trust anchors.
+pub struct WatchTrustAnchors {
+    path: PathBuf,
+    interval: Interval,
+    /// The contents of the file when it was last read.
+    pem: Option<String>,
+    /// The current trust anchors, whose settings are kept when the roots are
+    /// reloaded.
+    trust_anchors: TrustAnchors,
+    store: TrustAnchorsStore,
+}
+
+/// Polls a certificate revocation list file, so that it may be updated.
+pub struct WatchCrl {
+    crl: Crl,
+    interval: Interval,
+}
+
+/// Records the outcomes of a `Daemon`'s attempts to obtain a certificate, and
+/// the certificates that a `WatchFiles` reads before they are valid.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics(Arc<Counts>);
+
+/// Reports the outcomes of a `Daemon`'s attempts to obtain a certificate.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Counts>);
+
+#[derive(Debug, Default)]
+struct Counts {
+    consecutive_failures: AtomicUsize,
+    certify_successes: AtomicUsize,
+    certify_failures: AtomicUsize,
+    /// The current certificate's expiry, in seconds since the UNIX epoch, or
+    /// zero when no certificate has been obtained.
+    expiry_secs: AtomicUsize,
+    /// The time of the last refresh, in seconds since the UNIX epoch, or zero
+    /// when no certificate has been obtained.
+    refresh_secs: AtomicUsize,
+    /// The number of certificates that were not yet valid when obtained.
+    not_yet_valid: AtomicUsize,
+    /// The daemon's forced refresh limit, once a daemon has been created.
+    refresh: Mutex<Option<Refresh>>,
+}
+
+/// Labels `identity_cert_certify_total` by outcome.
+struct CertifyResult(&'static str);
+
+/// Labels `identity_refresh_requests_total` by whether the request was
+/// rate-limited.
+struct RefreshResult(&'static str);
+
+pub fn metrics() -> (Metrics, Report) {
+    let metrics = Metrics::default();
+    let report = Report(metrics.0.clone());
+    (metrics, report)
+}
+
+/// Reads the local identity's certificate and key from files.
+pub struct WatchFiles {
+    name: Name,
+    crt: PathBuf,
+    key: PathBuf,
+    interval: Interval,
+    /// The contents of the certificate and key files when they were last
+    /// certified.
+    contents: Option<(Vec<u8>, Vec<u8>)>,
+    trust_anchors: Watch<TrustAnchors>,
+    crt_key: CrtKeyStore,
+    metrics: Metrics,
+}
+
 /// Drives updates.
 pub struct Daemon<T>
 where
     T: GrpcService<BoxBody>,
     T::ResponseBody: grpc::Body,
 {
-    config: Config,
+    name: Name,
+    config: ServiceConfig,
     client: api::client::Identity<T>,
     crt_key: Store<Option<CrtKey>>,
+    trust_anchors: Watch<TrustAnchors>,
     expiry: SystemTime,
     inner: Inner<T>,
+    refresh: Refresh,
+    metrics: Metrics,
 }
 
 enum Inner<T>
@@ -66,51 +238,114 @@ where
     Pending(grpc::client::unary::ResponseFuture<api::CertifyResponse, T::Future, T::ResponseBody>),
 }
 
-// === impl Config ===
+// === impl ServiceConfig ===
 
-impl Config {
+impl ServiceConfig {
     /// Returns a future that fires when a refresh should occur.
     ///
-    /// A refresh is scheduled at 70% of the current certificate's lifetime;
-    /// though it is never less than min_refresh or larger than max_refresh.
+    /// A refresh is scheduled at refresh_fraction of the current
+    /// certificate's lifetime; though it is never less than min_refresh or
+    /// larger than max_refresh. The refresh is then advanced by a random
+    /// amount, up to refresh_jitter of the delay, so that proxies that were
+    /// certified together do not all refresh together.
     fn refresh(&self, expiry: SystemTime) -> Delay {
         let now = clock::now();
 
         let refresh = match expiry
             .duration_since(SystemTime::now())
             .ok()
-            .map(|d| d * 7 / 10) // 70% duration
+            .map(|d| scale(d, self.refresh_fraction))
         {
             None => self.min_refresh,
             Some(lifetime) if lifetime < self.min_refresh => self.min_refresh,
             Some(lifetime) if self.max_refresh < lifetime => self.max_refresh,
             Some(lifetime) => lifetime,
         };
+        let refresh = advance(refresh, self.refresh_jitter, self.min_refresh);
         trace!("will refresh in {:?}", refresh);
         Delay::new(now + refresh)
     }
+
+    /// Returns a future that fires when a failed refresh should be retried.
+    ///
+    /// The backoff doubles with each consecutive failure, from min_retry up
+    /// to max_retry, and is jittered so that proxies that failed together do
+    /// not retry together.
+    fn retry(&self, failures: usize) -> Delay {
+        let wait = backoff::exponential(self.min_retry, self.max_retry, failures);
+        let wait = backoff::jitter(wait);
+        debug!("will retry in {:?}", wait);
+        Delay::new(clock::now() + wait)
+    }
+}
+
+/// Scales `d` by `factor`, with millisecond precision.
+fn scale(d: Duration, factor: f64) -> Duration {
+    let ms = d.as_secs() * 1_000 + u64::from(d.subsec_millis());
+    Duration::from_millis((ms as f64 * factor) as u64)
+}
+
+/// Shortens `refresh` by a random amount of up to `jitter` of its duration,
+/// though never below `min`.
+fn advance(refresh: Duration, jitter: f64, min: Duration) -> Duration {
+    let early = scale(refresh, jitter * rand::random::<f64>());
+    (refresh - early).max(min)
 }
 
 // === impl Local ===
 
 impl Local {
-    pub fn new(config: &Config) -> (Self, CrtKeyStore) {
+    pub fn new(config: &Config) -> (Self, CrtKeyStore, TrustAnchorsStore) {
         let (w, s) = Watch::new(None);
+        let (ta_w, ta_s) = Watch::new(config.trust_anchors.clone());
         let l = Local {
             name: config.local_name.clone(),
-            trust_anchors: config.trust_anchors.clone(),
+            additional_names: config.additional_names.clone(),
+            trust_anchors: ta_w,
             crt_key: w,
         };
-        (l, s)
+        (l, s, ta_s)
     }
 
     pub fn name(&self) -> &Name {
         &self.name
     }
 
+    /// Indicates whether a certificate has been obtained.
+    pub fn is_ready(&self) -> bool {
+        self.crt_key.borrow().is_some()
+    }
+
+    /// Polls until a certificate has been obtained.
+    pub fn poll_ready(&mut self) -> Poll<(), LostDaemon> {
+        use futures::Stream;
+
+        loop {
+            if self.is_ready() {
+                return Ok(Async::Ready(()));
+            }
+
+            match self.crt_key.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(Some(()))) => {} // continue
+                Err(_) | Ok(Async::Ready(None)) => return Err(LostDaemon),
+            }
+        }
+    }
+
+    /// Returns the current certificate, if one has been obtained.
+    pub fn crt_key(&self) -> Option<CrtKey> {
+        self.crt_key.borrow().clone()
+    }
+
     pub fn await_crt(self) -> AwaitCrt {
         AwaitCrt(Some(self))
     }
+
+    /// Returns a watch that is notified when the trust anchors change.
+    pub fn watch_trust_anchors(&self) -> Watch<TrustAnchors> {
+        self.trust_anchors.clone()
+    }
 }
 
 impl tls::client::HasConfig for Local {
@@ -119,13 +354,16 @@ impl tls::client::HasConfig for Local {
             return c.tls_client_config();
         }
 
-        self.trust_anchors.tls_client_config()
+        self.trust_anchors.borrow().tls_client_config()
     }
 }
 
 impl tls::listen::HasConfig for Local {
-    fn tls_server_name(&self) -> Name {
-        self.name.clone()
+    fn tls_server_names(&self) -> Vec<Name> {
+        let mut names = Vec::with_capacity(self.additional_names.len() + 1);
+        names.push(self.name.clone());
+        names.extend(self.additional_names.iter().cloned());
+        names
     }
 
     fn tls_server_config(&self) -> Arc<tls::listen::Config> {
@@ -143,15 +381,33 @@ impl<T> Daemon<T>
 where
     T: GrpcService<BoxBody> + Clone,
 {
-    pub fn new(config: Config, crt_key: CrtKeyStore, client: T) -> Self {
+    pub fn new(
+        name: Name,
+        config: ServiceConfig,
+        crt_key: CrtKeyStore,
+        trust_anchors: Watch<TrustAnchors>,
+        client: T,
+        metrics: Metrics,
+    ) -> Self {
+        let refresh = Refresh::new(config.min_refresh);
+        metrics.report_refreshes(&refresh);
         Self {
+            name,
             config,
             crt_key,
+            trust_anchors,
             inner: Inner::ShouldRefresh,
             expiry: UNIX_EPOCH,
             client: api::client::Identity::new(client),
+            refresh,
+            metrics,
         }
     }
+
+    /// Returns a handle that may be used to force this daemon to refresh.
+    pub fn refresh(&self) -> Refresh {
+        self.refresh.clone()
+    }
 }
 
 impl<T> Future for Daemon<T>
@@ -166,7 +422,11 @@ where
             self.inner = match self.inner {
                 Inner::Waiting(ref mut d) => {
                     trace!("daemon waiting");
-                    if let Ok(Async::NotReady) = d.poll() {
+                    if self.refresh.take_requested() {
+                        debug!("daemon refresh requested");
+                    } else if self.trust_anchors_changed() {
+                        debug!("trust anchors changed; refreshing");
+                    } else if let Ok(Async::NotReady) = d.poll() {
                         return Ok(Async::NotReady);
                     }
                     Inner::ShouldRefresh
@@ -182,15 +442,17 @@ where
                         Ok(token) => {
                             let req = grpc::Request::new(api::CertifyRequest {
                                 token,
-                                identity: self.config.local_name.as_ref().to_owned(),
+                                identity: self.name.as_ref().to_owned(),
                                 certificate_signing_request: self.config.csr.to_vec(),
                             });
                             trace!("daemon certifying");
                             Inner::Pending(self.client.certify(req))
                         }
                         Err(e) => {
-                            error!("Failed to read authentication token: {}", e);
-                            Inner::Waiting(self.config.refresh(self.expiry))
+                            // The token is read again on the next attempt, in
+                            // case it has not been provisioned yet.
+                            warn!("Failed to read authentication token: {}", e);
+                            Inner::Waiting(self.config.retry(self.metrics.incr_failures()))
                         }
                     }
                 }
@@ -205,27 +467,40 @@ where
                                 valid_until,
                             } = rsp.into_inner();
 
-                            match valid_until
+                            let delay = match valid_until
                                 .and_then(|d| Result::<SystemTime, Duration>::from(d).ok())
                             {
-                                None => error!(
+                                None => {
+                                    error!(
                                     "Identity service did not specify a certificate expiration."
-                                ),
+                                );
+                                    self.config.retry(self.metrics.certify_failed())
+                                }
                                 Some(expiry) => {
                                     let key = self.config.key.clone();
                                     let crt = Crt::new(
-                                        self.config.local_name.clone(),
+                                        self.name.clone(),
                                         leaf_certificate,
                                         intermediate_certificates,
                                         expiry,
                                     );
 
-                                    match self.config.trust_anchors.certify(key, crt) {
+                                    let certified = self.trust_anchors.borrow().certify(key, crt);
+                                    match certified {
                                         Err(e) => {
                                             error!("Received invalid ceritficate: {}", e);
+                                            self.config.retry(self.metrics.certify_failed())
                                         }
                                         Ok(crt_key) => {
                                             debug!("daemon certified until {:?}", expiry);
+                                            if let Some(skew) = not_yet_valid(&crt_key) {
+                                                warn!(
+                                                    "certificate is not valid for another {:?}; \
+                                                     the local clock may be skewed",
+                                                    skew
+                                                );
+                                                self.metrics.not_yet_valid();
+                                            }
                                             if self.crt_key.store(Some(crt_key)).is_err() {
                                                 // If we can't store a value, than all observations
                                                 // have been dropped and we can stop refreshing.
@@ -233,16 +508,18 @@ where
                                             }
 
                                             self.expiry = expiry;
+                                            self.metrics.certified(expiry);
+                                            self.config.refresh(self.expiry)
                                         }
                                     }
                                 }
-                            }
+                            };
 
-                            Inner::Waiting(self.config.refresh(self.expiry))
+                            Inner::Waiting(delay)
                         }
                         Err(e) => {
                             error!("Failed to certify identity: {}", e);
-                            Inner::Waiting(self.config.refresh(self.expiry))
+                            Inner::Waiting(self.config.retry(self.metrics.certify_failed()))
                         }
                     }
                 }
@@ -251,29 +528,493 @@ where
     }
 }
 
-// === impl AwaitCrt ===
+impl<T> Daemon<T>
+where
+    T: GrpcService<BoxBody>,
+{
+    /// Returns whether the trust anchors have changed since this was last
+    /// called, registering the current task to be notified of changes.
+    ///
+    /// Certificates are certified with the trust anchors, so they must be
+    /// obtained again when the trust anchors change.
+    fn trust_anchors_changed(&mut self) -> bool {
+        use futures::Stream;
 
-impl Future for AwaitCrt {
-    type Item = Local;
-    type Error = LostDaemon;
+        match self.trust_anchors.poll() {
+            Ok(Async::Ready(Some(()))) => true,
+            _ => false,
+        }
+    }
+}
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+// === impl WatchFiles ===
+
+impl WatchFiles {
+    pub fn new(
+        name: Name,
+        crt: PathBuf,
+        key: PathBuf,
+        trust_anchors: Watch<TrustAnchors>,
+        crt_key: CrtKeyStore,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            name,
+            crt,
+            key,
+            interval: Interval::new(clock::now(), FILES_POLL_INTERVAL),
+            contents: None,
+            trust_anchors,
+            crt_key,
+            metrics,
+        }
+    }
+
+    /// Reads the certificate and key, if they have changed since they were
+    /// last certified (or if the trust anchors have changed).
+    fn read(&self, trust_anchors_changed: bool) -> Option<(Vec<u8>, Vec<u8>)> {
+        let read = |path: &PathBuf| {
+            fs::read(path)
+                .map_err(|e| warn!("failed to read {}: {}", path.display(), e))
+                .ok()
+        };
+        let contents = (read(&self.crt)?, read(&self.key)?);
+        if !trust_anchors_changed && self.contents.as_ref() == Some(&contents) {
+            return None;
+        }
+        Some(contents)
+    }
+
+    fn certify(&self, crt: &[u8], key: &[u8]) -> Option<CrtKey> {
+        let key = Key::from_pem(key)
+            .map_err(|e| warn!("invalid key in {}: {}", self.key.display(), e))
+            .ok()?;
+        // The certificate's expiry is not known, since it is not parsed;
+        // instead, the files are read again periodically.
+        let crt = Crt::from_pem(self.name.clone(), crt, UNIX_EPOCH).or_else(|| {
+            warn!("no certificates in {}", self.crt.display());
+            None
+        })?;
+        self.trust_anchors
+            .borrow()
+            .certify(key, crt)
+            .map_err(|e| warn!("invalid certificate in {}: {}", self.crt.display(), e))
+            .ok()
+    }
+}
+
+impl Future for WatchFiles {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
         use futures::Stream;
 
-        let mut local = self.0.take().expect("polled after ready");
         loop {
-            if (*local.crt_key.borrow()).is_some() {
-                return Ok(Async::Ready(local));
+            let trust_anchors_changed = match self.trust_anchors.poll() {
+                Ok(Async::Ready(Some(()))) => true,
+                _ => false,
+            };
+            if !trust_anchors_changed {
+                match self.interval.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(_)) => {}
+                    Err(e) => {
+                        error!("identity files timer failed: {}", e);
+                        return Err(());
+                    }
+                }
             }
 
-            match local.crt_key.poll() {
-                Ok(Async::NotReady) => {
-                    self.0 = Some(local);
-                    return Ok(Async::NotReady);
+            let (crt, key) = match self.read(trust_anchors_changed) {
+                Some(contents) => contents,
+                None => continue,
+            };
+            if let Some(crt_key) = self.certify(&crt, &key) {
+                debug!("certified identity from {}", self.crt.display());
+                if let Some(skew) = not_yet_valid(&crt_key) {
+                    warn!(
+                        "certificate in {} is not valid for another {:?}; \
+                         the local clock may be skewed",
+                        self.crt.display(),
+                        skew
+                    );
+                    self.metrics.not_yet_valid();
                 }
-                Ok(Async::Ready(Some(()))) => {} // continue
-                Err(_) | Ok(Async::Ready(None)) => return Err(LostDaemon),
+                if self.crt_key.store(Some(crt_key)).is_err() {
+                    // The identity is no longer used.
+                    return Ok(Async::Ready(()));
+                }
+                self.contents = Some((crt, key));
+            }
+        }
+    }
+}
+
+// === impl WatchTrustAnchors ===
+
+/// Reloads the trust anchors from `path` whenever its contents change.
+pub fn watch_trust_anchors(
+    path: PathBuf,
+    trust_anchors: TrustAnchors,
+    store: TrustAnchorsStore,
+) -> WatchTrustAnchors {
+    // The configured trust anchors were read from the file at startup.
+    let pem = fs::read_to_string(&path).ok();
+    WatchTrustAnchors {
+        path,
+        interval: Interval::new(
+            clock::now() + TRUST_ANCHORS_POLL_INTERVAL,
+            TRUST_ANCHORS_POLL_INTERVAL,
+        ),
+        pem,
+        trust_anchors,
+        store,
+    }
+}
+
+impl Future for WatchTrustAnchors {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.interval.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(_)) => {}
+                Err(e) => {
+                    error!("trust anchors timer failed: {}", e);
+                    return Err(());
+                }
+            }
+
+            let pem = match fs::read_to_string(&self.path) {
+                Ok(pem) => pem,
+                Err(e) => {
+                    warn!("failed to read {}: {}", self.path.display(), e);
+                    continue;
+                }
+            };
+            if self.pem.as_ref() == Some(&pem) {
+                continue;
+            }
+
+            let trust_anchors = self.trust_anchors.with_roots_from_pem(&pem);
+            match trust_anchors {
+                Some(trust_anchors) => {
+                    if self.store.store(trust_anchors.clone()).is_err() {
+                        // The identity is no longer used.
+                        return Ok(Async::Ready(()));
+                    }
+                    self.trust_anchors = trust_anchors;
+                    info!("reloaded trust anchors from {}", self.path.display());
+                }
+                None => warn!("ignoring invalid trust anchors in {}", self.path.display()),
             }
+            self.pem = Some(pem);
+        }
+    }
+}
+
+// === impl WatchCrl ===
+
+/// Reloads the CRL whenever its file changes.
+pub fn watch_crl(crl: Crl) -> WatchCrl {
+    WatchCrl {
+        crl,
+        interval: Interval::new(clock::now() + CRL_POLL_INTERVAL, CRL_POLL_INTERVAL),
+    }
+}
+
+impl Future for WatchCrl {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.interval.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(_)) => {}
+                Err(e) => {
+                    error!("CRL timer failed: {}", e);
+                    return Err(());
+                }
+            }
+
+            match self.crl.reload() {
+                Ok(true) => info!("reloaded CRL from {}", self.crl.path().display()),
+                Ok(false) => {}
+                Err(e) => warn!(
+                    "keeping the previous CRL; failed to read {}: {}",
+                    self.crl.path().display(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+// === impl Metrics ===
+
+impl Metrics {
+    /// Records a failure, returning the number of consecutive failures.
+    fn incr_failures(&self) -> usize {
+        self.0.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Records a failed certification, returning the number of consecutive
+    /// failures.
+    fn certify_failed(&self) -> usize {
+        self.0.certify_failures.fetch_add(1, Ordering::AcqRel);
+        self.incr_failures()
+    }
+
+    /// Records a certificate that expires at `expiry`.
+    fn certified(&self, expiry: SystemTime) {
+        self.0.consecutive_failures.store(0, Ordering::Release);
+        self.0.certify_successes.fetch_add(1, Ordering::AcqRel);
+        self.0
+            .expiry_secs
+            .store(unix_secs(expiry), Ordering::Release);
+        self.0
+            .refresh_secs
+            .store(unix_secs(SystemTime::now()), Ordering::Release);
+    }
+
+    /// Records a certificate that was obtained before it became valid.
+    fn not_yet_valid(&self) {
+        self.0.not_yet_valid.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Reports the state of `refresh`'s rate limit.
+    fn report_refreshes(&self, refresh: &Refresh) {
+        *self
+            .0
+            .refresh
+            .lock()
+            .expect("refresh metrics lock poisoned") = Some(refresh.clone());
+    }
+}
+
+/// Returns how long until a certificate becomes valid, if its validity period
+/// has not yet begun.
+///
+/// Such a certificate is only accepted within the configured clock skew
+/// tolerance, but its presence indicates that this host's clock is behind the
+/// issuer's.
+fn not_yet_valid(crt_key: &CrtKey) -> Option<Duration> {
+    let not_before = crt_key.info()?.not_before;
+    not_before.duration_since(SystemTime::now()).ok()
+}
+
+fn unix_secs(t: SystemTime) -> usize {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as usize)
+        .unwrap_or(0)
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let load = |n: &AtomicUsize| n.load(Ordering::Acquire) as u64;
+
+        let failures = Gauge::from(load(&self.0.consecutive_failures));
+        identity_cert_refresh_consecutive_failures.fmt_help(f)?;
+        identity_cert_refresh_consecutive_failures.fmt_metric(f, failures)?;
+
+        identity_cert_certify_total.fmt_help(f)?;
+        Counter::from(load(&self.0.certify_successes)).fmt_metric_labeled(
+            f,
+            identity_cert_certify_total.name,
+            CertifyResult("success"),
+        )?;
+        Counter::from(load(&self.0.certify_failures)).fmt_metric_labeled(
+            f,
+            identity_cert_certify_total.name,
+            CertifyResult("failure"),
+        )?;
+
+        // The timestamps are only known once a certificate has been obtained.
+        let expiry = load(&self.0.expiry_secs);
+        if expiry != 0 {
+            identity_cert_expiration_timestamp_seconds.fmt_help(f)?;
+            identity_cert_expiration_timestamp_seconds.fmt_metric(f, Gauge::from(expiry))?;
+        }
+        let refresh = load(&self.0.refresh_secs);
+        if refresh != 0 {
+            identity_cert_refresh_timestamp_seconds.fmt_help(f)?;
+            identity_cert_refresh_timestamp_seconds.fmt_metric(f, Gauge::from(refresh))?;
+        }
+
+        identity_cert_not_yet_valid_total.fmt_help(f)?;
+        identity_cert_not_yet_valid_total
+            .fmt_metric(f, Counter::from(load(&self.0.not_yet_valid)))?;
+
+        let refresh = self
+            .0
+            .refresh
+            .lock()
+            .expect("refresh metrics lock poisoned");
+        if let Some(ref refresh) = *refresh {
+            let limit = refresh.snapshot();
+            identity_refresh_tokens_available.fmt_help(f)?;
+            identity_refresh_tokens_available.fmt_metric(f, Gauge::from(limit.available))?;
+
+            identity_refresh_requests_total.fmt_help(f)?;
+            Counter::from(limit.acquired).fmt_metric_labeled(
+                f,
+                identity_refresh_requests_total.name,
+                RefreshResult("accepted"),
+            )?;
+            Counter::from(limit.rejected).fmt_metric_labeled(
+                f,
+                identity_refresh_requests_total.name,
+                RefreshResult("rate_limited"),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for CertifyResult {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "result=\"{}\"", self.0)
+    }
+}
+
+impl FmtLabels for RefreshResult {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "result=\"{}\"", self.0)
+    }
+}
+
+// === impl Refresh ===
+
+impl Refresh {
+    pub(super) fn new(min_interval: Duration) -> Self {
+        Refresh(Arc::new(RefreshState {
+            requested: AtomicBool::new(false),
+            task: AtomicTask::new(),
+            limit: Mutex::new(TokenBucket::new(1, min_interval)),
+        }))
+    }
+
+    /// Asks the daemon to refresh its certificate, unless a refresh was
+    /// requested within the past `min_refresh`.
+    pub fn request(&self) -> Result<(), RateLimited> {
+        self.0
+            .limit
+            .lock()
+            .expect("refresh lock poisoned")
+            .try_acquire()
+            .map_err(|token_bucket::Empty(wait)| RateLimited(wait))?;
+
+        self.0.requested.store(true, Ordering::Release);
+        self.0.task.notify();
+        Ok(())
+    }
+
+    fn snapshot(&self) -> token_bucket::Snapshot {
+        self.0
+            .limit
+            .lock()
+            .expect("refresh lock poisoned")
+            .snapshot()
+    }
+
+    /// Returns whether a refresh has been requested since this was last
+    /// called, registering the current task to be notified of new requests.
+    fn take_requested(&self) -> bool {
+        self.0.task.register();
+        self.0.requested.swap(false, Ordering::AcqRel)
+    }
+}
+
+// === impl AwaitCrt ===
+
+impl Future for AwaitCrt {
+    type Item = Local;
+    type Error = LostDaemon;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        try_ready!(self.0.as_mut().expect("polled after ready").poll_ready());
+        Ok(Async::Ready(self.0.take().expect("polled after ready")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_record_certifications() {
+        let (metrics, report) = metrics();
+        assert_eq!(metrics.incr_failures(), 1);
+        assert_eq!(metrics.certify_failed(), 2);
+
+        let expiry = UNIX_EPOCH + Duration::from_secs(1_000);
+        metrics.certified(expiry);
+        assert_eq!(report.0.consecutive_failures.load(Ordering::Acquire), 0);
+        assert_eq!(report.0.certify_successes.load(Ordering::Acquire), 1);
+        assert_eq!(report.0.certify_failures.load(Ordering::Acquire), 1);
+        assert_eq!(report.0.expiry_secs.load(Ordering::Acquire), 1_000);
+        assert_ne!(report.0.refresh_secs.load(Ordering::Acquire), 0);
+
+        metrics.not_yet_valid();
+        assert_eq!(report.0.not_yet_valid.load(Ordering::Acquire), 1);
+    }
+
+    #[test]
+    fn metrics_report_refresh_limit() {
+        let (metrics, report) = metrics();
+        let refresh = Refresh::new(Duration::from_secs(60));
+        metrics.report_refreshes(&refresh);
+
+        assert!(refresh.request().is_ok());
+        assert!(refresh.request().is_err());
+
+        let text = report.as_display().to_string();
+        assert!(
+            text.contains("identity_refresh_tokens_available 0\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("identity_refresh_requests_total{result=\"accepted\"} 1\n"),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains("identity_refresh_requests_total{result=\"rate_limited\"} 1\n"),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn scales_durations() {
+        let d = Duration::from_secs(10);
+        assert_eq!(scale(d, 0.7), Duration::from_secs(7));
+        assert_eq!(scale(d, 0.0), Duration::from_secs(0));
+        assert_eq!(
+            scale(Duration::from_millis(1_500), 0.5),
+            Duration::from_millis(750)
+        );
+    }
+
+    #[test]
+    fn refresh_advance_is_bounded() {
+        let refresh = Duration::from_secs(100);
+        let min = Duration::from_secs(95);
+        for _ in 0..100 {
+            let r = advance(refresh, 0.1, Duration::from_secs(1));
+            assert!(Duration::from_secs(90) <= r && r <= refresh, "{:?}", r);
+
+            let r = advance(refresh, 0.1, min);
+            assert!(min <= r && r <= refresh, "{:?}", r);
         }
+        assert_eq!(advance(refresh, 0.0, min), refresh);
     }
 }
@@ -10,7 +10,7 @@ use super::identity;
 use proxy::http::router;
 use proxy::server::Source;
 use tap;
-use transport::{connect, tls};
+use transport::{connect, mesh, tls};
 use {Conditional, NameAddr};
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -18,11 +18,14 @@ pub struct Endpoint {
     pub addr: SocketAddr,
     pub dst_name: Option<NameAddr>,
     pub tls_client_id: tls::PeerIdentity,
+    /// The mesh status of the client, if it is known.
+    pub client_mesh: Option<mesh::Status>,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct RecognizeEndpoint {
     default_addr: Option<SocketAddr>,
+    networks: mesh::Networks,
 }
 
 // === impl Endpoint ===
@@ -33,6 +36,7 @@ impl From<SocketAddr> for Endpoint {
             addr,
             dst_name: None,
             tls_client_id: Conditional::None(tls::ReasonForNoPeerName::NotHttp.into()),
+            client_mesh: None,
         }
     }
 }
@@ -108,7 +112,16 @@ impl fmt::Display for Endpoint {
 
 impl RecognizeEndpoint {
     pub fn new(default_addr: Option<SocketAddr>) -> Self {
-        Self { default_addr }
+        Self {
+            default_addr,
+            networks: mesh::Networks::default(),
+        }
+    }
+
+    /// Classifies clients as unmeshed, rather than external, when they are
+    /// in `networks`.
+    pub fn with_networks(self, networks: mesh::Networks) -> Self {
+        Self { networks, ..self }
     }
 }
 
@@ -125,6 +138,7 @@ impl<A> router::Recognize<http::Request<A>> for RecognizeEndpoint {
         let tls_client_id = src
             .map(|s| s.tls_peer.clone())
             .unwrap_or_else(|| Conditional::None(tls::ReasonForNoIdentity::Disabled));
+        let client_mesh = src.map(|s| self.networks.status(s.remote.ip(), s.tls_peer.is_some()));
 
         let dst_name = req
             .extensions()
@@ -137,6 +151,7 @@ impl<A> router::Recognize<http::Request<A>> for RecognizeEndpoint {
             addr,
             dst_name,
             tls_client_id,
+            client_mesh,
         })
     }
 }
@@ -274,7 +289,9 @@ pub mod rewrite_loopback_addr {
 
 /// Adds `l5d-client-id` headers to http::Requests derived from the
 /// TlsIdentity of a `Source`.
-#[allow(dead_code)] // TODO #2597
+///
+/// Any `l5d-client-id` header sent by the client must be stripped before this
+/// layer, so that applications may trust the header's value.
 pub mod set_client_id_on_req {
     use super::super::L5D_CLIENT_ID;
     use http::header::HeaderValue;
@@ -331,18 +348,23 @@ mod tests {
     use super::{Endpoint, RecognizeEndpoint};
     use proxy::http::router::Recognize;
     use proxy::server::Source;
-    use transport::tls;
+    use transport::{mesh, tls};
     use Conditional;
 
-    fn make_h1_endpoint(addr: net::SocketAddr) -> Endpoint {
+    fn make_h1_endpoint(addr: net::SocketAddr, client_mesh: Option<mesh::Status>) -> Endpoint {
         let tls_client_id = TLS_DISABLED;
         Endpoint {
             addr,
             dst_name: None,
             tls_client_id,
+            client_mesh,
         }
     }
 
+    fn client_mesh(remote: net::SocketAddr) -> Option<mesh::Status> {
+        Some(mesh::Networks::default().status(remote.ip(), false))
+    }
+
     const TLS_DISABLED: tls::PeerIdentity = Conditional::None(tls::ReasonForNoIdentity::Disabled);
 
     quickcheck! {
@@ -352,7 +374,9 @@ mod tests {
             remote: net::SocketAddr
         ) -> bool {
             let src = Source::for_test(remote, local, Some(orig_dst), TLS_DISABLED);
-            let rec = src.orig_dst_if_not_local().map(make_h1_endpoint);
+            let rec = src
+                .orig_dst_if_not_local()
+                .map(|addr| make_h1_endpoint(addr, client_mesh(remote)));
 
             let mut req = http::Request::new(());
             req.extensions_mut().insert(src);
@@ -369,12 +393,14 @@ mod tests {
             req.extensions_mut()
                 .insert(Source::for_test(remote, local, None, TLS_DISABLED));
 
-            RecognizeEndpoint::new(default).recognize(&req) == default.map(make_h1_endpoint)
+            RecognizeEndpoint::new(default).recognize(&req)
+                == default.map(|addr| make_h1_endpoint(addr, client_mesh(remote)))
         }
 
         fn recognize_default_no_ctx(default: Option<net::SocketAddr>) -> bool {
             let req = http::Request::new(());
-            RecognizeEndpoint::new(default).recognize(&req) == default.map(make_h1_endpoint)
+            RecognizeEndpoint::new(default).recognize(&req)
+                == default.map(|addr| make_h1_endpoint(addr, None))
         }
 
         fn recognize_default_no_loop(
@@ -386,7 +412,8 @@ mod tests {
             req.extensions_mut()
                 .insert(Source::for_test(remote, local, Some(local), TLS_DISABLED));
 
-            RecognizeEndpoint::new(default).recognize(&req) == default.map(make_h1_endpoint)
+            RecognizeEndpoint::new(default).recognize(&req)
+                == default.map(|addr| make_h1_endpoint(addr, client_mesh(remote)))
         }
     }
 }
@@ -1,12 +1,15 @@
-use futures::{self, future, Future, Poll};
+use futures::{self, future, sync::oneshot, Future, Poll};
 use http;
 use hyper;
+use indexmap::IndexSet;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, SystemTime};
 use std::{error, fmt, io};
 use tokio::executor::{self, DefaultExecutor, Executor};
 use tokio::runtime::current_thread;
+use tokio_timer::{clock, Delay};
 use tower_grpc as grpc;
 
 use app::classify::{self, Class};
@@ -18,12 +21,14 @@ use logging;
 use metrics::FmtMetrics;
 use never::Never;
 use proxy::{
-    self, buffer,
+    self, authz, buffer, connection_limit, egress_policy,
     http::{
-        client, insert_target, metrics as http_metrics, normalize_uri, profiles, router, settings,
-        strip_header,
+        client, compress, connect_retry, deadline, egress, failfast, forwarded, grpc_web,
+        insert_target, load_shed, max_body_size, max_header_size, metrics as http_metrics,
+        normalize_uri, profiles, request_id, router, scrub_headers, settings, stream_idle_timeout,
+        strip_header, untrusted_headers,
     },
-    limit, reconnect,
+    limit, reconnect, tls_passthrough,
 };
 use svc::{
     self, shared,
@@ -33,14 +38,20 @@ use svc::{
 use tap;
 use task;
 use telemetry;
-use transport::{self, connect, keepalive, tls, Connection, GetOriginalDst, Listen};
+use transport::{
+    self, connect, keepalive, proxy_protocol, tls, unix, Connection, GetOriginalDst, Listen,
+};
 use {Addr, Conditional};
 
+use super::access_log::{self, AccessLog};
 use super::admin::{Admin, Readiness};
+use super::capture::{self, Captures};
 use super::config::{Config, H2Settings};
 use super::dst::DstAddr;
 use super::identity;
+use super::node_drain;
 use super::profiles::Client as ProfilesClient;
+use super::trace::{self, Tracer};
 
 /// Runs a sidecar proxy.
 ///
@@ -61,7 +72,11 @@ pub struct Main<G> {
 
 struct ProxyParts<G> {
     config: Config,
-    identity: tls::Conditional<(identity::Local, identity::CrtKeyStore)>,
+    identity: tls::Conditional<(
+        identity::Local,
+        identity::CrtKeyStore,
+        identity::TrustAnchorsStore,
+    )>,
 
     start_time: SystemTime,
 
@@ -69,6 +84,7 @@ struct ProxyParts<G> {
     control_listener: Listen<identity::Local, ()>,
 
     inbound_listener: Listen<identity::Local, G>,
+    inbound_unix_listener: Option<unix::Listen>,
     outbound_listener: Listen<identity::Local, G>,
 }
 
@@ -83,28 +99,52 @@ where
         let start_time = SystemTime::now();
 
         let identity = config.identity_config.as_ref().map(identity::Local::new);
-        let local_identity = identity.as_ref().map(|(l, _)| l.clone());
+        let local_identity = identity.as_ref().map(|(l, _, _)| l.clone());
 
-        let control_listener = Listen::bind(config.control_listener.addr, local_identity.clone())
-            .expect("dst_svc listener bind");
+        let control_listener = Listen::bind_with_options(
+            config.control_listener.addr,
+            config.control_listener.options,
+            local_identity.clone(),
+        )
+        .expect("dst_svc listener bind");
 
-        let admin_listener = Listen::bind(config.admin_listener.addr, local_identity.clone())
-            .expect("metrics listener bind");
+        let admin_listener = Listen::bind_with_options(
+            config.admin_listener.addr,
+            config.admin_listener.options,
+            local_identity.clone(),
+        )
+        .expect("metrics listener bind");
 
-        let outbound_listener = Listen::bind(
+        let outbound_listener = Listen::bind_with_options(
             config.outbound_listener.addr,
+            config.outbound_listener.options,
             Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
         )
         .expect("outbound listener bind")
         .with_original_dst(get_original_dst.clone())
         .without_protocol_detection_for(config.outbound_ports_disable_protocol_detection.clone());
 
-        let inbound_listener = Listen::bind(config.inbound_listener.addr, local_identity)
-            .expect("inbound listener bind")
-            .with_original_dst(get_original_dst.clone())
-            .without_protocol_detection_for(
-                config.inbound_ports_disable_protocol_detection.clone(),
-            );
+        let inbound_listener = Listen::bind_with_options(
+            config.inbound_listener.addr,
+            config.inbound_listener.options,
+            local_identity,
+        )
+        .expect("inbound listener bind")
+        .with_original_dst(get_original_dst.clone())
+        .without_protocol_detection_for(config.inbound_ports_disable_protocol_detection.clone())
+        .with_tls_policy(config.inbound_tls_policy.clone())
+        .with_proxy_protocol(proxy_protocol::Config {
+            ports: config.inbound_proxy_protocol_ports.clone(),
+            sources: config.inbound_proxy_protocol_sources.clone(),
+            timeout: config.inbound_proxy_protocol_timeout,
+        });
+
+        let inbound_unix_listener = config.inbound_listen_unix_path.clone().map(|path| {
+            unix::Listen::bind(path)
+                .expect("inbound unix listener bind")
+                .with_allowed_uids(config.inbound_listen_unix_allowed_uids.clone())
+                .with_original_dst(config.inbound_forward)
+        });
 
         let runtime = runtime.into();
 
@@ -115,6 +155,7 @@ where
             identity,
             start_time,
             inbound_listener,
+            inbound_unix_listener,
             outbound_listener,
             control_listener,
             admin_listener,
@@ -152,6 +193,7 @@ where
         } = self;
 
         let (drain_tx, drain_rx) = drain::channel();
+        let grace_period = proxy_parts.config.shutdown_grace_period;
 
         runtime.spawn(futures::lazy(move || {
             proxy_parts.build_proxy_task(drain_rx);
@@ -159,12 +201,29 @@ where
             Ok(())
         }));
 
-        let shutdown_signal = shutdown_signal.and_then(move |()| {
+        // Once shutdown is signaled, listeners are closed and connections are
+        // given until the grace period elapses to complete. The drain runs on
+        // the runtime, so that its deadline uses the runtime's timer.
+        let (drained_tx, drained_rx) = oneshot::channel();
+        runtime.spawn(shutdown_signal.and_then(move |()| {
             debug!("shutdown signaled");
-            drain_tx.drain()
-        });
+            let deadline = Delay::new(clock::now() + grace_period);
+            drain_tx.drain().select2(deadline).then(move |r| {
+                match r {
+                    Ok(future::Either::A(_)) => debug!("drained"),
+                    _ => info!(
+                        "connections did not complete within {:?}; closing them",
+                        grace_period
+                    ),
+                }
+                let _ = drained_tx.send(());
+                Ok(())
+            })
+        }));
 
-        runtime.run_until(shutdown_signal).expect("executor");
+        runtime
+            .run_until(drained_rx.map_err(|_| ()))
+            .expect("executor");
 
         debug!("shutdown complete");
     }
@@ -183,18 +242,23 @@ where
             start_time,
             control_listener,
             inbound_listener,
+            inbound_unix_listener,
             outbound_listener,
             admin_listener,
         } = self;
 
         const MAX_IN_FLIGHT: usize = 10_000;
 
-        const EWMA_DEFAULT_RTT: Duration = Duration::from_millis(30);
-        const EWMA_DECAY: Duration = Duration::from_secs(10);
-
         info!("using destination service at {:?}", config.destination_addr);
         match config.identity_config.as_ref() {
-            Conditional::Some(config) => info!("using identity service at {:?}", config.svc.addr),
+            Conditional::Some(config) => match config.source {
+                identity::Source::Service(ref svc) => {
+                    info!("using identity service at {:?}", svc.svc.addr)
+                }
+                identity::Source::Files { ref crt, .. } => {
+                    info!("using identity certificate from {}", crt.display())
+                }
+            },
             Conditional::None(reason) => info!("identity is DISABLED: {}", reason),
         }
         info!("routing on {:?}", outbound_listener.local_addr());
@@ -216,50 +280,129 @@ where
             config.outbound_ports_disable_protocol_detection,
         );
 
+        let (dns_metrics, dns_report) = dns::metrics::new();
+        let (dst_metrics, dst_report) = control::destination::metrics::new();
         let (dns_resolver, dns_bg) = dns::Resolver::from_system_config_with(&config)
             .unwrap_or_else(|e| {
                 // FIXME: DNS configuration should be infallible.
                 panic!("invalid DNS configuration: {:?}", e);
             });
+        let mut dns_resolver = dns_resolver.with_metrics(dns_metrics);
+        if let Some(ttl) = config.dns_negative_cache_ttl {
+            dns_resolver = dns_resolver.with_negative_cache(ttl);
+        }
 
         let (tap_layer, tap_grpc, tap_daemon) = tap::new();
 
+        let retain_idle = config.metrics_retain_idle;
+        let max_scopes = config.metrics_max_scopes;
         let (ctl_http_metrics, ctl_http_report) = {
-            let (m, r) = http_metrics::new::<ControlLabels, Class>(config.metrics_retain_idle);
+            let (m, r) = http_metrics::new::<ControlLabels, Class>(retain_idle, max_scopes);
             (m, r.with_prefix("control"))
         };
 
         let (endpoint_http_metrics, endpoint_http_report) =
-            http_metrics::new::<EndpointLabels, Class>(config.metrics_retain_idle);
+            http_metrics::new::<EndpointLabels, Class>(retain_idle, max_scopes);
 
         let (route_http_metrics, route_http_report) = {
-            let (m, r) = http_metrics::new::<RouteLabels, Class>(config.metrics_retain_idle);
+            let (m, r) = http_metrics::new::<RouteLabels, Class>(retain_idle, max_scopes);
             (m, r.with_prefix("route"))
         };
 
         let (retry_http_metrics, retry_http_report) = {
-            let (m, r) = http_metrics::new::<RouteLabels, Class>(config.metrics_retain_idle);
+            let (m, r) = http_metrics::new::<RouteLabels, Class>(retain_idle, max_scopes);
             (m, r.with_prefix("route_actual"))
         };
 
-        let (transport_metrics, transport_report) = transport::metrics::new();
+        let (transport_metrics, transport_report) =
+            transport::metrics::new(config.cluster_networks.clone());
+
+        let (egress_metrics, egress_report) = egress::new();
+
+        let (router_metrics, router_report) = router::metrics(config.metrics_retain_idle);
+
+        let (compress_metrics, compress_report) = compress::new();
+
+        let (inbound_authz, authz_report) = authz::new(config.inbound_authz.clone());
+
+        let (inbound_tls_passthrough, tls_passthrough_report) =
+            tls_passthrough::new(config.inbound_tls_passthrough.clone());
+
+        let (connection_limits, connection_limit_report) = connection_limit::new();
+        let inbound_connection_limit =
+            connection_limits.limit("inbound", config.inbound_max_connections);
+        let outbound_connection_limit =
+            connection_limits.limit("outbound", config.outbound_max_connections);
+
+        // Traffic is part of the mesh when it is addressed to a name resolved
+        // by the Destination service or to an address in the cluster.
+        let (egress_policy, egress_policy_report) =
+            egress_policy::new(config.egress_allow.clone().map(|rules| {
+                egress_policy::Config::new(
+                    rules,
+                    config.destination_get_suffixes.clone(),
+                    config.cluster_networks.clone(),
+                )
+            }));
+
+        let (identity_metrics, identity_report) = identity::metrics();
+        let (profile_metrics, profile_report) = super::profiles::metrics();
+
+        let (tasks, tasks_report) = telemetry::tasks::new();
 
         let report = endpoint_http_report
             .and_then(route_http_report)
             .and_then(retry_http_report)
             .and_then(transport_report)
+            .and_then(egress_report)
+            .and_then(router_report)
+            .and_then(compress_report)
+            .and_then(authz_report)
+            .and_then(egress_policy_report)
+            .and_then(tls_passthrough_report)
+            .and_then(connection_limit_report)
+            .and_then(identity_report)
+            .and_then(dns_report)
+            .and_then(dst_report)
+            .and_then(profile_report)
             //.and_then(tls_config_report)
             .and_then(ctl_http_report)
+            .and_then(tasks_report)
+            .and_then(telemetry::alloc_audit::Report::default())
+            .and_then(telemetry::alloc_stats::Report::default())
             .and_then(telemetry::process::Report::new(start_time));
 
         let mut identity_daemon = None;
-        let (readiness, ready_latch) = Readiness::new();
-        let local_identity = match identity {
-            Conditional::None(r) => {
-                ready_latch.release();
-                Conditional::None(r)
+        let mut identity_refresh = None;
+        let (node_draining, node_drain) = match config.node_drain_path.clone() {
+            Some(path) => {
+                let (draining, watch) = node_drain::watch(path, config.node_drain_poll_interval);
+                (draining, Some(watch))
             }
-            Conditional::Some((local_identity, crt_store)) => {
+            None => (node_drain::Draining::default(), None),
+        };
+        let readiness = Readiness::default().with_node_drain(node_draining.clone());
+        let deprecated_env_vars = config.deprecated_env_vars.clone();
+        let captures = Captures::new(
+            config.failure_capture_capacity,
+            config.failure_capture_sample_rate,
+        );
+        let admin_captures = captures.clone();
+        let access_log = AccessLog::new(config.access_log.as_ref(), config.access_log_capacity)
+            .unwrap_or_else(|e| panic!("failed to open access log: {}", e));
+        let tracer = Tracer::new(
+            config.trace_collector.clone(),
+            config.trace_sampler,
+            trace::RouteSamplers::new(config.trace_route_samplers.clone()),
+            config.trace_export_capacity,
+        )
+        .unwrap_or_else(|e| panic!("failed to start the span exporter: {}", e));
+        let admin_trace_sampling = tracer.route_samplers();
+        let metrics_statsd = config.metrics_statsd.clone();
+        let metrics_prometheus_enabled = config.metrics_prometheus_enabled;
+        let local_identity = match identity {
+            Conditional::None(r) => Conditional::None(r),
+            Conditional::Some((local_identity, crt_store, trust_anchors_store)) => {
                 use super::control;
 
                 let id_config = match config.identity_config.as_ref() {
@@ -267,43 +410,86 @@ where
                     Conditional::None(_) => unreachable!(),
                 };
 
-                // If the service is on localhost, use the inbound keepalive.
-                // If the service. is remote, use the outbound keepalive.
-                let keepalive = if id_config.svc.addr.is_loopback() {
-                    config.inbound_connect_keepalive
-                } else {
-                    config.outbound_connect_keepalive
-                };
-
-                let svc = connect::Stack::new()
-                    .push(phantom_data::layer())
-                    .push(tls::client::layer(Conditional::Some(
+                if let Some(path) = id_config.trust_anchors_file.clone() {
+                    task::spawn(identity::watch_trust_anchors(
+                        path,
                         id_config.trust_anchors.clone(),
-                    )))
-                    .push(keepalive::connect::layer(keepalive))
-                    .push(svc::timeout::layer(config.control_connect_timeout))
-                    .push(control::client::layer())
-                    .push(control::resolve::layer(dns_resolver.clone()))
-                    .push(reconnect::layer().with_fixed_backoff(config.control_backoff_delay))
-                    .push(http_metrics::layer::<_, classify::Response>(
-                        ctl_http_metrics.clone(),
-                    ))
-                    .push(proxy::grpc::req_body_as_payload::layer())
-                    .push(phantom_data::layer())
-                    .push(control::add_origin::layer())
-                    .push(buffer::layer(config.destination_concurrency_limit))
-                    .push(limit::layer(config.destination_concurrency_limit))
-                    .make(&id_config.svc)
-                    .unwrap_or_else(|e| panic!("failed to build dst_svc: {}", e));
-
-                identity_daemon = Some(identity::Daemon::new(id_config, crt_store, svc));
+                        trust_anchors_store,
+                    ));
+                }
+
+                if let Some(crl) = id_config.crl.clone() {
+                    task::spawn(identity::watch_crl(crl));
+                }
+
+                match id_config.source {
+                    identity::Source::Service(svc_config) => {
+                        // Unless a control keepalive is configured: if the service is
+                        // on localhost, use the inbound keepalive. If the service. is
+                        // remote, use the outbound keepalive.
+                        let keepalive = if config.control_connect_keepalive.is_some() {
+                            config.control_connect_keepalive
+                        } else if svc_config.svc.addr.is_loopback() {
+                            config.inbound_connect_keepalive
+                        } else {
+                            config.outbound_connect_keepalive
+                        };
+
+                        let svc = connect::Stack::new()
+                            .push(phantom_data::layer())
+                            .push(tls::client::layer(Conditional::Some(
+                                id_config.trust_anchors.clone(),
+                            )))
+                            .push(keepalive::connect::layer(keepalive))
+                            .push(svc::timeout::layer(config.control_connect_timeout))
+                            .push(control::client::layer())
+                            .push(control::resolve::layer(dns_resolver.clone()))
+                            .push(control::standby::layer(config.control_warm_standby))
+                            .push(control::response_timeout::layer(
+                                config.control_request_timeout,
+                            ))
+                            .push(
+                                reconnect::layer().with_fixed_backoff(config.control_backoff_delay),
+                            )
+                            .push(http_metrics::layer::<_, classify::Response>(
+                                ctl_http_metrics.clone(),
+                            ))
+                            .push(proxy::grpc::req_body_as_payload::layer())
+                            .push(phantom_data::layer())
+                            .push(control::add_origin::layer())
+                            .push(buffer::layer(config.destination_concurrency_limit))
+                            .push(limit::layer(config.destination_concurrency_limit))
+                            .make(&svc_config.svc)
+                            .unwrap_or_else(|e| panic!("failed to build dst_svc: {}", e));
+
+                        let daemon = identity::Daemon::new(
+                            id_config.local_name,
+                            svc_config,
+                            crt_store,
+                            local_identity.watch_trust_anchors(),
+                            svc,
+                            identity_metrics,
+                        );
+                        identity_refresh = Some(daemon.refresh());
+                        identity_daemon = Some(daemon);
+                    }
+                    identity::Source::Files { crt, key } => {
+                        task::spawn(identity::WatchFiles::new(
+                            id_config.local_name,
+                            crt,
+                            key,
+                            local_identity.watch_trust_anchors(),
+                            crt_store,
+                            identity_metrics,
+                        ));
+                    }
+                }
 
                 task::spawn(
                     local_identity
                         .clone()
                         .await_crt()
                         .map(move |id| {
-                            ready_latch.release();
                             info!("Certified identity: {}", id.name().as_ref());
                         })
                         .map_err(|_| {
@@ -315,13 +501,68 @@ where
                 Conditional::Some(local_identity)
             }
         };
+        let readiness = match local_identity {
+            Conditional::Some(ref local) => readiness.with_identity(local.clone()),
+            Conditional::None(_) => readiness,
+        };
+        let admin_identity = match local_identity {
+            Conditional::Some(ref local) => Some(local.clone()),
+            Conditional::None(_) => None,
+        };
+        // The proxies do not accept connections until a certificate has been
+        // obtained, so that connections are not served without TLS.
+        let outbound_identity = await_identity(local_identity.clone());
+        let inbound_identity = await_identity(local_identity.clone());
+
+        // Metrics are pushed to an OTLP collector over a control client, so
+        // that the collector authenticates the proxy by its identity.
+        let metrics_otlp_svc = config.metrics_otlp_addr.as_ref().map(|addr| {
+            use super::control;
+
+            let keepalive = if config.control_connect_keepalive.is_some() {
+                config.control_connect_keepalive
+            } else if addr.addr.is_loopback() {
+                config.inbound_connect_keepalive
+            } else {
+                config.outbound_connect_keepalive
+            };
+
+            connect::Stack::new()
+                .push(phantom_data::layer())
+                .push(tls::client::layer(local_identity.clone()))
+                .push(keepalive::connect::layer(keepalive))
+                .push(svc::timeout::layer(config.control_connect_timeout))
+                .push(control::client::layer())
+                .push(control::resolve::layer(dns_resolver.clone()))
+                .push(control::standby::layer(config.control_warm_standby))
+                .push(control::response_timeout::layer(
+                    config.control_request_timeout,
+                ))
+                .push(reconnect::layer().with_fixed_backoff(config.control_backoff_delay))
+                .push(http_metrics::layer::<_, classify::Response>(
+                    ctl_http_metrics.clone(),
+                ))
+                .push(proxy::grpc::req_body_as_payload::layer())
+                .push(phantom_data::layer())
+                .push(control::add_origin::layer())
+                // Exports are sent one at a time.
+                .push(buffer::layer(1))
+                .push(limit::layer(1))
+                .make(&addr)
+                .unwrap_or_else(|e| panic!("failed to build metrics_otlp_svc: {}", e))
+        });
+        let metrics_otlp_push_interval = config.metrics_otlp_push_interval;
+        let metrics_otlp_service_name = config.metrics_otlp_service_name.clone();
 
         let dst_svc = config.destination_addr.as_ref().map(|addr| {
             use super::control;
 
-            // If the dst_svc is on localhost, use the inbound keepalive.
-            // If the dst_svc is remote, use the outbound keepalive.
-            let keepalive = if addr.addr.is_loopback() {
+            // Unless a control keepalive is configured: if the dst_svc is on
+            // localhost, use the inbound keepalive. If the dst_svc is remote,
+            // use the outbound keepalive.
+            let keepalive = if config.control_connect_keepalive.is_some() {
+                config.control_connect_keepalive
+            } else if addr.addr.is_loopback() {
                 config.inbound_connect_keepalive
             } else {
                 config.outbound_connect_keepalive
@@ -334,6 +575,10 @@ where
                 .push(svc::timeout::layer(config.control_connect_timeout))
                 .push(control::client::layer())
                 .push(control::resolve::layer(dns_resolver.clone()))
+                .push(control::standby::layer(config.control_warm_standby))
+                .push(control::response_timeout::layer(
+                    config.control_request_timeout,
+                ))
                 .push(reconnect::layer().with_fixed_backoff(config.control_backoff_delay))
                 .push(http_metrics::layer::<_, classify::Response>(
                     ctl_http_metrics,
@@ -347,16 +592,58 @@ where
                 .unwrap_or_else(|e| panic!("failed to build dst_svc: {}", e))
         });
 
+        // Names that are resolved by the Destination service may skip DNS
+        // canonicalization so that discovery alone determines their routing.
+        let canonicalize_bypass_suffixes =
+            if config.dns_canonicalize_bypass_destination && dst_svc.is_some() {
+                config.destination_get_suffixes.clone()
+            } else {
+                Vec::new()
+            };
+
+        // Names outside of the mesh are those not resolved by the
+        // Destination service.
+        let egress_scrub_headers = if config.egress_scrub_headers.is_empty() {
+            None
+        } else {
+            Some(scrub_headers::Config::new(
+                config.egress_scrub_headers.clone(),
+                config.destination_get_suffixes.clone(),
+            ))
+        };
+
+        // Without an identity, no client can authenticate, so routing headers
+        // are only stripped from unauthenticated clients when TLS is enabled.
+        let inbound_untrusted_headers = if local_identity.is_some() {
+            Some(untrusted_headers::Config::new(
+                config.inbound_trusted_headers.clone(),
+            ))
+        } else {
+            None
+        };
+
         let (resolver, resolver_bg) = control::destination::new(
             dst_svc.clone(),
             dns_resolver.clone(),
             config.destination_get_suffixes,
-            config.destination_concurrency_limit,
+            config.destination_resolution_limit,
             config.destination_context.clone(),
+            config.destination_cache_path.clone(),
+            config.destination_dns_fallback_timeout,
+            config.destination_idle_timeout,
+            dst_metrics,
         );
 
+        // Background tasks that run for the life of the process are tracked,
+        // though not canceled.
+        let dns_bg = tasks.track("dns", dns_bg);
+        let resolver_bg = tasks.track("destination", resolver_bg);
+        let identity_daemon = identity_daemon.map(|d| tasks.track("identity", d));
+        let node_drain = node_drain.map(|w| tasks.track("node_drain", w));
+
         // Spawn a separate thread to handle the admin stuff.
         {
+            let drain_progress = drain_rx.progress();
             let (tx, admin_shutdown_signal) = futures::sync::oneshot::channel::<()>();
             thread::Builder::new()
                 .name("admin".into())
@@ -366,10 +653,40 @@ where
                     let mut rt =
                         current_thread::Runtime::new().expect("initialize admin thread runtime");
 
+                    // Metrics are served to Prometheus and pushed to StatsD
+                    // and OTLP collectors from the same registry.
+                    let report = Arc::new(report);
+                    if let Some(statsd) = metrics_statsd {
+                        match telemetry::statsd::Exporter::new(statsd, report.clone()) {
+                            Ok(exporter) => {
+                                rt.spawn(exporter);
+                            }
+                            Err(e) => error!("failed to push metrics to statsd: {}", e),
+                        }
+                    }
+                    if let Some(svc) = metrics_otlp_svc {
+                        rt.spawn(telemetry::otlp::Exporter::new(
+                            svc,
+                            report.clone(),
+                            metrics_otlp_push_interval,
+                            metrics_otlp_service_name,
+                        ));
+                    }
+
                     rt.spawn(control::serve_http(
                         "admin",
                         admin_listener,
-                        Admin::new(report, readiness),
+                        Admin::new(
+                            report,
+                            readiness,
+                            deprecated_env_vars,
+                            admin_captures,
+                            admin_identity,
+                            identity_refresh,
+                        )
+                        .with_drain_progress(drain_progress)
+                        .with_trace_sampling(admin_trace_sampling)
+                        .with_prometheus(metrics_prometheus_enabled),
                     ));
 
                     rt.spawn(tap_daemon.map_err(|_| ()));
@@ -379,6 +696,10 @@ where
 
                     rt.spawn(::logging::admin().bg("resolver").future(resolver_bg));
 
+                    if let Some(w) = node_drain {
+                        rt.spawn(::logging::admin().bg("node-drain").future(w));
+                    }
+
                     if let Some(d) = identity_daemon {
                         rt.spawn(
                             ::logging::admin()
@@ -408,8 +729,13 @@ where
 
         // Build the outbound and inbound proxies using the dst_svc client.
 
-        let profiles_client =
-            ProfilesClient::new(dst_svc, Duration::from_secs(3), config.destination_context);
+        let profiles_client = ProfilesClient::new(
+            dst_svc,
+            Duration::from_secs(3),
+            config.destination_context,
+            profile_metrics,
+            tasks.clone(),
+        );
 
         let outbound = {
             use super::outbound::{
@@ -423,6 +749,7 @@ where
                 http::{balance, header_from_target, metrics, retry},
                 resolve,
             };
+            use telemetry::alloc_audit;
 
             let profiles_client = profiles_client.clone();
             let capacity = config.outbound_router_capacity;
@@ -430,24 +757,33 @@ where
             let endpoint_http_metrics = endpoint_http_metrics.clone();
             let route_http_metrics = route_http_metrics.clone();
             let profile_suffixes = config.destination_profile_suffixes.clone();
+            let profile_wildcards = config.destination_profile_wildcards.clone();
             let canonicalize_timeout = config.dns_canonicalize_timeout;
+            let canonicalize_min_ttl = config.dns_canonicalize_min_ttl;
+            let canonicalize_max_ttl = config.dns_canonicalize_max_ttl;
 
             // Establishes connections to remote peers (for both TCP
             // forwarding and HTTP proxying).
-            let connect = connect::Stack::new()
+            let connect = connect::Stack::with_options(config.outbound_connect_options.clone())
                 .push(phantom_data::layer())
                 .push(tls::client::layer(local_identity.clone()))
                 .push(keepalive::connect::layer(config.outbound_connect_keepalive))
                 .push(svc::timeout::layer(config.outbound_connect_timeout))
                 .push(transport_metrics.connect("outbound"));
 
-            // Instantiates an HTTP client for for a `client::Config`
+            // Instantiates an HTTP client for for a `client::Config`. The
+            // client refuses to send requests whose headers exceed the
+            // outbound limit, responding with a `431` instead.
             let client_stack = connect
                 .clone()
                 .push(client::layer("out", config.h2_settings))
-                .push(reconnect::layer().with_fixed_backoff(config.outbound_connect_backoff))
+                .push(reconnect::layer().with_exponential_backoff(
+                    config.outbound_connect_backoff,
+                    config.outbound_connect_backoff_max,
+                ))
                 .push(svc::stack_per_request::layer())
-                .push(normalize_uri::layer());
+                .push(normalize_uri::layer())
+                .push(max_header_size::layer(config.outbound_max_header_size));
 
             // A per-`outbound::Endpoint` stack that:
             //
@@ -461,18 +797,30 @@ where
             //    request version and headers).
             // 6. Strips any `l5d-server-id` that may have been received from
             //    the server, before we apply our own.
+            // 7. Scrubs internal headers from traffic to destinations outside
+            //    of the mesh.
+            // 8. Closes HTTP/1 connections after each request while the node
+            //    is draining.
+            // 9. Makes the endpoint unavailable to the balancer for a backoff
+            //    after a connection to it cannot be established.
             let endpoint_stack = client_stack
                 .push(buffer::layer(MAX_IN_FLIGHT))
                 .push(strip_header::response::layer(super::L5D_SERVER_ID))
                 .push(strip_header::response::layer(super::L5D_REMOTE_IP))
+                .push(node_drain::layer(node_draining.clone()))
                 .push(settings::router::layer::<_, Endpoint>())
                 //.push(add_server_id_on_rsp::layer())
                 //.push(add_remote_ip_on_rsp::layer())
                 .push(orig_proto_upgrade::layer())
+                .push(alloc_audit::layer(alloc_audit::Subsystem::Client))
                 .push(tap_layer.clone())
                 .push(metrics::layer::<_, classify::Response>(
                     endpoint_http_metrics,
-                ));
+                ))
+                .push(alloc_audit::layer(alloc_audit::Subsystem::Telemetry))
+                .push(capture::endpoint_layer())
+                .push(scrub_headers::layer(egress_scrub_headers))
+                .push(connect_retry::eject_layer(config.outbound_connect_backoff));
 
             // A per-`dst::Route` layer that uses profile data to configure
             // a per-route layer.
@@ -485,15 +833,23 @@ where
             //    retries.
             // 3. Retries are optionally enabled depending on if the route
             //    is retryable.
+            // 4. Each request, rather than each retry, is recorded in the
+            //    access log, if one is configured.
+            // 5. Trace context is propagated, and a client span is recorded
+            //    for each request, if a trace collector is configured.
             let dst_route_layer = phantom_data::layer()
                 .push(insert_target::layer())
+                .push(max_body_size::route_layer())
+                .push(capture::layer(captures.clone()))
                 .push(metrics::layer::<_, classify::Response>(
                     retry_http_metrics.clone(),
                 ))
                 .push(retry::layer(retry_http_metrics))
                 .push(proxy::http::timeout::layer())
                 .push(metrics::layer::<_, classify::Response>(route_http_metrics))
-                .push(classify::layer());
+                .push(classify::layer())
+                .push(access_log::layer(access_log.clone()))
+                .push(trace::layer(tracer.clone()));
 
             // A per-`DstAddr` stack that does the following:
             //
@@ -501,16 +857,38 @@ where
             // 2. Determines the profile of the destination and applies
             //    per-route policy.
             // 3. Creates a load balancer , configured by resolving the
-            //   `DstAddr` with a resolver.
+            //   `DstAddr` with a resolver. Requests are balanced by load or,
+            //   when a hash key is configured, over a consistent-hash ring.
+            //   New endpoints may be ramped up over a slow-start window, and
+            //   unready endpoints are used when too few endpoints are ready.
+            // 4. Fails requests with a `503 Service Unavailable` when the
+            //    load balancer has been unavailable for too long, or when its
+            //    buffer is full.
+            // 5. Retries requests on another endpoint when a connection could
+            //    not be established.
             let dst_stack = endpoint_stack
-                .push(resolve::layer(Resolve::new(resolver)))
-                .push(balance::layer(EWMA_DEFAULT_RTT, EWMA_DECAY))
-                .push(buffer::layer(MAX_IN_FLIGHT))
-                .push(profiles::router::layer(
-                    profile_suffixes,
-                    profiles_client,
-                    dst_route_layer,
+                .push(resolve::layer(
+                    Resolve::new(resolver).with_networks(config.cluster_networks.clone()),
                 ))
+                .push(
+                    balance::layer(
+                        config.outbound_balance_ewma_default_rtt,
+                        config.outbound_balance_ewma_decay,
+                    )
+                    .with_ring_hash(config.outbound_balance_hash_key.clone())
+                    .with_slow_start(config.outbound_balance_slow_start)
+                    .with_panic_threshold(config.outbound_balance_panic_threshold),
+                )
+                .push(alloc_audit::layer(alloc_audit::Subsystem::Balance))
+                .push(failfast::layer(config.outbound_failfast_timeout))
+                .push(buffer::layer(MAX_IN_FLIGHT))
+                .push(connect_retry::layer(config.outbound_connect_attempts))
+                .push(load_shed::layer())
+                .push(
+                    profiles::router::layer(profile_suffixes, profiles_client, dst_route_layer)
+                        .with_wildcards(profile_wildcards)
+                        .with_redirect_class(config.redirect_class),
+                )
                 .push(header_from_target::layer(super::CANONICAL_DST_HEADER));
 
             // Routes request using the `DstAddr` extension.
@@ -529,20 +907,35 @@ where
                     debug!("outbound dst={:?}", addr);
                     addr
                 }))
-                .make(&router::Config::new("out dst", capacity, max_idle_age))
+                .make(
+                    &router::Config::new("out dst", capacity, max_idle_age)
+                        .with_overflow(config.outbound_router_overflow, router_metrics.clone()),
+                )
                 .map(shared::stack)
                 .expect("outbound dst router")
+                .push(alloc_audit::layer(alloc_audit::Subsystem::Router))
                 .push(phantom_data::layer());
 
             // Canonicalizes the request-specified `Addr` via DNS, and
             // annotates each request with a `DstAddr` so that it may be
-            // routed by the dst_router.
+            // routed by the dst_router. Requests to external domains are
+            // tagged for cost attribution and, once canonicalized, fail unless
+            // the egress policy allows them.
             let addr_stack = dst_router
                 .push(insert_target::layer())
                 .push(map_target::layer(|addr: &Addr| {
                     DstAddr::outbound(addr.clone())
                 }))
-                .push(canonicalize::layer(dns_resolver, canonicalize_timeout));
+                .push(egress_policy::layer(egress_policy.clone()))
+                .push(
+                    canonicalize::layer(dns_resolver, canonicalize_timeout, tasks.clone())
+                        .with_ttl_bounds(canonicalize_min_ttl, canonicalize_max_ttl)
+                        .without_canonicalization_for(canonicalize_bypass_suffixes),
+                )
+                .push(egress::layer(
+                    config.outbound_cost_attribution.clone(),
+                    egress_metrics,
+                ));
 
             // Routes requests to an `Addr`:
             //
@@ -576,7 +969,10 @@ where
                         })
                         .ok()
                 }))
-                .make(&router::Config::new("out addr", capacity, max_idle_age))
+                .make(
+                    &router::Config::new("out addr", capacity, max_idle_age)
+                        .with_overflow(config.outbound_router_overflow, router_metrics.clone()),
+                )
                 .map(shared::stack)
                 .expect("outbound addr router")
                 .push(phantom_data::layer());
@@ -584,9 +980,15 @@ where
             // Instantiates an HTTP service for each `Source` using the
             // shared `addr_router`. The `Source` is stored in the request's
             // extensions so that it can be used by the `addr_router`.
+            // Requests without an `l5d-request-id` are assigned one.
             let server_stack = addr_router
                 .push(insert_target::layer())
-                .push(super::errors::layer());
+                .push(request_id::layer())
+                .push(super::errors::layer())
+                .push(max_body_size::layer(config.outbound_max_request_body_size))
+                .push(stream_idle_timeout::layer(
+                    config.h2_settings.stream_idle_timeout,
+                ));
 
             // Instantiated for each TCP connection received from the local
             // application (including HTTP connections).
@@ -597,23 +999,30 @@ where
             serve(
                 "out",
                 outbound_listener,
+                None,
                 accept,
                 connect,
                 server_stack,
+                Some(egress_policy),
+                None,
+                outbound_connection_limit,
+                config.outbound_proxy_protocol_ports.clone(),
                 config.h2_settings,
+                config.outbound_max_header_size,
                 drain_rx.clone(),
             )
             .map_err(|e| error!("outbound proxy background task failed: {}", e))
         };
-        task::spawn(outbound);
+        task::spawn(outbound_identity.and_then(move |()| outbound));
 
         let inbound = {
             use super::inbound::{
                 orig_proto_downgrade,
                 rewrite_loopback_addr,
+                set_client_id_on_req,
                 Endpoint,
                 RecognizeEndpoint,
-                // set_client_id_on_req, set_remote_ip_on_req,
+                // set_remote_ip_on_req,
             };
 
             let capacity = config.inbound_router_capacity;
@@ -623,7 +1032,7 @@ where
 
             // Establishes connections to the local application (for both
             // TCP forwarding and HTTP proxying).
-            let connect = connect::Stack::new()
+            let connect = unix::ConnectStack::new(config.inbound_connect_unix_path.clone())
                 .push(phantom_data::layer())
                 .push(tls::client::layer(local_identity))
                 .push(keepalive::connect::layer(config.inbound_connect_keepalive))
@@ -652,9 +1061,16 @@ where
                 .push(http_metrics::layer::<_, classify::Response>(
                     endpoint_http_metrics,
                 ))
+                .push(capture::endpoint_layer())
                 .push(buffer::layer(MAX_IN_FLIGHT))
-                .push(router::layer(RecognizeEndpoint::new(default_fwd_addr)))
-                .make(&router::Config::new("in endpoint", capacity, max_idle_age))
+                .push(router::layer(
+                    RecognizeEndpoint::new(default_fwd_addr)
+                        .with_networks(config.cluster_networks.clone()),
+                ))
+                .make(
+                    &router::Config::new("in endpoint", capacity, max_idle_age)
+                        .with_overflow(config.inbound_router_overflow, router_metrics.clone()),
+                )
                 .map(shared::stack)
                 .expect("inbound endpoint router");
 
@@ -663,13 +1079,19 @@ where
             //
             // The `classify` module installs a `classify::Response`
             // extension into each request so that all lower metrics
-            // implementations can use the route-specific configuration.
+            // implementations can use the route-specific configuration. Each
+            // request is recorded in the access log, and traced as a server
+            // span, if they are configured.
             let dst_route_stack = phantom_data::layer()
                 .push(insert_target::layer())
+                .push(max_body_size::route_layer())
+                .push(capture::layer(captures.clone()))
                 .push(http_metrics::layer::<_, classify::Response>(
                     route_http_metrics,
                 ))
-                .push(classify::layer());
+                .push(classify::layer())
+                .push(access_log::layer(access_log))
+                .push(trace::layer(tracer));
 
             // A per-`DstAddr` stack that does the following:
             //
@@ -681,11 +1103,12 @@ where
                 .push(phantom_data::layer())
                 .push(insert_target::layer())
                 .push(buffer::layer(MAX_IN_FLIGHT))
-                .push(profiles::router::layer(
-                    profile_suffixes,
-                    profiles_client,
-                    dst_route_stack,
-                ));
+                .push(
+                    profiles::router::layer(profile_suffixes, profiles_client, dst_route_stack)
+                        .with_path_templates(config.inbound_path_templates)
+                        .with_wildcards(config.destination_profile_wildcards)
+                        .with_redirect_class(config.redirect_class),
+                );
 
             // Routes requests to a `DstAddr`.
             //
@@ -720,7 +1143,10 @@ where
                     debug!("inbound dst={:?}", dst);
                     dst.map(DstAddr::inbound)
                 }))
-                .make(&router::Config::new("in dst", capacity, max_idle_age))
+                .make(
+                    &router::Config::new("in dst", capacity, max_idle_age)
+                        .with_overflow(config.inbound_router_overflow, router_metrics),
+                )
                 .map(shared::stack)
                 .expect("inbound dst router");
 
@@ -730,49 +1156,101 @@ where
             // Furthermore, HTTP/2 requests may be downgraded to HTTP/1.1 per
             // `orig-proto` headers. This happens in the source stack so that
             // the router need not detect whether a request _will be_ downgraded.
+            // Likewise, gRPC-Web requests are translated to gRPC (and so to
+            // HTTP/2) before they are routed. Requests without an
+            // `l5d-request-id` are assigned one, and trusted clients'
+            // deadlines bound the time spent routing and dispatching requests.
+            // Clients' `l5d-client-id` headers are replaced with the verified
+            // identity of the connection's peer, if any, and requests that the
+            // authorization policy denies fail with a 403 response.
+            // Unauthenticated clients' `l5d-*` headers are stripped before
+            // any of these layers read them.
             let source_stack = dst_router
+                .push(grpc_web::layer(config.inbound_grpc_web))
                 .push(orig_proto_downgrade::layer())
                 .push(insert_target::layer())
                 //.push(set_remote_ip_on_req::layer())
-                //.push(set_client_id_on_req::layer())
+                .push(set_client_id_on_req::layer())
                 .push(strip_header::request::layer(super::L5D_REMOTE_IP))
                 .push(strip_header::request::layer(super::L5D_CLIENT_ID))
                 .push(strip_header::response::layer(super::L5D_SERVER_ID))
                 .push(strip_header::request::layer(super::DST_OVERRIDE_HEADER))
-                .push(super::errors::layer());
+                .push(authz::layer(inbound_authz.clone()))
+                .push(request_id::layer())
+                .push(forwarded::layer(config.inbound_forwarded_headers))
+                .push(deadline::layer(config.inbound_deadline_headers))
+                .push(untrusted_headers::layer(inbound_untrusted_headers))
+                .push(compress::layer(
+                    config.inbound_compression.clone(),
+                    compress_metrics,
+                ))
+                .push(super::errors::layer())
+                .push(max_body_size::layer(config.inbound_max_request_body_size))
+                .push(max_header_size::layer(config.inbound_max_header_size))
+                .push(stream_idle_timeout::layer(
+                    config.h2_settings.stream_idle_timeout,
+                ));
 
-            // As the inbound proxy accepts connections, we don't do any
-            // special transport-level handling.
+            // As the inbound proxy accepts connections, it refuses those that
+            // the port's TLS policy does not permit.
             let accept = keepalive::accept::layer(config.inbound_accept_keepalive)
                 .push(transport_metrics.accept("inbound"))
+                .push(tls::policy::layer(config.inbound_tls_policy.clone()))
                 .bind(());
 
             serve(
                 "in",
                 inbound_listener,
+                inbound_unix_listener,
                 accept,
                 connect,
                 source_stack,
+                Some(inbound_authz),
+                Some(inbound_tls_passthrough),
+                inbound_connection_limit,
+                IndexSet::new(),
                 config.h2_settings,
+                config.inbound_max_header_size,
                 drain_rx.clone(),
             )
             .map_err(|e| error!("inbound proxy background task failed: {}", e))
         };
-        task::spawn(inbound);
+        task::spawn(inbound_identity.and_then(move |()| inbound));
+    }
+}
+
+/// Completes once the local identity, if any, has obtained a certificate.
+fn await_identity(
+    local_identity: tls::Conditional<identity::Local>,
+) -> impl Future<Item = (), Error = ()> + Send + 'static {
+    match local_identity {
+        Conditional::None(_) => future::Either::A(future::ok(())),
+        Conditional::Some(mut local) => future::Either::B(future::poll_fn(move || {
+            local
+                .poll_ready()
+                .map_err(|_| error!("identity daemon lost before certifying"))
+        })),
     }
 }
 
-fn serve<A, T, C, R, B, G>(
+fn serve<A, T, C, R, B, G, Z>(
     proxy_name: &'static str,
     bound_port: Listen<identity::Local, G>,
+    unix_listener: Option<unix::Listen>,
     accept: A,
     connect: C,
     router: R,
+    authorize: Option<Z>,
+    tls_passthrough: Option<tls_passthrough::Passthrough>,
+    connection_limit: connection_limit::Limit,
+    proxy_protocol_ports: IndexSet<u16>,
     h2_settings: H2Settings,
+    max_header_size: Option<usize>,
     drain_rx: drain::Watch,
 ) -> impl Future<Item = (), Error = io::Error> + Send + 'static
 where
-    A: svc::Stack<proxy::server::Source, Error = Never> + Send + Clone + 'static,
+    A: svc::Stack<proxy::server::Source> + Send + Clone + 'static,
+    A::Error: fmt::Display,
     A::Value: proxy::Accept<Connection>,
     <A::Value as proxy::Accept<Connection>>::Io: fmt::Debug + Send + transport::Peek + 'static,
     T: From<SocketAddr> + Send + 'static,
@@ -788,7 +1266,8 @@ where
         Into<Box<dyn error::Error + Send + Sync>> + Send,
     <R::Value as svc::Service<http::Request<proxy::http::Body>>>::Future: Send + 'static,
     B: hyper::body::Payload + Default + Send + 'static,
-    G: GetOriginalDst + Send + 'static,
+    G: GetOriginalDst + Clone + Send + 'static,
+    Z: proxy::server::AuthorizeForward + Send + Sync + 'static,
 {
     let listen_addr = bound_port.local_addr();
     let server = proxy::Server::new(
@@ -798,11 +1277,15 @@ where
         connect,
         router,
         drain_rx.clone(),
-    );
+    )
+    .with_max_header_size(max_header_size)
+    .with_authorization(authorize)
+    .with_tls_passthrough(tls_passthrough)
+    .with_connection_limit(connection_limit)
+    .with_proxy_protocol_for(proxy_protocol_ports);
     let log = server.log().clone();
 
-    let future = log.future(bound_port.listen_and_fold(
-        (),
+    let serve_connection = move |server: proxy::Server<_, _, _, _, _>| {
         move |(), (connection, remote_addr)| {
             let s = server.serve(connection, remote_addr, h2_settings);
             // Logging context is configured by the server.
@@ -810,8 +1293,31 @@ where
                 .spawn(Box::new(s))
                 .map_err(task::Error::into_io);
             future::result(r)
-        },
-    ));
+        }
+    };
+
+    let acceptors = bound_port.into_acceptors().expect("acceptor listener bind");
+    // Each acceptor runs on its own task, so that connections may be accepted
+    // on several threads of a thread pool runtime. The tasks are canceled when
+    // their handles are dropped.
+    let future = future::lazy(move || {
+        let mut accepts = acceptors
+            .into_iter()
+            .map(|acceptor| {
+                let accept = acceptor.listen_and_fold((), serve_connection(server.clone()));
+                oneshot::spawn(log.clone().future(accept), &DefaultExecutor::current())
+            })
+            .collect::<Vec<_>>();
+        if let Some(listener) = unix_listener {
+            let accept = listener.listen_and_fold((), serve_connection(server.clone()));
+            accepts.push(oneshot::spawn(
+                log.clone().future(accept),
+                &DefaultExecutor::current(),
+            ));
+        }
+        future::join_all(accepts)
+    })
+    .map(|_| ());
 
     let accept_until = Cancelable {
         future,
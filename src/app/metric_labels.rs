@@ -1,9 +1,9 @@
-use std::fmt::{self, Write};
+use std::fmt;
 
 use metrics::FmtLabels;
 
 use identity;
-use transport::tls;
+use transport::{mesh, tls};
 use {Addr, Conditional, NameAddr};
 
 use super::{classify, control, dst, inbound, outbound};
@@ -20,6 +20,10 @@ pub struct EndpointLabels {
     tls_id: Conditional<TlsId, tls::ReasonForNoIdentity>,
     dst_name: Option<NameAddr>,
     labels: Option<String>,
+    mesh: Option<mesh::Status>,
+    /// Set when the endpoint was discovered via DNS because the Destination
+    /// service was unavailable.
+    dns_fallback: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -78,11 +82,12 @@ impl FmtLabels for RouteLabels {
     fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.dst.fmt_labels(f)?;
 
-        if let Some(labels) = self.labels.as_ref() {
-            write!(f, ",{}", labels)?;
+        match self.labels.as_ref() {
+            Some(labels) => write!(f, ",{}", labels),
+            // Requests that don't match a labeled route are reported as the
+            // default route.
+            None => f.write_str(",rt_route=\"default\""),
         }
-
-        Ok(())
     }
 }
 
@@ -95,23 +100,52 @@ impl From<inbound::Endpoint> for EndpointLabels {
             direction: Direction::In,
             tls_id: ep.tls_client_id.map(TlsId::ClientId),
             labels: None,
+            mesh: ep.client_mesh,
+            dns_fallback: false,
         }
     }
 }
 
+/// Formats arbitrary labels, e.g. from the Destination service, as Prometheus
+/// labels.
+///
+/// Characters that may not appear in a label name (e.g. the `.` and `/` in
+/// `topology.kubernetes.io/zone`) are replaced with `_`, and values are
+/// escaped.
 fn prefix_labels<'i, I>(prefix: &str, mut labels_iter: I) -> Option<String>
 where
     I: Iterator<Item = (&'i String, &'i String)>,
 {
     let (k0, v0) = labels_iter.next()?;
-    let mut out = format!("{}_{}=\"{}\"", prefix, k0, v0);
+    let mut out = String::new();
+    write_label(&mut out, prefix, k0, v0);
 
     for (k, v) in labels_iter {
-        write!(out, ",{}_{}=\"{}\"", prefix, k, v).expect("label concat must succeed");
+        out.push(',');
+        write_label(&mut out, prefix, k, v);
     }
     Some(out)
 }
 
+fn write_label(out: &mut String, prefix: &str, key: &str, value: &str) {
+    out.push_str(prefix);
+    out.push('_');
+    out.extend(key.chars().map(|c| match c {
+        'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => c,
+        _ => '_',
+    }));
+    out.push_str("=\"");
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
 impl From<outbound::Endpoint> for EndpointLabels {
     fn from(ep: outbound::Endpoint) -> Self {
         Self {
@@ -119,6 +153,8 @@ impl From<outbound::Endpoint> for EndpointLabels {
             direction: Direction::Out,
             tls_id: ep.identity.as_ref().map(|id| TlsId::ServerId(id.clone())),
             labels: prefix_labels("dst", ep.metadata.labels().into_iter()),
+            mesh: ep.server_mesh,
+            dns_fallback: ep.metadata.is_dns_fallback(),
         }
     }
 }
@@ -132,6 +168,10 @@ impl FmtLabels for EndpointLabels {
             write!(f, ",{}", labels)?;
         }
 
+        if self.dns_fallback {
+            write!(f, ",discovery=\"dns_fallback\"")?;
+        }
+
         write!(f, ",")?;
         self.tls_id.as_ref().map(|_| ()).fmt_labels(f)?;
 
@@ -140,6 +180,11 @@ impl FmtLabels for EndpointLabels {
             id.fmt_labels(f)?;
         }
 
+        if let Some(ref mesh) = self.mesh {
+            write!(f, ",")?;
+            mesh.fmt_labels(f)?;
+        }
+
         Ok(())
     }
 }
@@ -193,10 +238,11 @@ impl FmtLabels for classify::Class {
 
 impl fmt::Display for classify::SuccessOrFailure {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use self::classify::SuccessOrFailure::{Failure, Success};
+        use self::classify::SuccessOrFailure::{Failure, Neutral, Success};
         match self {
             Success => write!(f, "success"),
             Failure => write!(f, "failure"),
+            Neutral => write!(f, "neutral"),
         }
     }
 }
@@ -220,3 +266,29 @@ impl FmtLabels for TlsId {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_labels_are_valid() {
+        let labels = vec![
+            ("pod".to_owned(), "web-0".to_owned()),
+            (
+                "topology.kubernetes.io/zone".to_owned(),
+                "us-east-1a".to_owned(),
+            ),
+            ("note".to_owned(), "a \"quoted\"\\path\n".to_owned()),
+        ];
+        let out = prefix_labels("dst", labels.iter().map(|&(ref k, ref v)| (k, v)));
+        assert_eq!(
+            out.as_ref().map(String::as_str),
+            Some(
+                "dst_pod=\"web-0\",\
+                 dst_topology_kubernetes_io_zone=\"us-east-1a\",\
+                 dst_note=\"a \\\"quoted\\\"\\\\path\\n\""
+            ),
+        );
+    }
+}
@@ -2,7 +2,9 @@
 
 use http;
 
+mod access_log;
 mod admin;
+mod capture;
 mod classify;
 pub mod config;
 mod control;
@@ -12,8 +14,10 @@ mod identity;
 mod inbound;
 mod main;
 mod metric_labels;
+mod node_drain;
 mod outbound;
 mod profiles;
+mod trace;
 
 pub use self::main::Main;
 use addr::{self, Addr};
@@ -32,6 +36,7 @@ pub fn init() -> Result<config::Config, config::Error> {
 }
 
 const DEFAULT_PORT: u16 = 80;
+const DEFAULT_TLS_PORT: u16 = 443;
 
 fn http_request_l5d_override_dst_addr<B>(req: &http::Request<B>) -> Result<Addr, addr::Error> {
     use proxy;
@@ -42,10 +47,16 @@ fn http_request_l5d_override_dst_addr<B>(req: &http::Request<B>) -> Result<Addr,
 }
 
 fn http_request_authority_addr<B>(req: &http::Request<B>) -> Result<Addr, addr::Error> {
+    // Absolute-form targets may name a scheme other than HTTP, which
+    // determines the port when the authority omits it.
+    let default_port = match req.uri().scheme_part() {
+        Some(s) if s == &http::uri::Scheme::HTTPS => DEFAULT_TLS_PORT,
+        _ => DEFAULT_PORT,
+    };
     req.uri()
         .authority_part()
         .ok_or(addr::Error::InvalidHost)
-        .and_then(|a| Addr::from_authority_and_default_port(a, DEFAULT_PORT))
+        .and_then(|a| Addr::from_authority_and_default_port(a, default_port))
 }
 
 fn http_request_host_addr<B>(req: &http::Request<B>) -> Result<Addr, addr::Error> {
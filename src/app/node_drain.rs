@@ -0,0 +1,202 @@
+//! Coordinates node drains with mesh traffic.
+//!
+//! A node-drain controller may mark the pods on a draining node, e.g. with an
+//! annotation that the Kubernetes downward API projects into a file. The proxy
+//! polls that file, and while it reads `true` the proxy reports that it is not
+//! ready and stops reusing outbound HTTP/1 connections, so that traffic moves
+//! off of the node before its pods are evicted.
+
+use futures::{Async, Future, Poll};
+use http::{self, header};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_timer::{clock, Interval};
+
+use svc;
+
+/// Indicates whether the proxy's node is draining.
+///
+/// The default value never drains.
+#[derive(Clone, Debug, Default)]
+pub struct Draining(Arc<AtomicBool>);
+
+/// Polls a file to update a `Draining`.
+pub struct Watch {
+    path: PathBuf,
+    interval: Interval,
+    draining: Draining,
+}
+
+pub fn watch(path: PathBuf, interval: Duration) -> (Draining, Watch) {
+    let draining = Draining::default();
+    let watch = Watch {
+        path,
+        interval: Interval::new(clock::now(), interval),
+        draining: draining.clone(),
+    };
+    (draining, watch)
+}
+
+/// Closes outbound HTTP/1 connections after each request while draining.
+pub fn layer(draining: Draining) -> Layer {
+    Layer { draining }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    draining: Draining,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    draining: Draining,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    draining: Draining,
+}
+
+// === impl Draining ===
+
+impl Draining {
+    pub fn is_draining(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+// === impl Watch ===
+
+impl Watch {
+    fn read(&self) -> bool {
+        match fs::read_to_string(&self.path) {
+            Ok(s) => s.trim().eq_ignore_ascii_case("true"),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => {
+                debug!("failed to read {}: {}", self.path.display(), e);
+                false
+            }
+        }
+    }
+}
+
+impl Future for Watch {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.interval.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(_)) => {}
+                Err(e) => {
+                    error!("node drain timer failed: {}", e);
+                    return Err(());
+                }
+            }
+
+            let draining = self.read();
+            if self.draining.0.swap(draining, Ordering::AcqRel) != draining {
+                if draining {
+                    info!("node is draining");
+                } else {
+                    info!("node is no longer draining");
+                }
+            }
+        }
+    }
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            draining: self.draining.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            draining: self.draining.clone(),
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        // HTTP/2 connections are multiplexed, so they are left to be closed
+        // by their peers.
+        let is_http1 =
+            req.version() == http::Version::HTTP_11 || req.version() == http::Version::HTTP_10;
+        if is_http1 && self.draining.is_draining() {
+            trace!("node is draining; closing connection after request");
+            req.headers_mut().insert(
+                header::CONNECTION,
+                header::HeaderValue::from_static("close"),
+            );
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    #[test]
+    fn reads_drain_file() {
+        let path = env::temp_dir().join(format!("linkerd2-proxy-node-drain-{}", process::id()));
+        let (_, watch) = watch(path.clone(), Duration::from_secs(1));
+        assert!(!watch.read());
+
+        fs::write(&path, "true\n").unwrap();
+        assert!(watch.read());
+
+        fs::write(&path, "false").unwrap();
+        assert!(!watch.read());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
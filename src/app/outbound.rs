@@ -5,8 +5,9 @@ use std::{fmt, hash};
 
 use super::identity;
 use control::destination::{Metadata, ProtocolHint};
+use proxy::http::scrub_headers;
 use tap;
-use transport::{connect, tls};
+use transport::{connect, mesh, tls};
 use {Conditional, NameAddr};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -15,6 +16,8 @@ pub struct Endpoint {
     pub addr: SocketAddr,
     pub identity: tls::PeerIdentity,
     pub metadata: Metadata,
+    /// The mesh status of the server, if it is known.
+    pub server_mesh: Option<mesh::Status>,
 }
 
 // === impl Endpoint ===
@@ -35,6 +38,7 @@ impl From<SocketAddr> for Endpoint {
             dst_name: None,
             identity: Conditional::None(tls::ReasonForNoPeerName::NotHttp.into()),
             metadata: Metadata::empty(),
+            server_mesh: None,
         }
     }
 }
@@ -54,10 +58,20 @@ impl hash::Hash for Endpoint {
     }
 }
 
+impl scrub_headers::HasDstName for Endpoint {
+    fn dst_name(&self) -> Option<&NameAddr> {
+        self.dst_name.as_ref()
+    }
+}
+
 impl tls::HasPeerIdentity for Endpoint {
     fn peer_identity(&self) -> tls::PeerIdentity {
         self.identity.clone()
     }
+
+    fn peer_server_name(&self) -> Option<identity::Name> {
+        self.metadata.server_name().cloned()
+    }
 }
 
 impl connect::HasPeerAddr for Endpoint {
@@ -114,14 +128,23 @@ pub mod discovery {
     use super::Endpoint;
     use control::destination::Metadata;
     use proxy::resolve;
-    use transport::tls;
+    use transport::{mesh, tls};
     use {Addr, Conditional, NameAddr};
 
     #[derive(Clone, Debug)]
-    pub struct Resolve<R: resolve::Resolve<NameAddr>>(R);
+    pub struct Resolve<R: resolve::Resolve<NameAddr>> {
+        resolve: R,
+        networks: mesh::Networks,
+    }
 
     #[derive(Debug)]
-    pub enum Resolution<R: resolve::Resolution> {
+    pub struct Resolution<R: resolve::Resolution> {
+        kind: Kind<R>,
+        networks: mesh::Networks,
+    }
+
+    #[derive(Debug)]
+    enum Kind<R: resolve::Resolution> {
         Name(NameAddr, R),
         Addr(Option<SocketAddr>),
     }
@@ -133,7 +156,16 @@ pub mod discovery {
         R: resolve::Resolve<NameAddr, Endpoint = Metadata>,
     {
         pub fn new(resolve: R) -> Self {
-            Resolve(resolve)
+            Self {
+                resolve,
+                networks: mesh::Networks::default(),
+            }
+        }
+
+        /// Classifies endpoints without an identity as unmeshed, rather than
+        /// external, when they are in `networks`.
+        pub fn with_networks(self, networks: mesh::Networks) -> Self {
+            Self { networks, ..self }
         }
     }
 
@@ -145,9 +177,13 @@ pub mod discovery {
         type Resolution = Resolution<R::Resolution>;
 
         fn resolve(&self, dst: &DstAddr) -> Self::Resolution {
-            match dst.as_ref() {
-                Addr::Name(ref name) => Resolution::Name(name.clone(), self.0.resolve(&name)),
-                Addr::Socket(ref addr) => Resolution::Addr(Some(*addr)),
+            let kind = match dst.as_ref() {
+                Addr::Name(ref name) => Kind::Name(name.clone(), self.resolve.resolve(&name)),
+                Addr::Socket(ref addr) => Kind::Addr(Some(*addr)),
+            };
+            Resolution {
+                kind,
+                networks: self.networks.clone(),
             }
         }
     }
@@ -162,8 +198,8 @@ pub mod discovery {
         type Error = R::Error;
 
         fn poll(&mut self) -> Poll<resolve::Update<Self::Endpoint>, Self::Error> {
-            match self {
-                Resolution::Name(ref name, ref mut res) => match try_ready!(res.poll()) {
+            match self.kind {
+                Kind::Name(ref name, ref mut res) => match try_ready!(res.poll()) {
                     resolve::Update::Remove(addr) => {
                         debug!("removing {}", addr);
                         Ok(Async::Ready(resolve::Update::Remove(addr)))
@@ -179,16 +215,18 @@ pub mod discovery {
                                 )
                             });
                         debug!("adding addr={}; identity={:?}", addr, identity);
+                        let server_mesh = self.networks.status(addr.ip(), identity.is_some());
                         let ep = Endpoint {
                             dst_name: Some(name.clone()),
                             addr,
                             identity,
                             metadata,
+                            server_mesh: Some(server_mesh),
                         };
                         Ok(Async::Ready(resolve::Update::Add(addr, ep)))
                     }
                 },
-                Resolution::Addr(ref mut addr) => match addr.take() {
+                Kind::Addr(ref mut addr) => match addr.take() {
                     Some(addr) => {
                         let ep = Endpoint {
                             dst_name: None,
@@ -197,6 +235,7 @@ pub mod discovery {
                                 tls::ReasonForNoPeerName::NoAuthorityInHttpRequest.into(),
                             ),
                             metadata: Metadata::empty(),
+                            server_mesh: Some(self.networks.status(addr.ip(), false)),
                         };
                         Ok(Async::Ready(resolve::Update::Add(addr, ep)))
                     }
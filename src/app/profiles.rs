@@ -1,30 +1,77 @@
-use futures::sync::{mpsc, oneshot};
+use futures::sync::mpsc;
 use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
 use http;
 use regex::Regex;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::executor::{DefaultExecutor, Executor};
 use tokio_timer::{clock, Delay};
 use tower_grpc::{self as grpc, generic::client::GrpcService, Body, BoxBody};
 use tower_retry::budget::Budget;
 
 use api::destination as api;
+use backoff::Backoff;
+use metrics::{Counter, FmtMetric, FmtMetrics, Gauge};
 use never::Never;
 
 use proxy::http::profiles;
-use NameAddr;
+use task;
+use telemetry::tasks;
+
+metrics! {
+    profile_stream_resets_total: Counter {
+        "Total count of profile streams that ended or failed and were reconnected"
+    },
+    profile_stale_destinations: Gauge {
+        "Number of destinations whose last-known routes are served while their profile stream reconnects"
+    }
+}
+
+/// The maximum number of a profile's routes whose labels are reported in
+/// route metrics.
+///
+/// Each labeled route adds a scope to the route metrics of every destination
+/// with the profile, so the metrics of further routes are reported as those
+/// of the default route.
+const MAX_LABELED_ROUTES: usize = 100;
+
+/// The longest time to wait before reconnecting a profile stream that
+/// repeatedly fails.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A route metrics label that, rather than labeling the route's metrics,
+/// limits the size of the route's request bodies, in bytes.
+const MAX_REQUEST_BODY_SIZE_LABEL: &str = "max_request_body_size";
 
 #[derive(Clone, Debug)]
 pub struct Client<T> {
     service: Option<T>,
     backoff: Duration,
     context_token: String,
+    metrics: Metrics,
+    tasks: tasks::Registry,
+}
+
+/// Records the health of profile streams.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics(Arc<Counts>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted profile stream
+/// metrics.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Counts>);
+
+#[derive(Debug, Default)]
+struct Counts {
+    resets: AtomicUsize,
+    stale: AtomicUsize,
 }
 
 pub struct Rx {
-    rx: mpsc::Receiver<profiles::Routes>,
-    _hangup: oneshot::Sender<Never>,
+    rx: mpsc::Receiver<profiles::Profile>,
+    /// Stops the daemon when the stream is dropped.
+    _daemon: task::Handle,
 }
 
 struct Daemon<T>
@@ -35,9 +82,22 @@ where
     backoff: Duration,
     service: Option<T>,
     state: State<T>,
-    tx: mpsc::Sender<profiles::Routes>,
+    tx: mpsc::Sender<profiles::Profile>,
     context_token: String,
-    hangup: oneshot::Receiver<Never>,
+    recovery: Recovery,
+}
+
+/// Tracks a daemon's reconnects, so that they are backed off while the
+/// stream keeps failing, and so that a destination's routes are reported as
+/// stale until its stream recovers.
+///
+/// The router continues to serve the last routes it received while the
+/// stream reconnects.
+struct Recovery {
+    backoff: Backoff,
+    metrics: Metrics,
+    received: bool,
+    stale: bool,
 }
 
 enum State<T>
@@ -62,11 +122,19 @@ where
     <T::ResponseBody as Body>::Item: Send,
     T::Future: Send,
 {
-    pub fn new(service: Option<T>, backoff: Duration, context_token: String) -> Self {
+    pub fn new(
+        service: Option<T>,
+        backoff: Duration,
+        context_token: String,
+        metrics: Metrics,
+        tasks: tasks::Registry,
+    ) -> Self {
         Self {
             service,
             backoff,
             context_token,
+            metrics,
+            tasks,
         }
     }
 }
@@ -80,26 +148,27 @@ where
 {
     type Stream = Rx;
 
-    fn get_routes(&self, dst: &NameAddr) -> Option<Self::Stream> {
+    fn get_routes(&self, dst: &profiles::ProfileName) -> Option<Self::Stream> {
         let (tx, rx) = mpsc::channel(1);
-        // This oneshot allows the daemon to be notified when the Self::Stream
-        // is dropped.
-        let (hangup_tx, hangup_rx) = oneshot::channel();
 
         let daemon = Daemon {
             tx,
-            hangup: hangup_rx,
             dst: format!("{}", dst),
             state: State::Disconnected,
             service: self.service.clone(),
-            backoff: self.backoff,
             context_token: self.context_token.clone(),
+            recovery: Recovery {
+                backoff: Backoff::new(self.backoff, MAX_BACKOFF.max(self.backoff)),
+                metrics: self.metrics.clone(),
+                received: false,
+                stale: false,
+            },
         };
-        let spawn = DefaultExecutor::current().spawn(Box::new(daemon.map_err(|_| ())));
+        let spawn = self.tasks.spawn("profile", daemon.map_err(|_| ()));
 
-        spawn.ok().map(|_| Rx {
+        spawn.ok().map(|daemon| Rx {
             rx,
-            _hangup: hangup_tx,
+            _daemon: daemon,
         })
     }
 }
@@ -107,7 +176,7 @@ where
 // === impl Rx ===
 
 impl Stream for Rx {
-    type Item = profiles::Routes;
+    type Item = profiles::Profile;
     type Error = Never;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
@@ -128,8 +197,8 @@ where
 {
     fn proxy_stream(
         rx: &mut grpc::Streaming<api::DestinationProfile, T::ResponseBody>,
-        tx: &mut mpsc::Sender<profiles::Routes>,
-        hangup: &mut oneshot::Receiver<Never>,
+        tx: &mut mpsc::Sender<profiles::Profile>,
+        recovery: &mut Recovery,
     ) -> Async<StreamState> {
         loop {
             match tx.poll_ready() {
@@ -139,29 +208,35 @@ where
             }
 
             match rx.poll() {
-                Ok(Async::NotReady) => match hangup.poll() {
-                    Ok(Async::Ready(never)) => match never {}, // unreachable!
-                    Ok(Async::NotReady) => {
-                        // We are now scheduled to be notified if the hangup tx
-                        // is dropped.
-                        return Async::NotReady;
-                    }
-                    Err(_) => {
-                        // Hangup tx has been dropped.
-                        debug!("profile stream cancelled");
-                        return StreamState::SendLost.into();
-                    }
-                },
+                Ok(Async::NotReady) => return Async::NotReady,
                 Ok(Async::Ready(None)) => return StreamState::RecvDone.into(),
                 Ok(Async::Ready(Some(profile))) => {
                     debug!("profile received: {:?}", profile);
                     let retry_budget = profile.retry_budget.and_then(convert_retry_budget);
-                    let routes = profile
+                    let mut routes = profile
                         .routes
                         .into_iter()
-                        .filter_map(move |orig| convert_route(orig, retry_budget.as_ref()));
-                    match tx.start_send(routes.collect()) {
-                        Ok(AsyncSink::Ready) => {} // continue
+                        .filter_map(move |orig| convert_route(orig, retry_budget.as_ref()))
+                        .collect::<Vec<_>>();
+                    if routes.len() > MAX_LABELED_ROUTES {
+                        warn!(
+                            "profile has {} routes; only the first {} are labeled in metrics",
+                            routes.len(),
+                            MAX_LABELED_ROUTES,
+                        );
+                        for &mut (_, ref mut route) in routes.iter_mut().skip(MAX_LABELED_ROUTES) {
+                            route.clear_labels();
+                        }
+                    }
+                    // This revision of the Destination API does not describe
+                    // traffic splits, so profiles never override the
+                    // destination.
+                    let profile = profiles::Profile {
+                        routes,
+                        dst_overrides: Vec::new(),
+                    };
+                    match tx.start_send(profile) {
+                        Ok(AsyncSink::Ready) => recovery.received(),
                         Ok(AsyncSink::NotReady(_)) => {
                             info!("dropping profile update due to a full buffer");
                             // This must have been because another task stole
@@ -195,7 +270,7 @@ where
                 State::Disconnected => {
                     let svc = match self.service {
                         Some(ref mut svc) => match svc.poll_ready() {
-                            Ok(Async::Ready(())) => svc.as_service(),
+                            Ok(Async::Ready(())) => Some(svc.as_service()),
                             Ok(Async::NotReady) => return Ok(Async::NotReady),
                             Err(err) => {
                                 error!(
@@ -203,21 +278,26 @@ where
                                     self.dst,
                                     err.into(),
                                 );
-                                return Ok(Async::Ready(()));
+                                None
                             }
                         },
                         None => return Ok(Async::Ready(())),
                     };
 
-                    let req = api::GetDestination {
-                        scheme: "k8s".to_owned(),
-                        path: self.dst.clone(),
-                        context_token: self.context_token.clone(),
-                    };
-                    debug!("getting profile: {:?}", req);
-                    let mut client = api::client::Destination::new(svc);
-                    let rspf = client.get_profile(grpc::Request::new(req));
-                    State::Waiting(rspf)
+                    match svc {
+                        Some(svc) => {
+                            let req = api::GetDestination {
+                                scheme: "k8s".to_owned(),
+                                path: self.dst.clone(),
+                                context_token: self.context_token.clone(),
+                            };
+                            debug!("getting profile: {:?}", req);
+                            let mut client = api::client::Destination::new(svc);
+                            let rspf = client.get_profile(grpc::Request::new(req));
+                            State::Waiting(rspf)
+                        }
+                        None => State::Backoff(self.recovery.reset()),
+                    }
                 }
                 State::Waiting(ref mut f) => match f.poll() {
                     Ok(Async::NotReady) => return Ok(Async::NotReady),
@@ -227,15 +307,16 @@ where
                     }
                     Err(e) => {
                         warn!("error fetching profile for {}: {:?}", self.dst, e);
-                        State::Backoff(Delay::new(clock::now() + self.backoff))
+                        State::Backoff(self.recovery.reset())
                     }
                 },
                 State::Streaming(ref mut s) => {
-                    match Self::proxy_stream(s, &mut self.tx, &mut self.hangup) {
+                    match Self::proxy_stream(s, &mut self.tx, &mut self.recovery) {
                         Async::NotReady => return Ok(Async::NotReady),
                         Async::Ready(StreamState::SendLost) => return Ok(().into()),
                         Async::Ready(StreamState::RecvDone) => {
-                            State::Backoff(Delay::new(clock::now() + self.backoff))
+                            debug!("profile stream for {} ended; reconnecting", self.dst);
+                            State::Backoff(self.recovery.reset())
                         }
                     }
                 }
@@ -248,6 +329,64 @@ where
     }
 }
 
+// === impl Recovery ===
+
+impl Recovery {
+    /// Records that a profile was received, so that the stream is no longer
+    /// stale.
+    fn received(&mut self) {
+        self.backoff.reset();
+        self.received = true;
+        if self.stale {
+            self.stale = false;
+            self.metrics.0.stale.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    /// Records that the stream failed, returning the delay after which it
+    /// should be reconnected.
+    fn reset(&mut self) -> Delay {
+        self.metrics.0.resets.fetch_add(1, Ordering::AcqRel);
+        if self.received && !self.stale {
+            self.stale = true;
+            self.metrics.0.stale.fetch_add(1, Ordering::AcqRel);
+        }
+        Delay::new(clock::now() + self.backoff.next())
+    }
+}
+
+impl Drop for Recovery {
+    fn drop(&mut self) {
+        if self.stale {
+            self.metrics.0.stale.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+// === impl Metrics ===
+
+pub fn metrics() -> (Metrics, Report) {
+    let metrics = Metrics::default();
+    let report = Report(metrics.0.clone());
+    (metrics, report)
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let load = |n: &AtomicUsize| n.load(Ordering::Acquire) as u64;
+
+        profile_stream_resets_total.fmt_help(f)?;
+        profile_stream_resets_total.fmt_metric(f, Counter::from(load(&self.0.resets)))?;
+
+        profile_stale_destinations.fmt_help(f)?;
+        profile_stale_destinations.fmt_metric(f, Gauge::from(load(&self.0.stale)))?;
+
+        Ok(())
+    }
+}
+
 fn convert_route(
     orig: api::Route,
     retry_budget: Option<&Arc<Budget>>,
@@ -258,13 +397,18 @@ fn convert_route(
         .into_iter()
         .filter_map(convert_rsp_class)
         .collect();
-    let mut route = profiles::Route::new(orig.metrics_labels.into_iter(), rsp_classes);
+    let mut labels = orig.metrics_labels;
+    let max_body_size = labels.remove(MAX_REQUEST_BODY_SIZE_LABEL);
+    let mut route = profiles::Route::new(labels.into_iter(), rsp_classes);
     if orig.is_retryable {
         set_route_retry(&mut route, retry_budget);
     }
     if let Some(timeout) = orig.timeout {
         set_route_timeout(&mut route, timeout.into());
     }
+    if let Some(max) = max_body_size {
+        set_route_max_request_body_size(&mut route, &max);
+    }
     Some((req_match, route))
 }
 
@@ -291,6 +435,17 @@ fn set_route_timeout(route: &mut profiles::Route, timeout: Result<Duration, Dura
     }
 }
 
+fn set_route_max_request_body_size(route: &mut profiles::Route, max: &str) {
+    match max.parse::<usize>() {
+        Ok(max) => {
+            route.set_max_request_body_size(max);
+        }
+        Err(_) => {
+            warn!("route max request body size is invalid: {:?}", max);
+        }
+    }
+}
+
 fn convert_req_match(orig: api::RequestMatch) -> Option<profiles::RequestMatch> {
     let m = match orig.r#match? {
         api::request_match::Match::All(ms) => {
@@ -433,4 +588,62 @@ mod tests {
             true
         }
     }
+    #[test]
+    fn route_max_request_body_size_from_label() {
+        let route = |labels: &[(&str, &str)]| {
+            let proto = api::Route {
+                condition: Some(api::RequestMatch {
+                    r#match: Some(api::request_match::Match::Path(api::PathMatch {
+                        regex: "/upload".to_owned(),
+                    })),
+                }),
+                metrics_labels: labels
+                    .iter()
+                    .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect(),
+                ..api::Route::default()
+            };
+            convert_route(proto, None).expect("route must convert").1
+        };
+
+        let r = route(&[("route", "upload"), ("max_request_body_size", "1024")]);
+        assert_eq!(r.max_request_body_size(), Some(1024));
+        assert_eq!(r.labels().len(), 1);
+        assert_eq!(r.labels().get("route").map(String::as_str), Some("upload"));
+
+        let r = route(&[("route", "upload"), ("max_request_body_size", "lots")]);
+        assert_eq!(r.max_request_body_size(), None);
+
+        let r = route(&[("route", "upload")]);
+        assert_eq!(r.max_request_body_size(), None);
+    }
+
+    #[test]
+    fn recovery_reports_stale_routes_until_a_profile_is_received() {
+        let (metrics, report) = metrics();
+        let stale = || report.0.stale.load(Ordering::Acquire);
+        let mut recovery = Recovery {
+            backoff: Backoff::new(Duration::from_secs(1), MAX_BACKOFF),
+            metrics,
+            received: false,
+            stale: false,
+        };
+
+        // Without routes, there's nothing stale to serve.
+        recovery.reset();
+        assert_eq!(stale(), 0);
+
+        recovery.received();
+        recovery.reset();
+        recovery.reset();
+        assert_eq!(stale(), 1);
+        assert_eq!(report.0.resets.load(Ordering::Acquire), 3);
+
+        recovery.received();
+        assert_eq!(stale(), 0);
+
+        recovery.reset();
+        drop(recovery);
+        assert_eq!(stale(), 0);
+    }
 }
@@ -0,0 +1,865 @@
+//! Records a span for each proxied request and exports it to a collector.
+//!
+//! Trace context is read from the request's `traceparent` or B3 headers, and
+//! the proxy's own span is propagated to the next hop in the same format. Spans
+//! are handed to a dedicated exporter thread over a bounded channel, which
+//! posts them in batches to an OpenTelemetry (OTLP/HTTP) or OpenCensus agent
+//! collector; when the exporter falls behind, spans are dropped rather than
+//! applying backpressure to requests.
+//!
+//! Which requests are sampled is decided by a global `Sampler`, which may be
+//! overridden for individual profile routes at runtime through the admin
+//! server, e.g. to trace every request on a route under investigation.
+
+use base64;
+use futures::{Async, Future, Poll};
+use http::{self, header};
+use hyper::{self, body::Payload};
+use indexmap::IndexMap;
+use rand;
+use std::fmt::{self, Write};
+use std::io;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::runtime::current_thread;
+use tokio_timer::Timeout;
+use tower_http_service;
+
+use proxy::http::trace_context::{self, Context, SpanId};
+use proxy::server::Source;
+use svc;
+use Conditional;
+
+use super::access_log::Escape;
+use super::dst::{Direction, Route};
+
+/// The maximum number of spans that are exported in a single request.
+const MAX_BATCH: usize = 512;
+
+/// How long spans may be buffered before they are exported.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The wire protocol spoken by a trace collector.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// OTLP's JSON encoding, posted to `/v1/traces`.
+    Otlp,
+    /// The OpenCensus agent's JSON encoding, posted to `/v1/trace`.
+    OpenCensus,
+}
+
+/// Decides which requests' spans are recorded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sampler {
+    Always,
+    Never,
+    /// Follows the sampling decision of the request's trace context, if any.
+    /// Requests that do not carry a sampled trace context are not recorded.
+    Parent,
+    /// Samples this proportion of requests, regardless of their trace
+    /// context.
+    Ratio(f64),
+}
+
+/// Samplers that override the global sampler for requests on specific
+/// routes, keyed by the name that the route's profile gives it.
+#[derive(Clone, Debug, Default)]
+pub struct RouteSamplers(Arc<RwLock<IndexMap<String, Sampler>>>);
+
+/// Where spans are exported.
+#[derive(Clone, Debug)]
+pub struct Collector {
+    pub uri: http::Uri,
+    pub protocol: Protocol,
+    /// Identifies the proxy's spans.
+    pub service_name: String,
+}
+
+/// A handle to the span exporter.
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    tx: Option<mpsc::SyncSender<Span>>,
+    sampler: Sampler,
+    route_samplers: RouteSamplers,
+    dropped: Arc<AtomicUsize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Server,
+    Client,
+}
+
+/// A completed span.
+#[derive(Debug)]
+struct Span {
+    context: Context,
+    kind: Kind,
+    name: String,
+    start: SystemTime,
+    duration: Duration,
+    attributes: Vec<(String, String)>,
+    failed: bool,
+}
+
+pub fn layer(tracer: Tracer) -> Layer {
+    Layer { tracer }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    tracer: Tracer,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    tracer: Tracer,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    kind: Kind,
+    name: String,
+    /// The name of the profile route, if the request matched one.
+    route_name: Option<String>,
+    authority: String,
+    route: Vec<(String, String)>,
+    tracer: Tracer,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    pending: Option<Pending>,
+}
+
+/// A span retained until the response completes.
+struct Pending {
+    span: Span,
+    started_at: Instant,
+    tracer: Tracer,
+}
+
+/// Ends the request's span when the body is dropped.
+#[derive(Debug, Default)]
+pub struct ResponseBody<B> {
+    inner: B,
+    pending: Option<(Span, Instant, Tracer)>,
+}
+
+// === impl Protocol ===
+
+impl Protocol {
+    fn encode(&self, service_name: &str, spans: &[Span]) -> String {
+        let mut body = String::new();
+        let _ = match self {
+            Protocol::Otlp => write_otlp(&mut body, service_name, spans),
+            Protocol::OpenCensus => write_opencensus(&mut body, service_name, spans),
+        };
+        body
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("otlp") => Ok(Protocol::Otlp),
+            s if s.eq_ignore_ascii_case("opencensus") => Ok(Protocol::OpenCensus),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Protocol::Otlp => f.pad("otlp"),
+            Protocol::OpenCensus => f.pad("opencensus"),
+        }
+    }
+}
+
+// === impl Sampler ===
+
+impl Sampler {
+    fn sample(&self, parent: Option<&trace_context::Parent>) -> bool {
+        match self {
+            Sampler::Always => true,
+            Sampler::Never => false,
+            Sampler::Parent => parent.and_then(|p| p.sampled).unwrap_or(false),
+            Sampler::Ratio(ratio) => rand::random::<f64>() < ratio,
+        }
+    }
+}
+
+impl FromStr for Sampler {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("always") => Ok(Sampler::Always),
+            s if s.eq_ignore_ascii_case("never") => Ok(Sampler::Never),
+            s if s.eq_ignore_ascii_case("parent") => Ok(Sampler::Parent),
+            s => match s.parse::<f64>() {
+                Ok(ratio) if ratio >= 0.0 && ratio <= 1.0 => Ok(Sampler::Ratio(ratio)),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sampler::Always => f.pad("always"),
+            Sampler::Never => f.pad("never"),
+            Sampler::Parent => f.pad("parent"),
+            Sampler::Ratio(ratio) => fmt::Display::fmt(ratio, f),
+        }
+    }
+}
+
+// === impl RouteSamplers ===
+
+impl RouteSamplers {
+    pub fn new<I>(samplers: I) -> Self
+    where
+        I: IntoIterator<Item = (String, Sampler)>,
+    {
+        RouteSamplers(Arc::new(RwLock::new(samplers.into_iter().collect())))
+    }
+
+    fn get(&self, route: &str) -> Option<Sampler> {
+        self.0.read().ok()?.get(route).cloned()
+    }
+
+    pub fn set(&self, route: String, sampler: Sampler) {
+        if let Ok(mut samplers) = self.0.write() {
+            info!("sampling traces on route {:?} with {}", route, sampler);
+            samplers.insert(route, sampler);
+        }
+    }
+
+    /// Returns false if the route had no sampler.
+    pub fn remove(&self, route: &str) -> bool {
+        match self.0.write() {
+            Ok(mut samplers) => samplers.remove(route).is_some(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Lists each route's sampler as `<route>=<sampler>`, one per line.
+impl fmt::Display for RouteSamplers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let samplers = match self.0.read() {
+            Ok(samplers) => samplers,
+            Err(_) => return Ok(()),
+        };
+        for (route, sampler) in samplers.iter() {
+            writeln!(f, "{}={}", route, sampler)?;
+        }
+        Ok(())
+    }
+}
+
+// === impl Tracer ===
+
+impl Tracer {
+    /// Spawns a thread that exports up to `capacity` buffered spans to
+    /// `collector`.
+    ///
+    /// If no collector is configured, trace context is neither propagated nor
+    /// recorded.
+    pub fn new(
+        collector: Option<Collector>,
+        sampler: Sampler,
+        route_samplers: RouteSamplers,
+        capacity: usize,
+    ) -> io::Result<Self> {
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let collector = match collector {
+            Some(c) => c,
+            None => {
+                return Ok(Self {
+                    tx: None,
+                    sampler,
+                    route_samplers,
+                    dropped,
+                });
+            }
+        };
+
+        info!(
+            "exporting spans to {} ({})",
+            collector.uri, collector.protocol
+        );
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let exporter_dropped = dropped.clone();
+        thread::Builder::new()
+            .name("trace-export".into())
+            .spawn(move || export_spans(rx, collector, &exporter_dropped))?;
+
+        Ok(Self {
+            tx: Some(tx),
+            sampler,
+            route_samplers,
+            dropped,
+        })
+    }
+
+    /// Returns the per-route samplers, if tracing is enabled.
+    pub fn route_samplers(&self) -> Option<RouteSamplers> {
+        if self.is_enabled() {
+            Some(self.route_samplers.clone())
+        } else {
+            None
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    fn sampler(&self, route_name: Option<&String>) -> Sampler {
+        route_name
+            .and_then(|r| self.route_samplers.get(r))
+            .unwrap_or(self.sampler)
+    }
+
+    fn record(&self, span: Span) {
+        if let Some(ref tx) = self.tx {
+            if let Err(mpsc::TrySendError::Full(_)) = tx.try_send(span) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Exports spans until all senders have been dropped.
+///
+/// Spans are buffered until a batch fills or the flush interval elapses.
+fn export_spans(rx: mpsc::Receiver<Span>, collector: Collector, dropped: &AtomicUsize) {
+    let mut rt = match current_thread::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            error!("failed to start the span exporter: {}", e);
+            return;
+        }
+    };
+    let client = hyper::Client::new();
+    let mut batch = Vec::with_capacity(MAX_BATCH);
+
+    while let Ok(span) = rx.recv() {
+        batch.push(span);
+        let deadline = Instant::now() + FLUSH_INTERVAL;
+        while batch.len() < MAX_BATCH {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match rx.recv_timeout(deadline - now) {
+                Ok(span) => batch.push(span),
+                Err(_) => break,
+            }
+        }
+
+        let body = collector.protocol.encode(&collector.service_name, &batch);
+        batch.clear();
+        let req = http::Request::post(collector.uri.clone())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(hyper::Body::from(body))
+            .expect("export request must be valid");
+        match rt.block_on(Timeout::new(client.request(req), EXPORT_TIMEOUT)) {
+            Ok(ref rsp) if rsp.status().is_success() => {}
+            Ok(rsp) => warn!("failed to export spans: {}", rsp.status()),
+            Err(e) => warn!("failed to export spans: {}", e),
+        }
+
+        let n = dropped.swap(0, Ordering::Relaxed);
+        if n > 0 {
+            warn!("dropped {} spans", n);
+        }
+    }
+}
+
+// === impl Span ===
+
+impl Span {
+    fn end(&self) -> SystemTime {
+        self.start + self.duration
+    }
+}
+
+fn unix_nanos(t: SystemTime) -> u64 {
+    let d = t
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0));
+    d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos())
+}
+
+/// Writes spans as an OTLP `ExportTraceServiceRequest`.
+fn write_otlp<W: Write>(w: &mut W, service_name: &str, spans: &[Span]) -> fmt::Result {
+    write!(
+        w,
+        "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[\
+         {{\"key\":\"service.name\",\"value\":{{\"stringValue\":\"{}\"}}}}]}},\
+         \"scopeSpans\":[{{\"scope\":{{\"name\":\"linkerd2-proxy\"}},\"spans\":[",
+        Escape(service_name),
+    )?;
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            w.write_str(",")?;
+        }
+        write!(
+            w,
+            "{{\"traceId\":\"{}\",\"spanId\":\"{}\",",
+            span.context.trace_id, span.context.span_id,
+        )?;
+        if let Some(parent) = span.context.parent_id {
+            write!(w, "\"parentSpanId\":\"{}\",", parent)?;
+        }
+        let kind = match span.kind {
+            Kind::Server => 2,
+            Kind::Client => 3,
+        };
+        write!(
+            w,
+            "\"name\":\"{}\",\"kind\":{},\
+             \"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[",
+            Escape(&span.name),
+            kind,
+            unix_nanos(span.start),
+            unix_nanos(span.end()),
+        )?;
+        for (i, (k, v)) in span.attributes.iter().enumerate() {
+            if i > 0 {
+                w.write_str(",")?;
+            }
+            write!(
+                w,
+                "{{\"key\":\"{}\",\"value\":{{\"stringValue\":\"{}\"}}}}",
+                Escape(k),
+                Escape(v),
+            )?;
+        }
+        // OTLP status codes: 1 is OK, 2 is ERROR.
+        write!(
+            w,
+            "],\"status\":{{\"code\":{}}}}}",
+            if span.failed { 2 } else { 1 }
+        )?;
+    }
+    w.write_str("]}]}]}")
+}
+
+/// Writes spans as an OpenCensus agent `ExportTraceServiceRequest`.
+fn write_opencensus<W: Write>(w: &mut W, service_name: &str, spans: &[Span]) -> fmt::Result {
+    write!(
+        w,
+        "{{\"node\":{{\"serviceInfo\":{{\"name\":\"{}\"}}}},\"spans\":[",
+        Escape(service_name),
+    )?;
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            w.write_str(",")?;
+        }
+        write!(
+            w,
+            "{{\"traceId\":\"{}\",\"spanId\":\"{}\",",
+            base64::encode(&span.context.trace_id.0),
+            base64::encode(&span.context.span_id.0),
+        )?;
+        if let Some(SpanId(ref parent)) = span.context.parent_id {
+            write!(w, "\"parentSpanId\":\"{}\",", base64::encode(parent))?;
+        }
+        let kind = match span.kind {
+            Kind::Server => "SERVER",
+            Kind::Client => "CLIENT",
+        };
+        write!(
+            w,
+            "\"name\":{{\"value\":\"{}\"}},\"kind\":\"{}\",\
+             \"startTime\":\"{}\",\"endTime\":\"{}\",\"attributes\":{{\"attributeMap\":{{",
+            Escape(&span.name),
+            kind,
+            Rfc3339(span.start),
+            Rfc3339(span.end()),
+        )?;
+        for (i, (k, v)) in span.attributes.iter().enumerate() {
+            if i > 0 {
+                w.write_str(",")?;
+            }
+            write!(
+                w,
+                "\"{}\":{{\"stringValue\":{{\"value\":\"{}\"}}}}",
+                Escape(k),
+                Escape(v),
+            )?;
+        }
+        // OpenCensus uses gRPC status codes: 0 is OK, 2 is UNKNOWN.
+        write!(
+            w,
+            "}}}},\"status\":{{\"code\":{}}}}}",
+            if span.failed { 2 } else { 0 }
+        )?;
+    }
+    w.write_str("]}")
+}
+
+/// Formats a timestamp as an RFC 3339 UTC date-time.
+struct Rfc3339(SystemTime);
+
+impl fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let d = self
+            .0
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        let secs = d.as_secs();
+        let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+        let rem = secs % 86_400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+            year,
+            month,
+            day,
+            rem / 3_600,
+            (rem % 3_600) / 60,
+            rem % 60,
+            d.subsec_nanos(),
+        )
+    }
+}
+
+/// Converts a number of days since the Unix epoch to a (year, month, day)
+/// date in the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Route, Route, M> for Layer
+where
+    M: svc::Stack<Route>,
+{
+    type Value = <Stack<M> as svc::Stack<Route>>::Value;
+    type Error = <Stack<M> as svc::Stack<Route>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            tracer: self.tracer.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Route> for Stack<M>
+where
+    M: svc::Stack<Route>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &Route) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        let labels = target.route.labels();
+        let authority = target.dst_addr.to_string();
+        let route_name = labels.get("route").cloned();
+        let name = route_name.clone().unwrap_or_else(|| authority.clone());
+        let route = labels
+            .iter()
+            .map(|(k, v)| (format!("route.{}", k), v.clone()))
+            .collect();
+        let kind = match target.dst_addr.direction() {
+            Direction::In => Kind::Server,
+            Direction::Out => Kind::Client,
+        };
+        Ok(Service {
+            inner,
+            kind,
+            name,
+            route_name,
+            authority,
+            route,
+            tracer: self.tracer.clone(),
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S> Service<S> {
+    fn attributes<A>(&self, req: &http::Request<A>) -> Vec<(String, String)> {
+        let mut attrs = vec![
+            ("http.method".to_owned(), req.method().to_string()),
+            ("http.path".to_owned(), req.uri().path().to_owned()),
+            ("http.host".to_owned(), self.authority.clone()),
+        ];
+        attrs.extend(self.route.iter().cloned());
+
+        // Inbound spans describe the client; outbound spans describe the
+        // destination, which is already recorded as the host.
+        if self.kind == Kind::Server {
+            if let Some(src) = req.extensions().get::<Source>() {
+                attrs.push(("net.peer.addr".to_owned(), src.remote.to_string()));
+                if let Conditional::Some(ref id) = src.tls_peer {
+                    attrs.push(("peer.identity".to_owned(), id.as_ref().to_owned()));
+                }
+            }
+        }
+        attrs
+    }
+}
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        let pending = if self.tracer.is_enabled() {
+            let parent = trace_context::extract(req.headers());
+            let sampler = self.tracer.sampler(self.route_name.as_ref());
+            let sampled = sampler.sample(parent.as_ref());
+            let context = Context::new(parent.as_ref(), sampled);
+            trace_context::inject(req.headers_mut(), &context);
+
+            if sampled {
+                Some(Pending {
+                    span: Span {
+                        context,
+                        kind: self.kind,
+                        name: self.name.clone(),
+                        start: SystemTime::now(),
+                        duration: Duration::from_secs(0),
+                        attributes: self.attributes(&req),
+                        failed: false,
+                    },
+                    started_at: Instant::now(),
+                    tracer: self.tracer.clone(),
+                })
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            pending,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<ResponseBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = match self.inner.poll() {
+            Ok(Async::Ready(rsp)) => rsp,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                if let Some(mut p) = self.pending.take() {
+                    p.span.failed = true;
+                    p.span.duration = p.started_at.elapsed();
+                    p.tracer.record(p.span);
+                }
+                return Err(e);
+            }
+        };
+
+        let pending = self.pending.take().map(|mut p| {
+            let status = rsp.status();
+            p.span
+                .attributes
+                .push(("http.status_code".to_owned(), status.as_u16().to_string()));
+            p.span.failed = status.is_server_error();
+            (p.span, p.started_at, p.tracer)
+        });
+
+        Ok(Async::Ready(
+            rsp.map(|inner| ResponseBody { inner, pending }),
+        ))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B: Payload> Payload for ResponseBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = self.inner.poll_data();
+        if data.is_err() {
+            if let Some((ref mut span, _, _)) = self.pending {
+                span.failed = true;
+            }
+        }
+        data
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+impl<B: Payload> tower_http_service::Body for ResponseBody<B> {
+    type Item = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_buf(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B> Drop for ResponseBody<B> {
+    fn drop(&mut self) {
+        if let Some((mut span, started_at, tracer)) = self.pending.take() {
+            span.duration = started_at.elapsed();
+            tracer.record(span);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proxy::http::trace_context::TraceId;
+
+    fn span() -> Span {
+        let mut context = Context::new(None, true);
+        context.trace_id = TraceId([0xab; 16]);
+        context.span_id = SpanId([0x01; 8]);
+        context.parent_id = Some(SpanId([0x02; 8]));
+        Span {
+            context,
+            kind: Kind::Server,
+            name: "GET /books/{id}".into(),
+            start: UNIX_EPOCH + Duration::from_millis(1_500),
+            duration: Duration::from_millis(12),
+            attributes: vec![("http.method".into(), "GET".into())],
+            failed: false,
+        }
+    }
+
+    #[test]
+    fn encodes_otlp() {
+        assert_eq!(
+            Protocol::Otlp.encode("web", &[span()]),
+            "{\"resourceSpans\":[{\"resource\":{\"attributes\":[\
+             {\"key\":\"service.name\",\"value\":{\"stringValue\":\"web\"}}]},\
+             \"scopeSpans\":[{\"scope\":{\"name\":\"linkerd2-proxy\"},\"spans\":[\
+             {\"traceId\":\"abababababababababababababababab\",\
+             \"spanId\":\"0101010101010101\",\"parentSpanId\":\"0202020202020202\",\
+             \"name\":\"GET /books/{id}\",\"kind\":2,\
+             \"startTimeUnixNano\":\"1500000000\",\"endTimeUnixNano\":\"1512000000\",\
+             \"attributes\":[{\"key\":\"http.method\",\"value\":{\"stringValue\":\"GET\"}}],\
+             \"status\":{\"code\":1}}]}]}]}"
+        );
+    }
+
+    #[test]
+    fn encodes_opencensus() {
+        assert_eq!(
+            Protocol::OpenCensus.encode("web", &[span()]),
+            "{\"node\":{\"serviceInfo\":{\"name\":\"web\"}},\"spans\":[\
+             {\"traceId\":\"q6urq6urq6urq6urq6urqw==\",\"spanId\":\"AQEBAQEBAQE=\",\
+             \"parentSpanId\":\"AgICAgICAgI=\",\
+             \"name\":{\"value\":\"GET /books/{id}\"},\"kind\":\"SERVER\",\
+             \"startTime\":\"1970-01-01T00:00:01.500000000Z\",\
+             \"endTime\":\"1970-01-01T00:00:01.512000000Z\",\
+             \"attributes\":{\"attributeMap\":{\"http.method\":{\"stringValue\":{\"value\":\"GET\"}}}},\
+             \"status\":{\"code\":0}}]}"
+        );
+    }
+
+    #[test]
+    fn route_samplers_override_the_global_sampler() {
+        let routes = RouteSamplers::new(vec![("GET /books".to_owned(), Sampler::Always)]);
+        let tracer = Tracer::new(None, Sampler::Ratio(0.001), routes.clone(), 1).unwrap();
+        assert_eq!(
+            tracer.sampler(Some(&"GET /books".to_owned())),
+            Sampler::Always
+        );
+        assert_eq!(
+            tracer.sampler(Some(&"GET /authors".to_owned())),
+            Sampler::Ratio(0.001)
+        );
+        assert_eq!(tracer.sampler(None), Sampler::Ratio(0.001));
+
+        routes.set("GET /authors".to_owned(), Sampler::Never);
+        assert!(routes.remove("GET /books"));
+        assert!(!routes.remove("GET /books"));
+        assert_eq!(routes.to_string(), "GET /authors=never\n");
+        assert_eq!(
+            tracer.sampler(Some(&"GET /books".to_owned())),
+            Sampler::Ratio(0.001)
+        );
+    }
+
+    #[test]
+    fn parses_samplers() {
+        assert_eq!("parent".parse(), Ok(Sampler::Parent));
+        assert_eq!("0.001".parse(), Ok(Sampler::Ratio(0.001)));
+        assert_eq!("1".parse(), Ok(Sampler::Ratio(1.0)));
+        assert_eq!("1.5".parse::<Sampler>(), Err(()));
+        assert_eq!(Sampler::Ratio(0.25).to_string(), "0.25");
+    }
+
+    #[test]
+    fn formats_rfc3339() {
+        let t = UNIX_EPOCH + Duration::from_secs(1_551_398_400);
+        assert_eq!(Rfc3339(t).to_string(), "2019-03-01T00:00:00.000000000Z");
+    }
+}
@@ -0,0 +1,95 @@
+//! Exponential backoff, for spacing out retries of an operation that keeps
+//! failing.
+
+use rand;
+use std::time::Duration;
+
+/// Spaces out retries of a failing operation.
+///
+/// The backoff doubles with each consecutive failure, from `min` up to `max`,
+/// and is jittered so that operations that failed together (e.g. because a
+/// server was unreachable) do not retry together.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    failures: usize,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            failures: 0,
+        }
+    }
+
+    /// Records a failure and returns how long to wait before retrying.
+    pub fn next(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        jitter(exponential(self.min, self.max, self.failures))
+    }
+
+    /// Records a success, so that the next failure is retried after `min`.
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// Returns the backoff after `failures` consecutive failures, which doubles
+/// with each failure from `min` up to `max`.
+pub fn exponential(min: Duration, max: Duration, failures: usize) -> Duration {
+    let exp = failures.saturating_sub(1).min(31) as u32;
+    min.checked_mul(1 << exp)
+        .map(|backoff| backoff.min(max))
+        .unwrap_or(max)
+}
+
+/// Returns a random duration between half of `backoff` and `backoff`.
+pub fn jitter(backoff: Duration) -> Duration {
+    let ms = backoff.as_secs() * 1_000 + u64::from(backoff.subsec_millis());
+    let half = ms / 2;
+    Duration::from_millis(half + rand::random::<u64>() % (ms - half + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_doubles_up_to_max() {
+        let min = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+        let backoffs = (1..9)
+            .map(|n| exponential(min, max, n).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(backoffs, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(exponential(min, max, usize::max_value()), max);
+    }
+
+    #[test]
+    fn jitter_is_bounded() {
+        let backoff = Duration::from_secs(10);
+        for _ in 0..100 {
+            let j = jitter(backoff);
+            assert!(Duration::from_secs(5) <= j && j <= backoff, "{:?}", j);
+        }
+    }
+
+    #[test]
+    fn doubles_up_to_max_until_reset() {
+        let mut backoff = Backoff::new(Duration::from_secs(3), Duration::from_secs(60));
+        let failures = (0..7)
+            .map(|_| {
+                backoff.next();
+                exponential(backoff.min, backoff.max, backoff.failures).as_secs()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(failures, vec![3, 6, 12, 24, 48, 60, 60]);
+
+        backoff.reset();
+        let next = backoff.next();
+        assert!(Duration::from_millis(1_500) <= next && next <= Duration::from_secs(3));
+    }
+}
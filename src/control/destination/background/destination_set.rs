@@ -8,6 +8,7 @@ use std::{
 };
 
 use futures::{Async, Future, Stream};
+use tokio_timer::Delay;
 use tower_grpc::{generic::client::GrpcService, BoxBody};
 
 use api::{
@@ -17,6 +18,7 @@ use api::{
     net::TcpAddress,
 };
 
+use backoff::Backoff;
 use control::{
     cache::{Cache, CacheChange, Exists},
     destination::{Metadata, ProtocolHint, Responder, Update},
@@ -28,6 +30,10 @@ use NameAddr;
 
 use super::{ActiveQuery, DestinationServiceQuery, UpdateRx};
 
+/// An endpoint label that, when set, overrides the SNI name used to originate
+/// TLS to the endpoint. It is not exposed as a metric label.
+pub(super) const SERVER_NAME_LABEL: &str = "tls_server_name";
+
 /// Holds the state of a single resolution.
 pub(super) struct DestinationSet<T>
 where
@@ -37,6 +43,24 @@ where
     pub query: DestinationServiceQuery<T>,
     pub dns_query: Option<IpAddrListFuture>,
     pub responders: Vec<Responder>,
+    /// Set when `addrs` is updated, until the update is persisted.
+    pub modified: bool,
+    /// Fires when the destination should be resolved via DNS, unless the
+    /// Destination service has responded by then.
+    pub dns_fallback_timeout: Option<Delay>,
+    /// Set while `addrs` are resolved via DNS because the Destination service
+    /// is unavailable.
+    pub dns_fallback: bool,
+    /// Spaces out reconnects of a Destination service stream that keeps
+    /// failing without providing an update.
+    pub reconnect_backoff: Backoff,
+    /// Fires when the Destination service stream should be reconnected.
+    pub reconnect_delay: Option<Delay>,
+    /// Set while `addrs` were provided by a stream that has since ended (or
+    /// by a previous process), until the Destination service updates them.
+    pub stale: bool,
+    /// Fires when a destination without responders should be dropped.
+    pub idle: Option<Delay>,
 }
 
 // ===== impl DestinationSet =====
@@ -60,6 +84,120 @@ where
         self.dns_query = Some(dns_resolver.resolve_all_ips(deadline, authority.name()));
     }
 
+    /// Starts the DNS fallback timeout, if one is configured, unless it is
+    /// already due to fire sooner or the destination is already resolved via
+    /// DNS.
+    pub(super) fn start_dns_fallback_timeout(&mut self, timeout: Option<Duration>) {
+        let timeout = match timeout {
+            Some(timeout) if !self.dns_fallback => timeout,
+            _ => return,
+        };
+        let deadline = Instant::now() + timeout;
+        match self.dns_fallback_timeout {
+            Some(ref mut delay) => {
+                if delay.deadline() > deadline {
+                    delay.reset(deadline);
+                }
+            }
+            None => self.dns_fallback_timeout = Some(Delay::new(deadline)),
+        }
+    }
+
+    /// Starts resolving the destination via DNS once the fallback timeout
+    /// fires, if the Destination service has yet to resolve it.
+    pub(super) fn poll_dns_fallback(&mut self, dns_resolver: &dns::Resolver, authority: &NameAddr) {
+        let fired = match self.dns_fallback_timeout {
+            Some(ref mut timeout) => match timeout.poll() {
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(())) => true,
+                Err(e) => {
+                    warn!("DNS fallback timer failed for {:?}: {}", authority, e);
+                    true
+                }
+            },
+            None => false,
+        };
+        if !fired {
+            return;
+        }
+        self.dns_fallback_timeout = None;
+
+        if let Exists::Unknown = self.addrs {
+            if self.dns_query.is_none() {
+                debug!(
+                    "Destination service unavailable; resolving {:?} via DNS",
+                    authority
+                );
+                self.dns_fallback = true;
+                self.reset_dns_query(dns_resolver, Instant::now(), authority);
+            }
+        }
+    }
+
+    /// Stops resolving the destination via DNS once the Destination service
+    /// has responded.
+    ///
+    /// The DNS query resets the cache each time it is polled, so the
+    /// Destination service's first update replaces the DNS endpoints.
+    pub(super) fn end_dns_fallback(&mut self) {
+        self.dns_fallback_timeout = None;
+        if self.dns_fallback {
+            self.dns_fallback = false;
+            self.dns_query = None;
+        }
+    }
+
+    /// Schedules the Destination service stream to be reconnected after a
+    /// backoff. In the meantime, the last-known endpoints are served.
+    pub(super) fn schedule_reconnect(&mut self, authority: &NameAddr) {
+        let backoff = self.reconnect_backoff.next();
+        debug!("reconnecting to {:?} in {:?}", authority, backoff);
+        self.reconnect_delay = Some(Delay::new(Instant::now() + backoff));
+        if let Exists::Yes(_) = self.addrs {
+            self.stale = true;
+        }
+    }
+
+    /// Returns true once the Destination service stream should be
+    /// reconnected.
+    pub(super) fn poll_reconnect_delay(&mut self) -> bool {
+        let ready = match self.reconnect_delay {
+            Some(ref mut delay) => match delay.poll() {
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(())) => true,
+                Err(e) => {
+                    warn!("reconnect timer failed: {}", e);
+                    true
+                }
+            },
+            None => false,
+        };
+        if ready {
+            self.reconnect_delay = None;
+        }
+        ready
+    }
+
+    /// Returns true until a destination without responders has been idle for
+    /// `timeout`.
+    pub(super) fn poll_idle(&mut self, timeout: Option<Duration>) -> bool {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+        let idle = self
+            .idle
+            .get_or_insert_with(|| Delay::new(Instant::now() + timeout));
+        match idle.poll() {
+            Ok(Async::NotReady) => true,
+            Ok(Async::Ready(())) => false,
+            Err(e) => {
+                warn!("idle timer failed: {}", e);
+                false
+            }
+        }
+    }
+
     // Processes Destination service updates from `request_rx`, returning the new query
     // and an indication of any *change* to whether the service exists as far as the
     // Destination service is concerned, where `Exists::Unknown` is to be interpreted as
@@ -73,35 +211,42 @@ where
 
         loop {
             match rx.poll() {
-                Ok(Async::Ready(Some(update))) => match update.update {
-                    Some(PbUpdate2::Add(a_set)) => {
-                        let set_labels = a_set.metric_labels;
-                        let addrs = a_set
-                            .addrs
-                            .into_iter()
-                            .filter_map(|pb| pb_to_addr_meta(pb, &set_labels));
-                        self.add(auth, addrs)
-                    }
-                    Some(PbUpdate2::Remove(r_set)) => {
-                        exists = Exists::Yes(());
-                        self.remove(
-                            auth,
-                            r_set
+                Ok(Async::Ready(Some(update))) => {
+                    // The stream is healthy, so if it ends, it is reconnected
+                    // promptly.
+                    self.reconnect_backoff.reset();
+                    self.stale = false;
+                    match update.update {
+                        Some(PbUpdate2::Add(a_set)) => {
+                            let set_labels = a_set.metric_labels;
+                            let addrs = a_set
                                 .addrs
-                                .iter()
-                                .filter_map(|addr| pb_to_sock_addr(addr.clone())),
-                        );
-                    }
-                    Some(PbUpdate2::NoEndpoints(ref no_endpoints)) if no_endpoints.exists => {
-                        exists = Exists::Yes(());
-                        self.no_endpoints(auth, no_endpoints.exists);
-                    }
-                    Some(PbUpdate2::NoEndpoints(no_endpoints)) => {
-                        debug_assert!(!no_endpoints.exists);
-                        exists = Exists::No;
+                                .into_iter()
+                                .filter_map(|pb| pb_to_addr_meta(pb, &set_labels));
+                            exists = Exists::Yes(());
+                            self.add(auth, addrs)
+                        }
+                        Some(PbUpdate2::Remove(r_set)) => {
+                            exists = Exists::Yes(());
+                            self.remove(
+                                auth,
+                                r_set
+                                    .addrs
+                                    .iter()
+                                    .filter_map(|addr| pb_to_sock_addr(addr.clone())),
+                            );
+                        }
+                        Some(PbUpdate2::NoEndpoints(ref no_endpoints)) if no_endpoints.exists => {
+                            exists = Exists::Yes(());
+                            self.no_endpoints(auth, no_endpoints.exists);
+                        }
+                        Some(PbUpdate2::NoEndpoints(no_endpoints)) => {
+                            debug_assert!(!no_endpoints.exists);
+                            exists = Exists::No;
+                        }
+                        None => (),
                     }
-                    None => (),
-                },
+                }
                 Ok(Async::Ready(None)) => {
                     trace!(
                         "Destination.Get stream ended for {:?}, must reconnect",
@@ -139,11 +284,15 @@ where
                         authority,
                         ips
                     );
+                    let meta = if self.dns_fallback {
+                        Metadata::dns_fallback()
+                    } else {
+                        Metadata::empty()
+                    };
                     self.add(
                         authority,
-                        ips.iter().map(|ip| {
-                            (SocketAddr::from((ip, authority.port())), Metadata::empty())
-                        }),
+                        ips.iter()
+                            .map(|ip| (SocketAddr::from((ip, authority.port())), meta.clone())),
                     );
 
                     // Poll again after the deadline on the DNS response.
@@ -192,10 +341,23 @@ where
         }
     }
 
+    /// Adds endpoints that were persisted by a previous process. They are
+    /// served until they are replaced by the next update.
+    pub(super) fn add_stale<A>(&mut self, authority_for_logging: &NameAddr, addrs: A)
+    where
+        A: Iterator<Item = (SocketAddr, Metadata)>,
+    {
+        self.add(authority_for_logging, addrs);
+        self.reset_on_next_modification();
+        self.modified = false;
+        self.stale = true;
+    }
+
     fn add<A>(&mut self, authority_for_logging: &NameAddr, addrs_to_add: A)
     where
         A: Iterator<Item = (SocketAddr, Metadata)>,
     {
+        self.modified = true;
         let mut cache = match self.addrs.take() {
             Exists::Yes(mut cache) => cache,
             Exists::Unknown | Exists::No => Cache::new(),
@@ -210,6 +372,7 @@ where
     where
         A: Iterator<Item = SocketAddr>,
     {
+        self.modified = true;
         let cache = match self.addrs.take() {
             Exists::Yes(mut cache) => {
                 cache.remove(addrs_to_remove, &mut |change| {
@@ -228,6 +391,7 @@ where
             authority_for_logging,
             if exists { "exist" } else { "not exist" }
         );
+        self.modified = true;
         match self.addrs.take() {
             Exists::Yes(mut cache) => {
                 cache.clear(&mut |change| {
@@ -269,11 +433,15 @@ where
 }
 
 /// Construct a new labeled `SocketAddr `from a protobuf `WeightedAddr`.
-fn pb_to_addr_meta(
-    pb: WeightedAddr,
+pub(super) fn pb_to_addr_meta(
+    mut pb: WeightedAddr,
     set_labels: &HashMap<String, String>,
 ) -> Option<(SocketAddr, Metadata)> {
     let addr = pb.addr.and_then(pb_to_sock_addr)?;
+    let server_name = pb
+        .metric_labels
+        .remove(SERVER_NAME_LABEL)
+        .and_then(pb_to_server_name);
 
     let meta = {
         let mut t = set_labels
@@ -302,7 +470,7 @@ fn pb_to_addr_meta(
     }
 
     let tls_id = pb.tls_identity.and_then(pb_to_id);
-    let meta = Metadata::new(meta, proto_hint, tls_id);
+    let meta = Metadata::new(meta, proto_hint, tls_id).with_server_name(server_name);
     Some((addr, meta))
 }
 
@@ -310,7 +478,7 @@ fn pb_to_id(pb: TlsIdentity) -> Option<identity::Name> {
     use api::destination::tls_identity::Strategy;
 
     let Strategy::DnsLikeIdentity(i) = pb.strategy?;
-    match identity::Name::from_hostname(i.name.as_bytes()) {
+    match identity::Name::parse(i.name.as_bytes()) {
         Ok(i) => Some(i),
         Err(_) => {
             warn!("Ignoring invalid identity: {}", i.name);
@@ -319,6 +487,16 @@ fn pb_to_id(pb: TlsIdentity) -> Option<identity::Name> {
     }
 }
 
+fn pb_to_server_name(name: String) -> Option<identity::Name> {
+    match identity::Name::from_hostname(name.as_bytes()) {
+        Ok(n) => Some(n),
+        Err(_) => {
+            warn!("Ignoring invalid TLS server name: {}", name);
+            None
+        }
+    }
+}
+
 fn pb_to_sock_addr(pb: TcpAddress) -> Option<SocketAddr> {
     use api::net::ip_address::Ip;
     use std::net::{Ipv4Addr, Ipv6Addr};
@@ -370,3 +548,66 @@ fn pb_to_sock_addr(pb: TcpAddress) -> Option<SocketAddr> {
         None => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{
+        destination::tls_identity::{DnsLikeIdentity, Strategy},
+        net::{ip_address::Ip, IpAddress},
+    };
+
+    const ID: &str = "foo.ns.serviceaccount.identity.linkerd.cluster.local";
+
+    fn pb_addr(server_name: &str) -> WeightedAddr {
+        let mut metric_labels = HashMap::new();
+        metric_labels.insert("pod".to_owned(), "foo-0".to_owned());
+        metric_labels.insert(SERVER_NAME_LABEL.to_owned(), server_name.to_owned());
+        WeightedAddr {
+            addr: Some(TcpAddress {
+                ip: Some(IpAddress {
+                    ip: Some(Ip::Ipv4(0x0a01_0101)),
+                }),
+                port: 8080,
+            }),
+            metric_labels,
+            tls_identity: Some(TlsIdentity {
+                strategy: Some(Strategy::DnsLikeIdentity(DnsLikeIdentity {
+                    name: ID.to_owned(),
+                })),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn server_name_label_overrides_sni() {
+        let (addr, meta) = pb_to_addr_meta(pb_addr("web.example.com"), &HashMap::new())
+            .expect("address must be valid");
+        assert_eq!(addr, "10.1.1.1:8080".parse::<SocketAddr>().unwrap());
+        let id = identity::Name::from_hostname(ID.as_bytes()).unwrap();
+        assert_eq!(meta.identity(), Some(&id));
+        let sni = identity::Name::from_hostname(b"web.example.com").unwrap();
+        assert_eq!(meta.server_name(), Some(&sni));
+        // The label is consumed rather than exposed as a metric label.
+        assert_eq!(meta.labels().get(SERVER_NAME_LABEL), None);
+        assert_eq!(meta.labels().get("pod").map(String::as_str), Some("foo-0"));
+    }
+
+    #[test]
+    fn invalid_server_name_label_is_ignored() {
+        for name in &[
+            "web.example.com.",
+            "not a name",
+            "spiffe://cluster.local/ns/foo",
+        ] {
+            assert_eq!(pb_to_server_name(name.to_string()), None);
+
+            let (_, meta) =
+                pb_to_addr_meta(pb_addr(name), &HashMap::new()).expect("address must be valid");
+            assert_eq!(meta.server_name(), None);
+            assert!(meta.identity().is_some(), "identity must be kept");
+            assert_eq!(meta.labels().get(SERVER_NAME_LABEL), None);
+        }
+    }
+}
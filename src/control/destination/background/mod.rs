@@ -5,15 +5,18 @@ use std::{
         VecDeque,
     },
     mem,
+    path::PathBuf,
     sync::Arc,
-    time::Instant,
+    time::{Duration, Instant},
 };
+use tokio_timer::Delay;
 use tower_grpc::{self as grpc, generic::client::GrpcService, BoxBody};
 
 use api::destination::client::Destination;
 use api::destination::{GetDestination, Update as PbUpdate};
 
-use super::{ResolveRequest, Update};
+use super::{Metrics, ResolveRequest, Update};
+use backoff::Backoff;
 use control::{
     cache::Exists,
     remote_stream::{Receiver, Remote},
@@ -22,12 +25,18 @@ use dns;
 use NameAddr;
 
 mod destination_set;
+mod persist;
 
 use self::destination_set::DestinationSet;
 
 type ActiveQuery<T> = Remote<PbUpdate, T>;
 type UpdateRx<T> = Receiver<PbUpdate, T>;
 
+/// Bounds the backoff between reconnects of a Destination service stream that
+/// fails without providing an update.
+const RECONNECT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
 /// Satisfies resolutions as requested via `request_rx`.
 ///
 /// As the `Background` is polled with a client to Destination service, if the client to the
@@ -41,6 +50,12 @@ where
     new_query: NewQuery,
     dns_resolver: dns::Resolver,
     dsts: DestinationCache<T>,
+    /// Persists the endpoints of each destination, when configured.
+    persist: Option<persist::Store>,
+    /// When set, destinations that the Destination service has not resolved
+    /// within this timeout are resolved via DNS instead.
+    dns_fallback_timeout: Option<Duration>,
+    metrics: Metrics,
     /// The Destination.Get RPC client service.
     /// Each poll, records whether the rpc service was till ready.
     rpc_ready: bool,
@@ -58,6 +73,11 @@ where
     destinations: HashMap<NameAddr, DestinationSet<T>>,
     /// A queue of authorities that need to be reconnected.
     reconnects: VecDeque<NameAddr>,
+    /// A queue of authorities that are waiting for query capacity.
+    queued: VecDeque<NameAddr>,
+    /// When set, destinations without responders are retained for this long,
+    /// so that their resolution may be shared by a later request.
+    idle_timeout: Option<Duration>,
 }
 
 /// The configurationn necessary to create a new Destination service
@@ -96,11 +116,18 @@ where
         suffixes: Vec<dns::Suffix>,
         concurrency_limit: usize,
         context_token: String,
+        cache_path: Option<PathBuf>,
+        dns_fallback_timeout: Option<Duration>,
+        idle_timeout: Option<Duration>,
+        metrics: Metrics,
     ) -> Self {
         Self {
             new_query: NewQuery::new(suffixes, concurrency_limit, context_token),
             dns_resolver,
-            dsts: DestinationCache::new(),
+            dsts: DestinationCache::new(idle_timeout),
+            persist: cache_path.map(persist::Store::load),
+            dns_fallback_timeout,
+            metrics,
             rpc_ready: false,
             request_rx,
         }
@@ -111,6 +138,9 @@ where
         // in `poll_destinations` while the `rpc` service is ready should
         // be reconnected now, otherwise the task would just sleep...
         loop {
+            if let Some(ref mut persist) = self.persist {
+                persist.poll_flush();
+            }
             if let Async::Ready(()) = self.poll_resolve_requests(client) {
                 // request_rx has closed, meaning the main thread is terminating.
                 return Ok(Async::Ready(()));
@@ -118,7 +148,9 @@ where
             self.dsts.retain_active();
             self.poll_destinations();
 
-            if self.dsts.reconnects.is_empty() || !self.rpc_ready {
+            // Queries that were dropped may have made room for queued ones.
+            let startable = !self.dsts.queued.is_empty() && self.new_query.has_more_queries();
+            if (self.dsts.reconnects.is_empty() && !startable) || !self.rpc_ready {
                 return Ok(Async::NotReady);
             }
         }
@@ -127,24 +159,26 @@ where
     fn poll_resolve_requests(&mut self, client: &mut Option<T>) -> Async<()> {
         loop {
             if let Some(client) = client {
-                // if rpc service isn't ready, not much we can do...
+                // if rpc service isn't ready, not much we can do, unless new
+                // watches may fall back to DNS in the meantime...
                 match client.poll_ready() {
                     Ok(Async::Ready(())) => {
                         self.rpc_ready = true;
                     }
                     Ok(Async::NotReady) => {
                         self.rpc_ready = false;
-                        return Async::NotReady;
                     }
                     Err(err) => {
                         warn!("Destination.Get poll_ready error: {:?}", err.into());
                         self.rpc_ready = false;
-                        return Async::NotReady;
                     }
                 }
+                if !self.rpc_ready && self.dns_fallback_timeout.is_none() {
+                    return Async::NotReady;
+                }
 
-                // handle any pending reconnects first
-                if self.poll_reconnect(client) {
+                // handle any pending reconnects and queued queries first
+                if self.rpc_ready && (self.poll_reconnect(client) || self.poll_queued(client)) {
                     continue;
                 }
             }
@@ -156,6 +190,8 @@ where
 
                     let new_query = &self.new_query;
                     let dsts = &mut self.dsts;
+                    let rpc_ready = self.rpc_ready;
+                    let dns_fallback_timeout = self.dns_fallback_timeout;
 
                     // If the requested authority currently needs more
                     // query capacity to query the destination service, go
@@ -184,7 +220,7 @@ where
                                 Exists::No | Exists::Unknown => (),
                             }
 
-                            if occ.get().needs_query_capacity() {
+                            if rpc_ready && occ.get().needs_query_capacity() {
                                 trace!("--> {:?} wants to query Destination", occ.key());
                                 let query = new_query.query_destination_service_if_relevant(
                                     client.as_mut(),
@@ -197,22 +233,66 @@ where
                             occ.get_mut().responders.push(resolve.responder);
                         }
                         Entry::Vacant(vac) => {
-                            let query = new_query.query_destination_service_if_relevant(
-                                client.as_mut(),
-                                vac.key(),
-                                "connect",
-                            );
+                            // If the Destination service isn't ready, the query
+                            // is started once it is, like a reconnect.
+                            let awaiting_client =
+                                !rpc_ready && client.is_some() && new_query.is_relevant(vac.key());
+                            let query = if awaiting_client {
+                                dsts.reconnects.push_back(vac.key().clone());
+                                DestinationServiceQuery::Inactive
+                            } else {
+                                new_query.query_destination_service_if_relevant(
+                                    client.as_mut(),
+                                    vac.key(),
+                                    "connect",
+                                )
+                            };
                             let mut set = DestinationSet {
                                 addrs: Exists::Unknown,
                                 query,
                                 dns_query: None,
                                 responders: vec![resolve.responder],
+                                modified: false,
+                                dns_fallback_timeout: None,
+                                dns_fallback: false,
+                                reconnect_backoff: Backoff::new(
+                                    RECONNECT_MIN_BACKOFF,
+                                    RECONNECT_MAX_BACKOFF,
+                                ),
+                                reconnect_delay: None,
+                                stale: false,
+                                idle: None,
                             };
+                            // Rather than resolving the authority via DNS, wait for
+                            // query capacity.
+                            if set.needs_query_capacity() {
+                                dsts.queued.push_back(vac.key().clone());
+                                self.metrics.resolution_queued();
+                            }
+                            let awaiting_destination = set.query.is_active()
+                                || set.needs_query_capacity()
+                                || awaiting_client;
+                            if awaiting_destination {
+                                set.start_dns_fallback_timeout(dns_fallback_timeout);
+                            }
+                            // While the Destination service has yet to respond,
+                            // serve the endpoints known to a previous process.
+                            if awaiting_destination {
+                                let stale = self.persist.as_ref().and_then(|p| p.get(vac.key()));
+                                if let Some(eps) = stale {
+                                    debug!(
+                                        "using {} stale endpoints for {:?}",
+                                        eps.len(),
+                                        vac.key()
+                                    );
+                                    set.add_stale(vac.key(), eps.iter().cloned());
+                                }
+                            }
                             // If the authority is one for which the Destination service is never
                             // relevant (e.g. an absolute name that doesn't end in ".svc.$zone." in
                             // Kubernetes), or if we don't have a `client`, then immediately start
                             // polling DNS.
-                            if !set.query.is_active() {
+                            if !awaiting_destination {
                                 set.reset_dns_query(&self.dns_resolver, Instant::now(), vac.key());
                             }
                             vac.insert(set);
@@ -240,7 +320,12 @@ where
                     &auth,
                     "reconnect",
                 );
-                return !set.needs_query_capacity();
+                if set.needs_query_capacity() {
+                    self.dsts.queued.push_back(auth);
+                    self.metrics.resolution_queued();
+                    return false;
+                }
+                return true;
             } else {
                 trace!("reconnect no longer needed: {:?}", auth);
             }
@@ -248,6 +333,31 @@ where
         false
     }
 
+    /// Tries to start the next query that was queued for lack of capacity.
+    /// Returns true if a query started.
+    fn poll_queued(&mut self, client: &mut T) -> bool {
+        debug_assert!(self.rpc_ready);
+
+        while self.new_query.has_more_queries() {
+            let auth = match self.dsts.queued.pop_front() {
+                Some(auth) => auth,
+                None => return false,
+            };
+            if let Some(set) = self.dsts.destinations.get_mut(&auth) {
+                if set.needs_query_capacity() {
+                    set.query = self.new_query.query_destination_service_if_relevant(
+                        Some(client),
+                        &auth,
+                        "connect (previously at capacity)",
+                    );
+                    return true;
+                }
+            }
+            trace!("queued query no longer needed: {:?}", auth);
+        }
+        false
+    }
+
     fn poll_destinations(&mut self) {
         for (auth, set) in &mut self.dsts.destinations {
             // Query the Destination service first.
@@ -257,38 +367,68 @@ where
                         set.poll_destination_service(auth, rx);
                     if let Remote::NeedsReconnect = new_query {
                         set.reset_on_next_modification();
-                        self.dsts.reconnects.push_back(auth.clone());
+                        set.schedule_reconnect(auth);
+                        self.metrics.stream_reset();
+                        // Don't wait out the timeout for a query that has
+                        // already failed.
+                        let fallback_now =
+                            self.dns_fallback_timeout.map(|_| Duration::from_secs(0));
+                        set.start_dns_fallback_timeout(fallback_now);
                     }
                     (new_query.into(), found_by_destination_service)
                 }
                 query => (query, Exists::Unknown),
             };
             set.query = new_query;
+            if set.poll_reconnect_delay() {
+                self.dsts.reconnects.push_back(auth.clone());
+            }
 
             // Any active response from the Destination service cancels the DNS query except for a
             // positive assertion that the service doesn't exist.
             //
             // Any disconnection from the Destination service has no effect on the DNS query; we
-            // assume that if we were querying DNS before, we should continue to do so, and if we
-            // weren't querying DNS then we shouldn't start now. In particular, temporary
-            // disruptions of connectivity to the Destination service do not cause a fallback to
-            // DNS.
+            // assume that if we were querying DNS before, we should continue to do so. If we
+            // weren't querying DNS, we only start to when a DNS fallback timeout is configured
+            // and the Destination service has yet to resolve the destination. In that case, the
+            // endpoints are marked as such until the Destination service responds.
             match found_by_destination_service {
                 Exists::Yes(()) => {
                     // Stop polling DNS on any active update from the Destination service.
+                    set.end_dns_fallback();
                     set.dns_query = None;
                 }
                 Exists::No => {
                     // Fall back to DNS.
+                    set.end_dns_fallback();
                     set.reset_dns_query(&self.dns_resolver, Instant::now(), auth);
                 }
                 Exists::Unknown => (), // No change from Destination service's perspective.
             }
+            set.poll_dns_fallback(&self.dns_resolver, auth);
 
             // Poll DNS after polling the Destination service. This may reset the DNS query but it
             // won't affect the Destination Service query.
             set.poll_dns(&self.dns_resolver, auth);
+
+            if set.modified {
+                set.modified = false;
+                if let Some(ref mut persist) = self.persist {
+                    match set.addrs {
+                        Exists::Yes(ref cache) if set.query.is_active() && !set.dns_fallback => {
+                            persist.record(auth, cache)
+                        }
+                        Exists::No => persist.forget(auth),
+                        _ => (),
+                    }
+                }
+            }
         }
+
+        let stale = self.dsts.destinations.values().filter(|s| s.stale).count();
+        self.metrics.set_stale_resolutions(stale);
+        self.metrics
+            .set_active_queries(self.new_query.active_queries());
     }
 }
 
@@ -304,10 +444,20 @@ impl NewQuery {
         }
     }
 
+    /// Returns true if the Destination service should be queried for `dst`.
+    fn is_relevant(&self, dst: &NameAddr) -> bool {
+        self.suffixes.iter().any(|s| s.contains(dst.name()))
+    }
+
+    /// Returns the number of currently-active queries.
+    fn active_queries(&self) -> usize {
+        Arc::weak_count(&self.active_query_handle)
+    }
+
     /// Returns true if there is currently capacity for additional
     /// Destination service queries.
     fn has_more_queries(&self) -> bool {
-        Arc::weak_count(&self.active_query_handle) < self.concurrency_limit
+        self.active_queries() < self.concurrency_limit
     }
 
     /// Attepts to initiate a query `query` to the Destination service
@@ -336,7 +486,7 @@ impl NewQuery {
         T: GrpcService<BoxBody>,
     {
         trace!("DestinationServiceQuery {} {:?}", connect_or_reconnect, dst);
-        if !self.suffixes.iter().any(|s| s.contains(dst.name())) {
+        if !self.is_relevant(dst) {
             debug!("dst={} not in suffixes", dst.name());
             return DestinationServiceQuery::Inactive;
         }
@@ -381,10 +531,12 @@ impl<T> DestinationCache<T>
 where
     T: GrpcService<BoxBody>,
 {
-    fn new() -> Self {
+    fn new(idle_timeout: Option<Duration>) -> Self {
         Self {
             destinations: HashMap::new(),
             reconnects: VecDeque::new(),
+            queued: VecDeque::new(),
+            idle_timeout,
         }
     }
 
@@ -400,11 +552,28 @@ where
 
     /// Ensures that `destinations` is updated to only maintain active resolutions.
     ///
-    /// If there are no active resolutions for a destination, the destination is removed.
+    /// If there are no active resolutions for a destination, the destination is removed once it
+    /// has been idle for `idle_timeout`, or immediately if other destinations are waiting for
+    /// query capacity.
     fn retain_active(&mut self) {
+        let idle_timeout = self.idle_timeout;
+        let evict_idle = !self.queued.is_empty();
         self.destinations.retain(|_, ref mut dst| {
-            dst.responders.retain(|r| r.is_active());
-            dst.responders.len() > 0
+            // Responders must be polled to learn whether they're active, which
+            // `Vec::retain` does not allow.
+            let mut i = 0;
+            while i < dst.responders.len() {
+                if dst.responders[i].is_active() {
+                    i += 1;
+                } else {
+                    dst.responders.swap_remove(i);
+                }
+            }
+            if dst.responders.len() > 0 {
+                dst.idle = None;
+                return true;
+            }
+            !evict_idle && dst.poll_idle(idle_timeout)
         });
     }
 }
@@ -0,0 +1,279 @@
+//! Persists the last-known endpoints of each destination to disk.
+//!
+//! When a proxy restarts while the Destination service is unavailable, it
+//! would otherwise be unable to resolve any destinations. Instead, the
+//! endpoints that were last discovered for each destination are loaded from the
+//! cache file and served as stale until the first update for the destination
+//! replaces them.
+//!
+//! The file holds a sequence of length-delimited protobuf records: a
+//! `GetDestination` naming each destination, followed by an `Update` with all of
+//! its endpoints.
+
+use bytes::Buf;
+use futures::{Async, Stream};
+use prost::Message;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Cursor};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_timer::{clock, Interval};
+
+use api::destination::{
+    protocol_hint::{Protocol, H2},
+    tls_identity::{DnsLikeIdentity, Strategy},
+    update::Update as PbUpdate2,
+    GetDestination, ProtocolHint as PbProtocolHint, TlsIdentity, Update as PbUpdate, WeightedAddr,
+    WeightedAddrSet,
+};
+use api::net::{ip_address::Ip, IPv6, IpAddress, TcpAddress};
+
+use super::destination_set::{pb_to_addr_meta, SERVER_NAME_LABEL};
+use control::cache::Cache;
+use control::destination::{Metadata, ProtocolHint};
+use NameAddr;
+
+/// How often modified endpoints are written to disk.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+pub(super) struct Store {
+    path: PathBuf,
+    endpoints: HashMap<NameAddr, Vec<(SocketAddr, Metadata)>>,
+    /// Set when `endpoints` has been modified since it was last written.
+    dirty: bool,
+    flush: Interval,
+}
+
+// === impl Store ===
+
+impl Store {
+    /// Loads the endpoints stored at `path`, if any.
+    pub fn load(path: PathBuf) -> Self {
+        let endpoints = match fs::read(&path) {
+            Ok(buf) => match decode(&buf) {
+                Ok(endpoints) => {
+                    info!(
+                        "loaded {} destinations from {}",
+                        endpoints.len(),
+                        path.display()
+                    );
+                    endpoints
+                }
+                Err(e) => {
+                    warn!("ignoring invalid cache {}: {}", path.display(), e);
+                    HashMap::new()
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("failed to read cache {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path,
+            endpoints,
+            dirty: false,
+            flush: Interval::new(clock::now() + FLUSH_INTERVAL, FLUSH_INTERVAL),
+        }
+    }
+
+    /// Returns the last-known endpoints for `auth`.
+    pub fn get(&self, auth: &NameAddr) -> Option<&[(SocketAddr, Metadata)]> {
+        self.endpoints.get(auth).map(|eps| eps.as_slice())
+    }
+
+    /// Records the current endpoints for `auth`.
+    pub fn record(&mut self, auth: &NameAddr, cache: &Cache<SocketAddr, Metadata>) {
+        let eps = cache
+            .into_iter()
+            .map(|(addr, meta)| (*addr, meta.clone()))
+            .collect::<Vec<_>>();
+        if self.endpoints.get(auth) != Some(&eps) {
+            self.endpoints.insert(auth.clone(), eps);
+            self.dirty = true;
+        }
+    }
+
+    /// Forgets the endpoints for a destination that does not exist.
+    pub fn forget(&mut self, auth: &NameAddr) {
+        if self.endpoints.remove(auth).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the endpoints to disk, if they have changed, whenever the flush
+    /// interval elapses.
+    pub fn poll_flush(&mut self) {
+        loop {
+            match self.flush.poll() {
+                Ok(Async::Ready(_)) => {}
+                Ok(Async::NotReady) => return,
+                Err(e) => {
+                    warn!("destination cache timer failed: {}", e);
+                    return;
+                }
+            }
+
+            if self.dirty {
+                match self.write() {
+                    Ok(()) => self.dirty = false,
+                    Err(e) => warn!("failed to write cache {}: {}", self.path.display(), e),
+                }
+            }
+        }
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let buf = encode(&self.endpoints)?;
+        // Write to a temporary file first so that a partially-written cache is
+        // never loaded.
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, &buf)?;
+        fs::rename(&tmp, &self.path)?;
+        trace!(
+            "wrote {} destinations to {}",
+            self.endpoints.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+}
+
+fn encode(endpoints: &HashMap<NameAddr, Vec<(SocketAddr, Metadata)>>) -> io::Result<Vec<u8>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e));
+
+    let mut buf = Vec::new();
+    for (auth, eps) in endpoints {
+        let dst = GetDestination {
+            // The name is written as-is, so that absolute names keep their
+            // trailing dot.
+            path: format!("{}:{}", auth.name(), auth.port()),
+            ..Default::default()
+        };
+        let update = PbUpdate {
+            update: Some(PbUpdate2::Add(WeightedAddrSet {
+                addrs: eps
+                    .iter()
+                    .map(|&(addr, ref meta)| addr_meta_to_pb(addr, meta))
+                    .collect(),
+                metric_labels: HashMap::new(),
+            })),
+        };
+        dst.encode_length_delimited(&mut buf).map_err(invalid)?;
+        update.encode_length_delimited(&mut buf).map_err(invalid)?;
+    }
+    Ok(buf)
+}
+
+fn decode(buf: &[u8]) -> io::Result<HashMap<NameAddr, Vec<(SocketAddr, Metadata)>>> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e));
+
+    let mut endpoints = HashMap::new();
+    let mut buf = Cursor::new(buf);
+    while buf.has_remaining() {
+        let dst = GetDestination::decode_length_delimited(&mut buf).map_err(invalid)?;
+        let update = PbUpdate::decode_length_delimited(&mut buf).map_err(invalid)?;
+
+        let auth = NameAddr::from_str(&dst.path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid destination {}: {:?}", dst.path, e),
+            )
+        })?;
+        let eps = match update.update {
+            Some(PbUpdate2::Add(set)) => {
+                let set_labels = set.metric_labels;
+                set.addrs
+                    .into_iter()
+                    .filter_map(|pb| pb_to_addr_meta(pb, &set_labels))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        endpoints.insert(auth, eps);
+    }
+    Ok(endpoints)
+}
+
+fn addr_meta_to_pb(addr: SocketAddr, meta: &Metadata) -> WeightedAddr {
+    let mut metric_labels = meta
+        .labels()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect::<HashMap<_, _>>();
+    if let Some(name) = meta.server_name() {
+        metric_labels.insert(SERVER_NAME_LABEL.to_owned(), name.as_ref().to_owned());
+    }
+
+    let tls_identity = meta.identity().map(|id| TlsIdentity {
+        strategy: Some(Strategy::DnsLikeIdentity(DnsLikeIdentity {
+            name: id.as_ref().to_owned(),
+        })),
+    });
+
+    let protocol_hint = match meta.protocol_hint() {
+        ProtocolHint::Http2 => Some(PbProtocolHint {
+            protocol: Some(Protocol::H2(H2 {})),
+        }),
+        ProtocolHint::Unknown => None,
+    };
+
+    WeightedAddr {
+        addr: Some(sock_addr_to_pb(addr)),
+        metric_labels,
+        tls_identity,
+        protocol_hint,
+        ..Default::default()
+    }
+}
+
+fn sock_addr_to_pb(addr: SocketAddr) -> TcpAddress {
+    let ip = match addr.ip() {
+        IpAddr::V4(v4) => Ip::Ipv4(v4.into()),
+        IpAddr::V6(v6) => {
+            let o = v6.octets();
+            let u64_from = |o: &[u8]| o.iter().fold(0u64, |n, b| (n << 8) | u64::from(*b));
+            Ip::Ipv6(IPv6 {
+                first: u64_from(&o[..8]),
+                last: u64_from(&o[8..]),
+            })
+        }
+    };
+    TcpAddress {
+        ip: Some(IpAddress { ip: Some(ip) }),
+        port: u32::from(addr.port()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use identity;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn round_trips_endpoints() {
+        let mut labels = IndexMap::new();
+        labels.insert("pod".to_owned(), "foo-0".to_owned());
+        let id =
+            identity::Name::from_hostname(b"foo.ns.serviceaccount.identity.linkerd.cluster.local")
+                .unwrap();
+        let meta = Metadata::new(labels, ProtocolHint::Http2, Some(id));
+
+        let mut endpoints = HashMap::new();
+        endpoints.insert(
+            NameAddr::from_str("foo.ns.svc.cluster.local.:8080").unwrap(),
+            vec![
+                ("10.1.1.1:8080".parse().unwrap(), meta),
+                ("[fd00::1]:8080".parse().unwrap(), Metadata::empty()),
+            ],
+        );
+
+        let buf = encode(&endpoints).unwrap();
+        assert_eq!(decode(&buf).unwrap(), endpoints);
+    }
+}
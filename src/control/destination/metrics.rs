@@ -0,0 +1,120 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use metrics::{Counter, FmtMetric, FmtMetrics, Gauge};
+
+metrics! {
+    destination_stream_resets_total: Counter {
+        "Total count of Destination service streams that ended or failed and were reconnected"
+    },
+    destination_stale_resolutions: Gauge {
+        "Number of destinations whose last-known endpoints are served until the Destination service updates them"
+    },
+    router_active_destination_queries: Gauge {
+        "Number of destinations that are currently resolved by the Destination service"
+    },
+    destination_resolutions_queued_total: Counter {
+        "Total count of resolutions that were queued because the maximum number of destinations were being resolved"
+    }
+}
+
+pub fn new() -> (Metrics, Report) {
+    let inner = Arc::new(Mutex::new(Inner::default()));
+    (Metrics(inner.clone()), Report(inner))
+}
+
+/// Records the health of Destination service resolutions.
+#[derive(Clone, Debug)]
+pub struct Metrics(Arc<Mutex<Inner>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted Destination
+/// service resolution metrics.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Inner>>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    stream_resets: Counter,
+    stale_resolutions: Gauge,
+    active_queries: Gauge,
+    resolutions_queued: Counter,
+}
+
+// === impl Metrics ===
+
+impl Metrics {
+    pub(super) fn stream_reset(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.stream_resets.incr();
+        }
+    }
+
+    pub(super) fn set_stale_resolutions(&self, stale: usize) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.stale_resolutions = Gauge::from(stale as u64);
+        }
+    }
+
+    pub(super) fn set_active_queries(&self, active: usize) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.active_queries = Gauge::from(active as u64);
+        }
+    }
+
+    pub(super) fn resolution_queued(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.resolutions_queued.incr();
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        destination_stream_resets_total.fmt_help(f)?;
+        inner
+            .stream_resets
+            .fmt_metric(f, destination_stream_resets_total.name)?;
+
+        destination_stale_resolutions.fmt_help(f)?;
+        inner
+            .stale_resolutions
+            .fmt_metric(f, destination_stale_resolutions.name)?;
+
+        router_active_destination_queries.fmt_help(f)?;
+        inner
+            .active_queries
+            .fmt_metric(f, router_active_destination_queries.name)?;
+
+        destination_resolutions_queued_total.fmt_help(f)?;
+        inner
+            .resolutions_queued
+            .fmt_metric(f, destination_resolutions_queued_total.name)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_resets_and_stale_resolutions() {
+        let (metrics, report) = new();
+        metrics.stream_reset();
+        metrics.stream_reset();
+        metrics.set_stale_resolutions(3);
+        metrics.set_stale_resolutions(1);
+
+        let out = format!("{}", report.as_display());
+        assert!(out.contains("destination_stream_resets_total 2\n"));
+        assert!(out.contains("destination_stale_resolutions 1\n"));
+    }
+}
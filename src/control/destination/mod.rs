@@ -26,16 +26,20 @@
 
 use futures::{future, sync::mpsc, Async, Future, Poll, Stream};
 use indexmap::IndexMap;
-use std::sync::{Arc, Weak};
+use std::path::PathBuf;
+use std::time::Duration;
 use tower_grpc::{generic::client::GrpcService, BoxBody};
 
 use dns;
 use identity;
 use proxy::resolve::{self, Resolve, Update};
+use task;
 
 pub mod background;
+pub mod metrics;
 
 use self::background::Background;
+pub use self::metrics::Metrics;
 use NameAddr;
 
 /// A handle to request resolutions from the background discovery task.
@@ -57,8 +61,8 @@ struct Responder {
     /// Sends updates from the controller to a `Resolution`.
     update_tx: mpsc::UnboundedSender<Update<Metadata>>,
 
-    /// Indicates whether the corresponding `Resolution` is still active.
-    active: Weak<()>,
+    /// Completes when the corresponding `Resolution` is dropped.
+    canceled: task::Canceled,
 }
 
 #[derive(Debug)]
@@ -66,11 +70,9 @@ pub struct Resolution {
     /// Receives updates from the controller.
     update_rx: mpsc::UnboundedReceiver<Update<Metadata>>,
 
-    /// Allows `Responder` to detect when its `Resolution` has been lost.
-    ///
-    /// `Responder` holds a weak reference to this `Arc` and can determine when this
-    /// reference has been dropped.
-    _active: Arc<()>,
+    /// Notifies the background task, via its `Responder`, when the
+    /// `Resolution` is dropped, so that the query may be canceled.
+    _active: task::Handle,
 }
 
 /// Metadata describing an endpoint.
@@ -85,6 +87,14 @@ pub struct Metadata {
 
     /// How to verify TLS for the endpoint.
     identity: Option<identity::Name>,
+
+    /// Overrides the SNI name sent when originating TLS to the endpoint, e.g.
+    /// for endpoints that share a certificate behind a gateway.
+    server_name: Option<identity::Name>,
+
+    /// Set when the endpoint was resolved via DNS because the Destination
+    /// service was unavailable.
+    dns_fallback: bool,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -101,19 +111,48 @@ pub enum ProtocolHint {
 /// The `Resolver` is used by a listener to request resolutions, while
 /// the background future is executed on the controller thread's executor
 /// to drive the background task.
+///
+/// When `cache_path` is set, the endpoints of each destination are persisted
+/// there, so that they may be used by a subsequent process before it is able
+/// to reach the Destination service.
+///
+/// When `dns_fallback_timeout` is set, a destination that the Destination
+/// service has not resolved within that timeout (or whose query fails) is
+/// resolved via DNS until the Destination service responds.
+///
+/// Destination service streams that end or fail are reconnected with a
+/// backoff, during which the destination's last-known endpoints are served.
+///
+/// Concurrent resolutions of the same destination share a single query. When
+/// `idle_timeout` is set, the query outlives its last resolution by that
+/// timeout, so that it may be shared by a subsequent resolution.
 pub fn new<T>(
     mut client: Option<T>,
     dns_resolver: dns::Resolver,
     suffixes: Vec<dns::Suffix>,
     concurrency_limit: usize,
     proxy_id: String,
+    cache_path: Option<PathBuf>,
+    dns_fallback_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    metrics: Metrics,
 ) -> (Resolver, impl Future<Item = (), Error = ()>)
 where
     T: GrpcService<BoxBody>,
 {
     let (request_tx, rx) = mpsc::unbounded();
     let disco = Resolver { request_tx };
-    let mut bg = Background::new(rx, dns_resolver, suffixes, concurrency_limit, proxy_id);
+    let mut bg = Background::new(
+        rx,
+        dns_resolver,
+        suffixes,
+        concurrency_limit,
+        proxy_id,
+        cache_path,
+        dns_fallback_timeout,
+        idle_timeout,
+        metrics,
+    );
     let task = future::poll_fn(move || bg.poll_rpc(&mut client));
     (disco, task)
 }
@@ -128,14 +167,14 @@ impl Resolve<NameAddr> for Resolver {
     fn resolve(&self, authority: &NameAddr) -> Resolution {
         trace!("resolve; authority={:?}", authority);
         let (update_tx, update_rx) = mpsc::unbounded();
-        let active = Arc::new(());
+        let (active, canceled) = task::cancelable();
         let req = {
             let authority = authority.clone();
             ResolveRequest {
                 authority,
                 responder: Responder {
                     update_tx,
-                    active: Arc::downgrade(&active),
+                    canceled,
                 },
             }
         };
@@ -163,8 +202,11 @@ impl resolve::Resolution for Resolution {
 // ===== impl Responder =====
 
 impl Responder {
-    fn is_active(&self) -> bool {
-        self.active.upgrade().is_some()
+    /// Returns false once the `Resolution` has been dropped.
+    ///
+    /// If it has not been, the current task is notified when it is.
+    fn is_active(&mut self) -> bool {
+        !self.canceled.poll_canceled()
     }
 }
 
@@ -176,6 +218,17 @@ impl Metadata {
             labels: IndexMap::default(),
             protocol_hint: ProtocolHint::Unknown,
             identity: None,
+            server_name: None,
+            dns_fallback: false,
+        }
+    }
+
+    /// Describes an endpoint that was resolved via DNS because the
+    /// Destination service was unavailable.
+    pub fn dns_fallback() -> Self {
+        Self {
+            dns_fallback: true,
+            ..Self::empty()
         }
     }
 
@@ -188,6 +241,15 @@ impl Metadata {
             labels,
             protocol_hint,
             identity,
+            server_name: None,
+            dns_fallback: false,
+        }
+    }
+
+    pub fn with_server_name(self, server_name: Option<identity::Name>) -> Self {
+        Self {
+            server_name,
+            ..self
         }
     }
 
@@ -203,4 +265,13 @@ impl Metadata {
     pub fn identity(&self) -> Option<&identity::Name> {
         self.identity.as_ref()
     }
+
+    /// Returns the SNI name to use instead of the endpoint's identity, if any.
+    pub fn server_name(&self) -> Option<&identity::Name> {
+        self.server_name.as_ref()
+    }
+
+    pub fn is_dns_fallback(&self) -> bool {
+        self.dns_fallback
+    }
 }
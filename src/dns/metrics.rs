@@ -0,0 +1,195 @@
+use indexmap::IndexMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio_timer::clock;
+
+use super::{Error, ResolveError, ResolveErrorKind};
+use metrics::{latency, Counter, FmtLabels, FmtMetric, FmtMetrics, Histogram};
+
+metrics! {
+    dns_resolutions_total: Counter {
+        "Total count of DNS resolutions, by result"
+    },
+    dns_resolution_duration_ms: Histogram<latency::Ms> {
+        "DNS resolution latency, for resolutions that completed"
+    },
+    dns_negative_cache_hits_total: Counter {
+        "Total count of lookups that failed immediately because the name was recently not found"
+    }
+}
+
+pub fn new() -> (Metrics, Report) {
+    let inner = Arc::new(Mutex::new(Inner::default()));
+    (Metrics(inner.clone()), Report(inner))
+}
+
+/// Records the results of DNS resolutions.
+#[derive(Clone, Debug)]
+pub struct Metrics(Arc<Mutex<Inner>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted DNS metrics.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Inner>>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    results: IndexMap<Outcome, Counter>,
+    duration: Histogram<latency::Ms>,
+    negative_cache_hits: Counter,
+}
+
+/// Labels `dns_resolutions_total` by result.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Outcome {
+    Ok,
+    /// The name exists, but has no addresses.
+    NotFound,
+    Error,
+    /// The resolution was dropped before it completed, e.g. because it timed
+    /// out.
+    Canceled,
+}
+
+/// Records the result of a single resolution when it completes.
+///
+/// If the resolution is dropped before a result is recorded, it is recorded
+/// as canceled.
+pub(super) struct Recorder {
+    metrics: Option<Metrics>,
+    start: Instant,
+}
+
+// === impl Metrics ===
+
+impl Metrics {
+    /// Counts a resolution, and records its latency if it completed.
+    fn record(&self, outcome: Outcome, start: Option<Instant>) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner
+                .results
+                .entry(outcome)
+                .or_insert_with(Counter::default)
+                .incr();
+            if let Some(start) = start {
+                inner.duration.add(clock::now() - start);
+            }
+        }
+    }
+
+    pub(super) fn negative_cache_hit(&self) {
+        if let Ok(mut inner) = self.0.lock() {
+            inner.negative_cache_hits.incr();
+        }
+    }
+}
+
+// === impl Outcome ===
+
+impl<'a> From<&'a ResolveError> for Outcome {
+    fn from(e: &'a ResolveError) -> Self {
+        match *e.kind() {
+            ResolveErrorKind::NoRecordsFound { .. } => Outcome::NotFound,
+            _ => Outcome::Error,
+        }
+    }
+}
+
+impl<'a> From<&'a Error> for Outcome {
+    fn from(e: &'a Error) -> Self {
+        match *e {
+            Error::NoAddressesFound => Outcome::NotFound,
+            Error::ResolutionFailed(ref e) => Outcome::from(e),
+        }
+    }
+}
+
+impl FmtLabels for Outcome {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outcome = match *self {
+            Outcome::Ok => "ok",
+            Outcome::NotFound => "not_found",
+            Outcome::Error => "error",
+            Outcome::Canceled => "canceled",
+        };
+        write!(f, "result=\"{}\"", outcome)
+    }
+}
+
+// === impl Recorder ===
+
+impl Recorder {
+    pub(super) fn new(metrics: Option<Metrics>) -> Self {
+        Recorder {
+            metrics,
+            start: clock::now(),
+        }
+    }
+
+    pub(super) fn record(&mut self, outcome: Outcome) {
+        if let Some(metrics) = self.metrics.take() {
+            metrics.record(outcome, Some(self.start));
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Some(metrics) = self.metrics.take() {
+            metrics.record(Outcome::Canceled, None);
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if inner.results.is_empty() {
+            return Ok(());
+        }
+
+        dns_resolutions_total.fmt_help(f)?;
+        for (outcome, count) in inner.results.iter() {
+            count.fmt_metric_labeled(f, dns_resolutions_total.name, outcome)?;
+        }
+
+        dns_resolution_duration_ms.fmt_help(f)?;
+        inner
+            .duration
+            .fmt_metric(f, dns_resolution_duration_ms.name)?;
+
+        dns_negative_cache_hits_total.fmt_help(f)?;
+        inner
+            .negative_cache_hits
+            .fmt_metric(f, dns_negative_cache_hits_total.name)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_canceled_resolutions() {
+        let (metrics, report) = new();
+
+        let mut ok = Recorder::new(Some(metrics.clone()));
+        ok.record(Outcome::Ok);
+        drop(ok);
+        drop(Recorder::new(Some(metrics)));
+
+        let inner = report.0.lock().unwrap();
+        let count = |r| inner.results.get(&r).map(|c| c.value());
+        assert_eq!(count(Outcome::Ok), Some(1));
+        assert_eq!(count(Outcome::Canceled), Some(1));
+        assert_eq!(count(Outcome::Error), None);
+    }
+}
@@ -3,27 +3,84 @@ extern crate untrusted;
 extern crate webpki;
 
 use self::trust_dns_resolver::{
-    config::ResolverConfig, lookup_ip::LookupIp, system_conf, AsyncResolver, BackgroundLookupIp,
+    config::{LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig},
+    lookup_ip::LookupIp,
+    proto::rr::Name as ProtoName,
+    system_conf, AsyncResolver, BackgroundLookupIp,
 };
 use convert::TryFrom;
 use futures::prelude::*;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{fmt, net};
 use tokio::timer::Delay;
 
+pub mod metrics;
 mod name;
+mod negative_cache;
 
+pub use self::metrics::Metrics;
 pub use self::name::{InvalidName, Name};
+use self::negative_cache::NegativeCache;
 pub use self::trust_dns_resolver::config::ResolverOpts;
 pub use self::trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
 
 #[derive(Clone)]
 pub struct Resolver {
     resolver: AsyncResolver,
+    metrics: Option<Metrics>,
+    negative_cache: Option<NegativeCache>,
+    ip_preference: IpPreference,
 }
 
 pub trait ConfigureResolver {
     fn configure_resolver(&self, &mut ResolverOpts);
+
+    /// Returns the nameservers to query instead of the system's, if any.
+    fn nameservers(&self) -> Option<&[net::SocketAddr]> {
+        None
+    }
+
+    /// Returns how the configured nameservers are queried.
+    fn transport(&self) -> Transport {
+        Transport::Plain
+    }
+
+    /// Returns the search path to use instead of the system's, if any.
+    fn search(&self) -> Option<&[Name]> {
+        None
+    }
+
+    /// Returns the address families that are resolved, and the order in which
+    /// their addresses are returned.
+    fn ip_preference(&self) -> IpPreference {
+        IpPreference::default()
+    }
+}
+
+/// Determines which address families names are resolved to.
+///
+/// When both families are resolved, addresses in the preferred family are
+/// returned first, so that connections are attempted to them first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IpPreference {
+    V4Only,
+    V6Only,
+    PreferV4,
+    PreferV6,
+}
+
+/// How queries are sent to the configured nameservers.
+///
+/// Encrypted transports authenticate nameservers by the given name, using the
+/// resolver's bundled webpki roots rather than the proxy's trust anchors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// Queries are sent over UDP, falling back to TCP.
+    Plain,
+    /// Queries are sent over TLS (RFC 7858).
+    Tls(Name),
+    /// Queries are sent over HTTPS (RFC 8484).
+    Https(Name),
 }
 
 #[derive(Debug)]
@@ -37,9 +94,27 @@ pub enum Response {
     DoesNotExist { retry_after: Option<Instant> },
 }
 
-pub struct IpAddrFuture(::logging::ContextualFuture<Ctx, BackgroundLookupIp>);
+pub struct IpAddrsFuture {
+    inner: Lookup,
+    recorder: metrics::Recorder,
+    ip_preference: IpPreference,
+}
+
+pub struct RefineFuture {
+    inner: Lookup,
+    recorder: metrics::Recorder,
+}
 
-pub struct RefineFuture(::logging::ContextualFuture<Ctx, BackgroundLookupIp>);
+/// Either a lookup that is in flight, or the failure of a recent lookup that
+/// was negatively cached.
+enum Lookup {
+    Pending {
+        future: ::logging::ContextualFuture<Ctx, BackgroundLookupIp>,
+        name: Name,
+        negative_cache: Option<NegativeCache>,
+    },
+    Cached(Option<ResolveError>),
+}
 
 pub type IpAddrListFuture = Box<Future<Item = Response, Error = ResolveError> + Send>;
 
@@ -71,6 +146,62 @@ impl fmt::Display for Suffix {
     }
 }
 
+impl Transport {
+    /// The port on which nameservers usually serve this transport.
+    pub fn default_port(&self) -> u16 {
+        match *self {
+            Transport::Plain => 53,
+            Transport::Tls(_) => 853,
+            Transport::Https(_) => 443,
+        }
+    }
+
+    fn name_server_configs(&self, socket_addr: net::SocketAddr) -> Vec<NameServerConfig> {
+        let (protocols, tls_dns_name) = match *self {
+            Transport::Plain => (vec![Protocol::Udp, Protocol::Tcp], None),
+            Transport::Tls(ref name) => (vec![Protocol::Tls], Some(name)),
+            Transport::Https(ref name) => (vec![Protocol::Https], Some(name)),
+        };
+        protocols
+            .into_iter()
+            .map(|protocol| NameServerConfig {
+                socket_addr,
+                protocol,
+                tls_dns_name: tls_dns_name.map(|n| n.without_trailing_dot().to_owned()),
+            })
+            .collect()
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Plain
+    }
+}
+
+impl IpPreference {
+    fn lookup_ip_strategy(&self) -> LookupIpStrategy {
+        match *self {
+            IpPreference::V4Only => LookupIpStrategy::Ipv4Only,
+            IpPreference::V6Only => LookupIpStrategy::Ipv6Only,
+            IpPreference::PreferV4 | IpPreference::PreferV6 => LookupIpStrategy::Ipv4AndIpv6,
+        }
+    }
+
+    /// Moves addresses in the preferred family ahead of the others, while
+    /// otherwise preserving their order.
+    fn sort(&self, ips: &mut Vec<net::IpAddr>) {
+        let prefer_ipv6 = *self == IpPreference::PreferV6;
+        ips.sort_by_key(|ip| ip.is_ipv6() != prefer_ipv6);
+    }
+}
+
+impl Default for IpPreference {
+    fn default() -> Self {
+        IpPreference::PreferV4
+    }
+}
+
 impl From<Name> for Suffix {
     fn from(n: Name) -> Self {
         Suffix::Name(n)
@@ -113,6 +244,9 @@ impl Resolver {
     /// Construct a new `Resolver` from environment variables and system
     /// configuration.
     ///
+    /// The system configuration is not read if `c` configures both the
+    /// nameservers and the search path.
+    ///
     /// # Returns
     ///
     /// Either a tuple containing a new `Resolver` and the background task to
@@ -123,11 +257,22 @@ impl Resolver {
     pub fn from_system_config_with<C: ConfigureResolver>(
         c: &C,
     ) -> Result<(Self, impl Future<Item = (), Error = ()> + Send), ResolveError> {
-        let (config, mut opts) = system_conf::read_system_conf()?;
+        let (config, mut opts) = match (c.nameservers(), c.search()) {
+            (Some(_), Some(_)) => (ResolverConfig::new(), ResolverOpts::default()),
+            _ => system_conf::read_system_conf()?,
+        };
+        let config = override_config(c, config);
+        let ip_preference = c.ip_preference();
+        opts.ip_strategy = ip_preference.lookup_ip_strategy();
         c.configure_resolver(&mut opts);
         trace!("DNS config: {:?}", &config);
         trace!("DNS opts: {:?}", &opts);
-        Ok(Self::new(config, opts))
+        let (resolver, background) = Self::new(config, opts);
+        let resolver = Resolver {
+            ip_preference,
+            ..resolver
+        };
+        Ok((resolver, background))
     }
 
     /// NOTE: It would be nice to be able to return a named type rather than
@@ -140,10 +285,33 @@ impl Resolver {
         // Disable Trust-DNS's caching.
         opts.cache_size = 0;
         let (resolver, background) = AsyncResolver::new(config, opts);
-        let resolver = Resolver { resolver };
+        let resolver = Resolver {
+            resolver,
+            metrics: None,
+            negative_cache: None,
+            ip_preference: IpPreference::default(),
+        };
         (resolver, background)
     }
 
+    /// Caches names that do not exist for `ttl`, so that `resolve_ips` and
+    /// `refine` fail immediately for them until it elapses.
+    pub fn with_negative_cache(self, ttl: Duration) -> Self {
+        Self {
+            negative_cache: Some(NegativeCache::new(ttl)),
+            ..self
+        }
+    }
+
+    /// Records the results and latencies of the resolutions done by
+    /// `resolve_ips` and `refine`.
+    pub fn with_metrics(self, metrics: Metrics) -> Self {
+        Self {
+            metrics: Some(metrics),
+            ..self
+        }
+    }
+
     pub fn resolve_all_ips(&self, deadline: Instant, name: &Name) -> IpAddrListFuture {
         let lookup = self.resolver.lookup_ip(name.as_ref());
 
@@ -169,9 +337,15 @@ impl Resolver {
         Box::new(::logging::context_future(Ctx(name.clone()), f))
     }
 
-    pub fn resolve_one_ip(&self, name: &Name) -> IpAddrFuture {
-        let f = self.resolver.lookup_ip(name.as_ref());
-        IpAddrFuture(::logging::context_future(Ctx(name.clone()), f))
+    /// Resolves all of the IP addresses for `name`, in the order returned by
+    /// the resolver.
+    pub fn resolve_ips(&self, name: &Name) -> IpAddrsFuture {
+        let (inner, recorder) = self.lookup(name);
+        IpAddrsFuture {
+            inner,
+            recorder,
+            ip_preference: self.ip_preference,
+        }
     }
 
     /// Attempts to refine `name` to a fully-qualified name.
@@ -182,11 +356,65 @@ impl Resolver {
     /// For example, a name like `web` may be refined to `web.example.com.`,
     /// depending on the DNS search path.
     pub fn refine(&self, name: &Name) -> RefineFuture {
+        let (inner, recorder) = self.lookup(name);
+        RefineFuture { inner, recorder }
+    }
+
+    /// Looks up `name`, unless it was recently found not to exist.
+    ///
+    /// Negatively cached lookups are not recorded as resolutions.
+    fn lookup(&self, name: &Name) -> (Lookup, metrics::Recorder) {
+        if let Some(e) = self.negative_cache.as_ref().and_then(|c| c.get(name)) {
+            trace!("negatively cached: {}", name);
+            if let Some(ref metrics) = self.metrics {
+                metrics.negative_cache_hit();
+            }
+            return (Lookup::Cached(Some(e)), metrics::Recorder::new(None));
+        }
+
         let f = self.resolver.lookup_ip(name.as_ref());
-        RefineFuture(::logging::context_future(Ctx(name.clone()), f))
+        let lookup = Lookup::Pending {
+            future: ::logging::context_future(Ctx(name.clone()), f),
+            name: name.clone(),
+            negative_cache: self.negative_cache.clone(),
+        };
+        (lookup, metrics::Recorder::new(self.metrics.clone()))
     }
 }
 
+/// Replaces the nameservers and search path of `config` with those configured
+/// by `c`, if any.
+fn override_config<C: ConfigureResolver>(c: &C, config: ResolverConfig) -> ResolverConfig {
+    if c.nameservers().is_none() && c.search().is_none() {
+        return config;
+    }
+
+    // The system's domain is only searched when its search path is used.
+    let (domain, search) = match c.search() {
+        Some(search) => {
+            let search = search
+                .iter()
+                .map(|n| ProtoName::from_ascii(n.as_ref()).expect("search name must be valid"))
+                .collect();
+            (None, search)
+        }
+        None => (config.domain().cloned(), config.search().to_vec()),
+    };
+
+    let name_servers = match c.nameservers() {
+        Some(addrs) => {
+            let transport = c.transport();
+            addrs
+                .iter()
+                .flat_map(|&addr| transport.name_server_configs(addr))
+                .collect::<Vec<_>>()
+        }
+        None => config.name_servers().to_vec(),
+    };
+
+    ResolverConfig::from_parts(domain, search, name_servers)
+}
+
 /// Note: `AsyncResolver` does not implement `Debug`, so we must manually
 ///       implement this.
 impl fmt::Debug for Resolver {
@@ -197,16 +425,56 @@ impl fmt::Debug for Resolver {
     }
 }
 
-impl Future for IpAddrFuture {
-    type Item = net::IpAddr;
+impl Future for Lookup {
+    type Item = LookupIp;
+    type Error = ResolveError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            Lookup::Pending {
+                ref mut future,
+                ref name,
+                ref negative_cache,
+            } => future.poll().map_err(|e| {
+                if let Some(ref cache) = *negative_cache {
+                    cache.insert(name, &e);
+                }
+                e
+            }),
+            Lookup::Cached(ref mut error) => Err(error.take().expect("polled after failure")),
+        }
+    }
+}
+
+impl Future for IpAddrsFuture {
+    type Item = Vec<net::IpAddr>;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let ips = try_ready!(self.0.poll().map_err(Error::ResolutionFailed));
-        ips.iter()
-            .next()
-            .map(Async::Ready)
-            .ok_or_else(|| Error::NoAddressesFound)
+        let result = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(ips)) => {
+                let mut ips = ips.iter().collect::<Vec<_>>();
+                self.ip_preference.sort(&mut ips);
+                if ips.is_empty() {
+                    Err(Error::NoAddressesFound)
+                } else {
+                    Ok(ips)
+                }
+            }
+            Err(e) => Err(Error::ResolutionFailed(e)),
+        };
+
+        match result {
+            Ok(ips) => {
+                self.recorder.record(metrics::Outcome::Ok);
+                Ok(Async::Ready(ips))
+            }
+            Err(e) => {
+                self.recorder.record(metrics::Outcome::from(&e));
+                Err(e)
+            }
+        }
     }
 }
 
@@ -215,7 +483,17 @@ impl Future for RefineFuture {
     type Error = ResolveError;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let lookup = try_ready!(self.0.poll());
+        let lookup = match self.inner.poll() {
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Ok(Async::Ready(lookup)) => {
+                self.recorder.record(metrics::Outcome::Ok);
+                lookup
+            }
+            Err(e) => {
+                self.recorder.record(metrics::Outcome::from(&e));
+                return Err(e);
+            }
+        };
         let valid_until = lookup.valid_until();
 
         let n = lookup.query().name();
@@ -229,8 +507,25 @@ impl Future for RefineFuture {
 
 #[cfg(test)]
 mod tests {
-    use super::{Name, Suffix};
+    use super::{IpPreference, Name, Suffix};
     use convert::TryFrom;
+    use std::net::IpAddr;
+
+    #[test]
+    fn ip_preference_orders_families() {
+        let ips = ["10.0.0.1", "fd00::1", "10.0.0.2", "fd00::2"]
+            .iter()
+            .map(|ip| ip.parse::<IpAddr>().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut v6 = ips.clone();
+        IpPreference::PreferV6.sort(&mut v6);
+        assert_eq!(v6, vec![ips[1], ips[3], ips[0], ips[2]]);
+
+        let mut v4 = vec![ips[1], ips[0], ips[3], ips[2]];
+        IpPreference::PreferV4.sort(&mut v4);
+        assert_eq!(v4, vec![ips[0], ips[2], ips[1], ips[3]]);
+    }
 
     #[test]
     fn test_dns_name_parsing() {
@@ -0,0 +1,121 @@
+use indexmap::IndexMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+
+use super::{Name, ResolveError, ResolveErrorKind};
+
+/// Remembers names that do not exist (or have no addresses), so that they are
+/// not looked up again until `ttl` elapses.
+#[derive(Clone, Debug)]
+pub(super) struct NegativeCache {
+    ttl: Duration,
+    entries: Arc<Mutex<IndexMap<Name, Entry>>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    expiry: Instant,
+    kind: ResolveErrorKind,
+}
+
+impl NegativeCache {
+    pub(super) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(IndexMap::new())),
+        }
+    }
+
+    /// Returns the error with which `name`'s last lookup failed, if it is
+    /// still cached.
+    pub(super) fn get(&self, name: &Name) -> Option<ResolveError> {
+        let entries = self.entries.lock().ok()?;
+        let entry = entries.get(name)?;
+        if entry.expiry <= clock::now() {
+            return None;
+        }
+        Some(ResolveError::from(entry.kind.clone()))
+    }
+
+    /// Caches `error` for `name` if it indicates that `name` has no records.
+    ///
+    /// Expired entries are purged as new ones are cached.
+    pub(super) fn insert(&self, name: &Name, error: &ResolveError) {
+        match *error.kind() {
+            ResolveErrorKind::NoRecordsFound { .. } => {}
+            _ => return,
+        }
+
+        if let Ok(mut entries) = self.entries.lock() {
+            let now = clock::now();
+            entries.retain(|_, e| e.expiry > now);
+            entries.insert(
+                name.clone(),
+                Entry {
+                    expiry: now + self.ttl,
+                    kind: error.kind().clone(),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::trust_dns_resolver::proto::op::Query;
+    use super::*;
+    use convert::TryFrom;
+
+    fn name(s: &str) -> Name {
+        Name::try_from(s.as_bytes()).expect("name must be valid")
+    }
+
+    fn no_records() -> ResolveError {
+        ResolveError::from(ResolveErrorKind::NoRecordsFound {
+            query: Query::new(),
+            valid_until: None,
+        })
+    }
+
+    #[test]
+    fn caches_names_without_records() {
+        let cache = NegativeCache::new(Duration::from_secs(60));
+        let foo = name("foo.example.com");
+        assert!(cache.get(&foo).is_none());
+
+        cache.insert(&foo, &no_records());
+        match cache.get(&foo).map(|e| e.kind().clone()) {
+            Some(ResolveErrorKind::NoRecordsFound { .. }) => {}
+            other => panic!("expected a cached NoRecordsFound; got {:?}", other),
+        }
+        assert!(cache.get(&name("bar.example.com")).is_none());
+    }
+
+    #[test]
+    fn does_not_cache_other_errors() {
+        let cache = NegativeCache::new(Duration::from_secs(60));
+        let foo = name("foo.example.com");
+
+        cache.insert(&foo, &ResolveError::from(ResolveErrorKind::Timeout));
+        assert!(cache.get(&foo).is_none());
+
+        cache.insert(&foo, &ResolveError::from("resolver failed"));
+        assert!(cache.get(&foo).is_none());
+    }
+
+    #[test]
+    fn expired_entries_are_purged() {
+        let cache = NegativeCache::new(Duration::from_secs(0));
+        let foo = name("foo.example.com");
+        let bar = name("bar.example.com");
+
+        cache.insert(&foo, &no_records());
+        assert!(cache.get(&foo).is_none());
+
+        cache.insert(&bar, &no_records());
+        let entries = cache.entries.lock().unwrap();
+        assert!(!entries.contains_key(&foo));
+        assert_eq!(entries.len(), 1);
+    }
+}
@@ -1,4 +1,6 @@
 use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use futures::future::Shared;
 use futures::sync::{mpsc, oneshot};
@@ -13,11 +15,17 @@ use never::Never;
 pub fn channel() -> (Signal, Watch) {
     let (tx, rx) = oneshot::channel();
     let (drained_tx, drained_rx) = mpsc::channel(0);
+    let progress = Progress::default();
     (
-        Signal { drained_rx, tx },
+        Signal {
+            drained_rx,
+            tx,
+            progress: progress.clone(),
+        },
         Watch {
             drained_tx,
             rx: rx.shared(),
+            progress,
         },
     )
 }
@@ -30,6 +38,7 @@ pub fn channel() -> (Signal, Watch) {
 pub struct Signal {
     drained_rx: mpsc::Receiver<Never>,
     tx: oneshot::Sender<()>,
+    progress: Progress,
 }
 
 /// Watch for a drain command.
@@ -39,6 +48,18 @@ pub struct Signal {
 pub struct Watch {
     drained_tx: mpsc::Sender<Never>,
     rx: Shared<oneshot::Receiver<()>>,
+    progress: Progress,
+}
+
+/// Describes the progress of a drain.
+#[derive(Clone, Debug, Default)]
+pub struct Progress(Arc<Status>);
+
+#[derive(Debug, Default)]
+struct Status {
+    draining: AtomicBool,
+    /// The number of watched futures that have not completed.
+    pending: AtomicUsize,
 }
 
 /// The wrapped watching `Future`.
@@ -69,6 +90,7 @@ impl Signal {
     /// is returned from this method that resolves when all watchers have
     /// completed.
     pub fn drain(self) -> Drained {
+        self.progress.0.draining.store(true, Ordering::Release);
         let _ = self.tx.send(());
         Drained {
             drained_rx: self.drained_rx,
@@ -88,12 +110,33 @@ impl Watch {
         A: Future,
         F: FnOnce(&mut A),
     {
+        self.progress.0.pending.fetch_add(1, Ordering::AcqRel);
         Watching {
             future,
             state: State::Watch(on_drain),
             watch: self,
         }
     }
+
+    /// Returns a handle that describes the progress of a drain.
+    pub fn progress(&self) -> Progress {
+        self.progress.clone()
+    }
+}
+
+// ===== impl Progress =====
+
+impl Progress {
+    /// Returns true once a drain has been signaled.
+    pub fn is_draining(&self) -> bool {
+        self.0.draining.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of watched futures, e.g. connections, that have not
+    /// completed.
+    pub fn pending(&self) -> usize {
+        self.0.pending.load(Ordering::Acquire)
+    }
 }
 
 // ===== impl Watching =====
@@ -129,6 +172,12 @@ where
     }
 }
 
+impl<A, F> Drop for Watching<A, F> {
+    fn drop(&mut self) {
+        self.watch.progress.0.pending.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 // ===== impl Drained =====
 
 impl Future for Drained {
@@ -223,6 +272,7 @@ mod tests {
     fn watch_clones() {
         future::lazy(|| {
             let (tx, rx) = channel();
+            let progress = rx.progress();
 
             let fut1 = TestMe {
                 draining: false,
@@ -242,7 +292,11 @@ mod tests {
                 fut.draining = true;
             });
 
+            assert_eq!(progress.pending(), 2);
+            assert!(!progress.is_draining());
+
             let mut draining = tx.drain();
+            assert!(progress.is_draining());
 
             // Still 2 outstanding watchers
             assert!(draining.poll().unwrap().is_not_ready());
@@ -252,6 +306,7 @@ mod tests {
 
             // Still not ready, 1 other watcher still pending
             assert!(draining.poll().unwrap().is_not_ready());
+            assert_eq!(progress.pending(), 1);
 
             drop(watch2);
 
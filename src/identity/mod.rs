@@ -4,20 +4,29 @@ extern crate tokio_rustls;
 extern crate untrusted;
 
 use self::ring::rand;
-use self::ring::signature::EcdsaKeyPair;
+use self::ring::signature::{EcdsaKeyPair, KeyPair};
+use std::collections::HashSet;
 use std::error::Error;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 use std::{fmt, fs, io};
 
 pub use self::ring::error::KeyRejected;
 
+use base64;
 use convert::TryFrom;
 use dns;
 use transport::tls;
 
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11;
 #[cfg(test)]
 pub mod test_util;
+pub mod x509;
 
 pub use dns::InvalidName;
 
@@ -27,21 +36,127 @@ pub trait LocalIdentity {
 }
 
 /// A DER-encoded X.509 certificate signing request.
+///
+/// The request must be for the public half of the local `Key`.
 #[derive(Clone, Debug)]
 pub struct Csr(Arc<Vec<u8>>);
 
 /// An endpoint's identity.
+///
+/// An identity is either a DNS-like name, which certificates present as a DNS
+/// SAN, or a SPIFFE ID (`spiffe://trust-domain/path`), which certificates
+/// present as a URI SAN.
 #[derive(Clone, Eq, PartialEq, Hash)]
-pub struct Name(Arc<dns::Name>);
+pub struct Name(Arc<Kind>);
 
+#[derive(Clone, Eq, PartialEq, Hash)]
+enum Kind {
+    Dns(dns::Name),
+    Spiffe {
+        id: String,
+        /// SPIFFE IDs cannot be used as TLS server names, so the trust domain
+        /// is used instead.
+        trust_domain: dns::Name,
+    },
+}
+
+/// Verifies server certificates with webpki, also accepting SPIFFE SVIDs whose
+/// trust domain is the server name.
+struct CrtVerifier {
+    webpki: rustls::WebPKIVerifier,
+    crl: Option<Crl>,
+    clock_skew: Duration,
+}
+
+/// Verifies client certificates with webpki, tolerating clock skew and
+/// rejecting certificates that have been revoked.
+struct ClientCrtVerifier {
+    inner: Arc<rustls::ClientCertVerifier>,
+    roots: rustls::RootCertStore,
+    crl: Option<Crl>,
+    clock_skew: Duration,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Usage {
+    Server,
+    Client,
+}
+
+/// The local identity's ECDSA P-256 private key.
 #[derive(Clone, Debug)]
-pub struct Key(Arc<EcdsaKeyPair>);
+pub struct Key(Arc<Sign>);
 
-struct SigningKey(Arc<EcdsaKeyPair>);
-struct Signer(Arc<EcdsaKeyPair>);
+/// Signs with an ECDSA P-256 private key, which need not be held in the
+/// proxy's memory, e.g. when it is held by a PKCS#11 token.
+pub trait Sign: fmt::Debug + Send + Sync + 'static {
+    /// Returns the public key as an uncompressed point.
+    fn public_key(&self) -> &[u8];
+
+    /// Signs the SHA-256 digest of `msg`, returning an ASN.1-encoded
+    /// signature.
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SignError>;
+}
+
+/// Indicates that a message could not be signed.
+#[derive(Clone, Debug)]
+pub struct SignError(pub String);
+
+struct SigningKey(Arc<Sign>);
+struct Signer(Arc<Sign>);
 
 #[derive(Clone)]
-pub struct TrustAnchors(Arc<rustls::ClientConfig>);
+pub struct TrustAnchors {
+    config: Arc<rustls::ClientConfig>,
+    crl: Option<Crl>,
+    /// How far in the future a certificate's validity may begin.
+    clock_skew: Duration,
+    /// The protocol versions that servers accept, which may be more
+    /// restrictive than those that clients offer.
+    server_versions: Vec<rustls::ProtocolVersion>,
+    /// When set, the number of TLS sessions that are cached for resumption.
+    session_cache_size: Option<usize>,
+}
+
+/// A TLS protocol version.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+/// A TLS cipher suite, named as in the IANA registry, e.g.
+/// `TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256` or `TLS_AES_128_GCM_SHA256`.
+#[derive(Clone)]
+pub struct CipherSuite(&'static rustls::SupportedCipherSuite);
+
+/// A set of revoked certificates, read from a PEM- or DER-encoded certificate
+/// revocation list file.
+///
+/// The CRL is shared by the configurations that use it, so that it may be
+/// reloaded without certifying the local identity again. The file is trusted
+/// as configured: the CRLs' signatures are not verified.
+#[derive(Clone)]
+pub struct Crl(Arc<CrlFile>);
+
+struct CrlFile {
+    path: PathBuf,
+    /// The issuer names and serial numbers of revoked certificates.
+    revoked: RwLock<HashSet<(Vec<u8>, Vec<u8>)>>,
+}
+
+/// Writes TLS secrets to a file in the NSS key log format (as configured by
+/// `SSLKEYLOGFILE` in browsers), so that captured traffic may be decrypted.
+///
+/// This defeats the confidentiality of every logged session and must only be
+/// enabled while debugging.
+#[derive(Clone)]
+pub struct KeyLog(Arc<KeyLogFile>);
+
+struct KeyLogFile {
+    path: PathBuf,
+    file: Mutex<fs::File>,
+}
 
 #[derive(Clone, Debug)]
 pub struct TokenSource(Arc<String>);
@@ -57,15 +172,34 @@ pub struct Crt {
 pub struct CrtKey {
     name: Name,
     expiry: SystemTime,
+    info: Option<Arc<x509::Info>>,
     client_config: Arc<rustls::ClientConfig>,
     server_config: Arc<rustls::ServerConfig>,
 }
 
-struct CertResolver(rustls::sign::CertifiedKey);
+struct CertResolver {
+    name: Name,
+    key: rustls::sign::CertifiedKey,
+}
 
 #[derive(Clone, Debug)]
 pub struct InvalidCrt(rustls::TLSError);
 
+const SPIFFE_SCHEME: &str = "spiffe://";
+
+/// The signature algorithms that certificates verified outside of Rustls may be
+/// signed with.
+static SUPPORTED_SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+
 // These must be kept in sync:
 static SIGNATURE_ALG_RING_SIGNING: &ring::signature::EcdsaSigningAlgorithm =
     &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING;
@@ -86,6 +220,32 @@ impl Csr {
         Some(Csr(Arc::new(der)))
     }
 
+    /// Generates a request for `key` that is valid for `name` and each of
+    /// `additional_names`, which must be DNS-like names.
+    ///
+    /// A DNS-like `name` is also used as the request's common name.
+    pub fn generate(key: &Key, name: &Name, additional_names: &[Name]) -> Option<Self> {
+        let mut dns_sans = Vec::with_capacity(additional_names.len() + 1);
+        let mut uri_sans = Vec::new();
+        let common_name = if name.is_spiffe() {
+            uri_sans.push(name.as_ref());
+            None
+        } else {
+            dns_sans.push(name.as_ref());
+            Some(name.as_ref())
+        };
+        for n in additional_names {
+            if n.is_spiffe() {
+                return None;
+            }
+            dns_sans.push(n.as_ref());
+        }
+
+        let info = x509::csr_info(common_name, key.0.public_key(), &dns_sans, &uri_sans);
+        let signature = key.0.sign(&info).ok()?;
+        Self::from_der(x509::csr(&info, &signature))
+    }
+
     pub fn to_vec(&self) -> Vec<u8> {
         self.0.to_vec()
     }
@@ -94,10 +254,41 @@ impl Csr {
 // === impl Key ===
 
 impl Key {
+    /// Reads a PEM-encoded PKCS#8 key.
+    pub fn from_pem(b: &[u8]) -> Result<Self, KeyRejected> {
+        use self::rustls::internal::pemfile;
+
+        let der = pemfile::pkcs8_private_keys(&mut io::Cursor::new(b))
+            .ok()
+            .and_then(|keys| keys.into_iter().next())
+            .map(|k| k.0)
+            .unwrap_or_default();
+        Self::from_pkcs8(&der)
+    }
+
+    /// Reads a PKCS#8 v2-encoded ECDSA P-256 key, which must include its public
+    /// key.
     pub fn from_pkcs8(b: &[u8]) -> Result<Self, KeyRejected> {
         let i = untrusted::Input::from(b);
         let k = EcdsaKeyPair::from_pkcs8(SIGNATURE_ALG_RING_SIGNING, i)?;
-        Ok(Key(Arc::new(k)))
+        Ok(Self::new(k))
+    }
+
+    pub fn new<S: Sign>(signer: S) -> Self {
+        Key(Arc::new(signer))
+    }
+}
+
+impl Sign for EcdsaKeyPair {
+    fn public_key(&self) -> &[u8] {
+        KeyPair::public_key(self).as_ref()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SignError> {
+        let rng = rand::SystemRandom::new();
+        EcdsaKeyPair::sign(self, &rng, untrusted::Input::from(msg))
+            .map(|signature| signature.as_ref().to_owned())
+            .map_err(|ring::error::Unspecified| SignError("signing failed".to_owned()))
     }
 }
 
@@ -120,13 +311,10 @@ impl rustls::sign::SigningKey for SigningKey {
 
 impl rustls::sign::Signer for Signer {
     fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::TLSError> {
-        let rng = rand::SystemRandom::new();
-        self.0
-            .sign(&rng, untrusted::Input::from(message))
-            .map(|signature| signature.as_ref().to_owned())
-            .map_err(|ring::error::Unspecified| {
-                rustls::TLSError::General("Signing Failed".to_owned())
-            })
+        self.0.sign(message).map_err(|SignError(e)| {
+            error!("Failed to sign: {}", e);
+            rustls::TLSError::General("Signing Failed".to_owned())
+        })
     }
 
     fn get_scheme(&self) -> rustls::SignatureScheme {
@@ -138,7 +326,7 @@ impl rustls::sign::Signer for Signer {
 
 impl From<dns::Name> for Name {
     fn from(n: dns::Name) -> Self {
-        Name(Arc::new(n))
+        Name(Arc::new(Kind::Dns(n)))
     }
 }
 
@@ -148,32 +336,145 @@ impl Name {
             return Err(dns::InvalidName); // SNI hostnames are implicitly absolute.
         }
 
-        dns::Name::try_from(hostname).map(|n| Name(Arc::new(n)))
+        dns::Name::try_from(hostname).map(Name::from)
+    }
+
+    /// Parses a SPIFFE ID, e.g. `spiffe://cluster.local/ns/default/sa/web`.
+    ///
+    /// The trust domain must be a valid DNS name, and the path must not have
+    /// empty, `.`, or `..` segments.
+    pub fn from_spiffe_id(id: &[u8]) -> Result<Self, InvalidName> {
+        let id = ::std::str::from_utf8(id).map_err(|_| InvalidName)?;
+        if !id.starts_with(SPIFFE_SCHEME) || id.len() > 2048 {
+            return Err(InvalidName);
+        }
+
+        let (trust_domain, path) = {
+            let rest = &id[SPIFFE_SCHEME.len()..];
+            match rest.find('/') {
+                Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+                None => (rest, None),
+            }
+        };
+
+        let is_td_char =
+            |b: u8| b.is_ascii_lowercase() || b.is_ascii_digit() || b"-._".contains(&b);
+        if trust_domain.is_empty() || !trust_domain.bytes().all(is_td_char) {
+            return Err(InvalidName);
+        }
+        if trust_domain.ends_with('.') {
+            return Err(InvalidName);
+        }
+        let trust_domain = dns::Name::try_from(trust_domain.as_bytes())?;
+
+        if let Some(path) = path {
+            let is_path_char = |b: u8| b.is_ascii_alphanumeric() || b"-._".contains(&b);
+            for segment in path.split('/') {
+                if segment.is_empty()
+                    || segment == "."
+                    || segment == ".."
+                    || !segment.bytes().all(is_path_char)
+                {
+                    return Err(InvalidName);
+                }
+            }
+        }
+
+        Ok(Name(Arc::new(Kind::Spiffe {
+            id: id.to_owned(),
+            trust_domain,
+        })))
+    }
+
+    /// Parses either a SPIFFE ID or a DNS-like name.
+    pub fn parse(s: &[u8]) -> Result<Self, InvalidName> {
+        if s.starts_with(SPIFFE_SCHEME.as_bytes()) {
+            Self::from_spiffe_id(s)
+        } else {
+            Self::from_hostname(s)
+        }
+    }
+
+    /// Reads the identity that an end-entity certificate presents: its SPIFFE
+    /// ID, if it has one, or else its first DNS SAN.
+    pub fn from_crt(der: &[u8]) -> Option<Self> {
+        let spiffe_id = x509::uri_sans(der)?
+            .into_iter()
+            .filter_map(|uri| Self::from_spiffe_id(uri.as_bytes()).ok())
+            .next();
+        if spiffe_id.is_some() {
+            return spiffe_id;
+        }
+
+        let crt = webpki::EndEntityCert::from(untrusted::Input::from(der)).ok()?;
+        let dns_names = crt.dns_names().ok()?;
+        let n = dns_names.first()?.to_owned();
+        Some(Name::from(dns::Name::from(n)))
+    }
+
+    pub fn is_spiffe(&self) -> bool {
+        match *self.0 {
+            Kind::Spiffe { .. } => true,
+            Kind::Dns(_) => false,
+        }
+    }
+
+    /// The name that is used for SNI.
+    pub fn sni(&self) -> &dns::Name {
+        match *self.0 {
+            Kind::Dns(ref n) => n,
+            Kind::Spiffe {
+                ref trust_domain, ..
+            } => trust_domain,
+        }
     }
 
     pub fn as_dns_name_ref(&self) -> webpki::DNSNameRef {
-        self.0.as_dns_name_ref()
+        self.sni().as_dns_name_ref()
+    }
+
+    /// Checks that an end-entity certificate is valid for this identity.
+    ///
+    /// This does not verify the certificate's chain of trust.
+    pub fn is_valid_for_crt(&self, der: &[u8]) -> bool {
+        match *self.0 {
+            Kind::Dns(ref n) => webpki::EndEntityCert::from(untrusted::Input::from(der))
+                .and_then(|c| c.verify_is_valid_for_dns_name(n.as_dns_name_ref()))
+                .is_ok(),
+            Kind::Spiffe { ref id, .. } => x509::uri_sans(der)
+                .map(|uris| uris.iter().any(|uri| uri == id))
+                .unwrap_or(false),
+        }
     }
 }
 
 impl AsRef<str> for Name {
     fn as_ref(&self) -> &str {
-        (*self.0).as_ref()
+        match *self.0 {
+            Kind::Dns(ref n) => n.as_ref(),
+            Kind::Spiffe { ref id, .. } => id,
+        }
     }
 }
 
 impl fmt::Debug for Name {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt::Debug::fmt(&self.0, f)
+        match *self.0 {
+            Kind::Dns(ref n) => fmt::Debug::fmt(n, f),
+            Kind::Spiffe { ref id, .. } => fmt::Debug::fmt(id, f),
+        }
     }
 }
 
 // === impl TokenSource ===
 
 impl TokenSource {
-    pub fn if_nonempty_file(p: String) -> io::Result<Self> {
-        let ts = TokenSource(Arc::new(p));
-        ts.load().map(|_| ts)
+    /// Reads the token from the file at `p`.
+    ///
+    /// The file is read whenever the token is loaded, since the token may be
+    /// rotated, and it need not exist yet.
+    pub fn from_file(p: String) -> Self {
+        TokenSource(Arc::new(p))
     }
 
     pub fn load(&self) -> io::Result<Vec<u8>> {
@@ -195,10 +496,56 @@ impl TokenSource {
 impl TrustAnchors {
     #[cfg(test)]
     fn empty() -> Self {
-        TrustAnchors(Arc::new(rustls::ClientConfig::new()))
+        TrustAnchors {
+            config: Arc::new(rustls::ClientConfig::new()),
+            crl: None,
+            clock_skew: Duration::from_secs(0),
+            server_versions: TLS_VERSIONS.to_vec(),
+            session_cache_size: None,
+        }
     }
 
     pub fn from_pem(s: &str) -> Option<Self> {
+        let mut c = rustls::ClientConfig::new();
+
+        // XXX: Rustls's built-in verifiers don't let us tweak things as fully
+        // as we'd like (e.g. controlling the set of trusted signature
+        // algorithms), but they provide good enough defaults for now.
+        // TODO: lock down the verification further.
+        // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
+        c.root_store = Self::read_roots(s)?;
+
+        // Session tickets are disabled unless session resumption is enabled
+        // with `with_session_resumption`.
+        c.enable_tickets = false;
+
+        let ta = TrustAnchors {
+            config: Arc::new(c),
+            crl: None,
+            clock_skew: Duration::from_secs(0),
+            server_versions: TLS_VERSIONS.to_vec(),
+            session_cache_size: None,
+        };
+        Some(ta.with_verifier(None, Duration::from_secs(0)))
+    }
+
+    fn with_verifier(self, crl: Option<Crl>, clock_skew: Duration) -> Self {
+        let mut c = self.config.as_ref().clone();
+        let verifier = CrtVerifier {
+            webpki: rustls::WebPKIVerifier::new(),
+            crl: crl.clone(),
+            clock_skew,
+        };
+        c.dangerous().set_certificate_verifier(Arc::new(verifier));
+        TrustAnchors {
+            config: Arc::new(c),
+            crl,
+            clock_skew,
+            ..self
+        }
+    }
+
+    fn read_roots(s: &str) -> Option<rustls::RootCertStore> {
         use std::io::Cursor;
 
         let mut roots = rustls::RootCertStore::empty();
@@ -209,25 +556,95 @@ impl TrustAnchors {
         if added == 0 {
             return None;
         }
+        Some(roots)
+    }
 
-        let mut c = rustls::ClientConfig::new();
+    /// Replaces the trusted roots with those in `s`, keeping the other
+    /// settings (e.g. the key log and CRL).
+    pub fn with_roots_from_pem(&self, s: &str) -> Option<Self> {
+        let mut c = self.config.as_ref().clone();
+        c.root_store = Self::read_roots(s)?;
+        Some(TrustAnchors {
+            config: Arc::new(c),
+            ..self.clone()
+        })
+    }
 
-        // XXX: Rustls's built-in verifiers don't let us tweak things as fully
-        // as we'd like (e.g. controlling the set of trusted signature
-        // algorithms), but they provide good enough defaults for now.
-        // TODO: lock down the verification further.
-        // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
-        c.root_store = roots;
+    /// Logs the secrets of the TLS sessions established with these trust
+    /// anchors, including those accepted by a `CrtKey` that they certify.
+    pub fn with_key_log(self, key_log: &KeyLog) -> Self {
+        let mut c = self.config.as_ref().clone();
+        c.key_log = key_log.0.clone();
+        TrustAnchors {
+            config: Arc::new(c),
+            ..self
+        }
+    }
 
-        // Disable session resumption for the time-being until resumption is
-        // more tested.
-        c.enable_tickets = false;
+    /// Rejects the certificates that `crl` revokes, both when certifying the
+    /// local identity and when verifying peers.
+    pub fn with_crl(self, crl: Crl) -> Self {
+        let clock_skew = self.clock_skew;
+        self.with_verifier(Some(crl), clock_skew)
+    }
+
+    /// Accepts certificates whose validity begins up to `tolerance` in the
+    /// future, e.g. when they are issued by a host whose clock is ahead.
+    pub fn with_clock_skew_tolerance(self, tolerance: Duration) -> Self {
+        let crl = self.crl.clone();
+        self.with_verifier(crl, tolerance)
+    }
+
+    /// Restricts the protocol versions that are negotiated, by both clients
+    /// and servers, to those between `min` and `max`.
+    ///
+    /// Otherwise, clients offer all supported versions, but servers only
+    /// accept TLS 1.2.
+    pub fn with_tls_versions(self, min: TlsVersion, max: TlsVersion) -> Self {
+        let versions = [TlsVersion::Tls13, TlsVersion::Tls12]
+            .iter()
+            .filter(|&&v| min <= v && v <= max)
+            .map(|v| v.as_rustls())
+            .collect::<Vec<_>>();
+        let mut c = self.config.as_ref().clone();
+        c.versions = versions.clone();
+        TrustAnchors {
+            config: Arc::new(c),
+            server_versions: versions,
+            ..self
+        }
+    }
+
+    /// Restricts the cipher suites that are negotiated, by both clients and
+    /// servers, to `suites`, in order of preference.
+    pub fn with_cipher_suites(self, suites: &[CipherSuite]) -> Self {
+        let mut c = self.config.as_ref().clone();
+        c.ciphersuites = suites.iter().map(|s| s.0).collect();
+        TrustAnchors {
+            config: Arc::new(c),
+            ..self
+        }
+    }
 
-        Some(TrustAnchors(Arc::new(c)))
+    /// Resumes TLS sessions with recently contacted servers, so that
+    /// reconnecting to them does not require a full handshake.
+    ///
+    /// Clients cache up to `cache_size` sessions, keyed by the server's name,
+    /// i.e. its identity. Servers issue session tickets and cache as many
+    /// sessions, so that their clients may resume sessions, too.
+    pub fn with_session_resumption(self, cache_size: usize) -> Self {
+        let mut c = self.config.as_ref().clone();
+        c.enable_tickets = true;
+        c.session_persistence = rustls::ClientSessionMemoryCache::new(cache_size);
+        TrustAnchors {
+            config: Arc::new(c),
+            session_cache_size: Some(cache_size),
+            ..self
+        }
     }
 
     pub fn certify(&self, key: Key, crt: Crt) -> Result<CrtKey, InvalidCrt> {
-        let mut client = self.0.as_ref().clone();
+        let mut client = self.config.as_ref().clone();
 
         // Ensure the certificate is valid for the services we terminate for
         // TLS. This assumes that server cert validation does the same or
@@ -251,11 +668,21 @@ impl TrustAnchors {
                 NO_OCSP,
             )
             .map_err(InvalidCrt)?;
+        // The verifier only checks that an SVID is in the trust domain.
+        if !crt.name.is_valid_for_crt(&crt.chain[0].0) {
+            return Err(InvalidCrt(rustls::TLSError::WebPKIError(
+                webpki::Error::CertNotValidForName,
+            )));
+        }
         debug!("certified {}", crt.name.as_ref());
+        let info = x509::parse(&crt.chain[0].0).map(Arc::new);
 
         let k = SigningKey(key.0.clone());
         let key = rustls::sign::CertifiedKey::new(crt.chain, Arc::new(Box::new(k)));
-        let resolver = Arc::new(CertResolver(key));
+        let resolver = Arc::new(CertResolver {
+            name: crt.name.clone(),
+            key,
+        });
 
         // Enable client authentication.
         client.client_auth_cert_resolver = resolver.clone();
@@ -269,15 +696,37 @@ impl TrustAnchors {
         // TODO: lock down the verification further.
         //
         // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
-        let mut server = rustls::ServerConfig::new(
-            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.0.root_store.clone()),
-        );
-        server.versions = TLS_VERSIONS.to_vec();
+        let client_verifier =
+            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.config.root_store.clone());
+        let client_verifier: Arc<rustls::ClientCertVerifier> =
+            if self.crl.is_some() || self.clock_skew > Duration::from_secs(0) {
+                Arc::new(ClientCrtVerifier {
+                    inner: client_verifier,
+                    roots: self.config.root_store.clone(),
+                    crl: self.crl.clone(),
+                    clock_skew: self.clock_skew,
+                })
+            } else {
+                client_verifier
+            };
+        let mut server = rustls::ServerConfig::new(client_verifier);
+        server.versions = self.server_versions.clone();
+        server.ciphersuites = client.ciphersuites.clone();
         server.cert_resolver = resolver;
+        server.key_log = client.key_log.clone();
+
+        // Each certified identity has its own session caches, so that
+        // sessions established with a previous certificate aren't resumed.
+        if let Some(size) = self.session_cache_size {
+            client.session_persistence = rustls::ClientSessionMemoryCache::new(size);
+            server.session_storage = rustls::ServerSessionMemoryCache::new(size);
+            server.ticketer = rustls::Ticketer::new();
+        }
 
         Ok(CrtKey {
             name: crt.name,
             expiry: crt.expiry,
+            info,
             client_config: Arc::new(client),
             server_config: Arc::new(server),
         })
@@ -286,7 +735,7 @@ impl TrustAnchors {
 
 impl tls::client::HasConfig for TrustAnchors {
     fn tls_client_config(&self) -> Arc<rustls::ClientConfig> {
-        self.0.clone()
+        self.config.clone()
     }
 }
 
@@ -296,9 +745,219 @@ impl fmt::Debug for TrustAnchors {
     }
 }
 
+// === impl TlsVersion ===
+
+impl TlsVersion {
+    fn as_rustls(self) -> rustls::ProtocolVersion {
+        match self {
+            TlsVersion::Tls12 => rustls::ProtocolVersion::TLSv1_2,
+            TlsVersion::Tls13 => rustls::ProtocolVersion::TLSv1_3,
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "1.2" | "TLSv1.2" => Ok(TlsVersion::Tls12),
+            "1.3" | "TLSv1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(()),
+        }
+    }
+}
+
+// === impl CipherSuite ===
+
+impl FromStr for CipherSuite {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        rustls::ALL_CIPHERSUITES
+            .iter()
+            .find(|cs| {
+                // Rustls names TLS 1.3 suites with a `TLS13_` prefix, where
+                // the IANA registry uses `TLS_`.
+                let name = format!("{:?}", cs.suite);
+                name.eq_ignore_ascii_case(s)
+                    || name.replacen("TLS13_", "TLS_", 1).eq_ignore_ascii_case(s)
+            })
+            .map(|cs| CipherSuite(*cs))
+            .ok_or(())
+    }
+}
+
+impl fmt::Debug for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CipherSuite").field(&self.0.suite).finish()
+    }
+}
+
+// === impl KeyLog ===
+
+impl KeyLog {
+    /// Opens `path` for appending, creating it if necessary.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        Ok(KeyLog(Arc::new(KeyLogFile {
+            path,
+            file: Mutex::new(file),
+        })))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0.path
+    }
+}
+
+impl fmt::Debug for KeyLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("KeyLog").field(&self.0.path).finish()
+    }
+}
+
+impl rustls::KeyLog for KeyLogFile {
+    fn log(&self, label: &str, client_random: &[u8], secret: &[u8]) {
+        let mut line =
+            String::with_capacity(label.len() + 2 * (client_random.len() + secret.len()) + 3);
+        line.push_str(label);
+        line.push(' ');
+        for b in client_random {
+            write!(line, "{:02x}", b).expect("writing to a string must not fail");
+        }
+        line.push(' ');
+        for b in secret {
+            write!(line, "{:02x}", b).expect("writing to a string must not fail");
+        }
+        line.push('\n');
+
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!(
+                "failed to write TLS key log to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+// === impl Crl ===
+
+impl Crl {
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let revoked = Self::read_revoked(&path)?;
+        Ok(Crl(Arc::new(CrlFile {
+            path,
+            revoked: RwLock::new(revoked),
+        })))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0.path
+    }
+
+    /// Reads the file again, returning whether the set of revoked
+    /// certificates changed.
+    pub fn reload(&self) -> io::Result<bool> {
+        let revoked = Self::read_revoked(&self.0.path)?;
+        let mut current = self.0.revoked.write().expect("CRL lock poisoned");
+        if *current == revoked {
+            return Ok(false);
+        }
+        *current = revoked;
+        Ok(true)
+    }
+
+    /// Checks whether any of the certificates in a chain have been revoked.
+    pub fn is_revoked(&self, chain: &[rustls::Certificate]) -> bool {
+        let revoked = self.0.revoked.read().expect("CRL lock poisoned");
+        chain.iter().any(|c| match x509::issuer_and_serial(&c.0) {
+            Some((issuer, serial)) => revoked.contains(&(issuer.to_vec(), serial.to_vec())),
+            None => false,
+        })
+    }
+
+    fn read_revoked(path: &Path) -> io::Result<HashSet<(Vec<u8>, Vec<u8>)>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid CRL");
+
+        let b = fs::read(path)?;
+        let ders = if b.starts_with(b"-----BEGIN") {
+            Self::decode_pem(&b).ok_or_else(invalid)?
+        } else {
+            vec![b]
+        };
+
+        let mut revoked = HashSet::new();
+        for der in &ders {
+            let (issuer, serials) = x509::crl_revoked(der).ok_or_else(invalid)?;
+            for serial in serials {
+                revoked.insert((issuer.to_vec(), serial.to_vec()));
+            }
+        }
+        Ok(revoked)
+    }
+
+    /// Decodes each of the `X509 CRL` sections of a PEM file.
+    fn decode_pem(b: &[u8]) -> Option<Vec<Vec<u8>>> {
+        const BEGIN: &str = "-----BEGIN X509 CRL-----";
+        const END: &str = "-----END X509 CRL-----";
+
+        let s = ::std::str::from_utf8(b).ok()?;
+        let mut ders = Vec::new();
+        let mut section: Option<String> = None;
+        for line in s.lines().map(str::trim) {
+            if line == BEGIN {
+                section = Some(String::new());
+            } else if line == END {
+                ders.push(base64::decode(&section.take()?).ok()?);
+            } else if let Some(ref mut section) = section {
+                section.push_str(line);
+            }
+        }
+        if section.is_some() || ders.is_empty() {
+            return None;
+        }
+        Some(ders)
+    }
+}
+
+impl fmt::Debug for Crl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Crl").field(&self.0.path).finish()
+    }
+}
+
 // === Crt ===
 
 impl Crt {
+    /// Reads a PEM-encoded certificate chain, starting with the leaf
+    /// certificate.
+    pub fn from_pem(name: Name, b: &[u8], expiry: SystemTime) -> Option<Self> {
+        use self::rustls::internal::pemfile;
+
+        let chain = pemfile::certs(&mut io::Cursor::new(b)).ok()?;
+        if chain.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            name,
+            chain,
+            expiry,
+        })
+    }
+
     pub fn new(name: Name, leaf: Vec<u8>, intermediates: Vec<Vec<u8>>, expiry: SystemTime) -> Self {
         let mut chain = Vec::with_capacity(intermediates.len() + 1);
         chain.push(rustls::Certificate(leaf));
@@ -314,6 +973,13 @@ impl Crt {
 
 // === CrtKey ===
 
+impl CrtKey {
+    /// Describes the leaf certificate, if it could be read.
+    pub fn info(&self) -> Option<&x509::Info> {
+        self.info.as_ref().map(|i| i.as_ref())
+    }
+}
+
 impl tls::client::HasConfig for CrtKey {
     fn tls_client_config(&self) -> Arc<tls::client::Config> {
         self.client_config.clone()
@@ -321,8 +987,8 @@ impl tls::client::HasConfig for CrtKey {
 }
 
 impl tls::listen::HasConfig for CrtKey {
-    fn tls_server_name(&self) -> Name {
-        self.name.clone()
+    fn tls_server_names(&self) -> Vec<Name> {
+        vec![self.name.clone()]
     }
 
     fn tls_server_config(&self) -> Arc<tls::listen::Config> {
@@ -366,7 +1032,7 @@ impl CertResolver {
             debug!("signature scheme not supported -> no certificate");
             return None;
         }
-        Some(self.0.clone())
+        Some(self.key.clone())
     }
 }
 
@@ -383,8 +1049,14 @@ impl rustls::ResolvesServerCert for CertResolver {
             return None;
         };
 
-        // Verify that our certificate is valid for the given SNI name.
-        let c = (&self.0.cert)
+        // An SVID cannot be valid for its trust domain, which is used for SNI.
+        if self.name.is_spiffe() && dns::Name::from(server_name.to_owned()) == *self.name.sni() {
+            return self.resolve_(sigschemes);
+        }
+
+        // Otherwise, verify that our certificate is valid for the given SNI
+        // name, which may be any of its DNS SANs.
+        let c = (&self.key.cert)
             .first()
             .map(rustls::Certificate::as_ref)
             .unwrap_or(&[]); // An empty input will fail to parse.
@@ -402,8 +1074,203 @@ impl rustls::ResolvesServerCert for CertResolver {
     }
 }
 
+// === impl CrtVerifier ===
+
+impl CrtVerifier {
+    fn verify_chain(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        dns_name: webpki::DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<(), rustls::TLSError> {
+        let err =
+            match self
+                .webpki
+                .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)
+            {
+                Ok(_) => return Ok(()),
+                Err(e) => e,
+            };
+
+        // Otherwise, the certificate may be an SVID in the trust domain named
+        // by SNI (callers must check that it has the expected identity), or
+        // it may become valid within the tolerated clock skew. In either case,
+        // the chain is verified here.
+        let sni = dns::Name::from(dns_name.to_owned());
+        let is_svid = presented_certs
+            .first()
+            .and_then(|c| x509::uri_sans(&c.0))
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|uri| Name::from_spiffe_id(uri.as_bytes()).ok())
+            .any(|id| *id.sni() == sni);
+        let is_skewed = is_not_yet_valid(&err) && self.clock_skew > Duration::from_secs(0);
+        if !is_svid && !is_skewed {
+            return Err(err);
+        }
+
+        let crt = verify_chain_skewed(roots, presented_certs, self.clock_skew, Usage::Server)?;
+        if !is_svid {
+            crt.verify_is_valid_for_dns_name(dns_name)
+                .map_err(rustls::TLSError::WebPKIError)?;
+        }
+        Ok(())
+    }
+}
+
+impl rustls::ServerCertVerifier for CrtVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        dns_name: webpki::DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        self.verify_chain(roots, presented_certs, dns_name, ocsp_response)?;
+        if let Some(ref crl) = self.crl {
+            if crl.is_revoked(presented_certs) {
+                return Err(revoked());
+            }
+        }
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+// === impl ClientCrtVerifier ===
+
+impl rustls::ClientCertVerifier for ClientCrtVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.inner.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self) -> bool {
+        self.inner.client_auth_mandatory()
+    }
+
+    fn client_auth_root_subjects(&self) -> rustls::DistinguishedNames {
+        self.inner.client_auth_root_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        presented_certs: &[rustls::Certificate],
+    ) -> Result<rustls::ClientCertVerified, rustls::TLSError> {
+        match self.inner.verify_client_cert(presented_certs) {
+            Ok(_) => {}
+            Err(ref e) if is_not_yet_valid(e) && self.clock_skew > Duration::from_secs(0) => {
+                verify_chain_skewed(&self.roots, presented_certs, self.clock_skew, Usage::Client)?;
+            }
+            Err(e) => return Err(e),
+        }
+        if let Some(ref crl) = self.crl {
+            if crl.is_revoked(presented_certs) {
+                return Err(revoked());
+            }
+        }
+        Ok(rustls::ClientCertVerified::assertion())
+    }
+}
+
+/// Verifies a certificate's chain of trust as of now, returning the end-entity
+/// certificate.
+///
+/// If a presented certificate's validity begins within `clock_skew` of now,
+/// the chain is verified as of that time instead. The tolerance only applies
+/// to the start of the validity period, so it never extends a certificate's
+/// expiry.
+fn verify_chain_skewed<'a>(
+    roots: &rustls::RootCertStore,
+    presented_certs: &'a [rustls::Certificate],
+    clock_skew: Duration,
+    usage: Usage,
+) -> Result<webpki::EndEntityCert<'a>, rustls::TLSError> {
+    let now = SystemTime::now();
+    match verify_chain_at(roots, presented_certs, now, usage) {
+        Err(ref e) if is_not_yet_valid(e) && clock_skew > Duration::from_secs(0) => {}
+        verified => return verified,
+    }
+
+    let not_yet_valid = || rustls::TLSError::WebPKIError(webpki::Error::CertNotValidYet);
+    let valid_from = presented_certs
+        .iter()
+        .filter_map(|c| x509::parse(&c.0))
+        .map(|info| info.not_before)
+        .max()
+        .ok_or_else(not_yet_valid)?;
+    if valid_from > now + clock_skew {
+        return Err(not_yet_valid());
+    }
+
+    let crt = verify_chain_at(roots, presented_certs, valid_from, usage)?;
+    debug!("accepted a certificate that is not yet valid, within the clock skew tolerance");
+    Ok(crt)
+}
+
+/// Verifies a certificate's chain of trust as of `time`, returning the
+/// end-entity certificate.
+fn verify_chain_at<'a>(
+    roots: &rustls::RootCertStore,
+    presented_certs: &'a [rustls::Certificate],
+    time: SystemTime,
+    usage: Usage,
+) -> Result<webpki::EndEntityCert<'a>, rustls::TLSError> {
+    let (end_entity, intermediates) = presented_certs
+        .split_first()
+        .ok_or(rustls::TLSError::NoCertificatesPresented)?;
+    let anchors = roots
+        .roots
+        .iter()
+        .map(|r| r.to_trust_anchor())
+        .collect::<Vec<_>>();
+    let intermediates = intermediates
+        .iter()
+        .map(|c| untrusted::Input::from(&c.0))
+        .collect::<Vec<_>>();
+    let time =
+        webpki::Time::try_from(time).map_err(|_| rustls::TLSError::FailedToGetCurrentTime)?;
+
+    let crt = webpki::EndEntityCert::from(untrusted::Input::from(&end_entity.0))
+        .map_err(rustls::TLSError::WebPKIError)?;
+    let verified = match usage {
+        Usage::Server => crt.verify_is_valid_tls_server_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TLSServerTrustAnchors(&anchors),
+            &intermediates,
+            time,
+        ),
+        Usage::Client => crt.verify_is_valid_tls_client_cert(
+            SUPPORTED_SIG_ALGS,
+            &webpki::TLSClientTrustAnchors(&anchors),
+            &intermediates,
+            time,
+        ),
+    };
+    verified.map_err(rustls::TLSError::WebPKIError)?;
+    Ok(crt)
+}
+
+fn is_not_yet_valid(e: &rustls::TLSError) -> bool {
+    match *e {
+        rustls::TLSError::WebPKIError(webpki::Error::CertNotValidYet) => true,
+        _ => false,
+    }
+}
+
+fn revoked() -> rustls::TLSError {
+    rustls::TLSError::General("certificate has been revoked".into())
+}
+
 // === impl InvalidCrt ===
 
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for SignError {}
+
 impl fmt::Display for InvalidCrt {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&self.0, f)
@@ -429,6 +1296,182 @@ mod tests {
         FOO_NS1.validate().expect("foo.ns1 must be valid");
     }
 
+    #[test]
+    fn can_certify_spiffe_svids() {
+        let crt_key = FOO_NS1_SPIFFE
+            .validate()
+            .expect("foo.ns1 SVID must be valid");
+        assert!(crt_key.name.is_spiffe());
+
+        let s = Strings {
+            name: "spiffe://cluster.local/ns/ns1/sa/bar",
+            ..FOO_NS1_SPIFFE
+        };
+        assert!(s.validate().is_err(), "SPIFFE ID should not be valid");
+    }
+
+    #[test]
+    fn generates_csrs_with_additional_names() {
+        use super::{Csr, Name};
+
+        let name = Name::parse(FOO_NS1.name.as_bytes()).unwrap();
+        let alt = Name::parse(b"foo.example.com").unwrap();
+        let csr = Csr::generate(&FOO_NS1.key(), &name, &[alt])
+            .expect("CSR must be generated")
+            .to_vec();
+        for n in &[FOO_NS1.name, "foo.example.com"] {
+            assert!(
+                csr.windows(n.len()).any(|w| w == n.as_bytes()),
+                "CSR must request {}",
+                n
+            );
+        }
+
+        let spiffe = Name::parse(FOO_NS1_SPIFFE.name.as_bytes()).unwrap();
+        assert!(
+            Csr::generate(&FOO_NS1.key(), &name, &[spiffe]).is_none(),
+            "additional names must be DNS-like"
+        );
+    }
+
+    #[test]
+    fn parses_spiffe_ids() {
+        use super::Name;
+
+        let id = Name::parse(b"spiffe://cluster.local/ns/ns1/sa/foo").unwrap();
+        assert!(id.is_spiffe());
+        assert_eq!(id.as_ref(), "spiffe://cluster.local/ns/ns1/sa/foo");
+        assert_eq!(id.sni().as_ref(), "cluster.local");
+        assert!(Name::parse(b"spiffe://cluster.local").unwrap().is_spiffe());
+        assert!(!Name::parse(b"foo.ns1.cluster.local").unwrap().is_spiffe());
+
+        for invalid in &[
+            "spiffe://",
+            "spiffe:///ns/ns1",
+            "spiffe://Cluster.local/ns/ns1",
+            "spiffe://cluster.local./ns/ns1",
+            "spiffe://cluster.local/",
+            "spiffe://cluster.local/ns//ns1",
+            "spiffe://cluster.local/ns/../ns1",
+            "spiffe://cluster.local/ns/ns1?x=y",
+            "spiffe://user@cluster.local/ns/ns1",
+            "spiffe://cluster.local:8443/ns/ns1",
+        ] {
+            assert!(
+                Name::parse(invalid.as_bytes()).is_err(),
+                "{} must be invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn reads_identities_from_crts() {
+        use super::Name;
+
+        let der = super::fs::read("src/identity/testdata/foo-ns1-spiffe-ca1/crt.der").unwrap();
+        let id = Name::from_crt(&der).expect("SVID must have an identity");
+        assert_eq!(id.as_ref(), FOO_NS1_SPIFFE.name);
+        assert!(id.is_valid_for_crt(&der));
+
+        let der = super::fs::read("src/identity/testdata/foo-ns1-ca1/crt.der").unwrap();
+        let id = Name::from_crt(&der).expect("crt must have an identity");
+        assert_eq!(id.as_ref(), FOO_NS1.name);
+        assert!(id.is_valid_for_crt(&der));
+    }
+
+    #[test]
+    fn svids_are_only_valid_for_their_spiffe_id() {
+        use super::Name;
+
+        // The verifier accepts an SVID for its trust domain, so the client
+        // relies on this check to reject SVIDs for other identities.
+        let der = super::fs::read("src/identity/testdata/foo-ns1-spiffe-ca1/crt.der").unwrap();
+        let trust_domain = Name::from_hostname(b"cluster.local").unwrap();
+        assert!(!trust_domain.is_valid_for_crt(&der));
+        let other = Name::parse(b"spiffe://cluster.local/ns/ns1/sa/bar").unwrap();
+        assert!(!other.is_valid_for_crt(&der));
+    }
+
+    #[test]
+    fn rejects_revoked_crts() {
+        let crl = super::Crl::read("src/identity/testdata/ca1-crl.pem").expect("CRL must be valid");
+        let der = super::Crl::read("src/identity/testdata/ca1-crl.der").expect("CRL must be valid");
+        for crl in &[crl, der] {
+            assert!(crl.is_revoked(&FOO_NS1.crt().chain));
+            assert!(!crl.is_revoked(&BAR_NS1.crt().chain));
+
+            let ta = FOO_NS1.trust_anchors().with_crl(crl.clone());
+            assert!(ta.certify(FOO_NS1.key(), FOO_NS1.crt()).is_err());
+            let ta = BAR_NS1.trust_anchors().with_crl(crl.clone());
+            assert!(ta.certify(BAR_NS1.key(), BAR_NS1.crt()).is_ok());
+        }
+    }
+
+    #[test]
+    fn restricts_tls_versions_and_cipher_suites() {
+        use super::{rustls::ProtocolVersion, CipherSuite, TlsVersion};
+
+        let suites = ["TLS_AES_128_GCM_SHA256", "TLS13_CHACHA20_POLY1305_SHA256"]
+            .iter()
+            .map(|s| s.parse::<CipherSuite>().unwrap())
+            .collect::<Vec<_>>();
+        assert!("TLS_RSA_WITH_RC4_128_MD5".parse::<CipherSuite>().is_err());
+        assert_eq!("1.3".parse(), Ok(TlsVersion::Tls13));
+
+        let crt_key = FOO_NS1
+            .trust_anchors()
+            .with_tls_versions(TlsVersion::Tls13, TlsVersion::Tls13)
+            .with_cipher_suites(&suites)
+            .certify(FOO_NS1.key(), FOO_NS1.crt())
+            .expect("foo.ns1 must be valid");
+        assert_eq!(
+            crt_key.client_config.versions,
+            vec![ProtocolVersion::TLSv1_3]
+        );
+        assert_eq!(
+            crt_key.server_config.versions,
+            vec![ProtocolVersion::TLSv1_3]
+        );
+        assert_eq!(crt_key.server_config.ciphersuites.len(), 2);
+    }
+
+    #[test]
+    fn enables_session_tickets_for_resumption() {
+        let crt_key = FOO_NS1.validate().expect("foo.ns1 must be valid");
+        assert!(!crt_key.client_config.enable_tickets);
+
+        let ta = FOO_NS1.trust_anchors().with_session_resumption(64);
+        assert!(ta.config.enable_tickets);
+        let crt_key = ta
+            .certify(FOO_NS1.key(), FOO_NS1.crt())
+            .expect("foo.ns1 must be valid");
+        assert!(crt_key.client_config.enable_tickets);
+    }
+
+    #[test]
+    fn reads_pem_crts() {
+        let pem = include_bytes!("testdata/ca1.pem");
+        let name = super::Name::from_hostname(FOO_NS1.name.as_bytes()).unwrap();
+        let crt = super::Crt::from_pem(name.clone(), pem, super::SystemTime::now());
+        assert_eq!(crt.map(|c| c.chain.len()), Some(1));
+        assert!(super::Crt::from_pem(name, b"", super::SystemTime::now()).is_none());
+        assert!(super::Key::from_pem(b"").is_err());
+    }
+
+    #[test]
+    fn writes_nss_key_log() {
+        use super::rustls::KeyLog;
+
+        let path = ::std::env::temp_dir().join(format!("key-log-{}.txt", ::std::process::id()));
+        let key_log = super::KeyLog::open(&path).expect("key log must open");
+        key_log.0.log("CLIENT_RANDOM", &[0x01, 0xab], &[0xff]);
+        key_log.0.log("CLIENT_RANDOM", &[0x02], &[0x00, 0x10]);
+        let logged = super::fs::read_to_string(&path).unwrap();
+        let _ = super::fs::remove_file(&path);
+        assert_eq!(logged, "CLIENT_RANDOM 01ab ff\nCLIENT_RANDOM 02 0010\n");
+    }
+
     #[test]
     fn recognize_ca_did_not_issue_cert() {
         let s = Strings {
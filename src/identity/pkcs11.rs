@@ -0,0 +1,215 @@
+//! Signs with an ECDSA P-256 private key that is held by a PKCS#11 token, e.g.
+//! an HSM or a TPM, so that the key is never present in the proxy's memory.
+//!
+//! The token must hold both halves of the key pair under the same label: the
+//! public key is read once, so that certificate signing requests may be
+//! generated, while every signature is made by the token.
+
+extern crate pkcs11;
+
+use self::pkcs11::types::*;
+use self::pkcs11::Ctx;
+use std::path::Path;
+use std::sync::Mutex;
+use std::{error, fmt, ptr};
+
+use super::ring::digest;
+use super::{x509, Key, Sign, SignError};
+
+/// Identifies a key pair in a PKCS#11 token.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The path to the token's PKCS#11 module (a shared library).
+    pub module: String,
+    pub slot: CK_SLOT_ID,
+    pub label: String,
+    /// The user PIN, if the token requires a login.
+    pub pin: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Pkcs11(pkcs11::errors::Error),
+    NotFound(&'static str, String),
+    InvalidPublicKey,
+}
+
+struct Pkcs11Key {
+    label: String,
+    public_key: Vec<u8>,
+    /// Sessions must not be used concurrently.
+    session: Mutex<Session>,
+}
+
+struct Session {
+    ctx: Ctx,
+    handle: CK_SESSION_HANDLE,
+    key: CK_OBJECT_HANDLE,
+}
+
+/// Opens a session with the token and finds the key pair.
+pub fn open(config: &Config) -> Result<Key, Error> {
+    let ctx = Ctx::new_and_initialize(Path::new(&config.module))?;
+    let handle = ctx.open_session(config.slot, CKF_SERIAL_SESSION, None, None)?;
+    if let Some(ref pin) = config.pin {
+        ctx.login(handle, CKU_USER, Some(pin.as_str()))?;
+    }
+
+    let key = find(&ctx, handle, CKO_PRIVATE_KEY, &config.label)?
+        .ok_or_else(|| Error::NotFound("private key", config.label.clone()))?;
+    let public = find(&ctx, handle, CKO_PUBLIC_KEY, &config.label)?
+        .ok_or_else(|| Error::NotFound("public key", config.label.clone()))?;
+    let public_key = ec_point(&ctx, handle, public)?;
+    debug!(
+        "found key {:?} in PKCS#11 slot {}",
+        config.label, config.slot
+    );
+
+    Ok(Key::new(Pkcs11Key {
+        label: config.label.clone(),
+        public_key,
+        session: Mutex::new(Session { ctx, handle, key }),
+    }))
+}
+
+/// Finds the object of the given class with the given label.
+fn find(
+    ctx: &Ctx,
+    session: CK_SESSION_HANDLE,
+    class: CK_OBJECT_CLASS,
+    label: &str,
+) -> Result<Option<CK_OBJECT_HANDLE>, Error> {
+    let template = vec![
+        CK_ATTRIBUTE::new(CKA_CLASS).with_ck_ulong(&class),
+        CK_ATTRIBUTE::new(CKA_LABEL).with_bytes(label.as_bytes()),
+    ];
+    ctx.find_objects_init(session, &template)?;
+    let found = ctx.find_objects(session, 1);
+    ctx.find_objects_final(session)?;
+    Ok(found?.into_iter().next())
+}
+
+/// Reads a public key's uncompressed point.
+fn ec_point(
+    ctx: &Ctx,
+    session: CK_SESSION_HANDLE,
+    public: CK_OBJECT_HANDLE,
+) -> Result<Vec<u8>, Error> {
+    // The first call determines the attribute's length.
+    let mut template = vec![CK_ATTRIBUTE::new(CKA_EC_POINT)];
+    let (_, attrs) = ctx.get_attribute_value(session, public, &mut template)?;
+    let len = attrs.first().ok_or(Error::InvalidPublicKey)?.ulValueLen as usize;
+
+    let value = vec![0; len];
+    let mut template = vec![CK_ATTRIBUTE::new(CKA_EC_POINT).with_bytes(&value)];
+    ctx.get_attribute_value(session, public, &mut template)?;
+
+    decode_ec_point(&value)
+}
+
+/// Decodes an uncompressed P-256 point from the DER-encoded OCTET STRING in
+/// which tokens hold it.
+fn decode_ec_point(der: &[u8]) -> Result<Vec<u8>, Error> {
+    match der.split_first() {
+        Some((&0x04, rest)) if rest.len() == 66 && rest[0] == 65 => Ok(rest[1..].to_vec()),
+        _ => Err(Error::InvalidPublicKey),
+    }
+}
+
+// === impl Pkcs11Key ===
+
+impl Sign for Pkcs11Key {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SignError> {
+        // CKM_ECDSA signs a digest that has already been computed.
+        let digest = digest::digest(&digest::SHA256, msg);
+        let mechanism = CK_MECHANISM {
+            mechanism: CKM_ECDSA,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+
+        let session = self.session.lock().expect("PKCS#11 session lock poisoned");
+        let r_s = session
+            .ctx
+            .sign_init(session.handle, &mechanism, session.key)
+            .and_then(|()| session.ctx.sign(session.handle, digest.as_ref()))
+            .map_err(|e| SignError(e.to_string()))?;
+        x509::ecdsa_signature(&r_s)
+            .ok_or_else(|| SignError(format!("invalid signature length: {}", r_s.len())))
+    }
+}
+
+impl fmt::Debug for Pkcs11Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pkcs11Key")
+            .field("label", &self.label)
+            .finish()
+    }
+}
+
+// === impl Error ===
+
+impl From<pkcs11::errors::Error> for Error {
+    fn from(e: pkcs11::errors::Error) -> Self {
+        Error::Pkcs11(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Pkcs11(ref e) => fmt::Display::fmt(e, f),
+            Error::NotFound(kind, ref label) => write!(f, "no {} labeled {:?}", kind, label),
+            Error::InvalidPublicKey => write!(f, "public key is not an uncompressed P-256 point"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_uncompressed_points() {
+        let mut point = vec![0x04];
+        point.extend((0..64).map(|i| i as u8));
+
+        let mut der = vec![0x04, 65];
+        der.extend(&point);
+        assert_eq!(decode_ec_point(&der).unwrap(), point);
+    }
+
+    #[test]
+    fn rejects_invalid_points() {
+        // Not an OCTET STRING.
+        assert!(decode_ec_point(&[0x03, 65]).is_err());
+        // Truncated.
+        assert!(decode_ec_point(&[0x04, 65, 0x04]).is_err());
+        // A compressed point.
+        let mut der = vec![0x04, 33, 0x02];
+        der.extend(&[0; 32]);
+        assert!(decode_ec_point(&der).is_err());
+        assert!(decode_ec_point(&[]).is_err());
+    }
+
+    #[test]
+    fn fails_to_open_a_missing_module() {
+        let config = Config {
+            module: "/nonexistent/libpkcs11.so".into(),
+            slot: 0,
+            label: "linkerd".into(),
+            pin: None,
+        };
+        match open(&config) {
+            Err(Error::Pkcs11(_)) => {}
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("opened a missing module"),
+        }
+    }
+}
@@ -21,6 +21,13 @@ pub static FOO_NS1: Strings = Strings {
     //csr: "foo-ns1-ca1/csr.der",
 };
 
+pub static FOO_NS1_SPIFFE: Strings = Strings {
+    name: "spiffe://cluster.local/ns/ns1/sa/foo",
+    trust_anchors: "ca1.pem",
+    crt: "foo-ns1-spiffe-ca1/crt.der",
+    key: "foo-ns1-spiffe-ca1/key.p8",
+};
+
 pub static BAR_NS1: Strings = Strings {
     name: "bar.ns1.serviceaccount.identity.linkerd.cluster.local",
     trust_anchors: "ca1.pem",
@@ -55,7 +62,7 @@ impl Strings {
     pub fn crt(&self) -> Crt {
         const HOUR: Duration = Duration::from_secs(60 * 60);
 
-        let n = Name::from_hostname(self.name.as_bytes()).expect("name must be valid");
+        let n = Name::parse(self.name.as_bytes()).expect("name must be valid");
         let der = Self::read(&self.crt);
         Crt::new(n, der, vec![], SystemTime::now() + HOUR)
     }
@@ -25,7 +25,25 @@ ee() {
 
   hostname="${ee_name}.${ee_ns}.serviceaccount.identity.${cp_ns}.cluster.local"
 
-  ee="${ee_name}-${ee_ns}-${ca_name}"
+  gen "${ca_name}" "${ee_name}-${ee_ns}-${ca_name}" "${hostname}"
+}
+
+# An end entity whose identity is a SPIFFE ID, presented as a URI SAN.
+ee_spiffe() {
+  ca_name=$1
+  ee_name=$2
+  ee_ns=$3
+
+  uri="spiffe://cluster.local/ns/${ee_ns}/sa/${ee_name}"
+
+  gen "${ca_name}" "${ee_name}-${ee_ns}-spiffe-${ca_name}" "${uri}"
+}
+
+gen() {
+  ca_name=$1
+  ee=$2
+  hostname=$3
+
   echo '{}' \
     | cfssl gencert -ca "${ca_name}.pem" -ca-key "${ca_name}-key.pem" -hostname "${hostname}" - \
     | cfssljson -bare "${ee}"
@@ -57,3 +75,23 @@ ee ca1 controller linkerd linkerd
 ee ca1 foo ns1 linkerd
 ee ca2 foo ns1 linkerd # Same, but different CA
 ee ca1 bar ns1 linkerd # Different service.
+ee_spiffe ca1 foo ns1
+
+# A CRL that revokes foo.ns1's certificate from ca1.
+crl_dir=$(mktemp -d)
+touch "${crl_dir}/index.txt"
+cat > "${crl_dir}/ca.cnf" <<CNF
+[ca]
+default_ca = ca1
+[ca1]
+database = ${crl_dir}/index.txt
+certificate = ca1.pem
+private_key = ca1-key.pem
+default_md = sha256
+default_crl_days = 3650
+CNF
+openssl x509 -inform der -in foo-ns1-ca1/crt.der -out "${crl_dir}/foo.pem"
+openssl ca -config "${crl_dir}/ca.cnf" -revoke "${crl_dir}/foo.pem"
+openssl ca -config "${crl_dir}/ca.cnf" -gencrl -out ca1-crl.pem
+openssl crl -in ca1-crl.pem -outform der -out ca1-crl.der
+rm -r "${crl_dir}"
@@ -0,0 +1,546 @@
+//! Reads descriptive fields from DER-encoded X.509 certificates and
+//! certificate revocation lists, and encodes certificate signing requests.
+//!
+//! Certificates are verified by webpki, which does not expose their contents;
+//! this only reads the fields that are useful when debugging, and does not
+//! validate the certificate.
+
+use std::fmt::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const INTEGER: u8 = 0x02;
+const OID: u8 = 0x06;
+const OCTET_STRING: u8 = 0x04;
+const BOOLEAN: u8 = 0x01;
+const BIT_STRING: u8 = 0x03;
+const UTF8_STRING: u8 = 0x0c;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const VERSION: u8 = 0xa0;
+const ISSUER_UID: u8 = 0x81;
+const SUBJECT_UID: u8 = 0x82;
+const EXTENSIONS: u8 = 0xa3;
+const CSR_ATTRIBUTES: u8 = 0xa0;
+const SAN_DNS: u8 = 0x82;
+const SAN_URI: u8 = 0x86;
+
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EXTENSION_REQUEST: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x0e];
+const ATTRIBUTES: &[(&[u8], &str)] = &[
+    (&[0x55, 0x04, 0x03], "CN"),
+    (&[0x55, 0x04, 0x06], "C"),
+    (&[0x55, 0x04, 0x07], "L"),
+    (&[0x55, 0x04, 0x08], "ST"),
+    (&[0x55, 0x04, 0x0a], "O"),
+    (&[0x55, 0x04, 0x0b], "OU"),
+];
+
+/// Describes a certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Info {
+    /// The hex-encoded serial number.
+    pub serial: String,
+    /// The issuer's distinguished name, e.g. `CN=identity.linkerd.cluster.local`.
+    pub issuer: String,
+    pub not_before: SystemTime,
+    pub not_after: SystemTime,
+    /// The DNS names and URIs for which the certificate is valid.
+    pub sans: Vec<String>,
+}
+
+/// The fields of a certificate's `TBSCertificate` that are read.
+struct Tbs<'a> {
+    serial: &'a [u8],
+    issuer: &'a [u8],
+    validity: &'a [u8],
+    extensions: Option<&'a [u8]>,
+}
+
+/// Reads the fields of a DER-encoded certificate, if it is well-formed.
+pub fn parse(der: &[u8]) -> Option<Info> {
+    let tbs = tbs(der)?;
+
+    let (tag, not_before, validity) = read_any(tbs.validity)?;
+    let not_before = any_time(tag, not_before)?;
+    let (tag, not_after, _) = read_any(validity)?;
+    let not_after = any_time(tag, not_after)?;
+
+    let sans = match tbs.extensions {
+        Some(exts) => sans(exts, &[SAN_DNS, SAN_URI])?,
+        None => Vec::new(),
+    };
+
+    Some(Info {
+        serial: hex(tbs.serial),
+        issuer: name(tbs.issuer)?,
+        not_before,
+        not_after,
+        sans,
+    })
+}
+
+/// Reads the URIs of a DER-encoded certificate's subject alternative names.
+pub fn uri_sans(der: &[u8]) -> Option<Vec<String>> {
+    match tbs(der)?.extensions {
+        Some(exts) => sans(exts, &[SAN_URI]),
+        None => Some(Vec::new()),
+    }
+}
+
+/// Reads the raw issuer name and serial number of a DER-encoded certificate,
+/// which together identify it in a CRL.
+pub fn issuer_and_serial(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let tbs = tbs(der)?;
+    Some((tbs.issuer, tbs.serial))
+}
+
+/// Reads the raw issuer name and the serial numbers of the revoked
+/// certificates of a DER-encoded CRL.
+///
+/// The CRL's signature is not verified.
+pub fn crl_revoked(der: &[u8]) -> Option<(&[u8], Vec<&[u8]>)> {
+    let (crl, _) = read(der, SEQUENCE)?;
+    let (tbs, _) = read(crl, SEQUENCE)?;
+
+    let tbs = skip(tbs, INTEGER);
+    let (_signature, tbs) = read(tbs, SEQUENCE)?;
+    let (issuer, tbs) = read(tbs, SEQUENCE)?;
+    let (_this_update, _, tbs) = read_any(tbs)?;
+    let tbs = skip(skip(tbs, UTC_TIME), GENERALIZED_TIME);
+
+    let mut serials = Vec::new();
+    if let Some((mut revoked, _)) = read(tbs, SEQUENCE) {
+        while !revoked.is_empty() {
+            let (entry, rest) = read(revoked, SEQUENCE)?;
+            revoked = rest;
+            let (serial, _) = read(entry, INTEGER)?;
+            serials.push(serial);
+        }
+    }
+    Some((issuer, serials))
+}
+
+/// Encodes the `CertificationRequestInfo` of a PKCS#10 request for an ECDSA
+/// P-256 public key, requesting the given DNS and URI subject alternative
+/// names.
+///
+/// The request is completed by `csr`, once this has been signed.
+pub fn csr_info(
+    common_name: Option<&str>,
+    public_key: &[u8],
+    dns_sans: &[&str],
+    uri_sans: &[&str],
+) -> Vec<u8> {
+    let subject = match common_name {
+        Some(cn) => write(
+            SET,
+            &write(
+                SEQUENCE,
+                &[
+                    write(OID, OID_COMMON_NAME),
+                    write(UTF8_STRING, cn.as_bytes()),
+                ]
+                .concat(),
+            ),
+        ),
+        None => Vec::new(),
+    };
+
+    let spki = [
+        write(
+            SEQUENCE,
+            &[write(OID, OID_EC_PUBLIC_KEY), write(OID, OID_P256)].concat(),
+        ),
+        write_bits(public_key),
+    ]
+    .concat();
+
+    let names = dns_sans
+        .iter()
+        .map(|n| write(SAN_DNS, n.as_bytes()))
+        .chain(uri_sans.iter().map(|u| write(SAN_URI, u.as_bytes())))
+        .collect::<Vec<_>>()
+        .concat();
+    let san = [
+        write(OID, OID_SUBJECT_ALT_NAME),
+        write(OCTET_STRING, &write(SEQUENCE, &names)),
+    ]
+    .concat();
+    let extension_request = [
+        write(OID, OID_EXTENSION_REQUEST),
+        write(SET, &write(SEQUENCE, &write(SEQUENCE, &san))),
+    ]
+    .concat();
+
+    write(
+        SEQUENCE,
+        &[
+            write(INTEGER, &[0]),
+            write(SEQUENCE, &subject),
+            write(SEQUENCE, &spki),
+            write(CSR_ATTRIBUTES, &write(SEQUENCE, &extension_request)),
+        ]
+        .concat(),
+    )
+}
+
+/// Encodes a PKCS#10 request from its `CertificationRequestInfo` and the
+/// ASN.1-encoded ECDSA P-256 SHA-256 signature of it.
+pub fn csr(info: &[u8], signature: &[u8]) -> Vec<u8> {
+    write(
+        SEQUENCE,
+        &[
+            info.to_vec(),
+            write(SEQUENCE, &write(OID, OID_ECDSA_SHA256)),
+            write_bits(signature),
+        ]
+        .concat(),
+    )
+}
+
+/// Encodes an ECDSA signature that is represented as the concatenation of its
+/// fixed-width `r` and `s` values, as PKCS#11 tokens return them, in ASN.1.
+#[cfg_attr(not(feature = "pkcs11"), allow(dead_code))]
+pub fn ecdsa_signature(r_s: &[u8]) -> Option<Vec<u8>> {
+    if r_s.is_empty() || r_s.len() % 2 != 0 {
+        return None;
+    }
+    let (r, s) = r_s.split_at(r_s.len() / 2);
+    Some(write(SEQUENCE, &[write_uint(r), write_uint(s)].concat()))
+}
+
+fn tbs(der: &[u8]) -> Option<Tbs> {
+    let (crt, _) = read(der, SEQUENCE)?;
+    let (tbs, _) = read(crt, SEQUENCE)?;
+
+    let tbs = skip(tbs, VERSION);
+    let (serial, tbs) = read(tbs, INTEGER)?;
+    let (_signature, tbs) = read(tbs, SEQUENCE)?;
+    let (issuer, tbs) = read(tbs, SEQUENCE)?;
+    let (validity, tbs) = read(tbs, SEQUENCE)?;
+    let (_subject, tbs) = read(tbs, SEQUENCE)?;
+    let (_spki, tbs) = read(tbs, SEQUENCE)?;
+    let tbs = skip(skip(tbs, ISSUER_UID), SUBJECT_UID);
+
+    let extensions = match read(tbs, EXTENSIONS) {
+        Some((exts, _)) => Some(read(exts, SEQUENCE)?.0),
+        None => None,
+    };
+
+    Some(Tbs {
+        serial,
+        issuer,
+        validity,
+        extensions,
+    })
+}
+
+/// Reads a value, returning its tag, its contents, and the remaining input.
+fn read_any(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&len, mut input) = input.split_first()?;
+    let len = if len < 0x80 {
+        usize::from(len)
+    } else {
+        // The long form encodes the length in up to 3 bytes.
+        let n = usize::from(len & 0x7f);
+        if n == 0 || n > 3 || input.len() < n {
+            return None;
+        }
+        let (bytes, rest) = input.split_at(n);
+        input = rest;
+        bytes.iter().fold(0, |len, b| (len << 8) | usize::from(*b))
+    };
+    if input.len() < len {
+        return None;
+    }
+    let (value, rest) = input.split_at(len);
+    Some((tag, value, rest))
+}
+
+/// Reads a value with the given tag.
+fn read(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match read_any(input)? {
+        (t, value, rest) if t == tag => Some((value, rest)),
+        _ => None,
+    }
+}
+
+/// Skips an optional value with the given tag.
+fn skip(input: &[u8], tag: u8) -> &[u8] {
+    read(input, tag).map(|(_, rest)| rest).unwrap_or(input)
+}
+
+/// Encodes a value with the given tag.
+fn write(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len() + 4);
+    out.push(tag);
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else if len <= 0xff {
+        out.extend_from_slice(&[0x81, len as u8]);
+    } else {
+        assert!(len <= 0xffff, "DER value too long");
+        out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+    }
+    out.extend_from_slice(value);
+    out
+}
+
+/// Encodes a big-endian unsigned integer minimally.
+fn write_uint(mut value: &[u8]) -> Vec<u8> {
+    while value.len() > 1 && value[0] == 0 {
+        value = &value[1..];
+    }
+    if value.first().map(|b| b & 0x80 != 0).unwrap_or(false) {
+        write(INTEGER, &[&[0][..], value].concat())
+    } else {
+        write(INTEGER, value)
+    }
+}
+
+/// Encodes a bit string with no unused bits.
+fn write_bits(value: &[u8]) -> Vec<u8> {
+    write(BIT_STRING, &[&[0][..], value].concat())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    // Positive serial numbers may be prefixed with a zero byte.
+    let bytes = match bytes.split_first() {
+        Some((&0, rest)) if !rest.is_empty() => rest,
+        _ => bytes,
+    };
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{:02x}", b).expect("writing to a string must not fail");
+    }
+    s
+}
+
+/// Formats the well-known attributes of a distinguished name.
+fn name(mut rdns: &[u8]) -> Option<String> {
+    let mut attrs = Vec::new();
+    while !rdns.is_empty() {
+        let (mut set, rest) = read(rdns, SET)?;
+        rdns = rest;
+        while !set.is_empty() {
+            let (attr, rest) = read(set, SEQUENCE)?;
+            set = rest;
+            let (oid, attr) = read(attr, OID)?;
+            let (_, value, _) = read_any(attr)?;
+            if let Some(&(_, label)) = ATTRIBUTES.iter().find(|&&(o, _)| o == oid) {
+                attrs.push(format!("{}={}", label, String::from_utf8_lossy(value)));
+            }
+        }
+    }
+    Some(attrs.join(","))
+}
+
+/// Reads the names of the subject alternative name extension that have the
+/// given tags.
+fn sans(mut exts: &[u8], tags: &[u8]) -> Option<Vec<String>> {
+    while !exts.is_empty() {
+        let (ext, rest) = read(exts, SEQUENCE)?;
+        exts = rest;
+        let (oid, ext) = read(ext, OID)?;
+        if oid != OID_SUBJECT_ALT_NAME {
+            continue;
+        }
+
+        let (value, _) = read(skip(ext, BOOLEAN), OCTET_STRING)?;
+        let (mut names, _) = read(value, SEQUENCE)?;
+        let mut sans = Vec::new();
+        while !names.is_empty() {
+            let (tag, name, rest) = read_any(names)?;
+            names = rest;
+            if tags.contains(&tag) {
+                sans.push(String::from_utf8_lossy(name).into_owned());
+            }
+        }
+        return Some(sans);
+    }
+    Some(Vec::new())
+}
+
+fn any_time(tag: u8, t: &[u8]) -> Option<SystemTime> {
+    match tag {
+        UTC_TIME => time(t, false),
+        GENERALIZED_TIME => time(t, true),
+        _ => None,
+    }
+}
+
+/// Reads a `UTCTime` or `GeneralizedTime` in UTC.
+fn time(t: &[u8], generalized: bool) -> Option<SystemTime> {
+    let t = ::std::str::from_utf8(t).ok()?;
+    if !t.ends_with('Z') || !t[..t.len() - 1].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let num = |s: &str| s.parse::<u64>().ok();
+    let (year, t) = if generalized {
+        (num(t.get(..4)?)?, &t[4..])
+    } else {
+        // Two-digit years from 50 are in the 20th century.
+        let yy = num(t.get(..2)?)?;
+        (if yy < 50 { 2000 + yy } else { 1900 + yy }, &t[2..])
+    };
+    if t.len() != 11 {
+        return None;
+    }
+    let (month, day) = (num(&t[..2])?, num(&t[2..4])?);
+    let (hour, min, sec) = (num(&t[4..6])?, num(&t[6..8])?, num(&t[8..10])?);
+    if month < 1 || month > 12 || day < 1 || day > 31 || year < 1970 {
+        return None;
+    }
+
+    let secs = days_since_epoch(year, month, day) * 86_400 + hour * 3_600 + min * 60 + sec;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Counts the days from 1970-01-01 to the given date.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Count years from March, so that leap days fall at the end of a year.
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_crt() {
+        let info = parse(include_bytes!("testdata/foo-ns1-ca1/crt.der")).unwrap();
+        assert_eq!(info.serial, "28247957eff46da4ea818c798ff3ca2f4afdc518");
+        assert_eq!(info.issuer, "OU=None");
+        assert_eq!(
+            info.not_before,
+            UNIX_EPOCH + Duration::from_secs(1_552_586_520)
+        );
+        assert_eq!(
+            info.not_after,
+            UNIX_EPOCH + Duration::from_secs(1_584_122_520)
+        );
+        assert_eq!(
+            info.sans,
+            vec!["foo.ns1.serviceaccount.identity.linkerd.cluster.local".to_owned()]
+        );
+    }
+
+    #[test]
+    fn reads_uri_sans() {
+        let der = include_bytes!("testdata/foo-ns1-ca1/crt.der");
+        assert_eq!(uri_sans(der), Some(vec![]));
+
+        let der = include_bytes!("testdata/foo-ns1-spiffe-ca1/crt.der");
+        assert_eq!(
+            uri_sans(der),
+            Some(vec!["spiffe://cluster.local/ns/ns1/sa/foo".to_owned()])
+        );
+    }
+
+    #[test]
+    fn reads_crls() {
+        let crt = include_bytes!("testdata/foo-ns1-ca1/crt.der");
+        let (issuer, serial) = issuer_and_serial(crt).unwrap();
+
+        let (crl_issuer, serials) = crl_revoked(include_bytes!("testdata/ca1-crl.der")).unwrap();
+        assert_eq!(crl_issuer, issuer);
+        assert_eq!(serials, vec![serial]);
+    }
+
+    #[test]
+    fn encodes_csrs() {
+        let info = csr_info(
+            Some("foo.ns1"),
+            &[4; 65],
+            &["foo.ns1", "foo.example.com"],
+            &["spiffe://cluster.local/ns/ns1/sa/foo"],
+        );
+        let der = csr(&info, &[1, 2, 3]);
+
+        let (req, _) = read(&der, SEQUENCE).unwrap();
+        let (info, req) = read(req, SEQUENCE).unwrap();
+        let (_alg, req) = read(req, SEQUENCE).unwrap();
+        let (signature, _) = read(req, BIT_STRING).unwrap();
+        assert_eq!(signature, &[0, 1, 2, 3]);
+
+        let info = skip(info, INTEGER);
+        let (subject, info) = read(info, SEQUENCE).unwrap();
+        assert_eq!(name(subject), Some("CN=foo.ns1".to_owned()));
+        let (_spki, info) = read(info, SEQUENCE).unwrap();
+        let (attrs, _) = read(info, CSR_ATTRIBUTES).unwrap();
+        let (attr, _) = read(attrs, SEQUENCE).unwrap();
+        let (oid, attr) = read(attr, OID).unwrap();
+        assert_eq!(oid, OID_EXTENSION_REQUEST);
+        let (exts, _) = read(read(attr, SET).unwrap().0, SEQUENCE).unwrap();
+        assert_eq!(
+            sans(exts, &[SAN_DNS]),
+            Some(vec!["foo.ns1".to_owned(), "foo.example.com".to_owned()])
+        );
+        assert_eq!(
+            sans(exts, &[SAN_URI]),
+            Some(vec!["spiffe://cluster.local/ns/ns1/sa/foo".to_owned()])
+        );
+    }
+
+    #[test]
+    fn encodes_ecdsa_signatures() {
+        let mut r_s = vec![0; 64];
+        r_s[1] = 0x7f;
+        r_s[32] = 0x80;
+        let der = ecdsa_signature(&r_s).unwrap();
+
+        let (sig, _) = read(&der, SEQUENCE).unwrap();
+        let (r, sig) = read(sig, INTEGER).unwrap();
+        let (s, _) = read(sig, INTEGER).unwrap();
+        assert_eq!(r, &r_s[1..32]);
+        assert_eq!(s, &[&[0][..], &r_s[32..]].concat()[..]);
+
+        assert!(ecdsa_signature(&[1, 2, 3]).is_none());
+    }
+
+    #[test]
+    fn encodes_long_lengths() {
+        let value = vec![7; 300];
+        let der = write(OCTET_STRING, &value);
+        assert_eq!(&der[..4], &[OCTET_STRING, 0x82, 0x01, 0x2c]);
+        assert_eq!(read(&der, OCTET_STRING), Some((&value[..], &[][..])));
+    }
+
+    #[test]
+    fn rejects_truncated_crts() {
+        let der = include_bytes!("testdata/foo-ns1-ca1/crt.der");
+        assert!(parse(&der[..der.len() / 2]).is_none());
+    }
+
+    #[test]
+    fn parses_times() {
+        let t = |s: &str, g| time(s.as_bytes(), g).map(|t| t.duration_since(UNIX_EPOCH).unwrap());
+        assert_eq!(t("700101000000Z", false), Some(Duration::from_secs(0)));
+        assert_eq!(
+            t("20000301000000Z", true),
+            Some(Duration::from_secs(951_868_800))
+        );
+        assert_eq!(
+            t("491231235959Z", false),
+            Some(Duration::from_secs(2_524_607_999))
+        );
+        assert_eq!(t("20000301000000", true), None);
+    }
+}
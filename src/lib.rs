@@ -1,8 +1,10 @@
 #![deny(warnings)]
 #![recursion_limit = "128"]
 
+extern crate base64;
 extern crate bytes;
 extern crate env_logger;
+extern crate flate2;
 #[macro_use]
 extern crate futures;
 extern crate futures_mpsc_lossy;
@@ -18,10 +20,13 @@ extern crate libc;
 extern crate log;
 #[cfg_attr(test, macro_use)]
 extern crate indexmap;
+extern crate net2;
 #[cfg(target_os = "linux")]
 extern crate procinfo;
 extern crate prost;
 extern crate prost_types;
+#[macro_use]
+extern crate prost_derive;
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
@@ -46,6 +51,7 @@ use self::linkerd2_metrics as metrics;
 
 mod addr;
 pub mod app;
+mod backoff;
 mod conditional;
 pub mod control;
 pub mod convert;
@@ -57,6 +63,7 @@ mod proxy;
 mod svc;
 mod tap;
 pub mod telemetry;
+mod token_bucket;
 pub mod transport;
 
 use self::addr::{Addr, NameAddr};
@@ -21,10 +21,21 @@ fn main() {
             process::exit(64)
         }
     };
-    // NOTE: eventually, this is where we would choose to use the threadpool
-    //       runtime instead, if acting as an ingress proxy.
-    let runtime = tokio::runtime::current_thread::Runtime::new().expect("initialize main runtime");
-    let main = linkerd2_proxy::app::Main::new(config, linkerd2_proxy::SoOriginalDst, runtime);
+    // When connections are accepted by several acceptors, they only scale
+    // across cores if the acceptors' tasks may run on different threads.
+    let acceptors = config
+        .inbound_listener
+        .options
+        .acceptors
+        .max(config.outbound_listener.options.acceptors);
+    let main = if acceptors > 1 {
+        let runtime = tokio::runtime::Runtime::new().expect("initialize main runtime");
+        linkerd2_proxy::app::Main::new(config, linkerd2_proxy::SoOriginalDst, runtime)
+    } else {
+        let runtime =
+            tokio::runtime::current_thread::Runtime::new().expect("initialize main runtime");
+        linkerd2_proxy::app::Main::new(config, linkerd2_proxy::SoOriginalDst, runtime)
+    };
     let shutdown_signal = signal::shutdown();
     main.run_until(shutdown_signal);
 }
@@ -0,0 +1,783 @@
+//! Authorizes inbound requests and connections.
+//!
+//! A policy is an ordered list of rules, each of which allows or denies the
+//! traffic it matches. Rules match on the client's verified identity, the
+//! client's network, and the target port and (for HTTP requests) path; the
+//! first matching rule decides, and traffic that matches no rule is decided by
+//! the policy's default action.
+//!
+//! Paths are normalized before they are matched, so that a rule cannot be
+//! bypassed by an equivalent spelling of a path: unreserved characters are
+//! percent-decoded, repeated slashes are collapsed, and dot segments are
+//! resolved. A rule's path matches whole segments, so `path=/admin` matches
+//! `/admin` and `/admin/users` but not `/administrator`.
+//!
+//! An encoded `/` or `\` (`%2F` or `%5C`) is not a separator to the policy,
+//! but an application may decode it into one before resolving the path, so
+//! requests whose paths contain them are rejected with a `400 Bad Request`
+//! response when the policy has path rules.
+//!
+//! Denied HTTP requests fail with a `403 Forbidden` response, while denied
+//! connections that are not HTTP are closed.
+
+use futures::{Async, Future, Poll};
+use http;
+use indexmap::IndexMap;
+use ipnet::IpNet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::{error, fmt};
+
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics};
+use proxy::server::{AuthorizeForward, Source};
+use svc;
+use transport::mesh;
+use Conditional;
+
+metrics! {
+    authz_decisions_total: Counter {
+        "Total count of requests and connections allowed or denied, by authorization rule"
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+/// Matches traffic to which an `Action` applies.
+///
+/// Each criterion is optional; a rule without any criteria matches all
+/// traffic.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    action: Action,
+    identities: Option<Vec<IdentityMatch>>,
+    networks: Option<mesh::Networks>,
+    port: Option<u16>,
+    /// A normalized path.
+    path_prefix: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum IdentityMatch {
+    /// Matches any verified identity.
+    Any,
+    Exact(String),
+    /// Matches identities that end with the given suffix, which starts with
+    /// a `.`.
+    Suffix(String),
+}
+
+/// An ordered list of rules.
+///
+/// The default policy allows all traffic.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+    default: Action,
+}
+
+pub fn new(policy: Policy) -> (Authorize, Report) {
+    let decisions = Arc::new(Mutex::new(IndexMap::new()));
+    let authorize = Authorize {
+        policy: Arc::new(policy),
+        decisions: decisions.clone(),
+    };
+    (authorize, Report(decisions))
+}
+
+/// Applies a `Policy`, recording each decision.
+#[derive(Clone, Debug)]
+pub struct Authorize {
+    policy: Arc<Policy>,
+    decisions: Arc<Mutex<Decisions>>,
+}
+
+/// Implements `FmtMetrics` to render prometheus-formatted decision counts.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Decisions>>);
+
+type Decisions = IndexMap<DecisionLabels, Counter>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct DecisionLabels {
+    /// The index of the rule that decided, or `None` for the default action.
+    rule: Option<usize>,
+    action: Action,
+}
+
+/// Indicates that traffic was denied by the authorization policy.
+#[derive(Clone, Debug)]
+pub struct Denied {
+    rule: Option<usize>,
+}
+
+pub fn layer(authorize: Authorize) -> Layer {
+    Layer { authorize }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    authorize: Authorize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    authorize: Authorize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    authorize: Authorize,
+    source: Source,
+}
+
+pub enum ResponseFuture<F> {
+    Authorized(F),
+    Denied,
+    BadRequest,
+}
+
+// === impl Action ===
+
+impl Default for Action {
+    fn default() -> Self {
+        Action::Allow
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("allow") => Ok(Action::Allow),
+            s if s.eq_ignore_ascii_case("deny") => Ok(Action::Deny),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Action::Allow => f.pad("allow"),
+            Action::Deny => f.pad("deny"),
+        }
+    }
+}
+
+// === impl Rule ===
+
+impl Rule {
+    /// Determines whether the rule applies to traffic from `source`.
+    ///
+    /// Connections that are not HTTP have no `path`, and so they are not
+    /// matched by rules that constrain the path.
+    fn matches(&self, source: &Source, path: Option<&str>) -> bool {
+        if let Some(ref identities) = self.identities {
+            let id = match source.tls_peer {
+                Conditional::Some(ref id) => id,
+                Conditional::None(_) => return false,
+            };
+            if !identities.iter().any(|m| m.matches(id.as_ref())) {
+                return false;
+            }
+        }
+
+        if let Some(ref networks) = self.networks {
+            if !networks.contains(source.remote.ip()) {
+                return false;
+            }
+        }
+
+        if let Some(port) = self.port {
+            if source.orig_dst.unwrap_or(source.local).port() != port {
+                return false;
+            }
+        }
+
+        if let Some(ref prefix) = self.path_prefix {
+            match path {
+                Some(path) if has_path_prefix(path, prefix) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Parses a rule from an action followed by whitespace-separated criteria,
+/// e.g. `allow id=*.ns1.serviceaccount.identity.linkerd.cluster.local
+/// net=10.0.0.0/8 port=8080 path=/api`.
+///
+/// The `id` and `net` criteria accept comma-separated lists.
+impl FromStr for Rule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let action = fields.next().ok_or(())?.parse()?;
+        let mut rule = Rule {
+            action,
+            identities: None,
+            networks: None,
+            port: None,
+            path_prefix: None,
+        };
+
+        for field in fields {
+            let mut kv = field.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some("id"), Some(ids)) => {
+                    let ids = ids
+                        .split(',')
+                        .map(IdentityMatch::from_str)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    rule.identities = Some(ids);
+                }
+                (Some("net"), Some(nets)) => {
+                    let nets = nets
+                        .split(',')
+                        .map(IpNet::from_str)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| ())?;
+                    rule.networks = Some(mesh::Networks::new(nets));
+                }
+                (Some("port"), Some(port)) => {
+                    rule.port = Some(port.parse().map_err(|_| ())?);
+                }
+                (Some("path"), Some(path)) if path.starts_with('/') => {
+                    rule.path_prefix = Some(normalize_path(path));
+                }
+                _ => return Err(()),
+            }
+        }
+
+        Ok(rule)
+    }
+}
+
+// === impl IdentityMatch ===
+
+impl IdentityMatch {
+    fn matches(&self, id: &str) -> bool {
+        match *self {
+            IdentityMatch::Any => true,
+            IdentityMatch::Exact(ref name) => id == name,
+            IdentityMatch::Suffix(ref suffix) => id.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+impl FromStr for IdentityMatch {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            Ok(IdentityMatch::Any)
+        } else if s.starts_with("*.") && s.len() > 2 {
+            Ok(IdentityMatch::Suffix(s[1..].to_owned()))
+        } else if !s.is_empty() && !s.contains('*') {
+            Ok(IdentityMatch::Exact(s.to_owned()))
+        } else {
+            Err(())
+        }
+    }
+}
+
+// === impl Policy ===
+
+impl Policy {
+    pub fn new(rules: Vec<Rule>, default: Action) -> Self {
+        Self { rules, default }
+    }
+
+    fn has_path_rules(&self) -> bool {
+        self.rules.iter().any(|rule| rule.path_prefix.is_some())
+    }
+
+    /// Finds the index of the first rule that matches, if any, and the
+    /// action that applies.
+    fn decide(&self, source: &Source, path: Option<&str>) -> (Option<usize>, Action) {
+        self.rules
+            .iter()
+            .position(|rule| rule.matches(source, path))
+            .map(|idx| (Some(idx), self.rules[idx].action))
+            .unwrap_or((None, self.default))
+    }
+}
+
+// === impl Authorize ===
+
+impl Authorize {
+    /// Authorizes a connection that is not HTTP.
+    pub fn connection(&self, source: &Source) -> Result<(), Denied> {
+        self.authorize(source, None)
+    }
+
+    /// Authorizes an HTTP request to `path`.
+    pub fn request(&self, source: &Source, path: &str) -> Result<(), Denied> {
+        self.authorize(source, Some(&normalize_path(path)))
+    }
+
+    /// Determines whether a request to `path` must be rejected because the
+    /// policy's path rules cannot be applied to it reliably.
+    fn rejects_path(&self, path: &str) -> bool {
+        self.policy.has_path_rules() && has_encoded_separator(path)
+    }
+
+    fn authorize(&self, source: &Source, path: Option<&str>) -> Result<(), Denied> {
+        let (rule, action) = self.policy.decide(source, path);
+        trace!("authz: rule={:?}; action={}; path={:?}", rule, action, path);
+        if let Ok(mut decisions) = self.decisions.lock() {
+            decisions
+                .entry(DecisionLabels { rule, action })
+                .or_insert_with(Counter::default)
+                .incr();
+        }
+
+        match action {
+            Action::Allow => Ok(()),
+            Action::Deny => Err(Denied { rule }),
+        }
+    }
+}
+
+impl AuthorizeForward for Authorize {
+    fn authorize_forward(
+        &self,
+        source: &Source,
+    ) -> Result<(), Box<dyn error::Error + Send + Sync>> {
+        self.connection(source)?;
+        Ok(())
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decisions = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if decisions.is_empty() {
+            return Ok(());
+        }
+
+        authz_decisions_total.fmt_help(f)?;
+        for (labels, count) in decisions.iter() {
+            count.fmt_metric_labeled(f, authz_decisions_total.name, labels)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for DecisionLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rule {
+            Some(idx) => write!(f, "rule=\"{}\",action=\"{}\"", idx, self.action),
+            None => write!(f, "rule=\"default\",action=\"{}\"", self.action),
+        }
+    }
+}
+
+// === impl Denied ===
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rule {
+            Some(idx) => write!(f, "denied by authorization rule {}", idx),
+            None => write!(f, "denied by default authorization policy"),
+        }
+    }
+}
+
+impl error::Error for Denied {}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Source, Source, M> for Layer
+where
+    M: svc::Stack<Source>,
+{
+    type Value = <Stack<M> as svc::Stack<Source>>::Value;
+    type Error = <Stack<M> as svc::Stack<Source>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            authorize: self.authorize.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Source> for Stack<M>
+where
+    M: svc::Stack<Source>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, source: &Source) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(source)?;
+        Ok(Service {
+            inner,
+            authorize: self.authorize.clone(),
+            source: source.clone(),
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Response = http::Response<B>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        if self.authorize.rejects_path(req.uri().path()) {
+            debug!(
+                "path has an encoded separator: {} {}",
+                req.method(),
+                req.uri().path()
+            );
+            return ResponseFuture::BadRequest;
+        }
+
+        if let Err(e) = self.authorize.request(&self.source, req.uri().path()) {
+            debug!("{}: {} {}", e, req.method(), req.uri().path());
+            return ResponseFuture::Denied;
+        }
+
+        ResponseFuture::Authorized(self.inner.call(req))
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = http::Response<B>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Authorized(ref mut f) => f.poll(),
+            ResponseFuture::Denied => {
+                let mut rsp = http::Response::default();
+                *rsp.status_mut() = http::StatusCode::FORBIDDEN;
+                Ok(Async::Ready(rsp))
+            }
+            ResponseFuture::BadRequest => {
+                let mut rsp = http::Response::default();
+                *rsp.status_mut() = http::StatusCode::BAD_REQUEST;
+                Ok(Async::Ready(rsp))
+            }
+        }
+    }
+}
+
+/// Normalizes a request path, so that equivalent paths are matched alike.
+///
+/// Percent-encoded unreserved characters are decoded (other encodings are
+/// kept, with uppercase hex digits), empty and `.` segments are removed, and
+/// `..` segments remove the preceding segment. The result has no trailing
+/// slash, except for the root path.
+fn normalize_path(path: &str) -> String {
+    let decoded = decode_unreserved(path);
+
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    if segments.is_empty() {
+        return "/".to_owned();
+    }
+    let mut normalized = String::with_capacity(decoded.len());
+    for segment in segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    normalized
+}
+
+fn decode_unreserved(path: &str) -> String {
+    fn hex(b: u8) -> Option<u8> {
+        (b as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                let b = (hi << 4) | lo;
+                if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                    decoded.push(b);
+                } else {
+                    decoded.push(b'%');
+                    decoded.push(bytes[i + 1].to_ascii_uppercase());
+                    decoded.push(bytes[i + 2].to_ascii_uppercase());
+                }
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    // Only ASCII escapes were replaced, so the result is still UTF-8.
+    String::from_utf8(decoded).expect("decoded path must be UTF-8")
+}
+
+/// Determines whether `path` contains a percent-encoded `/` or `\`.
+fn has_encoded_separator(path: &str) -> bool {
+    path.as_bytes()
+        .windows(3)
+        .any(|w| match (w[0], w[1], w[2].to_ascii_uppercase()) {
+            (b'%', b'2', b'F') | (b'%', b'5', b'C') => true,
+            _ => false,
+        })
+}
+
+/// Determines whether the normalized `path` is `prefix` or is beneath it.
+fn has_path_prefix(path: &str, prefix: &str) -> bool {
+    if prefix == "/" || path == prefix {
+        return true;
+    }
+    path.starts_with(prefix) && path[prefix.len()..].starts_with('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use identity;
+    use std::net::SocketAddr;
+    use transport::tls::{self, ReasonForNoPeerName};
+
+    fn source(remote: [u8; 4], port: u16, tls_peer: tls::PeerIdentity) -> Source {
+        let local = SocketAddr::from(([10, 0, 0, 1], 4143));
+        let orig_dst = SocketAddr::from(([10, 0, 0, 1], port));
+        Source::for_test(
+            SocketAddr::from((remote, 40_000)),
+            local,
+            Some(orig_dst),
+            tls_peer,
+        )
+    }
+
+    fn id(name: &str) -> tls::PeerIdentity {
+        Conditional::Some(identity::Name::from_hostname(name.as_bytes()).unwrap())
+    }
+
+    fn no_id() -> tls::PeerIdentity {
+        Conditional::None(ReasonForNoPeerName::NotProvidedByRemote.into())
+    }
+
+    fn policy(rules: &[&str], default: Action) -> Policy {
+        let rules = rules.iter().map(|r| r.parse().unwrap()).collect();
+        Policy::new(rules, default)
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let (authz, _) = new(policy(
+            &[
+                "deny path=/admin",
+                "allow id=*.ns1.serviceaccount.identity.linkerd.cluster.local",
+                "allow net=10.1.0.0/16 port=8080",
+            ],
+            Action::Deny,
+        ));
+
+        let web = id("web.ns1.serviceaccount.identity.linkerd.cluster.local");
+        let other = id("web.ns2.serviceaccount.identity.linkerd.cluster.local");
+
+        assert!(authz
+            .request(&source([10, 2, 0, 1], 80, web.clone()), "/api")
+            .is_ok());
+        assert!(authz
+            .request(&source([10, 2, 0, 1], 80, web.clone()), "/admin")
+            .is_err());
+        assert!(authz.connection(&source([10, 2, 0, 1], 80, web)).is_ok());
+        assert!(authz
+            .request(&source([10, 2, 0, 1], 80, other), "/api")
+            .is_err());
+        assert!(authz
+            .connection(&source([10, 1, 2, 3], 8080, no_id()))
+            .is_ok());
+        assert!(authz
+            .connection(&source([10, 1, 2, 3], 9090, no_id()))
+            .is_err());
+    }
+
+    #[test]
+    fn counts_decisions_by_rule() {
+        let (authz, report) = new(policy(&["deny port=9990"], Action::Allow));
+
+        assert!(authz
+            .connection(&source([10, 1, 2, 3], 9990, no_id()))
+            .is_err());
+        assert!(authz
+            .connection(&source([10, 1, 2, 3], 9990, no_id()))
+            .is_err());
+        assert!(authz
+            .connection(&source([10, 1, 2, 3], 8080, no_id()))
+            .is_ok());
+
+        let decisions = report.0.lock().unwrap();
+        let deny = DecisionLabels {
+            rule: Some(0),
+            action: Action::Deny,
+        };
+        let allow = DecisionLabels {
+            rule: None,
+            action: Action::Allow,
+        };
+        assert_eq!(decisions.get(&deny).map(|c| c.value()), Some(2));
+        assert_eq!(decisions.get(&allow).map(|c| c.value()), Some(1));
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize_path("/"), "/");
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/api/v1/"), "/api/v1");
+        assert_eq!(normalize_path("//api///v1"), "/api/v1");
+        assert_eq!(normalize_path("/./api/./v1"), "/api/v1");
+        assert_eq!(normalize_path("/api/../admin"), "/admin");
+        assert_eq!(normalize_path("/../../admin"), "/admin");
+        assert_eq!(normalize_path("/%61%64%6D%69%6e"), "/admin");
+        assert_eq!(normalize_path("/%2e%2E/admin"), "/admin");
+        assert_eq!(normalize_path("/a%2fb"), "/a%2Fb");
+        assert_eq!(normalize_path("/a%zzb%"), "/a%zzb%");
+    }
+
+    #[test]
+    fn path_rules_cannot_be_bypassed() {
+        let (authz, _) = new(policy(&["deny path=/admin"], Action::Allow));
+        let src = || source([10, 2, 0, 1], 80, no_id());
+
+        for path in &[
+            "/admin",
+            "/admin/",
+            "/admin/users",
+            "//admin",
+            "/%61dmin",
+            "/./admin",
+            "/api/../admin",
+            "/%2E/admin",
+        ] {
+            assert!(authz.request(&src(), path).is_err(), "{} allowed", path);
+        }
+
+        for path in &["/", "/api", "/adminx", "/administrator", "/api/admin"] {
+            assert!(authz.request(&src(), path).is_ok(), "{} denied", path);
+        }
+    }
+
+    #[test]
+    fn rejects_encoded_separators_in_paths() {
+        use futures::future;
+        use svc::Service as _Service;
+
+        struct Respond;
+
+        impl svc::Service<http::Request<()>> for Respond {
+            type Response = http::Response<()>;
+            type Error = ();
+            type Future = future::FutureResult<http::Response<()>, ()>;
+
+            fn poll_ready(&mut self) -> Poll<(), ()> {
+                Ok(Async::Ready(()))
+            }
+
+            fn call(&mut self, _: http::Request<()>) -> Self::Future {
+                future::ok(http::Response::default())
+            }
+        }
+
+        let status = |policy: Policy, path: &str| {
+            let (authorize, _) = new(policy);
+            let mut svc = Service {
+                inner: Respond,
+                authorize,
+                source: source([10, 2, 0, 1], 80, no_id()),
+            };
+            let req = http::Request::get(path).body(()).unwrap();
+            svc.call(req).wait().unwrap().status()
+        };
+
+        let path_rules = || policy(&["allow path=/public", "deny path=/admin"], Action::Allow);
+        for path in &[
+            "/admin%2Fusers",
+            "/public/..%2Fadmin",
+            "/public/..%2fadmin",
+            "/public/..%5Cadmin",
+        ] {
+            assert_eq!(
+                status(path_rules(), path),
+                http::StatusCode::BAD_REQUEST,
+                "{}",
+                path
+            );
+        }
+        assert_eq!(status(path_rules(), "/public/a"), http::StatusCode::OK);
+        assert_eq!(
+            status(path_rules(), "/admin/a"),
+            http::StatusCode::FORBIDDEN
+        );
+
+        // Paths are only rejected when the policy has path rules.
+        let no_path_rules = policy(&["deny port=9990"], Action::Allow);
+        assert_eq!(status(no_path_rules, "/a%2Fb"), http::StatusCode::OK);
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert!("allow".parse::<Rule>().is_ok());
+        assert!("deny id=* net=10.0.0.0/8,fd00::/8 port=80 path=/"
+            .parse::<Rule>()
+            .is_ok());
+        assert!("permit".parse::<Rule>().is_err());
+        assert!("allow id=".parse::<Rule>().is_err());
+        assert!("allow id=*foo".parse::<Rule>().is_err());
+        assert!("allow net=10.0.0.0".parse::<Rule>().is_err());
+        assert!("allow port=http".parse::<Rule>().is_err());
+        assert!("allow path=api".parse::<Rule>().is_err());
+        assert!("allow host=foo".parse::<Rule>().is_err());
+    }
+}
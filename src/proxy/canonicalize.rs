@@ -6,28 +6,41 @@
 //! this module may build its inner stack with either `web.example.com.:8080`,
 //! `web.example.net.:8080`, or `web:8080`, depending on the state of DNS.
 //!
-//! DNS TTLs are honored and, if the resolution changes, the inner stack is
-//! rebuilt with the updated value.
+//! DNS TTLs are honored, within optional bounds, and, if the resolution
+//! changes, the inner stack is rebuilt with the updated value. Failed resolutions are retried with an
+//! exponential, jittered backoff.
+//!
+//! The resolution task is stopped when its service is dropped.
+//!
+//! Names within a set of bypass suffixes (i.e. names for which the Destination
+//! service is authoritative) may be passed through without canonicalization,
+//! so that discovery, rather than DNS, determines how they are routed.
 
 use futures::{future, sync::mpsc, Async, Future, Poll, Stream};
-use std::time::Duration;
-use tokio::executor::{DefaultExecutor, Executor};
+use std::time::{Duration, Instant};
 use tokio_timer::{clock, Delay, Timeout};
 
+use backoff::Backoff;
 use dns;
 use svc;
+use task;
+use telemetry::tasks;
 use {Addr, NameAddr};
 
 type Error = Box<dyn std::error::Error + Send + Sync>;
 
-/// Duration to wait before polling DNS again after an error (or a NXDOMAIN
-/// response with no TTL).
-const DNS_ERROR_TTL: Duration = Duration::from_secs(3);
+/// Bounds the duration to wait before polling DNS again after consecutive
+/// errors (or NXDOMAIN responses with no TTL).
+const DNS_ERROR_MIN_BACKOFF: Duration = Duration::from_secs(3);
+const DNS_ERROR_MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone)]
 pub struct Layer {
     resolver: dns::Resolver,
     timeout: Duration,
+    ttl_bounds: TtlBounds,
+    bypass_suffixes: Vec<dns::Suffix>,
+    tasks: tasks::Registry,
 }
 
 #[derive(Clone, Debug)]
@@ -35,12 +48,16 @@ pub struct Stack<M: svc::Stack<Addr>> {
     resolver: dns::Resolver,
     inner: M,
     timeout: Duration,
+    ttl_bounds: TtlBounds,
+    bypass_suffixes: Vec<dns::Suffix>,
+    tasks: tasks::Registry,
 }
 
 pub struct Service<M: svc::Stack<Addr>> {
     rx: mpsc::Receiver<NameAddr>,
     stack: M,
     service: Option<M::Value>,
+    _task: task::Handle,
 }
 
 struct Task {
@@ -49,6 +66,8 @@ struct Task {
     resolver: dns::Resolver,
     state: State,
     timeout: Duration,
+    ttl_bounds: TtlBounds,
+    backoff: Backoff,
     tx: mpsc::Sender<NameAddr>,
 }
 
@@ -66,6 +85,13 @@ enum Cache {
     Resolved(NameAddr),
 }
 
+/// Bounds the time for which a resolution is used before it is refreshed.
+#[derive(Copy, Clone, Debug, Default)]
+struct TtlBounds {
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
 enum State {
     Init,
     Pending(Timeout<dns::RefineFuture>),
@@ -76,8 +102,43 @@ enum State {
 
 // FIXME the resolver should be abstracted to a trait so that this can be tested
 // without a real DNS service.
-pub fn layer(resolver: dns::Resolver, timeout: Duration) -> Layer {
-    Layer { resolver, timeout }
+pub fn layer(resolver: dns::Resolver, timeout: Duration, tasks: tasks::Registry) -> Layer {
+    Layer {
+        resolver,
+        timeout,
+        ttl_bounds: TtlBounds::default(),
+        bypass_suffixes: Vec::new(),
+        tasks,
+    }
+}
+
+impl Layer {
+    /// Refreshes resolutions when their TTLs expire, but no sooner than `min`
+    /// and no later than `max` after they were resolved.
+    pub fn with_ttl_bounds(self, min: Duration, max: Duration) -> Self {
+        Self {
+            ttl_bounds: TtlBounds {
+                min: Some(min),
+                max: Some(max),
+            },
+            ..self
+        }
+    }
+
+    /// Skips DNS canonicalization for names within any of the given suffixes.
+    ///
+    /// The root suffix is ignored, since it would otherwise cause unqualified
+    /// names to bypass the resolver's search path.
+    pub fn without_canonicalization_for(self, suffixes: Vec<dns::Suffix>) -> Self {
+        let bypass_suffixes = suffixes
+            .into_iter()
+            .filter(|sfx| *sfx != dns::Suffix::Root)
+            .collect();
+        Self {
+            bypass_suffixes,
+            ..self
+        }
+    }
 }
 
 impl<M> svc::Layer<Addr, Addr, M> for Layer
@@ -93,6 +154,9 @@ where
             inner,
             resolver: self.resolver.clone(),
             timeout: self.timeout,
+            ttl_bounds: self.ttl_bounds,
+            bypass_suffixes: self.bypass_suffixes.clone(),
+            tasks: self.tasks.clone(),
         }
     }
 }
@@ -108,22 +172,30 @@ where
 
     fn make(&self, addr: &Addr) -> Result<Self::Value, Self::Error> {
         match addr {
+            Addr::Name(na) if self.is_bypassed(na) => {
+                debug!("skipping canonicalization for {}", na);
+                self.inner.make(&addr).map(svc::Either::B)
+            }
             Addr::Name(na) => {
                 let (tx, rx) = mpsc::channel(2);
 
-                DefaultExecutor::current()
-                    .spawn(Box::new(Task::new(
-                        na.clone(),
-                        self.resolver.clone(),
-                        self.timeout,
-                        tx,
-                    )))
+                let task = Task::new(
+                    na.clone(),
+                    self.resolver.clone(),
+                    self.timeout,
+                    self.ttl_bounds,
+                    tx,
+                );
+                let task = self
+                    .tasks
+                    .spawn("canonicalize", task)
                     .expect("must be able to spawn");
 
                 let svc = Service {
                     rx,
                     stack: self.inner.clone(),
                     service: None,
+                    _task: task,
                 };
                 Ok(svc::Either::A(svc))
             }
@@ -132,6 +204,14 @@ where
     }
 }
 
+impl<M: svc::Stack<Addr>> Stack<M> {
+    fn is_bypassed(&self, addr: &NameAddr) -> bool {
+        self.bypass_suffixes
+            .iter()
+            .any(|sfx| sfx.contains(addr.name()))
+    }
+}
+
 // === impl Task ===
 
 impl Task {
@@ -139,6 +219,7 @@ impl Task {
         original: NameAddr,
         resolver: dns::Resolver,
         timeout: Duration,
+        ttl_bounds: TtlBounds,
         tx: mpsc::Sender<NameAddr>,
     ) -> Self {
         Self {
@@ -147,6 +228,8 @@ impl Task {
             resolver,
             state: State::Init,
             timeout,
+            ttl_bounds,
+            backoff: Backoff::new(DNS_ERROR_MIN_BACKOFF, DNS_ERROR_MAX_BACKOFF),
             tx,
         }
     }
@@ -169,6 +252,8 @@ impl Future for Task {
                             return Ok(Async::NotReady);
                         }
                         Ok(Async::Ready(refine)) => {
+                            self.backoff.reset();
+
                             // If the resolved name is a new name, bind a
                             // service with it and set a delay that will notify
                             // when the resolver should be consulted again.
@@ -182,7 +267,9 @@ impl Future for Task {
                                 self.resolved = Cache::Resolved(resolved);
                             }
 
-                            State::ValidUntil(Delay::new(refine.valid_until))
+                            let refresh =
+                                self.ttl_bounds.refresh_at(clock::now(), refine.valid_until);
+                            State::ValidUntil(Delay::new(refresh))
                         }
                         Err(e) => {
                             if self.resolved == Cache::AwaitingInitial {
@@ -210,15 +297,18 @@ impl Future for Task {
                                 );
                             }
 
-                            let valid_until = e
-                                .into_inner()
-                                .and_then(|e| match e.kind() {
-                                    dns::ResolveErrorKind::NoRecordsFound {
-                                        valid_until, ..
-                                    } => *valid_until,
-                                    _ => None,
-                                })
-                                .unwrap_or_else(|| clock::now() + DNS_ERROR_TTL);
+                            let ttl = e.into_inner().and_then(|e| match e.kind() {
+                                dns::ResolveErrorKind::NoRecordsFound { valid_until, .. } => {
+                                    *valid_until
+                                }
+                                _ => None,
+                            });
+                            let valid_until = match ttl {
+                                Some(valid_until) => {
+                                    self.ttl_bounds.refresh_at(clock::now(), valid_until)
+                                }
+                                None => clock::now() + self.backoff.next(),
+                            };
 
                             State::ValidUntil(Delay::new(valid_until))
                         }
@@ -239,6 +329,21 @@ impl Future for Task {
     }
 }
 
+impl TtlBounds {
+    /// Returns when a resolution that is valid until `valid_until` should be
+    /// refreshed.
+    fn refresh_at(&self, now: Instant, valid_until: Instant) -> Instant {
+        let mut refresh = valid_until.max(now);
+        if let Some(min) = self.min {
+            refresh = refresh.max(now + min);
+        }
+        if let Some(max) = self.max {
+            refresh = refresh.min(now + max);
+        }
+        refresh
+    }
+}
+
 impl Cache {
     fn get(&self) -> Option<&NameAddr> {
         match self {
@@ -288,3 +393,29 @@ where
             .map_err(Into::into)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_is_bounded() {
+        let now = Instant::now();
+        let secs = Duration::from_secs;
+        let bounds = TtlBounds {
+            min: Some(secs(5)),
+            max: Some(secs(300)),
+        };
+
+        assert_eq!(bounds.refresh_at(now, now), now + secs(5));
+        assert_eq!(bounds.refresh_at(now, now + secs(30)), now + secs(30));
+        assert_eq!(bounds.refresh_at(now, now + secs(3600)), now + secs(300));
+
+        let unbounded = TtlBounds::default();
+        assert_eq!(unbounded.refresh_at(now + secs(1), now), now + secs(1));
+        assert_eq!(
+            unbounded.refresh_at(now, now + secs(3600)),
+            now + secs(3600)
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Limits the number of connections that a server handles concurrently.
+//!
+//! Connections beyond the limit are still accepted, so that they do not queue
+//! in the listener's backlog (and so that the proxy does not exhaust its file
+//! descriptors), but they are closed as soon as their protocol is known. HTTP
+//! clients are first sent a `503 Service Unavailable` response.
+
+use indexmap::IndexMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics};
+
+metrics! {
+    connections_rejected_total: Counter {
+        "Total count of connections closed because the proxy was handling its maximum number of connections"
+    }
+}
+
+pub fn new() -> (Registry, Report) {
+    let rejected = Arc::new(Mutex::new(IndexMap::new()));
+    (Registry(rejected.clone()), Report(rejected))
+}
+
+/// Creates connection limits that record the connections they reject.
+#[derive(Clone, Debug)]
+pub struct Registry(Arc<Mutex<Rejected>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted rejection counts.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Rejected>>);
+
+type Rejected = IndexMap<Direction, Counter>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Direction(&'static str);
+
+/// Bounds the number of connections that hold a `Permit`.
+///
+/// The default limit permits any number of connections.
+#[derive(Clone, Debug, Default)]
+pub struct Limit(Option<Arc<Inner>>);
+
+#[derive(Debug)]
+struct Inner {
+    max: usize,
+    active: AtomicUsize,
+    direction: Direction,
+    rejected: Arc<Mutex<Rejected>>,
+}
+
+/// Counts a connection against its limit until it is dropped.
+#[derive(Debug)]
+pub struct Permit(Option<Arc<Inner>>);
+
+// === impl Registry ===
+
+impl Registry {
+    /// Creates a limit for connections in `direction`, e.g. `inbound`, if a
+    /// maximum number of connections is configured.
+    pub fn limit(&self, direction: &'static str, max: Option<usize>) -> Limit {
+        let max = match max {
+            Some(max) => max,
+            None => return Limit::default(),
+        };
+
+        // Report that no connections have been rejected yet.
+        if let Ok(mut rejected) = self.0.lock() {
+            rejected
+                .entry(Direction(direction))
+                .or_insert_with(Counter::default);
+        }
+
+        Limit(Some(Arc::new(Inner {
+            max,
+            active: AtomicUsize::new(0),
+            direction: Direction(direction),
+            rejected: self.0.clone(),
+        })))
+    }
+}
+
+// === impl Limit ===
+
+impl Limit {
+    /// Permits a new connection, unless the maximum number of connections are
+    /// already permitted.
+    pub fn acquire(&self) -> Option<Permit> {
+        let inner = match self.0 {
+            Some(ref inner) => inner,
+            None => return Some(Permit(None)),
+        };
+
+        if inner.active.fetch_add(1, Ordering::AcqRel) >= inner.max {
+            inner.active.fetch_sub(1, Ordering::AcqRel);
+            if let Ok(mut rejected) = inner.rejected.lock() {
+                rejected
+                    .entry(inner.direction.clone())
+                    .or_insert_with(Counter::default)
+                    .incr();
+            }
+            return None;
+        }
+
+        Some(Permit(Some(inner.clone())))
+    }
+}
+
+// === impl Permit ===
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(ref inner) = self.0 {
+            inner.active.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rejected = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if rejected.is_empty() {
+            return Ok(());
+        }
+
+        connections_rejected_total.fmt_help(f)?;
+        for (direction, count) in rejected.iter() {
+            count.fmt_metric_labeled(f, connections_rejected_total.name, direction)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for Direction {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "direction=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_connections_beyond_limit() {
+        let (registry, report) = new();
+        let limit = registry.limit("inbound", Some(2));
+
+        let first = limit.acquire().expect("first connection must be permitted");
+        let _second = limit
+            .acquire()
+            .expect("second connection must be permitted");
+        assert!(limit.acquire().is_none());
+
+        drop(first);
+        assert!(limit.acquire().is_some());
+
+        let rejected = report.0.lock().unwrap();
+        let count = rejected.get(&Direction("inbound")).map(|c| c.value());
+        assert_eq!(count, Some(1));
+    }
+
+    #[test]
+    fn unlimited_without_max() {
+        let (registry, report) = new();
+        let limit = registry.limit("outbound", None);
+        let permits = (0..16).map(|_| limit.acquire()).collect::<Vec<_>>();
+        assert!(permits.iter().all(Option::is_some));
+        assert!(report.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,511 @@
+//! Restricts outbound traffic to destinations outside of the mesh.
+//!
+//! A destination is outside of the mesh when its name is not within one of the
+//! mesh's domains or, for traffic addressed by IP, when its address is not in
+//! one of the cluster's networks. Traffic to these destinations is only
+//! forwarded when it matches one of the configured allow rules; otherwise,
+//! HTTP requests fail with a `403 Forbidden` response and other connections
+//! are closed.
+
+use futures::{Async, Future, Poll};
+use http;
+use indexmap::IndexMap;
+use ipnet::IpNet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::{error, fmt};
+
+use convert::TryFrom;
+use dns;
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics};
+use proxy::server::{AuthorizeForward, Source};
+use svc;
+use transport::mesh;
+use Addr;
+
+metrics! {
+    egress_policy_decisions_total: Counter {
+        "Total count of requests and connections to destinations outside of the mesh, by allow rule"
+    }
+}
+
+/// Matches destinations outside of the mesh that traffic may be sent to.
+///
+/// Each criterion is optional, but a rule must have at least one.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    hosts: Option<Vec<HostMatch>>,
+    networks: Option<mesh::Networks>,
+    port: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HostMatch {
+    /// Matches a lowercase name without a trailing dot.
+    Exact(String),
+    /// Matches names that end with the given lowercase suffix, which starts
+    /// with a `.`.
+    Suffix(String),
+}
+
+/// Configures which destinations are outside of the mesh, and which of them
+/// traffic may be sent to.
+#[derive(Clone, Debug)]
+pub struct Config {
+    rules: Vec<Rule>,
+    internal: Vec<dns::Suffix>,
+    networks: mesh::Networks,
+}
+
+pub fn new(config: Option<Config>) -> (Policy, Report) {
+    let decisions = Arc::new(Mutex::new(IndexMap::new()));
+    let policy = Policy {
+        config: config.map(Arc::new),
+        decisions: decisions.clone(),
+    };
+    (policy, Report(decisions))
+}
+
+/// Applies a `Config`, if one is configured, recording each decision.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    config: Option<Arc<Config>>,
+    decisions: Arc<Mutex<Decisions>>,
+}
+
+/// Implements `FmtMetrics` to render prometheus-formatted decision counts.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Decisions>>);
+
+/// Counts decisions by the index of the rule that allowed the traffic, or
+/// `None` when it was denied.
+type Decisions = IndexMap<Option<usize>, Counter>;
+
+/// Indicates that traffic to a destination outside of the mesh was denied.
+#[derive(Clone, Debug)]
+pub struct Denied {
+    addr: Addr,
+}
+
+pub fn layer(policy: Policy) -> Layer {
+    Layer { policy }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    policy: Policy,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    policy: Policy,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    policy: Policy,
+    addr: Addr,
+    /// The rule that allows requests to `addr`, if any.
+    rule: Option<usize>,
+}
+
+pub enum ResponseFuture<F> {
+    Allowed(F),
+    Denied,
+}
+
+// === impl Rule ===
+
+impl Rule {
+    fn matches(&self, addr: &Addr) -> bool {
+        if let Some(port) = self.port {
+            if addr.port() != port {
+                return false;
+            }
+        }
+
+        if let Some(ref hosts) = self.hosts {
+            let name = match addr.name_addr() {
+                Some(n) => n.name().without_trailing_dot().to_ascii_lowercase(),
+                None => return false,
+            };
+            if !hosts.iter().any(|h| h.matches(&name)) {
+                return false;
+            }
+        }
+
+        if let Some(ref networks) = self.networks {
+            match addr.socket_addr() {
+                Some(sa) if networks.contains(sa.ip()) => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Parses a rule from whitespace-separated criteria, e.g.
+/// `host=api.example.com,*.example.org port=443` or `net=203.0.113.0/24`.
+///
+/// The `host` and `net` criteria accept comma-separated lists.
+impl FromStr for Rule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rule = Rule {
+            hosts: None,
+            networks: None,
+            port: None,
+        };
+
+        let mut fields = s.split_whitespace().peekable();
+        if fields.peek().is_none() {
+            return Err(());
+        }
+        for field in fields {
+            let mut kv = field.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some("host"), Some(hosts)) => {
+                    let hosts = hosts
+                        .split(',')
+                        .map(HostMatch::from_str)
+                        .collect::<Result<Vec<_>, _>>()?;
+                    rule.hosts = Some(hosts);
+                }
+                (Some("net"), Some(nets)) => {
+                    let nets = nets
+                        .split(',')
+                        .map(IpNet::from_str)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| ())?;
+                    rule.networks = Some(mesh::Networks::new(nets));
+                }
+                (Some("port"), Some(port)) => {
+                    rule.port = Some(port.parse().map_err(|_| ())?);
+                }
+                _ => return Err(()),
+            }
+        }
+
+        Ok(rule)
+    }
+}
+
+// === impl HostMatch ===
+
+impl HostMatch {
+    fn matches(&self, name: &str) -> bool {
+        match *self {
+            HostMatch::Exact(ref host) => name == host,
+            HostMatch::Suffix(ref suffix) => name.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+impl FromStr for HostMatch {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (wildcard, host) = if s.starts_with("*.") {
+            (true, &s[2..])
+        } else {
+            (false, s)
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        dns::Name::try_from(host.as_bytes()).map_err(|_| ())?;
+
+        if wildcard {
+            Ok(HostMatch::Suffix(format!(".{}", host)))
+        } else {
+            Ok(HostMatch::Exact(host))
+        }
+    }
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Allows traffic outside of the mesh that matches any of `rules`. Names
+    /// within `internal` and addresses in `networks` are part of the mesh.
+    pub fn new(rules: Vec<Rule>, internal: Vec<dns::Suffix>, networks: mesh::Networks) -> Self {
+        Self {
+            rules,
+            internal,
+            networks,
+        }
+    }
+
+    fn is_external(&self, addr: &Addr) -> bool {
+        match *addr {
+            Addr::Name(ref n) => {
+                !n.is_localhost() && !self.internal.iter().any(|sfx| sfx.contains(n.name()))
+            }
+            Addr::Socket(ref sa) => !sa.ip().is_loopback() && !self.networks.contains(sa.ip()),
+        }
+    }
+}
+
+// === impl Policy ===
+
+impl Policy {
+    /// Determines whether `addr` is outside of the mesh and, if so, finds
+    /// the index of the first rule that allows traffic to it.
+    ///
+    /// Returns `None` when traffic to `addr` is not restricted.
+    fn decide(&self, addr: &Addr) -> Option<Option<usize>> {
+        let config = self.config.as_ref()?;
+        if !config.is_external(addr) {
+            return None;
+        }
+
+        Some(config.rules.iter().position(|rule| rule.matches(addr)))
+    }
+
+    fn record(&self, addr: &Addr, rule: Option<usize>) -> Result<(), Denied> {
+        trace!("egress policy: addr={}; rule={:?}", addr, rule);
+        if let Ok(mut decisions) = self.decisions.lock() {
+            decisions
+                .entry(rule)
+                .or_insert_with(Counter::default)
+                .incr();
+        }
+
+        match rule {
+            Some(_) => Ok(()),
+            None => Err(Denied { addr: addr.clone() }),
+        }
+    }
+
+    /// Checks whether traffic may be sent to `addr`.
+    pub fn check(&self, addr: &Addr) -> Result<(), Denied> {
+        match self.decide(addr) {
+            None => Ok(()),
+            Some(rule) => self.record(addr, rule),
+        }
+    }
+}
+
+impl AuthorizeForward for Policy {
+    fn authorize_forward(
+        &self,
+        source: &Source,
+    ) -> Result<(), Box<dyn error::Error + Send + Sync>> {
+        // Connections without an original destination cannot be forwarded
+        // anyway.
+        if let Some(orig_dst) = source.orig_dst {
+            self.check(&Addr::Socket(orig_dst))?;
+        }
+        Ok(())
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decisions = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if decisions.is_empty() {
+            return Ok(());
+        }
+
+        egress_policy_decisions_total.fmt_help(f)?;
+        for (rule, count) in decisions.iter() {
+            count.fmt_metric_labeled(f, egress_policy_decisions_total.name, RuleLabel(*rule))?;
+        }
+
+        Ok(())
+    }
+}
+
+struct RuleLabel(Option<usize>);
+
+impl FmtLabels for RuleLabel {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(idx) => write!(f, "rule=\"{}\",action=\"allow\"", idx),
+            None => write!(f, "rule=\"default\",action=\"deny\""),
+        }
+    }
+}
+
+// === impl Denied ===
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} is outside of the mesh and not allowed by the egress policy",
+            self.addr
+        )
+    }
+}
+
+impl error::Error for Denied {}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Addr, Addr, M> for Layer
+where
+    M: svc::Stack<Addr>,
+{
+    type Value = <Stack<M> as svc::Stack<Addr>>::Value;
+    type Error = <Stack<M> as svc::Stack<Addr>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Addr> for Stack<M>
+where
+    M: svc::Stack<Addr>,
+{
+    type Value = svc::Either<Service<M::Value>, M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, addr: &Addr) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(addr)?;
+
+        match self.policy.decide(addr) {
+            None => Ok(svc::Either::B(inner)),
+            Some(rule) => {
+                debug!("egress policy for {}: rule={:?}", addr, rule);
+                Ok(svc::Either::A(Service {
+                    inner,
+                    policy: self.policy.clone(),
+                    addr: addr.clone(),
+                    rule,
+                }))
+            }
+        }
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Response = http::Response<B>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        if let Err(e) = self.policy.record(&self.addr, self.rule) {
+            debug!("{}", e);
+            return ResponseFuture::Denied;
+        }
+
+        ResponseFuture::Allowed(self.inner.call(req))
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = http::Response<B>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match *self {
+            ResponseFuture::Allowed(ref mut f) => f.poll(),
+            ResponseFuture::Denied => {
+                let mut rsp = http::Response::default();
+                *rsp.status_mut() = http::StatusCode::FORBIDDEN;
+                Ok(Async::Ready(rsp))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(rules: &[&str]) -> (Policy, Report) {
+        let rules = rules.iter().map(|r| r.parse().unwrap()).collect();
+        let internal = vec![dns::Suffix::Name(
+            dns::Name::try_from("svc.cluster.local".as_bytes()).unwrap(),
+        )];
+        let networks = mesh::Networks::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        new(Some(Config::new(rules, internal, networks)))
+    }
+
+    fn addr(s: &str) -> Addr {
+        Addr::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn allows_matching_external_destinations() {
+        let (policy, _) = policy(&[
+            "host=api.example.com,*.example.org port=443",
+            "net=203.0.113.0/24",
+        ]);
+
+        assert!(policy.check(&addr("web.ns.svc.cluster.local:80")).is_ok());
+        assert!(policy.check(&addr("10.1.2.3:8080")).is_ok());
+        assert!(policy.check(&addr("localhost:8080")).is_ok());
+
+        assert!(policy.check(&addr("api.example.com:443")).is_ok());
+        assert!(policy.check(&addr("API.example.com:443")).is_ok());
+        assert!(policy.check(&addr("cdn.example.org:443")).is_ok());
+        assert!(policy.check(&addr("203.0.113.7:5432")).is_ok());
+
+        assert!(policy.check(&addr("api.example.com:80")).is_err());
+        assert!(policy.check(&addr("example.org:443")).is_err());
+        assert!(policy.check(&addr("evil.example.net:443")).is_err());
+        assert!(policy.check(&addr("198.51.100.1:443")).is_err());
+    }
+
+    #[test]
+    fn counts_decisions_by_rule() {
+        let (policy, report) = policy(&["host=api.example.com"]);
+
+        assert!(policy.check(&addr("api.example.com:443")).is_ok());
+        assert!(policy.check(&addr("evil.example.net:443")).is_err());
+        assert!(policy.check(&addr("evil.example.net:443")).is_err());
+        assert!(policy.check(&addr("web.ns.svc.cluster.local:80")).is_ok());
+
+        let decisions = report.0.lock().unwrap();
+        assert_eq!(decisions.get(&Some(0)).map(|c| c.value()), Some(1));
+        assert_eq!(decisions.get(&None).map(|c| c.value()), Some(2));
+        assert_eq!(decisions.len(), 2);
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert!("host=*.example.com".parse::<Rule>().is_ok());
+        assert!("net=203.0.113.0/24,2001:db8::/32 port=443"
+            .parse::<Rule>()
+            .is_ok());
+        assert!("".parse::<Rule>().is_err());
+        assert!("host=".parse::<Rule>().is_err());
+        assert!("host=*".parse::<Rule>().is_err());
+        assert!("net=203.0.113.0".parse::<Rule>().is_err());
+        assert!("port=https".parse::<Rule>().is_err());
+        assert!("path=/".parse::<Rule>().is_err());
+    }
+}
@@ -10,15 +10,27 @@ use std::time::Duration;
 pub use self::hyper_balance::{PendingUntilFirstData, PendingUntilFirstDataBody};
 pub use self::tower_balance::{choose::PowerOfTwoChoices, load::WithPeakEwma, Balance};
 
+use super::panic_threshold::PanicThreshold;
+use super::ring_hash::{self, HashKey};
+use super::slow_start::SlowStart;
 use http;
 use svc;
 
+/// A balancer's endpoints.
+///
+/// Slow-start wraps the panic threshold, so that endpoints that are held back
+/// while they warm up are not counted as unready.
+type Endpoints<D> = SlowStart<PanicThreshold<D>>;
+
 /// Configures a stack to resolve `T` typed targets to balance requests over
 /// `M`-typed endpoint stacks.
 #[derive(Debug)]
 pub struct Layer<A, B> {
     decay: Duration,
     default_rtt: Duration,
+    ring_hash: Option<HashKey>,
+    slow_start: Option<Duration>,
+    panic_threshold: Option<f64>,
     _marker: PhantomData<fn(A) -> B>,
 }
 
@@ -27,6 +39,9 @@ pub struct Layer<A, B> {
 pub struct Stack<M, A, B> {
     decay: Duration,
     default_rtt: Duration,
+    ring_hash: Option<HashKey>,
+    slow_start: Option<Duration>,
+    panic_threshold: Option<f64>,
     inner: M,
     _marker: PhantomData<fn(A) -> B>,
 }
@@ -37,15 +52,44 @@ pub fn layer<A, B>(default_rtt: Duration, decay: Duration) -> Layer<A, B> {
     Layer {
         decay,
         default_rtt,
+        ring_hash: None,
+        slow_start: None,
+        panic_threshold: None,
         _marker: PhantomData,
     }
 }
 
+impl<A, B> Layer<A, B> {
+    /// When set, requests are balanced over a consistent-hash ring keyed by the
+    /// given request property, rather than by power of two choices.
+    pub fn with_ring_hash(self, ring_hash: Option<HashKey>) -> Self {
+        Self { ring_hash, ..self }
+    }
+
+    /// When set, endpoints that are added to a balancer are ramped up to a
+    /// full share of its traffic over the given window.
+    pub fn with_slow_start(self, slow_start: Option<Duration>) -> Self {
+        Self { slow_start, ..self }
+    }
+
+    /// When set, endpoints that are not ready are balanced over while the
+    /// fraction of ready endpoints is below the given threshold.
+    pub fn with_panic_threshold(self, panic_threshold: Option<f64>) -> Self {
+        Self {
+            panic_threshold,
+            ..self
+        }
+    }
+}
+
 impl<A, B> Clone for Layer<A, B> {
     fn clone(&self) -> Self {
         Layer {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            ring_hash: self.ring_hash.clone(),
+            slow_start: self.slow_start,
+            panic_threshold: self.panic_threshold,
             _marker: PhantomData,
         }
     }
@@ -55,7 +99,9 @@ impl<T, M, A, B> svc::Layer<T, T, M> for Layer<A, B>
 where
     M: svc::Stack<T> + Clone,
     M::Value: Discover,
-    <M::Value as Discover>::Service: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    <M::Value as Discover>::Key: Clone,
+    <M::Value as Discover>::Service:
+        svc::Service<http::Request<A>, Response = http::Response<B>> + Clone,
     A: Payload,
     B: Payload,
 {
@@ -67,6 +113,9 @@ where
         Stack {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            ring_hash: self.ring_hash.clone(),
+            slow_start: self.slow_start,
+            panic_threshold: self.panic_threshold,
             inner,
             _marker: PhantomData,
         }
@@ -80,6 +129,9 @@ impl<M: Clone, A, B> Clone for Stack<M, A, B> {
         Stack {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            ring_hash: self.ring_hash.clone(),
+            slow_start: self.slow_start,
+            panic_threshold: self.panic_threshold,
             inner: self.inner.clone(),
             _marker: PhantomData,
         }
@@ -90,17 +142,28 @@ impl<T, M, A, B> svc::Stack<T> for Stack<M, A, B>
 where
     M: svc::Stack<T> + Clone,
     M::Value: Discover,
-    <M::Value as Discover>::Service: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    <M::Value as Discover>::Key: Clone,
+    <M::Value as Discover>::Service:
+        svc::Service<http::Request<A>, Response = http::Response<B>> + Clone,
     A: Payload,
     B: Payload,
 {
-    type Value = Balance<WithPeakEwma<M::Value, PendingUntilFirstData>, PowerOfTwoChoices>;
+    type Value = svc::Either<
+        Balance<WithPeakEwma<Endpoints<M::Value>, PendingUntilFirstData>, PowerOfTwoChoices>,
+        ring_hash::Balance<WithPeakEwma<Endpoints<M::Value>, PendingUntilFirstData>>,
+    >;
     type Error = M::Error;
 
     fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
-        let discover = self.inner.make(target)?;
+        let discover = PanicThreshold::new(self.inner.make(target)?, self.panic_threshold);
+        let discover = SlowStart::new(discover, self.slow_start);
         let instrument = PendingUntilFirstData::default();
+        // Endpoints are instrumented in both modes so that the balancers'
+        // responses have the same type.
         let loaded = WithPeakEwma::new(discover, self.default_rtt, self.decay, instrument);
-        Ok(Balance::p2c(loaded))
+        match self.ring_hash {
+            None => Ok(svc::Either::A(Balance::p2c(loaded))),
+            Some(ref key) => Ok(svc::Either::B(ring_hash::Balance::new(loaded, key.clone()))),
+        }
     }
 }
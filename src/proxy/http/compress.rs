@@ -0,0 +1,453 @@
+//! Compresses responses for clients that accept gzip-encoded content.
+//!
+//! Only responses with a configured content type, that are not already
+//! encoded, and whose bodies are not known to be smaller than a minimum size
+//! are compressed. Compressed output is flushed as each chunk of the response
+//! is read, so that streaming responses are not delayed.
+
+use bytes::{Buf, Bytes};
+use flate2::{write::GzEncoder, Compression as Level};
+use futures::{Async, Future, Poll};
+use http::{
+    self,
+    header::{self, HeaderValue},
+};
+use hyper::body::Payload;
+use std::io::{self, Cursor, Write};
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+
+use metrics::{Counter, FmtMetrics};
+use svc;
+
+metrics! {
+    response_compression_total: Counter {
+        "Total count of responses compressed by the proxy"
+    },
+    response_compression_input_bytes_total: Counter {
+        "Total count of response body bytes before compression"
+    },
+    response_compression_output_bytes_total: Counter {
+        "Total count of response body bytes after compression; the difference from the input is the count of bytes saved"
+    }
+}
+
+/// Configures which responses are compressed.
+#[derive(Clone, Debug)]
+pub struct Config {
+    content_types: Vec<String>,
+    min_size: usize,
+}
+
+pub fn new() -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Metrics::default()));
+    (Registry(inner.clone()), Report(inner))
+}
+
+#[derive(Clone, Debug)]
+pub struct Registry(Arc<Mutex<Metrics>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted compression metrics.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Metrics>>);
+
+#[derive(Debug, Default)]
+struct Metrics {
+    responses: Counter,
+    input_bytes: Counter,
+    output_bytes: Counter,
+}
+
+pub fn layer(config: Option<Config>, registry: Registry) -> Layer {
+    Layer { config, registry }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    config: Option<Config>,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    config: Option<Config>,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    config: Option<Config>,
+    registry: Registry,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    compress: Option<(Config, Registry)>,
+}
+
+/// A response body that is gzip-encoded, if the response is compressed.
+#[derive(Debug, Default)]
+pub struct ResponseBody<B> {
+    inner: B,
+    gzip: Option<Gzip>,
+}
+
+#[derive(Debug)]
+struct Gzip {
+    encoder: Option<GzEncoder<Vec<u8>>>,
+    registry: Registry,
+}
+
+/// The data of a `ResponseBody`.
+#[derive(Debug)]
+pub enum Data<D> {
+    Uncompressed(D),
+    Compressed(Cursor<Bytes>),
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Compresses responses whose content type starts with any of
+    /// `content_types`, unless their bodies are shorter than `min_size`.
+    pub fn new(content_types: Vec<String>, min_size: usize) -> Self {
+        Self {
+            content_types,
+            min_size,
+        }
+    }
+
+    fn should_compress<B>(&self, rsp: &http::Response<B>) -> bool {
+        let status = rsp.status();
+        if status.is_informational()
+            || status == http::StatusCode::NO_CONTENT
+            || status == http::StatusCode::NOT_MODIFIED
+        {
+            return false;
+        }
+
+        let headers = rsp.headers();
+        if headers.contains_key(header::CONTENT_ENCODING) {
+            return false;
+        }
+
+        let is_compressible = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .map(|ct| {
+                self.content_types
+                    .iter()
+                    .any(|t| ct.starts_with(t.as_str()))
+            })
+            .unwrap_or(false);
+        if !is_compressible {
+            return false;
+        }
+
+        // Bodies of unknown length are compressed, since they may be large.
+        headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<usize>().ok())
+            .map(|len| len >= self.min_size)
+            .unwrap_or(true)
+    }
+}
+
+/// Determines whether a request's `accept-encoding` allows gzip.
+fn accepts_gzip(headers: &http::HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or("");
+            if name != "gzip" && name != "*" {
+                return false;
+            }
+            // A quality of zero indicates that the coding is not acceptable.
+            parts
+                .filter(|p| p.starts_with("q="))
+                .filter_map(|p| p[2..].parse::<f32>().ok())
+                .all(|q| q > 0.0)
+        })
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let compress = self
+            .config
+            .as_ref()
+            .filter(|_| *req.method() != http::Method::HEAD && accepts_gzip(req.headers()))
+            .map(|config| (config.clone(), self.registry.clone()));
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            compress,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<ResponseBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+
+        let gzip = match self.compress.take() {
+            Some((ref config, ref registry)) if config.should_compress(&rsp) => {
+                let headers = rsp.headers_mut();
+                headers.remove(header::CONTENT_LENGTH);
+                headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+                if let Ok(mut m) = registry.0.lock() {
+                    m.responses.incr();
+                }
+                Some(Gzip {
+                    encoder: Some(GzEncoder::new(Vec::new(), Level::default())),
+                    registry: registry.clone(),
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Async::Ready(rsp.map(|inner| ResponseBody { inner, gzip })))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B: Payload> Payload for ResponseBody<B> {
+    type Data = Data<B::Data>;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        match self.gzip {
+            None => self.inner.is_end_stream(),
+            Some(ref gzip) => gzip.encoder.is_none() && self.inner.is_end_stream(),
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let gzip = match self.gzip.as_mut() {
+            Some(gzip) => gzip,
+            None => {
+                let data = try_ready!(self.inner.poll_data());
+                return Ok(Async::Ready(data.map(Data::Uncompressed)));
+            }
+        };
+
+        loop {
+            if gzip.encoder.is_none() {
+                return Ok(Async::Ready(None));
+            }
+
+            let out = match try_ready!(self.inner.poll_data()) {
+                Some(data) => gzip.write(data),
+                None => gzip.finish(),
+            };
+
+            match out {
+                Ok(ref out) if out.is_empty() => continue,
+                Ok(out) => return Ok(Async::Ready(Some(Data::Compressed(Cursor::new(out))))),
+                Err(e) => {
+                    // Writing to a `Vec` is not expected to fail.
+                    warn!("failed to compress response: {}", e);
+                    gzip.encoder = None;
+                    return Ok(Async::Ready(None));
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+// === impl Gzip ===
+
+impl Gzip {
+    /// Compresses `data`, returning the compressed output that is available.
+    fn write<D: Buf>(&mut self, mut data: D) -> io::Result<Bytes> {
+        let input = data.remaining();
+        let out = {
+            let encoder = self.encoder.as_mut().expect("encoder must not be finished");
+            while data.has_remaining() {
+                let n = {
+                    let bytes = data.bytes();
+                    encoder.write_all(bytes)?;
+                    bytes.len()
+                };
+                data.advance(n);
+            }
+            encoder.flush()?;
+            mem::replace(encoder.get_mut(), Vec::new())
+        };
+        self.record(input, out.len());
+        Ok(out.into())
+    }
+
+    /// Completes the stream, returning the remaining compressed output.
+    fn finish(&mut self) -> io::Result<Bytes> {
+        let encoder = self.encoder.take().expect("encoder must not be finished");
+        let out = encoder.finish()?;
+        self.record(0, out.len());
+        Ok(out.into())
+    }
+
+    fn record(&self, input: usize, output: usize) {
+        if let Ok(mut m) = self.registry.0.lock() {
+            m.input_bytes += input as u64;
+            m.output_bytes += output as u64;
+        }
+    }
+}
+
+// === impl Data ===
+
+impl<D: Buf> Buf for Data<D> {
+    fn remaining(&self) -> usize {
+        match self {
+            Data::Uncompressed(ref d) => d.remaining(),
+            Data::Compressed(ref c) => c.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Data::Uncompressed(ref d) => d.bytes(),
+            Data::Compressed(ref c) => c.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match self {
+            Data::Uncompressed(ref mut d) => d.advance(cnt),
+            Data::Compressed(ref mut c) => c.advance(cnt),
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        response_compression_total.fmt_help(f)?;
+        response_compression_total.fmt_metric(f, m.responses)?;
+
+        response_compression_input_bytes_total.fmt_help(f)?;
+        response_compression_input_bytes_total.fmt_metric(f, m.input_bytes)?;
+
+        response_compression_output_bytes_total.fmt_help(f)?;
+        response_compression_output_bytes_total.fmt_metric(f, m.output_bytes)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept_encoding(v: &'static str) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(v));
+        headers
+    }
+
+    #[test]
+    fn accepts_gzip_codings() {
+        assert!(accepts_gzip(&accept_encoding("gzip")));
+        assert!(accepts_gzip(&accept_encoding("deflate, gzip;q=0.5")));
+        assert!(accepts_gzip(&accept_encoding("*")));
+        assert!(!accepts_gzip(&accept_encoding("gzip;q=0")));
+        assert!(!accepts_gzip(&accept_encoding("br, deflate")));
+        assert!(!accepts_gzip(&http::HeaderMap::new()));
+    }
+
+    #[test]
+    fn compresses_configured_content_types() {
+        let config = Config::new(vec!["text/".into(), "application/json".into()], 10);
+        let rsp = |ct: &str, len: Option<&str>| {
+            let mut rsp = http::Response::builder();
+            rsp.header(header::CONTENT_TYPE, ct);
+            if let Some(len) = len {
+                rsp.header(header::CONTENT_LENGTH, len);
+            }
+            rsp.body(()).unwrap()
+        };
+
+        assert!(config.should_compress(&rsp("text/html; charset=utf-8", None)));
+        assert!(config.should_compress(&rsp("application/json", Some("10"))));
+        assert!(!config.should_compress(&rsp("application/json", Some("9"))));
+        assert!(!config.should_compress(&rsp("image/png", None)));
+    }
+}
@@ -0,0 +1,438 @@
+//! Retries requests on another endpoint when a connection cannot be
+//! established.
+//!
+//! HTTP/1 clients connect lazily, so an endpoint that refuses connections (or
+//! fails the TLS handshake) remains ready and fails each request that is
+//! dispatched to it. Because such a request was never sent, it is safe to send
+//! it again: `layer` wraps a balancer so that these requests are redispatched,
+//! up to a bounded number of attempts, before the error is returned to the
+//! caller. `eject_layer` wraps each of the balancer's endpoints so that an
+//! endpoint is not ready for a backoff after a connection to it fails, so that
+//! the balancer chooses a different endpoint for the retry.
+//!
+//! Only requests without a body (or with an empty body) may be retried, and
+//! HTTP/1.1 upgrades are never retried.
+
+use futures::{Async, Future, Poll};
+use http::Request;
+use std::error::Error as StdError;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::{clock, Delay};
+
+use super::glue;
+use super::retry::TryClone;
+use super::upgrade::Http11Upgrade;
+use svc;
+
+type Error = Box<dyn StdError + Send + Sync>;
+
+/// Retries requests on connect errors, up to `attempts` times in total.
+pub fn layer(attempts: usize) -> Layer {
+    Layer { attempts }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    attempts: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    attempts: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    attempts: usize,
+}
+
+pub struct ResponseFuture<S, B>
+where
+    S: svc::Service<Request<B>>,
+{
+    state: State<S::Future, B>,
+    /// Set when the request may be retried.
+    service: Option<S>,
+    /// The number of attempts that remain after the current one.
+    remaining: usize,
+}
+
+enum State<F, B> {
+    Called {
+        future: F,
+        /// A clone of the request, to be sent if the connection fails.
+        retry: Option<Request<B>>,
+    },
+    Retrying(Option<Request<B>>),
+}
+
+/// Makes endpoints unready for `backoff` after a connection to them fails.
+pub fn eject_layer(backoff: Duration) -> EjectLayer {
+    EjectLayer { backoff }
+}
+
+#[derive(Clone, Debug)]
+pub struct EjectLayer {
+    backoff: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct EjectStack<M> {
+    inner: M,
+    backoff: Duration,
+}
+
+pub struct Eject<S> {
+    inner: S,
+    backoff: Duration,
+    /// When set, the endpoint is not ready until this time. This is shared
+    /// by all clones of the endpoint.
+    ejected: Arc<Mutex<Option<Instant>>>,
+    delay: Option<Delay>,
+}
+
+pub struct EjectFuture<F> {
+    inner: F,
+    backoff: Duration,
+    ejected: Arc<Mutex<Option<Instant>>>,
+}
+
+/// Indicates whether `err` was caused by a connection that could not be
+/// established.
+fn is_connect_error(err: &(dyn StdError + 'static)) -> bool {
+    let mut cause = Some(err);
+    while let Some(err) = cause {
+        if let Some(e) = err.downcast_ref::<glue::Error>() {
+            if e.is_connect() {
+                return true;
+            }
+        }
+        if let Some(e) = err.downcast_ref::<io::Error>() {
+            if e.kind() == io::ErrorKind::ConnectionRefused {
+                return true;
+            }
+        }
+        cause = err.source();
+    }
+    false
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            attempts: self.attempts,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            attempts: self.attempts,
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<Request<B>> for Service<S>
+where
+    S: svc::Service<Request<B>> + Clone,
+    S::Error: Into<Error>,
+    B: TryClone,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S, B>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let remaining = self.attempts.saturating_sub(1);
+        let is_upgrade = req.extensions().get::<Http11Upgrade>().is_some();
+        let retry = if remaining > 0 && !is_upgrade {
+            req.try_clone()
+        } else {
+            None
+        };
+        let service = retry.as_ref().map(|_| self.inner.clone());
+        ResponseFuture {
+            state: State::Called {
+                future: self.inner.call(req),
+                retry,
+            },
+            service,
+            remaining,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<S, B> Future for ResponseFuture<S, B>
+where
+    S: svc::Service<Request<B>>,
+    S::Error: Into<Error>,
+    B: TryClone,
+{
+    type Item = S::Response;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let state = match self.state {
+                State::Called {
+                    ref mut future,
+                    ref mut retry,
+                } => match future.poll() {
+                    Ok(ready) => return Ok(ready),
+                    Err(e) => {
+                        let e = e.into();
+                        match retry.take() {
+                            Some(req) if is_connect_error(&*e) => {
+                                debug!("retrying request on another endpoint: {}", e);
+                                State::Retrying(Some(req))
+                            }
+                            _ => return Err(e),
+                        }
+                    }
+                },
+                State::Retrying(ref mut req) => {
+                    let service = self
+                        .service
+                        .as_mut()
+                        .expect("service must be set while retrying");
+                    try_ready!(service.poll_ready().map_err(Into::into));
+
+                    let req = req.take().expect("request must only be sent once");
+                    self.remaining -= 1;
+                    let retry = if self.remaining > 0 {
+                        req.try_clone()
+                    } else {
+                        None
+                    };
+                    State::Called {
+                        future: service.call(req),
+                        retry,
+                    }
+                }
+            };
+            self.state = state;
+        }
+    }
+}
+
+// === impl EjectLayer ===
+
+impl<T, M> svc::Layer<T, T, M> for EjectLayer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <EjectStack<M> as svc::Stack<T>>::Value;
+    type Error = <EjectStack<M> as svc::Stack<T>>::Error;
+    type Stack = EjectStack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        EjectStack {
+            inner,
+            backoff: self.backoff,
+        }
+    }
+}
+
+// === impl EjectStack ===
+
+impl<T, M> svc::Stack<T> for EjectStack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Eject<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Eject {
+            inner,
+            backoff: self.backoff,
+            ejected: Arc::new(Mutex::new(None)),
+            delay: None,
+        })
+    }
+}
+
+// === impl Eject ===
+
+impl<S: Clone> Clone for Eject<S> {
+    fn clone(&self) -> Self {
+        Eject {
+            inner: self.inner.clone(),
+            backoff: self.backoff,
+            ejected: self.ejected.clone(),
+            delay: None,
+        }
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Eject<S>
+where
+    S: svc::Service<Req>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = EjectFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        let ejected = *self.ejected.lock().expect("eject lock poisoned");
+        if let Some(until) = ejected {
+            if until > clock::now() {
+                let delay = self.delay.get_or_insert_with(|| Delay::new(until));
+                if delay.deadline() != until {
+                    delay.reset(until);
+                }
+                match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => {}
+                    Err(e) => error!("timer failed; continuing without backoff: {}", e),
+                }
+            }
+        }
+        self.delay = None;
+
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        EjectFuture {
+            inner: self.inner.call(req),
+            backoff: self.backoff,
+            ejected: self.ejected.clone(),
+        }
+    }
+}
+
+// === impl EjectFuture ===
+
+impl<F> Future for EjectFuture<F>
+where
+    F: Future,
+    F::Error: Into<Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner.poll() {
+            Ok(ready) => Ok(ready),
+            Err(e) => {
+                let e = e.into();
+                if is_connect_error(&*e) {
+                    trace!("ejecting endpoint for {:?}", self.backoff);
+                    let until = clock::now() + self.backoff;
+                    *self.ejected.lock().expect("eject lock poisoned") = Some(until);
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use svc::Service as _Service;
+
+    /// Refuses the first `refusals` requests.
+    #[derive(Clone)]
+    struct Refuse {
+        refusals: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    struct Body(bool);
+
+    impl TryClone for Body {
+        fn try_clone(&self) -> Option<Self> {
+            if self.0 {
+                Some(Body(true))
+            } else {
+                None
+            }
+        }
+    }
+
+    impl svc::Service<Request<Body>> for Refuse {
+        type Response = ();
+        type Error = io::Error;
+        type Future = future::FutureResult<(), io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Request<Body>) -> Self::Future {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.refusals {
+                future::err(io::ErrorKind::ConnectionRefused.into())
+            } else {
+                future::ok(())
+            }
+        }
+    }
+
+    fn send(attempts: usize, refusals: usize, body: Body) -> (bool, usize) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut svc = Service {
+            inner: Refuse {
+                refusals,
+                calls: calls.clone(),
+            },
+            attempts,
+        };
+        let ok = svc.call(Request::new(body)).wait().is_ok();
+        (ok, calls.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn retries_refused_connections() {
+        assert_eq!(send(3, 2, Body(true)), (true, 3));
+    }
+
+    #[test]
+    fn retries_are_bounded() {
+        assert_eq!(send(3, 3, Body(true)), (false, 3));
+        assert_eq!(send(1, 1, Body(true)), (false, 1));
+    }
+
+    #[test]
+    fn does_not_retry_uncloneable_requests() {
+        assert_eq!(send(3, 1, Body(false)), (false, 1));
+    }
+}
@@ -0,0 +1,265 @@
+//! Bounds the time that the inbound proxy spends on a request by the deadline
+//! that its client set.
+//!
+//! A client may indicate how long it is willing to wait for a response with
+//! either an `l5d-deadline` or a `grpc-timeout` header. Both hold a relative
+//! timeout in the `grpc-timeout` format: up to eight digits followed by a unit,
+//! e.g. `250m` for 250 milliseconds. When the deadline of a trusted request
+//! elapses before a response is received, the request is cancelled and the
+//! client receives a `504 Gateway Timeout`.
+//!
+//! The headers are forwarded unmodified, so that the application may honor the
+//! deadline as well.
+
+use futures::{Async, Future, Poll};
+use http::{self, header, StatusCode};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_timer::{clock, Delay};
+
+use proxy::server::Source;
+use svc;
+
+pub const L5D_DEADLINE: &str = "l5d-deadline";
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+
+/// Determines which clients' deadlines are honored.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Trust {
+    /// Deadline headers are ignored.
+    Never,
+    /// Deadlines are honored for clients with a verified identity.
+    Meshed,
+    /// Deadlines are honored for all clients.
+    Always,
+}
+
+pub fn layer(trust: Trust) -> Layer {
+    Layer { trust }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    trust: Trust,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    trust: Trust,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    /// Whether the client's deadlines are honored.
+    trusted: bool,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    /// Set when the request has a deadline.
+    deadline: Option<(Delay, Duration)>,
+}
+
+/// Reads the timeout set by a request's deadline headers, if any.
+///
+/// `l5d-deadline` takes precedence over `grpc-timeout`.
+fn timeout<B>(req: &http::Request<B>) -> Option<Duration> {
+    [L5D_DEADLINE, GRPC_TIMEOUT]
+        .iter()
+        .filter_map(|name| req.headers().get(*name))
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(parse_timeout)
+        .next()
+}
+
+/// Parses a timeout in the `grpc-timeout` format.
+fn parse_timeout(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    if s.len() < 2 {
+        return None;
+    }
+    let (value, unit) = s.split_at(s.len() - 1);
+    if value.len() > 8 || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let value = u64::from_str(value).ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(value * 60 * 60)),
+        "M" => Some(Duration::from_secs(value * 60)),
+        "S" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_millis(value)),
+        "u" => Some(Duration::from_micros(value)),
+        "n" => Some(Duration::from_nanos(value)),
+        _ => None,
+    }
+}
+
+// === impl Trust ===
+
+impl Default for Trust {
+    fn default() -> Self {
+        Trust::Meshed
+    }
+}
+
+impl FromStr for Trust {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("never") => Ok(Trust::Never),
+            s if s.eq_ignore_ascii_case("meshed") => Ok(Trust::Meshed),
+            s if s.eq_ignore_ascii_case("always") => Ok(Trust::Always),
+            _ => Err(()),
+        }
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Source, Source, M> for Layer
+where
+    M: svc::Stack<Source>,
+{
+    type Value = <Stack<M> as svc::Stack<Source>>::Value;
+    type Error = <Stack<M> as svc::Stack<Source>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            trust: self.trust,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Source> for Stack<M>
+where
+    M: svc::Stack<Source>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, source: &Source) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(source)?;
+        let trusted = match self.trust {
+            Trust::Never => false,
+            Trust::Meshed => source.tls_peer.is_some(),
+            Trust::Always => true,
+        };
+        Ok(Service { inner, trusted })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+    B: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let deadline = if self.trusted { timeout(&req) } else { None };
+        let deadline = deadline.map(|t| (Delay::new(clock::now() + t), t));
+        ResponseFuture {
+            inner: self.inner.call(req),
+            deadline,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(rsp) = self.inner.poll()? {
+            return Ok(Async::Ready(rsp));
+        }
+
+        let expired = match self.deadline {
+            None => return Ok(Async::NotReady),
+            Some((ref mut delay, timeout)) => match delay.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(())) => Some(timeout),
+                Err(e) => {
+                    error!("timer failed; ignoring deadline: {}", e);
+                    None
+                }
+            },
+        };
+
+        match expired {
+            Some(timeout) => {
+                debug!("request deadline of {:?} exceeded", timeout);
+                let rsp = http::Response::builder()
+                    .status(StatusCode::GATEWAY_TIMEOUT)
+                    .header(header::CONTENT_LENGTH, "0")
+                    .body(B::default())
+                    .expect("deadline response must be valid");
+                Ok(Async::Ready(rsp))
+            }
+            None => {
+                self.deadline = None;
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timeouts() {
+        assert_eq!(parse_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_timeout("2M"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_timeout("3S"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_timeout("10u"), Some(Duration::from_micros(10)));
+        assert_eq!(
+            parse_timeout("99999999n"),
+            Some(Duration::from_nanos(99999999))
+        );
+        assert_eq!(parse_timeout("123456789m"), None);
+        assert_eq!(parse_timeout("-1m"), None);
+        assert_eq!(parse_timeout("10"), None);
+        assert_eq!(parse_timeout("m"), None);
+        assert_eq!(parse_timeout("1.5S"), None);
+    }
+
+    #[test]
+    fn prefers_l5d_deadline() {
+        let req = http::Request::builder()
+            .header(GRPC_TIMEOUT, "1S")
+            .header(L5D_DEADLINE, "100m")
+            .body(())
+            .unwrap();
+        assert_eq!(timeout(&req), Some(Duration::from_millis(100)));
+
+        let req = http::Request::builder()
+            .header(GRPC_TIMEOUT, "1S")
+            .body(())
+            .unwrap();
+        assert_eq!(timeout(&req), Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,456 @@
+//! Tags requests to external domains for cost attribution.
+//!
+//! Requests to names within a configured set of domains are annotated with a
+//! header identifying the workload that sent them (e.g. its team and service),
+//! and the bytes of their bodies are counted per domain, so that egress costs
+//! may be charged back to the workloads that incur them.
+
+use bytes::Buf;
+use futures::{Async, Poll};
+use http::header::{HeaderName, HeaderValue};
+use http::{self, Request};
+use hyper::body::Payload;
+use indexmap::IndexMap;
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use tower_http_service;
+
+use super::retry::TryClone;
+use dns;
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics};
+use svc;
+use Addr;
+
+metrics! {
+    egress_request_bytes_total: Counter {
+        "Total count of request body bytes sent to external domains"
+    }
+}
+
+/// Configures which domains are tagged, and how.
+#[derive(Clone, Debug)]
+pub struct CostAttribution {
+    header: HeaderName,
+    value: HeaderValue,
+    domains: Vec<dns::Suffix>,
+}
+
+pub fn new() -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(IndexMap::new()));
+    (Registry(inner.clone()), Report(inner))
+}
+
+/// Records egress metrics for each domain.
+#[derive(Clone, Debug)]
+pub struct Registry(Arc<Mutex<Domains>>);
+
+/// Implements `FmtMetrics` to render prometheus-formatted egress metrics.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Domains>>);
+
+type Domains = IndexMap<Domain, Arc<Mutex<Counter>>>;
+
+/// Labels metrics with the configured domain that a request's name is within.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct Domain(dns::Suffix);
+
+pub fn layer(config: Option<CostAttribution>, registry: Registry) -> Layer {
+    Layer { config, registry }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    config: Option<CostAttribution>,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    config: Option<CostAttribution>,
+    registry: Registry,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    tag: Option<Tag>,
+}
+
+#[derive(Clone, Debug)]
+struct Tag {
+    header: HeaderName,
+    value: HeaderValue,
+    bytes: Arc<Mutex<Counter>>,
+}
+
+/// A request body that counts the bytes sent to an external domain.
+///
+/// When the body is cloned so that its request may be retried, the clones
+/// share a count of the bytes that have been recorded, so that each of the
+/// request's bytes is only recorded once.
+#[derive(Debug)]
+pub struct CountBody<B> {
+    inner: B,
+    read: usize,
+    count: Option<Count>,
+}
+
+#[derive(Clone, Debug)]
+struct Count {
+    bytes: Arc<Mutex<Counter>>,
+    recorded: Arc<AtomicUsize>,
+}
+
+// === impl CostAttribution ===
+
+impl CostAttribution {
+    pub fn new(header: HeaderName, value: HeaderValue, domains: Vec<dns::Suffix>) -> Self {
+        Self {
+            header,
+            value,
+            domains,
+        }
+    }
+
+    fn domain(&self, addr: &Addr) -> Option<&dns::Suffix> {
+        let name = addr.name_addr()?.name();
+        self.domains.iter().find(|sfx| sfx.contains(name))
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Addr, Addr, M> for Layer
+where
+    M: svc::Stack<Addr>,
+{
+    type Value = <Stack<M> as svc::Stack<Addr>>::Value;
+    type Error = <Stack<M> as svc::Stack<Addr>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Addr> for Stack<M>
+where
+    M: svc::Stack<Addr>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, addr: &Addr) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(addr)?;
+
+        let tag = self.config.as_ref().and_then(|config| {
+            let domain = config.domain(addr)?;
+            debug!("tagging egress to {}; domain={}", addr, domain);
+            Some(Tag {
+                header: config.header.clone(),
+                value: config.value.clone(),
+                bytes: self.registry.bytes(domain),
+            })
+        });
+
+        Ok(Service { inner, tag })
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<Request<B>> for Service<S>
+where
+    S: svc::Service<Request<CountBody<B>>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let count = self.tag.as_ref().map(|tag| {
+            req.headers_mut()
+                .insert(tag.header.clone(), tag.value.clone());
+            Count {
+                bytes: tag.bytes.clone(),
+                recorded: Arc::new(AtomicUsize::new(0)),
+            }
+        });
+
+        self.inner.call(req.map(move |inner| CountBody {
+            inner,
+            read: 0,
+            count,
+        }))
+    }
+}
+
+// === impl CountBody ===
+
+impl<B: Payload> Payload for CountBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = try_ready!(self.inner.poll_data());
+
+        if let (Some(ref count), Some(ref d)) = (self.count.as_ref(), data.as_ref()) {
+            self.read += d.remaining();
+            count.record(self.read);
+        }
+
+        Ok(Async::Ready(data))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+impl<B: Payload> tower_http_service::Body for CountBody<B> {
+    type Item = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_buf(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B: TryClone> TryClone for CountBody<B> {
+    fn try_clone(&self) -> Option<Self> {
+        self.inner.try_clone().map(|inner| CountBody {
+            inner,
+            read: 0,
+            count: self.count.clone(),
+        })
+    }
+}
+
+// === impl Count ===
+
+impl Count {
+    /// Records the bytes of a body, of which `read` have been read, that have
+    /// not already been recorded.
+    fn record(&self, read: usize) {
+        let mut recorded = self.recorded.load(Ordering::Acquire);
+        while recorded < read {
+            let prev = self
+                .recorded
+                .compare_and_swap(recorded, read, Ordering::AcqRel);
+            if prev == recorded {
+                if let Ok(mut bytes) = self.bytes.lock() {
+                    *bytes += (read - recorded) as u64;
+                }
+                return;
+            }
+            recorded = prev;
+        }
+    }
+}
+
+// === impl Registry ===
+
+impl Registry {
+    fn bytes(&self, domain: &dns::Suffix) -> Arc<Mutex<Counter>> {
+        match self.0.lock() {
+            Ok(mut domains) => domains
+                .entry(Domain(domain.clone()))
+                .or_insert_with(Default::default)
+                .clone(),
+            // If the registry is poisoned, bytes are still counted, but they
+            // are not reported.
+            Err(_) => Default::default(),
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let domains = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if domains.is_empty() {
+            return Ok(());
+        }
+
+        egress_request_bytes_total.fmt_help(f)?;
+        for (domain, bytes) in domains.iter() {
+            if let Ok(bytes) = bytes.lock() {
+                bytes.fmt_metric_labeled(f, egress_request_bytes_total.name, domain)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Domain ===
+
+impl FmtLabels for Domain {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dst_domain=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use convert::TryFrom;
+    use futures::future;
+    use std::collections::VecDeque;
+    use std::io::Cursor;
+
+    #[derive(Clone, Debug, Default)]
+    struct Chunks(VecDeque<Bytes>);
+
+    impl TryClone for Chunks {
+        fn try_clone(&self) -> Option<Self> {
+            Some(self.clone())
+        }
+    }
+
+    impl Payload for Chunks {
+        type Data = Cursor<Bytes>;
+        type Error = ::h2::Error;
+
+        fn is_end_stream(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+            Ok(Async::Ready(self.0.pop_front().map(Cursor::new)))
+        }
+    }
+
+    /// Returns each request it receives, without reading its body.
+    #[derive(Clone)]
+    struct Echo;
+
+    impl<B> svc::Service<Request<B>> for Echo {
+        type Response = Request<B>;
+        type Error = ();
+        type Future = future::FutureResult<Request<B>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, req: Request<B>) -> Self::Future {
+            future::ok(req)
+        }
+    }
+
+    fn service(registry: &Registry, addr: &str) -> Service<Echo> {
+        use svc::Stack as _Stack;
+
+        let config = CostAttribution::new(
+            HeaderName::from_static("l5d-cost-attribution"),
+            HeaderValue::from_static("team=payments"),
+            vec![dns::Suffix::try_from("example.com").unwrap()],
+        );
+        layer(Some(config), registry.clone())
+            .bind(svc::shared::stack(Echo))
+            .make(&Addr::from_str(addr).unwrap())
+            .unwrap()
+    }
+
+    fn request(chunks: &[&'static str]) -> Request<Chunks> {
+        let chunks = chunks.iter().map(|c| Bytes::from_static(c.as_bytes()));
+        Request::new(Chunks(chunks.collect()))
+    }
+
+    fn read_to_end(body: &mut CountBody<Chunks>) {
+        while let Async::Ready(Some(_)) = body.poll_data().expect("body must not fail") {}
+    }
+
+    #[test]
+    fn tags_requests_to_configured_domains() {
+        use svc::Service as _Service;
+
+        let (registry, report) = new();
+
+        let req = service(&registry, "api.example.com:443")
+            .call(request(&[]))
+            .wait()
+            .unwrap();
+        assert_eq!(req.headers()["l5d-cost-attribution"], "team=payments");
+
+        let req = service(&registry, "example.org:443")
+            .call(request(&[]))
+            .wait()
+            .unwrap();
+        assert!(req.headers().get("l5d-cost-attribution").is_none());
+
+        let out = report.as_display().to_string();
+        assert!(out.contains("dst_domain=\"example.com\""), "{}", out);
+        assert!(!out.contains("example.org"), "{}", out);
+    }
+
+    #[test]
+    fn counts_retried_bytes_once() {
+        use svc::Service as _Service;
+
+        let (registry, report) = new();
+        let req = service(&registry, "api.example.com:443")
+            .call(request(&["hello", "world"]))
+            .wait()
+            .unwrap();
+
+        let mut body = req.into_body();
+        let mut retry = body.try_clone().expect("body must be cloneable");
+        read_to_end(&mut body);
+        read_to_end(&mut retry);
+
+        let out = report.as_display().to_string();
+        assert!(
+            out.contains("egress_request_bytes_total{dst_domain=\"example.com\"} 10"),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn counts_bytes_read_by_a_retry_beyond_the_original() {
+        let (registry, _) = new();
+        let count = Count {
+            bytes: registry.bytes(&dns::Suffix::try_from("example.com").unwrap()),
+            recorded: Arc::new(AtomicUsize::new(0)),
+        };
+
+        count.record(5);
+        count.record(3);
+        count.record(12);
+        assert_eq!(count.bytes.lock().unwrap().value(), 12);
+    }
+}
@@ -0,0 +1,188 @@
+//! Classifies the errors that HTTP stacks fail with, so that failures may be
+//! labeled by their kind rather than reported as opaque errors.
+
+use h2;
+use std::error::Error as StdError;
+use std::{fmt, io};
+
+use super::glue;
+use super::router::error as router;
+use svc::linkerd2_timeout::error::Timedout;
+use transport::tls;
+
+/// The kind of failure that caused a stack to fail a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Timeout,
+    /// A connection could not be established.
+    Connect,
+    Tls,
+    /// The peer violated the HTTP protocol, or reset the stream.
+    Protocol,
+    /// A router's route cache is full.
+    NoCapacity,
+    /// A router could not determine a target for the request.
+    NotRecognized,
+    Other,
+}
+
+/// Implemented by errors that know what kind of failure they represent.
+pub trait HasErrorKind {
+    /// Returns `None` if the kind is determined by the error's source, if any.
+    fn error_kind(&self) -> Option<Kind>;
+}
+
+// === impl Kind ===
+
+impl Kind {
+    /// Classifies `err` by the first error in its chain of sources that has a
+    /// known kind.
+    pub fn of(err: &(dyn StdError + 'static)) -> Self {
+        let mut cause = Some(err);
+        while let Some(err) = cause {
+            if let Some(kind) = known_kind(err) {
+                return kind;
+            }
+            cause = err.source();
+        }
+        Kind::Other
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Timeout => "timeout",
+            Kind::Connect => "connect",
+            Kind::Tls => "tls",
+            Kind::Protocol => "protocol",
+            Kind::NoCapacity => "no_capacity",
+            Kind::NotRecognized => "not_recognized",
+            Kind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+fn known_kind(err: &(dyn StdError + 'static)) -> Option<Kind> {
+    if let Some(e) = err.downcast_ref::<Timedout>() {
+        e.error_kind()
+    } else if let Some(e) = err.downcast_ref::<io::Error>() {
+        e.error_kind()
+    } else if let Some(e) = err.downcast_ref::<tls::Error>() {
+        e.error_kind()
+    } else if let Some(e) = err.downcast_ref::<glue::Error>() {
+        e.error_kind()
+    } else if let Some(e) = err.downcast_ref::<h2::Error>() {
+        e.error_kind()
+    } else if let Some(e) = err.downcast_ref::<router::NoCapacity>() {
+        e.error_kind()
+    } else if let Some(e) = err.downcast_ref::<router::NotRecognized>() {
+        e.error_kind()
+    } else {
+        None
+    }
+}
+
+// === impl HasErrorKind ===
+
+impl HasErrorKind for Box<dyn StdError + Send + Sync> {
+    fn error_kind(&self) -> Option<Kind> {
+        Some(Kind::of(&**self))
+    }
+}
+
+impl HasErrorKind for Timedout {
+    fn error_kind(&self) -> Option<Kind> {
+        Some(Kind::Timeout)
+    }
+}
+
+impl HasErrorKind for io::Error {
+    fn error_kind(&self) -> Option<Kind> {
+        // `io::Error::source` skips over a wrapped error, so it is inspected
+        // here.
+        if let Some(inner) = self.get_ref() {
+            if inner.is::<tls::Error>() {
+                return Some(Kind::Tls);
+            }
+        }
+
+        match self.kind() {
+            io::ErrorKind::TimedOut => Some(Kind::Timeout),
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::AddrNotAvailable => Some(Kind::Connect),
+            io::ErrorKind::InvalidData => Some(Kind::Protocol),
+            _ => None,
+        }
+    }
+}
+
+impl HasErrorKind for tls::Error {
+    fn error_kind(&self) -> Option<Kind> {
+        Some(Kind::Tls)
+    }
+}
+
+impl HasErrorKind for glue::Error {
+    fn error_kind(&self) -> Option<Kind> {
+        if self.is_connect() {
+            Some(Kind::Connect)
+        } else {
+            None
+        }
+    }
+}
+
+impl HasErrorKind for h2::Error {
+    fn error_kind(&self) -> Option<Kind> {
+        Some(Kind::Protocol)
+    }
+}
+
+impl HasErrorKind for router::NoCapacity {
+    fn error_kind(&self) -> Option<Kind> {
+        Some(Kind::NoCapacity)
+    }
+}
+
+impl HasErrorKind for router::NotRecognized {
+    fn error_kind(&self) -> Option<Kind> {
+        Some(Kind::NotRecognized)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    #[test]
+    fn classifies_by_source() {
+        let refused: Error = io::Error::from(io::ErrorKind::ConnectionRefused).into();
+        assert_eq!(refused.error_kind(), Some(Kind::Connect));
+
+        let timeout: Error = io::Error::from(io::ErrorKind::TimedOut).into();
+        assert_eq!(timeout.error_kind(), Some(Kind::Timeout));
+
+        let not_recognized: Error = router::NotRecognized.into();
+        assert_eq!(not_recognized.error_kind(), Some(Kind::NotRecognized));
+
+        let no_capacity: Error = router::NoCapacity {
+            capacity: 1,
+            in_flight: 1,
+        }
+        .into();
+        assert_eq!(no_capacity.error_kind(), Some(Kind::NoCapacity));
+
+        let other: Error = "bad".into();
+        assert_eq!(other.error_kind(), Some(Kind::Other));
+    }
+}
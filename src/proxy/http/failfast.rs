@@ -0,0 +1,248 @@
+//! Fails requests quickly when a service is unavailable.
+//!
+//! When an inner service, such as a balancer without endpoints, remains
+//! unready for longer than a configured duration, the service enters a
+//! failfast state: it reports itself as ready and fails each request with a
+//! `503 Service Unavailable` response, rather than letting requests wait until
+//! they time out. Once the inner service becomes ready, requests are
+//! dispatched to it as usual.
+//!
+//! When no duration is configured, requests are never failed early.
+
+use futures::{Async, Future, Poll};
+use http::{Request, Response, StatusCode};
+use std::time::Duration;
+use tokio_timer::{clock, Delay};
+
+use svc;
+
+/// A marker set in `http::Response::extensions` that *this* process failed
+/// the request because the service was unavailable.
+#[derive(Debug)]
+pub struct FailFast(());
+
+pub fn layer(timeout: Option<Duration>) -> Layer {
+    Layer { timeout }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    timeout: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    timeout: Option<Duration>,
+}
+
+pub struct Service<S> {
+    inner: S,
+    timeout: Duration,
+    state: State,
+}
+
+pub struct ResponseFuture<F> {
+    inner: Option<F>,
+}
+
+enum State {
+    Open,
+    /// The inner service has not been ready since the delay was started.
+    Waiting(Delay),
+    FailFast,
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = svc::Either<Service<M::Value>, M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Ok(svc::Either::B(inner)),
+        };
+
+        Ok(svc::Either::A(Service {
+            inner,
+            timeout,
+            state: State::Open,
+        }))
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<Request<A>> for Service<S>
+where
+    S: svc::Service<Request<A>, Response = Response<B>>,
+    B: Default,
+{
+    type Response = Response<B>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        loop {
+            if self.inner.poll_ready()?.is_ready() {
+                if let State::FailFast = self.state {
+                    debug!("service has recovered");
+                }
+                self.state = State::Open;
+                return Ok(Async::Ready(()));
+            }
+
+            self.state = match self.state {
+                State::Open => State::Waiting(Delay::new(clock::now() + self.timeout)),
+                State::Waiting(ref mut delay) => match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) | Err(_) => {
+                        warn!(
+                            "service unavailable for {:?}; failing requests",
+                            self.timeout
+                        );
+                        State::FailFast
+                    }
+                },
+                // Requests fail immediately until the inner service recovers.
+                State::FailFast => return Ok(Async::Ready(())),
+            };
+        }
+    }
+
+    fn call(&mut self, req: Request<A>) -> Self::Future {
+        if let State::FailFast = self.state {
+            return ResponseFuture { inner: None };
+        }
+
+        ResponseFuture {
+            inner: Some(self.inner.call(req)),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: Default,
+{
+    type Item = Response<B>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner {
+            Some(ref mut f) => f.poll(),
+            None => {
+                let mut res = Response::default();
+                *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                res.extensions_mut().insert(FailFast(()));
+                Ok(Async::Ready(res))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use svc::Service as _Service;
+    use tokio::runtime::current_thread::Runtime;
+
+    /// Ready only while `ready` is set.
+    #[derive(Clone)]
+    struct Toggle {
+        ready: Arc<AtomicBool>,
+    }
+
+    impl svc::Service<Request<()>> for Toggle {
+        type Response = Response<()>;
+        type Error = ();
+        type Future = future::FutureResult<Response<()>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            if self.ready.load(Ordering::SeqCst) {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        }
+
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            assert!(self.ready.load(Ordering::SeqCst), "called when not ready");
+            future::ok(Response::default())
+        }
+    }
+
+    fn send<S>(rt: &mut Runtime, svc: &mut S) -> Response<()>
+    where
+        S: svc::Service<Request<()>, Response = Response<()>, Error = ()>,
+    {
+        rt.block_on(future::poll_fn(|| svc.poll_ready()))
+            .expect("ready");
+        rt.block_on(future::lazy(|| svc.call(Request::default())))
+            .expect("response")
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        use svc::Stack as _Stack;
+
+        let ready = Arc::new(AtomicBool::new(false));
+        let stack = layer(None).bind(svc::shared::stack(Toggle { ready }));
+        match stack.make(&()).unwrap() {
+            svc::Either::B(_) => {}
+            svc::Either::A(_) => panic!("failfast must be disabled"),
+        }
+    }
+
+    #[test]
+    fn fails_fast_until_ready() {
+        let mut rt = Runtime::new().unwrap();
+        let ready = Arc::new(AtomicBool::new(false));
+        let mut svc = Service {
+            inner: Toggle {
+                ready: ready.clone(),
+            },
+            timeout: Duration::from_millis(10),
+            state: State::Open,
+        };
+
+        let rsp = send(&mut rt, &mut svc);
+        assert_eq!(rsp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(rsp.extensions().get::<FailFast>().is_some());
+
+        ready.store(true, Ordering::SeqCst);
+        let rsp = send(&mut rt, &mut svc);
+        assert_eq!(rsp.status(), StatusCode::OK);
+        assert!(rsp.extensions().get::<FailFast>().is_none());
+    }
+}
@@ -0,0 +1,278 @@
+//! Handles the `X-Forwarded-For`, `X-Forwarded-Proto`, and RFC 7239
+//! `Forwarded` headers of requests.
+//!
+//! Since the proxy terminates its clients' connections, the application would
+//! otherwise only ever see the proxy's address as its peer.
+
+use futures::Poll;
+use http::{self, header::HeaderValue};
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use proxy::server::Source;
+use svc;
+
+const FORWARDED: &str = "forwarded";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Determines how forwarding headers are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Requests' headers are not modified.
+    Passthrough,
+    /// The peer is appended to any forwarding headers set by earlier hops.
+    Append,
+    /// Forwarding headers set by earlier hops, which cannot be verified, are
+    /// replaced with the peer's.
+    Sanitize,
+}
+
+pub fn layer(mode: Mode) -> Layer {
+    Layer { mode }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    mode: Mode,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    mode: Mode,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    mode: Mode,
+    peer: SocketAddr,
+}
+
+// === impl Mode ===
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Passthrough
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("passthrough") => Ok(Mode::Passthrough),
+            s if s.eq_ignore_ascii_case("append") => Ok(Mode::Append),
+            s if s.eq_ignore_ascii_case("sanitize") => Ok(Mode::Sanitize),
+            _ => Err(()),
+        }
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Source, Source, M> for Layer
+where
+    M: svc::Stack<Source>,
+{
+    type Value = <Stack<M> as svc::Stack<Source>>::Value;
+    type Error = <Stack<M> as svc::Stack<Source>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            mode: self.mode,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Source> for Stack<M>
+where
+    M: svc::Stack<Source>,
+{
+    type Value = svc::Either<Service<M::Value>, M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, source: &Source) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(source)?;
+        if self.mode == Mode::Passthrough {
+            return Ok(svc::Either::B(inner));
+        }
+
+        Ok(svc::Either::A(Service {
+            inner,
+            mode: self.mode,
+            peer: source.remote,
+        }))
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        // The scheme of the request as the client sent it; mesh TLS between
+        // proxies is transparent to the application, so it is not considered.
+        let proto = match req.uri().scheme_part() {
+            Some(s) if s == &http::uri::Scheme::HTTPS => "https",
+            _ => "http",
+        };
+        forward(self.mode, self.peer, proto, req.headers_mut());
+        self.inner.call(req)
+    }
+}
+
+fn forward(mode: Mode, peer: SocketAddr, proto: &'static str, headers: &mut http::HeaderMap) {
+    match mode {
+        Mode::Passthrough => return,
+        Mode::Sanitize => {
+            headers.remove(X_FORWARDED_FOR);
+            headers.remove(X_FORWARDED_PROTO);
+            headers.remove(FORWARDED);
+        }
+        Mode::Append => {}
+    }
+
+    let ip = peer.ip();
+    // IPv6 nodes must be quoted and bracketed in `Forwarded`.
+    let node = if ip.is_ipv6() {
+        format!("\"[{}]\"", ip)
+    } else {
+        ip.to_string()
+    };
+    let forwarded = format!("for={};proto={}", node, proto);
+
+    append(headers, X_FORWARDED_FOR, &ip.to_string());
+    append(headers, FORWARDED, &forwarded);
+    if !headers.contains_key(X_FORWARDED_PROTO) {
+        headers.insert(X_FORWARDED_PROTO, HeaderValue::from_static(proto));
+    }
+}
+
+/// Appends `value` to the comma-separated list in the `name` header, joining
+/// all prior values of the header into a single value.
+fn append(headers: &mut http::HeaderMap, name: &'static str, value: &str) {
+    let mut list = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if !list.is_empty() {
+        list.push_str(", ");
+    }
+    list.push_str(value);
+
+    match HeaderValue::from_str(&list) {
+        Ok(v) => {
+            headers.insert(name, v);
+        }
+        Err(_) => debug!("invalid {} header", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{future, Future};
+
+    fn headers() -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, "203.0.113.7".parse().unwrap());
+        headers.insert(X_FORWARDED_PROTO, "https".parse().unwrap());
+        headers.insert(FORWARDED, "for=203.0.113.7;proto=https".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn appends_peer() {
+        let mut h = headers();
+        forward(
+            Mode::Append,
+            "10.1.1.1:4000".parse().unwrap(),
+            "http",
+            &mut h,
+        );
+        assert_eq!(h[X_FORWARDED_FOR], "203.0.113.7, 10.1.1.1");
+        assert_eq!(h[X_FORWARDED_PROTO], "https");
+        assert_eq!(
+            h[FORWARDED],
+            "for=203.0.113.7;proto=https, for=10.1.1.1;proto=http"
+        );
+    }
+
+    #[test]
+    fn sanitizes_untrusted_values() {
+        let mut h = headers();
+        forward(
+            Mode::Sanitize,
+            "[2001:db8::1]:4000".parse().unwrap(),
+            "http",
+            &mut h,
+        );
+        assert_eq!(h[X_FORWARDED_FOR], "2001:db8::1");
+        assert_eq!(h[X_FORWARDED_PROTO], "http");
+        assert_eq!(h[FORWARDED], "for=\"[2001:db8::1]\";proto=http");
+    }
+
+    /// Returns each request it receives.
+    struct Echo;
+
+    impl svc::Service<http::Request<()>> for Echo {
+        type Response = http::Request<()>;
+        type Error = ();
+        type Future = future::FutureResult<http::Request<()>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(().into())
+        }
+
+        fn call(&mut self, req: http::Request<()>) -> Self::Future {
+            future::ok(req)
+        }
+    }
+
+    #[test]
+    fn proto_is_the_request_scheme() {
+        use svc::Service as _Service;
+
+        let mut svc = Service {
+            inner: Echo,
+            mode: Mode::Sanitize,
+            peer: "10.1.1.1:4000".parse().unwrap(),
+        };
+
+        let req = http::Request::get("https://example.com/").body(()).unwrap();
+        let req = svc.call(req).wait().unwrap();
+        assert_eq!(req.headers()[X_FORWARDED_PROTO], "https");
+        assert_eq!(req.headers()[FORWARDED], "for=10.1.1.1;proto=https");
+
+        let req = http::Request::get("/").body(()).unwrap();
+        let req = svc.call(req).wait().unwrap();
+        assert_eq!(req.headers()[X_FORWARDED_PROTO], "http");
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("Append".parse::<Mode>(), Ok(Mode::Append));
+        assert_eq!(" sanitize".parse::<Mode>(), Ok(Mode::Sanitize));
+        assert_eq!("passthrough".parse::<Mode>(), Ok(Mode::Passthrough));
+        assert_eq!("strip".parse::<Mode>(), Err(()));
+    }
+}
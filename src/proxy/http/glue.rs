@@ -217,6 +217,14 @@ where
 
 // === impl Error ===
 
+impl Error {
+    /// Indicates whether the request failed because a connection could not be
+    /// established, in which case it was not sent.
+    pub fn is_connect(&self) -> bool {
+        self.0.is_connect()
+    }
+}
+
 impl HasH2Reason for Error {
     fn h2_reason(&self) -> Option<h2::Reason> {
         self.0
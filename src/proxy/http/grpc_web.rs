@@ -0,0 +1,374 @@
+//! Translates gRPC-Web requests into native gRPC.
+//!
+//! Browsers cannot speak gRPC directly, since they expose neither HTTP/2
+//! framing nor trailers. gRPC-Web clients instead send
+//! `application/grpc-web(+proto)` requests, possibly over HTTP/1.1, and expect
+//! trailers to be encoded at the end of the response body.
+//!
+//! Since the binary gRPC-Web message framing is identical to gRPC's, requests
+//! are translated by rewriting their headers and upgrading them to HTTP/2.
+//! Responses are translated back by encoding the application's trailers into a
+//! final, flagged, length-prefixed body frame.
+//!
+//! The base64-encoded `application/grpc-web-text` variant is not translated.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{Async, Future, Poll};
+use http::{
+    self,
+    header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, TE, TRANSFER_ENCODING},
+};
+use hyper::body::Payload;
+use std::io::Cursor;
+
+use super::h1;
+use svc;
+
+const GRPC: &str = "application/grpc";
+const GRPC_WEB: &str = "application/grpc-web";
+const GRPC_WEB_TEXT: &str = "application/grpc-web-text";
+
+/// Flags a gRPC-Web body frame as holding trailers rather than a message.
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// Translates gRPC-Web requests, if `enabled`.
+pub fn layer(enabled: bool) -> Layer {
+    Layer { enabled }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    enabled: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    enabled: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    enabled: bool,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    /// The version of the original gRPC-Web request, if it was translated.
+    translated: Option<http::Version>,
+}
+
+/// Encodes the inner body's trailers into the body, if the response is
+/// translated.
+#[derive(Debug, Default)]
+pub struct ResponseBody<B> {
+    inner: B,
+    state: State,
+}
+
+/// The data of a `ResponseBody`.
+#[derive(Debug)]
+pub enum Frame<D> {
+    Data(D),
+    Trailers(Cursor<Bytes>),
+}
+
+#[derive(Debug)]
+enum State {
+    Passthrough,
+    Data,
+    Trailers,
+    Done,
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            enabled: self.enabled,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            enabled: self.enabled,
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        let translated = if self.enabled {
+            translate_request(&mut req)
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            translated,
+        }
+    }
+}
+
+/// Rewrites a gRPC-Web request as a gRPC request, returning the request's
+/// original version if it was translated.
+fn translate_request<B>(req: &mut http::Request<B>) -> Option<http::Version> {
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .filter(|ct| ct.starts_with(GRPC_WEB))?
+        .to_owned();
+
+    if content_type.starts_with(GRPC_WEB_TEXT) {
+        debug!("not translating {}", content_type);
+        return None;
+    }
+
+    let grpc = format!("{}{}", GRPC, &content_type[GRPC_WEB.len()..]);
+    debug!("translating {} to {}", content_type, grpc);
+
+    let version = req.version();
+    if version != http::Version::HTTP_2 {
+        // Since the version is going to set to HTTP_2, the NormalizeUri
+        // middleware won't normalize the URI automatically, so it needs to be
+        // done now.
+        h1::normalize_our_view_of_uri(req);
+        h1::strip_connection_headers(req.headers_mut());
+        // transfer-encoding is illegal in HTTP2
+        req.headers_mut().remove(TRANSFER_ENCODING);
+        *req.version_mut() = http::Version::HTTP_2;
+    }
+
+    let headers = req.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(&grpc).expect("grpc content-type must be valid"),
+    );
+    headers.insert(TE, HeaderValue::from_static("trailers"));
+
+    Some(version)
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = http::Response<ResponseBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+
+        let version = match self.translated {
+            Some(version) => version,
+            None => return Ok(Async::Ready(rsp.map(ResponseBody::passthrough))),
+        };
+
+        let grpc_web = rsp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .filter(|ct| ct.starts_with(GRPC))
+            .map(|ct| format!("{}{}", GRPC_WEB, &ct[GRPC.len()..]))
+            .unwrap_or_else(|| GRPC_WEB.to_owned());
+
+        rsp.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&grpc_web).expect("grpc-web content-type must be valid"),
+        );
+        // The body is extended with trailers.
+        rsp.headers_mut().remove(CONTENT_LENGTH);
+        *rsp.version_mut() = version;
+
+        Ok(Async::Ready(rsp.map(|inner| ResponseBody {
+            inner,
+            state: State::Data,
+        })))
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B> ResponseBody<B> {
+    fn passthrough(inner: B) -> Self {
+        Self {
+            inner,
+            state: State::Passthrough,
+        }
+    }
+}
+
+impl<B: Payload> Payload for ResponseBody<B> {
+    type Data = Frame<B::Data>;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        match self.state {
+            State::Passthrough => self.inner.is_end_stream(),
+            State::Done => true,
+            State::Data | State::Trailers => false,
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Passthrough => {
+                    let data = try_ready!(self.inner.poll_data());
+                    return Ok(Async::Ready(data.map(Frame::Data)));
+                }
+                State::Data => match try_ready!(self.inner.poll_data()) {
+                    Some(data) => return Ok(Async::Ready(Some(Frame::Data(data)))),
+                    None => State::Trailers,
+                },
+                State::Trailers => {
+                    let trailers = try_ready!(self.inner.poll_trailers()).unwrap_or_default();
+                    self.state = State::Done;
+                    let frame = encode_trailers(&trailers);
+                    return Ok(Async::Ready(Some(Frame::Trailers(Cursor::new(frame)))));
+                }
+                State::Done => return Ok(Async::Ready(None)),
+            };
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        match self.state {
+            State::Passthrough => self.inner.poll_trailers(),
+            _ => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Passthrough
+    }
+}
+
+/// Encodes trailers as a gRPC-Web trailers frame.
+fn encode_trailers(trailers: &http::HeaderMap) -> Bytes {
+    let mut block = BytesMut::new();
+    for (name, value) in trailers.iter() {
+        block.reserve(name.as_str().len() + value.len() + 4);
+        block.put_slice(name.as_str().as_bytes());
+        block.put_slice(b": ");
+        block.put_slice(value.as_bytes());
+        block.put_slice(b"\r\n");
+    }
+
+    let mut frame = BytesMut::with_capacity(5 + block.len());
+    frame.put_u8(TRAILERS_FLAG);
+    frame.put_u32_be(block.len() as u32);
+    frame.put_slice(&block);
+    frame.freeze()
+}
+
+// === impl Frame ===
+
+impl<D: Buf> Buf for Frame<D> {
+    fn remaining(&self) -> usize {
+        match self {
+            Frame::Data(ref d) => d.remaining(),
+            Frame::Trailers(ref t) => t.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Frame::Data(ref d) => d.bytes(),
+            Frame::Trailers(ref t) => t.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match self {
+            Frame::Data(ref mut d) => d.advance(cnt),
+            Frame::Trailers(ref mut t) => t.advance(cnt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_grpc_web_requests() {
+        let mut req = http::Request::builder()
+            .version(http::Version::HTTP_11)
+            .uri("/svc.Greeter/Hello")
+            .header("host", "greeter.ns.svc.cluster.local")
+            .header(CONTENT_TYPE, "application/grpc-web+proto")
+            .body(())
+            .unwrap();
+
+        assert_eq!(translate_request(&mut req), Some(http::Version::HTTP_11));
+        assert_eq!(req.version(), http::Version::HTTP_2);
+        assert_eq!(req.headers()[CONTENT_TYPE], "application/grpc+proto");
+        assert_eq!(req.headers()[TE], "trailers");
+    }
+
+    #[test]
+    fn does_not_translate_other_requests() {
+        for ct in &[
+            "application/grpc",
+            "application/grpc-web-text",
+            "text/plain",
+        ] {
+            let mut req = http::Request::builder()
+                .header(CONTENT_TYPE, *ct)
+                .body(())
+                .unwrap();
+            assert_eq!(translate_request(&mut req), None, "content-type={}", ct);
+        }
+    }
+
+    #[test]
+    fn encodes_trailers_frame() {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+
+        let frame = encode_trailers(&trailers);
+        assert_eq!(&frame[..], &b"\x80\x00\x00\x00\x10grpc-status: 0\r\n"[..]);
+    }
+}
@@ -10,12 +10,20 @@ use super::upgrade::HttpConnect;
 
 /// Tries to make sure the `Uri` of the request is in a form needed by
 /// hyper's Client.
+///
+/// Requests in absolute-form, as sent by clients that treat the proxy as a
+/// forward proxy, already name their authority. As required by RFC 7230
+/// section 5.4, it takes precedence over the `Host` header, so the target is
+/// rewritten into origin-form with its authority, and the `Host` header is
+/// left as it is.
 pub fn normalize_our_view_of_uri<B>(req: &mut http::Request<B>) {
-    debug_assert!(
-        req.uri().scheme_part().is_none(),
-        "normalize_uri shouldn't be called with absolute URIs: {:?}",
-        req.uri()
-    );
+    if is_absolute_form(req.uri()) {
+        trace!("rewriting absolute-form request target: {:?}", req.uri());
+        let auth = absolute_form_authority(req.uri());
+        set_origin_form(req.uri_mut());
+        set_authority(req.uri_mut(), auth);
+        return;
+    }
 
     // try to parse the Host header
     if let Some(auth) = authority_from_host(&req) {
@@ -46,6 +54,22 @@ pub fn set_origin_form(uri: &mut Uri) {
     *uri = Uri::from_parts(parts).expect("path only is valid origin-form uri")
 }
 
+/// Returns the authority of an absolute-form target, with an explicit port if
+/// its scheme implies one other than HTTP's, since the scheme is not kept.
+fn absolute_form_authority(uri: &Uri) -> Authority {
+    let auth = uri
+        .authority_part()
+        .cloned()
+        .expect("absolute-form uri must have an authority");
+    if uri.scheme_part() != Some(&Scheme::HTTPS) || auth.port_part().is_some() {
+        return auth;
+    }
+
+    let mut bytes = BytesMut::with_capacity(auth.as_str().len() + 4);
+    write!(&mut bytes, "{}:443", auth).expect("authority must be writable");
+    Authority::from_shared(bytes.freeze()).unwrap_or(auth)
+}
+
 /// Returns an Authority from a request's Host header.
 pub fn authority_from_host<B>(req: &http::Request<B>) -> Option<Authority> {
     super::authority_from_header(req, HOST)
@@ -160,6 +184,13 @@ pub fn is_absolute_form(uri: &Uri) -> bool {
     uri.scheme_part().is_some()
 }
 
+/// Returns if the request target is in `asterisk-form`.
+///
+/// This is only valid for server-wide `OPTIONS` requests: `OPTIONS *`.
+pub fn is_asterisk_form(uri: &Uri) -> bool {
+    uri.scheme_part().is_none() && uri.authority_part().is_none() && uri.path() == "*"
+}
+
 /// Returns if the request target is in `origin-form`.
 ///
 /// This is `origin-form`: `example.com`
@@ -190,7 +221,69 @@ pub fn is_bad_request<B>(req: &http::Request<B>) -> bool {
     } else if is_origin_form(req.uri()) {
         debug!("{} request with illegal URI: {:?}", req.method(), req.uri());
         return true;
+    // Only OPTIONS may target the server as a whole.
+    } else if req.method() != &http::Method::OPTIONS && is_asterisk_form(req.uri()) {
+        debug!("{} request with illegal URI: {:?}", req.method(), req.uri());
+        return true;
     }
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_form_keeps_its_authority() {
+        let mut req = http::Request::get("http://example.com/docs?page=2")
+            .header(HOST, "other.example.com")
+            .body(())
+            .unwrap();
+        normalize_our_view_of_uri(&mut req);
+        assert_eq!(req.uri().authority_part().unwrap(), "example.com");
+        assert_eq!(req.uri().path_and_query().unwrap(), "/docs?page=2");
+        assert_eq!(req.headers()[HOST], "other.example.com");
+    }
+
+    #[test]
+    fn absolute_form_keeps_the_port_of_its_scheme() {
+        let mut req = http::Request::get("https://example.com/docs")
+            .body(())
+            .unwrap();
+        normalize_our_view_of_uri(&mut req);
+        assert_eq!(req.uri(), "http://example.com:443/docs");
+        assert!(req.headers().get(HOST).is_none());
+
+        let mut req = http::Request::get("https://example.com:8443/docs")
+            .body(())
+            .unwrap();
+        normalize_our_view_of_uri(&mut req);
+        assert_eq!(req.uri(), "http://example.com:8443/docs");
+    }
+
+    #[test]
+    fn origin_form_gets_authority_from_host() {
+        let mut req = http::Request::get("/docs")
+            .header(HOST, "example.com")
+            .body(())
+            .unwrap();
+        normalize_our_view_of_uri(&mut req);
+        assert_eq!(req.uri().authority_part().unwrap(), "example.com");
+        assert_eq!(req.uri().path(), "/docs");
+    }
+
+    #[test]
+    fn asterisk_form_is_only_valid_for_options() {
+        let options = http::Request::builder()
+            .method(http::Method::OPTIONS)
+            .uri("*")
+            .body(())
+            .unwrap();
+        assert!(is_asterisk_form(options.uri()));
+        assert!(!is_bad_request(&options));
+
+        let get = http::Request::get("*").body(()).unwrap();
+        assert!(is_bad_request(&get));
+    }
+}
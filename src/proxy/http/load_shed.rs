@@ -0,0 +1,193 @@
+//! Sheds load when an inner service is saturated.
+//!
+//! Rather than queueing requests until a saturated service (e.g. a full
+//! balancer buffer) has capacity, requests fail immediately with a
+//! `503 Service Unavailable` response carrying a `Retry-After` header, so
+//! that clients may back off.
+
+use futures::{Async, Future, Poll};
+use http::{header, Request, Response, StatusCode};
+
+use svc;
+
+/// The number of seconds after which clients are asked to retry shed requests.
+pub const RETRY_AFTER_SECS: &str = "1";
+
+/// A marker set in `http::Response::extensions` that *this* process shed the
+/// request.
+#[derive(Debug)]
+pub struct Shed(());
+
+pub fn layer() -> Layer {
+    Layer
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer;
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+}
+
+#[derive(Debug)]
+pub struct Service<S> {
+    inner: S,
+    /// Whether the inner service was ready when last polled.
+    ready: bool,
+}
+
+pub struct ResponseFuture<F> {
+    inner: Option<F>,
+}
+
+/// Builds a response for a request that was shed.
+pub fn response<B: Default>() -> Response<B> {
+    let mut res = Response::default();
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    res.headers_mut().insert(
+        header::RETRY_AFTER,
+        header::HeaderValue::from_static(RETRY_AFTER_SECS),
+    );
+    res.extensions_mut().insert(Shed(()));
+    res
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack { inner }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            ready: false,
+        })
+    }
+}
+
+// === impl Service ===
+
+/// A clone has not polled the inner service, so it is not ready.
+impl<S: Clone> Clone for Service<S> {
+    fn clone(&self) -> Self {
+        Service {
+            inner: self.inner.clone(),
+            ready: false,
+        }
+    }
+}
+
+impl<S, A, B> svc::Service<Request<A>> for Service<S>
+where
+    S: svc::Service<Request<A>, Response = Response<B>>,
+    B: Default,
+{
+    type Response = Response<B>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    /// Always ready, so that requests are shed rather than queued when the
+    /// inner service is not ready.
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.ready = self.inner.poll_ready()?.is_ready();
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<A>) -> Self::Future {
+        if !self.ready {
+            debug!("shedding load; service is not ready");
+            return ResponseFuture { inner: None };
+        }
+
+        self.ready = false;
+        ResponseFuture {
+            inner: Some(self.inner.call(req)),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: Default,
+{
+    type Item = Response<B>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner {
+            Some(ref mut f) => f.poll(),
+            None => Ok(Async::Ready(response())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+
+    struct Saturated;
+
+    impl svc::Service<Request<()>> for Saturated {
+        type Response = Response<()>;
+        type Error = ();
+        type Future = future::FutureResult<Response<()>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::NotReady)
+        }
+
+        fn call(&mut self, _: Request<()>) -> Self::Future {
+            panic!("saturated service must not be called");
+        }
+    }
+
+    #[test]
+    fn sheds_when_not_ready() {
+        use svc::Service as _Service;
+
+        let mut svc = Service {
+            inner: Saturated,
+            ready: false,
+        };
+        assert!(svc.poll_ready().unwrap().is_ready());
+
+        let rsp = svc.call(Request::new(())).wait().unwrap();
+        assert_eq!(rsp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rsp.headers()[header::RETRY_AFTER], RETRY_AFTER_SECS);
+        assert!(rsp.extensions().get::<Shed>().is_some());
+    }
+
+    #[test]
+    fn clones_are_not_ready() {
+        let svc = Service {
+            inner: (),
+            ready: true,
+        };
+        assert!(!svc.clone().ready);
+    }
+}
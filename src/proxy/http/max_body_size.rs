@@ -0,0 +1,523 @@
+use bytes::Buf;
+use futures::{Async, Future, Poll};
+use h2;
+use http::{header, Request, Response, StatusCode};
+use hyper::body::Payload;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+use std::usize;
+use tower_http_service;
+
+use super::retry::TryClone;
+use svc;
+
+/// Implemented by targets that may limit the size of request bodies.
+pub trait HasMaxBodySize {
+    fn max_body_size(&self) -> Option<usize>;
+}
+
+/// Fails requests whose bodies exceed a maximum size with a
+/// `413 Payload Too Large` response.
+///
+/// The maximum is `default`, unless a `route_layer` beneath this layer
+/// replaces it with the limit of the request's route. The request body is
+/// aborted as soon as the maximum is exceeded.
+pub fn layer(default: Option<usize>) -> Layer {
+    Layer { default }
+}
+
+/// Replaces the maximum body size enforced by a `layer` above this one with
+/// the target's limit, if it specifies one.
+///
+/// Requests with a `content-length` over the maximum are rejected before they
+/// are dispatched.
+pub fn route_layer() -> RouteLayer {
+    RouteLayer(())
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    default: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    default: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    default: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RouteLayer(());
+
+#[derive(Clone, Debug)]
+pub struct RouteStack<M> {
+    inner: M,
+}
+
+#[derive(Clone, Debug)]
+pub struct RouteService<S> {
+    inner: S,
+    max: Option<usize>,
+}
+
+pub struct ResponseFuture<F> {
+    inner: Option<F>,
+    limit: Option<Arc<Limit>>,
+}
+
+/// A request body that fails once more than the request's maximum number of
+/// bytes have been read.
+#[derive(Debug)]
+pub struct LimitBody<B> {
+    inner: B,
+    read: usize,
+    limit: Arc<Limit>,
+}
+
+/// The limit shared by a request's body and the layers it passes through.
+///
+/// It is stored in the request's extensions so that a route may replace the
+/// limit before the body is read.
+#[derive(Clone, Debug)]
+struct Handle(Arc<Limit>);
+
+#[derive(Debug)]
+struct Limit {
+    /// The maximum number of bytes, or `usize::MAX` when unlimited.
+    max: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            default: self.default,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            default: self.default,
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<Request<A>> for Service<S>
+where
+    S: svc::Service<Request<LimitBody<A>>, Response = Response<B>>,
+    A: Payload<Error = h2::Error>,
+    B: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<A>) -> Self::Future {
+        let limit = Arc::new(Limit::new(self.default));
+        req.extensions_mut().insert(Handle(limit.clone()));
+
+        let body_limit = limit.clone();
+        let req = req.map(move |inner| LimitBody {
+            inner,
+            read: 0,
+            limit: body_limit,
+        });
+
+        ResponseFuture {
+            inner: Some(self.inner.call(req)),
+            limit: Some(limit),
+        }
+    }
+}
+
+// === impl RouteLayer ===
+
+impl<T, M> svc::Layer<T, T, M> for RouteLayer
+where
+    T: HasMaxBodySize,
+    M: svc::Stack<T>,
+{
+    type Value = <RouteStack<M> as svc::Stack<T>>::Value;
+    type Error = <RouteStack<M> as svc::Stack<T>>::Error;
+    type Stack = RouteStack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        RouteStack { inner }
+    }
+}
+
+// === impl RouteStack ===
+
+impl<T, M> svc::Stack<T> for RouteStack<M>
+where
+    T: HasMaxBodySize,
+    M: svc::Stack<T>,
+{
+    type Value = RouteService<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        let max = target.max_body_size();
+        Ok(RouteService { inner, max })
+    }
+}
+
+// === impl RouteService ===
+
+impl<S, A, B> svc::Service<Request<A>> for RouteService<S>
+where
+    S: svc::Service<Request<A>, Response = Response<B>>,
+    B: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<A>) -> Self::Future {
+        let limit = match req.extensions().get::<Handle>() {
+            Some(&Handle(ref limit)) => limit.clone(),
+            None => {
+                // Retried requests are not re-limited, as their bodies share
+                // the limit of the original request.
+                return ResponseFuture {
+                    inner: Some(self.inner.call(req)),
+                    limit: None,
+                };
+            }
+        };
+
+        if let Some(max) = self.max {
+            limit.max.store(max, Ordering::Release);
+        }
+
+        if let Some(max) = limit.max() {
+            let len = req
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|len| len.to_str().ok())
+                .and_then(|len| len.parse::<usize>().ok());
+            if len.map(|len| len > max).unwrap_or(false) {
+                debug!("content-length exceeds max body size; max={}", max);
+                limit.exceeded.store(true, Ordering::Release);
+                return ResponseFuture {
+                    inner: None,
+                    limit: Some(limit),
+                };
+            }
+        }
+
+        ResponseFuture {
+            inner: Some(self.inner.call(req)),
+            limit: Some(limit),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = match self.inner.as_mut() {
+            Some(f) => f.poll(),
+            None => return Ok(Async::Ready(payload_too_large())),
+        };
+
+        let exceeded = self.limit.as_ref().map(|l| l.is_exceeded());
+        match res {
+            Err(_) if exceeded == Some(true) => Ok(Async::Ready(payload_too_large())),
+            res => res,
+        }
+    }
+}
+
+fn payload_too_large<B: Default>() -> Response<B> {
+    Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .header(header::CONTENT_LENGTH, "0")
+        .body(B::default())
+        .expect("payload too large response must be valid")
+}
+
+// === impl Limit ===
+
+impl Limit {
+    fn new(max: Option<usize>) -> Self {
+        Limit {
+            max: AtomicUsize::new(max.unwrap_or(usize::MAX)),
+            exceeded: AtomicBool::new(false),
+        }
+    }
+
+    fn max(&self) -> Option<usize> {
+        match self.max.load(Ordering::Acquire) {
+            usize::MAX => None,
+            max => Some(max),
+        }
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Acquire)
+    }
+}
+
+// === impl LimitBody ===
+
+impl<B> Payload for LimitBody<B>
+where
+    B: Payload<Error = h2::Error>,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = try_ready!(self.inner.poll_data());
+
+        if let (Some(max), Some(ref d)) = (self.limit.max(), data.as_ref()) {
+            self.read += d.remaining();
+            if self.read > max {
+                debug!("request body exceeds max body size; max={}", max);
+                self.limit.exceeded.store(true, Ordering::Release);
+                return Err(h2::Reason::CANCEL.into());
+            }
+        }
+
+        Ok(Async::Ready(data))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+impl<B> tower_http_service::Body for LimitBody<B>
+where
+    B: Payload<Error = h2::Error>,
+{
+    type Item = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_buf(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B: TryClone> TryClone for LimitBody<B> {
+    fn try_clone(&self) -> Option<Self> {
+        self.inner.try_clone().map(|inner| LimitBody {
+            inner,
+            read: self.read,
+            limit: self.limit.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::future;
+    use std::collections::VecDeque;
+    use std::io::Cursor;
+
+    /// A request body made of a fixed sequence of chunks.
+    #[derive(Clone, Debug, Default)]
+    struct Chunks(VecDeque<Bytes>);
+
+    impl TryClone for Chunks {
+        fn try_clone(&self) -> Option<Self> {
+            Some(self.clone())
+        }
+    }
+
+    impl Payload for Chunks {
+        type Data = Cursor<Bytes>;
+        type Error = h2::Error;
+
+        fn is_end_stream(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+            Ok(Async::Ready(self.0.pop_front().map(Cursor::new)))
+        }
+    }
+
+    /// Reads the request body to completion before responding.
+    struct ReadBody;
+
+    impl<B: Payload> svc::Service<Request<B>> for ReadBody {
+        type Response = Response<()>;
+        type Error = B::Error;
+        type Future = Box<Future<Item = Response<()>, Error = B::Error>>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, req: Request<B>) -> Self::Future {
+            let mut body = req.into_body();
+            let read = future::poll_fn(move || loop {
+                if try_ready!(body.poll_data()).is_none() {
+                    return Ok(Async::Ready(()));
+                }
+            });
+            Box::new(read.map(|()| Response::new(())))
+        }
+    }
+
+    fn request(chunks: &[&'static str]) -> Request<Chunks> {
+        let chunks = chunks.iter().map(|c| Bytes::from_static(c.as_bytes()));
+        Request::new(Chunks(chunks.collect()))
+    }
+
+    fn status<S>(mut svc: S, req: Request<Chunks>) -> StatusCode
+    where
+        S: svc::Service<Request<Chunks>, Response = Response<()>>,
+        S::Error: ::std::fmt::Debug,
+    {
+        svc.call(req).wait().expect("response").status()
+    }
+
+    /// Builds a server-level service beneath which each request is handled by
+    /// a route with the given limit.
+    fn service(default: Option<usize>, route: Option<usize>) -> Service<RouteService<ReadBody>> {
+        let route = RouteService {
+            inner: ReadBody,
+            max: route,
+        };
+        Service {
+            inner: route,
+            default,
+        }
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let req = request(&["hello", "world"]);
+        assert_eq!(status(service(None, None), req), StatusCode::OK);
+    }
+
+    #[test]
+    fn aborts_bodies_that_exceed_the_default() {
+        let req = request(&["hello", "world"]);
+        assert_eq!(
+            status(service(Some(5), None), req),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let req = request(&["hello", "world"]);
+        assert_eq!(status(service(Some(10), None), req), StatusCode::OK);
+    }
+
+    #[test]
+    fn rejects_content_length_over_the_max() {
+        let mut req = request(&[]);
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, "11".parse().unwrap());
+        assert_eq!(
+            status(service(Some(10), None), req),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[test]
+    fn route_limit_may_raise_the_default() {
+        let req = request(&["hello", "world"]);
+        assert_eq!(status(service(Some(5), Some(10)), req), StatusCode::OK);
+
+        let mut req = request(&["hello", "world"]);
+        req.headers_mut()
+            .insert(header::CONTENT_LENGTH, "10".parse().unwrap());
+        assert_eq!(status(service(Some(5), Some(10)), req), StatusCode::OK);
+    }
+
+    #[test]
+    fn route_limit_may_lower_the_default() {
+        let req = request(&["hello", "world"]);
+        assert_eq!(
+            status(service(Some(10), Some(5)), req),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let req = request(&["hello", "world"]);
+        assert_eq!(
+            status(service(None, Some(5)), req),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[test]
+    fn body_clones_share_the_limit() {
+        let limit = Arc::new(Limit::new(Some(10)));
+        let body = LimitBody {
+            inner: Chunks::default(),
+            read: 4,
+            limit: limit.clone(),
+        };
+        let clone = body.try_clone().expect("clone");
+        assert_eq!(clone.read, 4);
+
+        limit.max.store(5, Ordering::Release);
+        assert_eq!(clone.limit.max(), Some(5));
+    }
+}
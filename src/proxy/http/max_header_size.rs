@@ -0,0 +1,121 @@
+use futures::{future, Poll};
+use http::{header, Request, Response, StatusCode};
+
+use svc;
+
+/// Rejects requests whose header block exceeds `max` bytes with a
+/// `431 Request Header Fields Too Large` response.
+///
+/// The size of a header block is the sum of the lengths of its header names
+/// and values. If no maximum is configured, requests are passed through
+/// unmodified.
+pub fn layer(max: Option<usize>) -> Layer {
+    Layer { max }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    max: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    max: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    max: usize,
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            max: self.max,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = svc::Either<Service<M::Value>, M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        match self.max {
+            Some(max) => Ok(svc::Either::A(Service { inner, max })),
+            None => Ok(svc::Either::B(inner)),
+        }
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<Request<A>> for Service<S>
+where
+    S: svc::Service<Request<A>, Response = Response<B>>,
+    B: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = future::Either<future::FutureResult<Self::Response, Self::Error>, S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<A>) -> Self::Future {
+        let size = header_block_size(req.headers());
+        if size > self.max {
+            debug!("header block too large; size={} max={}", size, self.max);
+            let rsp = Response::builder()
+                .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+                .header(header::CONTENT_LENGTH, "0")
+                .body(B::default())
+                .expect("header size response must be valid");
+            return future::Either::A(future::ok(rsp));
+        }
+
+        future::Either::B(self.inner.call(req))
+    }
+}
+
+fn header_block_size(headers: &http::HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_block_size_sums_names_and_values() {
+        let mut headers = http::HeaderMap::new();
+        assert_eq!(header_block_size(&headers), 0);
+
+        headers.insert("cookie", "abc".parse().unwrap());
+        headers.append("cookie", "defgh".parse().unwrap());
+        headers.insert("x-id", "1".parse().unwrap());
+        assert_eq!(header_block_size(&headers), (6 + 3) + (6 + 5) + (4 + 1));
+    }
+}
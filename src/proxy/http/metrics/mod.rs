@@ -14,12 +14,17 @@ mod service;
 pub use self::report::Report;
 pub use self::service::layer;
 
-pub fn new<T, C>(retain_idle: Duration) -> (Arc<Mutex<Registry<T, C>>>, Report<T, C>)
+/// Once metrics are recorded for `max_scopes` targets, further targets are
+/// recorded in a single overflow scope.
+pub fn new<T, C>(
+    retain_idle: Duration,
+    max_scopes: usize,
+) -> (Arc<Mutex<Registry<T, C>>>, Report<T, C>)
 where
     T: FmtLabels + Clone + Hash + Eq,
     C: FmtLabels + Hash + Eq,
 {
-    let registry = Arc::new(Mutex::new(Registry::default()));
+    let registry = Arc::new(Mutex::new(Registry::new(max_scopes)));
     (registry.clone(), Report::new(retain_idle, registry))
 }
 
@@ -30,6 +35,11 @@ where
     C: Hash + Eq,
 {
     by_target: IndexMap<T, Arc<Mutex<RequestMetrics<C>>>>,
+    max_scopes: usize,
+    /// Records the metrics of targets that were not given their own scope
+    /// because `max_scopes` had been reached.
+    overflow: Option<Arc<Mutex<RequestMetrics<C>>>>,
+    overflows: Counter,
 }
 
 pub trait Scoped<T> {
@@ -39,6 +49,7 @@ pub trait Scoped<T> {
 
 pub trait Stats {
     fn incr_retry_skipped_budget(&self);
+    fn incr_retry_attempt(&self, attempt: usize);
 }
 
 #[derive(Debug)]
@@ -49,6 +60,7 @@ where
     last_update: Instant,
     total: Counter,
     by_retry_skipped: IndexMap<RetrySkipped, Counter>,
+    by_retry_attempt: IndexMap<RetryAttempt, Counter>,
     by_status: IndexMap<http::StatusCode, StatusMetrics<C>>,
 }
 
@@ -71,23 +83,41 @@ enum RetrySkipped {
     Budget,
 }
 
-impl<T, C> Default for Registry<T, C>
+/// The number of a retry, starting at 1.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct RetryAttempt(usize);
+
+impl<T, C> Registry<T, C>
 where
     T: Hash + Eq,
     C: Hash + Eq,
 {
-    fn default() -> Self {
+    fn new(max_scopes: usize) -> Self {
         Self {
             by_target: IndexMap::default(),
+            max_scopes,
+            overflow: None,
+            overflows: Counter::default(),
         }
     }
-}
 
-impl<T, C> Registry<T, C>
-where
-    T: Hash + Eq,
-    C: Hash + Eq,
-{
+    /// Returns the metrics for `target`, or those of the overflow scope if
+    /// `target` has no scope and no more may be created.
+    fn get_or_insert(&mut self, target: T) -> Arc<Mutex<RequestMetrics<C>>> {
+        if self.by_target.len() >= self.max_scopes && !self.by_target.contains_key(&target) {
+            self.overflows.incr();
+            return self
+                .overflow
+                .get_or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::default())))
+                .clone();
+        }
+
+        self.by_target
+            .entry(target)
+            .or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::default())))
+            .clone()
+    }
+
     /// Retains metrics for all targets that (1) no longer have an active
     /// reference to the `RequestMetrics` structure and (2) have not been updated since `epoch`.
     fn retain_since(&mut self, epoch: Instant) {
@@ -107,10 +137,7 @@ where
     fn scoped(&self, target: T) -> Self::Scope {
         self.lock()
             .expect("metrics Registry lock")
-            .by_target
-            .entry(target)
-            .or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::default())))
-            .clone()
+            .get_or_insert(target)
     }
 }
 
@@ -135,6 +162,7 @@ where
             last_update: clock::now(),
             total: Counter::default(),
             by_retry_skipped: IndexMap::default(),
+            by_retry_attempt: IndexMap::default(),
             by_status: IndexMap::default(),
         }
     }
@@ -150,6 +178,17 @@ where
             metrics.incr_retry_skipped(RetrySkipped::Budget);
         }
     }
+
+    fn incr_retry_attempt(&self, attempt: usize) {
+        if let Ok(mut metrics) = self.lock() {
+            metrics.last_update = clock::now();
+            metrics
+                .by_retry_attempt
+                .entry(RetryAttempt(attempt))
+                .or_insert_with(Counter::default)
+                .incr();
+        }
+    }
 }
 
 impl<C> Default for StatusMetrics<C>
@@ -199,7 +238,7 @@ mod tests {
         }
 
         let retain_idle_for = Duration::from_secs(1);
-        let (r, report) = super::new::<Target, Class>(retain_idle_for);
+        let (r, report) = super::new::<Target, Class>(retain_idle_for, 100);
         let mut registry = r.lock().unwrap();
 
         let before_update = clock::now();
@@ -235,4 +274,37 @@ mod tests {
 
         drop((registry, report));
     }
+
+    #[test]
+    fn overflow() {
+        use std::fmt;
+        use std::time::Duration;
+
+        use metrics::FmtLabels;
+
+        #[derive(Clone, Debug, Hash, Eq, PartialEq)]
+        struct Target(usize);
+        impl FmtLabels for Target {
+            fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "n=\"{}\"", self.0)
+            }
+        }
+
+        let (r, _report) = super::new::<Target, Target>(Duration::from_secs(1), 2);
+        let mut registry = r.lock().unwrap();
+
+        let a = registry.get_or_insert(Target(0));
+        registry.get_or_insert(Target(1));
+        assert!(registry.overflow.is_none());
+
+        // Existing targets keep their scopes once the limit is reached.
+        let a2 = registry.get_or_insert(Target(0));
+        assert!(::std::sync::Arc::ptr_eq(&a, &a2));
+
+        let o1 = registry.get_or_insert(Target(2));
+        let o2 = registry.get_or_insert(Target(3));
+        assert!(::std::sync::Arc::ptr_eq(&o1, &o2));
+        assert_eq!(registry.by_target.len(), 2);
+        assert_eq!(registry.overflows.value(), 2);
+    }
 }
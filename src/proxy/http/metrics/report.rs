@@ -7,7 +7,7 @@ use tokio_timer::clock;
 
 use metrics::{latency, Counter, FmtLabels, FmtMetric, FmtMetrics, Histogram, Metric};
 
-use super::{ClassMetrics, Registry, RequestMetrics, RetrySkipped, StatusMetrics};
+use super::{ClassMetrics, Registry, RequestMetrics, RetryAttempt, RetrySkipped, StatusMetrics};
 
 /// Reports HTTP metrics for prometheus.
 #[derive(Clone, Debug)]
@@ -23,12 +23,20 @@ where
 
 struct Status(http::StatusCode);
 
+/// Labels a target's scope, or the overflow scope.
+enum ScopeLabels<'a, T: 'a> {
+    Target(&'a T),
+    Overflow,
+}
+
 #[derive(Clone, Debug)]
 struct Scope {
     request_total_key: String,
     response_total_key: String,
     response_latency_ms_key: String,
     retry_skipped_total_key: String,
+    retry_attempt_total_key: String,
+    scope_overflow_total_key: String,
 }
 
 // ===== impl Report =====
@@ -77,7 +85,7 @@ where
 
         let registry = registry;
         trace!("fmt_metrics: by_target={}", registry.by_target.len());
-        if registry.by_target.is_empty() {
+        if registry.by_target.is_empty() && registry.overflow.is_none() {
             return Ok(());
         }
 
@@ -93,6 +101,16 @@ where
         self.scope.retry_skipped_total().fmt_help(f)?;
         registry.fmt_by_retry(f, self.scope.retry_skipped_total())?;
 
+        self.scope.retry_attempt_total().fmt_help(f)?;
+        registry.fmt_by_retry_attempt(f, self.scope.retry_attempt_total())?;
+
+        if registry.overflow.is_some() {
+            self.scope.scope_overflow_total().fmt_help(f)?;
+            registry
+                .overflows
+                .fmt_metric(f, self.scope.scope_overflow_total().name)?;
+        }
+
         Ok(())
     }
 }
@@ -102,6 +120,17 @@ where
     T: FmtLabels + Hash + Eq,
     C: FmtLabels + Hash + Eq,
 {
+    /// Iterates over the metrics of each target, followed by those of the
+    /// overflow scope.
+    fn scopes<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (ScopeLabels<'a, T>, &'a Arc<Mutex<RequestMetrics<C>>>)> + 'a {
+        self.by_target
+            .iter()
+            .map(|(tgt, tm)| (ScopeLabels::Target(tgt), tm))
+            .chain(self.overflow.iter().map(|tm| (ScopeLabels::Overflow, tm)))
+    }
+
     fn fmt_by_target<M, F>(
         &self,
         f: &mut fmt::Formatter,
@@ -112,9 +141,9 @@ where
         M: FmtMetric,
         F: Fn(&RequestMetrics<C>) -> &M,
     {
-        for (tgt, tm) in &self.by_target {
+        for (tgt, tm) in self.scopes() {
             if let Ok(m) = tm.lock() {
-                get_metric(&*m).fmt_metric_labeled(f, metric.name, tgt)?;
+                get_metric(&*m).fmt_metric_labeled(f, metric.name, &tgt)?;
             }
         }
 
@@ -125,10 +154,26 @@ where
     where
         M: FmtMetric,
     {
-        for (tgt, tm) in &self.by_target {
+        for (tgt, tm) in self.scopes() {
             if let Ok(tm) = tm.lock() {
                 for (retry, m) in &tm.by_retry_skipped {
-                    let labels = (tgt, retry);
+                    let labels = (&tgt, retry);
+                    m.fmt_metric_labeled(f, metric.name, labels)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fmt_by_retry_attempt<M>(&self, f: &mut fmt::Formatter, metric: Metric<M>) -> fmt::Result
+    where
+        M: FmtMetric,
+    {
+        for (tgt, tm) in self.scopes() {
+            if let Ok(tm) = tm.lock() {
+                for (attempt, m) in &tm.by_retry_attempt {
+                    let labels = (&tgt, attempt);
                     m.fmt_metric_labeled(f, metric.name, labels)?;
                 }
             }
@@ -147,10 +192,10 @@ where
         M: FmtMetric,
         F: Fn(&StatusMetrics<C>) -> &M,
     {
-        for (tgt, tm) in &self.by_target {
+        for (tgt, tm) in self.scopes() {
             if let Ok(tm) = tm.lock() {
                 for (status, m) in &tm.by_status {
-                    let labels = (tgt, Status(*status));
+                    let labels = (&tgt, Status(*status));
                     get_metric(&*m).fmt_metric_labeled(f, metric.name, labels)?;
                 }
             }
@@ -169,11 +214,11 @@ where
         M: FmtMetric,
         F: Fn(&ClassMetrics) -> &M,
     {
-        for (tgt, tm) in &self.by_target {
+        for (tgt, tm) in self.scopes() {
             if let Ok(tm) = tm.lock() {
                 for (status, sm) in &tm.by_status {
                     for (cls, m) in &sm.by_class {
-                        let labels = (tgt, (Status(*status), cls));
+                        let labels = (&tgt, (Status(*status), cls));
                         get_metric(&*m).fmt_metric_labeled(f, metric.name, labels)?;
                     }
                 }
@@ -193,6 +238,8 @@ impl Default for Scope {
             response_total_key: "response_total".to_owned(),
             response_latency_ms_key: "response_latency_ms".to_owned(),
             retry_skipped_total_key: "retry_skipped_total".to_owned(),
+            retry_attempt_total_key: "retry_attempt_total".to_owned(),
+            scope_overflow_total_key: "scope_overflow_total".to_owned(),
         }
     }
 }
@@ -208,6 +255,8 @@ impl Scope {
             response_total_key: format!("{}_response_total", prefix),
             response_latency_ms_key: format!("{}_response_latency_ms", prefix),
             retry_skipped_total_key: format!("{}_retry_skipped_total", prefix),
+            retry_attempt_total_key: format!("{}_retry_attempt_total", prefix),
+            scope_overflow_total_key: format!("{}_scope_overflow_total", prefix),
         }
     }
 
@@ -233,6 +282,20 @@ impl Scope {
         )
     }
 
+    fn retry_attempt_total(&self) -> Metric<Counter> {
+        Metric::new(
+            &self.retry_attempt_total_key,
+            &Self::RETRY_ATTEMPT_TOTAL_HELP,
+        )
+    }
+
+    fn scope_overflow_total(&self) -> Metric<Counter> {
+        Metric::new(
+            &self.scope_overflow_total_key,
+            &Self::SCOPE_OVERFLOW_TOTAL_HELP,
+        )
+    }
+
     const REQUEST_TOTAL_HELP: &'static str = "Total count of HTTP requests.";
 
     const RESPONSE_TOTAL_HELP: &'static str = "Total count of HTTP responses.";
@@ -243,6 +306,22 @@ impl Scope {
 
     const RETRY_SKIPPED_TOTAL_HELP: &'static str =
         "Total count of retryable HTTP responses that were not retried.";
+
+    const RETRY_ATTEMPT_TOTAL_HELP: &'static str =
+        "Total count of HTTP requests that were retried, by attempt.";
+
+    const SCOPE_OVERFLOW_TOTAL_HELP: &'static str =
+        "Total count of targets whose metrics were recorded with overflow=\"true\" \
+         because the maximum number of label sets had been reached.";
+}
+
+impl<'a, T: FmtLabels> FmtLabels for ScopeLabels<'a, T> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScopeLabels::Target(tgt) => tgt.fmt_labels(f),
+            ScopeLabels::Overflow => f.write_str("overflow=\"true\""),
+        }
+    }
 }
 
 impl FmtLabels for Status {
@@ -251,6 +330,12 @@ impl FmtLabels for Status {
     }
 }
 
+impl FmtLabels for RetryAttempt {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "attempt=\"{}\"", self.0)
+    }
+}
+
 impl FmtLabels for RetrySkipped {
     fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
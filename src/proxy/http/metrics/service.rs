@@ -170,12 +170,7 @@ where
         let inner = self.inner.make(target)?;
 
         let metrics = match self.registry.lock() {
-            Ok(mut r) => Some(
-                r.by_target
-                    .entry(target.clone().into())
-                    .or_insert_with(|| Arc::new(Mutex::new(RequestMetrics::default())))
-                    .clone(),
-            ),
+            Ok(mut r) => Some(r.get_or_insert(target.clone().into())),
             Err(_) => None,
         };
 
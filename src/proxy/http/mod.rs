@@ -1,20 +1,41 @@
 pub mod add_header;
 pub mod balance;
 pub mod client;
+pub mod compress;
+pub mod connect_retry;
+pub mod deadline;
+pub mod egress;
+pub mod error_kind;
+pub mod failfast;
+pub mod forwarded;
 pub(super) mod glue;
+pub mod grpc_web;
 pub mod h1;
 pub mod h2;
 pub mod header_from_target;
 pub mod insert_target;
+pub mod load_shed;
+pub mod max_body_size;
+pub mod max_header_size;
 pub mod metrics;
 pub mod normalize_uri;
 pub mod orig_proto;
+pub mod panic_threshold;
+pub mod path_template;
 pub mod profiles;
+pub mod request_id;
 pub mod retry;
+pub mod ring_hash;
 pub mod router;
+pub mod scrub_headers;
 pub mod settings;
+pub mod slow_start;
+pub mod split;
+pub mod stream_idle_timeout;
 pub mod strip_header;
 pub mod timeout;
+pub mod trace_context;
+pub mod untrusted_headers;
 pub mod upgrade;
 
 pub use self::client::Client;
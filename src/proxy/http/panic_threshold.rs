@@ -0,0 +1,258 @@
+//! Balances over all endpoints when too few of them are ready.
+//!
+//! When most of a balancer's endpoints are unavailable, sending all of its
+//! traffic to the few that remain tends to overload them as well, so that a
+//! partial outage cascades. Once the fraction of ready endpoints falls below
+//! the panic threshold, endpoints that are not ready are offered to the
+//! balancer anyway; requests dispatched to them wait for them to become ready.
+
+extern crate tower_discover;
+
+use futures::{Async, Future, Poll};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use self::tower_discover::{Change, Discover};
+use svc;
+
+/// Wraps the endpoints discovered by `D` so that they may be used while they
+/// are not ready.
+pub struct PanicThreshold<D> {
+    inner: D,
+    endpoints: Arc<Endpoints>,
+}
+
+pub struct Service<S> {
+    inner: S,
+    /// Whether the endpoint was ready when it was last polled.
+    ready: bool,
+    endpoints: Arc<Endpoints>,
+}
+
+pub enum ResponseFuture<S, Req>
+where
+    S: svc::Service<Req>,
+{
+    Called(S::Future),
+    Pending(S, Option<Req>),
+}
+
+/// Counts the endpoints of a balancer.
+#[derive(Debug)]
+struct Endpoints {
+    total: AtomicUsize,
+    ready: AtomicUsize,
+    threshold: f64,
+}
+
+// === impl PanicThreshold ===
+
+impl<D: Discover> PanicThreshold<D> {
+    /// When `threshold` is `None`, unready endpoints are never used.
+    pub fn new(inner: D, threshold: Option<f64>) -> Self {
+        Self {
+            inner,
+            endpoints: Arc::new(Endpoints {
+                total: AtomicUsize::new(0),
+                ready: AtomicUsize::new(0),
+                threshold: threshold.unwrap_or(0.0),
+            }),
+        }
+    }
+}
+
+impl<D: Discover> Discover for PanicThreshold<D> {
+    type Key = D::Key;
+    type Service = Service<D::Service>;
+    type Error = D::Error;
+
+    fn poll(&mut self) -> Poll<Change<D::Key, Self::Service>, D::Error> {
+        match try_ready!(self.inner.poll()) {
+            Change::Remove(key) => Ok(Async::Ready(Change::Remove(key))),
+            Change::Insert(key, inner) => {
+                // Endpoints are considered ready until they are polled.
+                self.endpoints.total.fetch_add(1, Ordering::AcqRel);
+                self.endpoints.ready.fetch_add(1, Ordering::AcqRel);
+                let svc = Service {
+                    inner,
+                    ready: true,
+                    endpoints: self.endpoints.clone(),
+                };
+                Ok(Async::Ready(Change::Insert(key, svc)))
+            }
+        }
+    }
+}
+
+// === impl Service ===
+
+impl<S> Service<S> {
+    fn set_ready(&mut self, ready: bool) {
+        if ready == self.ready {
+            return;
+        }
+
+        self.ready = ready;
+        if ready {
+            self.endpoints.ready.fetch_add(1, Ordering::AcqRel);
+        } else {
+            self.endpoints.ready.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Service<S>
+where
+    S: svc::Service<Req> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, Req>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        let ready = self.inner.poll_ready()?.is_ready();
+        self.set_ready(ready);
+        if ready {
+            return Ok(Async::Ready(()));
+        }
+
+        if self.endpoints.is_panicking() {
+            trace!("too few endpoints are ready; using an unready endpoint");
+            return Ok(Async::Ready(()));
+        }
+
+        Ok(Async::NotReady)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        if self.ready {
+            ResponseFuture::Called(self.inner.call(req))
+        } else {
+            ResponseFuture::Pending(self.inner.clone(), Some(req))
+        }
+    }
+}
+
+impl<S> Drop for Service<S> {
+    fn drop(&mut self) {
+        self.endpoints.total.fetch_sub(1, Ordering::AcqRel);
+        if self.ready {
+            self.endpoints.ready.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<S, Req> Future for ResponseFuture<S, Req>
+where
+    S: svc::Service<Req>,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let future = match *self {
+                ResponseFuture::Called(ref mut f) => return f.poll(),
+                ResponseFuture::Pending(ref mut svc, ref mut req) => {
+                    try_ready!(svc.poll_ready());
+                    let req = req.take().expect("request must only be sent once");
+                    svc.call(req)
+                }
+            };
+            *self = ResponseFuture::Called(future);
+        }
+    }
+}
+
+// === impl Endpoints ===
+
+impl Endpoints {
+    fn is_panicking(&self) -> bool {
+        let total = self.total.load(Ordering::Acquire);
+        let ready = self.ready.load(Ordering::Acquire);
+        total > 0 && (ready as f64) < self.threshold * (total as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::collections::VecDeque;
+
+    #[derive(Clone)]
+    struct Svc(bool);
+
+    impl svc::Service<()> for Svc {
+        type Response = ();
+        type Error = ();
+        type Future = future::FutureResult<(), ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            if self.0 {
+                Ok(Async::Ready(()))
+            } else {
+                Ok(Async::NotReady)
+            }
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            future::ok(())
+        }
+    }
+
+    struct Changes(VecDeque<Change<usize, Svc>>);
+
+    impl Discover for Changes {
+        type Key = usize;
+        type Service = Svc;
+        type Error = ();
+
+        fn poll(&mut self) -> Poll<Change<usize, Svc>, ()> {
+            match self.0.pop_front() {
+                Some(change) => Ok(Async::Ready(change)),
+                None => Ok(Async::NotReady),
+            }
+        }
+    }
+
+    /// Discovers one ready and two unready endpoints, and polls each of them.
+    fn poll_endpoints(threshold: f64) -> Vec<bool> {
+        let changes = vec![
+            Change::Insert(0, Svc(true)),
+            Change::Insert(1, Svc(false)),
+            Change::Insert(2, Svc(false)),
+        ];
+        let mut discover =
+            PanicThreshold::new(Changes(changes.into_iter().collect()), Some(threshold));
+
+        let mut endpoints = Vec::new();
+        while let Ok(Async::Ready(Change::Insert(_, endpoint))) = discover.poll() {
+            endpoints.push(endpoint);
+        }
+        // Poll all endpoints once so that the unready endpoints are counted.
+        for endpoint in endpoints.iter_mut() {
+            let _ = <_ as svc::Service<()>>::poll_ready(endpoint);
+        }
+        endpoints
+            .iter_mut()
+            .map(|endpoint| {
+                <_ as svc::Service<()>>::poll_ready(endpoint)
+                    .unwrap()
+                    .is_ready()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uses_unready_endpoints_below_threshold() {
+        assert_eq!(poll_endpoints(0.5), vec![true, true, true]);
+    }
+
+    #[test]
+    fn ignores_unready_endpoints_above_threshold() {
+        assert_eq!(poll_endpoints(0.3), vec![true, false, false]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Normalizes request paths into templates suitable for use as metric labels.
+//!
+//! Path segments that look like identifiers (i.e. numbers and UUIDs, or
+//! segments matching a configured rule) are replaced with `{id}`, so that, for
+//! instance, `/users/123/orders/456` is reported as `/users/{id}/orders/{id}`.
+//!
+//! Because a path's template is still chosen by the client, the number of
+//! distinct templates is capped. Once the cap is reached, paths with unknown
+//! templates are not templated at all.
+
+use indexmap::IndexSet;
+use regex::Regex;
+use std::sync::{Arc, Mutex};
+
+/// Replaces segments that are determined to be identifiers.
+pub const ID_SEGMENT: &str = "{id}";
+
+const NUMERIC_RULE: &str = "[0-9]+";
+const UUID_RULE: &str =
+    "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}";
+
+#[derive(Clone, Debug)]
+pub struct PathTemplates {
+    rules: Arc<Vec<Regex>>,
+    max_templates: usize,
+    known: Arc<Mutex<IndexSet<String>>>,
+}
+
+// === impl PathTemplates ===
+
+impl PathTemplates {
+    /// Builds a normalizer that, in addition to numeric and UUID segments,
+    /// replaces any segment matched by one of `rules`.
+    ///
+    /// Rules should be built with `rule`, so that they only match whole
+    /// segments.
+    pub fn new(rules: Vec<Regex>, max_templates: usize) -> Self {
+        let mut all = vec![
+            rule(NUMERIC_RULE).expect("numeric rule must be valid"),
+            rule(UUID_RULE).expect("uuid rule must be valid"),
+        ];
+        all.extend(rules);
+        Self {
+            rules: Arc::new(all),
+            max_templates,
+            known: Arc::new(Mutex::new(IndexSet::new())),
+        }
+    }
+
+    pub fn max_templates(&self) -> usize {
+        self.max_templates
+    }
+
+    /// Returns the template for `path`, unless doing so would exceed the
+    /// maximum number of distinct templates.
+    pub fn template(&self, path: &str) -> Option<String> {
+        let template = self.normalize(path);
+
+        let mut known = self.known.lock().ok()?;
+        if known.contains(&template) {
+            return Some(template);
+        }
+        if known.len() >= self.max_templates {
+            trace!("path template limit reached; template={}", template);
+            return None;
+        }
+
+        known.insert(template.clone());
+        Some(template)
+    }
+
+    fn normalize(&self, path: &str) -> String {
+        path.split('/')
+            .map(|segment| {
+                if !segment.is_empty() && self.is_id(segment) {
+                    ID_SEGMENT
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn is_id(&self, segment: &str) -> bool {
+        self.rules.iter().any(|rule| rule.is_match(segment))
+    }
+}
+
+/// Compiles a rule that matches path segments in their entirety.
+pub fn rule(pattern: &str) -> Result<Regex, ::regex::Error> {
+    Regex::new(&format!("^(?:{})$", pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_numeric_and_uuid_segments() {
+        let t = PathTemplates::new(Vec::new(), 10);
+        assert_eq!(
+            t.template("/users/123/orders/8f2c0a3e-1f4b-4c9e-9a7d-0b6e2f1c3d4a"),
+            Some("/users/{id}/orders/{id}".to_owned())
+        );
+        assert_eq!(t.template("/users/abc1"), Some("/users/abc1".to_owned()));
+        assert_eq!(t.template("/"), Some("/".to_owned()));
+    }
+
+    #[test]
+    fn templates_custom_rules_match_whole_segments() {
+        let t = PathTemplates::new(vec![rule("[a-z]{2}-[0-9]+").unwrap()], 10);
+        assert_eq!(t.template("/sku/ab-42"), Some("/sku/{id}".to_owned()));
+        assert_eq!(t.template("/sku/xab-42"), Some("/sku/xab-42".to_owned()));
+    }
+
+    #[test]
+    fn caps_distinct_templates() {
+        let t = PathTemplates::new(Vec::new(), 2);
+        assert!(t.template("/a/1").is_some());
+        assert!(t.template("/b/2").is_some());
+        assert!(t.template("/c/3").is_none());
+        // Known templates are still returned.
+        assert_eq!(t.template("/a/7"), Some("/a/{id}".to_owned()));
+    }
+}
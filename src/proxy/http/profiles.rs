@@ -4,30 +4,62 @@ use futures::Stream;
 use http;
 use indexmap::IndexMap;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
 use std::ops::Deref;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tower_retry::budget::Budget;
 
 use never::Never;
 
+use dns;
 use NameAddr;
 
 pub type Routes = Vec<(RequestMatch, Route)>;
 
-/// Watches a destination's Routes.
+/// A destination's routes and, if its traffic is split, the backends over
+/// which its requests are distributed.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub routes: Routes,
+    pub dst_overrides: Vec<WeightedAddr>,
+}
+
+/// A backend that receives a share of a destination's requests, in
+/// proportion to its weight.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WeightedAddr {
+    pub addr: NameAddr,
+    pub weight: u32,
+}
+
+/// Watches a destination's Profile.
 ///
-/// The stream updates with all routes for the given destination. The stream
-/// never ends and cannot fail.
+/// The stream updates with the whole profile for the given destination. The
+/// stream never ends and cannot fail.
 pub trait GetRoutes {
-    type Stream: Stream<Item = Routes, Error = Never>;
+    type Stream: Stream<Item = Profile, Error = Never>;
+
+    fn get_routes(&self, dst: &ProfileName) -> Option<Self::Stream>;
+}
 
-    fn get_routes(&self, dst: &NameAddr) -> Option<Self::Stream>;
+/// Identifies the profile that applies to a destination.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ProfileName {
+    /// The destination's own profile.
+    Name(NameAddr),
+    /// A wildcard profile, shared by all names within a domain on a port.
+    Wildcard(Wildcard, u16),
 }
 
+/// Matches all names within a domain, e.g. `*.payments.svc.cluster.local`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Wildcard(dns::Name);
+
 /// Implemented by target types that may be combined with a Route.
 pub trait WithRoute {
     type Output;
@@ -41,12 +73,22 @@ pub trait CanGetDestination {
     fn get_destination(&self) -> Option<&NameAddr>;
 }
 
+/// Implemented by target types whose requests may be sent to the backends of a
+/// traffic split.
+pub trait WithDstOverride: Sized {
+    /// Returns the target for a backend, or `None` if the target's requests
+    /// may not be split.
+    fn with_dst_override(&self, addr: NameAddr) -> Option<Self>;
+}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Route {
     labels: Labels,
     response_classes: ResponseClasses,
     retries: Option<Retries>,
     timeout: Option<Duration>,
+    max_request_body_size: Option<usize>,
+    redirect_class: Option<RedirectClass>,
 }
 
 #[derive(Clone, Debug)]
@@ -58,6 +100,27 @@ pub enum RequestMatch {
     Method(http::Method),
 }
 
+/// Routes compiled so that each request is only evaluated against the routes
+/// that could possibly match it.
+///
+/// Routes are switched on the method they require, if any, and then on the
+/// literal prefix that their path regex requires, if any. The remaining
+/// candidates are evaluated in their configured order, so the first matching
+/// route is chosen exactly as if all routes were evaluated linearly.
+#[derive(Debug, Default)]
+struct RouteTable {
+    routes: Routes,
+    by_method: HashMap<http::Method, PrefixTrie>,
+    any_method: PrefixTrie,
+}
+
+/// Indexes routes by the literal prefix that a request's path must start with.
+#[derive(Debug, Default)]
+struct PrefixTrie {
+    routes: Vec<usize>,
+    children: HashMap<u8, PrefixTrie>,
+}
+
 #[derive(Clone, Debug)]
 pub struct ResponseClass {
     is_failure: bool,
@@ -67,6 +130,17 @@ pub struct ResponseClass {
 #[derive(Clone, Default)]
 pub struct ResponseClasses(Arc<Vec<ResponseClass>>);
 
+/// How responses with a `3xx` status are classified, unless they match one of
+/// a route's response classes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RedirectClass {
+    Success,
+    Failure,
+    /// Neither a success nor a failure, so that redirects do not affect
+    /// success rates and are neither retried nor credited to retry budgets.
+    Neutral,
+}
+
 #[derive(Clone, Debug)]
 pub enum ResponseMatch {
     All(Vec<ResponseMatch>),
@@ -104,6 +178,8 @@ impl Route {
             response_classes: ResponseClasses(response_classes.into()),
             retries: None,
             timeout: None,
+            max_request_body_size: None,
+            redirect_class: None,
         }
     }
 
@@ -111,6 +187,12 @@ impl Route {
         &self.labels.0
     }
 
+    /// Removes the route's labels, so that its metrics are reported as those
+    /// of the default route.
+    pub fn clear_labels(&mut self) {
+        self.labels = Labels::default();
+    }
+
     pub fn response_classes(&self) -> &ResponseClasses {
         &self.response_classes
     }
@@ -130,6 +212,63 @@ impl Route {
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout);
     }
+
+    pub fn max_request_body_size(&self) -> Option<usize> {
+        self.max_request_body_size
+    }
+
+    pub fn set_max_request_body_size(&mut self, max: usize) {
+        self.max_request_body_size = Some(max);
+    }
+
+    pub fn redirect_class(&self) -> Option<RedirectClass> {
+        self.redirect_class
+    }
+
+    pub fn set_redirect_class(&mut self, class: RedirectClass) {
+        self.redirect_class = Some(class);
+    }
+}
+
+// === impl ProfileName ===
+
+impl fmt::Display for ProfileName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileName::Name(ref addr) => fmt::Display::fmt(addr, f),
+            ProfileName::Wildcard(ref wildcard, port) => write!(f, "{}:{}", wildcard, port),
+        }
+    }
+}
+
+// === impl Wildcard ===
+
+impl Wildcard {
+    pub fn new(domain: dns::Name) -> Self {
+        Wildcard(domain)
+    }
+
+    /// Returns true if `name` is a subdomain of the wildcard's domain.
+    ///
+    /// Like a DNS wildcard, `*` stands for one or more labels, so the domain
+    /// itself is not matched.
+    pub fn matches(&self, name: &dns::Name) -> bool {
+        let name = name.without_trailing_dot();
+        let domain = self.domain();
+        name.len() > domain.len()
+            && name.ends_with(domain)
+            && name[..name.len() - domain.len()].ends_with('.')
+    }
+
+    fn domain(&self) -> &str {
+        self.0.without_trailing_dot()
+    }
+}
+
+impl fmt::Display for Wildcard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "*.{}", self.domain())
+    }
 }
 
 // === impl RequestMatch ===
@@ -144,6 +283,185 @@ impl RequestMatch {
             RequestMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(req)),
         }
     }
+
+    /// Returns a method that requests must have in order to match.
+    fn required_method(&self) -> Option<&http::Method> {
+        match self {
+            RequestMatch::Method(ref method) => Some(method),
+            RequestMatch::All(ref ms) => ms.iter().filter_map(RequestMatch::required_method).next(),
+            _ => None,
+        }
+    }
+
+    /// Returns a literal prefix that request paths must have in order to match.
+    fn required_path_prefix(&self) -> Option<String> {
+        match self {
+            RequestMatch::Path(ref re) => literal_prefix(re.as_str()),
+            RequestMatch::All(ref ms) => ms
+                .iter()
+                .filter_map(RequestMatch::required_path_prefix)
+                .max_by_key(|p| p.len()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the literal text that every match of an anchored pattern starts
+/// with.
+///
+/// This is conservative: when a pattern's structure isn't understood, a
+/// shorter prefix, or none at all, is returned.
+fn literal_prefix(pattern: &str) -> Option<String> {
+    if !pattern.starts_with('^') || may_alternate(pattern) {
+        return None;
+    }
+
+    let mut prefix = String::new();
+    let mut chars = pattern[1..].chars().peekable();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped.is_ascii_punctuation() => escaped,
+                _ => break,
+            },
+            '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => break,
+            c => c,
+        };
+
+        match chars.peek() {
+            // The literal is optional.
+            Some(&'?') | Some(&'*') | Some(&'{') => break,
+            // The literal is required but may repeat.
+            Some(&'+') => {
+                prefix.push(literal);
+                break;
+            }
+            _ => prefix.push(literal),
+        }
+    }
+
+    if prefix.is_empty() {
+        None
+    } else {
+        Some(prefix)
+    }
+}
+
+/// Returns false if a pattern certainly has no top-level alternation.
+fn may_alternate(pattern: &str) -> bool {
+    let mut depth = 0usize;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => {
+                // Skip the (possibly nested) class, in which parentheses and
+                // bars are literals. A leading `]` is a literal, too.
+                if chars.peek() == Some(&'^') {
+                    chars.next();
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                }
+                let mut classes = 1usize;
+                while classes > 0 {
+                    match chars.next() {
+                        Some('\\') => {
+                            chars.next();
+                        }
+                        Some('[') => classes += 1,
+                        Some(']') => classes -= 1,
+                        Some(_) => {}
+                        None => return true,
+                    }
+                }
+            }
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '|' if depth == 0 => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+// === impl RouteTable ===
+
+impl RouteTable {
+    fn new(routes: Routes) -> Self {
+        let mut by_method = HashMap::new();
+        let mut any_method = PrefixTrie::default();
+
+        for (idx, &(ref condition, _)) in routes.iter().enumerate() {
+            let prefix = condition.required_path_prefix().unwrap_or_default();
+            let trie = match condition.required_method() {
+                Some(method) => by_method
+                    .entry(method.clone())
+                    .or_insert_with(PrefixTrie::default),
+                None => &mut any_method,
+            };
+            trie.insert(prefix.as_bytes(), idx);
+        }
+
+        Self {
+            routes,
+            by_method,
+            any_method,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Returns the first route whose condition matches the request.
+    fn recognize<B>(&self, req: &http::Request<B>) -> Option<&(RequestMatch, Route)> {
+        let path = req.uri().path().as_bytes();
+
+        let mut candidates = Vec::new();
+        if let Some(trie) = self.by_method.get(req.method()) {
+            trie.candidates(path, &mut candidates);
+        }
+        self.any_method.candidates(path, &mut candidates);
+        // Each route is indexed exactly once, so the candidates only need to
+        // be restored to their configured order.
+        candidates.sort_unstable();
+
+        candidates
+            .into_iter()
+            .map(|idx| &self.routes[idx])
+            .find(|route| route.0.is_match(req))
+    }
+}
+
+// === impl PrefixTrie ===
+
+impl PrefixTrie {
+    fn insert(&mut self, prefix: &[u8], route: usize) {
+        match prefix.split_first() {
+            None => self.routes.push(route),
+            Some((byte, rest)) => self
+                .children
+                .entry(*byte)
+                .or_insert_with(PrefixTrie::default)
+                .insert(rest, route),
+        }
+    }
+
+    /// Appends the routes indexed by any prefix of `path` to `out`.
+    fn candidates(&self, path: &[u8], out: &mut Vec<usize>) {
+        let mut node = self;
+        out.extend_from_slice(&node.routes);
+        for byte in path {
+            node = match node.children.get(byte) {
+                Some(child) => child,
+                None => return,
+            };
+            out.extend_from_slice(&node.routes);
+        }
+    }
 }
 
 // === impl ResponseClass ===
@@ -162,6 +480,21 @@ impl ResponseClass {
     }
 }
 
+// === impl RedirectClass ===
+
+impl FromStr for RedirectClass {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("success") => Ok(RedirectClass::Success),
+            s if s.eq_ignore_ascii_case("failure") => Ok(RedirectClass::Failure),
+            s if s.eq_ignore_ascii_case("neutral") => Ok(RedirectClass::Neutral),
+            _ => Err(()),
+        }
+    }
+}
+
 // === impl ResponseClasses ===
 
 impl Deref for ResponseClasses {
@@ -261,6 +594,10 @@ impl fmt::Debug for Labels {
 /// underlying stack is buffered, and so `poll_ready` is NOT called on the routes
 /// before requests are dispatched. If an individual route wishes to apply
 /// backpressure, it must implement its own buffer/limit strategy.
+///
+/// When a profile splits the destination's traffic, the underlying stack is
+/// also built for each of the split's backends, and each route's requests are
+/// distributed over those backends by weight.
 pub mod router {
     extern crate linkerd2_router as rt;
 
@@ -271,6 +608,8 @@ pub mod router {
     use never::Never;
 
     use dns;
+    use proxy::http::path_template::PathTemplates;
+    use proxy::http::split::Split;
     use svc;
 
     use super::*;
@@ -283,14 +622,14 @@ pub mod router {
         route_layer: R,
     ) -> Layer<G, M, R, B>
     where
-        T: CanGetDestination + WithRoute + Clone,
+        T: CanGetDestination + WithRoute + WithDstOverride + Clone,
         M: svc::Stack<T>,
         M::Value: Clone,
         G: GetRoutes + Clone,
         R: svc::Layer<
                 <T as WithRoute>::Output,
                 <T as WithRoute>::Output,
-                svc::shared::Stack<M::Value>,
+                svc::shared::Stack<Split<M::Value>>,
             > + Clone,
     {
         Layer {
@@ -298,6 +637,8 @@ pub mod router {
             get_routes,
             route_layer,
             default_route: Route::default(),
+            path_templates: None,
+            wildcards: Vec::new(),
             _p: ::std::marker::PhantomData,
         }
     }
@@ -310,6 +651,8 @@ pub mod router {
         /// This is saved into a field so that the same `Arc`s are used and
         /// cloned, instead of calling `Route::default()` every time.
         default_route: Route,
+        path_templates: Option<PathTemplates>,
+        wildcards: Vec<Wildcard>,
         _p: ::std::marker::PhantomData<fn() -> (M, B)>,
     }
 
@@ -320,29 +663,43 @@ pub mod router {
         route_layer: R,
         suffixes: Vec<dns::Suffix>,
         default_route: Route,
+        path_templates: Option<PathTemplates>,
+        wildcards: Vec<Wildcard>,
         _p: ::std::marker::PhantomData<fn(B)>,
     }
 
-    pub struct Service<G, T, R, B>
+    pub struct Service<G, T, M, R, B>
     where
         T: WithRoute + Clone,
         T::Output: Eq + Hash,
-        R: svc::Stack<T::Output>,
+        M: svc::Stack<T>,
+        M::Value: Clone,
+        R: svc::Layer<T::Output, T::Output, svc::shared::Stack<Split<M::Value>>>,
         R::Value: svc::Service<http::Request<B>> + Clone,
     {
         target: T,
-        stack: R,
+        /// Builds the backends of traffic splits.
+        inner: M,
+        /// The backend for `target` itself, used unless its traffic is split.
+        default_backend: M::Value,
+        /// The backends of the current traffic split, if any.
+        backends: IndexMap<NameAddr, M::Value>,
+        dst_overrides: Vec<WeightedAddr>,
+        route_layer: R,
+        stack: R::Stack,
         route_stream: Option<G>,
-        router: Router<B, T, R>,
+        router: Router<B, T, R::Stack>,
         default_route: Route,
+        path_templates: Option<PathTemplates>,
     }
 
     type Router<B, T, M> = rt::Router<http::Request<B>, Recognize<T>, M>;
 
     pub struct Recognize<T> {
         target: T,
-        routes: Routes,
+        routes: RouteTable,
         default_route: Route,
+        path_templates: Option<PathTemplates>,
     }
 
     impl<B, T> rt::Recognize<http::Request<B>> for Recognize<T>
@@ -353,10 +710,22 @@ pub mod router {
         type Target = T::Output;
 
         fn recognize(&self, req: &http::Request<B>) -> Option<Self::Target> {
-            for (ref condition, ref route) in &self.routes {
-                if condition.is_match(&req) {
-                    trace!("using configured route: {:?}", condition);
-                    return Some(self.target.clone().with_route(route.clone()));
+            if let Some(&(ref condition, ref route)) = self.routes.recognize(req) {
+                trace!("using configured route: {:?}", condition);
+                return Some(self.target.clone().with_route(route.clone()));
+            }
+
+            if self.routes.is_empty() {
+                if let Some(ref templates) = self.path_templates {
+                    if let Some(path) = templates.template(req.uri().path()) {
+                        trace!("using path template route: {}", path);
+                        let labels = ::std::iter::once(("path".to_owned(), path));
+                        let mut route = Route::new(labels, Vec::new());
+                        if let Some(class) = self.default_route.redirect_class() {
+                            route.set_redirect_class(class);
+                        }
+                        return Some(self.target.clone().with_route(route));
+                    }
                 }
             }
 
@@ -365,21 +734,47 @@ pub mod router {
         }
     }
 
+    impl<G, M, R, B> Layer<G, M, R, B> {
+        /// When a destination has no routes, labels each request's route with
+        /// its path template (as `rt_path`) rather than using the default route.
+        pub fn with_path_templates(self, path_templates: Option<PathTemplates>) -> Self {
+            Self {
+                path_templates,
+                ..self
+            }
+        }
+
+        /// Fetches routes for destinations matching any of `wildcards` from
+        /// the most specific matching wildcard's profile, rather than from
+        /// their own profiles.
+        pub fn with_wildcards(self, wildcards: Vec<Wildcard>) -> Self {
+            Self { wildcards, ..self }
+        }
+
+        /// Classifies redirects with `class` on routes that don't configure
+        /// their own redirect class.
+        pub fn with_redirect_class(mut self, class: Option<RedirectClass>) -> Self {
+            if let Some(class) = class {
+                self.default_route.set_redirect_class(class);
+            }
+            self
+        }
+    }
+
     impl<T, G, M, R, B> svc::Layer<T, T, M> for Layer<G, M, R, B>
     where
-        T: CanGetDestination + WithRoute + Clone,
+        T: CanGetDestination + WithRoute + WithDstOverride + Clone,
         <T as WithRoute>::Output: Eq + Hash,
         G: GetRoutes + Clone,
-        M: svc::Stack<T>,
+        M: svc::Stack<T> + Clone,
         M::Value: Clone,
         R: svc::Layer<
                 <T as WithRoute>::Output,
                 <T as WithRoute>::Output,
-                svc::shared::Stack<M::Value>,
+                svc::shared::Stack<Split<M::Value>>,
             > + Clone,
         R::Stack: Clone,
-        <R::Stack as svc::Stack<<T as WithRoute>::Output>>::Value:
-            svc::Service<http::Request<B>> + Clone,
+        R::Value: svc::Service<http::Request<B>> + Clone,
     {
         type Value = <Stack<G, M, R, B> as svc::Stack<T>>::Value;
         type Error = <Stack<G, M, R, B> as svc::Stack<T>>::Error;
@@ -392,6 +787,8 @@ pub mod router {
                 route_layer: self.route_layer.clone(),
                 suffixes: self.suffixes.clone(),
                 default_route: self.default_route.clone(),
+                path_templates: self.path_templates.clone(),
+                wildcards: self.wildcards.clone(),
                 _p: ::std::marker::PhantomData,
             }
         }
@@ -408,6 +805,8 @@ pub mod router {
                 get_routes: self.get_routes.clone(),
                 route_layer: self.route_layer.clone(),
                 default_route: self.default_route.clone(),
+                path_templates: self.path_templates.clone(),
+                wildcards: self.wildcards.clone(),
                 _p: ::std::marker::PhantomData,
             }
         }
@@ -415,45 +814,58 @@ pub mod router {
 
     impl<T, G, M, R, B> svc::Stack<T> for Stack<G, M, R, B>
     where
-        T: CanGetDestination + WithRoute + Clone,
+        T: CanGetDestination + WithRoute + WithDstOverride + Clone,
         <T as WithRoute>::Output: Eq + Hash,
-        M: svc::Stack<T>,
+        M: svc::Stack<T> + Clone,
         M::Value: Clone,
         G: GetRoutes,
         R: svc::Layer<
                 <T as WithRoute>::Output,
                 <T as WithRoute>::Output,
-                svc::shared::Stack<M::Value>,
+                svc::shared::Stack<Split<M::Value>>,
             > + Clone,
         R::Stack: Clone,
-        <R::Stack as svc::Stack<<T as WithRoute>::Output>>::Value:
-            svc::Service<http::Request<B>> + Clone,
+        R::Value: svc::Service<http::Request<B>> + Clone,
     {
-        type Value = Service<G::Stream, T, R::Stack, B>;
+        type Value = Service<G::Stream, T, M, R, B>;
         type Error = M::Error;
 
         fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
-            let inner = self.inner.make(&target)?;
-            let stack = self.route_layer.bind(svc::shared::stack(inner));
+            let default_backend = self.inner.make(&target)?;
+            let stack = self
+                .route_layer
+                .bind(svc::shared::stack(Split::single(default_backend.clone())));
 
             let router = Router::new(
                 Recognize {
                     target: target.clone(),
-                    routes: Vec::new(),
+                    routes: RouteTable::default(),
                     default_route: self.default_route.clone(),
+                    path_templates: self.path_templates.clone(),
                 },
                 stack.clone(),
-                // only need 1 for default_route at first
-                1,
+                // only need 1 for default_route at first, plus 1 for each
+                // path template.
+                route_capacity(0, self.path_templates.as_ref()),
                 // Doesn't matter, since we are guaranteed to have enough capacity.
                 Duration::from_secs(0),
             );
 
             let route_stream = match target.get_destination() {
                 Some(ref dst) => {
-                    if self.suffixes.iter().any(|s| s.contains(dst.name())) {
+                    let wildcard = self
+                        .wildcards
+                        .iter()
+                        .filter(|w| w.matches(dst.name()))
+                        .max_by_key(|w| w.domain().len());
+                    if let Some(wildcard) = wildcard {
+                        debug!("fetching routes for {:?} from {}", dst, wildcard);
+                        let name = ProfileName::Wildcard(wildcard.clone(), dst.port());
+                        self.get_routes.get_routes(&name)
+                    } else if self.suffixes.iter().any(|s| s.contains(dst.name())) {
                         debug!("fetching routes for {:?}", dst);
-                        self.get_routes.get_routes(&dst)
+                        self.get_routes
+                            .get_routes(&ProfileName::Name((*dst).clone()))
                     } else {
                         debug!("skipping route discovery for dst={:?}", dst);
                         None
@@ -467,10 +879,16 @@ pub mod router {
 
             Ok(Service {
                 target: target.clone(),
+                inner: self.inner.clone(),
+                default_backend,
+                backends: IndexMap::new(),
+                dst_overrides: Vec::new(),
+                route_layer: self.route_layer.clone(),
                 stack,
                 route_stream,
                 router,
                 default_route: self.default_route.clone(),
+                path_templates: self.path_templates.clone(),
             })
         }
     }
@@ -488,26 +906,93 @@ pub mod router {
                 route_layer: self.route_layer.clone(),
                 suffixes: self.suffixes.clone(),
                 default_route: self.default_route.clone(),
+                path_templates: self.path_templates.clone(),
+                wildcards: self.wildcards.clone(),
                 _p: ::std::marker::PhantomData,
             }
         }
     }
 
-    impl<G, T, R, B> Service<G, T, R, B>
+    impl<G, T, M, R, B> Service<G, T, M, R, B>
     where
-        G: Stream<Item = Routes, Error = Never>,
-        T: WithRoute + Clone,
+        G: Stream<Item = Profile, Error = Never>,
+        T: WithRoute + WithDstOverride + Clone,
         T::Output: Eq + Hash,
-        R: svc::Stack<T::Output> + Clone,
+        M: svc::Stack<T>,
+        M::Value: Clone,
+        R: svc::Layer<T::Output, T::Output, svc::shared::Stack<Split<M::Value>>>,
+        R::Stack: Clone,
         R::Value: svc::Service<http::Request<B>> + Clone,
     {
-        fn update_routes(&mut self, routes: Routes) {
-            let slots = routes.len() + 1;
+        fn update_profile(&mut self, profile: Profile) {
+            if profile.dst_overrides != self.dst_overrides {
+                self.update_dst_overrides(profile.dst_overrides);
+            }
+            self.update_routes(profile.routes);
+        }
+
+        /// Rebuilds the split over the destination's backends, reusing the
+        /// backends that remain in the split so that their load balancers
+        /// are preserved.
+        fn update_dst_overrides(&mut self, dst_overrides: Vec<WeightedAddr>) {
+            let mut backends = IndexMap::with_capacity(dst_overrides.len());
+            for o in &dst_overrides {
+                if backends.contains_key(&o.addr) {
+                    continue;
+                }
+                if let Some(svc) = self.backends.get(&o.addr).cloned() {
+                    backends.insert(o.addr.clone(), svc);
+                    continue;
+                }
+                let target = match self.target.with_dst_override(o.addr.clone()) {
+                    Some(target) => target,
+                    None => {
+                        debug!("ignoring traffic split for {:?}", o.addr);
+                        return;
+                    }
+                };
+                match self.inner.make(&target) {
+                    Ok(svc) => {
+                        backends.insert(o.addr.clone(), svc);
+                    }
+                    Err(_) => {
+                        warn!("failed to build backend {:?} of traffic split", o.addr);
+                        return;
+                    }
+                }
+            }
+
+            let split = if dst_overrides.is_empty() {
+                Split::single(self.default_backend.clone())
+            } else {
+                debug!("splitting traffic: {:?}", dst_overrides);
+                let weighted = dst_overrides
+                    .iter()
+                    .map(|o| (backends[&o.addr].clone(), o.weight))
+                    .collect();
+                Split::new(weighted)
+            };
+            self.stack = self.route_layer.bind(svc::shared::stack(split));
+            self.backends = backends;
+            self.dst_overrides = dst_overrides;
+        }
+
+        fn update_routes(&mut self, mut routes: Routes) {
+            if let Some(class) = self.default_route.redirect_class() {
+                for &mut (_, ref mut route) in routes.iter_mut() {
+                    if route.redirect_class().is_none() {
+                        route.set_redirect_class(class);
+                    }
+                }
+            }
+
+            let slots = route_capacity(routes.len(), self.path_templates.as_ref());
             self.router = Router::new(
                 Recognize {
                     target: self.target.clone(),
-                    routes,
+                    routes: RouteTable::new(routes),
                     default_route: self.default_route.clone(),
+                    path_templates: self.path_templates.clone(),
                 },
                 self.stack.clone(),
                 slots,
@@ -516,20 +1001,23 @@ pub mod router {
             );
         }
 
-        fn poll_route_stream(&mut self) -> Option<Async<Option<Routes>>> {
+        fn poll_route_stream(&mut self) -> Option<Async<Option<Profile>>> {
             self.route_stream
                 .as_mut()
                 .and_then(|ref mut s| s.poll().ok())
         }
     }
 
-    impl<G, T, Stk, B, Svc> svc::Service<http::Request<B>> for Service<G, T, Stk, B>
+    impl<G, T, M, R, B, Svc> svc::Service<http::Request<B>> for Service<G, T, M, R, B>
     where
-        G: Stream<Item = Routes, Error = Never>,
-        T: WithRoute + Clone,
+        G: Stream<Item = Profile, Error = Never>,
+        T: WithRoute + WithDstOverride + Clone,
         T::Output: Eq + Hash,
-        Stk: svc::Stack<T::Output, Value = Svc> + Clone,
-        Stk::Error: Into<Error>,
+        M: svc::Stack<T>,
+        M::Value: Clone,
+        R: svc::Layer<T::Output, T::Output, svc::shared::Stack<Split<M::Value>>, Value = Svc>,
+        R::Stack: Clone,
+        R::Error: Into<Error>,
         Svc: svc::Service<http::Request<B>> + Clone,
         Svc::Error: Into<Error>,
     {
@@ -538,8 +1026,8 @@ pub mod router {
         type Future = rt::ResponseFuture<http::Request<B>, Svc>;
 
         fn poll_ready(&mut self) -> Poll<(), Self::Error> {
-            while let Some(Async::Ready(Some(routes))) = self.poll_route_stream() {
-                self.update_routes(routes);
+            while let Some(Async::Ready(Some(profile))) = self.poll_route_stream() {
+                self.update_profile(profile);
             }
 
             Ok(Async::Ready(()))
@@ -549,4 +1037,116 @@ pub mod router {
             self.router.call(req)
         }
     }
+
+    /// Returns the number of router slots needed for the configured routes,
+    /// path templates, and the default route.
+    ///
+    /// Path templates are only used when there are no configured routes.
+    fn route_capacity(routes: usize, path_templates: Option<&PathTemplates>) -> usize {
+        let templates = match path_templates {
+            Some(t) if routes == 0 => t.max_templates(),
+            _ => 0,
+        };
+        routes + templates + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(re: &str) -> RequestMatch {
+        RequestMatch::Path(Regex::new(re).unwrap())
+    }
+
+    fn route(name: &str) -> Route {
+        Route::new(
+            ::std::iter::once(("name".to_owned(), name.to_owned())),
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn wildcards_match_subdomains() {
+        use convert::TryFrom;
+
+        let name = |s: &str| dns::Name::try_from(s.as_bytes()).unwrap();
+        let wildcard = Wildcard::new(name("payments.svc.cluster.local."));
+        assert_eq!(wildcard.to_string(), "*.payments.svc.cluster.local");
+
+        assert!(wildcard.matches(&name("tenant-a.payments.svc.cluster.local")));
+        assert!(wildcard.matches(&name("a.b.payments.svc.cluster.local.")));
+        assert!(!wildcard.matches(&name("payments.svc.cluster.local")));
+        assert!(!wildcard.matches(&name("xpayments.svc.cluster.local")));
+        assert!(!wildcard.matches(&name("web.svc.cluster.local")));
+    }
+
+    #[test]
+    fn extracts_literal_prefixes() {
+        assert_eq!(
+            literal_prefix(r"^/api/v1/users$"),
+            Some("/api/v1/users".into())
+        );
+        assert_eq!(
+            literal_prefix(r"^/api/v1/users/[^/]+$"),
+            Some("/api/v1/users/".into())
+        );
+        assert_eq!(literal_prefix(r"^/a[|(]b|/c$"), None);
+        assert_eq!(
+            literal_prefix(r"^/api/(v1|v2)/users$"),
+            Some("/api/".into())
+        );
+        assert_eq!(literal_prefix(r"^/api\.v1/x$"), Some("/api.v1/x".into()));
+        assert_eq!(literal_prefix(r"^/apis?/x$"), Some("/api".into()));
+        assert_eq!(literal_prefix(r"^/a+/x$"), Some("/a".into()));
+        assert_eq!(literal_prefix(r"^/a\d+$"), Some("/a".into()));
+        assert_eq!(literal_prefix(r"^/a|/b$"), None);
+        assert_eq!(literal_prefix(r"^(?i)/a$"), None);
+        assert_eq!(literal_prefix(r"/a$"), None);
+    }
+
+    #[test]
+    fn matches_first_route_like_linear_evaluation() {
+        let routes: Routes = vec![
+            (
+                RequestMatch::All(vec![
+                    RequestMatch::Method(http::Method::POST),
+                    path(r"^/api/users$"),
+                ]),
+                route("create-user"),
+            ),
+            (path(r"^/api/users/[^/]+$"), route("user")),
+            (path(r"^/api/.*$"), route("api")),
+            (
+                RequestMatch::Not(Box::new(RequestMatch::Method(http::Method::GET))),
+                route("not-get"),
+            ),
+            (path(r"^/api/users$"), route("users")),
+            (path(r"^/(healthz|readyz)$"), route("health")),
+            (RequestMatch::Any(vec![]), route("never")),
+        ];
+
+        let requests = vec![
+            (http::Method::POST, "/api/users"),
+            (http::Method::GET, "/api/users"),
+            (http::Method::GET, "/api/users/7"),
+            (http::Method::DELETE, "/api/users/7"),
+            (http::Method::PUT, "/other"),
+            (http::Method::GET, "/healthz"),
+            (http::Method::GET, "/other"),
+            (http::Method::GET, "/"),
+        ];
+
+        let table = RouteTable::new(routes.clone());
+        for (method, uri) in requests {
+            let req = http::Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(())
+                .unwrap();
+            let linear = routes.iter().find(|r| r.0.is_match(&req)).map(|r| &r.1);
+            let compiled = table.recognize(&req).map(|r| &r.1);
+            assert_eq!(compiled, linear, "{} {}", req.method(), uri);
+        }
+    }
 }
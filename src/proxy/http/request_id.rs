@@ -0,0 +1,117 @@
+//! Identifies each request so that it may be correlated across hops.
+//!
+//! Requests that do not already carry an `l5d-request-id` header are assigned
+//! a random ID, which is forwarded with the request. Applications that copy
+//! the header onto the requests they make in turn allow a request to be
+//! followed through every proxy it passes.
+
+use futures::Poll;
+use http::{self, header::HeaderValue};
+use rand;
+
+use svc;
+
+pub const L5D_REQUEST_ID: &str = "l5d-request-id";
+
+/// Returns a request's ID, if it has one.
+pub fn get<B>(req: &http::Request<B>) -> Option<&str> {
+    req.headers()
+        .get(L5D_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+}
+
+/// Assigns an ID to each request that does not have one.
+pub fn layer() -> Layer {
+    Layer
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer;
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+}
+
+/// Generates a random, 128-bit request ID.
+fn generate() -> HeaderValue {
+    let id = format!(
+        "{:016x}{:016x}",
+        rand::random::<u64>(),
+        rand::random::<u64>()
+    );
+    HeaderValue::from_str(&id).expect("request id must be a valid header value")
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack { inner }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service { inner })
+    }
+}
+
+// === impl Service ===
+
+impl<S, B> svc::Service<http::Request<B>> for Service<S>
+where
+    S: svc::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if !req.headers().contains_key(L5D_REQUEST_ID) {
+            let id = generate();
+            trace!("assigning request id {:?}", id);
+            req.headers_mut().insert(L5D_REQUEST_ID, id);
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_distinct_ids() {
+        let a = generate();
+        let b = generate();
+        assert_eq!(a.len(), 32);
+        assert_ne!(a, b);
+    }
+}
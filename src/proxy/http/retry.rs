@@ -1,12 +1,16 @@
 use std::marker::PhantomData;
 
 use futures::future;
-use http::{Request, Response};
+use http::{header::HeaderValue, Request, Response};
 use tower_retry;
 
 use proxy::http::metrics::{Scoped, Stats};
 use svc;
 
+/// Set on retried requests to the number of the retry, starting at 1, so that
+/// servers may distinguish them from original requests.
+pub const X_RETRY_ATTEMPT: &str = "x-retry-attempt";
+
 pub trait CanRetry {
     type Retry: Retry + Clone;
     fn can_retry(&self) -> Option<Self::Retry>;
@@ -42,6 +46,10 @@ pub type Service<R, Svc, St> = tower_retry::Retry<Policy<R, St>, Svc>;
 #[derive(Clone)]
 pub struct Policy<R, S>(R, S);
 
+/// The number of times a request has been retried.
+#[derive(Copy, Clone, Debug, Default)]
+struct Attempt(usize);
+
 // === impl Layer ===
 
 pub fn layer<S, K, A, B>(registry: S) -> Layer<S, K, A, B> {
@@ -137,7 +145,11 @@ where
         match result {
             Ok(res) => match self.0.retry(req, res) {
                 Ok(()) => {
-                    trace!("retrying request");
+                    // `req` is the clone that is dispatched as the retry, so
+                    // it already records the attempt.
+                    let attempt = Attempt::of(req);
+                    trace!("retrying request; attempt={}", attempt.0);
+                    self.1.incr_retry_attempt(attempt.0);
                     Some(future::ok(self.clone()))
                 }
                 Err(NoRetry::Budget) => {
@@ -154,8 +166,14 @@ where
     }
 
     fn clone_request(&self, req: &Request<A>) -> Option<Request<A>> {
-        if let Some(clone) = self.0.clone_request(req) {
+        if let Some(mut clone) = self.0.clone_request(req) {
             trace!("cloning request");
+            // The clone is only dispatched as a retry of `req`.
+            let attempt = Attempt::of(req).next();
+            clone
+                .headers_mut()
+                .insert(X_RETRY_ATTEMPT, HeaderValue::from(attempt.0));
+            clone.extensions_mut().insert(attempt);
             Some(clone)
         } else {
             trace!("request could not be cloned");
@@ -164,6 +182,21 @@ where
     }
 }
 
+// === impl Attempt ===
+
+impl Attempt {
+    fn of<B>(req: &Request<B>) -> Self {
+        req.extensions()
+            .get::<Attempt>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn next(self) -> Self {
+        Attempt(self.0 + 1)
+    }
+}
+
 impl<B: TryClone> TryClone for Request<B> {
     fn try_clone(&self) -> Option<Self> {
         if let Some(body) = self.body().try_clone() {
@@ -0,0 +1,435 @@
+//! Balances requests over a consistent-hash ring.
+//!
+//! Each request is hashed on a configured property, such as a header, a
+//! cookie, or its authority, so that requests that share the property are
+//! sent to the same endpoint. Every endpoint is placed at many points on the
+//! ring, so when an endpoint is added or removed only the requests that hashed
+//! to it move to another endpoint.
+//!
+//! Load is bounded: when the endpoint that a request hashes to already has
+//! more than `LOAD_FACTOR` times the average number of in-flight requests, the
+//! request is sent to the next endpoint on the ring instead ("Consistent
+//! Hashing with Bounded Loads", Mirrokni et al.).
+
+extern crate tower_discover;
+
+use futures::{Async, Future, Poll};
+use http::{
+    self,
+    header::{self, HeaderName},
+};
+use indexmap::IndexMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use self::tower_discover::{Change, Discover};
+use svc;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The number of points at which each endpoint is placed on the ring.
+const REPLICAS: usize = 100;
+
+/// How many times the average number of in-flight requests an endpoint may
+/// serve before requests that hash to it are sent elsewhere.
+const LOAD_FACTOR: f64 = 1.25;
+
+/// The request property on which requests are hashed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashKey {
+    Header(HeaderName),
+    Cookie(String),
+    /// The request URI's authority or, if it has none, its `Host` header.
+    Authority,
+}
+
+/// Balances requests over the endpoints of `D` by hashing them onto a ring.
+///
+/// Requests without the hashed property are sent to the least-loaded
+/// endpoint.
+pub struct Balance<D: Discover> {
+    discover: D,
+    key: HashKey,
+    endpoints: IndexMap<D::Key, Endpoint<D::Service>>,
+    /// The points of all endpoints on the ring, ordered by hash.
+    ring: Vec<(u64, D::Key)>,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    _in_flight: Arc<()>,
+}
+
+struct Endpoint<S> {
+    service: S,
+    ready: bool,
+    /// Cloned into each response future, so that the number of in-flight
+    /// requests is tracked by the reference count.
+    in_flight: Arc<()>,
+}
+
+// === impl HashKey ===
+
+impl HashKey {
+    /// Hashes the request's value of this property, if it has one.
+    fn hash<B>(&self, req: &http::Request<B>) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        match *self {
+            HashKey::Header(ref name) => req.headers().get(name)?.as_bytes().hash(&mut hasher),
+            HashKey::Cookie(ref name) => cookie(req.headers(), name)?.hash(&mut hasher),
+            HashKey::Authority => {
+                let authority = req.uri().authority_part().map(|a| a.as_str()).or_else(|| {
+                    req.headers()
+                        .get(header::HOST)
+                        .and_then(|h| h.to_str().ok())
+                })?;
+                authority.hash(&mut hasher)
+            }
+        }
+        Some(hasher.finish())
+    }
+}
+
+impl FromStr for HashKey {
+    type Err = ();
+
+    /// Parses `header:<name>`, `cookie:<name>`, or `authority`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("authority") {
+            return Ok(HashKey::Authority);
+        }
+
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next().map(str::trim)) {
+            (Some(kind), Some(name)) if kind.eq_ignore_ascii_case("header") => {
+                HeaderName::from_bytes(name.as_bytes())
+                    .map(HashKey::Header)
+                    .map_err(|_| ())
+            }
+            (Some(kind), Some(name)) if kind.eq_ignore_ascii_case("cookie") && !name.is_empty() => {
+                Ok(HashKey::Cookie(name.to_owned()))
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+/// Finds the value of the `name` cookie.
+fn cookie<'a>(headers: &'a http::HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| {
+            let mut kv = pair.trim().splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if k == name => Some(v),
+                _ => None,
+            }
+        })
+        .next()
+}
+
+// === impl Balance ===
+
+impl<D> Balance<D>
+where
+    D: Discover,
+    D::Key: Clone,
+{
+    pub fn new(discover: D, key: HashKey) -> Self {
+        Self {
+            discover,
+            key,
+            endpoints: IndexMap::new(),
+            ring: Vec::new(),
+        }
+    }
+
+    fn update_endpoints(&mut self) -> Result<(), D::Error> {
+        while let Async::Ready(change) = self.discover.poll()? {
+            match change {
+                Change::Insert(key, service) => {
+                    let endpoint = Endpoint {
+                        service,
+                        ready: false,
+                        in_flight: Arc::new(()),
+                    };
+                    // A replaced endpoint keeps its points on the ring.
+                    if self.endpoints.insert(key.clone(), endpoint).is_none() {
+                        self.ring
+                            .extend((0..REPLICAS).map(|i| (point(&key, i), key.clone())));
+                        self.ring.sort_by_key(|p| p.0);
+                    }
+                }
+                Change::Remove(key) => {
+                    if self.endpoints.swap_remove(&key).is_some() {
+                        self.ring.retain(|p| p.1 != key);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Selects the first ready endpoint at or after the request's point on the
+    /// ring that is within the load bound.
+    fn select<B>(&self, req: &http::Request<B>) -> Option<usize> {
+        let hash = match self.key.hash(req) {
+            Some(hash) => hash,
+            None => return self.least_loaded(),
+        };
+
+        let total = self
+            .endpoints
+            .values()
+            .map(Endpoint::in_flight)
+            .sum::<usize>();
+        let bound = (LOAD_FACTOR * (total + 1) as f64 / self.endpoints.len() as f64).ceil();
+
+        let start = match self.ring.binary_search_by_key(&hash, |p| p.0) {
+            Ok(i) | Err(i) => i,
+        };
+        for i in 0..self.ring.len() {
+            let key = &self.ring[(start + i) % self.ring.len()].1;
+            if let Some((idx, _, endpoint)) = self.endpoints.get_full(key) {
+                if endpoint.ready && (endpoint.in_flight() as f64) < bound {
+                    return Some(idx);
+                }
+            }
+        }
+
+        self.least_loaded()
+    }
+
+    fn least_loaded(&self) -> Option<usize> {
+        self.endpoints
+            .values()
+            .enumerate()
+            .filter(|&(_, ep)| ep.ready)
+            .min_by_key(|&(_, ep)| ep.in_flight())
+            .map(|(idx, _)| idx)
+    }
+}
+
+impl<D, A> svc::Service<http::Request<A>> for Balance<D>
+where
+    D: Discover,
+    D::Key: Clone,
+    D::Error: Into<Error>,
+    D::Service: svc::Service<http::Request<A>>,
+    <D::Service as svc::Service<http::Request<A>>>::Error: Into<Error>,
+{
+    type Response = <D::Service as svc::Service<http::Request<A>>>::Response;
+    type Error = Error;
+    type Future = ResponseFuture<<D::Service as svc::Service<http::Request<A>>>::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.update_endpoints().map_err(Into::into)?;
+
+        let mut ready = false;
+        for endpoint in self.endpoints.values_mut() {
+            endpoint.ready = endpoint
+                .service
+                .poll_ready()
+                .map_err(Into::into)?
+                .is_ready();
+            ready = ready || endpoint.ready;
+        }
+
+        if ready {
+            Ok(Async::Ready(()))
+        } else {
+            trace!("no ready endpoints of {}", self.endpoints.len());
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let idx = self.select(&req).expect("called before ready");
+        let (_, endpoint) = self
+            .endpoints
+            .get_index_mut(idx)
+            .expect("selected endpoint must exist");
+
+        endpoint.ready = false;
+        ResponseFuture {
+            inner: endpoint.service.call(req),
+            _in_flight: endpoint.in_flight.clone(),
+        }
+    }
+}
+
+/// The point at which an endpoint's `replica` is placed on the ring.
+///
+/// `DefaultHasher::new` always uses the same keys, so all proxies built from
+/// the same source place an endpoint at the same points.
+fn point<K: Hash>(key: &K, replica: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    replica.hash(&mut hasher);
+    hasher.finish()
+}
+
+// === impl Endpoint ===
+
+impl<S> Endpoint<S> {
+    fn in_flight(&self) -> usize {
+        Arc::strong_count(&self.in_flight) - 1
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F> Future for ResponseFuture<F>
+where
+    F: Future,
+    F::Error: Into<Error>,
+{
+    type Item = F::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::collections::VecDeque;
+
+    struct Svc(usize);
+
+    impl svc::Service<http::Request<()>> for Svc {
+        type Response = usize;
+        type Error = Error;
+        type Future = future::FutureResult<usize, Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: http::Request<()>) -> Self::Future {
+            future::ok(self.0)
+        }
+    }
+
+    struct Changes(VecDeque<Change<usize, Svc>>);
+
+    impl Discover for Changes {
+        type Key = usize;
+        type Service = Svc;
+        type Error = Error;
+
+        fn poll(&mut self) -> Poll<Change<usize, Svc>, Error> {
+            match self.0.pop_front() {
+                Some(change) => Ok(Async::Ready(change)),
+                None => Ok(Async::NotReady),
+            }
+        }
+    }
+
+    fn balance(endpoints: usize) -> Balance<Changes> {
+        let changes = (0..endpoints).map(|i| Change::Insert(i, Svc(i))).collect();
+        Balance::new(
+            Changes(changes),
+            HashKey::Header(HeaderName::from_static("x-user")),
+        )
+    }
+
+    fn request(user: &str) -> http::Request<()> {
+        http::Request::builder()
+            .header("x-user", user)
+            .body(())
+            .unwrap()
+    }
+
+    fn route(balance: &mut Balance<Changes>, user: &str) -> usize {
+        use svc::Service;
+
+        assert!(balance.poll_ready().unwrap().is_ready());
+        balance.call(request(user)).wait().unwrap()
+    }
+
+    #[test]
+    fn routes_are_stable_across_endpoint_changes() {
+        let mut balance = balance(5);
+        let users = (0..50).map(|i| format!("user-{}", i)).collect::<Vec<_>>();
+        let routes = users
+            .iter()
+            .map(|u| route(&mut balance, u))
+            .collect::<Vec<_>>();
+        for (user, endpoint) in users.iter().zip(&routes) {
+            assert_eq!(route(&mut balance, user), *endpoint);
+        }
+
+        balance.discover.0.push_back(Change::Remove(2));
+        for (user, endpoint) in users.iter().zip(&routes) {
+            let moved = route(&mut balance, user);
+            if *endpoint == 2 {
+                assert_ne!(moved, 2);
+            } else {
+                assert_eq!(moved, *endpoint, "{} moved", user);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_load() {
+        use svc::Service;
+
+        let mut balance = balance(2);
+        let mut in_flight = Vec::new();
+        for _ in 0..10 {
+            assert!(balance.poll_ready().unwrap().is_ready());
+            in_flight.push(balance.call(request("hot")));
+        }
+
+        let loads = balance
+            .endpoints
+            .values()
+            .map(Endpoint::in_flight)
+            .collect::<Vec<_>>();
+        // With 10 requests over 2 endpoints, neither may serve more than
+        // `ceil(1.25 * 10 / 2)`.
+        assert!(loads.iter().all(|&l| l > 0 && l <= 7), "{:?}", loads);
+    }
+
+    #[test]
+    fn parses_hash_keys() {
+        assert_eq!("authority".parse::<HashKey>(), Ok(HashKey::Authority));
+        assert_eq!(
+            "header:X-User".parse::<HashKey>(),
+            Ok(HashKey::Header(HeaderName::from_static("x-user")))
+        );
+        assert_eq!(
+            " cookie: session".parse::<HashKey>(),
+            Ok(HashKey::Cookie("session".to_owned()))
+        );
+        assert_eq!("cookie:".parse::<HashKey>(), Err(()));
+        assert_eq!("path".parse::<HashKey>(), Err(()));
+    }
+
+    #[test]
+    fn hashes_cookies() {
+        let key = HashKey::Cookie("session".to_owned());
+        let req = http::Request::builder()
+            .header(header::COOKIE, "theme=dark; session=abc")
+            .body(())
+            .unwrap();
+        let other = http::Request::builder()
+            .header(header::COOKIE, "session=abc")
+            .body(())
+            .unwrap();
+        assert!(key.hash(&req).is_some());
+        assert_eq!(key.hash(&req), key.hash(&other));
+        assert_eq!(key.hash(&request("abc")), None);
+    }
+}
@@ -1,16 +1,32 @@
-use futures::Poll;
-use http;
+use futures::{Async, Future, Poll};
+use http::{self, uri::Authority};
 use std::fmt;
 use std::marker::PhantomData;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_timer::{clock, Delay};
 
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics, Scopes};
 use never::Never;
 use svc;
 
+use super::error_kind::{HasErrorKind, Kind};
+
 extern crate linkerd2_router;
 
 pub use self::linkerd2_router::{error, Recognize, Router};
 
+use self::linkerd2_router::Overflowed;
+
+metrics! {
+    router_error_total: Counter {
+        "Total number of requests that a router could not route immediately, or whose route failed, by error kind"
+    }
+}
+
+/// How long a queued request waits before it checks for capacity again.
+const QUEUE_RETRY: Duration = Duration::from_millis(10);
+
 // compiler doesn't notice this type is used in where bounds below...
 #[allow(unused)]
 type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -20,6 +36,64 @@ pub struct Config {
     capacity: usize,
     max_idle_age: Duration,
     proxy_name: &'static str,
+    overflow: Overflow,
+    registry: Option<Registry>,
+}
+
+/// Determines how a router handles requests for new routes when it is at
+/// capacity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Requests fail immediately.
+    Reject,
+    /// Requests wait, up to the given timeout, for capacity to become
+    /// available.
+    Queue(Duration),
+    /// Requests are served by a route that is built for each request and not
+    /// cached.
+    Spill,
+}
+
+/// Errors whose labels haven't been recorded within `retain_idle` are no
+/// longer reported.
+pub fn metrics(retain_idle: Duration) -> (Registry, Report) {
+    let inner = Arc::new(Mutex::new(Scopes::default()));
+    (
+        Registry(inner.clone()),
+        Report {
+            errors: inner,
+            retain_idle,
+        },
+    )
+}
+
+/// Records router errors.
+#[derive(Clone, Debug)]
+pub struct Registry(Arc<Mutex<Errors>>);
+
+/// Records the failure of a routed request.
+struct Recorder {
+    registry: Registry,
+    proxy_name: &'static str,
+    authority: Option<Authority>,
+}
+
+/// Implements `FmtMetrics` to render prometheus-formatted router errors.
+#[derive(Clone, Debug)]
+pub struct Report {
+    errors: Arc<Mutex<Errors>>,
+    retain_idle: Duration,
+}
+
+type Errors = Scopes<ErrorLabels, Counter>;
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct ErrorLabels {
+    proxy_name: &'static str,
+    kind: Kind,
+    /// How a request was handled when the router was at capacity.
+    overflow: Option<&'static str>,
+    authority: Option<Authority>,
 }
 
 /// A layer that that builds a routing service.
@@ -47,6 +121,35 @@ where
     Stk::Value: svc::Service<Req>,
 {
     inner: Router<Req, Rec, Stk>,
+    proxy_name: &'static str,
+    overflow: Overflow,
+    registry: Option<Registry>,
+}
+
+pub struct ResponseFuture<Req, Rec, Stk>
+where
+    Rec: Recognize<Req>,
+    Stk: svc::Stack<Rec::Target>,
+    Stk::Value: svc::Service<Req>,
+{
+    state: State<Req, Rec, Stk>,
+    recorder: Option<Recorder>,
+}
+
+enum State<Req, Rec, Stk>
+where
+    Rec: Recognize<Req>,
+    Stk: svc::Stack<Rec::Target>,
+    Stk::Value: svc::Service<Req>,
+{
+    Routed(linkerd2_router::ResponseFuture<Req, Stk::Value>),
+    Failed(Option<error::NoCapacity>),
+    Queued {
+        router: Router<Req, Rec, Stk>,
+        request: Option<Req>,
+        deadline: Instant,
+        retry: Delay,
+    },
 }
 
 // === impl Config ===
@@ -57,6 +160,19 @@ impl Config {
             proxy_name,
             capacity,
             max_idle_age,
+            overflow: Overflow::Reject,
+            registry: None,
+        }
+    }
+
+    /// Configures how requests are handled when the router is at capacity.
+    /// Each such request, and each request whose route fails, is counted in
+    /// `registry`.
+    pub fn with_overflow(self, overflow: Overflow, registry: Registry) -> Self {
+        Self {
+            overflow,
+            registry: Some(registry),
+            ..self
         }
     }
 }
@@ -123,32 +239,71 @@ where
             config.capacity,
             config.max_idle_age,
         );
-        Ok(Service { inner })
+        Ok(Service {
+            inner,
+            proxy_name: config.proxy_name,
+            overflow: config.overflow,
+            registry: config.registry.clone(),
+        })
     }
 }
 
 // === impl Service ===
 
-impl<Req, Rec, Stk, B> svc::Service<Req> for Service<Req, Rec, Stk>
+impl<A, Rec, Stk, B> svc::Service<http::Request<A>> for Service<http::Request<A>, Rec, Stk>
 where
-    Rec: Recognize<Req> + Send + Sync + 'static,
+    Rec: Recognize<http::Request<A>> + Send + Sync + 'static,
     Stk: svc::Stack<Rec::Target> + Send + Sync + 'static,
-    Stk::Value: svc::Service<Req, Response = http::Response<B>> + Clone,
-    <Stk::Value as svc::Service<Req>>::Error: Into<Error>,
+    Stk::Value: svc::Service<http::Request<A>, Response = http::Response<B>> + Clone,
+    <Stk::Value as svc::Service<http::Request<A>>>::Error: Into<Error>,
     Stk::Error: Into<Error>,
     B: Default + Send + 'static,
 {
-    type Response = <Router<Req, Rec, Stk> as svc::Service<Req>>::Response;
-    type Error = <Router<Req, Rec, Stk> as svc::Service<Req>>::Error;
-    type Future = <Router<Req, Rec, Stk> as svc::Service<Req>>::Future;
+    type Response =
+        <Router<http::Request<A>, Rec, Stk> as svc::Service<http::Request<A>>>::Response;
+    type Error = <Router<http::Request<A>, Rec, Stk> as svc::Service<http::Request<A>>>::Error;
+    type Future = ResponseFuture<http::Request<A>, Rec, Stk>;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         self.inner.poll_ready()
     }
 
-    fn call(&mut self, request: Req) -> Self::Future {
+    fn call(&mut self, request: http::Request<A>) -> Self::Future {
         trace!("routing...");
-        self.inner.call(request)
+        let proxy_name = self.proxy_name;
+        let recorder = self.registry.as_ref().map(|registry| Recorder {
+            registry: registry.clone(),
+            proxy_name,
+            authority: request_authority(&request),
+        });
+
+        let Overflowed { request, error } = match self.inner.route(request) {
+            Ok(future) => return ResponseFuture::new(State::Routed(future), recorder),
+            Err(overflowed) => overflowed,
+        };
+
+        if let Some(ref recorder) = recorder {
+            recorder.incr(Kind::NoCapacity, Some(self.overflow.name()));
+        }
+
+        let state = match self.overflow {
+            Overflow::Reject => State::Failed(Some(error)),
+            Overflow::Spill => {
+                debug!("{}; spilling request to an uncached route", error);
+                State::Routed(self.inner.route_uncached(request))
+            }
+            Overflow::Queue(timeout) => {
+                debug!("{}; queueing request for up to {:?}", error, timeout);
+                let now = clock::now();
+                State::Queued {
+                    router: self.inner.clone(),
+                    request: Some(request),
+                    deadline: now + timeout,
+                    retry: Delay::new(now),
+                }
+            }
+        };
+        ResponseFuture::new(state, recorder)
     }
 }
 
@@ -162,6 +317,157 @@ where
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            proxy_name: self.proxy_name,
+            overflow: self.overflow,
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+/// The authority that a request targets, used to label its errors.
+fn request_authority<A>(req: &http::Request<A>) -> Option<Authority> {
+    req.uri()
+        .authority_part()
+        .cloned()
+        .or_else(|| super::authority_from_header(req, http::header::HOST))
+}
+
+// === impl ResponseFuture ===
+
+impl<Req, Rec, Stk> ResponseFuture<Req, Rec, Stk>
+where
+    Rec: Recognize<Req>,
+    Stk: svc::Stack<Rec::Target>,
+    Stk::Value: svc::Service<Req>,
+{
+    fn new(state: State<Req, Rec, Stk>, recorder: Option<Recorder>) -> Self {
+        Self { state, recorder }
+    }
+}
+
+impl<Req, Rec, Stk, B> Future for ResponseFuture<Req, Rec, Stk>
+where
+    Rec: Recognize<Req> + Send + Sync + 'static,
+    Stk: svc::Stack<Rec::Target> + Send + Sync + 'static,
+    Stk::Value: svc::Service<Req, Response = http::Response<B>> + Clone,
+    <Stk::Value as svc::Service<Req>>::Error: Into<Error>,
+    Stk::Error: Into<Error>,
+    B: Default + Send + 'static,
+{
+    type Item = http::Response<B>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let future = match self.state {
+                State::Routed(ref mut f) => {
+                    // Requests that could not be routed were counted when
+                    // they overflowed, so only the routes' failures are
+                    // recorded here.
+                    let recorder = &self.recorder;
+                    return f.poll().map_err(|e| {
+                        if let Some(ref recorder) = *recorder {
+                            let kind = e.error_kind().unwrap_or(Kind::Other);
+                            recorder.incr(kind, None);
+                        }
+                        e
+                    });
+                }
+                State::Failed(ref mut e) => {
+                    let e = e.take().expect("response future polled after failure");
+                    return Err(e.into());
+                }
+                State::Queued {
+                    ref mut router,
+                    ref mut request,
+                    deadline,
+                    ref mut retry,
+                } => {
+                    try_ready!(retry.poll().map_err(Error::from));
+
+                    let req = request.take().expect("request must only be routed once");
+                    match router.route(req) {
+                        Ok(future) => future,
+                        Err(Overflowed {
+                            request: req,
+                            error,
+                        }) => {
+                            let now = clock::now();
+                            if now >= deadline {
+                                return Err(error.into());
+                            }
+                            *request = Some(req);
+                            retry.reset(::std::cmp::min(now + QUEUE_RETRY, deadline));
+                            continue;
+                        }
+                    }
+                }
+            };
+            self.state = State::Routed(future);
+        }
+    }
+}
+
+// === impl Recorder ===
+
+impl Recorder {
+    fn incr(&self, kind: Kind, overflow: Option<&'static str>) {
+        let labels = ErrorLabels {
+            proxy_name: self.proxy_name,
+            kind,
+            overflow,
+            authority: self.authority.clone(),
+        };
+        if let Ok(mut errors) = self.registry.0.lock() {
+            errors.get_or_default(labels).incr();
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut errors = match self.errors.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        errors.retain_updated_within(self.retain_idle);
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        router_error_total.fmt_help(f)?;
+        for (labels, count) in &*errors {
+            count.fmt_metric_labeled(f, router_error_total.name, labels)?;
+        }
+
+        Ok(())
+    }
+}
+
+// === impl Overflow ===
+
+impl Overflow {
+    fn name(&self) -> &'static str {
+        match self {
+            Overflow::Reject => "reject",
+            Overflow::Queue(_) => "queue",
+            Overflow::Spill => "spill",
+        }
+    }
+}
+
+impl FmtLabels for ErrorLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "router=\"{}\",error=\"{}\"", self.proxy_name, self.kind)?;
+        if let Some(overflow) = self.overflow {
+            write!(f, ",action=\"{}\"", overflow)?;
+        }
+        if let Some(ref authority) = self.authority {
+            write!(f, ",authority=\"{}\"", authority)?;
         }
+        Ok(())
     }
 }
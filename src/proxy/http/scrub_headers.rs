@@ -0,0 +1,210 @@
+//! Scrubs internal headers from traffic to destinations outside of the mesh.
+//!
+//! Headers such as the proxy's own `l5d-*` headers or tracing baggage may
+//! reveal details about the mesh's topology. When a request is sent to a
+//! destination whose name is not within one of the mesh's domains, headers
+//! that match any of the configured patterns are removed from the request and
+//! from its response.
+
+use futures::{Future, Poll};
+use http::{self, header::HeaderName};
+
+use dns;
+use svc;
+use NameAddr;
+
+/// Describes the destination of a request.
+pub trait HasDstName {
+    fn dst_name(&self) -> Option<&NameAddr>;
+}
+
+/// Matches the names of headers that are scrubbed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    Name(HeaderName),
+    /// Matches all headers with names that start with the given prefix.
+    Prefix(String),
+}
+
+/// Configures which headers are scrubbed, and for which destinations.
+#[derive(Clone, Debug)]
+pub struct Config {
+    patterns: Vec<Pattern>,
+    /// Names within these domains are part of the mesh, and so are not scrubbed.
+    internal: Vec<dns::Suffix>,
+}
+
+pub fn layer(config: Option<Config>) -> Layer {
+    Layer { config }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    config: Option<Config>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    config: Option<Config>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    patterns: Vec<Pattern>,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    patterns: Vec<Pattern>,
+}
+
+// === impl Pattern ===
+
+impl Pattern {
+    fn matches(&self, name: &HeaderName) -> bool {
+        match *self {
+            Pattern::Name(ref n) => n == name,
+            Pattern::Prefix(ref p) => name.as_str().starts_with(p.as_str()),
+        }
+    }
+}
+
+// === impl Config ===
+
+impl Config {
+    pub fn new(patterns: Vec<Pattern>, internal: Vec<dns::Suffix>) -> Self {
+        Self { patterns, internal }
+    }
+
+    fn is_external(&self, name: &NameAddr) -> bool {
+        !self.internal.iter().any(|sfx| sfx.contains(name.name()))
+    }
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    T: HasDstName,
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    T: HasDstName,
+    M: svc::Stack<T>,
+{
+    type Value = svc::Either<Service<M::Value>, M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+
+        let name = match target.dst_name() {
+            Some(name) => name,
+            None => return Ok(svc::Either::B(inner)),
+        };
+        match self.config {
+            Some(ref config) if config.is_external(name) => {
+                debug!("scrubbing headers to external destination {}", name);
+                Ok(svc::Either::A(Service {
+                    inner,
+                    patterns: config.patterns.clone(),
+                }))
+            }
+            _ => Ok(svc::Either::B(inner)),
+        }
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>, Response = http::Response<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        scrub(&self.patterns, req.headers_mut());
+        ResponseFuture {
+            inner: self.inner.call(req),
+            patterns: self.patterns.clone(),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = http::Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut rsp = try_ready!(self.inner.poll());
+        scrub(&self.patterns, rsp.headers_mut());
+        Ok(rsp.into())
+    }
+}
+
+/// Removes all headers that match any of `patterns`.
+fn scrub(patterns: &[Pattern], headers: &mut http::HeaderMap) {
+    let names = headers
+        .keys()
+        .filter(|name| patterns.iter().any(|p| p.matches(name)))
+        .cloned()
+        .collect::<Vec<_>>();
+    for name in names {
+        trace!("scrubbing {} header", name);
+        headers.remove(&name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubs_matching_headers() {
+        let patterns = vec![
+            Pattern::Name(HeaderName::from_static("baggage")),
+            Pattern::Prefix("l5d-".to_owned()),
+        ];
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            "l5d-dst-canonical",
+            "web.ns.svc.cluster.local:80".parse().unwrap(),
+        );
+        headers.insert("l5d-request-id", "abc".parse().unwrap());
+        headers.insert("baggage", "team=payments".parse().unwrap());
+        headers.insert("accept", "*/*".parse().unwrap());
+
+        scrub(&patterns, &mut headers);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["accept"], "*/*");
+    }
+}
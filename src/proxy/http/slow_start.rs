@@ -0,0 +1,298 @@
+//! Ramps up the share of traffic sent to newly-discovered endpoints.
+//!
+//! Endpoints that join a balancer after its initial resolution start cold, so
+//! that backends that need to warm up (e.g. JIT-compiled runtimes) aren't
+//! immediately sent a full share of requests. Each time a cold endpoint is
+//! polled for readiness, it is admitted with a probability that grows linearly
+//! from `MIN_WEIGHT` to 1 over the slow-start window; when it isn't admitted, it
+//! reports that it is not ready, so that the balancer prefers other endpoints.
+//!
+//! Cold endpoints are only held back when some other endpoint is warm, so that
+//! a balancer whose endpoints have all been replaced still serves requests.
+
+extern crate tower_discover;
+
+use futures::{Async, Future, Poll};
+use rand;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio_timer::{clock, Delay};
+
+use self::tower_discover::{Change, Discover};
+use svc;
+
+/// The share of its traffic that an endpoint receives when it is added.
+const MIN_WEIGHT: f64 = 0.1;
+
+/// How long a cold endpoint that wasn't admitted waits before it may be
+/// admitted again.
+const RETRY_AFTER: Duration = Duration::from_millis(10);
+
+/// Wraps the endpoints discovered by `D` so that new endpoints are ramped up.
+pub struct SlowStart<D> {
+    inner: D,
+    window: Option<Duration>,
+    /// Each warm endpoint holds a reference, so that the number of warm
+    /// endpoints is tracked by the reference count.
+    warm: Arc<()>,
+    /// Set once the initial endpoints have been discovered. Until then,
+    /// endpoints start warm.
+    initialized: bool,
+}
+
+pub struct Service<S> {
+    inner: S,
+    state: State,
+}
+
+enum State {
+    Warm(Arc<()>),
+    Cold(Cold),
+}
+
+struct Cold {
+    added: Instant,
+    window: Duration,
+    warm: Weak<()>,
+    /// Set when the endpoint is admitted, until it is called.
+    admitted: bool,
+    retry: Option<Delay>,
+}
+
+// === impl SlowStart ===
+
+impl<D: Discover> SlowStart<D> {
+    /// When `window` is `None`, all endpoints start warm.
+    pub fn new(inner: D, window: Option<Duration>) -> Self {
+        Self {
+            inner,
+            window,
+            warm: Arc::new(()),
+            initialized: false,
+        }
+    }
+}
+
+impl<D: Discover> Discover for SlowStart<D> {
+    type Key = D::Key;
+    type Service = Service<D::Service>;
+    type Error = D::Error;
+
+    fn poll(&mut self) -> Poll<Change<D::Key, Self::Service>, D::Error> {
+        let change = match self.inner.poll()? {
+            Async::Ready(change) => change,
+            Async::NotReady => {
+                // The initial resolution is complete once some endpoints have
+                // been discovered and no more changes are pending.
+                self.initialized = self.initialized || Arc::strong_count(&self.warm) > 1;
+                return Ok(Async::NotReady);
+            }
+        };
+
+        match change {
+            Change::Remove(key) => Ok(Async::Ready(Change::Remove(key))),
+            Change::Insert(key, inner) => {
+                let state = match self.window {
+                    Some(window) if self.initialized => {
+                        trace!("starting endpoint cold for {:?}", window);
+                        State::Cold(Cold {
+                            added: clock::now(),
+                            window,
+                            warm: Arc::downgrade(&self.warm),
+                            admitted: false,
+                            retry: None,
+                        })
+                    }
+                    _ => State::Warm(self.warm.clone()),
+                };
+                Ok(Async::Ready(Change::Insert(key, Service { inner, state })))
+            }
+        }
+    }
+}
+
+// === impl Service ===
+
+impl<S> Service<S> {
+    fn poll_admit(&mut self) -> bool {
+        let warm = match self.state {
+            State::Warm(_) => return true,
+            State::Cold(ref mut cold) => match cold.poll_admit() {
+                Some(admit) => return admit,
+                None => cold.warm.upgrade().unwrap_or_else(|| Arc::new(())),
+            },
+        };
+
+        trace!("endpoint warmed up");
+        self.state = State::Warm(warm);
+        true
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Service<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if !self.poll_admit() {
+            return Ok(Async::NotReady);
+        }
+
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        if let State::Cold(ref mut cold) = self.state {
+            cold.admitted = false;
+        }
+
+        self.inner.call(req)
+    }
+}
+
+// === impl Cold ===
+
+impl Cold {
+    /// Returns whether the endpoint is admitted, or `None` once the window has
+    /// elapsed.
+    fn poll_admit(&mut self) -> Option<bool> {
+        let now = clock::now();
+        let elapsed = if now > self.added {
+            now - self.added
+        } else {
+            Duration::from_secs(0)
+        };
+        if elapsed >= self.window {
+            return None;
+        }
+
+        if self.admitted || !self.has_warm_peers() {
+            return Some(true);
+        }
+
+        if let Some(ref mut retry) = self.retry {
+            if let Ok(Async::NotReady) = retry.poll() {
+                return Some(false);
+            }
+        }
+        self.retry = None;
+
+        if rand::random::<f64>() < weight(elapsed, self.window) {
+            self.admitted = true;
+            return Some(true);
+        }
+
+        // Ensure that the balancer is notified to poll the endpoint again.
+        let mut retry = Delay::new(now + RETRY_AFTER);
+        if let Ok(Async::NotReady) = retry.poll() {
+            self.retry = Some(retry);
+        }
+        Some(false)
+    }
+
+    fn has_warm_peers(&self) -> bool {
+        // The balancer's `SlowStart` and the upgraded reference are not
+        // endpoints.
+        self.warm
+            .upgrade()
+            .map(|warm| Arc::strong_count(&warm) > 2)
+            .unwrap_or(false)
+    }
+}
+
+/// The share of its traffic that an endpoint receives `elapsed` into a
+/// slow-start `window`.
+fn weight(elapsed: Duration, window: Duration) -> f64 {
+    let secs = |d: Duration| d.as_secs() as f64 + f64::from(d.subsec_nanos()) * 1e-9;
+    if window == Duration::from_secs(0) {
+        return 1.0;
+    }
+
+    let ratio = (secs(elapsed) / secs(window)).min(1.0);
+    MIN_WEIGHT + (1.0 - MIN_WEIGHT) * ratio
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::collections::VecDeque;
+
+    struct Svc;
+
+    impl svc::Service<()> for Svc {
+        type Response = ();
+        type Error = ();
+        type Future = future::FutureResult<(), ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: ()) -> Self::Future {
+            future::ok(())
+        }
+    }
+
+    struct Changes(VecDeque<Option<Change<usize, Svc>>>);
+
+    impl Discover for Changes {
+        type Key = usize;
+        type Service = Svc;
+        type Error = ();
+
+        fn poll(&mut self) -> Poll<Change<usize, Svc>, ()> {
+            match self.0.pop_front() {
+                Some(Some(change)) => Ok(Async::Ready(change)),
+                _ => Ok(Async::NotReady),
+            }
+        }
+    }
+
+    fn is_cold(change: &Poll<Change<usize, Service<Svc>>, ()>) -> bool {
+        match *change {
+            Ok(Async::Ready(Change::Insert(_, ref svc))) => match svc.state {
+                State::Cold(_) => true,
+                State::Warm(_) => false,
+            },
+            _ => panic!("expected an insert"),
+        }
+    }
+
+    #[test]
+    fn only_endpoints_added_after_initial_resolution_start_cold() {
+        let changes = vec![
+            None,
+            Some(Change::Insert(0, Svc)),
+            Some(Change::Insert(1, Svc)),
+            None,
+            Some(Change::Insert(2, Svc)),
+        ];
+        let mut slow_start = SlowStart::new(
+            Changes(changes.into_iter().collect()),
+            Some(Duration::from_secs(30)),
+        );
+
+        assert!(slow_start.poll().unwrap().is_not_ready());
+        // The endpoints are held, as they would be by a balancer.
+        let initial = (slow_start.poll(), slow_start.poll());
+        assert!(!is_cold(&initial.0));
+        assert!(!is_cold(&initial.1));
+        assert!(slow_start.poll().unwrap().is_not_ready());
+        assert!(is_cold(&slow_start.poll()));
+    }
+
+    #[test]
+    fn weight_ramps_over_window() {
+        let window = Duration::from_secs(10);
+        assert_eq!(weight(Duration::from_secs(0), window), MIN_WEIGHT);
+        assert!((weight(Duration::from_secs(5), window) - 0.55).abs() < 1e-9);
+        assert_eq!(weight(Duration::from_secs(10), window), 1.0);
+        assert_eq!(weight(Duration::from_secs(20), window), 1.0);
+        assert_eq!(weight(Duration::from_secs(1), Duration::from_secs(0)), 1.0);
+    }
+}
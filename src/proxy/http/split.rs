@@ -0,0 +1,107 @@
+//! Distributes requests over the backends of a traffic split, in proportion to
+//! their weights.
+
+use futures::Poll;
+use rand;
+
+use svc;
+
+/// Sends each request to one of its backends, chosen at random in proportion
+/// to the backends' weights.
+#[derive(Clone, Debug)]
+pub struct Split<S> {
+    backends: Vec<(S, u32)>,
+    total_weight: u64,
+    /// The backend that was chosen, and polled for readiness, for the next
+    /// request.
+    next: Option<usize>,
+}
+
+// === impl Split ===
+
+impl<S> Split<S> {
+    /// Sends all requests to `backend`.
+    pub fn single(backend: S) -> Self {
+        Self::new(vec![(backend, 1)])
+    }
+
+    /// Backends with a weight of zero receive no requests, unless all
+    /// backends have a weight of zero, in which case requests are distributed
+    /// evenly.
+    pub fn new(backends: Vec<(S, u32)>) -> Self {
+        assert!(!backends.is_empty(), "a split must have a backend");
+        let total_weight = backends.iter().map(|&(_, w)| u64::from(w)).sum();
+        Split {
+            backends,
+            total_weight,
+            next: None,
+        }
+    }
+
+    fn choose(&self) -> usize {
+        if self.total_weight == 0 {
+            return rand::random::<usize>() % self.backends.len();
+        }
+
+        let mut n = rand::random::<u64>() % self.total_weight;
+        for (i, &(_, weight)) in self.backends.iter().enumerate() {
+            let weight = u64::from(weight);
+            if n < weight {
+                return i;
+            }
+            n -= weight;
+        }
+        unreachable!("weights must sum to the total weight");
+    }
+}
+
+impl<S, Req> svc::Service<Req> for Split<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        let i = match self.next {
+            Some(i) => i,
+            None => {
+                let i = self.choose();
+                self.next = Some(i);
+                i
+            }
+        };
+        self.backends[i].0.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        // Backends are expected to be buffered, so a request may be
+        // dispatched to a backend that was not polled.
+        let i = match self.next.take() {
+            Some(i) => i,
+            None => self.choose(),
+        };
+        self.backends[i].0.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chooses_in_proportion_to_weight() {
+        let split = Split::new(vec![("a", 0), ("b", 3), ("c", 1)]);
+        let mut counts = [0; 3];
+        for _ in 0..4_000 {
+            counts[split.choose()] += 1;
+        }
+        assert_eq!(counts[0], 0);
+        assert!(counts[1] > counts[2] * 2, "{:?}", counts);
+
+        let even = Split::new(vec![("a", 0), ("b", 0)]);
+        assert!((0..100).any(|_| even.choose() == 0));
+        assert!((0..100).any(|_| even.choose() == 1));
+    }
+}
@@ -0,0 +1,332 @@
+//! Resets HTTP/2 streams whose peer stops sending frames.
+//!
+//! hyper does not expose HTTP/2 PINGs, so a long-lived stream cannot tell
+//! whether a quiet peer is still alive. Instead, when a request or response
+//! body has waited longer than the configured timeout for its next frame, the
+//! body fails with `CANCEL`, which resets the stream on both sides of the
+//! proxy rather than leaving it open until the TCP connection times out.
+//!
+//! Bodies are only timed while the proxy is waiting on the peer, so a stream
+//! that is slow to be read is not reset. When no timeout is configured,
+//! streams are never reset for being idle.
+
+use futures::{Async, Future, Poll};
+use h2;
+use http::{self, Request, Response};
+use hyper::body::Payload;
+use std::time::Duration;
+use tokio_timer::{clock, Delay};
+use tower_http_service;
+
+use super::retry::TryClone;
+use svc;
+
+pub fn layer(timeout: Option<Duration>) -> Layer {
+    Layer { timeout }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    timeout: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    timeout: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    timeout: Option<Duration>,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    timeout: Option<Duration>,
+}
+
+/// A body that fails with `CANCEL` once it has waited `timeout` for a frame.
+#[derive(Debug)]
+pub struct IdleBody<B> {
+    inner: B,
+    timeout: Option<Duration>,
+    /// Set while the inner body is waiting for its next frame.
+    idle: Option<Delay>,
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            timeout: self.timeout,
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, A, B> svc::Service<Request<A>> for Service<S>
+where
+    S: svc::Service<Request<IdleBody<A>>, Response = Response<B>>,
+    A: Payload<Error = h2::Error>,
+    B: Payload<Error = h2::Error>,
+{
+    type Response = Response<IdleBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<A>) -> Self::Future {
+        // Only HTTP/2 streams may be reset without closing the connection.
+        let timeout = self
+            .timeout
+            .filter(|_| req.version() == http::Version::HTTP_2);
+        let req = req.map(|inner| IdleBody::new(inner, timeout));
+        ResponseFuture {
+            inner: self.inner.call(req),
+            timeout,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: Payload<Error = h2::Error>,
+{
+    type Item = Response<IdleBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rsp = try_ready!(self.inner.poll());
+        let timeout = self.timeout;
+        Ok(Async::Ready(rsp.map(|inner| IdleBody::new(inner, timeout))))
+    }
+}
+
+// === impl IdleBody ===
+
+impl<B> IdleBody<B> {
+    fn new(inner: B, timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            timeout,
+            idle: None,
+        }
+    }
+
+    /// Records the result of polling the inner body, failing if it has been
+    /// waiting for longer than the timeout.
+    fn check<T>(&mut self, poll: Poll<T, h2::Error>) -> Poll<T, h2::Error> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return poll,
+        };
+
+        if let Ok(Async::NotReady) = poll {
+            let idle = self
+                .idle
+                .get_or_insert_with(|| Delay::new(clock::now() + timeout));
+            match idle.poll() {
+                Ok(Async::NotReady) => {}
+                Ok(Async::Ready(())) | Err(_) => {
+                    info!("stream idle for {:?}; resetting", timeout);
+                    return Err(h2::Reason::CANCEL.into());
+                }
+            }
+        } else {
+            self.idle = None;
+        }
+
+        poll
+    }
+}
+
+impl<B> Payload for IdleBody<B>
+where
+    B: Payload<Error = h2::Error>,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let poll = self.inner.poll_data();
+        self.check(poll)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        let poll = self.inner.poll_trailers();
+        self.check(poll)
+    }
+}
+
+impl<B> tower_http_service::Body for IdleBody<B>
+where
+    B: Payload<Error = h2::Error>,
+{
+    type Item = B::Data;
+    type Error = B::Error;
+
+    fn is_end_stream(&self) -> bool {
+        Payload::is_end_stream(self)
+    }
+
+    fn poll_buf(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        Payload::poll_data(self)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        Payload::poll_trailers(self)
+    }
+}
+
+impl<B: Default> Default for IdleBody<B> {
+    fn default() -> Self {
+        Self::new(B::default(), None)
+    }
+}
+
+impl<B: TryClone> TryClone for IdleBody<B> {
+    fn try_clone(&self) -> Option<Self> {
+        self.inner
+            .try_clone()
+            .map(|inner| Self::new(inner, self.timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::future;
+    use futures::sync::oneshot;
+    use std::io::Cursor;
+    use svc::Service as _Service;
+    use tokio::runtime::current_thread::Runtime;
+    use tokio_timer::Timeout;
+
+    /// A body that never yields a frame.
+    #[derive(Debug, Default)]
+    struct Pending;
+
+    impl Payload for Pending {
+        type Data = Cursor<Bytes>;
+        type Error = h2::Error;
+
+        fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    /// Responds with a pending body, sending the request's body to the test.
+    struct Respond(Option<oneshot::Sender<IdleBody<Pending>>>);
+
+    impl svc::Service<Request<IdleBody<Pending>>> for Respond {
+        type Response = Response<Pending>;
+        type Error = ();
+        type Future = future::FutureResult<Response<Pending>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, req: Request<IdleBody<Pending>>) -> Self::Future {
+            if let Some(tx) = self.0.take() {
+                let _ = tx.send(req.into_body());
+            }
+            future::ok(Response::new(Pending))
+        }
+    }
+
+    fn send(
+        rt: &mut Runtime,
+        timeout: Option<Duration>,
+        version: http::Version,
+    ) -> (IdleBody<Pending>, IdleBody<Pending>) {
+        let (tx, rx) = oneshot::channel();
+        let mut svc = Service {
+            inner: Respond(Some(tx)),
+            timeout,
+        };
+        let mut req = Request::new(Pending);
+        *req.version_mut() = version;
+        let rsp = rt
+            .block_on(future::lazy(|| svc.call(req)))
+            .expect("response");
+        let req_body = rt.block_on(rx).expect("request body");
+        (req_body, rsp.into_body())
+    }
+
+    fn poll_idle(rt: &mut Runtime, mut body: IdleBody<Pending>) -> Result<(), h2::Error> {
+        rt.block_on(future::poll_fn(|| body.poll_data()).map(|_| ()))
+    }
+
+    #[test]
+    fn resets_idle_http2_streams() {
+        let mut rt = Runtime::new().unwrap();
+        let timeout = Some(Duration::from_millis(10));
+        let (req_body, rsp_body) = send(&mut rt, timeout, http::Version::HTTP_2);
+
+        let e = poll_idle(&mut rt, req_body).expect_err("request body must fail");
+        assert_eq!(e.reason(), Some(h2::Reason::CANCEL));
+        let e = poll_idle(&mut rt, rsp_body).expect_err("response body must fail");
+        assert_eq!(e.reason(), Some(h2::Reason::CANCEL));
+    }
+
+    #[test]
+    fn does_not_reset_http1_or_untimed_streams() {
+        let mut rt = Runtime::new().unwrap();
+        let timeout = Some(Duration::from_millis(10));
+        let untimed = vec![
+            send(&mut rt, timeout, http::Version::HTTP_11),
+            send(&mut rt, None, http::Version::HTTP_2),
+        ];
+
+        for (req_body, rsp_body) in untimed {
+            for mut body in vec![req_body, rsp_body] {
+                let poll = future::poll_fn(move || body.poll_data());
+                let wait = Timeout::new(poll, Duration::from_millis(50));
+                let e = rt.block_on(wait).expect_err("body must remain pending");
+                assert!(e.is_elapsed(), "body must not fail: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Propagates distributed trace contexts on HTTP requests.
+//!
+//! Both W3C Trace Context (`traceparent`) and Zipkin B3 headers, in their
+//! single-header (`b3`) and multi-header (`x-b3-*`) forms, are understood.
+//! A span created for a request is propagated in the format in which its
+//! parent was received, so that applications need only understand their own
+//! format. Spans that start a new trace are propagated in every format.
+
+use http::header::{HeaderMap, HeaderValue};
+use rand;
+use std::fmt;
+
+pub const TRACEPARENT: &str = "traceparent";
+pub const B3: &str = "b3";
+pub const B3_TRACE_ID: &str = "x-b3-traceid";
+pub const B3_SPAN_ID: &str = "x-b3-spanid";
+pub const B3_PARENT_SPAN_ID: &str = "x-b3-parentspanid";
+pub const B3_SAMPLED: &str = "x-b3-sampled";
+pub const B3_FLAGS: &str = "x-b3-flags";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TraceId(pub [u8; 16]);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpanId(pub [u8; 8]);
+
+/// The format in which a trace context is propagated.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    TraceContext,
+    B3Single,
+    B3Multi,
+}
+
+/// The span that a request was sent from, as described by its headers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Parent {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    /// The parent's sampling decision, if it made one.
+    pub sampled: Option<bool>,
+    pub format: Format,
+}
+
+/// The span that the proxy records for a request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Context {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    pub parent_id: Option<SpanId>,
+    pub sampled: bool,
+    /// The formats in which the context is propagated.
+    formats: &'static [Format],
+}
+
+const ALL_FORMATS: &[Format] = &[Format::TraceContext, Format::B3Multi];
+
+/// Reads the trace context that a request carries, if any.
+///
+/// A valid `traceparent` header is preferred over B3 headers.
+pub fn extract(headers: &HeaderMap) -> Option<Parent> {
+    header(headers, TRACEPARENT)
+        .and_then(parse_traceparent)
+        .or_else(|| header(headers, B3).and_then(parse_b3_single))
+        .or_else(|| parse_b3_multi(headers))
+}
+
+/// Replaces the trace context that a request carries with `ctx`.
+pub fn inject(headers: &mut HeaderMap, ctx: &Context) {
+    for format in ctx.formats {
+        match format {
+            Format::TraceContext => {
+                let flags = if ctx.sampled { "01" } else { "00" };
+                let value = format!("00-{}-{}-{}", ctx.trace_id, ctx.span_id, flags);
+                insert(headers, TRACEPARENT, value);
+            }
+            Format::B3Single => {
+                let mut value = format!("{}-{}-{}", ctx.trace_id, ctx.span_id, sampled(ctx));
+                if let Some(parent_id) = ctx.parent_id {
+                    value = format!("{}-{}", value, parent_id);
+                }
+                insert(headers, B3, value);
+            }
+            Format::B3Multi => {
+                insert(headers, B3_TRACE_ID, ctx.trace_id.to_string());
+                insert(headers, B3_SPAN_ID, ctx.span_id.to_string());
+                match ctx.parent_id {
+                    Some(id) => insert(headers, B3_PARENT_SPAN_ID, id.to_string()),
+                    None => {
+                        headers.remove(B3_PARENT_SPAN_ID);
+                    }
+                }
+                insert(headers, B3_SAMPLED, sampled(ctx).to_owned());
+                // The debug flag implies that the span is sampled, which is
+                // now stated explicitly.
+                headers.remove(B3_FLAGS);
+            }
+        }
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+}
+
+fn insert(headers: &mut HeaderMap, name: &'static str, value: String) {
+    let value = HeaderValue::from_str(&value).expect("trace context must be a valid header value");
+    headers.insert(name, value);
+}
+
+fn sampled(ctx: &Context) -> &'static str {
+    if ctx.sampled {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+/// Parses a `traceparent` header, i.e.
+/// `{version}-{trace-id}-{parent-id}-{trace-flags}`.
+fn parse_traceparent(value: &str) -> Option<Parent> {
+    let mut parts = value.split('-');
+    let version = parts.next()?;
+    // Version `ff` is invalid. Later versions may append fields, which are
+    // ignored.
+    if version.len() != 2 || version.eq_ignore_ascii_case("ff") {
+        return None;
+    }
+    parse_u8(version)?;
+    let trace_id = parse_trace_id(parts.next()?)?;
+    let span_id = parse_span_id(parts.next()?)?;
+    let flags = parts.next()?;
+    if flags.len() != 2 || (version == "00" && parts.next().is_some()) {
+        return None;
+    }
+    let flags = parse_u8(flags)?;
+    Some(Parent {
+        trace_id,
+        span_id,
+        sampled: Some(flags & 1 == 1),
+        format: Format::TraceContext,
+    })
+}
+
+/// Parses a `b3` header, i.e.
+/// `{trace-id}-{span-id}[-{sampling-state}[-{parent-span-id}]]`.
+///
+/// A header that only carries a sampling state describes no parent span.
+fn parse_b3_single(value: &str) -> Option<Parent> {
+    let mut parts = value.split('-');
+    let trace_id = parse_b3_trace_id(parts.next()?)?;
+    let span_id = parse_span_id(parts.next()?)?;
+    let sampled = match parts.next() {
+        None => None,
+        Some(s) => Some(parse_b3_sampled(s)?),
+    };
+    Some(Parent {
+        trace_id,
+        span_id,
+        sampled,
+        format: Format::B3Single,
+    })
+}
+
+fn parse_b3_multi(headers: &HeaderMap) -> Option<Parent> {
+    let trace_id = parse_b3_trace_id(header(headers, B3_TRACE_ID)?)?;
+    let span_id = parse_span_id(header(headers, B3_SPAN_ID)?)?;
+    let sampled = if header(headers, B3_FLAGS) == Some("1") {
+        Some(true)
+    } else {
+        match header(headers, B3_SAMPLED) {
+            None => None,
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(s) => Some(parse_b3_sampled(s)?),
+        }
+    };
+    Some(Parent {
+        trace_id,
+        span_id,
+        sampled,
+        format: Format::B3Multi,
+    })
+}
+
+fn parse_b3_sampled(s: &str) -> Option<bool> {
+    match s {
+        "1" | "d" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// B3 trace IDs may be 64 bits long, in which case they are padded to 128 bits.
+fn parse_b3_trace_id(s: &str) -> Option<TraceId> {
+    if s.len() == 16 {
+        let low = parse_span_id(s)?;
+        let mut id = [0; 16];
+        id[8..].copy_from_slice(&low.0);
+        return Some(TraceId(id));
+    }
+    parse_trace_id(s)
+}
+
+fn parse_trace_id(s: &str) -> Option<TraceId> {
+    let mut id = [0; 16];
+    parse_hex(s, &mut id)?;
+    if id == [0; 16] {
+        return None;
+    }
+    Some(TraceId(id))
+}
+
+fn parse_span_id(s: &str) -> Option<SpanId> {
+    let mut id = [0; 8];
+    parse_hex(s, &mut id)?;
+    if id == [0; 8] {
+        return None;
+    }
+    Some(SpanId(id))
+}
+
+fn parse_hex(s: &str, buf: &mut [u8]) -> Option<()> {
+    if s.len() != buf.len() * 2 {
+        return None;
+    }
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = parse_u8(s.get(i * 2..i * 2 + 2)?)?;
+    }
+    Some(())
+}
+
+fn parse_u8(s: &str) -> Option<u8> {
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(s, 16).ok()
+}
+
+fn fmt_hex(bytes: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    for b in bytes {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+// === impl TraceId ===
+
+impl TraceId {
+    fn random() -> Self {
+        TraceId(rand::random())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_hex(&self.0, f)
+    }
+}
+
+// === impl SpanId ===
+
+impl SpanId {
+    fn random() -> Self {
+        SpanId(rand::random())
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_hex(&self.0, f)
+    }
+}
+
+// === impl Context ===
+
+impl Context {
+    /// Creates a new span, as a child of `parent` or as the root of a new
+    /// trace.
+    pub fn new(parent: Option<&Parent>, sampled: bool) -> Self {
+        match parent {
+            Some(p) => Context {
+                trace_id: p.trace_id,
+                span_id: SpanId::random(),
+                parent_id: Some(p.span_id),
+                sampled,
+                formats: match p.format {
+                    Format::TraceContext => &[Format::TraceContext],
+                    Format::B3Single => &[Format::B3Single],
+                    Format::B3Multi => &[Format::B3Multi],
+                },
+            },
+            None => Context {
+                trace_id: TraceId::random(),
+                span_id: SpanId::random(),
+                parent_id: None,
+                sampled,
+                formats: ALL_FORMATS,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(k, v) in pairs {
+            headers.insert(k, v.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn extracts_traceparent() {
+        let tp = format!("00-{}-{}-01", TRACE_ID, SPAN_ID);
+        let parent = extract(&headers(&[(TRACEPARENT, tp.as_str())])).expect("traceparent");
+        assert_eq!(parent.trace_id.to_string(), TRACE_ID);
+        assert_eq!(parent.span_id.to_string(), SPAN_ID);
+        assert_eq!(parent.sampled, Some(true));
+        assert_eq!(parent.format, Format::TraceContext);
+
+        for invalid in &[
+            format!("ff-{}-{}-01", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-01", "0".repeat(32), SPAN_ID),
+            format!("00-{}-{}-01-extra", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-1", TRACE_ID, SPAN_ID),
+            format!("00-{}-{}-01", TRACE_ID, "xyz"),
+        ] {
+            assert_eq!(
+                extract(&headers(&[(TRACEPARENT, invalid.as_str())])),
+                None,
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn extracts_b3() {
+        let single = format!("{}-{}-0", TRACE_ID, SPAN_ID);
+        let parent = extract(&headers(&[(B3, single.as_str())])).expect("b3");
+        assert_eq!(parent.sampled, Some(false));
+        assert_eq!(parent.format, Format::B3Single);
+
+        let parent = extract(&headers(&[
+            (B3_TRACE_ID, &TRACE_ID[16..]),
+            (B3_SPAN_ID, SPAN_ID),
+            (B3_FLAGS, "1"),
+        ]))
+        .expect("x-b3");
+        assert_eq!(
+            parent.trace_id.to_string(),
+            format!("{}{}", "0".repeat(16), &TRACE_ID[16..])
+        );
+        assert_eq!(parent.sampled, Some(true));
+        assert_eq!(parent.format, Format::B3Multi);
+
+        assert_eq!(extract(&headers(&[(B3, "1")])), None);
+    }
+
+    #[test]
+    fn injects_in_the_parents_format() {
+        let tp = format!("00-{}-{}-01", TRACE_ID, SPAN_ID);
+        let mut hs = headers(&[(TRACEPARENT, tp.as_str())]);
+        let ctx = Context::new(extract(&hs).as_ref(), true);
+        inject(&mut hs, &ctx);
+        assert_eq!(
+            hs.get(TRACEPARENT).unwrap(),
+            &*format!("00-{}-{}-01", TRACE_ID, ctx.span_id)
+        );
+        assert!(!hs.contains_key(B3_TRACE_ID));
+
+        let mut hs = headers(&[(B3_TRACE_ID, TRACE_ID), (B3_SPAN_ID, SPAN_ID)]);
+        let ctx = Context::new(extract(&hs).as_ref(), false);
+        inject(&mut hs, &ctx);
+        assert_eq!(hs.get(B3_PARENT_SPAN_ID).unwrap(), SPAN_ID);
+        assert_eq!(hs.get(B3_SAMPLED).unwrap(), "0");
+        assert!(!hs.contains_key(TRACEPARENT));
+
+        let mut hs = HeaderMap::new();
+        let root = Context::new(None, true);
+        inject(&mut hs, &root);
+        assert_eq!(extract(&hs).map(|p| p.span_id), Some(root.span_id));
+        assert!(hs.contains_key(B3_TRACE_ID));
+    }
+}
@@ -0,0 +1,150 @@
+//! Strips the proxy's own `l5d-*` headers from requests sent by clients that
+//! did not authenticate with TLS.
+//!
+//! Headers such as `l5d-dst-canonical` are set by the client's proxy and
+//! influence how the request is routed, so they may only be trusted when the
+//! client is known to be a meshed proxy. Other headers may be configured as
+//! trusted, so that they are kept regardless; `l5d-orig-proto` is trusted
+//! unless otherwise configured, since meshed proxies without TLS send it too.
+
+use futures::Poll;
+use http::{self, header::HeaderName};
+
+use proxy::server::Source;
+use svc;
+
+/// The prefix of the headers that are stripped.
+const PREFIX: &str = "l5d-";
+
+/// Configures which headers are trusted from unauthenticated clients.
+#[derive(Clone, Debug)]
+pub struct Config {
+    trusted: Vec<HeaderName>,
+}
+
+pub fn layer(config: Option<Config>) -> Layer {
+    Layer { config }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    config: Option<Config>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    config: Option<Config>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    config: Config,
+}
+
+// === impl Config ===
+
+impl Config {
+    pub fn new(trusted: Vec<HeaderName>) -> Self {
+        Self { trusted }
+    }
+
+    /// Removes all untrusted `l5d-*` headers.
+    fn strip(&self, headers: &mut http::HeaderMap) {
+        let names = headers
+            .keys()
+            .filter(|name| name.as_str().starts_with(PREFIX) && !self.trusted.contains(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            debug!("stripping {} header from unauthenticated client", name);
+            headers.remove(&name);
+        }
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Source, Source, M> for Layer
+where
+    M: svc::Stack<Source>,
+{
+    type Value = <Stack<M> as svc::Stack<Source>>::Value;
+    type Error = <Stack<M> as svc::Stack<Source>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Source> for Stack<M>
+where
+    M: svc::Stack<Source>,
+{
+    type Value = svc::Either<Service<M::Value>, M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, source: &Source) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(source)?;
+
+        match self.config {
+            Some(ref config) if source.tls_peer.is_none() => Ok(svc::Either::A(Service {
+                inner,
+                config: config.clone(),
+            })),
+            _ => Ok(svc::Either::B(inner)),
+        }
+    }
+}
+
+// === impl Service ===
+
+impl<S, A> svc::Service<http::Request<A>> for Service<S>
+where
+    S: svc::Service<http::Request<A>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        self.config.strip(req.headers_mut());
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_untrusted_headers() {
+        let config = Config::new(vec![HeaderName::from_static("l5d-request-id")]);
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            "l5d-dst-override",
+            "evil.ns.svc.cluster.local:80".parse().unwrap(),
+        );
+        headers.insert("l5d-orig-proto", "HTTP/1.1".parse().unwrap());
+        headers.insert("l5d-request-id", "abc".parse().unwrap());
+        headers.insert("x-l5d-other", "kept".parse().unwrap());
+
+        config.strip(&mut headers);
+
+        assert!(!headers.contains_key("l5d-dst-override"));
+        assert!(!headers.contains_key("l5d-orig-proto"));
+        assert_eq!(headers["l5d-request-id"], "abc");
+        assert_eq!(headers["x-l5d-other"], "kept");
+    }
+}
@@ -2,8 +2,11 @@
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
+pub mod authz;
 pub mod buffer;
 pub mod canonicalize;
+pub mod connection_limit;
+pub mod egress_policy;
 pub mod grpc;
 pub mod http;
 pub mod limit;
@@ -12,6 +15,7 @@ pub mod reconnect;
 pub mod resolve;
 pub mod server;
 mod tcp;
+pub mod tls_passthrough;
 
 pub use self::resolve::{Resolution, Resolve};
 pub use self::server::{Server, Source};
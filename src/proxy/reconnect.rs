@@ -7,6 +7,7 @@ use std::marker::PhantomData;
 use std::time::Duration;
 use tokio_timer::{clock, Delay};
 
+use backoff;
 use svc;
 
 // compiler doesn't seem to notice this used in where bounds below...
@@ -43,6 +44,10 @@ where
     backoff: Backoff,
     active_backoff: Option<Delay>,
 
+    /// The number of consecutive connect errors, used to determine the
+    /// duration of an exponential backoff.
+    failures: usize,
+
     /// Prevents logging repeated connect errors.
     ///
     /// Set back to false after a connect succeeds, to log about future errors.
@@ -53,6 +58,12 @@ where
 enum Backoff {
     None,
     Fixed(Duration),
+    /// Waits `min` after the first connect error, doubling the wait after each
+    /// consecutive error up to `max`.
+    Exponential {
+        min: Duration,
+        max: Duration,
+    },
 }
 
 // === impl Layer ===
@@ -71,6 +82,22 @@ impl<Req> Layer<Req> {
             _req: PhantomData,
         }
     }
+
+    /// Backs off exponentially, from `min` up to `max`, while connect errors
+    /// persist, so that endpoints that are down are not retried at full speed.
+    ///
+    /// When `max` is not greater than `min`, this is a fixed backoff.
+    pub fn with_exponential_backoff(self, min: Duration, max: Duration) -> Self {
+        let backoff = if max > min {
+            Backoff::Exponential { min, max }
+        } else {
+            Backoff::Fixed(min)
+        };
+        Self {
+            backoff,
+            _req: PhantomData,
+        }
+    }
 }
 
 impl<Req> Clone for Layer<Req> {
@@ -133,6 +160,7 @@ where
             target: target.clone(),
             backoff: self.backoff.clone(),
             active_backoff: None,
+            failures: 0,
             mute_connect_error_log: false,
         })
     }
@@ -153,6 +181,7 @@ where
             target: "test",
             backoff: Backoff::None,
             active_backoff: None,
+            failures: 0,
             mute_connect_error_log: false,
         }
     }
@@ -163,6 +192,13 @@ where
             ..self
         }
     }
+
+    fn with_exponential_backoff(self, min: Duration, max: Duration) -> Self {
+        Self {
+            backoff: Backoff::Exponential { min, max },
+            ..self
+        }
+    }
 }
 
 impl<T, N, S, Req> svc::Service<Req> for Service<T, N>
@@ -179,7 +215,7 @@ where
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         match self.backoff {
             Backoff::None => {}
-            Backoff::Fixed(_) => {
+            Backoff::Fixed(_) | Backoff::Exponential { .. } => {
                 if let Some(delay) = self.active_backoff.as_mut() {
                     match delay.poll() {
                         Ok(Async::NotReady) => return Ok(Async::NotReady),
@@ -197,6 +233,7 @@ where
             Ok(Async::NotReady) => Ok(Async::NotReady),
             Ok(ready) => {
                 self.mute_connect_error_log = false;
+                self.failures = 0;
                 Ok(ready)
             }
             Err(err) => {
@@ -215,10 +252,11 @@ where
                 //
                 // This future need not be polled immediately because the
                 // task is notified below.
-                self.active_backoff = match self.backoff {
-                    Backoff::None => None,
-                    Backoff::Fixed(ref wait) => Some(Delay::new(clock::now() + *wait)),
-                };
+                self.failures = self.failures.saturating_add(1);
+                self.active_backoff = self
+                    .backoff
+                    .wait(self.failures)
+                    .map(|wait| Delay::new(clock::now() + wait));
 
                 // The inner service is now idle and will renew its internal
                 // state on the next poll. Instead of doing this immediately,
@@ -238,6 +276,19 @@ where
     }
 }
 
+// === impl Backoff ===
+
+impl Backoff {
+    /// Returns how long to wait after `failures` consecutive connect errors.
+    fn wait(&self, failures: usize) -> Option<Duration> {
+        match *self {
+            Backoff::None => None,
+            Backoff::Fixed(wait) => Some(wait),
+            Backoff::Exponential { min, max } => Some(backoff::exponential(min, max, failures)),
+        }
+    }
+}
+
 impl<T, N> fmt::Debug for Service<T, N>
 where
     T: fmt::Debug,
@@ -338,4 +389,36 @@ mod tests {
 
         assert!(t0.elapsed() >= Duration::from_millis(200))
     }
+
+    #[test]
+    fn reconnects_with_exponential_backoff() {
+        let mock = NewService { fails: 3.into() };
+        let mut backoff = super::Service::for_test(mock)
+            .with_exponential_backoff(Duration::from_millis(50), Duration::from_millis(150));
+        let mut rt = Runtime::new().unwrap();
+
+        // Waits 50ms, then 100ms, then 150ms (capped) before the fourth
+        // attempt succeeds.
+        let t0 = time::Instant::now();
+        let f = future::poll_fn(|| backoff.poll_ready());
+        rt.block_on(f).unwrap();
+
+        assert!(t0.elapsed() >= Duration::from_millis(300))
+    }
+
+    #[test]
+    fn exponential_backoff_is_capped() {
+        let backoff = Backoff::Exponential {
+            min: Duration::from_millis(100),
+            max: Duration::from_secs(2),
+        };
+        assert_eq!(backoff.wait(1), Some(Duration::from_millis(100)));
+        assert_eq!(backoff.wait(2), Some(Duration::from_millis(200)));
+        assert_eq!(backoff.wait(5), Some(Duration::from_millis(1600)));
+        assert_eq!(backoff.wait(6), Some(Duration::from_secs(2)));
+        assert_eq!(
+            backoff.wait(::std::usize::MAX),
+            Some(Duration::from_secs(2))
+        );
+    }
 }
@@ -1,20 +1,28 @@
-use futures::{future::Either, Future};
+use futures::{
+    future::{self, Either},
+    Future,
+};
 use http;
 use hyper;
+use indexmap::IndexSet;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::{error, fmt};
+use tokio::io::{AsyncRead, AsyncWrite};
 
 use super::Accept;
 use app::config::H2Settings;
 use drain;
 use never::Never;
+use proxy::connection_limit::Limit;
 use proxy::http::{
     glue::{HttpBody, HyperServerSvc},
     upgrade,
 };
 use proxy::protocol::Protocol;
 use proxy::tcp;
+use proxy::tls_passthrough::Passthrough;
 use svc::{Service, Stack};
 use transport::{
     connect,
@@ -32,25 +40,34 @@ use transport::{
 ///
 /// 2.  A `Source` is created to describe the accepted connection.
 ///
-/// 3. An `A`-typed `Accept` is used to decorate the transport (i.e., for
-///    telemetry).
+/// 3. If the server is handling its maximum number of connections, the
+///    connection is closed once its protocol is detected. HTTP clients are
+///    first sent a `503 Service Unavailable` response.
+///
+/// 4. An `A`-typed `Accept` is used to decorate the transport (i.e., for
+///    telemetry). If the `Accept` cannot be built, e.g. because policy does
+///    not permit the connection, the connection is closed.
 ///
-/// 4. If the original destination address's port is not specified in
+/// 5. If the original destination address's port is not specified in
 ///    `disable_protocol_detection_ports`, then data received on the connection is
 ///    buffered until the server can determine whether the streams begins with a
 ///    HTTP/1 or HTTP/2 preamble.
 ///
-/// 5. If the stream is not determined to be HTTP, then the orignal destination
+/// 6. If the stream is not determined to be HTTP, then the orignal destination
 ///    address is used to transparently forward the TCP stream. A `C`-typed
 ///    `Connect` `Stack` is used to build a connection to the destination (i.e.,
-///    instrumented with telemetry, etc).
+///    instrumented with telemetry, etc). If the server has an authorization
+///    policy that does not permit the connection, it is closed instead. TLS
+///    streams that the proxy did not terminate may instead be passed through
+///    to another upstream, or closed, according to the server's passthrough
+///    rules.
 ///
-/// 6. Otherwise, an `R`-typed `Service` `Stack` is used to build a service that
+/// 7. Otherwise, an `R`-typed `Service` `Stack` is used to build a service that
 ///    can route HTTP  requests for the `Source`.
 pub struct Server<A, T, C, R, B>
 where
     // Prepares a server transport, e.g. with telemetry.
-    A: Stack<Source, Error = Never> + Clone,
+    A: Stack<Source> + Clone,
     A::Value: Accept<Connection>,
     // Used when forwarding a TCP stream (e.g. with telemetry, timeouts).
     T: From<SocketAddr>,
@@ -67,6 +84,10 @@ where
     accept: A,
     connect: ForwardConnect<T, C>,
     route: R,
+    authorize: Option<Arc<dyn AuthorizeForward + Send + Sync>>,
+    tls_passthrough: Option<Passthrough>,
+    proxy_protocol_ports: IndexSet<u16>,
+    connection_limit: Limit,
     log: ::logging::Server,
 }
 
@@ -80,6 +101,13 @@ pub struct Source {
     _p: (),
 }
 
+/// Determines whether a connection that is not HTTP may be forwarded, e.g.
+/// according to an authorization policy.
+pub trait AuthorizeForward {
+    fn authorize_forward(&self, source: &Source)
+        -> Result<(), Box<dyn error::Error + Send + Sync>>;
+}
+
 /// Establishes connections for forwarded connections.
 ///
 /// Fails to produce a `Connect` if a `Source`'s `orig_dst` is None.
@@ -193,9 +221,38 @@ impl Stack<Source> for () {
     }
 }
 
+impl<A, T, C, R, B> Clone for Server<A, T, C, R, B>
+where
+    A: Stack<Source> + Clone,
+    A::Value: Accept<Connection>,
+    T: From<SocketAddr>,
+    C: Stack<T, Error = Never> + Clone,
+    C::Value: connect::Connect,
+    R: Stack<Source, Error = Never> + Clone,
+    R::Value: Service<http::Request<HttpBody>, Response = http::Response<B>>,
+    B: hyper::body::Payload,
+{
+    fn clone(&self) -> Self {
+        Server {
+            drain_signal: self.drain_signal.clone(),
+            http: self.http.clone(),
+            listen_addr: self.listen_addr,
+            accept: self.accept.clone(),
+            connect: self.connect.clone(),
+            route: self.route.clone(),
+            authorize: self.authorize.clone(),
+            tls_passthrough: self.tls_passthrough.clone(),
+            proxy_protocol_ports: self.proxy_protocol_ports.clone(),
+            connection_limit: self.connection_limit.clone(),
+            log: self.log.clone(),
+        }
+    }
+}
+
 impl<A, T, C, R, B> Server<A, T, C, R, B>
 where
-    A: Stack<Source, Error = Never> + Clone,
+    A: Stack<Source> + Clone,
+    A::Error: fmt::Display,
     A::Value: Accept<Connection>,
     <A::Value as Accept<Connection>>::Io: fmt::Debug + Send + Peek + 'static,
     T: From<SocketAddr> + Send + 'static,
@@ -230,10 +287,66 @@ where
             accept,
             connect,
             route,
+            authorize: None,
+            tls_passthrough: None,
+            proxy_protocol_ports: IndexSet::new(),
+            connection_limit: Limit::default(),
             log,
         }
     }
 
+    /// Bounds the size of the buffer used to read HTTP/1 request heads.
+    ///
+    /// hyper responds with a `431 Request Header Fields Too Large` when a
+    /// request's head does not fit in this buffer. The buffer is never smaller
+    /// than 8KB, so smaller limits must also be enforced by the HTTP stack.
+    pub fn with_max_header_size(mut self, max: Option<usize>) -> Self {
+        // hyper refuses buffers smaller than this.
+        const MIN_HTTP1_BUF_SIZE: usize = 8192;
+
+        if let Some(max) = max {
+            self.http.max_buf_size(max.max(MIN_HTTP1_BUF_SIZE));
+        }
+        self
+    }
+
+    /// Closes connections that are not HTTP unless `authorize` permits them.
+    ///
+    /// HTTP requests are authorized by the route stack.
+    pub fn with_authorization<Z>(self, authorize: Option<Z>) -> Self
+    where
+        Z: AuthorizeForward + Send + Sync + 'static,
+    {
+        let authorize = authorize.map(|z| Arc::new(z) as Arc<dyn AuthorizeForward + Send + Sync>);
+        Self { authorize, ..self }
+    }
+
+    /// Decides where TLS connections that were not terminated by the proxy are
+    /// forwarded, by their SNI.
+    pub fn with_tls_passthrough(self, tls_passthrough: Option<Passthrough>) -> Self {
+        Self {
+            tls_passthrough,
+            ..self
+        }
+    }
+
+    /// Writes a PROXY protocol header, describing the client's address, to
+    /// each TCP connection forwarded to one of `proxy_protocol_ports`.
+    pub fn with_proxy_protocol_for(self, proxy_protocol_ports: IndexSet<u16>) -> Self {
+        Self {
+            proxy_protocol_ports,
+            ..self
+        }
+    }
+
+    /// Bounds the number of connections that are handled concurrently.
+    pub fn with_connection_limit(self, connection_limit: Limit) -> Self {
+        Self {
+            connection_limit,
+            ..self
+        }
+    }
+
     pub fn log(&self) -> &::logging::Server {
         &self.log
     }
@@ -255,6 +368,13 @@ where
 
         let log = self.log.clone().with_remote(remote_addr);
 
+        // A connection that is not permitted is held open only until its
+        // protocol is known.
+        let permit = self.connection_limit.acquire();
+        if permit.is_none() {
+            debug!("connection limit reached");
+        }
+
         let source = Source {
             remote: remote_addr,
             local: connection.local_addr().unwrap_or(self.listen_addr),
@@ -263,17 +383,38 @@ where
             _p: (),
         };
 
+        let proxy_protocol_header = orig_dst
+            .filter(|dst| self.proxy_protocol_ports.contains(&dst.port()))
+            .map(|dst| (remote_addr, dst));
+
         let io = match self.accept.make(&source) {
             Ok(accept) => accept.accept(connection),
-            // Matching never allows LLVM to eliminate this entirely.
-            Err(never) => match never {},
+            Err(e) => {
+                // Dropping the connection closes it.
+                debug!("refusing connection: {}", e);
+                return log.future(Either::B(Either::B(future::ok(()))));
+            }
         };
 
         if disable_protocol_detection {
             trace!("protocol detection disabled for {:?}", orig_dst);
-            let fwd = tcp::forward(io, &self.connect, &source);
-            let fut = self.drain_signal.clone().watch(fwd, |_| {});
-            return log.future(Either::B(fut));
+            if permit.is_none() {
+                return log.future(Either::B(Either::B(future::ok(()))));
+            }
+            if let Some(Err(e)) = self
+                .authorize
+                .as_ref()
+                .map(|a| a.authorize_forward(&source))
+            {
+                debug!("refusing connection: {}", e);
+                return log.future(Either::B(Either::B(future::ok(()))));
+            }
+            let fwd = tcp::forward(io, &self.connect, &source, proxy_protocol_header);
+            let fut = self.drain_signal.clone().watch(fwd, |_| {}).then(move |r| {
+                drop(permit);
+                r
+            });
+            return log.future(Either::B(Either::A(fut)));
         }
 
         let detect_protocol = io
@@ -287,69 +428,147 @@ where
         let mut http = self.http.clone();
         let route = self.route.clone();
         let connect = self.connect.clone();
+        let authorize = self.authorize.clone();
+        let tls_passthrough = self.tls_passthrough.clone();
         let drain_signal = self.drain_signal.clone();
         let log_clone = log.clone();
-        let serve = detect_protocol.and_then(move |(proto, io)| match proto {
-            None => Either::A({
-                trace!("did not detect protocol; forwarding TCP");
-                let fwd = tcp::forward(io, &connect, &source);
-                drain_signal.watch(fwd, |_| {})
-            }),
-
-            Some(proto) => Either::B(match proto {
-                Protocol::Http1 => Either::A({
-                    trace!("detected HTTP/1");
-                    match route.make(&source) {
-                        Err(never) => match never {},
-                        Ok(s) => {
-                            // Enable support for HTTP upgrades (CONNECT and websockets).
-                            let svc = upgrade::Service::new(
-                                s,
-                                drain_signal.clone(),
-                                log_clone.executor(),
-                            );
-                            let svc = HyperServerSvc::new(svc);
-                            let conn = http
-                                .http1_only(true)
-                                .serve_connection(io, svc)
-                                .with_upgrades();
-                            drain_signal
-                                .watch(conn, |conn| {
-                                    conn.graceful_shutdown();
-                                })
-                                .map(|_| ())
-                                .map_err(|e| trace!("http1 server error: {:?}", e))
+        let serve = detect_protocol.and_then(move |(proto, io)| {
+            let permit = match permit {
+                Some(permit) => permit,
+                None => return Either::A(unavailable(http, proto, io)),
+            };
+
+            let serve = match proto {
+                None => Either::A({
+                    trace!("did not detect protocol; forwarding TCP");
+                    let fwd = match authorize.as_ref().map(|a| a.authorize_forward(&source)) {
+                        Some(Err(e)) => {
+                            debug!("refusing connection: {}", e);
+                            Either::A(future::ok(()))
                         }
-                    }
+                        _ => {
+                            // TLS connections may be passed through to another
+                            // upstream than their original destination.
+                            let passthrough =
+                                tls_passthrough.as_ref().map(|p| p.route(io.peeked()));
+                            match passthrough.unwrap_or(Ok(None)) {
+                                Err(e) => {
+                                    debug!("refusing connection: {}", e);
+                                    Either::A(future::ok(()))
+                                }
+                                Ok(upstream) => {
+                                    let orig_dst = upstream.or(source.orig_dst);
+                                    let source = Source { orig_dst, ..source };
+                                    Either::B(tcp::forward(
+                                        io,
+                                        &connect,
+                                        &source,
+                                        proxy_protocol_header,
+                                    ))
+                                }
+                            }
+                        }
+                    };
+                    drain_signal.watch(fwd, |_| {})
                 }),
-                Protocol::Http2 => Either::B({
-                    trace!("detected HTTP/2");
-                    match route.make(&source) {
-                        Err(never) => match never {},
-                        Ok(s) => {
-                            let svc = HyperServerSvc::new(s);
-                            let conn = http
-                                .with_executor(log_clone.executor())
-                                .http2_only(true)
-                                .http2_initial_stream_window_size(
-                                    h2_settings.initial_stream_window_size,
-                                )
-                                .http2_initial_connection_window_size(
-                                    h2_settings.initial_connection_window_size,
-                                )
-                                .serve_connection(io, svc);
-                            drain_signal
-                                .watch(conn, |conn| {
-                                    conn.graceful_shutdown();
-                                })
-                                .map(|_| ())
-                                .map_err(|e| trace!("http2 server error: {:?}", e))
+
+                Some(proto) => Either::B(match proto {
+                    Protocol::Http1 => Either::A({
+                        trace!("detected HTTP/1");
+                        match route.make(&source) {
+                            Err(never) => match never {},
+                            Ok(s) => {
+                                // Enable support for HTTP upgrades (CONNECT and websockets).
+                                let svc = upgrade::Service::new(
+                                    s,
+                                    drain_signal.clone(),
+                                    log_clone.executor(),
+                                );
+                                let svc = HyperServerSvc::new(svc);
+                                let conn = http
+                                    .http1_only(true)
+                                    .serve_connection(io, svc)
+                                    .with_upgrades();
+                                drain_signal
+                                    .watch(conn, |conn| {
+                                        conn.graceful_shutdown();
+                                    })
+                                    .map(|_| ())
+                                    .map_err(|e| trace!("http1 server error: {:?}", e))
+                            }
+                        }
+                    }),
+                    Protocol::Http2 => Either::B({
+                        trace!("detected HTTP/2");
+                        match route.make(&source) {
+                            Err(never) => match never {},
+                            Ok(s) => {
+                                let svc = HyperServerSvc::new(s);
+                                let conn = http
+                                    .with_executor(log_clone.executor())
+                                    .http2_only(true)
+                                    .http2_initial_stream_window_size(
+                                        h2_settings.initial_stream_window_size,
+                                    )
+                                    .http2_initial_connection_window_size(
+                                        h2_settings.initial_connection_window_size,
+                                    )
+                                    .serve_connection(io, svc);
+                                drain_signal
+                                    .watch(conn, |conn| {
+                                        conn.graceful_shutdown();
+                                    })
+                                    .map(|_| ())
+                                    .map_err(|e| trace!("http2 server error: {:?}", e))
+                            }
                         }
-                    }
+                    }),
                 }),
-            }),
+            };
+
+            Either::B(serve.then(move |r| {
+                drop(permit);
+                r
+            }))
         });
 
         log.future(Either::A(serve))
     }
 }
+
+/// Responds to an HTTP request on a connection that was not permitted by the
+/// server's connection limit with a `503 Service Unavailable`, and closes the
+/// connection.
+fn unavailable<I>(
+    mut http: hyper::server::conn::Http,
+    proto: Option<Protocol>,
+    io: I,
+) -> impl Future<Item = (), Error = ()>
+where
+    I: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let svc = hyper::service::service_fn_ok(|_| {
+        http::Response::builder()
+            .status(http::StatusCode::SERVICE_UNAVAILABLE)
+            .body(hyper::Body::empty())
+            .expect("unavailable response must be valid")
+    });
+
+    match proto {
+        // Dropping the connection closes it.
+        None => Either::A(future::ok(())),
+        Some(Protocol::Http1) => Either::B(Either::A(
+            http.http1_only(true)
+                .keep_alive(false)
+                .serve_connection(io, svc)
+                .map_err(|e| trace!("http1 server error: {:?}", e)),
+        )),
+        Some(Protocol::Http2) => Either::B(Either::B({
+            let mut conn = http.http2_only(true).serve_connection(io, svc);
+            // The connection is closed once the requests that the client has
+            // already sent are answered.
+            conn.graceful_shutdown();
+            conn.map_err(|e| trace!("http2 server error: {:?}", e))
+        })),
+    }
+}
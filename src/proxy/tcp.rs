@@ -1,20 +1,25 @@
 use bytes::{Buf, BufMut};
 use futures::future::{self, Either};
 use futures::{Async, Future, Poll};
+use std::net::SocketAddr;
 use std::{fmt, io};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use svc;
-use transport::connect::Connect;
+use transport::{connect::Connect, proxy_protocol};
 
 /// Attempt to proxy the `server_io` stream to a `T`-typed target.
 ///
 /// If the trget is not valid, an error is logged and the server stream is
 /// dropped.
+///
+/// If a `(source, destination)` pair is provided, it is written to the client
+/// stream as a PROXY protocol header before any data is forwarded.
 pub(super) fn forward<I, C, T>(
     server_io: I,
     connect: &C,
     target: &T,
+    proxy_protocol_header: Option<(SocketAddr, SocketAddr)>,
 ) -> impl Future<Item = (), Error = ()> + Send + 'static
 where
     T: fmt::Debug,
@@ -37,6 +42,10 @@ where
     let fwd = connect
         .connect()
         .map_err(|e| info!("forward connect failure: {:?}", e))
+        .and_then(move |io| {
+            proxy_protocol::write_optional_header(io, proxy_protocol_header)
+                .map_err(|e| debug!("forward PROXY protocol header failure: {}", e))
+        })
         .and_then(move |io| {
             Duplex::new(server_io, io).map_err(|e| debug!("forward duplex complete: {}", e))
         });
@@ -0,0 +1,298 @@
+//! Routes inbound TLS connections that the proxy does not terminate.
+//!
+//! A connection that begins with a ClientHello for a name other than the
+//! proxy's own is not meshed, so it is forwarded without being decrypted. By
+//! default, these connections are forwarded to their original destination,
+//! like any other connection that is not HTTP. When passthrough rules are
+//! configured, a connection is only forwarded if its SNI matches a rule,
+//! either to its original destination or to the rule's upstream address;
+//! other TLS connections are closed.
+//!
+//! Connections whose ClientHello has no SNI are not affected.
+
+use indexmap::IndexMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::{error, fmt};
+
+use identity;
+use metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics};
+use transport::tls;
+
+metrics! {
+    tls_passthrough_connections_total: Counter {
+        "Total count of TLS connections forwarded or refused without being terminated, by SNI rule"
+    }
+}
+
+/// Permits TLS connections to be passed through for matching server names.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    sni: SniMatch,
+    upstream: Option<SocketAddr>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SniMatch {
+    /// Matches any server name.
+    Any,
+    /// Matches a lowercase name.
+    Exact(String),
+    /// Matches names that end with the given lowercase suffix, which starts
+    /// with a `.`.
+    Suffix(String),
+}
+
+pub fn new(rules: Option<Vec<Rule>>) -> (Passthrough, Report) {
+    let connections = Arc::new(Mutex::new(IndexMap::new()));
+    let passthrough = Passthrough {
+        rules: rules.map(Arc::new),
+        connections: connections.clone(),
+    };
+    (passthrough, Report(connections))
+}
+
+/// Decides where TLS connections are passed through to, recording each
+/// decision.
+#[derive(Clone, Debug)]
+pub struct Passthrough {
+    rules: Option<Arc<Vec<Rule>>>,
+    connections: Arc<Mutex<Connections>>,
+}
+
+/// Implements `FmtMetrics` to render prometheus-formatted connection counts.
+#[derive(Clone, Debug)]
+pub struct Report(Arc<Mutex<Connections>>);
+
+type Connections = IndexMap<ConnectionLabels, Counter>;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ConnectionLabels {
+    /// The SNI pattern of the rule that matched, or `None` if no rule did.
+    rule: Option<String>,
+    forwarded: bool,
+}
+
+/// Indicates that a TLS connection's SNI did not match any passthrough rule.
+#[derive(Clone, Debug)]
+pub struct Denied {
+    sni: identity::Name,
+}
+
+// === impl Rule ===
+
+/// Parses a rule from a server name pattern and an optional upstream
+/// address, e.g. `api.example.com`, `*.example.com=10.1.2.3:443`, or `*`.
+impl FromStr for Rule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, '=');
+        let sni = parts.next().ok_or(())?.parse()?;
+        let upstream = match parts.next() {
+            Some(addr) => Some(addr.parse().map_err(|_| ())?),
+            None => None,
+        };
+        Ok(Rule { sni, upstream })
+    }
+}
+
+// === impl SniMatch ===
+
+impl SniMatch {
+    fn matches(&self, sni: &str) -> bool {
+        match *self {
+            SniMatch::Any => true,
+            SniMatch::Exact(ref name) => sni.eq_ignore_ascii_case(name),
+            SniMatch::Suffix(ref suffix) => {
+                sni.len() > suffix.len()
+                    && sni[sni.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            }
+        }
+    }
+}
+
+impl FromStr for SniMatch {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is_valid = |name: &str| identity::Name::from_hostname(name.as_bytes()).is_ok();
+        if s == "*" {
+            Ok(SniMatch::Any)
+        } else if s.starts_with("*.") && is_valid(&s[2..]) {
+            Ok(SniMatch::Suffix(s[1..].to_ascii_lowercase()))
+        } else if is_valid(s) {
+            Ok(SniMatch::Exact(s.to_ascii_lowercase()))
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl fmt::Display for SniMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SniMatch::Any => f.pad("*"),
+            SniMatch::Exact(ref name) => f.pad(name),
+            SniMatch::Suffix(ref suffix) => write!(f, "*{}", suffix),
+        }
+    }
+}
+
+// === impl Passthrough ===
+
+impl Passthrough {
+    /// Decides where a connection that is not HTTP is forwarded, given the
+    /// bytes peeked from it.
+    ///
+    /// `Ok(Some(addr))` is returned when a TLS connection is passed through to
+    /// a rule's upstream address, and `Ok(None)` when the connection is
+    /// forwarded to its original destination.
+    pub fn route(&self, peeked: &[u8]) -> Result<Option<SocketAddr>, Denied> {
+        let sni = match tls::client_hello_sni(peeked) {
+            Some(sni) => sni,
+            None => return Ok(None),
+        };
+
+        let rule = match self.rules {
+            None => {
+                debug!("passing through TLS connection for {}", sni.as_ref());
+                self.record(None, true);
+                return Ok(None);
+            }
+            Some(ref rules) => rules.iter().find(|r| r.sni.matches(sni.as_ref())),
+        };
+
+        match rule {
+            Some(rule) => {
+                debug!(
+                    "passing through TLS connection for {} to {:?}",
+                    sni.as_ref(),
+                    rule.upstream
+                );
+                self.record(Some(rule.sni.to_string()), true);
+                Ok(rule.upstream)
+            }
+            None => {
+                self.record(None, false);
+                Err(Denied { sni })
+            }
+        }
+    }
+
+    fn record(&self, rule: Option<String>, forwarded: bool) {
+        if let Ok(mut connections) = self.connections.lock() {
+            connections
+                .entry(ConnectionLabels { rule, forwarded })
+                .or_insert_with(Counter::default)
+                .incr();
+        }
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let connections = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(lock) => lock,
+        };
+
+        if connections.is_empty() {
+            return Ok(());
+        }
+
+        tls_passthrough_connections_total.fmt_help(f)?;
+        for (labels, count) in connections.iter() {
+            count.fmt_metric_labeled(f, tls_passthrough_connections_total.name, labels)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for ConnectionLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = if self.forwarded { "forward" } else { "deny" };
+        match self.rule {
+            Some(ref sni) => write!(f, "sni=\"{}\",action=\"{}\"", sni, action),
+            None => write!(f, "sni=\"\",action=\"{}\"", action),
+        }
+    }
+}
+
+// === impl Denied ===
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TLS passthrough is not permitted for {}",
+            self.sni.as_ref()
+        )
+    }
+}
+
+impl error::Error for Denied {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ClientHello for `example.com`.
+    static EXAMPLE_COM: &[u8] =
+        include_bytes!("../transport/tls/testdata/example-com-client-hello.bin");
+
+    fn policy(rules: Option<&[&str]>) -> (Passthrough, Report) {
+        new(rules.map(|rules| rules.iter().map(|r| r.parse().unwrap()).collect()))
+    }
+
+    #[test]
+    fn forwards_to_original_dst_without_rules() {
+        let (passthrough, report) = policy(None);
+        assert_eq!(passthrough.route(EXAMPLE_COM).unwrap(), None);
+        assert_eq!(passthrough.route(b"SSH-2.0-OpenSSH_7.9\r\n").unwrap(), None);
+
+        let connections = report.0.lock().unwrap();
+        let labels = ConnectionLabels {
+            rule: None,
+            forwarded: true,
+        };
+        assert_eq!(connections.get(&labels).map(|c| c.value()), Some(1));
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let (passthrough, _) = policy(Some(&["*.example.com", "example.com=10.1.2.3:443"]));
+        assert_eq!(
+            passthrough.route(EXAMPLE_COM).unwrap(),
+            Some("10.1.2.3:443".parse().unwrap())
+        );
+
+        let (passthrough, report) = policy(Some(&["example.org", "EXAMPLE.com"]));
+        assert_eq!(passthrough.route(EXAMPLE_COM).unwrap(), None);
+
+        let (denied, _) = policy(Some(&["*.example.com"]));
+        assert!(denied.route(EXAMPLE_COM).is_err());
+        assert_eq!(denied.route(b"GET / HTTP/1.1\r\n\r\n").unwrap(), None);
+
+        let connections = report.0.lock().unwrap();
+        let labels = ConnectionLabels {
+            rule: Some("example.com".into()),
+            forwarded: true,
+        };
+        assert_eq!(connections.get(&labels).map(|c| c.value()), Some(1));
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert!("*".parse::<Rule>().is_ok());
+        assert!("*.example.com=[2001:db8::1]:443".parse::<Rule>().is_ok());
+        assert!("example.com=10.1.2.3".parse::<Rule>().is_err());
+        assert!("example.com.".parse::<Rule>().is_err());
+        assert!("*example.com".parse::<Rule>().is_err());
+        assert!("".parse::<Rule>().is_err());
+    }
+}
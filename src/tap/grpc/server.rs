@@ -12,7 +12,7 @@ use tower_grpc::{self as grpc, Response};
 use api::{http_types, pb_duration, tap as api};
 
 use super::match_::Match;
-use proxy::http::HasH2Reason;
+use proxy::http::{request_id, HasH2Reason};
 use tap::{iface, Inspect};
 use Conditional;
 
@@ -544,6 +544,9 @@ fn base_event<B, I: Inspect>(req: &http::Request<B>, inspect: &I) -> api::TapEve
                 m.labels
                     .insert("client_id".to_owned(), id.as_ref().to_owned());
             }
+            if let Some(id) = request_id::get(req) {
+                m.labels.insert("request_id".to_owned(), id.to_owned());
+            }
             Some(m)
         },
         destination: inspect.dst_addr(req).as_ref().map(|a| a.into()),
@@ -0,0 +1,358 @@
+//! Attributes heap allocations to the proxy's subsystems.
+//!
+//! When the proxy is built with the `alloc-audit` feature, a counting global
+//! allocator records the number and size of allocations made while each
+//! subsystem is being polled. Subsystems are tagged by wrapping their stacks
+//! with `layer`; allocations are attributed to the innermost tagged service
+//! that is being polled on the current thread, or to `other`.
+//!
+//! The number of requests that enter each tagged subsystem is also counted, so
+//! that allocations per request may be computed from
+//! `alloc_audit_allocations_total / alloc_audit_requests_total`.
+//!
+//! Without the feature, the layer only forwards to its inner service and no
+//! metrics are reported.
+
+use futures::{Future, Poll};
+use std::fmt;
+
+use super::metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics};
+use svc;
+
+metrics! {
+    alloc_audit_requests_total: Counter {
+        "Total number of requests that entered a subsystem"
+    },
+    alloc_audit_allocations_total: Counter {
+        "Total number of heap allocations made by a subsystem"
+    },
+    alloc_audit_allocated_bytes_total: Counter {
+        "Total number of bytes allocated by a subsystem"
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Subsystem {
+    Other,
+    Router,
+    Balance,
+    Telemetry,
+    Client,
+}
+
+const SUBSYSTEMS: [Subsystem; 5] = [
+    Subsystem::Other,
+    Subsystem::Router,
+    Subsystem::Balance,
+    Subsystem::Telemetry,
+    Subsystem::Client,
+];
+
+/// Renders allocation counts, when the `alloc-audit` feature is enabled.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Report(());
+
+pub fn layer(subsystem: Subsystem) -> Layer {
+    Layer { subsystem }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Layer {
+    subsystem: Subsystem,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    subsystem: Subsystem,
+}
+
+#[derive(Clone, Debug)]
+pub struct Service<S> {
+    inner: S,
+    subsystem: Subsystem,
+}
+
+pub struct ResponseFuture<F> {
+    inner: F,
+    subsystem: Subsystem,
+}
+
+// === impl Subsystem ===
+
+impl Subsystem {
+    fn name(self) -> &'static str {
+        match self {
+            Subsystem::Other => "other",
+            Subsystem::Router => "router",
+            Subsystem::Balance => "balance",
+            Subsystem::Telemetry => "telemetry",
+            Subsystem::Client => "client",
+        }
+    }
+}
+
+impl FmtLabels for Subsystem {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "subsystem=\"{}\"", self.name())
+    }
+}
+
+// === impl Layer ===
+
+impl<T, M> svc::Layer<T, T, M> for Layer
+where
+    M: svc::Stack<T>,
+{
+    type Value = <Stack<M> as svc::Stack<T>>::Value;
+    type Error = <Stack<M> as svc::Stack<T>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            subsystem: self.subsystem,
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<T, M> svc::Stack<T> for Stack<M>
+where
+    M: svc::Stack<T>,
+{
+    type Value = Service<M::Value>;
+    type Error = M::Error;
+
+    fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
+        let inner = self.inner.make(target)?;
+        Ok(Service {
+            inner,
+            subsystem: self.subsystem,
+        })
+    }
+}
+
+// === impl Service ===
+
+impl<S, Req> svc::Service<Req> for Service<S>
+where
+    S: svc::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        let _scope = counting::enter(self.subsystem);
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let _scope = counting::enter(self.subsystem);
+        counting::request(self.subsystem);
+        ResponseFuture {
+            inner: self.inner.call(req),
+            subsystem: self.subsystem,
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F: Future> Future for ResponseFuture<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let _scope = counting::enter(self.subsystem);
+        self.inner.poll()
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = match counting::snapshot() {
+            Some(counts) => counts,
+            None => return Ok(()),
+        };
+
+        alloc_audit_requests_total.fmt_help(f)?;
+        for (subsystem, c) in SUBSYSTEMS.iter().zip(counts.iter()) {
+            let requests = Counter::from(c.requests);
+            requests.fmt_metric_labeled(f, alloc_audit_requests_total.name, subsystem)?;
+        }
+
+        alloc_audit_allocations_total.fmt_help(f)?;
+        for (subsystem, c) in SUBSYSTEMS.iter().zip(counts.iter()) {
+            let allocations = Counter::from(c.allocations);
+            allocations.fmt_metric_labeled(f, alloc_audit_allocations_total.name, subsystem)?;
+        }
+
+        alloc_audit_allocated_bytes_total.fmt_help(f)?;
+        for (subsystem, c) in SUBSYSTEMS.iter().zip(counts.iter()) {
+            let bytes = Counter::from(c.bytes);
+            bytes.fmt_metric_labeled(f, alloc_audit_allocated_bytes_total.name, subsystem)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Counts {
+    requests: u64,
+    allocations: u64,
+    bytes: u64,
+}
+
+#[cfg(feature = "alloc-audit")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{Counts, Subsystem, SUBSYSTEMS};
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    struct Counting;
+
+    /// Counters for each of `SUBSYSTEMS`.
+    struct Counters {
+        requests: [AtomicUsize; 5],
+        allocations: [AtomicUsize; 5],
+        bytes: [AtomicUsize; 5],
+    }
+
+    static COUNTERS: Counters = Counters {
+        requests: [
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+        ],
+        allocations: [
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+        ],
+        bytes: [
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+            AtomicUsize::new(0),
+        ],
+    };
+
+    thread_local! {
+        /// The index of the subsystem that is being polled on this thread.
+        static CURRENT: Cell<usize> = Cell::new(0);
+    }
+
+    /// Restores the previously-current subsystem when dropped.
+    pub struct Scope(usize);
+
+    pub fn enter(subsystem: Subsystem) -> Scope {
+        let prior = CURRENT
+            .try_with(|c| c.replace(index(subsystem)))
+            .unwrap_or(0);
+        Scope(prior)
+    }
+
+    pub fn request(subsystem: Subsystem) {
+        COUNTERS.requests[index(subsystem)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot() -> Option<[Counts; 5]> {
+        let mut counts = [Counts::default(); 5];
+        for (i, _) in SUBSYSTEMS.iter().enumerate() {
+            counts[i] = Counts {
+                requests: COUNTERS.requests[i].load(Ordering::Relaxed) as u64,
+                allocations: COUNTERS.allocations[i].load(Ordering::Relaxed) as u64,
+                bytes: COUNTERS.bytes[i].load(Ordering::Relaxed) as u64,
+            };
+        }
+        Some(counts)
+    }
+
+    impl Drop for Scope {
+        fn drop(&mut self) {
+            let _ = CURRENT.try_with(|c| c.set(self.0));
+        }
+    }
+
+    fn index(subsystem: Subsystem) -> usize {
+        subsystem as usize
+    }
+
+    fn record(size: usize) {
+        // The thread-local may be unavailable while a thread is torn down.
+        let i = CURRENT.try_with(|c| c.get()).unwrap_or(0);
+        COUNTERS.allocations[i].fetch_add(1, Ordering::Relaxed);
+        COUNTERS.bytes[i].fetch_add(size, Ordering::Relaxed);
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            record(layout.size());
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            record(layout.size());
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            record(new_size);
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+}
+
+#[cfg(not(feature = "alloc-audit"))]
+mod counting {
+    use super::{Counts, Subsystem};
+
+    pub struct Scope;
+
+    pub fn enter(_: Subsystem) -> Scope {
+        Scope
+    }
+
+    pub fn request(_: Subsystem) {}
+
+    pub fn snapshot() -> Option<[Counts; 5]> {
+        None
+    }
+}
+
+#[cfg(all(test, feature = "alloc-audit"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_allocations_to_entered_subsystem() {
+        let allocations = || counting::snapshot().unwrap()[Subsystem::Router as usize].allocations;
+        let before = allocations();
+        {
+            let _scope = counting::enter(Subsystem::Router);
+            let buf = Vec::<u8>::with_capacity(64);
+            assert_eq!(buf.capacity(), 64);
+        }
+        assert!(allocations() > before);
+    }
+}
@@ -0,0 +1,169 @@
+//! Reports the global allocator's memory usage.
+//!
+//! When the proxy is built with the `jemalloc` feature, jemalloc is used as
+//! the global allocator and its statistics are reported as gauges. Other
+//! allocators may be reported by implementing `AllocatorStats`.
+//!
+//! The difference between the bytes in pages that the allocator has made
+//! active and the bytes that are allocated is reported as fragmentation, so
+//! that growth in the heap can be distinguished from growth in its overhead.
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use super::metrics::{FmtMetrics, Gauge};
+
+metrics! {
+    allocator_allocated_bytes: Gauge {
+        "Number of bytes allocated by the proxy"
+    },
+    allocator_active_bytes: Gauge {
+        "Number of bytes in pages that the allocator has made active"
+    },
+    allocator_resident_bytes: Gauge {
+        "Number of bytes in pages that the allocator has mapped into physical memory"
+    },
+    allocator_fragmentation_bytes: Gauge {
+        "Number of bytes in active pages that are not allocated"
+    }
+}
+
+#[cfg(all(feature = "jemalloc", feature = "alloc-audit"))]
+compile_error!("the `jemalloc` and `alloc-audit` features each set a global allocator");
+
+/// Implemented by allocators that can report their memory usage.
+pub trait AllocatorStats: fmt::Debug + Send + Sync {
+    fn stats(&self) -> io::Result<Stats>;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub allocated: u64,
+    pub active: u64,
+    pub resident: u64,
+}
+
+/// Renders the statistics of an allocator, if one is configured.
+#[derive(Clone, Debug)]
+pub struct Report(Option<Arc<dyn AllocatorStats>>);
+
+// === impl Report ===
+
+impl Report {
+    pub fn new<A: AllocatorStats + 'static>(allocator: A) -> Self {
+        Report(Some(Arc::new(allocator)))
+    }
+}
+
+/// Reports jemalloc's statistics when the `jemalloc` feature is enabled, and
+/// nothing otherwise.
+impl Default for Report {
+    #[cfg(feature = "jemalloc")]
+    fn default() -> Self {
+        Self::new(jemalloc::Jemalloc)
+    }
+
+    #[cfg(not(feature = "jemalloc"))]
+    fn default() -> Self {
+        Report(None)
+    }
+}
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let allocator = match self.0 {
+            Some(ref a) => a,
+            None => return Ok(()),
+        };
+        let stats = match allocator.stats() {
+            Ok(stats) => stats,
+            Err(e) => {
+                warn!("failed to read allocator stats: {}", e);
+                return Ok(());
+            }
+        };
+
+        allocator_allocated_bytes.fmt_help(f)?;
+        allocator_allocated_bytes.fmt_metric(f, Gauge::from(stats.allocated))?;
+
+        allocator_active_bytes.fmt_help(f)?;
+        allocator_active_bytes.fmt_metric(f, Gauge::from(stats.active))?;
+
+        allocator_resident_bytes.fmt_help(f)?;
+        allocator_resident_bytes.fmt_metric(f, Gauge::from(stats.resident))?;
+
+        allocator_fragmentation_bytes.fmt_help(f)?;
+        allocator_fragmentation_bytes.fmt_metric(f, Gauge::from(stats.fragmentation()))?;
+
+        Ok(())
+    }
+}
+
+// === impl Stats ===
+
+impl Stats {
+    pub fn fragmentation(&self) -> u64 {
+        self.active.saturating_sub(self.allocated)
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+mod jemalloc {
+    extern crate jemalloc_ctl;
+    extern crate jemallocator;
+
+    use std::io;
+
+    use self::jemalloc_ctl::{epoch, stats};
+    use super::{AllocatorStats, Stats};
+
+    #[global_allocator]
+    static ALLOCATOR: jemallocator::Jemalloc = jemallocator::Jemalloc;
+
+    #[derive(Copy, Clone, Debug)]
+    pub struct Jemalloc;
+
+    impl AllocatorStats for Jemalloc {
+        fn stats(&self) -> io::Result<Stats> {
+            // jemalloc's statistics are cached until the epoch is advanced.
+            epoch::advance().map_err(to_io)?;
+            Ok(Stats {
+                allocated: stats::allocated::read().map_err(to_io)? as u64,
+                active: stats::active::read().map_err(to_io)? as u64,
+                resident: stats::resident::read().map_err(to_io)? as u64,
+            })
+        }
+    }
+
+    fn to_io(e: jemalloc_ctl::Error) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed(Stats);
+
+    impl AllocatorStats for Fixed {
+        fn stats(&self) -> io::Result<Stats> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn reports_fragmentation() {
+        let report = Report::new(Fixed(Stats {
+            allocated: 100,
+            active: 150,
+            resident: 200,
+        }));
+        let out = report.as_display().to_string();
+        assert!(out.contains("allocator_allocated_bytes 100\n"));
+        assert!(out.contains("allocator_resident_bytes 200\n"));
+        assert!(out.contains("allocator_fragmentation_bytes 50\n"));
+    }
+}
@@ -0,0 +1,225 @@
+//! Parses the Prometheus text exposition that the proxy's metrics are rendered
+//! as, so that they may be pushed to other sinks.
+
+use std::collections::HashMap;
+use std::mem;
+
+/// The type of a metric family, from its `# TYPE` line.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    /// Families without a type are treated as gauges.
+    Gauge,
+    Histogram,
+}
+
+/// A single sample of a metric family.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample<'a> {
+    pub family: &'a str,
+    pub help: &'a str,
+    pub kind: Kind,
+    /// The sample's name, which differs from the family's name for a
+    /// histogram's `_bucket`, `_count`, and `_sum` series.
+    pub name: &'a str,
+    /// The sample's name and labels, which identify it across expositions.
+    pub series: &'a str,
+    pub labels: Vec<(&'a str, String)>,
+    pub value: f64,
+}
+
+/// Tracks the values of counter and histogram series across expositions, so
+/// that the change in each series may be pushed.
+///
+/// Only the series in the latest exposition are remembered, so that series
+/// that have been dropped don't accumulate.
+#[derive(Debug, Default)]
+pub struct Deltas {
+    /// The value of each series in the previous exposition.
+    last: HashMap<String, f64>,
+    /// The value of each series in the current exposition.
+    next: HashMap<String, f64>,
+}
+
+/// Parses the samples in `exposition`, skipping lines that can't be parsed.
+pub fn parse(exposition: &str) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    let mut family = ("", "", Kind::Gauge);
+
+    for line in exposition.lines() {
+        if line.starts_with("# HELP ") {
+            let mut parts = line["# HELP ".len()..].splitn(2, ' ');
+            let name = parts.next().unwrap_or("");
+            family = (name, parts.next().unwrap_or(""), Kind::Gauge);
+            continue;
+        }
+        if line.starts_with("# TYPE ") {
+            let mut parts = line["# TYPE ".len()..].splitn(2, ' ');
+            let name = parts.next().unwrap_or("");
+            if name != family.0 {
+                family = (name, "", Kind::Gauge);
+            }
+            family.2 = match parts.next() {
+                Some("counter") => Kind::Counter,
+                Some("histogram") => Kind::Histogram,
+                _ => Kind::Gauge,
+            };
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_sample(line) {
+            Some((series, value)) => {
+                let (name, labels) = match series.find('{') {
+                    Some(i) => (&series[..i], parse_labels(&series[i + 1..series.len() - 1])),
+                    None => (series, Vec::new()),
+                };
+                let (family, help, kind) = if !family.0.is_empty() && name.starts_with(family.0) {
+                    family
+                } else {
+                    (name, "", Kind::Gauge)
+                };
+                samples.push(Sample {
+                    family,
+                    help,
+                    kind,
+                    name,
+                    series,
+                    labels,
+                    value,
+                });
+            }
+            None => debug!("failed to parse metric: {}", line),
+        }
+    }
+
+    samples
+}
+
+// === impl Deltas ===
+
+impl Deltas {
+    /// Returns the change in `sample`'s value since the previous exposition.
+    pub fn delta(&mut self, sample: &Sample) -> f64 {
+        self.next.insert(sample.series.to_owned(), sample.value);
+        // Counters are reset when the scopes that hold them are dropped, in
+        // which case the whole value is new.
+        match self.last.get(sample.series) {
+            Some(&last) if last <= sample.value => sample.value - last,
+            _ => sample.value,
+        }
+    }
+
+    /// Completes the current exposition, forgetting the series that were not
+    /// in it.
+    pub fn finish(&mut self) {
+        self.last = mem::replace(&mut self.next, HashMap::new());
+    }
+}
+
+/// Splits a sample line into its series, i.e. its name and labels, and its
+/// value.
+fn parse_sample(line: &str) -> Option<(&str, f64)> {
+    let i = line.rfind(' ')?;
+    let series = &line[..i];
+    if series.contains('{') && !series.ends_with('}') {
+        return None;
+    }
+    let value = line[i + 1..].parse().ok()?;
+    Some((series, value))
+}
+
+/// Parses `k="v",...` label pairs, unescaping their values.
+fn parse_labels(s: &str) -> Vec<(&str, String)> {
+    let mut labels = Vec::new();
+    let mut rest = s;
+    while let Some(eq) = rest.find("=\"") {
+        let key = rest[..eq].trim_start_matches(',');
+        let mut value = String::new();
+        let mut chars = rest[eq + 2..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    end = Some(eq + 2 + i + 1);
+                    break;
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, c)) => value.push(c),
+                    None => {}
+                },
+                c => value.push(c),
+            }
+        }
+        labels.push((key, value));
+        match end {
+            Some(end) => rest = &rest[end..],
+            None => break,
+        }
+    }
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_families() {
+        let samples = parse(
+            "# HELP request_total Total count of HTTP requests.\n\
+             # TYPE request_total counter\n\
+             request_total{route=\"GET \\\"/books\\\"\",le=\"1\"} 3\n\
+             # TYPE response_latency_ms histogram\n\
+             response_latency_ms_sum 12\n\
+             process_start_time_seconds 1551398400\n",
+        );
+
+        assert_eq!(
+            samples[0],
+            Sample {
+                family: "request_total",
+                help: "Total count of HTTP requests.",
+                kind: Kind::Counter,
+                name: "request_total",
+                series: "request_total{route=\"GET \\\"/books\\\"\",le=\"1\"}",
+                labels: vec![
+                    ("route", "GET \"/books\"".to_owned()),
+                    ("le", "1".to_owned())
+                ],
+                value: 3.0,
+            }
+        );
+        assert_eq!(samples[1].family, "response_latency_ms");
+        assert_eq!(samples[1].name, "response_latency_ms_sum");
+        assert_eq!(samples[1].kind, Kind::Histogram);
+        assert_eq!(samples[2].family, "process_start_time_seconds");
+        assert_eq!(samples[2].kind, Kind::Gauge);
+    }
+
+    #[test]
+    fn tracks_deltas_of_current_series() {
+        fn sample(series: &str, value: f64) -> Sample {
+            parse(&format!("# TYPE {0} counter\n{0} {1}\n", series, value))
+                .pop()
+                .expect("sample must parse")
+        }
+
+        let mut deltas = Deltas::default();
+        assert_eq!(deltas.delta(&sample("a_total", 3.0)), 3.0);
+        assert_eq!(deltas.delta(&sample("b_total", 5.0)), 5.0);
+        deltas.finish();
+
+        assert_eq!(deltas.delta(&sample("a_total", 4.0)), 1.0);
+        deltas.finish();
+        assert_eq!(deltas.last.len(), 1);
+
+        // `b_total` was dropped, so its whole value is new when it returns.
+        assert_eq!(deltas.delta(&sample("b_total", 7.0)), 7.0);
+        // Counters that are reset start over.
+        assert_eq!(deltas.delta(&sample("a_total", 2.0)), 2.0);
+    }
+}
@@ -1,6 +1,12 @@
 use metrics;
 
+pub mod alloc_audit;
+pub mod alloc_stats;
 mod errno;
+mod exposition;
+pub mod otlp;
 pub mod process;
+pub mod statsd;
+pub mod tasks;
 
 pub use self::errno::Errno;
@@ -0,0 +1,586 @@
+//! Pushes metrics to an OpenTelemetry collector over OTLP/gRPC.
+//!
+//! The proxy's metrics are rendered exactly as they are served to Prometheus
+//! and translated to OTLP metrics each time the exporter pushes. Counters and
+//! histograms are sent with delta temporality, i.e. as the change since the
+//! previous push; gauges are sent as they are.
+
+use futures::{Async, Future, Poll, Stream};
+use http;
+use indexmap::IndexMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_timer::{clock, Interval};
+use tower_grpc::{self as grpc, generic::client::GrpcService, BoxBody};
+
+use super::exposition::{self, Deltas, Kind, Sample};
+use super::metrics::FmtMetrics;
+
+const EXPORT_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+
+/// Periodically pushes metrics to an OTLP collector.
+pub struct Exporter<M, T>
+where
+    T: GrpcService<BoxBody>,
+    T::ResponseBody: grpc::Body,
+{
+    metrics: M,
+    client: grpc::client::Grpc<T>,
+    interval: Interval,
+    resource: proto::Resource,
+    encoder: Encoder,
+    state: State<T>,
+}
+
+enum State<T>
+where
+    T: GrpcService<BoxBody>,
+    T::ResponseBody: grpc::Body,
+{
+    Waiting,
+    ShouldExport,
+    Pending(
+        grpc::client::unary::ResponseFuture<
+            proto::ExportMetricsServiceResponse,
+            T::Future,
+            T::ResponseBody,
+        >,
+    ),
+}
+
+/// Translates Prometheus-formatted samples to OTLP metrics.
+#[derive(Debug)]
+struct Encoder {
+    counters: Deltas,
+    /// When metrics were last pushed, which starts each delta's interval.
+    last_push: SystemTime,
+}
+
+/// The samples of a metric family, grouped into data points.
+struct Family<'a> {
+    help: &'a str,
+    kind: Kind,
+    numbers: Vec<proto::NumberDataPoint>,
+    /// Histogram data points, keyed by their labels other than `le`.
+    histograms: IndexMap<String, Histogram>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    attributes: Vec<proto::KeyValue>,
+    bounds: Vec<f64>,
+    /// The change in each cumulative bucket, including the `+Inf` bucket.
+    buckets: Vec<f64>,
+    count: f64,
+    sum: f64,
+}
+
+// === impl Exporter ===
+
+impl<M, T> Exporter<M, T>
+where
+    M: FmtMetrics,
+    T: GrpcService<BoxBody>,
+    T::ResponseBody: grpc::Body,
+{
+    pub fn new(svc: T, metrics: M, push_interval: Duration, service_name: String) -> Self {
+        info!(
+            "pushing metrics to an OTLP collector every {:?}",
+            push_interval
+        );
+        Self {
+            metrics,
+            client: grpc::client::Grpc::new(svc),
+            interval: Interval::new(clock::now() + push_interval, push_interval),
+            resource: proto::Resource {
+                attributes: vec![key_value("service.name", service_name)],
+            },
+            encoder: Encoder {
+                counters: Deltas::default(),
+                last_push: SystemTime::now(),
+            },
+            state: State::Waiting,
+        }
+    }
+}
+
+impl<M, T> Future for Exporter<M, T>
+where
+    M: FmtMetrics,
+    T: GrpcService<BoxBody>,
+    T::ResponseBody: grpc::Body,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            self.state = match self.state {
+                State::Waiting => match self.interval.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(_)) => State::ShouldExport,
+                    Err(e) => {
+                        error!("metrics push timer failed: {}", e);
+                        return Err(());
+                    }
+                },
+                State::ShouldExport => match self.client.poll_ready() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => {
+                        let exposition = self.metrics.as_display().to_string();
+                        let metrics = self.encoder.encode(&exposition, SystemTime::now());
+                        let req = grpc::Request::new(proto::ExportMetricsServiceRequest {
+                            resource_metrics: vec![proto::ResourceMetrics {
+                                resource: Some(self.resource.clone()),
+                                scope_metrics: vec![proto::ScopeMetrics {
+                                    scope: Some(proto::InstrumentationScope {
+                                        name: "linkerd2-proxy".to_owned(),
+                                        version: env!("CARGO_PKG_VERSION").to_owned(),
+                                    }),
+                                    metrics,
+                                }],
+                            }],
+                        });
+                        let path = http::uri::PathAndQuery::from_static(EXPORT_PATH);
+                        State::Pending(self.client.unary(req, path))
+                    }
+                    Err(e) => {
+                        warn!("metrics collector is unavailable: {:?}", e);
+                        State::Waiting
+                    }
+                },
+                State::Pending(ref mut rsp) => {
+                    match rsp.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(_)) => trace!("pushed metrics"),
+                        // The deltas are lost, rather than being retried, so
+                        // that an unavailable collector doesn't accumulate
+                        // state.
+                        Err(e) => warn!("failed to push metrics: {:?}", e),
+                    }
+                    State::Waiting
+                }
+            };
+        }
+    }
+}
+
+// === impl Encoder ===
+
+impl Encoder {
+    /// Translates a Prometheus text exposition to OTLP metrics.
+    fn encode(&mut self, text: &str, now: SystemTime) -> Vec<proto::Metric> {
+        let start = unix_nanos(self.last_push);
+        let time = unix_nanos(now);
+        self.last_push = now;
+
+        let mut families = IndexMap::<&str, Family>::new();
+        for sample in exposition::parse(text) {
+            let family = families.entry(sample.family).or_insert_with(|| Family {
+                help: sample.help,
+                kind: sample.kind,
+                numbers: Vec::new(),
+                histograms: IndexMap::new(),
+            });
+
+            match sample.kind {
+                Kind::Gauge => family.numbers.push(number(&sample, sample.value, 0, time)),
+                Kind::Counter => {
+                    let delta = self.counters.delta(&sample);
+                    family.numbers.push(number(&sample, delta, start, time));
+                }
+                Kind::Histogram => {
+                    let delta = self.counters.delta(&sample);
+                    let key = sample
+                        .labels
+                        .iter()
+                        .filter(|&&(k, _)| k != "le")
+                        .map(|&(k, ref v)| format!("{}={:?},", k, v))
+                        .collect::<String>();
+                    let histogram = family.histograms.entry(key).or_insert_with(|| Histogram {
+                        attributes: attributes(&sample),
+                        ..Histogram::default()
+                    });
+
+                    let suffix = &sample.name[sample.family.len()..];
+                    match suffix {
+                        "_bucket" => {
+                            let le = sample.labels.iter().find(|&&(k, _)| k == "le");
+                            let bound = match le.map(|&(_, ref v)| v.as_str()) {
+                                // The `+Inf` bucket has no explicit bound.
+                                Some("+Inf") => Ok(None),
+                                Some(le) => le.parse::<f64>().map(Some),
+                                None => Ok(None),
+                            };
+                            match bound {
+                                Ok(Some(le)) => histogram.bounds.push(le),
+                                Ok(None) => {}
+                                Err(_) => {
+                                    debug!("invalid histogram bound: {}", sample.series);
+                                    continue;
+                                }
+                            }
+                            histogram.buckets.push(delta);
+                        }
+                        "_count" => histogram.count = delta,
+                        "_sum" => histogram.sum = delta,
+                        _ => debug!("unexpected histogram series: {}", sample.series),
+                    }
+                }
+            }
+        }
+        self.counters.finish();
+
+        families
+            .into_iter()
+            .map(|(name, family)| {
+                let data = match family.kind {
+                    Kind::Gauge => proto::metric::Data::Gauge(proto::Gauge {
+                        data_points: family.numbers,
+                    }),
+                    Kind::Counter => proto::metric::Data::Sum(proto::Sum {
+                        data_points: family.numbers,
+                        aggregation_temporality: proto::AggregationTemporality::Delta as i32,
+                        is_monotonic: true,
+                    }),
+                    Kind::Histogram => proto::metric::Data::Histogram(proto::Histogram {
+                        data_points: family
+                            .histograms
+                            .into_iter()
+                            .map(|(_, h)| h.into_data_point(start, time))
+                            .collect(),
+                        aggregation_temporality: proto::AggregationTemporality::Delta as i32,
+                    }),
+                };
+                proto::Metric {
+                    name: name.to_owned(),
+                    description: family.help.to_owned(),
+                    unit: String::new(),
+                    data: Some(data),
+                }
+            })
+            .collect()
+    }
+}
+
+// === impl Histogram ===
+
+impl Histogram {
+    fn into_data_point(self, start: u64, time: u64) -> proto::HistogramDataPoint {
+        // Prometheus buckets are cumulative, whereas OTLP buckets are not.
+        let mut prior = 0.0;
+        let bucket_counts = self
+            .buckets
+            .into_iter()
+            .map(|b| {
+                let count = if b > prior { b - prior } else { 0.0 };
+                prior = b;
+                count as u64
+            })
+            .collect();
+
+        proto::HistogramDataPoint {
+            attributes: self.attributes,
+            start_time_unix_nano: start,
+            time_unix_nano: time,
+            count: self.count as u64,
+            sum: Some(self.sum),
+            bucket_counts,
+            explicit_bounds: self.bounds,
+        }
+    }
+}
+
+fn number(sample: &Sample, value: f64, start: u64, time: u64) -> proto::NumberDataPoint {
+    proto::NumberDataPoint {
+        attributes: attributes(sample),
+        start_time_unix_nano: start,
+        time_unix_nano: time,
+        value: Some(proto::number_data_point::Value::AsDouble(value)),
+    }
+}
+
+fn attributes(sample: &Sample) -> Vec<proto::KeyValue> {
+    sample
+        .labels
+        .iter()
+        .filter(|&&(k, _)| k != "le")
+        .map(|&(k, ref v)| key_value(k, v.clone()))
+        .collect()
+}
+
+fn key_value(key: &str, value: String) -> proto::KeyValue {
+    proto::KeyValue {
+        key: key.to_owned(),
+        value: Some(proto::AnyValue {
+            value: Some(proto::any_value::Value::StringValue(value)),
+        }),
+    }
+}
+
+fn unix_nanos(t: SystemTime) -> u64 {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos())
+}
+
+/// The subset of the OpenTelemetry protocol's messages that the exporter
+/// sends, from `opentelemetry/proto/collector/metrics/v1`.
+mod proto {
+    #[derive(Clone, PartialEq, Message)]
+    pub struct ExportMetricsServiceRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub resource_metrics: Vec<ResourceMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct ExportMetricsServiceResponse {}
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct ResourceMetrics {
+        #[prost(message, optional, tag = "1")]
+        pub resource: Option<Resource>,
+        #[prost(message, repeated, tag = "2")]
+        pub scope_metrics: Vec<ScopeMetrics>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Resource {
+        #[prost(message, repeated, tag = "1")]
+        pub attributes: Vec<KeyValue>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct ScopeMetrics {
+        #[prost(message, optional, tag = "1")]
+        pub scope: Option<InstrumentationScope>,
+        #[prost(message, repeated, tag = "2")]
+        pub metrics: Vec<Metric>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct InstrumentationScope {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Metric {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub description: String,
+        #[prost(string, tag = "3")]
+        pub unit: String,
+        #[prost(oneof = "metric::Data", tags = "5, 7, 9")]
+        pub data: Option<metric::Data>,
+    }
+
+    pub mod metric {
+        #[derive(Clone, PartialEq, Oneof)]
+        pub enum Data {
+            #[prost(message, tag = "5")]
+            Gauge(super::Gauge),
+            #[prost(message, tag = "7")]
+            Sum(super::Sum),
+            #[prost(message, tag = "9")]
+            Histogram(super::Histogram),
+        }
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Gauge {
+        #[prost(message, repeated, tag = "1")]
+        pub data_points: Vec<NumberDataPoint>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Sum {
+        #[prost(message, repeated, tag = "1")]
+        pub data_points: Vec<NumberDataPoint>,
+        #[prost(enumeration = "AggregationTemporality", tag = "2")]
+        pub aggregation_temporality: i32,
+        #[prost(bool, tag = "3")]
+        pub is_monotonic: bool,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct Histogram {
+        #[prost(message, repeated, tag = "1")]
+        pub data_points: Vec<HistogramDataPoint>,
+        #[prost(enumeration = "AggregationTemporality", tag = "2")]
+        pub aggregation_temporality: i32,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct NumberDataPoint {
+        #[prost(message, repeated, tag = "7")]
+        pub attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        pub start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        pub time_unix_nano: u64,
+        #[prost(oneof = "number_data_point::Value", tags = "4")]
+        pub value: Option<number_data_point::Value>,
+    }
+
+    pub mod number_data_point {
+        #[derive(Clone, PartialEq, Oneof)]
+        pub enum Value {
+            #[prost(double, tag = "4")]
+            AsDouble(f64),
+        }
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct HistogramDataPoint {
+        #[prost(message, repeated, tag = "9")]
+        pub attributes: Vec<KeyValue>,
+        #[prost(fixed64, tag = "2")]
+        pub start_time_unix_nano: u64,
+        #[prost(fixed64, tag = "3")]
+        pub time_unix_nano: u64,
+        #[prost(fixed64, tag = "4")]
+        pub count: u64,
+        #[prost(double, optional, tag = "5")]
+        pub sum: Option<f64>,
+        #[prost(fixed64, repeated, tag = "6")]
+        pub bucket_counts: Vec<u64>,
+        #[prost(double, repeated, tag = "7")]
+        pub explicit_bounds: Vec<f64>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct KeyValue {
+        #[prost(string, tag = "1")]
+        pub key: String,
+        #[prost(message, optional, tag = "2")]
+        pub value: Option<AnyValue>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct AnyValue {
+        #[prost(oneof = "any_value::Value", tags = "1")]
+        pub value: Option<any_value::Value>,
+    }
+
+    pub mod any_value {
+        #[derive(Clone, PartialEq, Oneof)]
+        pub enum Value {
+            #[prost(string, tag = "1")]
+            StringValue(String),
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Enumeration)]
+    pub enum AggregationTemporality {
+        Unspecified = 0,
+        Delta = 1,
+        Cumulative = 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPOSITION: &str = "\
+# HELP request_total Total count of HTTP requests.
+# TYPE request_total counter
+request_total{direction=\"inbound\"} 3
+# HELP tcp_open_connections Number of currently-open connections.
+# TYPE tcp_open_connections gauge
+tcp_open_connections 2
+# HELP response_latency_ms Elapsed times between a request's headers being received and its response stream completing
+# TYPE response_latency_ms histogram
+response_latency_ms_bucket{direction=\"inbound\",le=\"10\"} 1
+response_latency_ms_bucket{direction=\"inbound\",le=\"100\"} 3
+response_latency_ms_bucket{direction=\"inbound\",le=\"+Inf\"} 4
+response_latency_ms_count{direction=\"inbound\"} 4
+response_latency_ms_sum{direction=\"inbound\"} 212
+";
+
+    fn encoder() -> Encoder {
+        Encoder {
+            counters: Deltas::default(),
+            last_push: UNIX_EPOCH + Duration::from_secs(1),
+        }
+    }
+
+    fn numbers(metric: &proto::Metric) -> Vec<f64> {
+        let points = match metric.data {
+            Some(proto::metric::Data::Gauge(ref g)) => &g.data_points,
+            Some(proto::metric::Data::Sum(ref s)) => &s.data_points,
+            _ => panic!("not a number metric: {:?}", metric),
+        };
+        points
+            .iter()
+            .map(|p| match p.value {
+                Some(proto::number_data_point::Value::AsDouble(v)) => v,
+                None => panic!("data point has no value"),
+            })
+            .collect()
+    }
+
+    fn histogram(metric: &proto::Metric) -> &proto::HistogramDataPoint {
+        match metric.data {
+            Some(proto::metric::Data::Histogram(ref h)) => &h.data_points[0],
+            _ => panic!("not a histogram: {:?}", metric),
+        }
+    }
+
+    #[test]
+    fn encodes_families() {
+        let metrics = encoder().encode(EXPOSITION, UNIX_EPOCH + Duration::from_secs(2));
+        assert_eq!(metrics.len(), 3);
+
+        assert_eq!(metrics[0].name, "request_total");
+        assert_eq!(metrics[0].description, "Total count of HTTP requests.");
+        match metrics[0].data {
+            Some(proto::metric::Data::Sum(ref s)) => {
+                assert!(s.is_monotonic);
+                assert_eq!(
+                    s.data_points[0].attributes,
+                    vec![key_value("direction", "inbound".into())]
+                );
+                assert_eq!(s.data_points[0].start_time_unix_nano, 1_000_000_000);
+                assert_eq!(s.data_points[0].time_unix_nano, 2_000_000_000);
+            }
+            ref data => panic!("not a sum: {:?}", data),
+        }
+
+        assert_eq!(metrics[1].name, "tcp_open_connections");
+        assert_eq!(numbers(&metrics[1]), vec![2.0]);
+
+        assert_eq!(metrics[2].name, "response_latency_ms");
+        let h = histogram(&metrics[2]);
+        assert_eq!(h.attributes, vec![key_value("direction", "inbound".into())]);
+        assert_eq!(h.explicit_bounds, vec![10.0, 100.0]);
+        assert_eq!(h.bucket_counts, vec![1, 2, 1]);
+        assert_eq!(h.count, 4);
+        assert_eq!(h.sum, Some(212.0));
+    }
+
+    #[test]
+    fn sends_deltas() {
+        let mut encoder = encoder();
+        encoder.encode(EXPOSITION, UNIX_EPOCH + Duration::from_secs(2));
+
+        let next = EXPOSITION
+            .replace("{direction=\"inbound\"} 3", "{direction=\"inbound\"} 5")
+            .replace("le=\"+Inf\"} 4", "le=\"+Inf\"} 5")
+            .replace(
+                "_count{direction=\"inbound\"} 4",
+                "_count{direction=\"inbound\"} 5",
+            );
+        let metrics = encoder.encode(&next, UNIX_EPOCH + Duration::from_secs(3));
+
+        assert_eq!(numbers(&metrics[0]), vec![2.0]);
+        assert_eq!(numbers(&metrics[1]), vec![2.0]);
+        let h = histogram(&metrics[2]);
+        assert_eq!(h.start_time_unix_nano, 2_000_000_000);
+        assert_eq!(h.bucket_counts, vec![0, 0, 1]);
+        assert_eq!(h.count, 1);
+        assert_eq!(h.sum, Some(0.0));
+    }
+}
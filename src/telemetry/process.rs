@@ -134,6 +134,8 @@ mod system {
             process_cpu_seconds_total
                 .fmt_metric(f, Counter::from(clock_ticks / self.clock_ticks_per_sec))?;
 
+            // Failing to inspect file descriptors doesn't prevent the
+            // memory metrics from being reported.
             match Self::open_fds(stat.pid) {
                 Ok(open_fds) => {
                     process_open_fds.fmt_help(f)?;
@@ -141,7 +143,6 @@ mod system {
                 }
                 Err(err) => {
                     warn!("could not determine process_open_fds: {}", err);
-                    return Ok(());
                 }
             }
 
@@ -153,7 +154,6 @@ mod system {
                 }
                 Err(err) => {
                     warn!("could not determine process_max_fds: {}", err);
-                    return Ok(());
                 }
             }
 
@@ -0,0 +1,345 @@
+//! Pushes metrics to a StatsD or DogStatsD agent over UDP.
+//!
+//! The proxy's metrics are rendered exactly as they are served to Prometheus,
+//! and each sample is translated to a StatsD line when the exporter flushes.
+//! Counters and histogram buckets, sums, and counts are sent as counters of the
+//! change since the previous flush; gauges are sent as gauges.
+
+use futures::{Async, Future, Poll, Stream};
+use std::fmt::{self, Write};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_timer::{clock, Interval};
+
+use super::exposition::{self, Deltas, Kind, Sample};
+use super::metrics::FmtMetrics;
+
+/// The largest datagram that is sent, so that packets are not fragmented on
+/// typical networks.
+const MAX_PACKET_SIZE: usize = 1_432;
+
+/// The dialect spoken by the agent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Flavor {
+    /// Labels are appended to metric names, e.g. `request_total.direction.inbound`.
+    Statsd,
+    /// Labels are sent as tags, e.g. `request_total:1|c|#direction:inbound`.
+    DogStatsd,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub addr: SocketAddr,
+    pub flavor: Flavor,
+    /// Prepended to each metric's name, separated by a `.`.
+    pub prefix: Option<String>,
+    pub flush_interval: Duration,
+}
+
+/// Periodically pushes metrics to a StatsD agent.
+pub struct Exporter<M> {
+    metrics: M,
+    socket: UdpSocket,
+    interval: Interval,
+    encoder: Encoder,
+}
+
+/// Translates Prometheus-formatted samples to StatsD lines.
+#[derive(Debug)]
+struct Encoder {
+    flavor: Flavor,
+    prefix: Option<String>,
+    counters: Deltas,
+}
+
+// === impl Flavor ===
+
+impl FromStr for Flavor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("statsd") => Ok(Flavor::Statsd),
+            s if s.eq_ignore_ascii_case("dogstatsd") => Ok(Flavor::DogStatsd),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Flavor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Flavor::Statsd => f.pad("statsd"),
+            Flavor::DogStatsd => f.pad("dogstatsd"),
+        }
+    }
+}
+
+// === impl Exporter ===
+
+impl<M: FmtMetrics> Exporter<M> {
+    pub fn new(config: Config, metrics: M) -> io::Result<Self> {
+        let bind: SocketAddr = if config.addr.is_ipv4() {
+            ([0u8; 4], 0).into()
+        } else {
+            ([0u16; 8], 0).into()
+        };
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(config.addr)?;
+        // Metrics are dropped, rather than blocking the admin thread, when the
+        // socket's buffer is full.
+        socket.set_nonblocking(true)?;
+        info!(
+            "pushing metrics to {} every {:?} ({})",
+            config.addr, config.flush_interval, config.flavor
+        );
+
+        Ok(Self {
+            metrics,
+            socket,
+            interval: Interval::new(clock::now() + config.flush_interval, config.flush_interval),
+            encoder: Encoder {
+                flavor: config.flavor,
+                prefix: config.prefix,
+                counters: Deltas::default(),
+            },
+        })
+    }
+
+    fn flush(&mut self) {
+        let exposition = self.metrics.as_display().to_string();
+        let lines = self.encoder.encode(&exposition);
+
+        let mut dropped = 0;
+        for packet in packets(&lines) {
+            if let Err(e) = self.socket.send(packet.as_bytes()) {
+                if e.kind() != io::ErrorKind::WouldBlock {
+                    debug!("failed to send metrics: {}", e);
+                }
+                dropped += 1;
+            }
+        }
+        if dropped > 0 {
+            warn!("dropped {} metrics packets", dropped);
+        }
+    }
+}
+
+impl<M: FmtMetrics> Future for Exporter<M> {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            match self.interval.poll() {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(_)) => self.flush(),
+                Err(e) => {
+                    error!("metrics push timer failed: {}", e);
+                    return Err(());
+                }
+            }
+        }
+    }
+}
+
+/// Joins lines into newline-delimited packets of at most `MAX_PACKET_SIZE`
+/// bytes. A line that is larger than a packet is sent on its own.
+fn packets(lines: &[String]) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > MAX_PACKET_SIZE {
+            packets.push(packet);
+            packet = String::new();
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
+
+// === impl Encoder ===
+
+impl Encoder {
+    /// Translates a Prometheus text exposition to StatsD lines.
+    ///
+    /// Counters that have not changed since the previous call are omitted.
+    fn encode(&mut self, text: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for sample in exposition::parse(text) {
+            let (value, suffix) = match sample.kind {
+                Kind::Gauge => (sample.value, "g"),
+                Kind::Counter | Kind::Histogram => {
+                    let delta = self.counters.delta(&sample);
+                    if delta == 0.0 {
+                        continue;
+                    }
+                    (delta, "c")
+                }
+            };
+
+            let mut out = String::new();
+            let _ = self.write_line(&mut out, &sample, value, suffix);
+            lines.push(out);
+        }
+
+        self.counters.finish();
+        lines
+    }
+
+    fn write_line(
+        &self,
+        out: &mut String,
+        sample: &Sample,
+        value: f64,
+        suffix: &str,
+    ) -> fmt::Result {
+        if let Some(ref prefix) = self.prefix {
+            write!(out, "{}.", prefix)?;
+        }
+        out.push_str(sample.name);
+
+        match self.flavor {
+            Flavor::Statsd => {
+                for (k, v) in &sample.labels {
+                    write!(out, ".{}.{}", k, Sanitize(v))?;
+                }
+                write!(out, ":{}|{}", value, suffix)
+            }
+            Flavor::DogStatsd => {
+                write!(out, ":{}|{}", value, suffix)?;
+                for (i, (k, v)) in sample.labels.iter().enumerate() {
+                    let sep = if i == 0 { "|#" } else { "," };
+                    write!(out, "{}{}:{}", sep, k, Sanitize(v))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Replaces characters that have meaning in StatsD lines, or that are not
+/// valid in metric names.
+struct Sanitize<'a>(&'a str);
+
+impl<'a> fmt::Display for Sanitize<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '-' | '+' => f.write_char(c)?,
+                _ => f.write_char('_')?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPOSITION: &str = "\
+# HELP request_total Total count of HTTP requests.
+# TYPE request_total counter
+request_total{direction=\"inbound\",authority=\"books:8080\"} 3
+# HELP tcp_open_connections Number of currently-open connections.
+# TYPE tcp_open_connections gauge
+tcp_open_connections 2
+# HELP response_latency_ms Elapsed times between a request's headers being received and its response stream completing
+# TYPE response_latency_ms histogram
+response_latency_ms_bucket{direction=\"inbound\",le=\"+Inf\"} 3
+response_latency_ms_count{direction=\"inbound\"} 3
+response_latency_ms_sum{direction=\"inbound\"} 12
+";
+
+    fn encoder(flavor: Flavor) -> Encoder {
+        Encoder {
+            flavor,
+            prefix: Some("linkerd".into()),
+            counters: Deltas::default(),
+        }
+    }
+
+    #[test]
+    fn encodes_dogstatsd_tags() {
+        let mut encoder = encoder(Flavor::DogStatsd);
+        assert_eq!(
+            encoder.encode(EXPOSITION),
+            vec![
+                "linkerd.request_total:3|c|#direction:inbound,authority:books_8080",
+                "linkerd.tcp_open_connections:2|g",
+                "linkerd.response_latency_ms_bucket:3|c|#direction:inbound,le:+Inf",
+                "linkerd.response_latency_ms_count:3|c|#direction:inbound",
+                "linkerd.response_latency_ms_sum:12|c|#direction:inbound",
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_statsd_names() {
+        let mut encoder = encoder(Flavor::Statsd);
+        let lines = encoder.encode(EXPOSITION);
+        assert_eq!(
+            lines[0],
+            "linkerd.request_total.direction.inbound.authority.books_8080:3|c"
+        );
+        assert_eq!(lines[1], "linkerd.tcp_open_connections:2|g");
+    }
+
+    #[test]
+    fn sends_counter_deltas() {
+        let mut encoder = encoder(Flavor::DogStatsd);
+        encoder.encode(EXPOSITION);
+
+        let next = EXPOSITION
+            .replace("\"books:8080\"} 3", "\"books:8080\"} 5")
+            .replace(
+                "_count{direction=\"inbound\"} 3",
+                "_count{direction=\"inbound\"} 1",
+            );
+        assert_eq!(
+            encoder.encode(&next),
+            vec![
+                "linkerd.request_total:2|c|#direction:inbound,authority:books_8080",
+                "linkerd.tcp_open_connections:2|g",
+                "linkerd.response_latency_ms_count:1|c|#direction:inbound",
+            ]
+        );
+
+        // A series that leaves the exposition is forgotten, so when it is
+        // recreated its whole value is new.
+        let dropped = next.replace(
+            "request_total{direction=\"inbound\",authority=\"books:8080\"} 5\n",
+            "",
+        );
+        assert!(encoder
+            .encode(&dropped)
+            .iter()
+            .all(|l| !l.contains("request_total")));
+
+        let recreated = next.replace("\"books:8080\"} 5", "\"books:8080\"} 7");
+        assert_eq!(
+            encoder.encode(&recreated)[0],
+            "linkerd.request_total:7|c|#direction:inbound,authority:books_8080"
+        );
+    }
+
+    #[test]
+    fn splits_lines_into_packets() {
+        let line = "x".repeat(MAX_PACKET_SIZE / 2 - 1);
+        let lines = vec![line.clone(), line.clone(), line.clone()];
+        let packets = packets(&lines);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0], format!("{}\n{}", line, line));
+        assert_eq!(packets[1], line);
+    }
+}
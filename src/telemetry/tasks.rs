@@ -0,0 +1,238 @@
+//! Tracks the proxy's background tasks.
+//!
+//! Per-destination tasks (e.g. profile lookups and DNS canonicalization) are
+//! spawned through a `Registry`, which ties each task to a `task::Handle` owned
+//! by its consumer, so that the task stops when the consumer is dropped (e.g.
+//! when a destination is evicted from a router). The number of running tasks
+//! of each kind is reported as a gauge.
+//!
+//! Each poll of a tracked task is timed, so that tasks that block the
+//! executor's threads (and so starve other tasks) may be detected.
+
+use futures::{Async, Future, Poll};
+use indexmap::IndexMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use tokio::executor::{DefaultExecutor, Executor};
+
+use super::metrics::{latency, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge, Histogram};
+use task;
+
+metrics! {
+    background_tasks: Gauge { "Number of background tasks that are running, by kind" },
+    background_tasks_spawned_total: Counter {
+        "Total count of background tasks that were started, by kind"
+    },
+    background_task_polls_total: Counter {
+        "Total count of times that background tasks were woken and polled, by kind"
+    },
+    background_task_poll_duration_ms: Histogram<latency::Ms> {
+        "Time spent polling background tasks, by kind"
+    }
+}
+
+type Kinds = Arc<Mutex<IndexMap<&'static str, Arc<Mutex<Stats>>>>>;
+
+pub fn new() -> (Registry, Report) {
+    let kinds = Kinds::default();
+    (Registry(kinds.clone()), Report(kinds))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Kinds);
+
+/// Implements `FmtMetrics` to render the number of running background tasks.
+#[derive(Clone, Debug)]
+pub struct Report(Kinds);
+
+/// A task that is counted while it exists.
+pub struct Tracked<F> {
+    inner: F,
+    /// Each task holds a reference, so that the number of tasks of a kind is
+    /// tracked by the reference count.
+    stats: Arc<Mutex<Stats>>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    spawned: Counter,
+    polls: Counter,
+    poll_duration: Histogram<latency::Ms>,
+}
+
+/// A tracked task that completes early when its `task::Handle` is dropped.
+struct Cancelable<F> {
+    inner: Tracked<F>,
+    canceled: task::Canceled,
+    kind: &'static str,
+}
+
+struct Kind(&'static str);
+
+// === impl Registry ===
+
+impl Registry {
+    /// Counts `future` as a `kind` task until it completes or is dropped.
+    pub fn track<F: Future>(&self, kind: &'static str, future: F) -> Tracked<F> {
+        let stats = match self.0.lock() {
+            Ok(mut kinds) => kinds.entry(kind).or_insert_with(Default::default).clone(),
+            // Tasks are still run, but are not counted.
+            Err(_) => Default::default(),
+        };
+        if let Ok(mut s) = stats.lock() {
+            s.spawned.incr();
+        }
+        Tracked {
+            inner: future,
+            stats,
+        }
+    }
+
+    /// Spawns `future` as a `kind` task on the default executor.
+    ///
+    /// The task is canceled when the returned handle is dropped.
+    pub fn spawn<F>(&self, kind: &'static str, future: F) -> Result<task::Handle, task::Error>
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        let (handle, canceled) = task::cancelable();
+        let task = Cancelable {
+            inner: self.track(kind, future),
+            canceled,
+            kind,
+        };
+        DefaultExecutor::current().spawn(Box::new(task))?;
+        Ok(handle)
+    }
+}
+
+// === impl Tracked ===
+
+impl<F: Future> Future for Tracked<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let t0 = Instant::now();
+        let poll = self.inner.poll();
+        let elapsed = t0.elapsed();
+        if let Ok(mut s) = self.stats.lock() {
+            s.polls.incr();
+            s.poll_duration.add(elapsed);
+        }
+        poll
+    }
+}
+
+// === impl Cancelable ===
+
+impl<F> Future for Cancelable<F>
+where
+    F: Future<Item = (), Error = ()>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        if self.canceled.poll_canceled() {
+            trace!("{} task canceled", self.kind);
+            return Ok(Async::Ready(()));
+        }
+
+        self.inner.poll()
+    }
+}
+
+// === impl Report ===
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds = match self.0.lock() {
+            Err(_) => return Ok(()),
+            Ok(kinds) => kinds,
+        };
+        if kinds.is_empty() {
+            return Ok(());
+        }
+
+        background_tasks.fmt_help(f)?;
+        for (kind, stats) in kinds.iter() {
+            let running = Arc::strong_count(stats) as u64 - 1;
+            Gauge::from(running).fmt_metric_labeled(f, background_tasks.name, Kind(*kind))?;
+        }
+
+        let stats = kinds
+            .iter()
+            .filter_map(|(kind, s)| s.lock().ok().map(|s| (Kind(*kind), s)))
+            .collect::<Vec<(Kind, MutexGuard<Stats>)>>();
+
+        background_tasks_spawned_total.fmt_help(f)?;
+        for &(ref kind, ref s) in &stats {
+            s.spawned
+                .fmt_metric_labeled(f, background_tasks_spawned_total.name, kind)?;
+        }
+
+        background_task_polls_total.fmt_help(f)?;
+        for &(ref kind, ref s) in &stats {
+            s.polls
+                .fmt_metric_labeled(f, background_task_polls_total.name, kind)?;
+        }
+
+        background_task_poll_duration_ms.fmt_help(f)?;
+        for &(ref kind, ref s) in &stats {
+            s.poll_duration
+                .fmt_metric_labeled(f, background_task_poll_duration_ms.name, kind)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for Kind {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "kind=\"{}\"", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use tokio::runtime::current_thread::Runtime;
+
+    #[test]
+    fn dropping_handle_stops_task() {
+        let (registry, report) = new();
+        let mut rt = Runtime::new().unwrap();
+
+        let handle = rt
+            .block_on(future::lazy(|| {
+                registry.spawn("test", future::empty::<(), ()>())
+            }))
+            .unwrap();
+        assert!(report
+            .as_display()
+            .to_string()
+            .contains("background_tasks{kind=\"test\"} 1"));
+
+        drop(handle);
+        rt.run().unwrap();
+        let out = report.as_display().to_string();
+        assert!(out.contains("background_tasks{kind=\"test\"} 0"));
+        assert!(out.contains("background_tasks_spawned_total{kind=\"test\"} 1"));
+    }
+
+    #[test]
+    fn counts_polls() {
+        let (registry, report) = new();
+        let mut rt = Runtime::new().unwrap();
+
+        let task = registry.track("test", future::ok::<(), ()>(()));
+        rt.block_on(task).unwrap();
+
+        let out = report.as_display().to_string();
+        assert!(out.contains("background_task_polls_total{kind=\"test\"} 1"));
+        assert!(out.contains("background_task_poll_duration_ms_count{kind=\"test\"} 1"));
+    }
+}
@@ -0,0 +1,247 @@
+//! A token bucket, shared by the proxy's limiters.
+//!
+//! A bucket holds up to `capacity` tokens and gains one token every
+//! `interval`, so that it admits bursts of up to `capacity` acquisitions
+//! while sustaining a rate of one acquisition per `interval`. Time is read
+//! from `tokio_timer::clock`, which is monotonic, and all arithmetic
+//! saturates, so that a bucket behaves sensibly however long it sits idle.
+
+use std::time::{Duration, Instant};
+use tokio_timer::clock;
+
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    capacity: u64,
+    interval: Duration,
+    tokens: u64,
+    /// The time at which `tokens` was last brought up to date. Refills are
+    /// accounted from this instant, so partial intervals are not lost.
+    refilled: Instant,
+    acquired: u64,
+    rejected: u64,
+}
+
+/// The state of a `TokenBucket`, for reporting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub available: u64,
+    /// The total number of tokens acquired from the bucket.
+    pub acquired: u64,
+    /// The total number of acquisitions that were refused.
+    pub rejected: u64,
+}
+
+/// Indicates that a bucket did not have enough tokens; they will be available
+/// after the contained duration.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Empty(pub Duration);
+
+// === impl TokenBucket ===
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: u64, interval: Duration) -> Self {
+        Self::new_at(capacity, interval, clock::now())
+    }
+
+    fn new_at(capacity: u64, interval: Duration, now: Instant) -> Self {
+        assert!(capacity > 0, "token bucket must have a capacity");
+        Self {
+            capacity,
+            interval,
+            tokens: capacity,
+            refilled: now,
+            acquired: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Takes a token from the bucket if one is available.
+    pub fn try_acquire(&mut self) -> Result<(), Empty> {
+        self.try_acquire_n(1)
+    }
+
+    /// Takes `n` tokens from the bucket if they are all available.
+    ///
+    /// Requests for more than the bucket's capacity always fail.
+    pub fn try_acquire_n(&mut self, n: u64) -> Result<(), Empty> {
+        self.try_acquire_n_at(n, clock::now())
+    }
+
+    pub fn snapshot(&mut self) -> Snapshot {
+        self.snapshot_at(clock::now())
+    }
+
+    fn try_acquire_n_at(&mut self, n: u64, now: Instant) -> Result<(), Empty> {
+        self.refill(now);
+
+        if n <= self.tokens {
+            self.tokens -= n;
+            self.acquired = self.acquired.saturating_add(n);
+            return Ok(());
+        }
+
+        self.rejected = self.rejected.saturating_add(1);
+        let missing = n - self.tokens;
+        let wait = if n > self.capacity {
+            // Never satisfiable.
+            Duration::from_secs(u64::max_value())
+        } else {
+            // `refilled` is at most one interval in the past, so the first
+            // missing token arrives `interval - elapsed` from now.
+            let elapsed = saturating_since(now, self.refilled);
+            mul(self.interval, missing)
+                .checked_sub(elapsed)
+                .unwrap_or_else(|| Duration::from_secs(0))
+        };
+        Err(Empty(wait))
+    }
+
+    fn snapshot_at(&mut self, now: Instant) -> Snapshot {
+        self.refill(now);
+        Snapshot {
+            available: self.tokens,
+            acquired: self.acquired,
+            rejected: self.rejected,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if self.tokens == self.capacity {
+            self.refilled = now;
+            return;
+        }
+
+        let interval = nanos(self.interval);
+        if interval == 0 {
+            self.tokens = self.capacity;
+            self.refilled = now;
+            return;
+        }
+
+        let new = nanos(saturating_since(now, self.refilled)) / interval;
+        if new == 0 {
+            return;
+        }
+
+        self.tokens = self.tokens.saturating_add(new);
+        if self.tokens >= self.capacity {
+            self.tokens = self.capacity;
+            self.refilled = now;
+        } else {
+            self.refilled += mul(self.interval, new);
+        }
+    }
+}
+
+/// Like `Instant::duration_since`, but zero when `earlier` is later than `now`.
+fn saturating_since(now: Instant, earlier: Instant) -> Duration {
+    if now > earlier {
+        now - earlier
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+fn nanos(d: Duration) -> u64 {
+    d.as_secs()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(u64::from(d.subsec_nanos()))
+}
+
+fn mul(d: Duration, n: u64) -> Duration {
+    if n > u64::from(u32::max_value()) {
+        return Duration::from_secs(u64::max_value());
+    }
+    d.checked_mul(n as u32)
+        .unwrap_or_else(|| Duration::from_secs(u64::max_value()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admits_bursts_up_to_capacity() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new_at(3, Duration::from_secs(1), t0);
+
+        for _ in 0..3 {
+            assert_eq!(bucket.try_acquire_n_at(1, t0), Ok(()));
+        }
+        assert_eq!(
+            bucket.try_acquire_n_at(1, t0),
+            Err(Empty(Duration::from_secs(1)))
+        );
+        assert_eq!(
+            bucket.snapshot_at(t0),
+            Snapshot {
+                available: 0,
+                acquired: 3,
+                rejected: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn refills_without_losing_partial_intervals() {
+        let t0 = Instant::now();
+        let interval = Duration::from_millis(100);
+        let mut bucket = TokenBucket::new_at(2, interval, t0);
+        assert_eq!(bucket.try_acquire_n_at(2, t0), Ok(()));
+
+        let t1 = t0 + Duration::from_millis(150);
+        assert_eq!(bucket.try_acquire_n_at(1, t1), Ok(()));
+        // The half interval left over from the first refill counts towards
+        // the next token.
+        assert_eq!(
+            bucket.try_acquire_n_at(1, t1),
+            Err(Empty(Duration::from_millis(50)))
+        );
+        assert_eq!(
+            bucket.try_acquire_n_at(1, t0 + Duration::from_millis(200)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn never_exceeds_capacity() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new_at(2, Duration::from_millis(1), t0);
+        assert_eq!(bucket.try_acquire_n_at(1, t0), Ok(()));
+
+        let later = t0 + Duration::from_secs(60 * 60 * 24 * 365);
+        assert_eq!(bucket.snapshot_at(later).available, 2);
+        assert_eq!(bucket.try_acquire_n_at(2, later), Ok(()));
+        assert!(bucket.try_acquire_n_at(1, later).is_err());
+    }
+
+    #[test]
+    fn tolerates_time_going_backwards() {
+        let t0 = Instant::now() + Duration::from_secs(10);
+        let mut bucket = TokenBucket::new_at(1, Duration::from_secs(1), t0);
+        assert_eq!(bucket.try_acquire_n_at(1, t0), Ok(()));
+
+        let earlier = t0 - Duration::from_secs(5);
+        assert_eq!(
+            bucket.try_acquire_n_at(1, earlier),
+            Err(Empty(Duration::from_secs(1)))
+        );
+        assert_eq!(bucket.snapshot_at(earlier).available, 0);
+    }
+
+    #[test]
+    fn rejects_requests_over_capacity() {
+        let t0 = Instant::now();
+        let mut bucket = TokenBucket::new_at(2, Duration::from_secs(1), t0);
+        match bucket.try_acquire_n_at(3, t0) {
+            Err(Empty(wait)) => assert_eq!(wait.as_secs(), u64::max_value()),
+            Ok(()) => panic!("acquired more tokens than the bucket holds"),
+        }
+        assert_eq!(bucket.snapshot_at(t0).available, 2);
+    }
+}
@@ -1,31 +1,67 @@
 extern crate tokio_connect;
 
 pub use self::tokio_connect::Connect;
-use futures::{Future, Poll};
+use futures::{future::Either, Async, Future, Poll};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{io, net::SocketAddr};
 use tokio::net::{tcp, TcpStream};
+use tokio::reactor::Handle;
+use tokio_timer::{clock, Delay};
 
 use never::Never;
 use svc;
+use transport::ConnectOptions;
+
+/// How long a connection attempt may be pending before an attempt to the next
+/// address is started, as recommended by RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
 
 pub trait HasPeerAddr {
     fn peer_addr(&self) -> SocketAddr;
+
+    /// Other addresses at which the peer may be reached, e.g. because its name
+    /// resolved to both IPv6 and IPv4 addresses.
+    fn fallback_peer_addrs(&self) -> &[SocketAddr] {
+        &[]
+    }
 }
 
 #[derive(Debug, Clone)]
-pub struct Stack(());
+pub struct Stack {
+    options: Arc<ConnectOptions>,
+}
 
 /// A TCP connection target, optionally with TLS.
 ///
 /// Comparison operations ignore the TLS ClientConfig and only account for the
 /// TLS status.
 #[derive(Clone, Debug)]
-pub struct ConnectSocketAddr(SocketAddr);
+pub struct ConnectSocketAddr {
+    addr: SocketAddr,
+    fallback_addrs: Vec<SocketAddr>,
+    options: Arc<ConnectOptions>,
+}
 
 #[derive(Debug)]
 pub struct ConnectFuture {
     addr: SocketAddr,
-    future: tcp::ConnectFuture,
+    /// Fails if the socket could not be created with the configured options.
+    future: Result<tcp::ConnectFuture, Option<io::Error>>,
+}
+
+/// Races connections to several addresses, per RFC 8305 ("Happy Eyeballs").
+///
+/// Attempts are started in turn, alternating between address families, each
+/// once the previous attempt has failed or has been pending for
+/// `CONNECTION_ATTEMPT_DELAY`. The first connection established is used.
+pub struct HappyEyeballs {
+    options: Arc<ConnectOptions>,
+    pending: VecDeque<SocketAddr>,
+    attempts: Vec<ConnectFuture>,
+    delay: Delay,
+    error: Option<io::Error>,
 }
 
 impl HasPeerAddr for SocketAddr {
@@ -38,7 +74,15 @@ impl HasPeerAddr for SocketAddr {
 
 impl Stack {
     pub fn new() -> Self {
-        Stack(())
+        Stack {
+            options: Arc::new(ConnectOptions::default()),
+        }
+    }
+
+    pub fn with_options(options: ConnectOptions) -> Self {
+        Stack {
+            options: Arc::new(options),
+        }
     }
 }
 
@@ -47,41 +91,97 @@ impl<T: HasPeerAddr> svc::Stack<T> for Stack {
     type Error = Never;
 
     fn make(&self, t: &T) -> Result<Self::Value, Self::Error> {
-        Ok(t.peer_addr().into())
+        Ok(ConnectSocketAddr {
+            addr: t.peer_addr(),
+            fallback_addrs: t.fallback_peer_addrs().to_vec(),
+            options: self.options.clone(),
+        })
     }
 }
 
 // === impl ConnectSocketAddr ===
 
 impl From<SocketAddr> for ConnectSocketAddr {
-    fn from(sa: SocketAddr) -> Self {
-        ConnectSocketAddr(sa)
+    fn from(addr: SocketAddr) -> Self {
+        ConnectSocketAddr {
+            addr,
+            fallback_addrs: Vec::new(),
+            options: Arc::new(ConnectOptions::default()),
+        }
     }
 }
 
 impl Connect for ConnectSocketAddr {
     type Connected = TcpStream;
     type Error = io::Error;
-    type Future = ConnectFuture;
+    type Future = Either<ConnectFuture, HappyEyeballs>;
 
     fn connect(&self) -> Self::Future {
-        debug!("connecting to {}", self.0);
-        ConnectFuture {
-            addr: self.0,
-            future: TcpStream::connect(&self.0),
+        if self.fallback_addrs.is_empty() {
+            return Either::A(ConnectFuture::new(self.addr, &self.options));
+        }
+
+        let addrs = Some(self.addr)
+            .into_iter()
+            .chain(self.fallback_addrs.iter().cloned());
+        Either::B(HappyEyeballs::new(
+            interleave_families(addrs),
+            self.options.clone(),
+        ))
+    }
+}
+
+/// Orders addresses so that address families alternate, starting with the
+/// family of the first address, while otherwise preserving their order.
+fn interleave_families<I: IntoIterator<Item = SocketAddr>>(addrs: I) -> VecDeque<SocketAddr> {
+    let mut addrs = addrs.into_iter().peekable();
+    let first_is_ipv6 = addrs.peek().map(SocketAddr::is_ipv6).unwrap_or(false);
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) =
+        addrs.partition(|a| a.is_ipv6() == first_is_ipv6);
+
+    let mut ordered = VecDeque::with_capacity(preferred.len() + other.len());
+    loop {
+        match (preferred.pop_front(), other.pop_front()) {
+            (None, None) => return ordered,
+            (a, b) => {
+                ordered.extend(a);
+                ordered.extend(b);
+            }
         }
     }
 }
 
 // === impl ConnectFuture ===
 
+impl ConnectFuture {
+    fn new(addr: SocketAddr, options: &ConnectOptions) -> Self {
+        debug!("connecting to {}", addr);
+        let future = if options.is_default() {
+            Ok(TcpStream::connect(&addr))
+        } else {
+            options
+                .socket(&addr)
+                .map(|socket| TcpStream::connect_std(socket, &addr, &Handle::current()))
+        };
+        ConnectFuture {
+            addr,
+            future: future.map_err(Some),
+        }
+    }
+}
+
 impl Future for ConnectFuture {
     type Item = TcpStream;
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let io = try_ready!(self.future.poll().map_err(|e| {
-            let details = format!("{} (address: {})", e, self.addr);
+        let poll = match self.future {
+            Ok(ref mut future) => future.poll(),
+            Err(ref mut e) => Err(e.take().expect("polled after failure")),
+        };
+        let addr = self.addr;
+        let io = try_ready!(poll.map_err(|e| {
+            let details = format!("{} (address: {})", e, addr);
             io::Error::new(e.kind(), details)
         }));
         debug!("connection established to {}", self.addr);
@@ -89,3 +189,95 @@ impl Future for ConnectFuture {
         Ok(io.into())
     }
 }
+
+// === impl HappyEyeballs ===
+
+impl HappyEyeballs {
+    fn new(pending: VecDeque<SocketAddr>, options: Arc<ConnectOptions>) -> Self {
+        HappyEyeballs {
+            options,
+            pending,
+            attempts: Vec::new(),
+            delay: Delay::new(clock::now()),
+            error: None,
+        }
+    }
+}
+
+impl Future for HappyEyeballs {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let mut i = 0;
+            while i < self.attempts.len() {
+                match self.attempts[i].poll() {
+                    Ok(Async::Ready(io)) => return Ok(Async::Ready(io)),
+                    Ok(Async::NotReady) => i += 1,
+                    Err(e) => {
+                        debug!("{}", e);
+                        self.attempts.swap_remove(i);
+                        self.error = Some(e);
+                    }
+                }
+            }
+
+            if self.pending.is_empty() {
+                if self.attempts.is_empty() {
+                    let e = self.error.take().unwrap_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::AddrNotAvailable,
+                            "no addresses to connect to",
+                        )
+                    });
+                    return Err(e);
+                }
+                return Ok(Async::NotReady);
+            }
+
+            // The next attempt is started immediately if all previous attempts
+            // have failed.
+            if !self.attempts.is_empty() {
+                match self.delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => {}
+                    Err(e) => warn!("timer failed; starting next connection attempt: {}", e),
+                }
+            }
+
+            let addr = self.pending.pop_front().expect("pending must not be empty");
+            self.attempts.push(ConnectFuture::new(addr, &self.options));
+            self.delay.reset(clock::now() + CONNECTION_ATTEMPT_DELAY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaves_address_families() {
+        let addrs = vec![
+            "[2001:db8::1]:80".parse().unwrap(),
+            "[2001:db8::2]:80".parse().unwrap(),
+            "[2001:db8::3]:80".parse().unwrap(),
+            "192.0.2.1:80".parse().unwrap(),
+            "192.0.2.2:80".parse().unwrap(),
+        ];
+        let ordered = interleave_families(addrs)
+            .into_iter()
+            .collect::<Vec<SocketAddr>>();
+        assert_eq!(
+            ordered,
+            vec![
+                "[2001:db8::1]:80".parse::<SocketAddr>().unwrap(),
+                "192.0.2.1:80".parse().unwrap(),
+                "[2001:db8::2]:80".parse().unwrap(),
+                "192.0.2.2:80".parse().unwrap(),
+                "[2001:db8::3]:80".parse().unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use net2::TcpBuilder;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+
+/// Socket options applied to connections before they are established.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectOptions {
+    /// When set, connections are bound to this local address, so that they
+    /// originate from a specific address of a multi-homed host. Connections
+    /// to addresses of another family are not bound.
+    pub source_addr: Option<IpAddr>,
+
+    /// When set, connections are bound to this network interface
+    /// (`SO_BINDTODEVICE`). Only supported on Linux, and requires
+    /// `CAP_NET_RAW`.
+    pub interface: Option<String>,
+
+    /// When set, the `SO_MARK` of connections, so that they may be matched by
+    /// policy routing or firewall rules. Only supported on Linux, and requires
+    /// `CAP_NET_ADMIN`.
+    pub mark: Option<u32>,
+}
+
+// === impl ConnectOptions ===
+
+impl ConnectOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Creates an unconnected socket, with these options set, from which a
+    /// connection to `addr` may be established.
+    pub fn socket(&self, addr: &SocketAddr) -> io::Result<TcpStream> {
+        let builder = match *addr {
+            SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+            SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+        };
+
+        if let Some(ref interface) = self.interface {
+            set_interface(&builder, interface)?;
+        }
+        if let Some(mark) = self.mark {
+            set_mark(&builder, mark)?;
+        }
+        match self.source_addr {
+            Some(ip) if ip.is_ipv4() == addr.is_ipv4() => {
+                builder.bind(SocketAddr::new(ip, 0))?;
+            }
+            Some(ip) => debug!("not binding connection to {} to {}", addr, ip),
+            None => {}
+        }
+
+        builder.to_tcp_stream()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_interface(builder: &TcpBuilder, interface: &str) -> io::Result<()> {
+    use libc;
+    use std::os::unix::io::AsRawFd;
+
+    let ret = unsafe {
+        libc::setsockopt(
+            builder.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr() as *const libc::c_void,
+            interface.len() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_interface(_: &TcpBuilder, _: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_BINDTODEVICE is only supported on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn set_mark(builder: &TcpBuilder, mark: u32) -> io::Result<()> {
+    use libc;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let ret = unsafe {
+        libc::setsockopt(
+            builder.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_MARK,
+            &mark as *const _ as *const libc::c_void,
+            mem::size_of::<u32>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_mark(_: &TcpBuilder, _: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_MARK is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_default() {
+        assert!(ConnectOptions::default().is_default());
+        let opts = ConnectOptions {
+            mark: Some(1),
+            ..ConnectOptions::default()
+        };
+        assert!(!opts.is_default());
+    }
+
+    #[test]
+    fn binds_to_source_addr() {
+        let opts = ConnectOptions {
+            source_addr: Some("127.0.0.1".parse().unwrap()),
+            ..ConnectOptions::default()
+        };
+        let socket = opts.socket(&"127.0.0.1:80".parse().unwrap()).unwrap();
+        let local = socket.local_addr().unwrap();
+        assert_eq!(local.ip(), "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert_ne!(local.port(), 0);
+    }
+
+    #[test]
+    fn does_not_bind_to_source_addr_of_another_family() {
+        let opts = ConnectOptions {
+            source_addr: Some("::1".parse().unwrap()),
+            ..ConnectOptions::default()
+        };
+        let socket = opts.socket(&"127.0.0.1:80".parse().unwrap()).unwrap();
+        let local = socket.local_addr().unwrap();
+        assert!(local.ip().is_unspecified());
+        assert_eq!(local.port(), 0);
+    }
+
+    #[test]
+    fn fails_on_unknown_interface() {
+        let opts = ConnectOptions {
+            interface: Some("l5d-no-such-if".into()),
+            ..ConnectOptions::default()
+        };
+        assert!(opts.socket(&"127.0.0.1:80".parse().unwrap()).is_err());
+    }
+}
@@ -6,7 +6,7 @@ use futures::Poll;
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use self::internal::Io;
-use super::{AddrInfo, SetKeepalive};
+use super::{AddrInfo, Keepalive, SetKeepalive};
 
 /// A public wrapper around a `Box<Io>`.
 ///
@@ -77,13 +77,13 @@ impl SetKeepalive for BoxedIo {
         self.0.keepalive()
     }
 
-    fn set_keepalive(&mut self, ka: Option<::std::time::Duration>) -> io::Result<()> {
+    fn set_keepalive(&mut self, ka: Option<Keepalive>) -> io::Result<()> {
         self.0.set_keepalive(ka)
     }
 }
 
 pub(super) mod internal {
-    use super::{AddrInfo, AsyncRead, AsyncWrite, Buf, Poll, SetKeepalive, Shutdown};
+    use super::{AddrInfo, AsyncRead, AsyncWrite, BoxedIo, Buf, Poll, SetKeepalive, Shutdown};
     use std::io;
     use tokio::net::TcpStream;
 
@@ -109,6 +109,16 @@ pub(super) mod internal {
             self.write_buf(&mut buf)
         }
     }
+
+    impl Io for BoxedIo {
+        fn shutdown_write(&mut self) -> io::Result<()> {
+            self.0.shutdown_write()
+        }
+
+        fn write_buf_erased(&mut self, buf: &mut Buf) -> Poll<usize, io::Error> {
+            self.0.write_buf_erased(buf)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -160,7 +170,7 @@ mod tests {
             unreachable!("not called in test")
         }
 
-        fn set_keepalive(&mut self, _: Option<::std::time::Duration>) -> io::Result<()> {
+        fn set_keepalive(&mut self, _: Option<Keepalive>) -> io::Result<()> {
             unreachable!("not called in test")
         }
     }
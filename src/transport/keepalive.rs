@@ -2,46 +2,114 @@ use std::io;
 use std::time::Duration;
 use tokio::net::TcpStream;
 
+/// Configures `SO_KEEPALIVE` on a socket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Keepalive {
+    /// How long a connection is idle before probes are sent (`TCP_KEEPIDLE`).
+    pub idle: Duration,
+
+    /// When set, the time between probes (`TCP_KEEPINTVL`).
+    pub interval: Option<Duration>,
+
+    /// When set, the number of unacknowledged probes after which the
+    /// connection is dropped (`TCP_KEEPCNT`).
+    pub count: Option<u32>,
+}
+
 pub trait SetKeepalive {
     fn keepalive(&self) -> io::Result<Option<Duration>>;
-    fn set_keepalive(&mut self, ka: Option<Duration>) -> ::std::io::Result<()>;
+    fn set_keepalive(&mut self, ka: Option<Keepalive>) -> ::std::io::Result<()>;
 }
 
+// === impl TcpStream ===
+
 impl SetKeepalive for TcpStream {
     fn keepalive(&self) -> io::Result<Option<Duration>> {
         TcpStream::keepalive(self)
     }
 
-    fn set_keepalive(&mut self, ka: Option<Duration>) -> ::std::io::Result<()> {
-        TcpStream::set_keepalive(self, ka)
+    fn set_keepalive(&mut self, ka: Option<Keepalive>) -> ::std::io::Result<()> {
+        TcpStream::set_keepalive(self, ka.map(|ka| ka.idle))?;
+        match ka {
+            Some(ka) => set_probes(self, ka.interval, ka.count),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_probes(
+    socket: &TcpStream,
+    interval: Option<Duration>,
+    count: Option<u32>,
+) -> io::Result<()> {
+    use libc;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let setsockopt = |name: libc::c_int, value: libc::c_int| {
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                name,
+                &value as *const _ as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    };
+
+    if let Some(interval) = interval {
+        // The interval is configured in whole seconds, and must be at least 1.
+        let secs = interval
+            .as_secs()
+            .max(1)
+            .min(libc::c_int::max_value() as u64);
+        setsockopt(libc::TCP_KEEPINTVL, secs as libc::c_int)?;
+    }
+    if let Some(count) = count {
+        let count = count.min(libc::c_int::max_value() as u32);
+        setsockopt(libc::TCP_KEEPCNT, count as libc::c_int)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_probes(_: &TcpStream, interval: Option<Duration>, count: Option<u32>) -> io::Result<()> {
+    if interval.is_some() || count.is_some() {
+        debug!("keepalive probe interval and count are only supported on Linux");
     }
+    Ok(())
 }
 
 pub mod accept {
-    use std::time::Duration;
     use tokio::io::{AsyncRead, AsyncWrite};
 
-    use super::SetKeepalive;
+    use super::{Keepalive, SetKeepalive};
     use svc;
 
-    pub fn layer(keepalive: Option<Duration>) -> Layer {
+    pub fn layer(keepalive: Option<Keepalive>) -> Layer {
         Layer { keepalive }
     }
 
     #[derive(Clone, Debug)]
     pub struct Layer {
-        keepalive: Option<Duration>,
+        keepalive: Option<Keepalive>,
     }
 
     #[derive(Clone, Debug)]
     pub struct Stack<M> {
-        keepalive: Option<Duration>,
+        keepalive: Option<Keepalive>,
         inner: M,
     }
 
     #[derive(Clone, Debug)]
     pub struct Accept<T> {
-        keepalive: Option<Duration>,
+        keepalive: Option<Keepalive>,
         inner: T,
     }
 
@@ -96,30 +164,29 @@ pub mod accept {
 
 pub mod connect {
     use futures::{Future, Poll};
-    use std::time::Duration;
 
-    use super::SetKeepalive;
+    use super::{Keepalive, SetKeepalive};
     use svc;
     use transport::connect;
 
-    pub fn layer(keepalive: Option<Duration>) -> Layer {
+    pub fn layer(keepalive: Option<Keepalive>) -> Layer {
         Layer { keepalive }
     }
 
     #[derive(Clone, Debug)]
     pub struct Layer {
-        keepalive: Option<Duration>,
+        keepalive: Option<Keepalive>,
     }
 
     #[derive(Clone, Debug)]
     pub struct Stack<M> {
-        keepalive: Option<Duration>,
+        keepalive: Option<Keepalive>,
         inner: M,
     }
 
     #[derive(Clone, Debug)]
     pub struct Connect<T> {
-        keepalive: Option<Duration>,
+        keepalive: Option<Keepalive>,
         inner: T,
     }
 
@@ -0,0 +1,214 @@
+use net2::TcpBuilder;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use tokio::net::TcpStream;
+
+use super::set_nodelay_or_warn;
+
+/// The accept backlog used by `std::net::TcpListener::bind`.
+pub const DEFAULT_BACKLOG: i32 = 128;
+
+/// Socket options applied to a listener and to the connections it accepts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ListenOptions {
+    /// The maximum number of pending connections queued by the kernel.
+    pub backlog: i32,
+
+    /// Whether `SO_REUSEPORT` is set, so that several processes may accept
+    /// connections on the same address. Only supported on Unix.
+    pub reuse_port: bool,
+
+    /// Whether `TCP_NODELAY` is set on accepted connections.
+    pub nodelay: bool,
+
+    /// When set, the `SO_RCVBUF` size of accepted connections.
+    pub recv_buffer_size: Option<usize>,
+
+    /// When set, the `SO_SNDBUF` size of accepted connections.
+    pub send_buffer_size: Option<usize>,
+
+    /// Whether `IP_TRANSPARENT` is set, so that connections redirected by
+    /// TPROXY may be accepted for any destination address. The original
+    /// destination of such a connection is its local address. Only supported
+    /// on Linux, and requires `CAP_NET_ADMIN`.
+    pub transparent: bool,
+
+    /// The number of sockets bound to the listener's address, each accepting
+    /// connections on its own task. When greater than one, `SO_REUSEPORT` is
+    /// set so that the kernel balances connections between the sockets.
+    pub acceptors: usize,
+}
+
+// === impl ListenOptions ===
+
+impl Default for ListenOptions {
+    fn default() -> Self {
+        Self {
+            backlog: DEFAULT_BACKLOG,
+            reuse_port: false,
+            nodelay: true,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            transparent: false,
+            acceptors: 1,
+        }
+    }
+}
+
+impl ListenOptions {
+    pub fn bind(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let builder = match addr {
+            SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+            SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+        };
+        self.set_reuse(&builder)?;
+        if self.transparent {
+            set_transparent(&builder, &addr)?;
+        }
+        builder.bind(addr)?;
+        builder.listen(self.backlog)
+    }
+
+    /// Sets the options of a newly-accepted connection.
+    pub fn set_accepted(&self, socket: &TcpStream) {
+        // TODO: On Linux and most other platforms it would be better to set the
+        // `TCP_NODELAY` option on the bound socket and then have the listening
+        // sockets inherit it. However, that doesn't work on all platforms and
+        // also the underlying libraries don't have the necessary API for that,
+        // so just do it here.
+        if self.nodelay {
+            set_nodelay_or_warn(socket);
+        }
+
+        if let Some(size) = self.recv_buffer_size {
+            if let Err(e) = socket.set_recv_buffer_size(size) {
+                warn!("could not set SO_RCVBUF on {:?}: {}", socket.peer_addr(), e);
+            }
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            if let Err(e) = socket.set_send_buffer_size(size) {
+                warn!("could not set SO_SNDBUF on {:?}: {}", socket.peer_addr(), e);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn set_reuse(&self, builder: &TcpBuilder) -> io::Result<()> {
+        use net2::unix::UnixTcpBuilderExt;
+
+        // `std::net::TcpListener::bind` sets `SO_REUSEADDR` on Unix, so that the
+        // address may be rebound while old connections are in `TIME_WAIT`.
+        builder.reuse_address(true)?;
+        if self.reuse_port || self.acceptors > 1 {
+            builder.reuse_port(true)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn set_reuse(&self, _: &TcpBuilder) -> io::Result<()> {
+        if self.reuse_port || self.acceptors > 1 {
+            warn!("SO_REUSEPORT is not supported on this platform");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_transparent(builder: &TcpBuilder, addr: &SocketAddr) -> io::Result<()> {
+    use libc;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    // From linux/in.h and linux/in6.h.
+    const IP_TRANSPARENT: libc::c_int = 19;
+    const IPV6_TRANSPARENT: libc::c_int = 75;
+
+    let (level, name) = match *addr {
+        SocketAddr::V4(_) => (libc::SOL_IP, IP_TRANSPARENT),
+        SocketAddr::V6(_) => (libc::SOL_IPV6, IPV6_TRANSPARENT),
+    };
+    let enabled: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            builder.as_raw_fd(),
+            level,
+            name,
+            &enabled as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_transparent(_: &TcpBuilder, _: &SocketAddr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "IP_TRANSPARENT is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream as StdStream;
+    use tokio::reactor::Handle;
+
+    fn localhost() -> SocketAddr {
+        "127.0.0.1:0".parse().unwrap()
+    }
+
+    #[test]
+    fn binds_a_listener() {
+        let listener = ListenOptions::default().bind(localhost()).expect("bind");
+        let addr = listener.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+        assert!(StdStream::connect(addr).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reuse_port_allows_several_listeners() {
+        let opts = ListenOptions {
+            reuse_port: true,
+            ..ListenOptions::default()
+        };
+        let first = opts.bind(localhost()).expect("first bind");
+        let addr = first.local_addr().unwrap();
+        let second = opts.bind(addr).expect("second bind");
+        assert_eq!(second.local_addr().unwrap(), addr);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn address_is_exclusive_without_reuse_port() {
+        let opts = ListenOptions::default();
+        let first = opts.bind(localhost()).expect("first bind");
+        let addr = first.local_addr().unwrap();
+        assert!(opts.bind(addr).is_err());
+    }
+
+    #[test]
+    fn sets_options_on_accepted_connections() {
+        let opts = ListenOptions {
+            recv_buffer_size: Some(64 * 1024),
+            send_buffer_size: Some(64 * 1024),
+            ..ListenOptions::default()
+        };
+        let listener = opts.bind(localhost()).expect("bind");
+        let _client = StdStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        let socket = TcpStream::from_std(accepted, &Handle::default()).unwrap();
+
+        opts.set_accepted(&socket);
+        assert!(socket.nodelay().unwrap());
+        // The kernel may round buffer sizes up, so only a lower bound holds.
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.send_buffer_size().unwrap() >= 64 * 1024);
+    }
+}
@@ -0,0 +1,90 @@
+//! Classifies peers by whether they participate in the mesh.
+//!
+//! A peer is _meshed_ when its identity was verified via TLS. Otherwise, it is
+//! _unmeshed_ when its address is in one of the cluster's networks (or is a
+//! loopback address, i.e. on the proxy's own host), or _external_ when it is
+//! not.
+
+use ipnet::{Contains, IpNet};
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use metrics::FmtLabels;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    Meshed,
+    Unmeshed,
+    External,
+}
+
+/// The networks that hold the cluster's pods.
+///
+/// The default value holds no networks, so that all peers without an identity
+/// are external.
+#[derive(Clone, Debug, Default)]
+pub struct Networks(Arc<Vec<IpNet>>);
+
+// === impl Status ===
+
+impl FmtLabels for Status {
+    fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Meshed => write!(f, "mesh=\"meshed\""),
+            Status::Unmeshed => write!(f, "mesh=\"unmeshed\""),
+            Status::External => write!(f, "mesh=\"external\""),
+        }
+    }
+}
+
+// === impl Networks ===
+
+impl Networks {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Networks(Arc::new(networks))
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| match (net, ip) {
+            (IpNet::V4(ref net), IpAddr::V4(ref ip)) => net.contains(ip),
+            (IpNet::V6(ref net), IpAddr::V6(ref ip)) => net.contains(ip),
+            _ => false,
+        })
+    }
+
+    /// Classifies a peer at `ip` that was (or was not) `meshed`.
+    pub fn status(&self, ip: IpAddr, meshed: bool) -> Status {
+        if meshed {
+            Status::Meshed
+        } else if ip.is_loopback() || self.contains(ip) {
+            Status::Unmeshed
+        } else {
+            Status::External
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_peers() {
+        let networks = Networks::new(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "fd00::/8".parse().unwrap(),
+        ]);
+        let in_cluster = "10.1.2.3".parse().unwrap();
+        let in_cluster6 = "fd00::1".parse().unwrap();
+        let external = "8.8.8.8".parse().unwrap();
+        let loopback = "127.0.0.1".parse().unwrap();
+
+        assert_eq!(networks.status(in_cluster, true), Status::Meshed);
+        assert_eq!(networks.status(external, true), Status::Meshed);
+        assert_eq!(networks.status(in_cluster, false), Status::Unmeshed);
+        assert_eq!(networks.status(in_cluster6, false), Status::Unmeshed);
+        assert_eq!(networks.status(loopback, false), Status::Unmeshed);
+        assert_eq!(networks.status(external, false), Status::External);
+    }
+}
@@ -158,7 +158,16 @@ where
     type Error = C::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let io = try_ready!(self.underlying.poll());
+        let io = match self.underlying.poll() {
+            Ok(Async::Ready(io)) => io,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                if let Some(new_sensor) = self.new_sensor.take() {
+                    new_sensor.connect_failed();
+                }
+                return Err(e);
+            }
+        };
         debug!("client connection open");
 
         let sensor = self
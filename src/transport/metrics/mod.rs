@@ -7,10 +7,11 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 use metrics::{latency, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge, Histogram, Metric};
 
+use identity;
 use proxy;
 use svc;
 use telemetry::Errno;
-use transport::{connect, tls};
+use transport::{connect, mesh, tls};
 
 mod io;
 
@@ -21,14 +22,23 @@ metrics! {
     tcp_open_connections: Gauge { "Number of currently-open connections" },
     tcp_read_bytes_total: Counter { "Total count of bytes read from peers" },
     tcp_write_bytes_total: Counter { "Total count of bytes written to peers" },
+    tcp_connect_errors_total: Counter { "Total count of connections that could not be opened" },
 
     tcp_close_total: Counter { "Total count of closed connections" },
     tcp_connection_duration_ms: Histogram<latency::Ms> { "Connection lifetimes" }
 }
 
-pub fn new() -> (Registry, Report) {
+/// Connections are labeled with the mesh status of their peers, relative to
+/// the given cluster `networks`.
+pub fn new(networks: mesh::Networks) -> (Registry, Report) {
     let inner = Arc::new(Mutex::new(Inner::default()));
-    (Registry(inner.clone()), Report(inner))
+    (
+        Registry {
+            inner: inner.clone(),
+            networks,
+        },
+        Report(inner),
+    )
 }
 
 /// Implements `FmtMetrics` to render prometheus-formatted metrics for all transports.
@@ -36,12 +46,16 @@ pub fn new() -> (Registry, Report) {
 pub struct Report(Arc<Mutex<Inner>>);
 
 #[derive(Clone, Debug, Default)]
-pub struct Registry(Arc<Mutex<Inner>>);
+pub struct Registry {
+    inner: Arc<Mutex<Inner>>,
+    networks: mesh::Networks,
+}
 
 #[derive(Debug)]
 pub struct LayerAccept<I, M> {
     direction: Direction,
     registry: Arc<Mutex<Inner>>,
+    networks: mesh::Networks,
     _p: PhantomData<fn() -> (I, M)>,
 }
 
@@ -50,6 +64,7 @@ pub struct StackAccept<I, M> {
     inner: M,
     direction: Direction,
     registry: Arc<Mutex<Inner>>,
+    networks: mesh::Networks,
     _p: PhantomData<fn() -> (I)>,
 }
 
@@ -64,6 +79,7 @@ pub struct Accept<I, A> {
 pub struct LayerConnect<T, M> {
     direction: Direction,
     registry: Arc<Mutex<Inner>>,
+    networks: mesh::Networks,
     _p: PhantomData<fn() -> (T, M)>,
 }
 
@@ -72,6 +88,7 @@ pub struct StackConnect<T, M> {
     inner: M,
     direction: Direction,
     registry: Arc<Mutex<Inner>>,
+    networks: mesh::Networks,
     _p: PhantomData<fn() -> (T)>,
 }
 
@@ -80,11 +97,15 @@ pub struct StackConnect<T, M> {
 /// A `Metrics` type exists for each unique `Key`.
 ///
 /// Implements `FmtLabels`.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 struct Key {
     direction: Direction,
     peer: Peer,
     tls_status: tls::Status,
+    mesh: mesh::Status,
+    /// The peer's TLS identity, i.e. the client's identity for accepted
+    /// connections and the server's identity for opened connections.
+    peer_id: Option<identity::Name>,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -108,6 +129,7 @@ struct Metrics {
     open_connections: Gauge,
     write_bytes_total: Counter,
     read_bytes_total: Counter,
+    connect_errors_total: Counter,
 
     by_eos: IndexMap<Eos, EosMetrics>,
 }
@@ -205,16 +227,16 @@ impl Registry {
         M: svc::Stack<proxy::Source>,
         M::Value: proxy::Accept<I>,
     {
-        LayerAccept::new(direction, self.0.clone())
+        LayerAccept::new(direction, self.inner.clone(), self.networks.clone())
     }
 
     pub fn connect<T, M>(&self, direction: &'static str) -> LayerConnect<T, M>
     where
-        T: tls::HasPeerIdentity + Clone,
+        T: tls::HasPeerIdentity + connect::HasPeerAddr + Clone,
         M: svc::Stack<T>,
         M::Value: connect::Connect,
     {
-        LayerConnect::new(direction, self.0.clone())
+        LayerConnect::new(direction, self.inner.clone(), self.networks.clone())
     }
 }
 
@@ -224,10 +246,11 @@ where
     M: svc::Stack<proxy::Source>,
     M::Value: proxy::Accept<I>,
 {
-    fn new(d: &'static str, registry: Arc<Mutex<Inner>>) -> Self {
+    fn new(d: &'static str, registry: Arc<Mutex<Inner>>, networks: mesh::Networks) -> Self {
         Self {
             direction: Direction(d),
             registry,
+            networks,
             _p: PhantomData,
         }
     }
@@ -240,7 +263,11 @@ where
     M::Value: proxy::Accept<I>,
 {
     fn clone(&self) -> Self {
-        Self::new(self.direction.0, self.registry.clone())
+        Self::new(
+            self.direction.0,
+            self.registry.clone(),
+            self.networks.clone(),
+        )
     }
 }
 
@@ -259,6 +286,7 @@ where
             inner,
             direction: self.direction,
             registry: self.registry.clone(),
+            networks: self.networks.clone(),
             _p: PhantomData,
         }
     }
@@ -275,6 +303,7 @@ where
             inner: self.inner.clone(),
             direction: self.direction,
             registry: self.registry.clone(),
+            networks: self.networks.clone(),
             _p: PhantomData,
         }
     }
@@ -290,9 +319,12 @@ where
     type Error = M::Error;
 
     fn make(&self, source: &proxy::Source) -> Result<Self::Value, Self::Error> {
-        // TODO use source metadata in `key`
         let tls_status = source.tls_peer.as_ref().map(|_| {});
-        let key = Key::accept(self.direction, tls_status);
+        let mesh = self
+            .networks
+            .status(source.remote.ip(), tls_status.is_some());
+        let peer_id = source.tls_peer.value().cloned();
+        let key = Key::accept(self.direction, tls_status, mesh, peer_id);
         let metrics = match self.registry.lock() {
             Ok(mut inner) => Some(inner.get_or_default(key).clone()),
             Err(_) => {
@@ -325,14 +357,15 @@ where
 
 impl<T, M> LayerConnect<T, M>
 where
-    T: tls::HasPeerIdentity + Clone,
+    T: tls::HasPeerIdentity + connect::HasPeerAddr + Clone,
     M: svc::Stack<T>,
     M::Value: connect::Connect,
 {
-    fn new(d: &'static str, registry: Arc<Mutex<Inner>>) -> Self {
+    fn new(d: &'static str, registry: Arc<Mutex<Inner>>, networks: mesh::Networks) -> Self {
         Self {
             direction: Direction(d),
             registry,
+            networks,
             _p: PhantomData,
         }
     }
@@ -340,18 +373,22 @@ where
 
 impl<T, M> Clone for LayerConnect<T, M>
 where
-    T: tls::HasPeerIdentity + Clone,
+    T: tls::HasPeerIdentity + connect::HasPeerAddr + Clone,
     M: svc::Stack<T>,
     M::Value: connect::Connect,
 {
     fn clone(&self) -> Self {
-        Self::new(self.direction.0, self.registry.clone())
+        Self::new(
+            self.direction.0,
+            self.registry.clone(),
+            self.networks.clone(),
+        )
     }
 }
 
 impl<T, M> svc::Layer<T, T, M> for LayerConnect<T, M>
 where
-    T: tls::HasPeerIdentity + Clone,
+    T: tls::HasPeerIdentity + connect::HasPeerAddr + Clone,
     M: svc::Stack<T>,
     M::Value: connect::Connect,
 {
@@ -364,6 +401,7 @@ where
             inner,
             direction: self.direction,
             registry: self.registry.clone(),
+            networks: self.networks.clone(),
             _p: PhantomData,
         }
     }
@@ -371,7 +409,7 @@ where
 
 impl<T, M> Clone for StackConnect<T, M>
 where
-    T: tls::HasPeerIdentity + Clone,
+    T: tls::HasPeerIdentity + connect::HasPeerAddr + Clone,
     M: svc::Stack<T> + Clone,
     M::Value: connect::Connect,
 {
@@ -380,6 +418,7 @@ where
             inner: self.inner.clone(),
             direction: self.direction,
             registry: self.registry.clone(),
+            networks: self.networks.clone(),
             _p: PhantomData,
         }
     }
@@ -387,7 +426,7 @@ where
 
 impl<T, M> svc::Stack<T> for StackConnect<T, M>
 where
-    T: tls::HasPeerIdentity + Clone,
+    T: tls::HasPeerIdentity + connect::HasPeerAddr + Clone,
     M: svc::Stack<T>,
     M::Value: connect::Connect,
 {
@@ -395,9 +434,13 @@ where
     type Error = M::Error;
 
     fn make(&self, target: &T) -> Result<Self::Value, Self::Error> {
-        // TODO use target metadata in `key`
-        let tls_status = target.peer_identity().as_ref().map(|_| ());
-        let key = Key::connect(self.direction, tls_status);
+        let peer_identity = target.peer_identity();
+        let tls_status = peer_identity.as_ref().map(|_| ());
+        let mesh = self
+            .networks
+            .status(target.peer_addr().ip(), tls_status.is_some());
+        let peer_id = peer_identity.value().cloned();
+        let key = Key::connect(self.direction, tls_status, mesh, peer_id);
         let metrics = match self.registry.lock() {
             Ok(mut inner) => Some(inner.get_or_default(key).clone()),
             Err(_) => {
@@ -436,6 +479,12 @@ impl FmtMetrics for Report {
         tcp_write_bytes_total.fmt_help(f)?;
         metrics.fmt_by(f, tcp_write_bytes_total, |m| &m.write_bytes_total)?;
 
+        tcp_connect_errors_total.fmt_help(f)?;
+        for (key, m) in metrics.iter().filter(|&(k, _)| k.peer == Peer::Dst) {
+            m.connect_errors_total
+                .fmt_metric_labeled(f, tcp_connect_errors_total.name, key)?;
+        }
+
         tcp_close_total.fmt_help(f)?;
         metrics.fmt_eos_by(f, tcp_close_total, |e| &e.close_total)?;
 
@@ -507,31 +556,60 @@ impl NewSensor {
     fn new_sensor(mut self) -> Sensor {
         Sensor::open(self.0.take())
     }
+
+    /// Records that the connection could not be opened.
+    fn connect_failed(self) {
+        if let Some(ref m) = self.0 {
+            if let Ok(mut m) = m.lock() {
+                m.connect_errors_total.incr();
+            }
+        }
+    }
 }
 
 // ===== impl Key =====
 
 impl Key {
-    pub fn accept(direction: Direction, tls_status: tls::Status) -> Self {
+    pub fn accept(
+        direction: Direction,
+        tls_status: tls::Status,
+        mesh: mesh::Status,
+        client_id: Option<identity::Name>,
+    ) -> Self {
         Self {
             peer: Peer::Src,
             direction,
             tls_status,
+            mesh,
+            peer_id: client_id,
         }
     }
 
-    pub fn connect(direction: Direction, tls_status: tls::Status) -> Self {
+    pub fn connect(
+        direction: Direction,
+        tls_status: tls::Status,
+        mesh: mesh::Status,
+        server_id: Option<identity::Name>,
+    ) -> Self {
         Self {
             direction,
             peer: Peer::Dst,
             tls_status,
+            mesh,
+            peer_id: server_id,
         }
     }
 }
 
 impl FmtLabels for Key {
     fn fmt_labels(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        ((self.direction, self.peer), self.tls_status).fmt_labels(f)
+        (((self.direction, self.peer), self.tls_status), self.mesh).fmt_labels(f)?;
+
+        match (self.peer, self.peer_id.as_ref()) {
+            (Peer::Src, Some(id)) => write!(f, ",client_id=\"{}\"", id.as_ref()),
+            (Peer::Dst, Some(id)) => write!(f, ",server_id=\"{}\"", id.as_ref()),
+            (_, None) => Ok(()),
+        }
     }
 }
 
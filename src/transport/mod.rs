@@ -1,17 +1,24 @@
 mod addr_info;
 pub mod connect;
+mod connect_options;
 mod io;
 pub mod keepalive;
+mod listen_options;
+pub mod mesh;
 pub mod metrics;
 mod peek;
 mod prefixed;
+pub mod proxy_protocol;
 pub mod tls;
+pub mod unix;
 
 pub use self::{
     addr_info::{AddrInfo, GetOriginalDst, SoOriginalDst},
     connect::Connect,
+    connect_options::ConnectOptions,
     io::BoxedIo,
-    keepalive::SetKeepalive,
+    keepalive::{Keepalive, SetKeepalive},
+    listen_options::ListenOptions,
     peek::Peek,
     tls::{Connection, Listen},
 };
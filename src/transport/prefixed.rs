@@ -3,7 +3,7 @@ use std::{cmp, fmt::Debug, io, net::SocketAddr};
 use tokio::prelude::*;
 
 use super::io::internal::Io;
-use transport::{AddrInfo, SetKeepalive};
+use transport::{AddrInfo, Keepalive, SetKeepalive};
 
 /// A TcpStream where the initial reads will be served from `prefix`.
 #[derive(Debug)]
@@ -103,7 +103,7 @@ where
         self.io.keepalive()
     }
 
-    fn set_keepalive(&mut self, ka: Option<::std::time::Duration>) -> io::Result<()> {
+    fn set_keepalive(&mut self, ka: Option<Keepalive>) -> io::Result<()> {
         self.io.set_keepalive(ka)
     }
 }
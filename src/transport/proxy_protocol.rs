@@ -0,0 +1,346 @@
+//! Reads and writes PROXY protocol headers.
+//!
+//! An L4 load balancer may prefix each connection with a PROXY protocol header
+//! that describes the client's original address, which would otherwise be
+//! hidden by the load balancer's own. Both the human-readable v1 and binary v2
+//! formats are read; v2 headers are written.
+//!
+//! Since a header may describe any address, headers are only read from the
+//! load balancers' networks.
+//!
+//! See <https://www.haproxy.org/download/1.8/doc/proxy-protocol.txt>.
+
+use bytes::Bytes;
+use futures::{future, Async, Future, Poll};
+use indexmap::IndexSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use std::{io, str};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use transport::mesh;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The shortest valid header, `PROXY UNKNOWN\r\n`, is read before the
+/// header's format is known, so that no bytes following it are consumed.
+const MIN_LEN: usize = 15;
+const V1_MAX_LEN: usize = 107;
+const V2_HEADER_LEN: usize = 16;
+
+/// Configures which accepted connections begin with a PROXY protocol header.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub ports: IndexSet<u16>,
+    /// The networks of the peers that may send headers. Connections to
+    /// `ports` from other peers are closed.
+    pub sources: mesh::Networks,
+    /// How long a peer may take to send its header.
+    pub timeout: Duration,
+}
+
+/// The addresses described by a PROXY protocol header.
+///
+/// Addresses are not described when the connection was made by the load
+/// balancer itself (e.g. for a health check), or when its protocol is not
+/// supported.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+}
+
+/// Reads a PROXY protocol header from the start of a connection.
+///
+/// The header is read without reading any of the bytes that follow it, so the
+/// connection may be used as if the header had not been sent.
+pub struct ReadHeader<I> {
+    io: Option<I>,
+    buf: Vec<u8>,
+}
+
+enum Parse {
+    /// The header is incomplete; at least this many more bytes are needed.
+    Incomplete(usize),
+    Complete(Header),
+}
+
+pub fn read_header<I: AsyncRead>(io: I) -> ReadHeader<I> {
+    ReadHeader {
+        io: Some(io),
+        buf: Vec::with_capacity(V1_MAX_LEN),
+    }
+}
+
+/// Writes a v2 header describing a connection from `source` to `destination`
+/// to the start of `io`.
+pub fn write_header<I: AsyncWrite>(
+    io: I,
+    source: SocketAddr,
+    destination: SocketAddr,
+) -> impl Future<Item = I, Error = io::Error> {
+    ::tokio::io::write_all(io, encode_v2(source, destination)).map(|(io, _)| io)
+}
+
+/// Writes `header` to the start of `io`, if there is one.
+pub fn write_optional_header<I: AsyncWrite>(
+    io: I,
+    header: Option<(SocketAddr, SocketAddr)>,
+) -> impl Future<Item = I, Error = io::Error> {
+    match header {
+        Some((src, dst)) => future::Either::A(write_header(io, src, dst)),
+        None => future::Either::B(future::ok(io)),
+    }
+}
+
+/// Encodes a v2 header for a TCP connection.
+///
+/// When the addresses' families differ, both are encoded as IPv6 addresses.
+pub fn encode_v2(source: SocketAddr, destination: SocketAddr) -> Bytes {
+    let mut buf = Vec::with_capacity(V2_HEADER_LEN + 36);
+    buf.extend_from_slice(V2_SIGNATURE);
+    // Version 2, PROXY command.
+    buf.push(0x21);
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            // TCP over IPv4.
+            buf.push(0x11);
+            buf.extend_from_slice(&12u16.to_be_bytes());
+            buf.extend_from_slice(&src.octets());
+            buf.extend_from_slice(&dst.octets());
+        }
+        (src, dst) => {
+            // TCP over IPv6.
+            buf.push(0x21);
+            buf.extend_from_slice(&36u16.to_be_bytes());
+            buf.extend_from_slice(&to_ipv6(src).octets());
+            buf.extend_from_slice(&to_ipv6(dst).octets());
+        }
+    }
+    buf.extend_from_slice(&source.port().to_be_bytes());
+    buf.extend_from_slice(&destination.port().to_be_bytes());
+    buf.into()
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn invalid() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid PROXY protocol header")
+}
+
+/// Parses a header from the start of `buf`, which must not extend past the
+/// end of the header.
+fn parse(buf: &[u8]) -> Result<Parse, io::Error> {
+    // Fail as soon as the buffer can't be the start of either format.
+    let prefix_len = buf.len().min(V2_SIGNATURE.len());
+    let is_v2 = buf[..prefix_len] == V2_SIGNATURE[..prefix_len];
+    let prefix_len = buf.len().min(V1_PREFIX.len());
+    let is_v1 = buf[..prefix_len] == V1_PREFIX[..prefix_len];
+
+    if buf.len() < MIN_LEN {
+        if !is_v1 && !is_v2 {
+            return Err(invalid());
+        }
+        return Ok(Parse::Incomplete(MIN_LEN - buf.len()));
+    }
+
+    if is_v2 {
+        if buf.len() < V2_HEADER_LEN {
+            return Ok(Parse::Incomplete(V2_HEADER_LEN - buf.len()));
+        }
+        let len = V2_HEADER_LEN + ((buf[14] as usize) << 8 | buf[15] as usize);
+        if buf.len() < len {
+            return Ok(Parse::Incomplete(len - buf.len()));
+        }
+        return parse_v2(&buf[12..]).map(Parse::Complete);
+    }
+
+    if is_v1 {
+        if buf.ends_with(b"\r\n") {
+            return parse_v1(&buf[V1_PREFIX.len()..buf.len() - 2]).map(Parse::Complete);
+        }
+        if buf.len() >= V1_MAX_LEN {
+            return Err(invalid());
+        }
+        // The end of the line is not known, so it is read a byte at a time.
+        return Ok(Parse::Incomplete(1));
+    }
+
+    Err(invalid())
+}
+
+/// Parses a v1 header's fields, e.g. `TCP4 192.0.2.1 198.51.100.1 56324 443`.
+fn parse_v1(line: &[u8]) -> Result<Header, io::Error> {
+    let line = str::from_utf8(line).map_err(|_| invalid())?;
+    let mut fields = line.split(' ');
+    match fields.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(Header::default()),
+        _ => return Err(invalid()),
+    }
+
+    let mut next = || fields.next().ok_or_else(invalid);
+    let src_ip = next()?.parse::<IpAddr>().map_err(|_| invalid())?;
+    let dst_ip = next()?.parse::<IpAddr>().map_err(|_| invalid())?;
+    let src_port = next()?.parse::<u16>().map_err(|_| invalid())?;
+    let dst_port = next()?.parse::<u16>().map_err(|_| invalid())?;
+
+    Ok(Header {
+        source: Some(SocketAddr::new(src_ip, src_port)),
+        destination: Some(SocketAddr::new(dst_ip, dst_port)),
+    })
+}
+
+/// Parses a v2 header, starting from its version and command.
+fn parse_v2(buf: &[u8]) -> Result<Header, io::Error> {
+    let (ver_cmd, family, addrs) = (buf[0], buf[1], &buf[4..]);
+    match ver_cmd {
+        // LOCAL: the connection was made by the load balancer itself.
+        0x20 => return Ok(Header::default()),
+        // PROXY
+        0x21 => {}
+        _ => return Err(invalid()),
+    }
+
+    let port = |b: &[u8]| u16::from(b[0]) << 8 | u16::from(b[1]);
+    match family {
+        // TCP or UDP over IPv4.
+        0x11 | 0x12 if addrs.len() >= 12 => {
+            let src = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            let dst = Ipv4Addr::new(addrs[4], addrs[5], addrs[6], addrs[7]);
+            Ok(Header {
+                source: Some(SocketAddr::new(src.into(), port(&addrs[8..10]))),
+                destination: Some(SocketAddr::new(dst.into(), port(&addrs[10..12]))),
+            })
+        }
+        // TCP or UDP over IPv6.
+        0x21 | 0x22 if addrs.len() >= 36 => {
+            let mut src = [0u8; 16];
+            let mut dst = [0u8; 16];
+            src.copy_from_slice(&addrs[0..16]);
+            dst.copy_from_slice(&addrs[16..32]);
+            Ok(Header {
+                source: Some(SocketAddr::new(
+                    Ipv6Addr::from(src).into(),
+                    port(&addrs[32..34]),
+                )),
+                destination: Some(SocketAddr::new(
+                    Ipv6Addr::from(dst).into(),
+                    port(&addrs[34..36]),
+                )),
+            })
+        }
+        0x11 | 0x12 | 0x21 | 0x22 => Err(invalid()),
+        // Unspecified or UNIX sockets, which do not describe IP addresses.
+        _ => Ok(Header::default()),
+    }
+}
+
+// === impl ReadHeader ===
+
+impl<I: AsyncRead> Future for ReadHeader<I> {
+    type Item = (I, Header);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let need = match parse(&self.buf)? {
+                Parse::Complete(header) => {
+                    let io = self.io.take().expect("polled after ready");
+                    return Ok(Async::Ready((io, header)));
+                }
+                Parse::Incomplete(need) => need,
+            };
+
+            let start = self.buf.len();
+            self.buf.resize(start + need, 0);
+            let read = self
+                .io
+                .as_mut()
+                .expect("polled after ready")
+                .poll_read(&mut self.buf[start..]);
+            let sz = match read {
+                Ok(Async::Ready(sz)) => sz,
+                Ok(Async::NotReady) => {
+                    self.buf.truncate(start);
+                    return Ok(Async::NotReady);
+                }
+                Err(e) => return Err(e),
+            };
+            self.buf.truncate(start + sz);
+            if sz == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(buf: &[u8]) -> Result<Header, io::Error> {
+        // Parse the buffer as it would be read, so that the parser never sees
+        // bytes beyond the end of the header.
+        let mut len = 0;
+        loop {
+            match parse(&buf[..len])? {
+                Parse::Complete(header) => {
+                    assert_eq!(len, buf.len(), "header must end the buffer");
+                    return Ok(header);
+                }
+                Parse::Incomplete(need) => {
+                    len += need;
+                    assert!(len <= buf.len(), "read beyond the end of the header");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parses_v1() {
+        let header = parse_all(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n").unwrap();
+        assert_eq!(header.source, Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(
+            header.destination,
+            Some("198.51.100.1:443".parse().unwrap())
+        );
+
+        let header = parse_all(b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n").unwrap();
+        assert_eq!(header.source, Some("[2001:db8::1]:56324".parse().unwrap()));
+
+        assert_eq!(parse_all(b"PROXY UNKNOWN\r\n").unwrap(), Header::default());
+        assert!(parse_all(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n").is_err());
+        assert!(parse_all(b"GET / HTTP/1.1\r\n").is_err());
+    }
+
+    #[test]
+    fn round_trips_v2() {
+        let src = "192.0.2.1:56324".parse().unwrap();
+        let dst = "198.51.100.1:443".parse().unwrap();
+        let header = parse_all(&encode_v2(src, dst)).unwrap();
+        assert_eq!(header.source, Some(src));
+        assert_eq!(header.destination, Some(dst));
+
+        let src = "[2001:db8::1]:56324".parse().unwrap();
+        let header = parse_all(&encode_v2(src, dst)).unwrap();
+        assert_eq!(header.source, Some(src));
+        assert_eq!(
+            header.destination,
+            Some("[::ffff:198.51.100.1]:443".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_v2_local() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(parse_all(&buf).unwrap(), Header::default());
+    }
+}
@@ -2,6 +2,8 @@ use futures::{Async, Future, Poll};
 use std::sync::Arc;
 use std::{fmt, io};
 
+use super::rustls::Session;
+
 use identity;
 use svc;
 use transport::{connect, io::internal::Io, tls, BoxedIo, Connection};
@@ -26,6 +28,8 @@ pub struct Stack<L, S> {
 pub struct Connect<L, C> {
     inner: C,
     tls: tls::Conditional<(identity::Name, L)>,
+    /// Overrides the SNI name, which is otherwise the peer's identity.
+    sni: Option<identity::Name>,
 }
 
 /// A socket that is in the process of connecting.
@@ -33,10 +37,12 @@ pub enum ConnectFuture<L, F: Future> {
     Init {
         future: F,
         tls: tls::Conditional<(identity::Name, L)>,
+        sni: Option<identity::Name>,
     },
     Handshake {
         future: tls::tokio_rustls::Connect<F::Item>,
         server_name: identity::Name,
+        sni: Option<identity::Name>,
     },
 }
 
@@ -87,7 +93,8 @@ where
         let inner = self.inner.make(&target)?;
         let server_name = target.peer_identity();
         let tls = self.local.clone().and_then(|l| server_name.map(|n| (n, l)));
-        Ok(Connect { inner, tls })
+        let sni = target.peer_server_name();
+        Ok(Connect { inner, tls, sni })
     }
 }
 
@@ -110,6 +117,7 @@ where
         ConnectFuture::Init {
             future: self.inner.connect(),
             tls: self.tls.clone(),
+            sni: self.sni.clone(),
         }
     }
 }
@@ -129,17 +137,24 @@ where
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         loop {
             *self = match self {
-                ConnectFuture::Init { future, tls } => {
+                ConnectFuture::Init { future, tls, sni } => {
                     let io = try_ready!(future.poll());
 
                     match tls {
                         Conditional::Some((server_name, local_tls)) => {
-                            trace!("initiating TLS to {}", server_name.as_ref());
+                            let sni = sni.take();
+                            let dns_name = sni.as_ref().unwrap_or(server_name).clone();
+                            trace!(
+                                "initiating TLS to {} (SNI {})",
+                                server_name.as_ref(),
+                                dns_name.as_ref()
+                            );
                             let future = tls::Connector::from(local_tls.tls_client_config())
-                                .connect(server_name.as_dns_name_ref(), io);
+                                .connect(dns_name.as_dns_name_ref(), io);
                             ConnectFuture::Handshake {
                                 future,
                                 server_name: server_name.clone(),
+                                sni,
                             }
                         }
                         Conditional::None(why) => {
@@ -151,8 +166,22 @@ where
                 ConnectFuture::Handshake {
                     future,
                     server_name,
+                    sni,
                 } => {
                     let io = try_ready!(future.poll());
+                    // The certificate has been verified for the SNI name, but
+                    // the verifier accepts any SVID in a trust domain of that
+                    // name, so it must also be verified for the expected
+                    // identity.
+                    let certs = io.get_ref().1.get_peer_certificates();
+                    if !is_valid_for(certs.as_ref().and_then(|c| c.first()), server_name) {
+                        let msg = format!(
+                            "certificate for SNI {:?} is not valid for identity {:?}",
+                            sni, server_name
+                        );
+                        let e = io::Error::new(io::ErrorKind::InvalidData, msg);
+                        return Err(F::Error::from(e));
+                    }
                     let io = BoxedIo::new(super::TlsIo::from(io));
                     trace!("established TLS to {}", server_name.as_ref());
                     let c = Connection::tls(io, Conditional::Some(server_name.clone()));
@@ -162,3 +191,107 @@ where
         }
     }
 }
+
+/// Checks that an end-entity certificate is valid for `name`.
+fn is_valid_for(cert: Option<&super::rustls::Certificate>, name: &identity::Name) -> bool {
+    cert.map(|c| name.is_valid_for_crt(c.as_ref()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::net::SocketAddr;
+    use svc::Stack as _Stack;
+    use transport::tls::{HasPeerIdentity, PeerIdentity, ReasonForNoIdentity, ReasonForNoPeerName};
+
+    const FOO_NS1: &str = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+    const BAR_NS1: &str = "bar.ns1.serviceaccount.identity.linkerd.cluster.local";
+    const NO_PEER_NAME: ReasonForNoIdentity =
+        ReasonForNoIdentity::NoPeerName(ReasonForNoPeerName::NotProvidedByServiceDiscovery);
+
+    #[derive(Clone, Debug)]
+    struct Local;
+
+    impl HasConfig for Local {
+        fn tls_client_config(&self) -> Arc<Config> {
+            Arc::new(Config::new())
+        }
+    }
+
+    struct Target {
+        identity: PeerIdentity,
+        server_name: Option<identity::Name>,
+    }
+
+    impl HasPeerIdentity for Target {
+        fn peer_identity(&self) -> PeerIdentity {
+            self.identity.clone()
+        }
+
+        fn peer_server_name(&self) -> Option<identity::Name> {
+            self.server_name.clone()
+        }
+    }
+
+    impl connect::HasPeerAddr for Target {
+        fn peer_addr(&self) -> SocketAddr {
+            ([127, 0, 0, 1], 4143).into()
+        }
+    }
+
+    fn name(s: &str) -> identity::Name {
+        identity::Name::parse(s.as_bytes()).expect("name must be valid")
+    }
+
+    fn make(target: &Target) -> Connect<Local, connect::ConnectSocketAddr> {
+        let stack = Stack {
+            local: Conditional::Some(Local),
+            inner: connect::Stack::new(),
+        };
+        stack.make(target).expect("connect must be built")
+    }
+
+    #[test]
+    fn server_name_overrides_sni() {
+        let c = make(&Target {
+            identity: Conditional::Some(name(FOO_NS1)),
+            server_name: Some(name("web.example.com")),
+        });
+        match c.tls {
+            Conditional::Some((ref id, _)) => assert_eq!(id, &name(FOO_NS1)),
+            Conditional::None(why) => panic!("TLS must be used: {:?}", why),
+        }
+        assert_eq!(c.sni, Some(name("web.example.com")));
+
+        let c = make(&Target {
+            identity: Conditional::Some(name(FOO_NS1)),
+            server_name: None,
+        });
+        assert_eq!(c.sni, None);
+    }
+
+    #[test]
+    fn server_name_does_not_enable_tls() {
+        let c = make(&Target {
+            identity: Conditional::None(NO_PEER_NAME),
+            server_name: Some(name("web.example.com")),
+        });
+        match c.tls {
+            Conditional::None(why) => assert_eq!(why, NO_PEER_NAME),
+            Conditional::Some((id, _)) => panic!("TLS must not be used: {:?}", id),
+        }
+    }
+
+    #[test]
+    fn crts_must_be_valid_for_the_peer_identity() {
+        let der = fs::read("src/identity/testdata/foo-ns1-ca1/crt.der").unwrap();
+        let crt = super::super::rustls::Certificate(der);
+        assert!(is_valid_for(Some(&crt), &name(FOO_NS1)));
+        // The certificate may be valid for an overridden SNI name, but the
+        // connection is rejected unless it is also valid for the identity.
+        assert!(!is_valid_for(Some(&crt), &name(BAR_NS1)));
+        assert!(!is_valid_for(None, &name(FOO_NS1)));
+    }
+}
@@ -14,7 +14,7 @@ pub enum Match {
 ///
 /// The determination is made based on whether the input looks like (the start
 /// of) a valid ClientHello that a reasonable TLS client might send, and the
-/// SNI matches the given identity.
+/// SNI matches any of the given identities.
 ///
 /// XXX: Once the TLS record header is matched, the determination won't be
 /// made until the entire TLS record including the entire ClientHello handshake
@@ -23,17 +23,12 @@ pub enum Match {
 /// This assumes that the ClientHello is small and is sent in a single TLS
 /// record, which is what all reasonable implementations do. (If they were not
 /// to, they wouldn't interoperate with picky servers.)
-pub fn match_client_hello(input: &[u8], identity: &identity::Name) -> Match {
-    let r = untrusted::Input::from(input).read_all(untrusted::EndOfInput, |input| {
-        let r = extract_sni(input);
-        input.skip_to_end(); // Ignore anything after what we parsed.
-        r
-    });
-    match r {
+pub fn match_client_hello(input: &[u8], identities: &[identity::Name]) -> Match {
+    match parse_sni(input) {
         Ok(Some(sni)) => {
             let m = identity::Name::from_hostname(sni.as_slice_less_safe())
                 .map(|sni| {
-                    if sni == *identity {
+                    if identities.iter().any(|id| sni.sni() == id.sni()) {
                         Match::Matched
                     } else {
                         Match::NotMatched
@@ -57,6 +52,26 @@ pub fn match_client_hello(input: &[u8], identity: &identity::Name) -> Match {
     }
 }
 
+/// Returns the SNI of the ClientHello at the start of `input`, if the input
+/// looks like a ClientHello and its SNI is a valid hostname.
+///
+/// This describes TLS connections that the proxy does not terminate, so
+/// `input` is expected to hold the entire ClientHello.
+pub fn client_hello_sni(input: &[u8]) -> Option<identity::Name> {
+    match parse_sni(input) {
+        Ok(Some(sni)) => identity::Name::from_hostname(sni.as_slice_less_safe()).ok(),
+        Ok(None) | Err(untrusted::EndOfInput) => None,
+    }
+}
+
+fn parse_sni(input: &[u8]) -> Result<Option<untrusted::Input>, untrusted::EndOfInput> {
+    untrusted::Input::from(input).read_all(untrusted::EndOfInput, |input| {
+        let r = extract_sni(input);
+        input.skip_to_end(); // Ignore anything after what we parsed.
+        r
+    })
+}
+
 /// The result is `Ok(Some(hostname))` if the SNI extension was found, `Ok(None)`
 /// if we affirmatively rejected the input before we found the SNI extension, or
 /// `Err(EndOfInput)` if we don't have enough input to continue.
@@ -205,48 +220,74 @@ mod tests {
 
     #[test]
     fn matches() {
-        check_all_prefixes(Match::Matched, "example.com", VALID_EXAMPLE_COM);
+        check_all_prefixes(Match::Matched, &["example.com"], VALID_EXAMPLE_COM);
+    }
+
+    #[test]
+    fn matches_any_identity() {
+        check_all_prefixes(
+            Match::Matched,
+            &["example.org", "example.com"],
+            VALID_EXAMPLE_COM,
+        );
     }
 
     #[test]
     fn mismatch_different_sni() {
-        check_all_prefixes(Match::NotMatched, "example.org", VALID_EXAMPLE_COM);
+        check_all_prefixes(Match::NotMatched, &["example.org"], VALID_EXAMPLE_COM);
     }
 
     #[test]
     fn mismatch_truncated_sni() {
-        check_all_prefixes(Match::NotMatched, "example.coma", VALID_EXAMPLE_COM);
+        check_all_prefixes(Match::NotMatched, &["example.coma"], VALID_EXAMPLE_COM);
     }
 
     #[test]
     fn mismatch_appended_sni() {
-        check_all_prefixes(Match::NotMatched, "example.co", VALID_EXAMPLE_COM);
+        check_all_prefixes(Match::NotMatched, &["example.co"], VALID_EXAMPLE_COM);
     }
 
     #[test]
     fn mismatch_prepended_sni() {
-        check_all_prefixes(Match::NotMatched, "aexample.com", VALID_EXAMPLE_COM);
+        check_all_prefixes(Match::NotMatched, &["aexample.com"], VALID_EXAMPLE_COM);
     }
 
     #[test]
     fn mismatch_http_1_0_request() {
         check_all_prefixes(
             Match::NotMatched,
-            "example.com",
+            &["example.com"],
             b"GET /TheProject.html HTTP/1.0\r\n\r\n",
         );
     }
 
-    fn check_all_prefixes(expected_match: Match, identity: &str, input: &[u8]) {
+    #[test]
+    fn extracts_sni() {
+        assert_eq!(
+            client_hello_sni(VALID_EXAMPLE_COM),
+            identity::Name::from_hostname(b"example.com").ok()
+        );
+        let truncated = &VALID_EXAMPLE_COM[..VALID_EXAMPLE_COM.len() / 2];
+        assert_eq!(client_hello_sni(truncated), None);
+        assert_eq!(
+            client_hello_sni(b"GET /TheProject.html HTTP/1.0\r\n\r\n"),
+            None
+        );
+    }
+
+    fn check_all_prefixes(expected_match: Match, identities: &[&str], input: &[u8]) {
         assert!(expected_match == Match::Matched || expected_match == Match::NotMatched);
 
-        let identity = identity::Name::from_hostname(identity.as_bytes()).unwrap();
+        let identities = identities
+            .iter()
+            .map(|id| identity::Name::from_hostname(id.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
 
         let mut i = 0;
 
         // `Async::NotReady` will be returned for some number of prefixes.
         loop {
-            let m = match_client_hello(&input[..i], &identity);
+            let m = match_client_hello(&input[..i], &identities);
             if m != Match::Incomplete {
                 assert_eq!(m, expected_match);
                 break;
@@ -256,7 +297,7 @@ mod tests {
 
         // The same result will be returned for all longer prefixes.
         for i in (i + 1)..input.len() {
-            assert_eq!(expected_match, match_client_hello(&input[..i], &identity))
+            assert_eq!(expected_match, match_client_hello(&input[..i], &identities))
         }
     }
 }
@@ -6,7 +6,7 @@ use tokio::prelude::*;
 use identity;
 use transport::io::internal::Io;
 use transport::tls::{ReasonForNoIdentity, ReasonForNoPeerName};
-use transport::{AddrInfo, BoxedIo, Peek, SetKeepalive};
+use transport::{AddrInfo, BoxedIo, Keepalive, Peek, SetKeepalive};
 use Conditional;
 
 /// Abstracts a plaintext socket vs. a TLS decorated one.
@@ -72,6 +72,20 @@ impl Connection {
         }
     }
 
+    /// A connection accepted on a Unix socket, which has no original
+    /// destination of its own.
+    pub(in transport) fn unix<I: Io + 'static>(io: I, orig_dst: Option<SocketAddr>) -> Self {
+        Connection {
+            io: BoxedIo::new(io),
+            peek_buf: BytesMut::new(),
+            tls_peer_identity: Conditional::None(ReasonForNoIdentity::NoPeerName(
+                ReasonForNoPeerName::Loopback,
+            )),
+            detect_protocol: true,
+            orig_dst,
+        }
+    }
+
     pub(super) fn tls(
         io: BoxedIo,
         tls_peer_identity: Conditional<identity::Name, super::ReasonForNoPeerName>,
@@ -173,7 +187,7 @@ impl SetKeepalive for Connection {
         self.io.keepalive()
     }
 
-    fn set_keepalive(&mut self, ka: Option<::std::time::Duration>) -> io::Result<()> {
+    fn set_keepalive(&mut self, ka: Option<Keepalive>) -> io::Result<()> {
         self.io.set_keepalive(ka)
     }
 }
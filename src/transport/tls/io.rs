@@ -6,7 +6,7 @@ use tokio::prelude::*;
 
 use super::{rustls::Session, tokio_rustls::TlsStream};
 use transport::io::internal::Io;
-use transport::{AddrInfo, SetKeepalive};
+use transport::{AddrInfo, Keepalive, SetKeepalive};
 
 /// Wraps a TLS stream to implement Io.
 #[derive(Debug)]
@@ -98,7 +98,7 @@ where
         self.0.get_ref().0.keepalive()
     }
 
-    fn set_keepalive(&mut self, ka: Option<::std::time::Duration>) -> io::Result<()> {
+    fn set_keepalive(&mut self, ka: Option<Keepalive>) -> io::Result<()> {
         self.0.get_mut().0.set_keepalive(ka)
     }
 }
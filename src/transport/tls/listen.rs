@@ -12,18 +12,24 @@ use tokio::{
     net::{TcpListener, TcpStream},
     reactor::Handle,
 };
+use tokio_timer::Timeout;
 
-use super::{rustls, tokio_rustls, webpki};
+use super::{rustls, tokio_rustls};
 use identity;
 use transport::prefixed::Prefixed;
-use transport::tls::{self, conditional_accept, Acceptor, Connection, ReasonForNoPeerName};
-use transport::{set_nodelay_or_warn, AddrInfo, BoxedIo, GetOriginalDst};
+use transport::tls::{
+    self, conditional_accept, policy, Acceptor, Connection, ReasonForNoIdentity,
+    ReasonForNoPeerName,
+};
+use transport::{proxy_protocol, AddrInfo, BoxedIo, GetOriginalDst, ListenOptions};
 use Conditional;
 
 pub use super::rustls::ServerConfig as Config;
 
 pub trait HasConfig {
-    fn tls_server_name(&self) -> identity::Name;
+    /// The names for which TLS connections are terminated, i.e. the local
+    /// identity and any additional names that its certificate is valid for.
+    fn tls_server_names(&self) -> Vec<identity::Name>;
     fn tls_server_config(&self) -> Arc<Config>;
 }
 
@@ -36,11 +42,22 @@ pub fn empty_config() -> Arc<Config> {
 pub struct Listen<L, G = ()> {
     inner: Option<StdListener>,
     local_addr: SocketAddr,
+    options: ListenOptions,
     tls: tls::Conditional<L>,
     disable_protocol_detection_ports: IndexSet<u16>,
+    tls_policy: policy::Policy,
+    proxy_protocol: proxy_protocol::Config,
     get_original_dst: G,
 }
 
+/// Describes how an accepted connection is established, as determined by its
+/// original destination.
+enum Accept {
+    WithoutProtocolDetection,
+    Plain(ReasonForNoIdentity),
+    Tls(Vec<identity::Name>, Arc<Config>),
+}
+
 /// A server socket that is in the process of conditionally upgrading to TLS.
 enum Handshake {
     Init(Option<Inner>),
@@ -50,7 +67,7 @@ enum Handshake {
 struct Inner {
     socket: TcpStream,
     config: Arc<Config>,
-    server_name: identity::Name,
+    server_names: Vec<identity::Name>,
     peek_buf: BytesMut,
 }
 
@@ -58,13 +75,24 @@ struct Inner {
 
 impl<L: HasConfig> Listen<L> {
     pub fn bind(addr: SocketAddr, tls: tls::Conditional<L>) -> Result<Self, io::Error> {
-        let inner = StdListener::bind(addr)?;
+        Self::bind_with_options(addr, ListenOptions::default(), tls)
+    }
+
+    pub fn bind_with_options(
+        addr: SocketAddr,
+        options: ListenOptions,
+        tls: tls::Conditional<L>,
+    ) -> Result<Self, io::Error> {
+        let inner = options.bind(addr)?;
         let local_addr = inner.local_addr()?;
         Ok(Self {
             inner: Some(inner),
             local_addr,
+            options,
             tls,
             disable_protocol_detection_ports: IndexSet::new(),
+            tls_policy: policy::Policy::default(),
+            proxy_protocol: proxy_protocol::Config::default(),
             get_original_dst: (),
         })
     }
@@ -76,8 +104,11 @@ impl<L: HasConfig> Listen<L> {
         Listen {
             inner: self.inner,
             local_addr: self.local_addr,
+            options: self.options,
             tls: self.tls,
             disable_protocol_detection_ports: self.disable_protocol_detection_ports,
+            tls_policy: self.tls_policy,
+            proxy_protocol: self.proxy_protocol,
             get_original_dst,
         }
     }
@@ -94,10 +125,57 @@ impl<L: HasConfig, G> Listen<L, G> {
         }
     }
 
+    /// Skips TLS for the ports on which `policy` allows only plaintext.
+    pub fn with_tls_policy(self, tls_policy: policy::Policy) -> Self {
+        Self { tls_policy, ..self }
+    }
+
+    /// Reads a PROXY protocol header from each connection accepted on
+    /// `proxy_protocol.ports`, so that the connection's remote address is the
+    /// client's rather than that of the load balancer in front of the proxy.
+    ///
+    /// Connections on these ports that are not from `proxy_protocol.sources`,
+    /// or that do not begin with a valid header within
+    /// `proxy_protocol.timeout`, are closed.
+    pub fn with_proxy_protocol(self, proxy_protocol: proxy_protocol::Config) -> Self {
+        Self {
+            proxy_protocol,
+            ..self
+        }
+    }
+
     pub fn local_addr(&self) -> SocketAddr {
         self.local_addr
     }
 
+    /// Splits this listener into `options.acceptors` listeners, each with its
+    /// own socket bound to the same address, so that connections may be
+    /// accepted on several tasks.
+    pub fn into_acceptors(self) -> Result<Vec<Self>, io::Error>
+    where
+        L: Clone,
+        G: Clone,
+    {
+        let mut acceptors = Vec::with_capacity(self.options.acceptors);
+        for _ in 1..self.options.acceptors {
+            // The first socket has already been bound, so a listener that
+            // was bound to an ephemeral port is rebound to the same port.
+            let inner = self.options.bind(self.local_addr)?;
+            acceptors.push(Listen {
+                inner: Some(inner),
+                local_addr: self.local_addr,
+                options: self.options,
+                tls: self.tls.clone(),
+                disable_protocol_detection_ports: self.disable_protocol_detection_ports.clone(),
+                tls_policy: self.tls_policy.clone(),
+                proxy_protocol: self.proxy_protocol.clone(),
+                get_original_dst: self.get_original_dst.clone(),
+            });
+        }
+        acceptors.push(self);
+        Ok(acceptors)
+    }
+
     // Listen for incoming connections and dispatch them to the handler `f`.
     //
     // This ensures that every incoming connection has the correct options set.
@@ -175,16 +253,9 @@ impl<L: HasConfig, G> Listen<L, G> {
             incoming
                 .take(connection_limit)
                 .and_then(move |(socket, remote_addr)| {
-                    // TODO: On Linux and most other platforms it would be better
-                    // to set the `TCP_NODELAY` option on the bound socket and
-                    // then have the listening sockets inherit it. However, that
-                    // doesn't work on all platforms and also the underlying
-                    // libraries don't have the necessary API for that, so just
-                    // do it here.
-                    set_nodelay_or_warn(&socket);
+                    self.options.set_accepted(&socket);
 
                     self.new_conn(socket, remote_addr)
-                        .map(move |conn| (conn, remote_addr))
                 })
                 .then(|r| {
                     future::ok(match r {
@@ -205,7 +276,7 @@ impl<L: HasConfig, G> Listen<L, G> {
         &self,
         socket: TcpStream,
         remote_addr: SocketAddr,
-    ) -> impl Future<Item = Connection, Error = io::Error> + Send + 'static
+    ) -> impl Future<Item = (Connection, SocketAddr), Error = io::Error> + Send + 'static
     where
         Self: GetOriginalDst,
     {
@@ -213,7 +284,7 @@ impl<L: HasConfig, G> Listen<L, G> {
         // determine whether to skip protocol detection, not any port that
         // would be found after doing discovery.
         let original_dst = self.get_original_dst(&socket);
-        match (original_dst, &self.tls) {
+        let accept = match (original_dst, &self.tls) {
             // Protocol detection is disabled for the original port. Return a
             // new connection without protocol detection.
             (Some(addr), _) if self.disable_protocol_detection_ports.contains(&addr.port()) => {
@@ -221,9 +292,16 @@ impl<L: HasConfig, G> Listen<L, G> {
                     "accepted connection from {} to {}; skipping protocol detection",
                     remote_addr, addr,
                 );
-                let conn =
-                    Connection::without_protocol_detection(socket).with_original_dst(Some(addr));
-                Either::A(future::ok(conn))
+                Accept::WithoutProtocolDetection
+            }
+            // TLS is disabled for the original port. Return a new plaintext
+            // connection.
+            (Some(addr), _) if self.tls_policy.mode(addr.port()) == policy::Mode::Plaintext => {
+                debug!(
+                    "accepted connection from {} to {}; skipping TLS by policy",
+                    remote_addr, addr,
+                );
+                Accept::Plain(ReasonForNoIdentity::Disabled)
             }
             // TLS is enabled. Try to accept a TLS handshake.
             (dst, Conditional::Some(tls)) => {
@@ -231,8 +309,7 @@ impl<L: HasConfig, G> Listen<L, G> {
                     "accepted connection from {} to {:?}; attempting TLS handshake",
                     remote_addr, dst,
                 );
-                let handshake = Handshake::new(socket, tls).map(move |c| c.with_original_dst(dst));
-                Either::B(Either::A(handshake))
+                Accept::Tls(tls.tls_server_names(), tls.tls_server_config())
             }
             // TLS is disabled. Return a new plaintext connection.
             (dst, Conditional::None(why_no_tls)) => {
@@ -240,10 +317,56 @@ impl<L: HasConfig, G> Listen<L, G> {
                     "accepted connection from {} to {:?}; skipping TLS ({})",
                     remote_addr, dst, why_no_tls,
                 );
-                let conn = Connection::plain(socket, *why_no_tls).with_original_dst(dst);
-                Either::B(Either::B(future::ok(conn)))
+                Accept::Plain(*why_no_tls)
             }
+        };
+
+        let port = original_dst
+            .map(|addr| addr.port())
+            .unwrap_or_else(|| self.local_addr.port());
+        if !self.proxy_protocol.ports.contains(&port) {
+            let conn = accept.connection(socket, original_dst);
+            return Either::A(conn.map(move |conn| (conn, remote_addr)));
+        }
+
+        // A header may describe any client address, so it is only trusted
+        // from the load balancers in front of the proxy.
+        if !self.proxy_protocol.sources.contains(remote_addr.ip()) {
+            let e = io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("PROXY protocol header from untrusted peer {}", remote_addr),
+            );
+            return Either::B(Either::A(future::err(e)));
         }
+
+        // The PROXY protocol header precedes everything else, including any
+        // TLS client hello, so it must be read before the connection is
+        // established.
+        let read = Timeout::new(
+            proxy_protocol::read_header(socket),
+            self.proxy_protocol.timeout,
+        )
+        .map_err(|e| {
+            e.into_inner().unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out reading PROXY protocol header",
+                )
+            })
+        });
+        let conn = read.and_then(move |(socket, header)| {
+            let client_addr = match header.source {
+                Some(addr) => {
+                    debug!("connection from {} is proxied for {}", remote_addr, addr);
+                    addr
+                }
+                None => remote_addr,
+            };
+            accept
+                .connection(socket, original_dst)
+                .map(move |conn| (conn, client_addr))
+        });
+        Either::B(Either::B(conn))
     }
 }
 
@@ -255,18 +378,48 @@ impl<L> GetOriginalDst for Listen<L, ()> {
 
 impl<L, G: GetOriginalDst> GetOriginalDst for Listen<L, G> {
     fn get_original_dst(&self, socket: &AddrInfo) -> Option<SocketAddr> {
+        // Connections redirected by TPROXY are not NATed, so their original
+        // destination is the address to which they were accepted.
+        if self.options.transparent {
+            return socket.local_addr().ok();
+        }
         self.get_original_dst.get_original_dst(socket)
     }
 }
 
+// === impl Accept ===
+
+impl Accept {
+    fn connection(
+        self,
+        socket: TcpStream,
+        original_dst: Option<SocketAddr>,
+    ) -> impl Future<Item = Connection, Error = io::Error> + Send + 'static {
+        match self {
+            Accept::WithoutProtocolDetection => {
+                let conn = Connection::without_protocol_detection(socket);
+                Either::A(future::ok(conn.with_original_dst(original_dst)))
+            }
+            Accept::Plain(why_no_tls) => {
+                let conn = Connection::plain(socket, why_no_tls);
+                Either::A(future::ok(conn.with_original_dst(original_dst)))
+            }
+            Accept::Tls(server_names, config) => {
+                let handshake = Handshake::new(socket, server_names, config);
+                Either::B(handshake.map(move |c| c.with_original_dst(original_dst)))
+            }
+        }
+    }
+}
+
 // === impl Handshake ===
 
 impl Handshake {
-    fn new<T: HasConfig>(socket: TcpStream, tls: &T) -> Self {
+    fn new(socket: TcpStream, server_names: Vec<identity::Name>, config: Arc<Config>) -> Self {
         Handshake::Init(Some(Inner {
             socket,
-            server_name: tls.tls_server_name(),
-            config: tls.tls_server_config(),
+            server_names,
+            config,
             peek_buf: BytesMut::with_capacity(8192),
         }))
     }
@@ -275,16 +428,11 @@ impl Handshake {
         tls: &tokio_rustls::TlsStream<S, rustls::ServerSession>,
     ) -> Option<identity::Name> {
         use super::rustls::Session;
-        use dns;
 
         let (_io, session) = tls.get_ref();
         let certs = session.get_peer_certificates()?;
         let c = certs.first().map(rustls::Certificate::as_ref)?;
-        let end_cert = webpki::EndEntityCert::from(untrusted::Input::from(c)).ok()?;
-        let dns_names = end_cert.dns_names().ok()?;
-
-        let n = dns_names.first()?.to_owned();
-        Some(identity::Name::from(dns::Name::from(n)))
+        identity::Name::from_crt(c)
     }
 }
 
@@ -349,7 +497,7 @@ impl Inner {
         }
 
         let buf = self.peek_buf.as_ref();
-        Ok(conditional_accept::match_client_hello(buf, &self.server_name).into())
+        Ok(conditional_accept::match_client_hello(buf, &self.server_names).into())
     }
 
     fn into_tls_upgrade(self) -> Handshake {
@@ -14,9 +14,11 @@ mod conditional_accept;
 mod connection;
 mod io;
 pub mod listen;
+pub mod policy;
 
 use self::io::TlsIo;
 
+pub use self::conditional_accept::client_hello_sni;
 pub use self::connection::Connection;
 pub use self::listen::Listen;
 pub use self::rustls::TLSError as Error;
@@ -30,6 +32,14 @@ pub type Status = Conditional<()>;
 
 pub trait HasPeerIdentity {
     fn peer_identity(&self) -> PeerIdentity;
+
+    /// Returns the SNI name to send to the peer, when it differs from the
+    /// peer's identity.
+    ///
+    /// The peer's certificate must be valid for both names.
+    fn peer_server_name(&self) -> Option<identity::Name> {
+        None
+    }
 }
 
 pub trait HasStatus {
@@ -0,0 +1,182 @@
+//! Determines, for each inbound port, whether connections must be
+//! authenticated with mutual TLS.
+//!
+//! The listener does not terminate TLS on `plaintext` ports, and the accept
+//! stack refuses connections to `require-identity` ports from clients that did
+//! not present a verified identity.
+
+use indexmap::IndexMap;
+use std::str::FromStr;
+use std::{error, fmt};
+
+use never::Never;
+use proxy::server::Source;
+use svc;
+
+/// The TLS policy of a port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Connections are refused unless the client presents a verified identity.
+    RequireIdentity,
+    /// TLS is terminated when the client initiates it, but connections without
+    /// a client identity are accepted.
+    Optional,
+    /// TLS is not terminated, e.g. for health checks that must reach the
+    /// application unmodified.
+    Plaintext,
+}
+
+/// The TLS policy of each port.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    default: Mode,
+    ports: IndexMap<u16, Mode>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    policy: Policy,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<M> {
+    inner: M,
+    policy: Policy,
+}
+
+/// Indicates that a connection was refused because its client did not present
+/// a verified identity.
+#[derive(Clone, Debug)]
+pub struct Refused {
+    port: u16,
+}
+
+pub fn layer(policy: Policy) -> Layer {
+    Layer { policy }
+}
+
+// === impl Mode ===
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Optional
+    }
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("require-identity") => Ok(Mode::RequireIdentity),
+            s if s.eq_ignore_ascii_case("optional") => Ok(Mode::Optional),
+            s if s.eq_ignore_ascii_case("plaintext") => Ok(Mode::Plaintext),
+            _ => Err(()),
+        }
+    }
+}
+
+// === impl Policy ===
+
+impl Policy {
+    pub fn new(default: Mode, ports: IndexMap<u16, Mode>) -> Self {
+        Self { default, ports }
+    }
+
+    pub fn mode(&self, port: u16) -> Mode {
+        self.ports.get(&port).cloned().unwrap_or(self.default)
+    }
+}
+
+// === impl Layer ===
+
+impl<M> svc::Layer<Source, Source, M> for Layer
+where
+    M: svc::Stack<Source, Error = Never>,
+{
+    type Value = <Stack<M> as svc::Stack<Source>>::Value;
+    type Error = <Stack<M> as svc::Stack<Source>>::Error;
+    type Stack = Stack<M>;
+
+    fn bind(&self, inner: M) -> Self::Stack {
+        Stack {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+// === impl Stack ===
+
+impl<M> svc::Stack<Source> for Stack<M>
+where
+    M: svc::Stack<Source, Error = Never>,
+{
+    type Value = M::Value;
+    type Error = Refused;
+
+    fn make(&self, source: &Source) -> Result<Self::Value, Self::Error> {
+        let port = source.orig_dst.unwrap_or(source.local).port();
+        if self.policy.mode(port) == Mode::RequireIdentity && source.tls_peer.is_none() {
+            return Err(Refused { port });
+        }
+
+        match self.inner.make(source) {
+            Ok(accept) => Ok(accept),
+            Err(never) => match never {},
+        }
+    }
+}
+
+// === impl Refused ===
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "port {} requires a client identity, but none was presented",
+            self.port
+        )
+    }
+}
+
+impl error::Error for Refused {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use identity;
+    use std::net::SocketAddr;
+    use svc::{Layer, Stack};
+    use transport::tls::{self, ReasonForNoPeerName};
+    use Conditional;
+
+    fn source(port: u16, tls_peer: tls::PeerIdentity) -> Source {
+        let addr = |p| SocketAddr::from(([10, 0, 0, 1], p));
+        Source::for_test(addr(40_000), addr(4143), Some(addr(port)), tls_peer)
+    }
+
+    #[test]
+    fn refuses_unauthenticated_connections_to_required_ports() {
+        let mut ports = IndexMap::new();
+        ports.insert(8443, Mode::RequireIdentity);
+        ports.insert(9990, Mode::Plaintext);
+        let stack = layer(Policy::new(Mode::Optional, ports)).bind(());
+
+        let id = identity::Name::from_hostname(b"foo.ns1.svc.cluster.local").unwrap();
+        let no_id = || Conditional::None(ReasonForNoPeerName::NotProvidedByRemote.into());
+
+        assert!(stack.make(&source(8443, Conditional::Some(id))).is_ok());
+        assert!(stack.make(&source(8443, no_id())).is_err());
+        assert!(stack.make(&source(8080, no_id())).is_ok());
+        assert!(stack.make(&source(9990, no_id())).is_ok());
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("require-identity".parse(), Ok(Mode::RequireIdentity));
+        assert_eq!(" Optional ".parse(), Ok(Mode::Optional));
+        assert_eq!("plaintext".parse(), Ok(Mode::Plaintext));
+        assert_eq!("required".parse::<Mode>(), Err(()));
+    }
+}
@@ -0,0 +1,254 @@
+//! Unix domain sockets, over which the proxy may accept connections from, and
+//! establish connections to, processes on the same host without a loopback
+//! TCP hop.
+
+use bytes::Buf;
+use futures::{future, Future, IntoFuture, Poll, Stream};
+use indexmap::IndexSet;
+use std::net::{Shutdown, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener as StdListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io};
+use tokio::io::AsyncWrite;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::reactor::Handle;
+
+use never::Never;
+use svc;
+use transport::connect::{self, HasPeerAddr};
+use transport::io::internal::Io;
+use transport::{AddrInfo, BoxedIo, Connection, Keepalive, SetKeepalive};
+
+/// Accepts connections on a Unix socket.
+#[derive(Debug)]
+pub struct Listen {
+    inner: Option<StdListener>,
+    path: PathBuf,
+    allowed_uids: Option<Arc<IndexSet<u32>>>,
+    orig_dst: Option<SocketAddr>,
+}
+
+/// Establishes connections to a Unix socket, if one is configured, rather
+/// than to each target's address.
+#[derive(Clone, Debug)]
+pub struct ConnectStack {
+    path: Option<Arc<PathBuf>>,
+    tcp: connect::Stack,
+}
+
+#[derive(Clone, Debug)]
+pub enum Connect {
+    Tcp(connect::ConnectSocketAddr),
+    Unix(Arc<PathBuf>),
+}
+
+// === impl Listen ===
+
+impl Listen {
+    /// Binds a listener to `path`, replacing a socket left there by a previous
+    /// process.
+    pub fn bind(path: PathBuf) -> io::Result<Self> {
+        match fs::symlink_metadata(&path) {
+            Ok(ref meta) if meta.file_type().is_socket() => fs::remove_file(&path)?,
+            _ => {}
+        }
+        let inner = StdListener::bind(&path)?;
+        Ok(Listen {
+            inner: Some(inner),
+            path,
+            allowed_uids: None,
+            orig_dst: None,
+        })
+    }
+
+    /// Closes connections from processes whose user ID is not in
+    /// `allowed_uids`, as reported by the socket's peer credentials.
+    pub fn with_allowed_uids(self, allowed_uids: Option<IndexSet<u32>>) -> Self {
+        Self {
+            allowed_uids: allowed_uids.map(Arc::new),
+            ..self
+        }
+    }
+
+    /// Connections accepted on a Unix socket have no `SO_ORIGINAL_DST`, so
+    /// they are handled as if they were addressed to `orig_dst`.
+    pub fn with_original_dst(self, orig_dst: Option<SocketAddr>) -> Self {
+        Self { orig_dst, ..self }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Listens for incoming connections and dispatches them to the handler
+    /// `f`, like `tls::Listen::listen_and_fold`.
+    ///
+    /// Connections are never upgraded to TLS, since they originate from the
+    /// same host.
+    pub fn listen_and_fold<T, F, Fut>(
+        mut self,
+        initial: T,
+        f: F,
+    ) -> impl Future<Item = (), Error = io::Error> + Send + 'static
+    where
+        F: Fn(T, (Connection, SocketAddr)) -> Fut + Send + 'static,
+        T: Send + 'static,
+        Fut: IntoFuture<Item = T, Error = io::Error> + Send + 'static,
+        <Fut as IntoFuture>::Future: Send,
+    {
+        let inner = self
+            .inner
+            .take()
+            .expect("listener shouldn't be taken twice");
+        let allowed_uids = self.allowed_uids;
+        let orig_dst = self.orig_dst;
+
+        // Like a TCP listener, the listener is registered with the reactor
+        // lazily, once the runtime has started.
+        future::lazy(move || UnixListener::from_std(inner, &Handle::current()))
+            .and_then(move |listener| {
+                listener
+                    .incoming()
+                    .filter(move |socket| is_allowed(socket, &allowed_uids))
+                    .map(move |socket| (Connection::unix(socket, orig_dst), peer_addr()))
+                    .fold(initial, f)
+            })
+            .map(|_| ())
+    }
+}
+
+/// The remote address of connections accepted on a Unix socket, which do not
+/// have one of their own.
+fn peer_addr() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 0))
+}
+
+fn is_allowed(socket: &UnixStream, allowed_uids: &Option<Arc<IndexSet<u32>>>) -> bool {
+    let allowed_uids = match *allowed_uids {
+        Some(ref uids) => uids,
+        None => return true,
+    };
+
+    match socket.peer_cred() {
+        Ok(cred) if allowed_uids.contains(&cred.uid) => true,
+        Ok(cred) => {
+            debug!("refusing connection from uid {}", cred.uid);
+            false
+        }
+        Err(e) => {
+            debug!("refusing connection without peer credentials: {}", e);
+            false
+        }
+    }
+}
+
+// === impl ConnectStack ===
+
+impl ConnectStack {
+    /// Connects to the Unix socket at `path` when it is set, or to each
+    /// target's address otherwise.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        ConnectStack {
+            path: path.map(Arc::new),
+            tcp: connect::Stack::new(),
+        }
+    }
+}
+
+impl<T: HasPeerAddr> svc::Stack<T> for ConnectStack {
+    type Value = Connect;
+    type Error = Never;
+
+    fn make(&self, t: &T) -> Result<Self::Value, Self::Error> {
+        match self.path {
+            Some(ref path) => Ok(Connect::Unix(path.clone())),
+            None => self.tcp.make(t).map(Connect::Tcp),
+        }
+    }
+}
+
+// === impl Connect ===
+
+impl connect::Connect for Connect {
+    type Connected = BoxedIo;
+    type Error = io::Error;
+    type Future = Box<Future<Item = BoxedIo, Error = io::Error> + Send>;
+
+    fn connect(&self) -> Self::Future {
+        match *self {
+            Connect::Tcp(ref tcp) => Box::new(connect::Connect::connect(tcp).map(BoxedIo::new)),
+            Connect::Unix(ref path) => {
+                debug!("connecting to {}", path.display());
+                let path = path.clone();
+                Box::new(
+                    UnixStream::connect(&*path)
+                        .map(BoxedIo::new)
+                        .map_err(move |e| {
+                            let details = format!("{} (path: {})", e, path.display());
+                            io::Error::new(e.kind(), details)
+                        }),
+                )
+            }
+        }
+    }
+}
+
+// === impl UnixStream ===
+
+impl AddrInfo for UnixStream {
+    fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "unix sockets do not have a socket address",
+        ))
+    }
+
+    fn get_original_dst(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+/// Keepalive probes are a TCP mechanism, so they are never set.
+impl SetKeepalive for UnixStream {
+    fn keepalive(&self) -> io::Result<Option<Duration>> {
+        Ok(None)
+    }
+
+    fn set_keepalive(&mut self, _: Option<Keepalive>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Io for UnixStream {
+    fn shutdown_write(&mut self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Write)
+    }
+
+    fn write_buf_erased(&mut self, mut buf: &mut Buf) -> Poll<usize, io::Error> {
+        self.write_buf(&mut buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream as StdStream;
+
+    #[test]
+    fn rebinds_stale_socket() {
+        let dir =
+            ::std::env::temp_dir().join(format!("linkerd2-proxy-unix-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("inbound.sock");
+
+        let first = Listen::bind(path.clone()).expect("first bind");
+        drop(first);
+        let second = Listen::bind(path.clone()).expect("stale socket must be replaced");
+        assert!(StdStream::connect(second.path()).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -759,6 +759,37 @@ mod proxy_to_proxy {
         assert_eq!(res.version(), http::Version::HTTP_2);
     }
 
+    #[test]
+    fn inbound_http1_from_peer_without_tls() {
+        let _ = env_logger_init();
+        let id = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+        let id_env = identity::Identity::new("foo-ns1", id.to_string());
+
+        let srv = server::http1()
+            .route_fn("/h1", |req| {
+                assert_eq!(req.version(), http::Version::HTTP_11);
+                Response::default()
+            })
+            .run();
+
+        // Untrusted headers are only stripped when the proxy has an identity.
+        let proxy = proxy::new()
+            .inbound(srv)
+            .identity(id_env.service().run())
+            .run_with_test_env(id_env.env.clone());
+
+        // This client will be used as a mocked-other-proxy without TLS.
+        let client = client::http2(proxy.inbound, "disco.test.svc.cluster.local");
+
+        let res = client.request(
+            client
+                .request_builder("/h1")
+                .header("l5d-orig-proto", "HTTP/1.1"),
+        );
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.version(), http::Version::HTTP_2);
+    }
+
     #[test]
     fn inbound_should_strip_l5d_client_id() {
         let _ = env_logger_init();
@@ -856,7 +887,7 @@ mod proxy_to_proxy {
     }
 
     macro_rules! generate_l5d_tls_id_test {
-        (server: $make_server:path, client: $make_client:path) => {
+        (server: $make_server:path, client: $make_client:path, tls_client: $make_tls_client:path, server_id: $server_id:expr) => {
             let _ = env_logger_init();
             let id = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
             let id_env = identity::Identity::new("foo-ns1", id.to_string());
@@ -884,18 +915,59 @@ mod proxy_to_proxy {
 
             let client = $make_client(out_proxy.outbound, "disco.test.svc.cluster.local");
 
-            let res = client.request(&mut client.request_builder("/hallo"));
+            let res = client.request(
+                client
+                    .request_builder("/hallo")
+                    .header("l5d-client-id", "sneaky.sneaky"),
+            );
+            assert_eq!(res.status(), 200);
+            if $server_id {
+                assert_eq!(res.headers()["l5d-server-id"], id);
+            }
+
+            // The outbound proxy strips the spoofed header before it reaches
+            // the inbound proxy, so also send it to the inbound proxy
+            // directly, as a peer would.
+            let tls = client::TlsConfig::new(id_env.client_config.clone(), id);
+            let client = $make_tls_client(in_proxy.inbound, "disco.test.svc.cluster.local", tls);
+
+            let res = client.request(
+                client
+                    .request_builder("/hallo")
+                    .header("l5d-client-id", "sneaky.sneaky"),
+            );
             assert_eq!(res.status(), 200);
-            assert_eq!(res.headers()["l5d-server-id"], id);
         };
     }
 
+    #[test]
+    fn outbound_http1_l5d_client_id() {
+        generate_l5d_tls_id_test! {
+            server: server::http1,
+            client: client::http1,
+            tls_client: client::http1_tls,
+            server_id: false
+        }
+    }
+
+    #[test]
+    fn outbound_http2_l5d_client_id() {
+        generate_l5d_tls_id_test! {
+            server: server::http2,
+            client: client::http2,
+            tls_client: client::http2_tls,
+            server_id: false
+        }
+    }
+
     #[test]
     #[ignore] // #2597
     fn outbound_http1_l5d_server_id_l5d_client_id() {
         generate_l5d_tls_id_test! {
             server: server::http1,
-            client: client::http1
+            client: client::http1,
+            tls_client: client::http1_tls,
+            server_id: true
         }
     }
 
@@ -904,7 +976,9 @@ mod proxy_to_proxy {
     fn outbound_http2_l5d_server_id_l5d_client_id() {
         generate_l5d_tls_id_test! {
             server: server::http2,
-            client: client::http2
+            client: client::http2,
+            tls_client: client::http2_tls,
+            server_id: true
         }
     }
 }
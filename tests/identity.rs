@@ -19,6 +19,7 @@ fn ready() {
     let identity::Identity {
         env,
         mut certify_rsp,
+        ..
     } = identity::Identity::new("foo-ns1", id.to_string());
 
     certify_rsp.valid_until = Some((SystemTime::now() + Duration::from_secs(666)).into());
@@ -51,6 +52,7 @@ fn refresh() {
     let identity::Identity {
         mut env,
         certify_rsp,
+        ..
     } = identity::Identity::new("foo-ns1", id.to_string());
 
     let (expiry_tx, expiry_rx) = oneshot::channel();
@@ -29,6 +29,7 @@ macro_rules! profile_test {
         let counter = AtomicUsize::new(0);
         let counter2 = AtomicUsize::new(0);
         let counter3 = AtomicUsize::new(0);
+        let counter4 = AtomicUsize::new(0);
         let host = "profiles.test.svc.cluster.local";
 
         let srv = server::$http()
@@ -87,6 +88,24 @@ macro_rules! profile_test {
                         .unwrap()
                 }
             })
+            .route_fn("/0.5/attempt", move |req| {
+                if counter4.fetch_add(1, Ordering::Relaxed) % 2 == 0 {
+                    Response::builder()
+                        .status(533)
+                        .body("nope".into())
+                        .unwrap()
+                } else {
+                    let attempt = req
+                        .headers()
+                        .get("x-retry-attempt")
+                        .map(|v| v.to_str().unwrap().to_owned())
+                        .unwrap_or_default();
+                    Response::builder()
+                        .status(200)
+                        .body(attempt.into())
+                        .unwrap()
+                }
+            })
             .run();
         let ctrl = controller::new();
 
@@ -167,7 +186,29 @@ fn retry_uses_budget() {
         with_metrics: |metrics: client::Client| {
             assert_eventually_contains!(
                 metrics.get("/metrics"),
-                "route_actual_retry_skipped_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",skipped=\"budget\"} 1"
+                "route_actual_retry_skipped_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_route=\"default\",skipped=\"budget\"} 1"
+            );
+        }
+    }
+}
+
+#[test]
+fn retry_sets_attempt_header() {
+    profile_test! {
+        routes: [
+            controller::route()
+                .request_any()
+                .response_failure(500..600)
+                .retryable(true)
+        ],
+        budget: Some(controller::retry_budget(Duration::from_secs(10), 0.1, 1)),
+        with_client: |client: client::Client| {
+            assert_eq!(client.get("/0.5/attempt"), "1");
+        },
+        with_metrics: |metrics: client::Client| {
+            assert_eventually_contains!(
+                metrics.get("/metrics"),
+                "route_actual_retry_attempt_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_route=\"default\",attempt=\"1\"} 1"
             );
         }
     }
@@ -335,7 +376,7 @@ fn timeout() {
         with_metrics: |metrics: client::Client| {
             assert_eventually_contains!(
                 metrics.get("/metrics"),
-                "route_response_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",status_code=\"504\",classification=\"failure\",error=\"timeout\"} 1"
+                "route_response_total{direction=\"outbound\",dst=\"profiles.test.svc.cluster.local:80\",rt_route=\"default\",status_code=\"504\",classification=\"failure\",error=\"timeout\"} 1"
             );
         }
     }
@@ -1,7 +1,7 @@
 use support::*;
 
 use std::io;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use self::futures::sync::{mpsc, oneshot};
 use self::tokio::{
@@ -10,14 +10,26 @@ use self::tokio::{
 };
 use support::bytes::IntoBuf;
 use support::hyper::body::Payload;
+use support::tokio_rustls::{webpki::DNSNameRef, TlsConnector};
 
 type Request = http::Request<Bytes>;
 type Response = http::Response<BytesBody>;
-type Sender = mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response, String>>)>;
+type Sender = mpsc::UnboundedSender<(
+    Request,
+    Option<HeaderMap>,
+    oneshot::Sender<Result<Response, String>>,
+)>;
 
 #[derive(Debug)]
 pub struct BytesBody(hyper::Body);
 
+/// Configures a client to connect over TLS, as another proxy would.
+#[derive(Clone)]
+pub struct TlsConfig {
+    client_config: Arc<rustls::ClientConfig>,
+    name: String,
+}
+
 pub fn new<T: Into<String>>(addr: SocketAddr, auth: T) -> Client {
     http2(addr, auth.into())
 }
@@ -29,6 +41,18 @@ pub fn http1<T: Into<String>>(addr: SocketAddr, auth: T) -> Client {
         Run::Http1 {
             absolute_uris: false,
         },
+        None,
+    )
+}
+
+pub fn http1_tls<T: Into<String>>(addr: SocketAddr, auth: T, tls: TlsConfig) -> Client {
+    Client::new(
+        addr,
+        auth.into(),
+        Run::Http1 {
+            absolute_uris: false,
+        },
+        Some(tls),
     )
 }
 
@@ -40,11 +64,16 @@ pub fn http1_absolute_uris<T: Into<String>>(addr: SocketAddr, auth: T) -> Client
         Run::Http1 {
             absolute_uris: true,
         },
+        None,
     )
 }
 
 pub fn http2<T: Into<String>>(addr: SocketAddr, auth: T) -> Client {
-    Client::new(addr, auth.into(), Run::Http2)
+    Client::new(addr, auth.into(), Run::Http2, None)
+}
+
+pub fn http2_tls<T: Into<String>>(addr: SocketAddr, auth: T, tls: TlsConfig) -> Client {
+    Client::new(addr, auth.into(), Run::Http2, Some(tls))
 }
 
 pub fn tcp(addr: SocketAddr) -> tcp::TcpClient {
@@ -61,12 +90,12 @@ pub struct Client {
 }
 
 impl Client {
-    fn new(addr: SocketAddr, authority: String, r: Run) -> Client {
+    fn new(addr: SocketAddr, authority: String, r: Run, tls: Option<TlsConfig>) -> Client {
         let v = match r {
             Run::Http1 { .. } => http::Version::HTTP_11,
             Run::Http2 => http::Version::HTTP_2,
         };
-        let (tx, running) = run(addr, r);
+        let (tx, running) = run(addr, r, tls);
         Client {
             authority,
             running,
@@ -107,6 +136,13 @@ impl Client {
         self.send_req(req).wait().expect("response")
     }
 
+    /// Sends `req`, followed by `trailers` after its body.
+    pub fn request_with_trailers(&self, req: Request, trailers: HeaderMap) -> Response {
+        self.send_req_with_trailers(req, Some(trailers))
+            .wait()
+            .expect("response")
+    }
+
     pub fn request_body_async(
         &self,
         req: Request,
@@ -121,7 +157,15 @@ impl Client {
         b
     }
 
-    fn send_req(&self, mut req: Request) -> Box<Future<Item = Response, Error = String> + Send> {
+    fn send_req(&self, req: Request) -> Box<Future<Item = Response, Error = String> + Send> {
+        self.send_req_with_trailers(req, None)
+    }
+
+    fn send_req_with_trailers(
+        &self,
+        mut req: Request,
+        trailers: Option<HeaderMap>,
+    ) -> Box<Future<Item = Response, Error = String> + Send> {
         if req.uri().scheme_part().is_none() {
             let absolute = format!("http://{}{}", self.authority, req.uri().path())
                 .parse()
@@ -129,7 +173,7 @@ impl Client {
             *req.uri_mut() = absolute;
         }
         let (tx, rx) = oneshot::channel();
-        let _ = self.tx.unbounded_send((req, tx));
+        let _ = self.tx.unbounded_send((req, trailers, tx));
         Box::new(rx.then(|oneshot_result| oneshot_result.expect("request canceled")))
     }
 
@@ -138,14 +182,27 @@ impl Client {
     }
 }
 
+impl TlsConfig {
+    pub fn new(client_config: Arc<rustls::ClientConfig>, name: &str) -> Self {
+        TlsConfig {
+            client_config,
+            name: name.into(),
+        }
+    }
+}
+
 #[derive(Debug)]
 enum Run {
     Http1 { absolute_uris: bool },
     Http2,
 }
 
-fn run(addr: SocketAddr, version: Run) -> (Sender, Running) {
-    let (tx, rx) = mpsc::unbounded::<(Request, oneshot::Sender<Result<Response, String>>)>();
+fn run(addr: SocketAddr, version: Run, tls: Option<TlsConfig>) -> (Sender, Running) {
+    let (tx, rx) = mpsc::unbounded::<(
+        Request,
+        Option<HeaderMap>,
+        oneshot::Sender<Result<Response, String>>,
+    )>();
     let (running_tx, running_rx) = running();
 
     let tname = format!("support {:?} server (test={})", version, thread_name(),);
@@ -165,6 +222,7 @@ fn run(addr: SocketAddr, version: Run) -> (Sender, Running) {
                 addr,
                 running: Mutex::new(Some(running_tx)),
                 absolute_uris,
+                tls,
             };
 
             let http2_only = match version {
@@ -174,11 +232,11 @@ fn run(addr: SocketAddr, version: Run) -> (Sender, Running) {
 
             let client = hyper::Client::builder()
                 .http2_only(http2_only)
-                .build::<Conn, hyper::Body>(conn);
+                .build::<Conn, BodyWithTrailers>(conn);
 
             let work = rx
-                .for_each(move |(req, cb)| {
-                    let req = req.map(hyper::Body::from);
+                .for_each(move |(req, trailers, cb)| {
+                    let req = req.map(|data| BodyWithTrailers::new(data, trailers));
                     let fut = client.request(req).then(move |result| {
                         let result = result
                             .map(|resp| resp.map(BytesBody))
@@ -204,6 +262,7 @@ struct Conn {
     /// When this Sender drops, that should mean the connection is closed.
     running: Mutex<Option<oneshot::Sender<()>>>,
     absolute_uris: bool,
+    tls: Option<TlsConfig>,
 }
 
 impl Conn {
@@ -214,16 +273,35 @@ impl Conn {
             .expect("running lock")
             .take()
             .expect("connected more than once");
+        let tls = self.tls.clone();
         let c = TcpStream::connect(&self.addr)
             .and_then(|tcp| tcp.set_nodelay(true).map(move |_| tcp))
-            .map(move |tcp| RunningIo {
-                inner: tcp,
+            .and_then(move |tcp| handshake(tcp, tls))
+            .map(move |io| RunningIo {
+                inner: io,
                 running: running,
             });
         Box::new(c)
     }
 }
 
+/// Initiates TLS on `tcp` if the client is configured to use it.
+fn handshake(
+    tcp: TcpStream,
+    tls: Option<TlsConfig>,
+) -> Box<Future<Item = Box<Io>, Error = io::Error> + Send> {
+    let tls = match tls {
+        Some(tls) => tls,
+        None => return Box::new(future::ok(Box::new(tcp) as Box<Io>)),
+    };
+    let name =
+        DNSNameRef::try_from_ascii_str(&tls.name).expect("TLS name must be a valid DNS name");
+    let c = TlsConnector::from(tls.client_config)
+        .connect(name, tcp)
+        .map(|io| Box::new(io) as Box<Io>);
+    Box::new(c)
+}
+
 impl Connect for Conn {
     type Connected = RunningIo;
     type Error = ::std::io::Error;
@@ -249,10 +327,14 @@ impl hyper::client::connect::Connect for Conn {
     }
 }
 
+trait Io: AsyncRead + AsyncWrite + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Send> Io for T {}
+
 /// A wrapper around a TcpStream, allowing us to signal when the connection
 /// is dropped.
 struct RunningIo {
-    inner: TcpStream,
+    inner: Box<Io>,
     /// When this drops, the related Receiver is notified that the connection
     /// is closed.
     running: oneshot::Sender<()>,
@@ -282,6 +364,18 @@ impl AsyncWrite for RunningIo {
     }
 }
 
+impl BytesBody {
+    /// Reads the remainder of the body, returning its trailers, if any.
+    pub fn trailers(mut self) -> Option<HeaderMap> {
+        future::poll_fn(move || {
+            while let Some(_) = try_ready!(self.0.poll_data()) {}
+            self.0.poll_trailers()
+        })
+        .wait()
+        .expect("trailers")
+    }
+}
+
 impl HttpBody for BytesBody {
     type Item = <Bytes as IntoBuf>::Buf;
     type Error = hyper::Error;
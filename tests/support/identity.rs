@@ -13,6 +13,9 @@ use linkerd2_proxy_api::identity as pb;
 pub struct Identity {
     pub env: app::config::TestEnv,
     pub certify_rsp: pb::CertifyResponse,
+    /// Configures a client to authenticate as this identity, so that tests
+    /// can connect to an inbound proxy as another proxy would.
+    pub client_config: Arc<rustls::ClientConfig>,
 }
 
 #[derive(Clone)]
@@ -28,14 +31,21 @@ type Certify = Box<
         + Send,
 >;
 
-pub fn rsp_from_cert<P>(p: P) -> Result<pb::CertifyResponse, io::Error>
+fn read_certs<P>(p: P) -> Result<Vec<rustls::Certificate>, io::Error>
 where
     P: AsRef<Path>,
 {
     let f = fs::File::open(p)?;
     let mut r = io::BufReader::new(f);
-    let certs = rustls::internal::pemfile::certs(&mut r)
-        .map_err(|_| io::Error::new(io::ErrorKind::Other, "rustls error reading certs"))?;
+    rustls::internal::pemfile::certs(&mut r)
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "rustls error reading certs"))
+}
+
+pub fn rsp_from_cert<P>(p: P) -> Result<pb::CertifyResponse, io::Error>
+where
+    P: AsRef<Path>,
+{
+    let certs = read_certs(p)?;
     let leaf_certificate = certs
         .get(0)
         .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no certs in pemfile"))?
@@ -52,7 +62,7 @@ where
 
 impl Identity {
     pub fn new(dir: &'static str, local_name: String) -> Self {
-        let (id_dir, token, trust_anchors, certify_rsp) = {
+        let (id_dir, token, trust_anchors, certify_rsp, client_config) = {
             let path_to_string = |path: &PathBuf| {
                 path.as_path()
                     .to_owned()
@@ -76,8 +86,19 @@ impl Identity {
 
             id.set_file_name("ca1-cert.pem");
             let rsp = rsp_from_cert(&id).expect("read cert");
+            let certs = read_certs(&id).expect("read cert");
+
+            id.set_file_name("key.p8");
+            let key = fs::read(&id).expect("read key");
+
+            let mut client_config = rustls::ClientConfig::new();
+            client_config
+                .root_store
+                .add_pem_file(&mut io::Cursor::new(&trust_anchors))
+                .expect("add trust anchors");
+            client_config.set_single_client_cert(certs, rustls::PrivateKey(key));
 
-            (id_dir, token, trust_anchors, rsp)
+            (id_dir, token, trust_anchors, rsp, Arc::new(client_config))
         };
 
         let mut env = app::config::TestEnv::new();
@@ -87,7 +108,11 @@ impl Identity {
         env.put(app::config::ENV_IDENTITY_TRUST_ANCHORS, trust_anchors);
         env.put(app::config::ENV_IDENTITY_IDENTITY_LOCAL_NAME, local_name);
 
-        Self { env, certify_rsp }
+        Self {
+            env,
+            certify_rsp,
+            client_config,
+        }
     }
 
     pub fn service(&self) -> Controller {
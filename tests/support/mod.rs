@@ -22,6 +22,7 @@ extern crate tokio;
 extern crate tokio_connect;
 extern crate tokio_current_thread;
 pub extern crate tokio_io;
+extern crate tokio_rustls;
 extern crate tower_grpc;
 extern crate tower_http_service;
 extern crate tower_service;
@@ -187,6 +188,43 @@ pub fn running() -> (oneshot::Sender<()>, Running) {
 
 pub type Running = Box<Future<Item = (), Error = ()> + Send>;
 
+/// A body that yields a single data frame, followed by optional trailers.
+#[derive(Debug, Default)]
+pub struct BodyWithTrailers {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl BodyWithTrailers {
+    pub fn new(data: Bytes, trailers: Option<HeaderMap>) -> Self {
+        let data = if data.is_empty() { None } else { Some(data) };
+        Self { data, trailers }
+    }
+}
+
+impl hyper::body::Payload for BodyWithTrailers {
+    type Data = hyper::Chunk;
+    type Error = hyper::Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none() && self.trailers.is_none()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(Async::Ready(self.data.take().map(hyper::Chunk::from)))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        Ok(Async::Ready(self.trailers.take()))
+    }
+}
+
+impl From<Bytes> for BodyWithTrailers {
+    fn from(data: Bytes) -> Self {
+        Self::new(data, None)
+    }
+}
+
 pub fn s(bytes: &[u8]) -> &str {
     ::std::str::from_utf8(bytes.as_ref()).unwrap()
 }
@@ -88,7 +88,14 @@ impl Server {
             Box::new(cb(req).into_future())
                 as Box<Future<Item = Response<Bytes>, Error = ()> + Send>
         };
-        self.routes.insert(path.into(), Route(Box::new(func)));
+        self.routes.insert(path.into(), Route::Func(Box::new(func)));
+        self
+    }
+
+    /// Respond to requests on `path` by echoing back the request's body and
+    /// trailers.
+    pub fn route_echo_trailers(mut self, path: &str) -> Self {
+        self.routes.insert(path.into(), Route::EchoTrailers);
         self
     }
 
@@ -210,14 +217,21 @@ enum Run {
     Http2,
 }
 
-struct Route(
-    Box<Fn(Request<ReqBody>) -> Box<Future<Item = Response<Bytes>, Error = ()> + Send> + Send>,
-);
+enum Route {
+    Func(
+        Box<Fn(Request<ReqBody>) -> Box<Future<Item = Response<Bytes>, Error = ()> + Send> + Send>,
+    ),
+    /// Echoes the request's body and trailers.
+    ///
+    /// This is handled separately from `Func`, since `ReqBody` does not
+    /// expose trailers.
+    EchoTrailers,
+}
 
 impl Route {
     fn string(body: &str) -> Route {
         let body = Bytes::from(body);
-        Route(Box::new(move |_| {
+        Route::Func(Box::new(move |_| {
             Box::new(future::ok(
                 http::Response::builder()
                     .status(200)
@@ -242,7 +256,8 @@ struct Svc(Arc<HashMap<String, Route>>);
 impl Svc {
     fn route(&mut self, req: Request<ReqBody>) -> impl Future<Item = Response<Bytes>, Error = ()> {
         match self.0.get(req.uri().path()) {
-            Some(Route(ref func)) => func(req),
+            Some(Route::Func(ref func)) => func(req),
+            Some(Route::EchoTrailers) => unreachable!("echo routes are handled by Svc::call"),
             None => {
                 println!("server 404: {:?}", req.uri().path());
                 let res = http::Response::builder()
@@ -257,11 +272,15 @@ impl Svc {
 
 impl hyper::service::Service for Svc {
     type ReqBody = hyper::Body;
-    type ResBody = hyper::Body;
+    type ResBody = BodyWithTrailers;
     type Error = http::Error;
-    type Future = Box<Future<Item = hyper::Response<hyper::Body>, Error = Self::Error> + Send>;
+    type Future = Box<Future<Item = hyper::Response<BodyWithTrailers>, Error = Self::Error> + Send>;
 
     fn call(&mut self, req: hyper::Request<hyper::Body>) -> Self::Future {
+        if let Some(Route::EchoTrailers) = self.0.get(req.uri().path()) {
+            return Box::new(echo_trailers(req));
+        }
+
         let req = req.map(|body| {
             Box::new(body.map(|chunk| chunk.into_bytes()).map_err(|err| {
                 panic!("body error: {}", err);
@@ -269,12 +288,35 @@ impl hyper::service::Service for Svc {
         });
         Box::new(
             self.route(req)
-                .map(|res| res.map(|s| hyper::Body::from(s)))
+                .map(|res| res.map(BodyWithTrailers::from))
                 .map_err(|()| panic!("test route handler errored")),
         )
     }
 }
 
+fn echo_trailers(
+    req: hyper::Request<hyper::Body>,
+) -> impl Future<Item = hyper::Response<BodyWithTrailers>, Error = http::Error> {
+    use support::hyper::body::Payload;
+
+    let mut body = req.into_body();
+    let mut data = Vec::new();
+    future::poll_fn(move || {
+        while let Some(chunk) = try_ready!(body.poll_data()) {
+            data.extend_from_slice(&chunk);
+        }
+        let trailers = try_ready!(body.poll_trailers());
+        Ok(Async::Ready((data.split_off(0), trailers)))
+    })
+    .map_err(|e: hyper::Error| panic!("echo body error: {}", e))
+    .map(|(data, trailers)| {
+        http::Response::builder()
+            .status(200)
+            .body(BodyWithTrailers::new(data.into(), trailers))
+            .unwrap()
+    })
+}
+
 #[derive(Debug)]
 struct NewSvc(Arc<HashMap<String, Route>>);
 
@@ -19,6 +19,31 @@ fn outbound_http1() {
     assert_eq!(client.get("/"), "hello h1");
 }
 
+#[test]
+fn outbound_http1_absolute_form_routes_by_its_target() {
+    let _ = env_logger_init();
+
+    // The absolute-form target, rather than the `Host` header, names the
+    // destination, and the `Host` header is passed through untouched.
+    let auth = "transparency.test.svc.cluster.local";
+    let host = "foo.bar";
+    let srv = server::http1()
+        .route_fn("/abs", move |req| {
+            assert_eq!(req.headers()["host"], host);
+            assert_eq!(req.uri().path(), "/abs");
+            Response::new("absolute".into())
+        })
+        .run();
+    let ctrl = controller::new()
+        .destination_and_close(auth, srv.addr)
+        .run();
+    let proxy = proxy::new().controller(ctrl).outbound(srv).run();
+    let client = client::http1_absolute_uris(proxy.outbound, auth);
+
+    let res = client.request(client.request_builder("/abs").header("host", host));
+    assert_eq!(res.status(), http::StatusCode::OK);
+}
+
 #[test]
 fn inbound_http1() {
     let _ = env_logger_init();
@@ -76,6 +101,68 @@ fn inbound_tcp() {
     assert_eq!(tcp_client.read(), msg2.as_bytes());
 }
 
+#[test]
+fn inbound_tcp_strips_proxy_protocol_header() {
+    let _ = env_logger_init();
+
+    let msg1 = "custom tcp hello";
+    let msg2 = "custom tcp bye";
+
+    let srv = server::tcp()
+        .accept(move |read| {
+            assert_eq!(read, msg1.as_bytes());
+            msg2
+        })
+        .run();
+    let mut env = app::config::TestEnv::new();
+    env.put(
+        app::config::ENV_INBOUND_PROXY_PROTOCOL_PORTS,
+        srv.addr.port().to_string(),
+    );
+    env.put(
+        app::config::ENV_INBOUND_PROXY_PROTOCOL_SOURCES,
+        "127.0.0.1".to_owned(),
+    );
+    let proxy = proxy::new().inbound_fuzz_addr(srv).run_with_test_env(env);
+
+    let client = client::tcp(proxy.inbound);
+
+    let tcp_client = client.connect();
+
+    tcp_client.write(format!(
+        "PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n{}",
+        msg1
+    ));
+    assert_eq!(tcp_client.read(), msg2.as_bytes());
+}
+
+#[test]
+fn inbound_tcp_rejects_proxy_protocol_header_from_untrusted_peer() {
+    let _ = env_logger_init();
+
+    let srv = server::tcp()
+        .accept(move |_| -> &'static str { panic!("connection must not be forwarded") })
+        .run();
+    let mut env = app::config::TestEnv::new();
+    env.put(
+        app::config::ENV_INBOUND_PROXY_PROTOCOL_PORTS,
+        srv.addr.port().to_string(),
+    );
+    env.put(
+        app::config::ENV_INBOUND_PROXY_PROTOCOL_SOURCES,
+        "192.0.2.0/24".to_owned(),
+    );
+    let proxy = proxy::new().inbound_fuzz_addr(srv).run_with_test_env(env);
+
+    let client = client::tcp(proxy.inbound);
+
+    let tcp_client = client.connect();
+
+    tcp_client.write("PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nhello");
+    let read = tcp_client.try_read().unwrap_or_default();
+    assert!(read.is_empty(), "connection must be closed");
+}
+
 fn test_server_speaks_first(env: app::config::TestEnv) {
     const TIMEOUT: Duration = Duration::from_secs(5);
 
@@ -1081,10 +1168,139 @@ fn retry_reconnect_errors() {
     // all the other threads currently running...
     assert_eventually_contains!(
         metrics.get("/metrics"),
-        "tcp_open_total{direction=\"inbound\",peer=\"src\",tls=\"disabled\"} 1"
+        "tcp_open_total{direction=\"inbound\",peer=\"src\",tls=\"disabled\",mesh=\"unmeshed\"} 1"
     );
 
     drop(tx); // start `listen` now
     let res = fut.wait().expect("response");
     assert_eq!(res.status(), http::StatusCode::OK);
 }
+
+fn assert_trailers_round_trip(client: &client::Client) {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", "0".parse().unwrap());
+    trailers.insert("grpc-message", "trailers".parse().unwrap());
+
+    let req = client
+        .request_builder("/")
+        .method("POST")
+        .body(Bytes::from("hello trailers"))
+        .unwrap();
+    let res = client.request_with_trailers(req, trailers.clone());
+    assert_eq!(res.status(), http::StatusCode::OK);
+    assert_eq!(res.into_body().trailers(), Some(trailers));
+}
+
+#[test]
+fn inbound_http2_trailers() {
+    let _ = env_logger_init();
+
+    let srv = server::http2().route_echo_trailers("/").run();
+    let proxy = proxy::new().inbound(srv).run();
+    let client = client::http2(proxy.inbound, "transparency.test.svc.cluster.local");
+
+    assert_trailers_round_trip(&client);
+}
+
+#[test]
+fn outbound_http2_trailers() {
+    let _ = env_logger_init();
+
+    let srv = server::http2().route_echo_trailers("/").run();
+    let ctrl = controller::new()
+        .destination_and_close("transparency.test.svc.cluster.local", srv.addr)
+        .run();
+    let proxy = proxy::new().controller(ctrl).outbound(srv).run();
+    let client = client::http2(proxy.outbound, "transparency.test.svc.cluster.local");
+
+    assert_trailers_round_trip(&client);
+}
+
+#[test]
+fn proxy_to_proxy_http2_trailers() {
+    let _ = env_logger_init();
+
+    let srv = server::http2().route_echo_trailers("/").run();
+    let inbound = proxy::new().inbound(srv).run();
+
+    let ctrl = controller::new();
+    let dst = ctrl.destination_tx("transparency.test.svc.cluster.local");
+    dst.send_h2_hinted(inbound.inbound);
+    let outbound = proxy::new().controller(ctrl.run()).run();
+    let client = client::http2(outbound.outbound, "transparency.test.svc.cluster.local");
+
+    assert_trailers_round_trip(&client);
+}
+
+#[test]
+fn proxy_to_proxy_http1_orig_proto_trailers() {
+    let _ = env_logger_init();
+
+    // HTTP/1 requests are upgraded to HTTP/2 between the proxies and are
+    // downgraded again by the inbound proxy. HTTP/1 bodies have no trailers,
+    // so none may be introduced by either translation.
+    let srv = server::http1().route_echo_trailers("/").run();
+    let inbound = proxy::new().inbound(srv).run();
+
+    let ctrl = controller::new();
+    let dst = ctrl.destination_tx("transparency.test.svc.cluster.local");
+    dst.send_h2_hinted(inbound.inbound);
+    let outbound = proxy::new().controller(ctrl.run()).run();
+    let client = client::http1(outbound.outbound, "transparency.test.svc.cluster.local");
+
+    let req = client
+        .request_builder("/")
+        .method("POST")
+        .body(Bytes::from("hello orig-proto"))
+        .unwrap();
+    let res = client.request_body(req);
+    assert_eq!(res.status(), http::StatusCode::OK);
+    assert_eq!(res.version(), http::Version::HTTP_11);
+    assert_eq!(res.headers().get("l5d-orig-proto"), None);
+    assert_eq!(res.into_body().trailers(), None);
+}
+
+fn assert_header_size_limited(client: &client::Client) {
+    let small = "a".repeat(512);
+    let res = client.request(client.request_builder("/").header("cookie", small.as_str()));
+    assert_eq!(res.status(), http::StatusCode::OK);
+
+    let large = "a".repeat(2048);
+    let res = client.request(client.request_builder("/").header("cookie", large.as_str()));
+    assert_eq!(
+        res.status(),
+        http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+    );
+}
+
+#[test]
+fn inbound_http1_max_header_size() {
+    let _ = env_logger_init();
+
+    let srv = server::http1().route("/", "hello").run();
+    let mut env = app::config::TestEnv::new();
+    env.put(app::config::ENV_INBOUND_MAX_HEADER_SIZE, "1024".to_owned());
+    let proxy = proxy::new().inbound(srv).run_with_test_env(env);
+    let client = client::http1(proxy.inbound, "transparency.test.svc.cluster.local");
+
+    assert_header_size_limited(&client);
+}
+
+#[test]
+fn outbound_http1_max_header_size() {
+    let _ = env_logger_init();
+
+    let srv = server::http1().route("/", "hello").run();
+    let ctrl = controller::new()
+        .destination_and_close("transparency.test.svc.cluster.local", srv.addr)
+        .run();
+    let mut env = app::config::TestEnv::new();
+    env.put(app::config::ENV_OUTBOUND_MAX_HEADER_SIZE, "1024".to_owned());
+    let proxy = proxy::new()
+        .controller(ctrl)
+        .outbound(srv)
+        .run_with_test_env(env);
+    let client = client::http1(proxy.outbound, "transparency.test.svc.cluster.local");
+
+    assert_header_size_limited(&client);
+}